@@ -0,0 +1,363 @@
+//! # aipilot
+//!
+//! The command line program of AI Pilot. It runs the workflow files with the providers, the
+//! tools and the stores of an `aipilot.toml` config, so a workflow can be used without
+//! writing a Rust program.
+//!
+//! - `aipilot run <workflow>`: run the workflow. The input of the start node is the text of
+//!   `--input`, or else the standard input, and the output of the end node is written to the
+//!   standard output. The providers and the stores are checked first (see
+//!   `Workflow::preflight`), unless `--skip-preflight` is given. Ctrl-C cancels the run.
+//!   With `--monitor`, the run is watched in a dashboard in the terminal (see `aipilot::tui`),
+//!   and the output is written when the dashboard is closed.
+//! - `aipilot validate <workflow>`: check the workflow and print its problems.
+//! - `aipilot graph <workflow>`: print the workflow as a Mermaid flowchart, or as Graphviz
+//!   DOT with `--dot`.
+//! - `aipilot usage`: print the token usage of every run in the execution log.
+//! - `aipilot serve <workflow>...`: serve the workflows over HTTP by the names of their
+//!   files (see `aipilot::server`), and with `--watch`, reload them when they change.
+//!
+//! The config is `aipilot.toml` in the current directory, or the file of `--config`. The
+//! profile is the one of `--profile`, or else of `AIPILOT_PROFILE`. The messages and the logs
+//! are written to the standard error, so the standard output only has the output of the run.
+
+use aipilot::config::{Config, ConfigLoader};
+use aipilot::workflow::context::RunContext;
+use aipilot::workflow::execution_log::ExecutionLog;
+use aipilot::workflow::run::RunStatus;
+use aipilot::workflow::validate::Diagnostic;
+use aipilot::workflow::Workflow;
+
+use clap::{Args, Parser, Subcommand};
+use tokio_util::sync::CancellationToken;
+
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+/// The config file read when `--config` is not given.
+const CONFIG_FILE: &str = "aipilot.toml";
+
+#[derive(Debug, Parser)]
+#[command(name = "aipilot", version, about = "Run AI Pilot workflows")]
+/// The struct of the command line.
+struct Cli {
+    /// The config file, `aipilot.toml` by default.
+    #[arg(long, global = true, value_name = "FILE")]
+    config: Option<PathBuf>,
+    /// The profile of the config.
+    #[arg(long, global = true, value_name = "NAME")]
+    profile: Option<String>,
+    /// Log more, `-v` for info and `-vv` for debug.
+    #[arg(short, long, global = true, action = clap::ArgAction::Count)]
+    verbose: u8,
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Debug, Subcommand)]
+/// The enum of the subcommands.
+enum Command {
+    /// Run a workflow, from the standard input to the standard output.
+    Run(RunArgs),
+    /// Check a workflow and print its problems.
+    Validate {
+        /// The workflow file.
+        workflow: PathBuf,
+    },
+    /// Print the graph of a workflow, as Mermaid by default.
+    Graph {
+        /// The workflow file.
+        workflow: PathBuf,
+        /// Print Graphviz DOT instead of Mermaid.
+        #[arg(long)]
+        dot: bool,
+    },
+    /// Print the token usage of the runs in the execution log.
+    Usage {
+        /// The execution log, the one of the config by default.
+        #[arg(long, value_name = "FILE")]
+        log: Option<PathBuf>,
+    },
+    /// Serve workflows over HTTP, by the names of their files.
+    #[cfg(feature = "server")]
+    Serve {
+        /// The workflow files.
+        #[arg(required = true)]
+        workflows: Vec<PathBuf>,
+        /// The address to listen on.
+        #[arg(long, default_value = "127.0.0.1:8080")]
+        addr: String,
+        /// Reload the workflows when their files change.
+        #[arg(long)]
+        watch: bool,
+    },
+}
+
+#[derive(Debug, Args)]
+/// The struct of the arguments of `run`.
+struct RunArgs {
+    /// The workflow file.
+    workflow: PathBuf,
+    /// The input of the start node, instead of the standard input.
+    #[arg(short, long, value_name = "TEXT")]
+    input: Option<String>,
+    /// Write the report of the run as json to the file.
+    #[arg(long, value_name = "FILE")]
+    report: Option<PathBuf>,
+    /// Don't check the providers and the stores before the run.
+    #[arg(long)]
+    skip_preflight: bool,
+    /// Watch the run in a dashboard in the terminal.
+    #[cfg(feature = "tui")]
+    #[arg(long)]
+    monitor: bool,
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    let cli = Cli::parse();
+    setup_logger(cli.verbose);
+    let result = match load_config(cli.config.as_deref(), cli.profile.as_deref()) {
+        Ok(config) => match cli.command {
+            Command::Run(args) => run(&config, args).await,
+            Command::Validate { workflow } => validate(&config, &workflow),
+            Command::Graph { workflow, dot } => graph(&config, &workflow, dot),
+            Command::Usage { log } => usage(&config, log),
+            #[cfg(feature = "server")]
+            Command::Serve {
+                workflows,
+                addr,
+                watch,
+            } => serve(&config, &workflows, &addr, watch).await,
+        },
+        Err(e) => Err(e),
+    };
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(message) => {
+            if !message.is_empty() {
+                eprintln!("error: {}", message);
+            }
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// Write the logs to the standard error, the warnings by default.
+fn setup_logger(verbose: u8) {
+    let level = match verbose {
+        0 => log::LevelFilter::Warn,
+        1 => log::LevelFilter::Info,
+        _ => log::LevelFilter::Debug,
+    };
+    let result = fern::Dispatch::new()
+        .format(|out, message, record| out.finish(format_args!("[{}] {}", record.level(), message)))
+        .level(level)
+        .chain(std::io::stderr())
+        .apply();
+    if let Err(e) = result {
+        eprintln!("warning: can't set up the logger. {}", e);
+    }
+}
+
+/// Load the config of the file, or of `aipilot.toml` if it exists, or else an empty one.
+fn load_config(path: Option<&Path>, profile: Option<&str>) -> Result<Config, String> {
+    let mut loader = ConfigLoader::from_env();
+    if profile.is_some() {
+        loader = loader.profile(profile);
+    }
+    let result = match path {
+        Some(path) => loader.load(path),
+        None if Path::new(CONFIG_FILE).exists() => loader.load(CONFIG_FILE),
+        None => loader.parse(""),
+    };
+    result.map_err(|e| e.to_string())
+}
+
+/// Load the workflow file with the config.
+fn load_workflow(config: &Config, path: &Path) -> Result<Workflow, String> {
+    config
+        .load_workflow(path)
+        .map_err(|e| format!("Can't load the workflow {}. {}", path.display(), e))
+}
+
+/// Print the diagnostics to the standard error.
+fn print_diagnostics(diagnostics: &[Diagnostic]) {
+    for diagnostic in diagnostics {
+        eprintln!("{}", diagnostic);
+    }
+}
+
+/// Run the workflow and print its output.
+async fn run(config: &Config, args: RunArgs) -> Result<(), String> {
+    let mut workflow = load_workflow(config, &args.workflow)?;
+    if !args.skip_preflight {
+        if let Err(diagnostics) = workflow.preflight().await {
+            print_diagnostics(&diagnostics);
+            return Err(format!(
+                "The preflight check found {} problem(s). Use --skip-preflight to run anyway.",
+                diagnostics.len()
+            ));
+        }
+    }
+    let input = match args.input {
+        Some(input) => input,
+        None => read_stdin()?,
+    };
+
+    let token = CancellationToken::new();
+    let cancel = token.clone();
+    tokio::spawn(async move {
+        if tokio::signal::ctrl_c().await.is_ok() {
+            log::warn!("Cancelling the run.");
+            cancel.cancel();
+        }
+    });
+    let context = RunContext::new();
+    #[cfg(feature = "tui")]
+    let report = if args.monitor {
+        aipilot::tui::monitor(&mut workflow, input, &context, &token)
+            .await
+            .map_err(|e| format!("The dashboard failed. {}", e))?
+    } else {
+        workflow.run_report(input, &context, &token).await
+    };
+    #[cfg(not(feature = "tui"))]
+    let report = workflow.run_report(input, &context, &token).await;
+
+    if let Some(path) = &args.report {
+        if let Err(e) = std::fs::write(path, report.to_json()) {
+            log::warn!("Can't write the report to {}. {}", path.display(), e);
+        }
+    }
+    match (report.get_output(), report.get_error()) {
+        (Some(output), _) => {
+            println!("{}", output);
+            Ok(())
+        }
+        (None, Some(error)) => Err(error.to_string()),
+        (None, None) => Err(format!("The run is {}.", status_name(report.get_status()))),
+    }
+}
+
+/// Read the standard input, without its last line break.
+fn read_stdin() -> Result<String, String> {
+    let mut input = String::new();
+    std::io::stdin()
+        .read_to_string(&mut input)
+        .map_err(|e| format!("Can't read the standard input. {}", e))?;
+    if input.ends_with('\n') {
+        input.pop();
+        if input.ends_with('\r') {
+            input.pop();
+        }
+    }
+    Ok(input)
+}
+
+/// Check the workflow and print its problems.
+fn validate(config: &Config, path: &Path) -> Result<(), String> {
+    let workflow = load_workflow(config, path)?;
+    match workflow.validate() {
+        Ok(()) => {
+            println!("{} is valid.", path.display());
+            Ok(())
+        }
+        Err(diagnostics) => {
+            print_diagnostics(&diagnostics);
+            Err(format!(
+                "{} has {} problem(s).",
+                path.display(),
+                diagnostics.len()
+            ))
+        }
+    }
+}
+
+/// Print the graph of the workflow.
+fn graph(config: &Config, path: &Path, dot: bool) -> Result<(), String> {
+    let workflow = load_workflow(config, path)?;
+    if dot {
+        print!("{}", workflow.to_dot());
+    } else {
+        print!("{}", workflow.to_mermaid());
+    }
+    Ok(())
+}
+
+/// Print the usage of every run in the execution log.
+fn usage(config: &Config, log: Option<PathBuf>) -> Result<(), String> {
+    let Some(path) = log.or_else(|| config.storage.execution_log.clone()) else {
+        return Err(
+            "No execution log is given. Use --log or set storage.execution_log in the config."
+                .to_string(),
+        );
+    };
+    let runs = ExecutionLog::new(&path)
+        .usage()
+        .map_err(|e| e.to_string())?;
+    println!(
+        "{:<36}  {:<20}  {:<9}  {:>5}  {:>6}  {:>8}  {:>10}  {:>8}",
+        "RUN", "STARTED", "STATUS", "NODES", "FAILED", "PROMPT", "COMPLETION", "TIME"
+    );
+    let mut total = 0;
+    for run in &runs {
+        println!(
+            "{:<36}  {:<20}  {:<9}  {:>5}  {:>6}  {:>8}  {:>10}  {:>8}",
+            run.run,
+            run.started.format("%Y-%m-%d %H:%M:%S"),
+            run.status.map_or("running", status_name),
+            run.finished,
+            run.failed,
+            run.usage.get_prompt_tokens(),
+            run.usage.get_completion_tokens(),
+            run.duration_ms
+                .map_or("-".to_string(), |ms| format!("{:.1}s", ms as f64 / 1000.0)),
+        );
+        total += run.usage.get_total_tokens();
+    }
+    println!("{} run(s), {} tokens in total.", runs.len(), total);
+    Ok(())
+}
+
+/// Serve the workflows over HTTP until the process ends.
+#[cfg(feature = "server")]
+async fn serve(config: &Config, paths: &[PathBuf], addr: &str, watch: bool) -> Result<(), String> {
+    let mut server = aipilot::server::WorkflowServer::new();
+    for path in paths {
+        let name = path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().to_string())
+            .ok_or_else(|| format!("{} is not a workflow file.", path.display()))?;
+        if server.get_names().contains(&name.as_str()) {
+            return Err(format!("Two workflows are named {}.", name));
+        }
+        server = if watch {
+            let watcher = config
+                .watch_workflow(path)
+                .map_err(|e| format!("Can't load the workflow {}. {}", path.display(), e))?;
+            server.watcher(&name, watcher)
+        } else {
+            server.workflow(&name, load_workflow(config, path)?)
+        };
+    }
+    let listener = tokio::net::TcpListener::bind(addr)
+        .await
+        .map_err(|e| format!("Can't listen on {}. {}", addr, e))?;
+    eprintln!(
+        "Serving {} on http://{}",
+        server.get_names().join(", "),
+        addr
+    );
+    server.serve(listener).await.map_err(|e| e.to_string())
+}
+
+/// Get the name of how a run ended.
+fn status_name(status: RunStatus) -> &'static str {
+    match status {
+        RunStatus::Completed => "completed",
+        RunStatus::Failed => "failed",
+        RunStatus::Cancelled => "cancelled",
+    }
+}
@@ -0,0 +1,1321 @@
+//! # Config
+//!
+//! This module loads the configuration of the program from an `aipilot.toml` file, so the
+//! providers, their keys and the limits of the runs are written once instead of in the code
+//! of every program.
+//!
+//! The file has these parts:
+//! - `providers`: the AI services and the embeddings and rerank apis by name, with their url,
+//!   model, the source of their api key (see [`KeySource`]) and their default sampling
+//!   parameters.
+//! - `params`: the default sampling parameters of all the providers, below the ones of each
+//!   provider (see [`crate::worknode::ai_node::params`]).
+//! - `limits`: the limits of the runs, like the max number of nodes running at the same time
+//!   and the budget.
+//! - `storage`: the files of the checkpoints and the execution log.
+//! - `secrets`: the secrets managers by name, which the api keys may be read from with
+//!   `{ secret = { provider = "vault", name = "ai/deepseek#api_key" } }`.
+//! - `tools`: the built-in tools that the workflows may use, by the module of the tool (see
+//!   [`crate::worknode::ai_node::tool`]).
+//! - `stores`: the vector stores by name.
+//!
+//! ```toml
+//! [limits]
+//! max_parallelism = 8
+//! max_cost = 1.0
+//!
+//! [storage]
+//! execution_log = "runs.jsonl"
+//!
+//! [providers.deepseek]
+//! kind = "deepseek"
+//! model = "deepseek-chat"
+//! api_key = { first_of = [{ env = "DEEPSEEK_API_KEY" }, { file = "deepseek.key" }] }
+//! max_concurrency = 2
+//! params = { temperature = 0.7, max_tokens = 2048 }
+//!
+//! [providers.embed]
+//! kind = "ollama_embeddings"
+//! model = "nomic-embed-text"
+//! ```
+//!
+//! The clients are built from the name of their provider with `Config::service`,
+//! `Config::deepseek_client`, `Config::embedding_client` and `Config::rerank_client`, or all
+//! at once into a [`Registry`] with `Config::registry`. The api keys are read when a client is
+//! built, not when the file is loaded. `Config::configure` applies the limits and the storage
+//! to a workflow.
+//!
+//! A workflow file refers to the providers, the tools and the stores by their names in the
+//! config, so `Config::load_workflow` loads a workflow that runs with no code:
+//!
+//! ```toml
+//! [tools]
+//! calculate = true
+//! web_search = { backend = "brave", api_key = { env = "BRAVE_API_KEY" } }
+//!
+//! [stores.docs]
+//! kind = "sqlite"
+//! path = "docs.db"
+//! ```
+//!
+//! ## Profiles and overrides
+//!
+//! The file may have profiles, like `dev`, `staging` and `prod`, each a table of settings laid
+//! over the rest of the file when it is selected:
+//!
+//! ```toml
+//! [profiles.prod.limits]
+//! max_cost = 10.0
+//!
+//! [profiles.prod.providers.deepseek]
+//! model = "deepseek-reasoner"
+//! ```
+//!
+//! A setting is taken from the first of these that has it:
+//! 1. the per-node overrides: the sampling parameters of a node or of a request, over the
+//!    ones of its provider and the global `[params]` (see
+//!    [`crate::worknode::ai_node::params`]).
+//! 2. the environment overrides: the variable `AIPILOT__LIMITS__MAX_COST=2.5` sets
+//!    `limits.max_cost`. The parts of the name are separated by `__` and lowercased, and the
+//!    value is read as a toml value, or else as a string.
+//! 3. the selected profile.
+//! 4. the rest of the config file.
+//! 5. the built-in defaults, like the url of the kind of a provider.
+//!
+//! The layers are resolved once, when the config is loaded with a [`ConfigLoader`].
+//! `Config::load` selects the profile of the environment variable `AIPILOT_PROFILE` and applies
+//! the environment overrides, `Config::parse` applies neither. Where the settings were read
+//! from is kept as a [`ConfigOrigin`], which `Config::configure` gives to the workflow, and
+//! which is recorded in the report of every run.
+
+use crate::error::config_error::{ConfigError, ConfigErrorType, ConfigResult};
+use crate::error::PilotResult;
+use crate::vector_store::memory::MemoryVectorStore;
+use crate::vector_store::sqlite::SqliteVectorStore;
+use crate::vector_store::VectorStore;
+use crate::workflow::cost::Budget;
+use crate::workflow::definition::Registry;
+use crate::workflow::execution_log::ExecutionLog;
+use crate::workflow::reload::WorkflowWatcher;
+use crate::workflow::run::Pricing;
+use crate::workflow::Workflow;
+use crate::worknode::ai_node::deepseek::{DeepSeekClient, DeepSeekModel, DEEPSEEK_API_URL};
+use crate::worknode::ai_node::embedding::{
+    EmbeddingClient, EmbeddingProvider, OLLAMA_EMBEDDINGS_URL, OPENAI_EMBEDDINGS_URL,
+};
+use crate::worknode::ai_node::key::{ApiKey, KeySource};
+use crate::worknode::ai_node::rerank::{
+    RerankClient, RerankProvider, COHERE_RERANK_URL, JINA_RERANK_URL,
+};
+use crate::worknode::ai_node::secrets::{
+    AwsSecretsManager, SecretsProvider, SecretsRegistry, VaultSecrets,
+};
+use crate::worknode::ai_node::tool::filesystem::FileSystem;
+use crate::worknode::ai_node::tool::run_code::{CodeLanguage, RunCode};
+use crate::worknode::ai_node::tool::web_search::{
+    SearchBackend, WebSearch, BING_SEARCH_URL, BRAVE_SEARCH_URL,
+};
+use crate::worknode::ai_node::tool::{self, ToolRegistry};
+use crate::worknode::ai_node::AIService;
+
+pub use crate::worknode::ai_node::params::SamplingParams;
+
+use serde::{Deserialize, Serialize};
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// The name of the config file.
+pub const CONFIG_FILE: &str = "aipilot.toml";
+/// The environment variable of the profile that `Config::load` selects.
+pub const PROFILE_ENV: &str = "AIPILOT_PROFILE";
+/// The prefix of the environment variables that override the settings.
+pub const ENV_PREFIX: &str = "AIPILOT__";
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+/// The struct of the configuration of the program.
+pub struct Config {
+    /// The providers by name.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub providers: BTreeMap<String, ProviderConfig>,
+    /// The default sampling parameters of all the providers, below the ones of a provider.
+    #[serde(default, skip_serializing_if = "SamplingParams::is_empty")]
+    pub params: SamplingParams,
+    /// The limits of the runs.
+    #[serde(default)]
+    pub limits: Limits,
+    /// The files of the runs.
+    #[serde(default)]
+    pub storage: StorageConfig,
+    /// The secrets managers that the api keys are read from, by name.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub secrets: BTreeMap<String, SecretsConfig>,
+    /// The built-in tools that the workflows may use.
+    #[serde(default)]
+    pub tools: ToolsConfig,
+    /// The vector stores by name.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub stores: BTreeMap<String, StoreConfig>,
+    /// The profiles by name, the tables of settings laid over the config when selected.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub profiles: BTreeMap<String, toml::Table>,
+    /// Where the settings were read from.
+    #[serde(skip)]
+    pub origin: ConfigOrigin,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+/// The struct of where the settings of a config were read from.
+pub struct ConfigOrigin {
+    /// The config file, if the config was loaded from a file.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub file: Option<PathBuf>,
+    /// The profile laid over the file.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub profile: Option<String>,
+    /// The settings overridden by environment variables, like `limits.max_cost`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub env_overrides: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default)]
+/// The struct of how a config is loaded: the profile it selects and the environment
+/// overrides it applies.
+pub struct ConfigLoader {
+    /// The name of the profile.
+    profile: Option<String>,
+    /// The environment variables of the overrides, with the prefix, sorted by name.
+    env: Vec<(String, String)>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+/// The enum of the api of a provider.
+pub enum ProviderKind {
+    /// The DeepSeek chat api.
+    #[serde(rename = "deepseek")]
+    DeepSeek,
+    /// The OpenAI embeddings api.
+    #[serde(rename = "openai_embeddings")]
+    OpenAIEmbeddings,
+    /// The Ollama embed api.
+    OllamaEmbeddings,
+    /// The Cohere rerank api.
+    CohereRerank,
+    /// The Jina rerank api.
+    JinaRerank,
+}
+
+impl ProviderKind {
+    /// Get the url of the api, used when the provider has no url.
+    pub fn default_url(&self) -> &'static str {
+        match self {
+            ProviderKind::DeepSeek => DEEPSEEK_API_URL,
+            ProviderKind::OpenAIEmbeddings => OPENAI_EMBEDDINGS_URL,
+            ProviderKind::OllamaEmbeddings => OLLAMA_EMBEDDINGS_URL,
+            ProviderKind::CohereRerank => COHERE_RERANK_URL,
+            ProviderKind::JinaRerank => JINA_RERANK_URL,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+/// The struct of the configuration of a provider.
+pub struct ProviderConfig {
+    /// The api of the provider.
+    pub kind: ProviderKind,
+    /// The url of the api, or the default url of the kind.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    /// The model. A DeepSeek provider uses `deepseek-chat` by default, the others need one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    /// Where the api key is read from, not needed by a local server.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub api_key: Option<KeySource>,
+    /// The default sampling parameters, only used by a DeepSeek provider.
+    #[serde(default)]
+    pub params: SamplingParams,
+    /// The max time in seconds to connect to the api.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub connect_timeout_secs: Option<u64>,
+    /// The max time in seconds between two reads of the answer.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub read_timeout_secs: Option<u64>,
+    /// The max total time in seconds of a request.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timeout_secs: Option<u64>,
+    /// The number of dimensions of the vectors of an embeddings provider.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dimensions: Option<usize>,
+    /// The max number of texts in one request of an embeddings provider.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub batch_size: Option<usize>,
+    /// The max number of nodes of the provider running at the same time.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_concurrency: Option<usize>,
+    /// The price of the provider.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pricing: Option<Pricing>,
+}
+
+impl ProviderConfig {
+    /// Create a new ProviderConfig of the kind, with the defaults of the kind.
+    pub fn new(kind: ProviderKind) -> Self {
+        ProviderConfig {
+            kind,
+            url: None,
+            model: None,
+            api_key: None,
+            params: SamplingParams::default(),
+            connect_timeout_secs: None,
+            read_timeout_secs: None,
+            timeout_secs: None,
+            dimensions: None,
+            batch_size: None,
+            max_concurrency: None,
+            pricing: None,
+        }
+    }
+    /// Get the url of the api.
+    pub fn get_url(&self) -> &str {
+        self.url.as_deref().unwrap_or(self.kind.default_url())
+    }
+    /// Read the api key with the secrets providers, or `None` if the provider has no key
+    /// source.
+    pub fn resolve_api_key(&self, secrets: &SecretsRegistry) -> ConfigResult<Option<ApiKey>> {
+        self.api_key
+            .as_ref()
+            .map(|source| resolve_key(source, secrets))
+            .transpose()
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+/// The struct of the limits of the runs.
+pub struct Limits {
+    /// The max number of nodes running at the same time.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_parallelism: Option<usize>,
+    /// The max number of tokens of a run.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_tokens: Option<i64>,
+    /// The max cost of a run.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_cost: Option<f64>,
+}
+
+impl Limits {
+    /// Get the budget of a run, or `None` if there is no cap.
+    pub fn budget(&self) -> Option<Budget> {
+        (self.max_tokens.is_some() || self.max_cost.is_some()).then(|| {
+            Budget::new()
+                .max_tokens(self.max_tokens)
+                .max_cost(self.max_cost)
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case", deny_unknown_fields)]
+/// The enum of the configuration of a secrets manager.
+pub enum SecretsConfig {
+    /// The KV engine of HashiCorp Vault.
+    Vault {
+        /// The address of the server, or the environment variable `VAULT_ADDR`.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        address: Option<String>,
+        /// Where the token is read from, or the environment variable `VAULT_TOKEN`.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        token: Option<KeySource>,
+        /// Where the KV engine is mounted, `secret` by default.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        mount: Option<String>,
+        /// The namespace of Vault Enterprise.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        namespace: Option<String>,
+    },
+    /// AWS Secrets Manager, with the credentials of the environment variables.
+    AwsSecretsManager {
+        /// The region, or the environment variable `AWS_REGION`.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        region: Option<String>,
+        /// The url of the api, instead of the one of the region.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        endpoint: Option<String>,
+    },
+}
+
+impl SecretsConfig {
+    /// Build the secrets provider.
+    pub fn build(&self) -> ConfigResult<Arc<dyn SecretsProvider>> {
+        let var = |name: &str| {
+            std::env::var(name).map_err(|_| {
+                ConfigError::new(
+                    ConfigErrorType::MissingValue,
+                    format!("Environment variable {} not found.", name),
+                )
+            })
+        };
+        Ok(match self {
+            SecretsConfig::Vault {
+                address,
+                token,
+                mount,
+                namespace,
+            } => {
+                let address = match address {
+                    Some(address) => address.clone(),
+                    None => var("VAULT_ADDR")?,
+                };
+                let token = match token {
+                    Some(token) => token.resolve().map_err(|e| {
+                        ConfigError::new(
+                            ConfigErrorType::MissingValue,
+                            format!("The Vault token of {} can't be read. {}", token, e),
+                        )
+                    })?,
+                    None => var("VAULT_TOKEN")?.into(),
+                };
+                let vault =
+                    VaultSecrets::new(&address, token.expose()).namespace(namespace.clone());
+                Arc::new(match mount {
+                    Some(mount) => vault.mount(mount),
+                    None => vault,
+                })
+            }
+            SecretsConfig::AwsSecretsManager { region, endpoint } => {
+                let region = match region {
+                    Some(region) => region.clone(),
+                    None => var("AWS_REGION")?,
+                };
+                let aws = AwsSecretsManager::new(
+                    &region,
+                    &var("AWS_ACCESS_KEY_ID")?,
+                    &var("AWS_SECRET_ACCESS_KEY")?,
+                )
+                .session_token(std::env::var("AWS_SESSION_TOKEN").ok())
+                .endpoint(endpoint.clone());
+                Arc::new(aws)
+            }
+        })
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+/// The struct of the built-in tools that the workflows may use. A tool that is not
+/// configured is not registered.
+pub struct ToolsConfig {
+    /// The `web_search` tool.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub web_search: Option<WebSearchConfig>,
+    /// Whether the `calculate` tool is registered.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub calculate: bool,
+    /// The `run_code` tool.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub run_code: Option<RunCodeConfig>,
+    /// The `read_file`, `list_dir` and `write_file` tools.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub filesystem: Option<FileSystemConfig>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+/// The struct of the configuration of the `web_search` tool.
+pub struct WebSearchConfig {
+    /// The api the search is asked from.
+    pub backend: SearchBackend,
+    /// The url of the api, or the one of the backend. A SearxNG instance needs one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    /// Where the api key is read from, not needed by SearxNG.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub api_key: Option<KeySource>,
+    /// The number of results when the call doesn't give one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_results: Option<usize>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+/// The struct of the configuration of the `run_code` tool.
+pub struct RunCodeConfig {
+    /// The languages the model may use, or python and shell.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub languages: Option<Vec<CodeLanguage>>,
+    /// The python interpreter, or `python3`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub python: Option<String>,
+    /// The time in seconds the code may take before it is killed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timeout_secs: Option<u64>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+/// The struct of the configuration of the filesystem tools.
+pub struct FileSystemConfig {
+    /// The directories that the tools can reach.
+    pub roots: Vec<PathBuf>,
+    /// Whether `write_file` is registered.
+    #[serde(default)]
+    pub writable: bool,
+}
+
+impl ToolsConfig {
+    /// Build the registry of the tools, with the api keys read with the secrets providers.
+    pub fn build(&self, secrets: &SecretsRegistry) -> ConfigResult<ToolRegistry> {
+        let mut tools = ToolRegistry::new();
+        if let Some(config) = &self.web_search {
+            let url = match (&config.url, config.backend) {
+                (Some(url), _) => url.as_str(),
+                (None, SearchBackend::Brave) => BRAVE_SEARCH_URL,
+                (None, SearchBackend::Bing) => BING_SEARCH_URL,
+                (None, SearchBackend::Searxng) => {
+                    return Err(ConfigError::new(
+                        ConfigErrorType::MissingValue,
+                        "The web_search tool of SearxNG has no url.".to_string(),
+                    ))
+                }
+            };
+            let api_key = config
+                .api_key
+                .as_ref()
+                .map(|source| resolve_key(source, secrets))
+                .transpose()?;
+            let mut search = WebSearch::new(config.backend, url).api_key(api_key);
+            if let Some(max_results) = config.max_results {
+                search = search.max_results(max_results);
+            }
+            search.register(&mut tools);
+        }
+        if self.calculate {
+            tool::calculate::register(&mut tools);
+        }
+        if let Some(config) = &self.run_code {
+            let mut runner = RunCode::new();
+            if let Some(languages) = &config.languages {
+                runner = runner.languages(languages.clone());
+            }
+            if let Some(python) = &config.python {
+                runner = runner.python(python);
+            }
+            if let Some(timeout) = config.timeout_secs {
+                runner = runner.timeout(Duration::from_secs(timeout));
+            }
+            runner.register(&mut tools);
+        }
+        if let Some(config) = &self.filesystem {
+            let (first, others) = config.roots.split_first().ok_or_else(|| {
+                ConfigError::new(
+                    ConfigErrorType::MissingValue,
+                    "The filesystem tools have no root directory.".to_string(),
+                )
+            })?;
+            others
+                .iter()
+                .fold(FileSystem::new(first), FileSystem::root)
+                .writable(config.writable)
+                .register(&mut tools);
+        }
+        Ok(tools)
+    }
+    /// Get the key sources of the tools.
+    fn key_sources(&self) -> impl Iterator<Item = &KeySource> {
+        self.web_search
+            .iter()
+            .filter_map(|config| config.api_key.as_ref())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case", deny_unknown_fields)]
+/// The enum of the configuration of a vector store.
+pub enum StoreConfig {
+    /// A store in memory, empty when the workflow is loaded.
+    Memory,
+    /// A store in a SQLite file.
+    Sqlite {
+        /// The path of the database file, created if it doesn't exist.
+        path: PathBuf,
+    },
+}
+
+impl StoreConfig {
+    /// Open the vector store.
+    pub fn open(&self) -> ConfigResult<Arc<dyn VectorStore>> {
+        Ok(match self {
+            StoreConfig::Memory => Arc::new(MemoryVectorStore::new()),
+            StoreConfig::Sqlite { path } => {
+                Arc::new(SqliteVectorStore::open(path).map_err(|e| {
+                    ConfigError::new(
+                        ConfigErrorType::ReadError,
+                        format!(
+                            "Failed to open the vector store {}. {}",
+                            path.display(),
+                            e.get_message()
+                        ),
+                    )
+                })?)
+            }
+        })
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+/// The struct of the files of the runs.
+pub struct StorageConfig {
+    /// The file that the checkpoint of a run is saved to.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub checkpoint: Option<PathBuf>,
+    /// The JSON Lines file that the events of the runs are appended to.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub execution_log: Option<PathBuf>,
+}
+
+impl ConfigLoader {
+    /// Create a new ConfigLoader without profile and overrides.
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Create a new ConfigLoader of the environment: the profile of `AIPILOT_PROFILE`, and the
+    /// overrides of the variables that start with `AIPILOT__`.
+    pub fn from_env() -> Self {
+        let mut env: Vec<(String, String)> = std::env::vars()
+            .filter(|(name, _)| name.starts_with(ENV_PREFIX))
+            .collect();
+        env.sort();
+        ConfigLoader {
+            profile: std::env::var(PROFILE_ENV)
+                .ok()
+                .filter(|profile| !profile.is_empty()),
+            env,
+        }
+    }
+    /// Set the profile as builder.
+    pub fn profile(mut self, profile: Option<&str>) -> Self {
+        self.profile = profile.map(str::to_string);
+        self
+    }
+    /// Get the profile.
+    pub fn get_profile(&self) -> Option<&str> {
+        self.profile.as_deref()
+    }
+    /// Add the override of an environment variable as builder, like
+    /// `("AIPILOT__LIMITS__MAX_COST", "2.5")`. An override of the same variable is replaced.
+    pub fn env_override(mut self, name: &str, value: &str) -> Self {
+        self.env.retain(|(other, _)| other != name);
+        self.env.push((name.to_string(), value.to_string()));
+        self.env.sort();
+        self
+    }
+    /// Load the config from the toml file, and validate it.
+    pub fn load<P: AsRef<Path>>(&self, path: P) -> ConfigResult<Config> {
+        let path = path.as_ref();
+        let text = std::fs::read_to_string(path).map_err(|e| {
+            ConfigError::new(
+                ConfigErrorType::ReadError,
+                format!("Failed to read the config file {}. {}", path.display(), e),
+            )
+        })?;
+        self.resolve(&text, Some(path))
+    }
+    /// Parse the config from the toml text, and validate it.
+    pub fn parse(&self, text: &str) -> ConfigResult<Config> {
+        self.resolve(text, None)
+    }
+    /// Lay the profile and the overrides over the text, and parse the config.
+    fn resolve(&self, text: &str, file: Option<&Path>) -> ConfigResult<Config> {
+        let mut table: toml::Table = toml::from_str(text).map_err(parse_error)?;
+        let mut origin = ConfigOrigin {
+            file: file.map(Path::to_path_buf),
+            ..ConfigOrigin::default()
+        };
+        if let Some(profile) = &self.profile {
+            let overlay = table
+                .get("profiles")
+                .and_then(|profiles| profiles.get(profile))
+                .and_then(toml::Value::as_table)
+                .cloned()
+                .ok_or_else(|| {
+                    ConfigError::new(
+                        ConfigErrorType::MissingValue,
+                        format!("No profile is named {}.", profile),
+                    )
+                })?;
+            if overlay.contains_key("profiles") {
+                return Err(ConfigError::new(
+                    ConfigErrorType::InvalidValue,
+                    format!("The profile {} can't have profiles.", profile),
+                ));
+            }
+            merge(&mut table, overlay);
+            origin.profile = Some(profile.clone());
+        }
+        for (name, value) in &self.env {
+            let path: Vec<String> = name
+                .strip_prefix(ENV_PREFIX)
+                .unwrap_or(name)
+                .split("__")
+                .map(str::to_lowercase)
+                .collect();
+            if path.iter().any(String::is_empty) || path[0] == "profiles" {
+                return Err(ConfigError::new(
+                    ConfigErrorType::InvalidValue,
+                    format!("The environment variable {} is not a setting.", name),
+                ));
+            }
+            let overlay = path.iter().rev().fold(env_value(value), |value, key| {
+                toml::Value::Table(toml::Table::from_iter([(key.clone(), value)]))
+            });
+            if let toml::Value::Table(overlay) = overlay {
+                merge(&mut table, overlay);
+            }
+            origin.env_overrides.push(path.join("."));
+        }
+        let mut config: Config = toml::Value::Table(table).try_into().map_err(parse_error)?;
+        config.origin = origin;
+        config.validate()?;
+        Ok(config)
+    }
+}
+
+impl Config {
+    /// Create a new empty Config.
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Load the config from the toml file with the profile and the overrides of the
+    /// environment (see [`ConfigLoader::from_env`]), and validate it.
+    pub fn load<P: AsRef<Path>>(path: P) -> ConfigResult<Self> {
+        ConfigLoader::from_env().load(path)
+    }
+    /// Parse the config from the toml text without profile and overrides, and validate it.
+    pub fn parse(text: &str) -> ConfigResult<Self> {
+        ConfigLoader::new().parse(text)
+    }
+    /// Get where the settings were read from.
+    pub fn get_origin(&self) -> &ConfigOrigin {
+        &self.origin
+    }
+    /// Write the config as toml.
+    pub fn to_toml(&self) -> ConfigResult<String> {
+        toml::to_string(self).map_err(|e| {
+            ConfigError::new(
+                ConfigErrorType::InvalidValue,
+                format!("Failed to write the config. {}", e),
+            )
+        })
+    }
+    /// Add a provider as builder. A provider with the same name is replaced.
+    pub fn provider(mut self, name: &str, provider: ProviderConfig) -> Self {
+        self.providers.insert(name.to_string(), provider);
+        self
+    }
+    /// Get a provider by its name.
+    pub fn get_provider(&self, name: &str) -> ConfigResult<&ProviderConfig> {
+        self.providers.get(name).ok_or_else(|| {
+            ConfigError::new(
+                ConfigErrorType::MissingValue,
+                format!("No provider is named {}.", name),
+            )
+        })
+    }
+    /// Build the secrets providers of the config. Only the providers that a key source
+    /// refers to are built, so a missing credential of an unused one isn't an error.
+    pub fn secrets_registry(&self) -> ConfigResult<SecretsRegistry> {
+        self.build_secrets(
+            self.providers
+                .values()
+                .filter_map(|provider| provider.api_key.as_ref())
+                .chain(self.tools.key_sources()),
+        )
+    }
+    /// Build the secrets providers that the key sources refer to.
+    fn build_secrets<'a>(
+        &'a self,
+        sources: impl IntoIterator<Item = &'a KeySource>,
+    ) -> ConfigResult<SecretsRegistry> {
+        let mut used = Vec::new();
+        for source in sources {
+            secrets_of(source, &mut used);
+        }
+        let mut registry = SecretsRegistry::new();
+        for name in used {
+            let secrets = self.secrets.get(name).ok_or_else(|| {
+                ConfigError::new(
+                    ConfigErrorType::MissingValue,
+                    format!("No secrets provider is named {}.", name),
+                )
+            })?;
+            registry.register(name, secrets.build()?);
+        }
+        Ok(registry)
+    }
+    /// Check that every provider can build its client, without reading the api keys.
+    pub fn validate(&self) -> ConfigResult<()> {
+        check_params(
+            "The global params",
+            &DeepSeekClient::new(DEEPSEEK_API_URL, DeepSeekModel::DeepseekChat)
+                .sampling_params(&self.params),
+        )?;
+        for (name, provider) in &self.providers {
+            match provider.kind {
+                ProviderKind::DeepSeek => {
+                    self.build_deepseek(name, provider)?;
+                }
+                _ => {
+                    model_of(name, provider)?;
+                }
+            }
+        }
+        Ok(())
+    }
+    /// Build the DeepSeek client of the provider.
+    pub fn deepseek_client(&self, name: &str) -> ConfigResult<DeepSeekClient> {
+        let provider = self.get_provider(name)?;
+        if provider.kind != ProviderKind::DeepSeek {
+            return Err(wrong_kind(name, "a DeepSeek provider"));
+        }
+        let mut client = self.build_deepseek(name, provider)?;
+        client.set_api_key(provider.resolve_api_key(&self.build_secrets(&provider.api_key)?)?);
+        Ok(client)
+    }
+    /// Build the AI service of the provider.
+    pub fn service(&self, name: &str) -> ConfigResult<AIService> {
+        Ok(AIService::new_deepseek(self.deepseek_client(name)?))
+    }
+    /// Build the embeddings client of the provider.
+    pub fn embedding_client(&self, name: &str) -> ConfigResult<EmbeddingClient> {
+        let provider = self.get_provider(name)?;
+        let api = match provider.kind {
+            ProviderKind::OpenAIEmbeddings => EmbeddingProvider::OpenAI,
+            ProviderKind::OllamaEmbeddings => EmbeddingProvider::Ollama,
+            _ => return Err(wrong_kind(name, "an embeddings provider")),
+        };
+        let mut client = EmbeddingClient::new(api, provider.get_url(), model_of(name, provider)?)
+            .dimensions(provider.dimensions)
+            .api_key(provider.resolve_api_key(&self.build_secrets(&provider.api_key)?)?);
+        if let Some(batch_size) = provider.batch_size {
+            client.set_batch_size(batch_size);
+        }
+        Ok(client)
+    }
+    /// Build the rerank client of the provider.
+    pub fn rerank_client(&self, name: &str) -> ConfigResult<RerankClient> {
+        let provider = self.get_provider(name)?;
+        let api = match provider.kind {
+            ProviderKind::CohereRerank => RerankProvider::Cohere,
+            ProviderKind::JinaRerank => RerankProvider::Jina,
+            _ => return Err(wrong_kind(name, "a rerank provider")),
+        };
+        Ok(
+            RerankClient::new(api, provider.get_url(), model_of(name, provider)?)
+                .api_key(provider.resolve_api_key(&self.build_secrets(&provider.api_key)?)?),
+        )
+    }
+    /// Build the registry of the tools.
+    pub fn tool_registry(&self) -> ConfigResult<ToolRegistry> {
+        self.tools
+            .build(&self.build_secrets(self.tools.key_sources())?)
+    }
+    /// Build the clients of all the providers, the tools and the vector stores into a
+    /// registry, by the names of the providers and the stores.
+    pub fn registry(&self) -> ConfigResult<Registry> {
+        let mut registry = Registry::new().tools(self.tool_registry()?);
+        for (name, store) in &self.stores {
+            registry.register_vector_store(name, store.open()?);
+        }
+        for (name, provider) in &self.providers {
+            match provider.kind {
+                ProviderKind::DeepSeek => registry.register_service(name, self.service(name)?),
+                ProviderKind::OpenAIEmbeddings | ProviderKind::OllamaEmbeddings => {
+                    registry.register_embedder(name, self.embedding_client(name)?)
+                }
+                ProviderKind::CohereRerank | ProviderKind::JinaRerank => {
+                    registry.register_reranker(name, self.rerank_client(name)?)
+                }
+            }
+        }
+        Ok(registry)
+    }
+    /// Apply the limits, the storage and the limits and prices of the providers to the
+    /// workflow, and give it the origin of the config for the reports of its runs. The
+    /// settings that the config doesn't have are left as they are.
+    pub fn configure(&self, mut workflow: Workflow) -> Workflow {
+        workflow.set_config_origin(Some(self.origin.clone()));
+        if let Some(max_parallelism) = self.limits.max_parallelism {
+            workflow.set_max_parallelism(max_parallelism);
+        }
+        if let Some(budget) = self.limits.budget() {
+            workflow.set_budget(Some(budget));
+        }
+        if let Some(checkpoint) = &self.storage.checkpoint {
+            workflow.set_checkpoint_path(Some(checkpoint.clone()));
+        }
+        if let Some(execution_log) = &self.storage.execution_log {
+            workflow.set_execution_log(Some(ExecutionLog::new(execution_log)));
+        }
+        for (name, provider) in &self.providers {
+            if let Some(limit) = provider.max_concurrency {
+                workflow.set_provider_limit(name, limit);
+            }
+            if let Some(pricing) = provider.pricing {
+                workflow.set_price(name, pricing);
+            }
+        }
+        workflow
+    }
+    /// Load the workflow file, with the environment variables in its strings, and the
+    /// providers, the tools and the stores of the config that it refers to by name. The config
+    /// is then applied to the workflow, so its limits replace the ones of the file.
+    pub fn load_workflow<P: AsRef<Path>>(&self, path: P) -> PilotResult<Workflow> {
+        let workflow = Workflow::load_with_env(path, &self.registry()?)?;
+        Ok(self.configure(workflow))
+    }
+    /// Load the workflow file like `load_workflow`, and reload it when it changes (see
+    /// [`crate::workflow::reload`]). The clients, the tools and the stores are built once,
+    /// so the documents of a memory store are kept across the reloads.
+    pub fn watch_workflow<P: AsRef<Path>>(&self, path: P) -> PilotResult<WorkflowWatcher> {
+        let registry = self.registry()?;
+        let config = self.clone();
+        let mut watcher = WorkflowWatcher::new(path, move |path| {
+            Ok(config.configure(Workflow::load_with_env(path, &registry)?))
+        })?;
+        watcher.watch()?;
+        Ok(watcher)
+    }
+    /// Build the DeepSeek client of the provider without its api key, and check its
+    /// parameters.
+    fn build_deepseek(
+        &self,
+        name: &str,
+        provider: &ProviderConfig,
+    ) -> ConfigResult<DeepSeekClient> {
+        let model = match &provider.model {
+            Some(model) => model.parse::<DeepSeekModel>().map_err(|e| {
+                ConfigError::new(
+                    ConfigErrorType::InvalidValue,
+                    format!("The model of the provider {} is not valid. {}", name, e),
+                )
+            })?,
+            None => DeepSeekModel::DeepseekChat,
+        };
+        let client = DeepSeekClient::new(provider.get_url(), model)
+            .sampling_params(&provider.params)
+            .defaults(self.params.clone())
+            .connect_timeout(provider.connect_timeout_secs.map(Duration::from_secs))
+            .read_timeout(provider.read_timeout_secs.map(Duration::from_secs))
+            .timeout(provider.timeout_secs.map(Duration::from_secs));
+        check_params(&format!("The params of the provider {}", name), &client)?;
+        Ok(client)
+    }
+}
+
+/// Check the sampling parameters of the client, the error tells whose params they are.
+fn check_params(whose: &str, client: &DeepSeekClient) -> ConfigResult<()> {
+    client.validate().map_err(|violations| {
+        let violations: Vec<String> = violations.iter().map(ToString::to_string).collect();
+        ConfigError::new(
+            ConfigErrorType::InvalidValue,
+            format!("{} are not valid: {}.", whose, violations.join("; ")),
+        )
+    })
+}
+
+/// Lay the overlay over the table. The tables of both are merged, the other values of the
+/// overlay replace the ones of the table.
+fn merge(table: &mut toml::Table, overlay: toml::Table) {
+    for (key, value) in overlay {
+        match (table.get_mut(&key), value) {
+            (Some(toml::Value::Table(inner)), toml::Value::Table(overlay)) => merge(inner, overlay),
+            (_, value) => {
+                table.insert(key, value);
+            }
+        }
+    }
+}
+
+/// Read the value of an environment override as a toml value, or else as a string.
+fn env_value(text: &str) -> toml::Value {
+    toml::from_str::<toml::Table>(&format!("value = {}", text))
+        .ok()
+        .and_then(|mut table| table.remove("value"))
+        .unwrap_or_else(|| toml::Value::String(text.to_string()))
+}
+
+/// The error of a config that can't be parsed.
+fn parse_error(e: toml::de::Error) -> ConfigError {
+    ConfigError::new(
+        ConfigErrorType::ParseError,
+        format!("Failed to parse the config. {}", e),
+    )
+}
+
+/// Collect the names of the secrets providers that the key source refers to.
+fn secrets_of<'a>(source: &'a KeySource, names: &mut Vec<&'a str>) {
+    match source {
+        KeySource::Secret { provider, .. } if !names.contains(&provider.as_str()) => {
+            names.push(provider);
+        }
+        KeySource::FirstOf(sources) => {
+            for source in sources {
+                secrets_of(source, names);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Read the key of the source with the secrets providers.
+fn resolve_key(source: &KeySource, secrets: &SecretsRegistry) -> ConfigResult<ApiKey> {
+    source.resolve_with(secrets).map_err(|e| {
+        ConfigError::new(
+            ConfigErrorType::MissingValue,
+            format!("The api key of {} can't be read. {}", source, e),
+        )
+    })
+}
+
+/// Get the model of an embeddings or rerank provider, which is required.
+fn model_of<'a>(name: &str, provider: &'a ProviderConfig) -> ConfigResult<&'a str> {
+    provider.model.as_deref().ok_or_else(|| {
+        ConfigError::new(
+            ConfigErrorType::MissingValue,
+            format!("The provider {} has no model.", name),
+        )
+    })
+}
+
+/// The error of a provider that is not of the kind that is asked.
+fn wrong_kind(name: &str, expected: &str) -> ConfigError {
+    ConfigError::new(
+        ConfigErrorType::InvalidValue,
+        format!("The provider {} is not {}.", name, expected),
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::worknode::Worknodecore;
+
+    const CONFIG: &str = r#"
+[limits]
+max_parallelism = 4
+max_cost = 1.5
+
+[storage]
+checkpoint = "run.checkpoint.json"
+
+[providers.deepseek]
+kind = "deepseek"
+model = "deepseek-reasoner"
+api_key = { env = "AIPILOT_CONFIG_TEST_KEY" }
+timeout_secs = 60
+max_concurrency = 2
+pricing = { prompt_cache_hit = 0.07, prompt_cache_miss = 0.27, completion = 1.1 }
+params = { temperature = 0.7, max_tokens = 2048, stop = ["END"] }
+
+[providers.embed]
+kind = "ollama_embeddings"
+model = "nomic-embed-text"
+batch_size = 16
+"#;
+
+    #[test]
+    fn load_providers() {
+        std::env::set_var("AIPILOT_CONFIG_TEST_KEY", "sk-test");
+        let config = Config::parse(CONFIG).unwrap();
+        let client = config.deepseek_client("deepseek").unwrap();
+        assert_eq!(client.get_url(), DEEPSEEK_API_URL);
+        assert!(matches!(
+            client.get_model(),
+            DeepSeekModel::DeepseekReasoner
+        ));
+        assert_eq!(client.get_api_key(), Some("sk-test"));
+        assert_eq!(client.get_temperature(), Some(0.7));
+        assert_eq!(client.get_max_tokens(), Some(2048));
+        assert_eq!(client.get_timeout(), Some(Duration::from_secs(60)));
+
+        let embedder = config.embedding_client("embed").unwrap();
+        assert_eq!(embedder.get_url(), OLLAMA_EMBEDDINGS_URL);
+        assert_eq!(embedder.get_batch_size(), 16);
+        assert_eq!(embedder.get_api_key(), None);
+        let registry = config.registry().unwrap();
+        assert!(registry.get_service("deepseek").is_some());
+        assert!(registry.get_embedder("embed").is_some());
+
+        let workflow = config.configure(Workflow::new());
+        assert_eq!(workflow.get_max_parallelism(), 4);
+        assert_eq!(workflow.get_budget().unwrap().max_cost, Some(1.5));
+        assert_eq!(workflow.get_provider_limit("deepseek"), Some(2));
+        assert_eq!(workflow.get_price("deepseek").unwrap().completion, 1.1);
+        assert_eq!(
+            workflow.get_checkpoint_path(),
+            Some(&PathBuf::from("run.checkpoint.json"))
+        );
+        assert_eq!(Config::parse(&config.to_toml().unwrap()).unwrap(), config);
+
+        // the errors
+        assert!(matches!(
+            config
+                .embedding_client("deepseek")
+                .unwrap_err()
+                .get_error_type(),
+            ConfigErrorType::InvalidValue
+        ));
+        assert!(matches!(
+            config.service("missing").unwrap_err().get_error_type(),
+            ConfigErrorType::MissingValue
+        ));
+        let unsupported = config.service("embed").unwrap_err();
+        assert!(matches!(
+            unsupported.get_error_type(),
+            ConfigErrorType::InvalidValue
+        ));
+        assert_eq!(
+            unsupported.get_message(),
+            "The provider embed is not a DeepSeek provider."
+        );
+        let invalid = Config::parse(
+            "[providers.deepseek]\nkind = \"deepseek\"\nparams = { temperature = 3.0 }",
+        )
+        .unwrap_err();
+        assert!(matches!(
+            invalid.get_error_type(),
+            ConfigErrorType::InvalidValue
+        ));
+        assert!(invalid.get_message().contains("temperature is 3"));
+        let invalid = Config::parse("[params]\ntop_p = 2.0").unwrap_err();
+        assert!(invalid.get_message().starts_with("The global params"));
+        let global = Config::parse(
+            "[params]\ntemperature = 0.3\ntop_p = 0.8\n[providers.deepseek]\nkind = \"deepseek\"\nparams = { temperature = 0.7 }",
+        )
+        .unwrap();
+        let params = global
+            .deepseek_client("deepseek")
+            .unwrap()
+            .effective_params(&Default::default());
+        assert_eq!(params.temperature.value, 0.7);
+        assert_eq!(params.top_p.value, 0.8);
+        assert!(matches!(
+            Config::parse("[providers.embed]\nkind = \"openai_embeddings\"")
+                .unwrap_err()
+                .get_error_type(),
+            ConfigErrorType::MissingValue
+        ));
+        assert!(matches!(
+            Config::parse("[limits]\nmax_paralelism = 4")
+                .unwrap_err()
+                .get_error_type(),
+            ConfigErrorType::ParseError
+        ));
+        let missing_key = Config::new().provider(
+            "deepseek",
+            ProviderConfig {
+                api_key: Some(KeySource::Env("AIPILOT_CONFIG_TEST_UNSET".to_string())),
+                ..ProviderConfig::new(ProviderKind::DeepSeek)
+            },
+        );
+        assert!(missing_key.validate().is_ok());
+        assert!(missing_key.service("deepseek").is_err());
+
+        let secret = Config::parse(
+            r#"
+[providers.deepseek]
+kind = "deepseek"
+api_key = { secret = { provider = "vault", name = "ai/deepseek#api_key" } }
+
+[secrets.vault]
+kind = "vault"
+address = "http://127.0.0.1:8200"
+token = { literal = "s.token" }
+"#,
+        )
+        .unwrap();
+        assert!(secret.secrets_registry().unwrap().get("vault").is_some());
+        let mut unknown = secret.clone();
+        unknown.secrets.clear();
+        assert_eq!(
+            unknown.service("deepseek").unwrap_err().get_message(),
+            "No secrets provider is named vault."
+        );
+    }
+
+    #[test]
+    fn profiles_and_overrides() {
+        let text = format!(
+            "{}\n{}",
+            CONFIG,
+            r#"
+[profiles.dev.limits]
+max_parallelism = 1
+
+[profiles.prod.limits]
+max_cost = 10.0
+
+[profiles.prod.providers.deepseek]
+model = "deepseek-chat"
+params = { temperature = 0.2 }
+"#
+        );
+        let base = Config::parse(&text).unwrap();
+        assert_eq!(base.limits.max_cost, Some(1.5));
+        assert_eq!(base.get_origin(), &ConfigOrigin::default());
+
+        // the profile is laid over the file, and the env overrides over the profile
+        let prod = ConfigLoader::new()
+            .profile(Some("prod"))
+            .env_override("AIPILOT__LIMITS__MAX_COST", "2.5")
+            .env_override("AIPILOT__PROVIDERS__DEEPSEEK__TIMEOUT_SECS", "30")
+            .parse(&text)
+            .unwrap();
+        assert_eq!(prod.limits.max_cost, Some(2.5));
+        assert_eq!(prod.limits.max_parallelism, Some(4));
+        let deepseek = prod.get_provider("deepseek").unwrap();
+        assert_eq!(deepseek.model.as_deref(), Some("deepseek-chat"));
+        assert_eq!(deepseek.params.temperature, Some(0.2));
+        assert_eq!(deepseek.params.max_tokens, Some(2048));
+        assert_eq!(deepseek.timeout_secs, Some(30));
+        assert_eq!(
+            prod.get_origin(),
+            &ConfigOrigin {
+                file: None,
+                profile: Some("prod".to_string()),
+                env_overrides: vec![
+                    "limits.max_cost".to_string(),
+                    "providers.deepseek.timeout_secs".to_string()
+                ],
+            }
+        );
+        let workflow = prod.configure(Workflow::new());
+        assert_eq!(workflow.get_config_origin(), Some(prod.get_origin()));
+
+        let path = std::env::temp_dir().join(format!("aipilot-{}.toml", uuid::Uuid::new_v4()));
+        std::fs::write(&path, &text).unwrap();
+        let dev = ConfigLoader::new()
+            .profile(Some("dev"))
+            .env_override("AIPILOT__STORAGE__EXECUTION_LOG", "runs.jsonl")
+            .load(&path)
+            .unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(dev.limits.max_parallelism, Some(1));
+        assert_eq!(dev.storage.execution_log, Some(PathBuf::from("runs.jsonl")));
+        assert_eq!(dev.get_origin().file.as_ref(), Some(&path));
+
+        // the errors
+        assert_eq!(
+            ConfigLoader::new()
+                .profile(Some("staging"))
+                .parse(&text)
+                .unwrap_err()
+                .get_message(),
+            "No profile is named staging."
+        );
+        assert!(matches!(
+            ConfigLoader::new()
+                .env_override("AIPILOT__LIMITS__MAX_COST", "cheap")
+                .parse(&text)
+                .unwrap_err()
+                .get_error_type(),
+            ConfigErrorType::ParseError
+        ));
+        assert!(ConfigLoader::new()
+            .env_override("AIPILOT__LIMITS____MAX_COST", "1")
+            .parse(&text)
+            .is_err());
+    }
+
+    #[test]
+    fn workflow_from_files() {
+        let config = Config::parse(
+            r#"
+[providers.deepseek]
+kind = "deepseek"
+api_key = { literal = "sk-literal" }
+
+[providers.embed]
+kind = "ollama_embeddings"
+model = "nomic-embed-text"
+
+[tools]
+calculate = true
+filesystem = { roots = ["."] }
+
+[stores.docs]
+kind = "memory"
+
+[limits]
+max_cost = 0.5
+"#,
+        )
+        .unwrap();
+        let tools = config.tool_registry().unwrap().names();
+        assert!(tools.contains(&"calculate".to_string()));
+        assert!(tools.contains(&"read_file".to_string()));
+        assert!(!tools.contains(&"write_file".to_string()));
+
+        std::env::set_var("AIPILOT_WORKFLOW_TEST_TOP_K", "3");
+        let path = std::env::temp_dir().join(format!("aipilot-{}.yaml", uuid::Uuid::new_v4()));
+        std::fs::write(
+            &path,
+            r#"
+nodes:
+  - uid: 8d1f7a6e-0000-4000-8000-000000000001
+    type: start
+  - uid: 8d1f7a6e-0000-4000-8000-000000000002
+    type: retrieve
+    provider: embed
+    store: docs
+    top_k: ${AIPILOT_WORKFLOW_TEST_TOP_K}
+  - uid: 8d1f7a6e-0000-4000-8000-000000000003
+    type: ai_node
+    provider: deepseek
+    role: You answer in ${AIPILOT_WORKFLOW_TEST_UNSET:-English}
+    tools: [calculate]
+  - uid: 8d1f7a6e-0000-4000-8000-000000000004
+    type: end
+edges:
+  - from: 8d1f7a6e-0000-4000-8000-000000000001
+    to: 8d1f7a6e-0000-4000-8000-000000000002
+  - from: 8d1f7a6e-0000-4000-8000-000000000002
+    to: 8d1f7a6e-0000-4000-8000-000000000003
+  - from: 8d1f7a6e-0000-4000-8000-000000000003
+    to: 8d1f7a6e-0000-4000-8000-000000000004
+"#,
+        )
+        .unwrap();
+        let workflow = config.load_workflow(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(workflow.get_budget().unwrap().max_cost, Some(0.5));
+        match workflow.get_nodes()[1].get_node() {
+            Worknodecore::Retrieve(retrieve) => {
+                assert_eq!(retrieve.get_options().top_k, 3);
+                assert_eq!(retrieve.get_store_name(), Some("docs"));
+            }
+            _ => panic!("The node should be a retrieve node"),
+        }
+        match workflow.get_nodes()[2].get_node() {
+            Worknodecore::AINode(ai_node) => {
+                assert_eq!(ai_node.get_role().as_deref(), Some("You answer in English"));
+                assert_eq!(ai_node.get_tools().names(), vec!["calculate"]);
+            }
+            _ => panic!("The node should be an AI node"),
+        }
+
+        let searxng = Config::parse("[tools]\nweb_search = { backend = \"searxng\" }").unwrap();
+        assert!(matches!(
+            searxng.registry().unwrap_err().get_error_type(),
+            ConfigErrorType::MissingValue
+        ));
+    }
+}
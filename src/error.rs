@@ -2,16 +2,136 @@
 //!
 //! This module is for a unified error handling. All errors that will happen in the program
 //! should be defined here in a hierarchical way.
+//!
+//! Every error has a stable code, the names of its types from the root down to the leaf
+//! joined by dots, like `ai_node.deepseek_error.request_error`, so a program can tell errors
+//! apart without parsing their text. The names are the ones the errors are serialized with,
+//! and a `PilotError` is serialized as `{"code", "message", "class", "error_type"}`, where
+//! `error_type` holds the whole hierarchy with the messages and the details of every layer.
+//!
+//! As an error goes up, the layers it passes can add what they were doing with
+//! `PilotError::context` or the `Context` trait on results, like the node, its provider and
+//! the attempt. The breadcrumbs are kept from the innermost to the outermost, printed under
+//! the error and serialized as `context`, so a report of a run with many nodes tells where a
+//! failure came from.
 
 pub mod ai_node_error;
+pub mod assert_node_error;
+pub mod chunker_node_error;
+pub mod config_error;
+pub mod delay_node_error;
+pub mod file_node_error;
+pub mod graph_error;
+pub mod ingest_node_error;
+pub mod load_node_error;
+pub mod local_node_error;
+pub mod map_node_error;
+pub mod notify_node_error;
+pub mod planner_node_error;
+pub mod reduce_node_error;
+pub mod retrieve_node_error;
+pub mod script_node_error;
+pub mod storage_error;
+pub mod template_error;
+pub mod timeout_error;
+pub mod transform_node_error;
+pub mod user_node_error;
+pub mod vector_store_error;
+pub mod wasm_node_error;
 
 use ai_node_error::AINodeError;
+use assert_node_error::AssertNodeError;
+use chunker_node_error::ChunkerNodeError;
+use config_error::ConfigError;
+use delay_node_error::DelayNodeError;
+use file_node_error::FileNodeError;
+use graph_error::GraphError;
+use ingest_node_error::IngestNodeError;
+use load_node_error::LoadNodeError;
+use local_node_error::LocalNodeError;
+use map_node_error::MapNodeError;
+use notify_node_error::NotifyNodeError;
+use planner_node_error::PlannerNodeError;
+use reduce_node_error::ReduceNodeError;
+use retrieve_node_error::RetrieveNodeError;
+use script_node_error::ScriptNodeError;
+use storage_error::StorageError;
+use timeout_error::TimeoutError;
+use transform_node_error::TransformNodeError;
+use user_node_error::UserNodeError;
+use wasm_node_error::WasmNodeError;
 
-#[derive(Debug)]
+use crate::worknode::retry::ErrorClass;
+
+use serde::ser::SerializeStruct;
+use serde::{Serialize, Serializer};
+
+#[derive(Debug, Serialize)]
 /// The enum of the error type.
 pub enum PilotErrorType {
     /// The error happens in ai node
+    #[serde(rename = "ai_node")]
     AINodeErr(AINodeError),
+    /// The error happens in the workflow graph
+    #[serde(rename = "graph")]
+    GraphErr(GraphError),
+    /// The error happens in local node
+    #[serde(rename = "local_node")]
+    LocalNodeErr(LocalNodeError),
+    /// The error happens in wasm node
+    #[serde(rename = "wasm_node")]
+    WasmNodeErr(WasmNodeError),
+    /// The error happens in script node
+    #[serde(rename = "script_node")]
+    ScriptNodeErr(ScriptNodeError),
+    /// The error happens in file read or file write node
+    #[serde(rename = "file_node")]
+    FileNodeErr(FileNodeError),
+    /// The error happens in user node
+    #[serde(rename = "user_node")]
+    UserNodeErr(UserNodeError),
+    /// The error happens in json transform node
+    #[serde(rename = "transform_node")]
+    TransformNodeErr(TransformNodeError),
+    /// The error happens in map node
+    #[serde(rename = "map_node")]
+    MapNodeErr(MapNodeError),
+    /// The error happens in reduce node
+    #[serde(rename = "reduce_node")]
+    ReduceNodeErr(ReduceNodeError),
+    /// The error happens in delay node
+    #[serde(rename = "delay_node")]
+    DelayNodeErr(DelayNodeError),
+    /// The error happens in assert node
+    #[serde(rename = "assert_node")]
+    AssertNodeErr(AssertNodeError),
+    /// The error happens in notify node
+    #[serde(rename = "notify_node")]
+    NotifyNodeErr(NotifyNodeError),
+    /// The error happens in chunker node
+    #[serde(rename = "chunker_node")]
+    ChunkerNodeErr(ChunkerNodeError),
+    /// The error happens in load node
+    #[serde(rename = "load_node")]
+    LoadNodeErr(LoadNodeError),
+    /// The error happens in retrieve node
+    #[serde(rename = "retrieve_node")]
+    RetrieveNodeErr(RetrieveNodeError),
+    /// The error happens in ingest node
+    #[serde(rename = "ingest_node")]
+    IngestNodeErr(IngestNodeError),
+    /// The error happens in planner node
+    #[serde(rename = "planner_node")]
+    PlannerNodeErr(PlannerNodeError),
+    /// The error happens in the configuration
+    #[serde(rename = "config")]
+    ConfigErr(ConfigError),
+    /// The error happens in the storage of the engine
+    #[serde(rename = "storage")]
+    StorageErr(StorageError),
+    /// The error happens when a node takes too long
+    #[serde(rename = "timeout")]
+    TimeoutErr(TimeoutError),
 }
 
 #[derive(Debug)]
@@ -19,6 +139,8 @@ pub enum PilotErrorType {
 pub struct PilotError {
     error_type: PilotErrorType,
     message: String,
+    /// What the layers were doing when the error went through them, innermost first.
+    context: Vec<String>,
 }
 
 impl PilotError {
@@ -27,16 +149,266 @@ impl PilotError {
         PilotError {
             error_type,
             message,
+            context: Vec::new(),
         }
     }
+    /// Add what was being done when the error happened, like `while executing node X`, as
+    /// builder.
+    pub fn context<C: Into<String>>(mut self, context: C) -> Self {
+        self.context.push(context.into());
+        self
+    }
+    /// Get what was being done when the error happened, innermost first.
+    pub fn get_context(&self) -> &[String] {
+        &self.context
+    }
+    /// Get the type of the error.
+    pub fn get_error_type(&self) -> &PilotErrorType {
+        &self.error_type
+    }
+    /// Get the message of the error.
+    pub fn get_message(&self) -> &str {
+        &self.message
+    }
+    /// Get the stable code of the error, like `ai_node.deepseek_error.request_error`.
+    pub fn code(&self) -> String {
+        // the error types only hold strings, numbers and other errors
+        code_of(&serde_json::to_value(&self.error_type).unwrap_or_default())
+    }
+    /// Get the class of the error, which tells how it may be handled.
+    pub fn class(&self) -> ErrorClass {
+        ErrorClass::of(self)
+    }
+    /// Whether the error is transient, so a retry may succeed.
+    pub fn is_retryable(&self) -> bool {
+        self.class().is_retryable()
+    }
 }
 
 impl std::fmt::Display for PilotError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match &self.error_type {
             PilotErrorType::AINodeErr(ref e) => write!(f, "AINodeError: {}\n{}", self.message, e),
+            PilotErrorType::GraphErr(ref e) => write!(f, "GraphError: {}\n{}", self.message, e),
+            PilotErrorType::LocalNodeErr(ref e) => {
+                write!(f, "LocalNodeError: {}\n{}", self.message, e)
+            }
+            PilotErrorType::WasmNodeErr(ref e) => {
+                write!(f, "WasmNodeError: {}\n{}", self.message, e)
+            }
+            PilotErrorType::ScriptNodeErr(ref e) => {
+                write!(f, "ScriptNodeError: {}\n{}", self.message, e)
+            }
+            PilotErrorType::FileNodeErr(ref e) => {
+                write!(f, "FileNodeError: {}\n{}", self.message, e)
+            }
+            PilotErrorType::UserNodeErr(ref e) => {
+                write!(f, "UserNodeError: {}\n{}", self.message, e)
+            }
+            PilotErrorType::TransformNodeErr(ref e) => {
+                write!(f, "TransformNodeError: {}\n{}", self.message, e)
+            }
+            PilotErrorType::MapNodeErr(ref e) => {
+                write!(f, "MapNodeError: {}\n{}", self.message, e)
+            }
+            PilotErrorType::ReduceNodeErr(ref e) => {
+                write!(f, "ReduceNodeError: {}\n{}", self.message, e)
+            }
+            PilotErrorType::DelayNodeErr(ref e) => {
+                write!(f, "DelayNodeError: {}\n{}", self.message, e)
+            }
+            PilotErrorType::AssertNodeErr(ref e) => {
+                write!(f, "AssertNodeError: {}\n{}", self.message, e)
+            }
+            PilotErrorType::NotifyNodeErr(ref e) => {
+                write!(f, "NotifyNodeError: {}\n{}", self.message, e)
+            }
+            PilotErrorType::ChunkerNodeErr(ref e) => {
+                write!(f, "ChunkerNodeError: {}\n{}", self.message, e)
+            }
+            PilotErrorType::LoadNodeErr(ref e) => {
+                write!(f, "LoadNodeError: {}\n{}", self.message, e)
+            }
+            PilotErrorType::RetrieveNodeErr(ref e) => {
+                write!(f, "RetrieveNodeError: {}\n{}", self.message, e)
+            }
+            PilotErrorType::IngestNodeErr(ref e) => {
+                write!(f, "IngestNodeError: {}\n{}", self.message, e)
+            }
+            PilotErrorType::PlannerNodeErr(ref e) => {
+                write!(f, "PlannerNodeError: {}\n{}", self.message, e)
+            }
+            PilotErrorType::ConfigErr(ref e) => write!(f, "ConfigError: {}\n{}", self.message, e),
+            PilotErrorType::StorageErr(ref e) => {
+                write!(f, "StorageError: {}\n{}", self.message, e)
+            }
+            PilotErrorType::TimeoutErr(ref e) => {
+                write!(f, "TimeoutError: {}\n{}", self.message, e)
+            }
+        }?;
+        for context in &self.context {
+            write!(f, "\n  {}", context)?;
         }
+        Ok(())
+    }
+}
+
+impl Serialize for PilotError {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut error = serializer.serialize_struct("PilotError", 5)?;
+        error.serialize_field("code", &self.code())?;
+        error.serialize_field("message", &self.message)?;
+        error.serialize_field("class", &self.class())?;
+        if self.context.is_empty() {
+            error.skip_field("context")?;
+        } else {
+            error.serialize_field("context", &self.context)?;
+        }
+        error.serialize_field("error_type", &self.error_type)?;
+        error.end()
+    }
+}
+
+/// The trait that adds what was being done to the error of a result, like
+/// `node.execute(input).await.context("while summarizing the answers")`. It works on the
+/// errors of every layer, which are turned into a PilotError.
+pub trait Context<T> {
+    /// Add the context to the error, if there is one.
+    fn context<C: Into<String>>(self, context: C) -> PilotResult<T>;
+    /// Add the context built by the function to the error, if there is one. The function is
+    /// only called on an error.
+    fn with_context<C: Into<String>, F: FnOnce() -> C>(self, context: F) -> PilotResult<T>;
+}
+
+impl<T, E: Into<PilotError>> Context<T> for Result<T, E> {
+    fn context<C: Into<String>>(self, context: C) -> PilotResult<T> {
+        self.map_err(|e| e.into().context(context))
     }
+    fn with_context<C: Into<String>, F: FnOnce() -> C>(self, context: F) -> PilotResult<T> {
+        self.map_err(|e| e.into().context(context()))
+    }
+}
+
+/// Get the code of a serialized error type: the name of the variant, followed by the code of
+/// the error it holds, if any.
+fn code_of(error_type: &serde_json::Value) -> String {
+    match error_type {
+        serde_json::Value::String(name) => name.clone(),
+        serde_json::Value::Object(variant) => match variant.iter().next() {
+            Some((name, inner)) => match inner.get("error_type") {
+                Some(inner) => format!("{}.{}", name, code_of(inner)),
+                None => name.clone(),
+            },
+            None => String::new(),
+        },
+        _ => String::new(),
+    }
+}
+
+/// Convert the errors of the layers into a PilotError with the message of the layer, so they
+/// can be returned with `?`. A new error type gets a line here.
+macro_rules! from_layer_errors {
+    ($($error:ty => $variant:ident, $message:literal;)*) => {
+        $(
+            impl From<$error> for PilotError {
+                fn from(e: $error) -> Self {
+                    PilotError::new(PilotErrorType::$variant(e), $message.to_string())
+                }
+            }
+        )*
+    };
+}
+
+from_layer_errors! {
+    AINodeError => AINodeErr, "AI node failed to execute";
+    GraphError => GraphErr, "The workflow is not valid";
+    LocalNodeError => LocalNodeErr, "Local node failed to execute";
+    WasmNodeError => WasmNodeErr, "Wasm node failed to execute";
+    ScriptNodeError => ScriptNodeErr, "Script node failed to execute";
+    FileNodeError => FileNodeErr, "File node failed to execute";
+    UserNodeError => UserNodeErr, "User node failed to execute";
+    TransformNodeError => TransformNodeErr, "Json transform node failed to execute";
+    MapNodeError => MapNodeErr, "Map node failed to execute";
+    ReduceNodeError => ReduceNodeErr, "Reduce node failed to execute";
+    DelayNodeError => DelayNodeErr, "Delay node failed to execute";
+    AssertNodeError => AssertNodeErr, "Assert node failed to execute";
+    NotifyNodeError => NotifyNodeErr, "Notify node failed to execute";
+    ChunkerNodeError => ChunkerNodeErr, "Chunker node failed to execute";
+    LoadNodeError => LoadNodeErr, "Load node failed to execute";
+    RetrieveNodeError => RetrieveNodeErr, "Retrieve node failed to execute";
+    IngestNodeError => IngestNodeErr, "Ingest node failed to execute";
+    PlannerNodeError => PlannerNodeErr, "Planner node failed to execute";
+    ConfigError => ConfigErr, "The configuration is not valid";
+    StorageError => StorageErr, "The storage failed";
+    TimeoutError => TimeoutErr, "The node timed out";
 }
 
 pub type PilotResult<T> = Result<T, PilotError>;
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use ai_node_error::deepseek_error::{DeepSeekError, DeepSeekErrorType};
+    use ai_node_error::AINodeErrorType;
+    use map_node_error::MapNodeErrorType;
+
+    #[test]
+    fn codes_and_json() {
+        let error: PilotError = AINodeError::from(
+            DeepSeekError::new(DeepSeekErrorType::ApiKeyError, "Invalid key.".to_string())
+                .status(Some(401)),
+        )
+        .into();
+        assert_eq!(error.code(), "ai_node.deepseek_error.api_key_error");
+        let json = serde_json::to_value(&error).unwrap();
+        assert_eq!(json["code"], "ai_node.deepseek_error.api_key_error");
+        assert_eq!(json["class"], "auth");
+        assert_eq!(json["message"], "AI node failed to execute");
+        let deepseek = &json["error_type"]["ai_node"]["error_type"]["deepseek_error"];
+        assert_eq!(deepseek["status"], 401);
+        assert!(deepseek.get("body").is_none());
+
+        let error: PilotError = MapNodeError::new(
+            MapNodeErrorType::ItemFailed(Box::new(error)),
+            "The item 2 failed.".to_string(),
+        )
+        .into();
+        assert_eq!(
+            error.code(),
+            "map_node.item_failed.ai_node.deepseek_error.api_key_error"
+        );
+        let error: PilotError =
+            AINodeError::new(AINodeErrorType::InvalidInput, "Empty.".to_string()).into();
+        assert_eq!(error.code(), "ai_node.invalid_input");
+
+        let error = crate::workflow::checkpoint::Checkpoint::load("/nonexistent/checkpoint.json")
+            .unwrap_err();
+        assert_eq!(error.code(), "storage.read_error");
+
+        let result: PilotResult<()> = Err(AINodeError::new(
+            AINodeErrorType::InvalidInput,
+            "Empty.".to_string(),
+        ))
+        .context("while building the prompt");
+        let error = result
+            .with_context(|| "while executing node 1234abcd")
+            .unwrap_err();
+        assert_eq!(
+            error.get_context(),
+            ["while building the prompt", "while executing node 1234abcd"]
+        );
+        assert!(error
+            .to_string()
+            .ends_with("\n  while building the prompt\n  while executing node 1234abcd"));
+        let json = serde_json::to_value(&error).unwrap();
+        assert_eq!(json["context"][1], "while executing node 1234abcd");
+        assert!(serde_json::to_value(PilotError::from(AINodeError::new(
+            AINodeErrorType::InvalidInput,
+            "Empty.".to_string(),
+        )))
+        .unwrap()
+        .get("context")
+        .is_none());
+        assert!(!error.is_retryable());
+    }
+}
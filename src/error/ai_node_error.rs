@@ -3,17 +3,77 @@
 //! This module defines all errors that will happen in ai node.
 
 pub mod deepseek_error;
+pub mod embedding_error;
+pub mod rerank_error;
 
+use super::template_error::TemplateError;
+use super::vector_store_error::VectorStoreError;
 use deepseek_error::DeepSeekError;
+use embedding_error::EmbeddingError;
+use rerank_error::RerankError;
 
-#[derive(Debug)]
+use serde::Serialize;
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "snake_case")]
 /// The enum of the ai node error type.
 pub enum AINodeErrorType {
     /// The error happens in DeepSeek.
-    DeepSeekError(DeepSeekError),
+    #[serde(rename = "deepseek_error")]
+    DeepSeekError(Box<DeepSeekError>),
+    /// The error happens when texts are embedded.
+    EmbeddingError(Box<EmbeddingError>),
+    /// The error happens when documents are reranked.
+    RerankError(Box<RerankError>),
+    /// The output of the AI service is not valid json while json output is required.
+    InvalidJsonOutput,
+    /// The input of the AI node is not valid.
+    InvalidInput,
+    /// The history can't be saved or loaded.
+    HistoryError,
+    /// The session doesn't exist or already exists.
+    SessionError,
+    /// The prompt template can't be rendered.
+    TemplateError(TemplateError),
+    /// The output doesn't match the json schema of the AI node.
+    SchemaViolation(Vec<SchemaViolation>),
+    /// The tool calls of the AI service can't be handled.
+    ToolError,
+    /// The recording of the requests can't be saved or loaded.
+    RecordingError,
+    /// The answer of the AI service matches none of the routes of a router node.
+    RouteError,
+    /// The store of the long-term memory can't be read or written.
+    MemoryError(VectorStoreError),
+    /// The MCP server can't be reached, or fails a request.
+    McpError,
+    /// The web search api can't be reached, or refuses the search.
+    SearchError,
+    /// The budget of the run is spent before the agent finished.
+    BudgetExceeded,
+}
+
+#[derive(Debug, Clone, Serialize)]
+/// The struct of one place where the output violates the json schema.
+pub struct SchemaViolation {
+    /// The json pointer to the violating value in the output, empty for the root.
+    pub path: String,
+    /// What is wrong with the value.
+    pub message: String,
+}
+
+impl std::fmt::Display for SchemaViolation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let path = if self.path.is_empty() {
+            "/"
+        } else {
+            &self.path
+        };
+        write!(f, "{}: {}", path, self.message)
+    }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Serialize)]
 /// The struct of the ai node error.
 pub struct AINodeError {
     error_type: AINodeErrorType,
@@ -28,6 +88,14 @@ impl AINodeError {
             message,
         }
     }
+    /// Get the type of the error.
+    pub fn get_error_type(&self) -> &AINodeErrorType {
+        &self.error_type
+    }
+    /// Get the message of the error.
+    pub fn get_message(&self) -> &str {
+        &self.message
+    }
 }
 
 impl std::fmt::Display for AINodeError {
@@ -36,8 +104,72 @@ impl std::fmt::Display for AINodeError {
             AINodeErrorType::DeepSeekError(e) => {
                 write!(f, "DeepSeekError: {}\n{}", self.message, e)
             }
+            AINodeErrorType::EmbeddingError(e) => {
+                write!(f, "EmbeddingError: {}\n{}", self.message, e)
+            }
+            AINodeErrorType::RerankError(e) => {
+                write!(f, "RerankError: {}\n{}", self.message, e)
+            }
+            AINodeErrorType::InvalidJsonOutput => {
+                write!(f, "InvalidJsonOutput: {}", self.message)
+            }
+            AINodeErrorType::InvalidInput => {
+                write!(f, "InvalidInput: {}", self.message)
+            }
+            AINodeErrorType::HistoryError => {
+                write!(f, "HistoryError: {}", self.message)
+            }
+            AINodeErrorType::SessionError => {
+                write!(f, "SessionError: {}", self.message)
+            }
+            AINodeErrorType::TemplateError(e) => {
+                write!(f, "TemplateError: {}\n{}", self.message, e)
+            }
+            AINodeErrorType::ToolError => write!(f, "ToolError: {}", self.message),
+            AINodeErrorType::RecordingError => write!(f, "RecordingError: {}", self.message),
+            AINodeErrorType::RouteError => write!(f, "RouteError: {}", self.message),
+            AINodeErrorType::McpError => write!(f, "McpError: {}", self.message),
+            AINodeErrorType::SearchError => write!(f, "SearchError: {}", self.message),
+            AINodeErrorType::BudgetExceeded => write!(f, "BudgetExceeded: {}", self.message),
+            AINodeErrorType::MemoryError(e) => {
+                write!(f, "MemoryError: {}\n{}", self.message, e)
+            }
+            AINodeErrorType::SchemaViolation(violations) => {
+                write!(f, "SchemaViolation: {}", self.message)?;
+                for violation in violations {
+                    write!(f, "\n  {}", violation)?;
+                }
+                Ok(())
+            }
         }
     }
 }
 
+impl From<DeepSeekError> for AINodeError {
+    fn from(e: DeepSeekError) -> Self {
+        AINodeError::new(
+            AINodeErrorType::DeepSeekError(Box::new(e)),
+            "Failed to send request to DeepSeek".to_string(),
+        )
+    }
+}
+
+impl From<EmbeddingError> for AINodeError {
+    fn from(e: EmbeddingError) -> Self {
+        AINodeError::new(
+            AINodeErrorType::EmbeddingError(Box::new(e)),
+            "Failed to embed the texts.".to_string(),
+        )
+    }
+}
+
+impl From<RerankError> for AINodeError {
+    fn from(e: RerankError) -> Self {
+        AINodeError::new(
+            AINodeErrorType::RerankError(Box::new(e)),
+            "Failed to rerank the documents.".to_string(),
+        )
+    }
+}
+
 pub type AINodeResult<T> = Result<T, AINodeError>;
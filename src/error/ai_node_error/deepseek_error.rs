@@ -1,25 +1,161 @@
 //! # DeepSeek Error
 //!
 //! This module defines all errors that will happen in DeepSeek.
+//!
+//! Besides the message, the error carries the details of the HTTP exchange when there is one
+//! (status code, `x-request-id`, raw body and endpoint), so callers can tell a 401 from a 429
+//! and correlate the failure with the logs of the provider. When the provider answers with
+//! an error, its kind is parsed from the status and the json body into an `ApiErrorKind`, so
+//! callers can branch on the failure, like an account out of balance or a prompt too long for
+//! the model, without matching the text of the message.
+
+use super::super::timeout_error::TimeoutError;
+
+use serde::Serialize;
 
-#[derive(Debug)]
+#[derive(Debug, Serialize)]
+#[allow(clippy::enum_variant_names)]
+#[serde(rename_all = "snake_case")]
 /// The enum of the DeepSeek error type.
 pub enum DeepSeekErrorType {
-    /// The parameter of the request for deepseek api is wrong.
-    RequestParamError,
+    /// The parameters of the request for deepseek api are wrong, with every parameter that
+    /// is wrong.
+    RequestParamError(Box<Vec<ParamViolation>>),
     /// The reqeust send to deepseek is failed.
     RequestError,
     /// The response from deepseek is not valid.
     ResponseError,
     /// Error with api key
     ApiKeyError,
+    /// The request can't be answered by the recording that is replayed.
+    ReplayError,
+    /// The request timed out.
+    TimeoutError(Box<TimeoutError>),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+/// The struct of a parameter of the request that is not valid.
+pub struct ParamViolation {
+    /// The name of the parameter, like `temperature`.
+    pub field: String,
+    /// The value of the parameter.
+    pub value: String,
+    /// The values that are allowed, like `between 0 and 2`.
+    pub allowed: String,
+}
+
+impl ParamViolation {
+    /// Create a new ParamViolation.
+    pub fn new(field: &str, value: String, allowed: &str) -> Self {
+        ParamViolation {
+            field: field.to_string(),
+            value,
+            allowed: allowed.to_string(),
+        }
+    }
+}
+
+impl std::fmt::Display for ParamViolation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} is {}, but it must be {}",
+            self.field, self.value, self.allowed
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+/// The enum of the kind of an error answered by the DeepSeek api.
+pub enum ApiErrorKind {
+    /// The body of the request is not valid (HTTP 400).
+    InvalidRequest,
+    /// The api key is wrong (HTTP 401).
+    Authentication,
+    /// The account has run out of balance (HTTP 402).
+    InsufficientBalance,
+    /// A parameter of the request is not valid (HTTP 422).
+    InvalidParameters,
+    /// The prompt and the completion don't fit in the context of the model.
+    ContextLengthExceeded,
+    /// Too many requests are sent (HTTP 429).
+    RateLimit,
+    /// The server fails (HTTP 500).
+    ServerError,
+    /// The server is overloaded (HTTP 503).
+    ServerOverloaded,
+    /// Any other error.
+    Unknown,
+}
+
+impl ApiErrorKind {
+    /// Get the kind of an error from the status of the response, and the `type`, `code` and
+    /// `message` of the `error` object of its body, which tell more than the status, like a
+    /// 400 that is a too long prompt.
+    pub fn of(status: u16, error_type: &str, code: &str, message: &str) -> Self {
+        let error = format!("{} {}", error_type, code).to_lowercase();
+        let message = message.to_lowercase();
+        if error.contains("context_length") || message.contains("context length") {
+            ApiErrorKind::ContextLengthExceeded
+        } else if error.contains("insufficient") || message.contains("insufficient balance") {
+            ApiErrorKind::InsufficientBalance
+        } else if error.contains("rate_limit") {
+            ApiErrorKind::RateLimit
+        } else if error.contains("auth") {
+            ApiErrorKind::Authentication
+        } else {
+            match status {
+                400 => ApiErrorKind::InvalidRequest,
+                401 => ApiErrorKind::Authentication,
+                402 => ApiErrorKind::InsufficientBalance,
+                422 => ApiErrorKind::InvalidParameters,
+                429 => ApiErrorKind::RateLimit,
+                503 => ApiErrorKind::ServerOverloaded,
+                500..600 => ApiErrorKind::ServerError,
+                _ if error.contains("invalid_request") => ApiErrorKind::InvalidRequest,
+                _ => ApiErrorKind::Unknown,
+            }
+        }
+    }
+}
+
+impl std::fmt::Display for ApiErrorKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ApiErrorKind::InvalidRequest => write!(f, "InvalidRequest"),
+            ApiErrorKind::Authentication => write!(f, "Authentication"),
+            ApiErrorKind::InsufficientBalance => write!(f, "InsufficientBalance"),
+            ApiErrorKind::InvalidParameters => write!(f, "InvalidParameters"),
+            ApiErrorKind::ContextLengthExceeded => write!(f, "ContextLengthExceeded"),
+            ApiErrorKind::RateLimit => write!(f, "RateLimit"),
+            ApiErrorKind::ServerError => write!(f, "ServerError"),
+            ApiErrorKind::ServerOverloaded => write!(f, "ServerOverloaded"),
+            ApiErrorKind::Unknown => write!(f, "Unknown"),
+        }
+    }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Serialize)]
 /// The struct of the DeepSeek error.
 pub struct DeepSeekError {
     error_type: DeepSeekErrorType,
     message: String,
+    /// The kind of the error answered by the api, if it answered with an error.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    api_error: Option<ApiErrorKind>,
+    /// The HTTP status code of the response, if a response was received.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    status: Option<u16>,
+    /// The `x-request-id` header of the response, if the provider sent one.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    request_id: Option<String>,
+    /// The raw body of the error response.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    body: Option<String>,
+    /// The endpoint the request was sent to.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    endpoint: Option<String>,
 }
 
 impl DeepSeekError {
@@ -28,26 +164,109 @@ impl DeepSeekError {
         DeepSeekError {
             error_type,
             message,
+            api_error: None,
+            status: None,
+            request_id: None,
+            body: None,
+            endpoint: None,
         }
     }
+    /// Set the kind of the error answered by the api as builder.
+    pub fn api_error(mut self, api_error: Option<ApiErrorKind>) -> Self {
+        self.api_error = api_error;
+        self
+    }
+    /// Set the HTTP status code as builder.
+    pub fn status(mut self, status: Option<u16>) -> Self {
+        self.status = status;
+        self
+    }
+    /// Set the request id as builder.
+    pub fn request_id(mut self, request_id: Option<String>) -> Self {
+        self.request_id = request_id;
+        self
+    }
+    /// Set the raw response body as builder.
+    pub fn body(mut self, body: Option<String>) -> Self {
+        self.body = body;
+        self
+    }
+    /// Set the endpoint as builder.
+    pub fn endpoint(mut self, endpoint: Option<String>) -> Self {
+        self.endpoint = endpoint;
+        self
+    }
+    /// Get the type of the error.
+    pub fn get_error_type(&self) -> &DeepSeekErrorType {
+        &self.error_type
+    }
+    /// Get the message of the error.
+    pub fn get_message(&self) -> &str {
+        &self.message
+    }
+    /// Get the kind of the error answered by the api.
+    pub fn get_api_error(&self) -> Option<ApiErrorKind> {
+        self.api_error
+    }
+    /// Get the HTTP status code.
+    pub fn get_status(&self) -> Option<u16> {
+        self.status
+    }
+    /// Get the request id.
+    pub fn get_request_id(&self) -> Option<&str> {
+        self.request_id.as_deref()
+    }
+    /// Get the raw response body.
+    pub fn get_body(&self) -> Option<&str> {
+        self.body.as_deref()
+    }
+    /// Get the endpoint.
+    pub fn get_endpoint(&self) -> Option<&str> {
+        self.endpoint.as_deref()
+    }
 }
 
 impl std::fmt::Display for DeepSeekError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match &self.error_type {
-            DeepSeekErrorType::RequestParamError => {
-                write!(f, "RequestParamError: {}", self.message)
+            DeepSeekErrorType::RequestParamError(violations) => {
+                write!(f, "RequestParamError: {}", self.message)?;
+                for violation in violations.iter() {
+                    write!(f, "\n  {}", violation)?;
+                }
             }
             DeepSeekErrorType::RequestError => {
-                write!(f, "RequestError: {}", self.message)
+                write!(f, "RequestError: {}", self.message)?;
             }
             DeepSeekErrorType::ResponseError => {
-                write!(f, "ResponseError: {}", self.message)
+                write!(f, "ResponseError: {}", self.message)?;
             }
             DeepSeekErrorType::ApiKeyError => {
-                write!(f, "ApiKeyError: {}", self.message)
+                write!(f, "ApiKeyError: {}", self.message)?;
+            }
+            DeepSeekErrorType::ReplayError => {
+                write!(f, "ReplayError: {}", self.message)?;
+            }
+            DeepSeekErrorType::TimeoutError(e) => {
+                write!(f, "TimeoutError: {}\n{}", self.message, e)?;
             }
         }
+        if let Some(api_error) = self.api_error {
+            write!(f, "\n  api error: {}", api_error)?;
+        }
+        if let Some(status) = self.status {
+            write!(f, "\n  status: {}", status)?;
+        }
+        if let Some(request_id) = &self.request_id {
+            write!(f, "\n  request id: {}", request_id)?;
+        }
+        if let Some(endpoint) = &self.endpoint {
+            write!(f, "\n  endpoint: {}", endpoint)?;
+        }
+        if let Some(body) = &self.body {
+            write!(f, "\n  body: {}", body)?;
+        }
+        Ok(())
     }
 }
 
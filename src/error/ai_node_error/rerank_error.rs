@@ -0,0 +1,91 @@
+//! # Rerank Error
+//!
+//! This module defines all errors that will happen when documents are reranked.
+//!
+//! Like the embedding error, the error carries the status code and the endpoint of the HTTP
+//! exchange when there is one, so a 429 can be told from a 401.
+
+use serde::Serialize;
+
+#[derive(Debug, Serialize)]
+#[allow(clippy::enum_variant_names)]
+#[serde(rename_all = "snake_case")]
+/// The enum of the rerank error type.
+pub enum RerankErrorType {
+    /// The request to the rerank api is failed.
+    RequestError,
+    /// The response from the rerank api is not valid.
+    ResponseError,
+    /// Error with api key.
+    ApiKeyError,
+}
+
+#[derive(Debug, Serialize)]
+/// The struct of the rerank error.
+pub struct RerankError {
+    error_type: RerankErrorType,
+    message: String,
+    /// The HTTP status code of the response, if a response was received.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    status: Option<u16>,
+    /// The endpoint the request was sent to.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    endpoint: Option<String>,
+}
+
+impl RerankError {
+    /// Create a new RerankError.
+    pub fn new(error_type: RerankErrorType, message: String) -> RerankError {
+        RerankError {
+            error_type,
+            message,
+            status: None,
+            endpoint: None,
+        }
+    }
+    /// Set the HTTP status code as builder.
+    pub fn status(mut self, status: Option<u16>) -> Self {
+        self.status = status;
+        self
+    }
+    /// Set the endpoint as builder.
+    pub fn endpoint(mut self, endpoint: Option<String>) -> Self {
+        self.endpoint = endpoint;
+        self
+    }
+    /// Get the type of the error.
+    pub fn get_error_type(&self) -> &RerankErrorType {
+        &self.error_type
+    }
+    /// Get the message of the error.
+    pub fn get_message(&self) -> &str {
+        &self.message
+    }
+    /// Get the HTTP status code.
+    pub fn get_status(&self) -> Option<u16> {
+        self.status
+    }
+    /// Get the endpoint.
+    pub fn get_endpoint(&self) -> Option<&str> {
+        self.endpoint.as_deref()
+    }
+}
+
+impl std::fmt::Display for RerankError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.error_type {
+            RerankErrorType::RequestError => write!(f, "RequestError: {}", self.message)?,
+            RerankErrorType::ResponseError => write!(f, "ResponseError: {}", self.message)?,
+            RerankErrorType::ApiKeyError => write!(f, "ApiKeyError: {}", self.message)?,
+        }
+        if let Some(status) = self.status {
+            write!(f, "\n  status: {}", status)?;
+        }
+        if let Some(endpoint) = &self.endpoint {
+            write!(f, "\n  endpoint: {}", endpoint)?;
+        }
+        Ok(())
+    }
+}
+
+pub type RerankResult<T> = Result<T, RerankError>;
@@ -0,0 +1,79 @@
+//! # Assert Node Error
+//!
+//! This module defines all errors that will happen in assert node.
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+/// The struct of one rule that the input breaks.
+pub struct Violation {
+    /// The name of the rule, like `regex`.
+    pub rule: String,
+    /// The json pointer to the checked value in the input, empty for the whole input.
+    pub path: String,
+    /// What is wrong with the value.
+    pub message: String,
+}
+
+impl std::fmt::Display for Violation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let path = if self.path.is_empty() {
+            "/"
+        } else {
+            &self.path
+        };
+        write!(f, "{} {}: {}", self.rule, path, self.message)
+    }
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "snake_case")]
+/// The enum of the assert node error type.
+pub enum AssertNodeErrorType {
+    /// A rule can't be checked, like a regex with a syntax error.
+    InvalidRule,
+    /// The input breaks the rules.
+    Violated(Vec<Violation>),
+}
+
+#[derive(Debug, Serialize)]
+/// The struct of the assert node error.
+pub struct AssertNodeError {
+    error_type: AssertNodeErrorType,
+    message: String,
+}
+
+impl AssertNodeError {
+    /// Create a new AssertNodeError.
+    pub fn new(error_type: AssertNodeErrorType, message: String) -> AssertNodeError {
+        AssertNodeError {
+            error_type,
+            message,
+        }
+    }
+    /// Get the type of the error.
+    pub fn get_error_type(&self) -> &AssertNodeErrorType {
+        &self.error_type
+    }
+    /// Get the message of the error.
+    pub fn get_message(&self) -> &str {
+        &self.message
+    }
+}
+
+impl std::fmt::Display for AssertNodeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.error_type {
+            AssertNodeErrorType::InvalidRule => write!(f, "InvalidRule: {}", self.message),
+            AssertNodeErrorType::Violated(violations) => {
+                write!(f, "Violated: {}", self.message)?;
+                for violation in violations {
+                    write!(f, "\n  {}", violation)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+pub type AssertNodeResult<T> = Result<T, AssertNodeError>;
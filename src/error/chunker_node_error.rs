@@ -0,0 +1,48 @@
+//! # Chunker Node Error
+//!
+//! This module defines all errors that will happen in chunker node.
+
+use serde::Serialize;
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "snake_case")]
+/// The enum of the chunker node error type.
+pub enum ChunkerNodeErrorType {
+    /// The size or the overlap of the chunks is not valid.
+    InvalidConfig,
+}
+
+#[derive(Debug, Serialize)]
+/// The struct of the chunker node error.
+pub struct ChunkerNodeError {
+    error_type: ChunkerNodeErrorType,
+    message: String,
+}
+
+impl ChunkerNodeError {
+    /// Create a new ChunkerNodeError.
+    pub fn new(error_type: ChunkerNodeErrorType, message: String) -> ChunkerNodeError {
+        ChunkerNodeError {
+            error_type,
+            message,
+        }
+    }
+    /// Get the type of the error.
+    pub fn get_error_type(&self) -> &ChunkerNodeErrorType {
+        &self.error_type
+    }
+    /// Get the message of the error.
+    pub fn get_message(&self) -> &str {
+        &self.message
+    }
+}
+
+impl std::fmt::Display for ChunkerNodeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.error_type {
+            ChunkerNodeErrorType::InvalidConfig => write!(f, "InvalidConfig: {}", self.message),
+        }
+    }
+}
+
+pub type ChunkerNodeResult<T> = Result<T, ChunkerNodeError>;
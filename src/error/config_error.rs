@@ -0,0 +1,59 @@
+//! # Config Error
+//!
+//! This module defines all errors that will happen when the configuration of the program is
+//! loaded, like a config file that can't be read or a setting that is missing or not valid.
+
+use serde::Serialize;
+
+#[derive(Debug, Serialize)]
+#[allow(clippy::enum_variant_names)]
+#[serde(rename_all = "snake_case")]
+/// The enum of the config error type.
+pub enum ConfigErrorType {
+    /// The config can't be read from its source.
+    ReadError,
+    /// The config is not in a valid format.
+    ParseError,
+    /// A setting of the config is not valid.
+    InvalidValue,
+    /// A setting that is required is missing.
+    MissingValue,
+}
+
+#[derive(Debug, Serialize)]
+/// The struct of the config error.
+pub struct ConfigError {
+    error_type: ConfigErrorType,
+    message: String,
+}
+
+impl ConfigError {
+    /// Create a new ConfigError.
+    pub fn new(error_type: ConfigErrorType, message: String) -> ConfigError {
+        ConfigError {
+            error_type,
+            message,
+        }
+    }
+    /// Get the type of the error.
+    pub fn get_error_type(&self) -> &ConfigErrorType {
+        &self.error_type
+    }
+    /// Get the message of the error.
+    pub fn get_message(&self) -> &str {
+        &self.message
+    }
+}
+
+impl std::fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.error_type {
+            ConfigErrorType::ReadError => write!(f, "ReadError: {}", self.message),
+            ConfigErrorType::ParseError => write!(f, "ParseError: {}", self.message),
+            ConfigErrorType::InvalidValue => write!(f, "InvalidValue: {}", self.message),
+            ConfigErrorType::MissingValue => write!(f, "MissingValue: {}", self.message),
+        }
+    }
+}
+
+pub type ConfigResult<T> = Result<T, ConfigError>;
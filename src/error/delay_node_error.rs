@@ -0,0 +1,51 @@
+//! # Delay Node Error
+//!
+//! This module defines all errors that will happen in delay node.
+
+use serde::Serialize;
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "snake_case")]
+/// The enum of the delay node error type.
+pub enum DelayNodeErrorType {
+    /// The cron expression has a syntax error.
+    ParseError,
+    /// The cron schedule has no moment to wait for, like the 30th of February.
+    NoMoment,
+}
+
+#[derive(Debug, Serialize)]
+/// The struct of the delay node error.
+pub struct DelayNodeError {
+    error_type: DelayNodeErrorType,
+    message: String,
+}
+
+impl DelayNodeError {
+    /// Create a new DelayNodeError.
+    pub fn new(error_type: DelayNodeErrorType, message: String) -> DelayNodeError {
+        DelayNodeError {
+            error_type,
+            message,
+        }
+    }
+    /// Get the type of the error.
+    pub fn get_error_type(&self) -> &DelayNodeErrorType {
+        &self.error_type
+    }
+    /// Get the message of the error.
+    pub fn get_message(&self) -> &str {
+        &self.message
+    }
+}
+
+impl std::fmt::Display for DelayNodeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.error_type {
+            DelayNodeErrorType::ParseError => write!(f, "ParseError: {}", self.message),
+            DelayNodeErrorType::NoMoment => write!(f, "NoMoment: {}", self.message),
+        }
+    }
+}
+
+pub type DelayNodeResult<T> = Result<T, DelayNodeError>;
@@ -0,0 +1,64 @@
+//! # File Node Error
+//!
+//! This module defines all errors that will happen in file read and file write nodes.
+
+use super::template_error::TemplateError;
+
+use serde::Serialize;
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "snake_case")]
+/// The enum of the file node error type.
+pub enum FileNodeErrorType {
+    /// The path is not under any of the allowed paths.
+    PathNotAllowed,
+    /// The file, or the content to write, is larger than the size limit.
+    TooLarge,
+    /// The file can't be read or written.
+    IoError,
+    /// The content can't be decoded or encoded with the encoding.
+    EncodingError,
+    /// The path template can't be rendered.
+    TemplateError(TemplateError),
+}
+
+#[derive(Debug, Serialize)]
+/// The struct of the file node error.
+pub struct FileNodeError {
+    error_type: FileNodeErrorType,
+    message: String,
+}
+
+impl FileNodeError {
+    /// Create a new FileNodeError.
+    pub fn new(error_type: FileNodeErrorType, message: String) -> FileNodeError {
+        FileNodeError {
+            error_type,
+            message,
+        }
+    }
+    /// Get the type of the error.
+    pub fn get_error_type(&self) -> &FileNodeErrorType {
+        &self.error_type
+    }
+    /// Get the message of the error.
+    pub fn get_message(&self) -> &str {
+        &self.message
+    }
+}
+
+impl std::fmt::Display for FileNodeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.error_type {
+            FileNodeErrorType::PathNotAllowed => write!(f, "PathNotAllowed: {}", self.message),
+            FileNodeErrorType::TooLarge => write!(f, "TooLarge: {}", self.message),
+            FileNodeErrorType::IoError => write!(f, "IoError: {}", self.message),
+            FileNodeErrorType::EncodingError => write!(f, "EncodingError: {}", self.message),
+            FileNodeErrorType::TemplateError(e) => {
+                write!(f, "TemplateError: {}\n{}", self.message, e)
+            }
+        }
+    }
+}
+
+pub type FileNodeResult<T> = Result<T, FileNodeError>;
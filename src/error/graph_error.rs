@@ -0,0 +1,79 @@
+//! # Graph Error
+//!
+//! This module defines all errors that will happen when building or running a workflow graph.
+
+use serde::Serialize;
+
+#[derive(Debug, Serialize)]
+#[allow(clippy::enum_variant_names)]
+#[serde(rename_all = "snake_case")]
+/// The enum of the graph error type.
+pub enum GraphErrorType {
+    /// The node is not in the workflow.
+    NodeNotFound,
+    /// The workflow doesn't have exactly one start node or one end node.
+    StartEndError,
+    /// The workflow has a cycle, so it can't be executed in order.
+    CycleError,
+    /// The end node can't be reached from the start node.
+    UnreachableEnd,
+    /// The workflow can't be saved to or loaded from a file.
+    DefinitionError,
+    /// A value of the run context can't be stored or taken out.
+    ContextError,
+    /// The run was cancelled.
+    Cancelled,
+    /// An approval can't be requested or decided.
+    ApprovalError,
+    /// The deadline of the run passed before the end node was reached.
+    DeadlineExceeded,
+    /// The budget of the run is spent.
+    BudgetExceeded,
+    /// A node panicked while it executed.
+    NodePanicked,
+}
+
+#[derive(Debug, Serialize)]
+/// The struct of the graph error.
+pub struct GraphError {
+    error_type: GraphErrorType,
+    message: String,
+}
+
+impl GraphError {
+    /// Create a new GraphError.
+    pub fn new(error_type: GraphErrorType, message: String) -> GraphError {
+        GraphError {
+            error_type,
+            message,
+        }
+    }
+    /// Get the type of the error.
+    pub fn get_error_type(&self) -> &GraphErrorType {
+        &self.error_type
+    }
+    /// Get the message of the error.
+    pub fn get_message(&self) -> &str {
+        &self.message
+    }
+}
+
+impl std::fmt::Display for GraphError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.error_type {
+            GraphErrorType::NodeNotFound => write!(f, "NodeNotFound: {}", self.message),
+            GraphErrorType::StartEndError => write!(f, "StartEndError: {}", self.message),
+            GraphErrorType::CycleError => write!(f, "CycleError: {}", self.message),
+            GraphErrorType::UnreachableEnd => write!(f, "UnreachableEnd: {}", self.message),
+            GraphErrorType::DefinitionError => write!(f, "DefinitionError: {}", self.message),
+            GraphErrorType::ContextError => write!(f, "ContextError: {}", self.message),
+            GraphErrorType::Cancelled => write!(f, "Cancelled: {}", self.message),
+            GraphErrorType::ApprovalError => write!(f, "ApprovalError: {}", self.message),
+            GraphErrorType::DeadlineExceeded => write!(f, "DeadlineExceeded: {}", self.message),
+            GraphErrorType::BudgetExceeded => write!(f, "BudgetExceeded: {}", self.message),
+            GraphErrorType::NodePanicked => write!(f, "NodePanicked: {}", self.message),
+        }
+    }
+}
+
+pub type GraphResult<T> = Result<T, GraphError>;
@@ -0,0 +1,71 @@
+//! # Ingest Node Error
+//!
+//! This module defines all errors that will happen in ingest node.
+
+use super::ai_node_error::AINodeError;
+use super::chunker_node_error::ChunkerNodeError;
+use super::load_node_error::LoadNodeError;
+use super::vector_store_error::VectorStoreError;
+
+use serde::Serialize;
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "snake_case")]
+/// The enum of the ingest node error type.
+pub enum IngestNodeErrorType {
+    /// A file can't be loaded.
+    LoadError(Box<LoadNodeError>),
+    /// A url can't be fetched.
+    FetchError,
+    /// A document can't be split into chunks.
+    ChunkError(ChunkerNodeError),
+    /// The chunks can't be embedded.
+    EmbeddingError(Box<AINodeError>),
+    /// The vector store can't be read or written.
+    StoreError(VectorStoreError),
+}
+
+#[derive(Debug, Serialize)]
+/// The struct of the ingest node error.
+pub struct IngestNodeError {
+    error_type: IngestNodeErrorType,
+    message: String,
+}
+
+impl IngestNodeError {
+    /// Create a new IngestNodeError.
+    pub fn new(error_type: IngestNodeErrorType, message: String) -> IngestNodeError {
+        IngestNodeError {
+            error_type,
+            message,
+        }
+    }
+    /// Get the type of the error.
+    pub fn get_error_type(&self) -> &IngestNodeErrorType {
+        &self.error_type
+    }
+    /// Get the message of the error.
+    pub fn get_message(&self) -> &str {
+        &self.message
+    }
+}
+
+impl std::fmt::Display for IngestNodeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.error_type {
+            IngestNodeErrorType::LoadError(e) => write!(f, "LoadError: {}\n{}", self.message, e),
+            IngestNodeErrorType::FetchError => write!(f, "FetchError: {}", self.message),
+            IngestNodeErrorType::ChunkError(e) => {
+                write!(f, "ChunkError: {}\n{}", self.message, e)
+            }
+            IngestNodeErrorType::EmbeddingError(e) => {
+                write!(f, "EmbeddingError: {}\n{}", self.message, e)
+            }
+            IngestNodeErrorType::StoreError(e) => {
+                write!(f, "StoreError: {}\n{}", self.message, e)
+            }
+        }
+    }
+}
+
+pub type IngestNodeResult<T> = Result<T, IngestNodeError>;
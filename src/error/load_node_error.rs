@@ -0,0 +1,58 @@
+//! # Load Node Error
+//!
+//! This module defines all errors that will happen in load node.
+
+use super::file_node_error::FileNodeError;
+
+use serde::Serialize;
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "snake_case")]
+/// The enum of the load node error type.
+pub enum LoadNodeErrorType {
+    /// The file can't be found, read or is not allowed.
+    FileError(Box<FileNodeError>),
+    /// The format of the file can't be detected from its extension.
+    UnsupportedFormat,
+    /// The content of the file is not valid for its format.
+    ParseError,
+}
+
+#[derive(Debug, Serialize)]
+/// The struct of the load node error.
+pub struct LoadNodeError {
+    error_type: LoadNodeErrorType,
+    message: String,
+}
+
+impl LoadNodeError {
+    /// Create a new LoadNodeError.
+    pub fn new(error_type: LoadNodeErrorType, message: String) -> LoadNodeError {
+        LoadNodeError {
+            error_type,
+            message,
+        }
+    }
+    /// Get the type of the error.
+    pub fn get_error_type(&self) -> &LoadNodeErrorType {
+        &self.error_type
+    }
+    /// Get the message of the error.
+    pub fn get_message(&self) -> &str {
+        &self.message
+    }
+}
+
+impl std::fmt::Display for LoadNodeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.error_type {
+            LoadNodeErrorType::FileError(e) => write!(f, "FileError: {}\n{}", self.message, e),
+            LoadNodeErrorType::UnsupportedFormat => {
+                write!(f, "UnsupportedFormat: {}", self.message)
+            }
+            LoadNodeErrorType::ParseError => write!(f, "ParseError: {}", self.message),
+        }
+    }
+}
+
+pub type LoadNodeResult<T> = Result<T, LoadNodeError>;
@@ -0,0 +1,81 @@
+//! # Local Node Error
+//!
+//! This module defines all errors that will happen in local node.
+
+use super::template_error::TemplateError;
+
+use serde::Serialize;
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "snake_case")]
+/// The enum of the local node error type.
+pub enum LocalNodeErrorType {
+    /// The command can't be started, like a program that doesn't exist.
+    SpawnError,
+    /// The input can't be written to the command, or its output can't be read.
+    IoError,
+    /// The command didn't finish in time, and was killed.
+    Timeout,
+    /// The command exited with a status other than 0.
+    ExitError {
+        /// The exit code, or `None` if the command was killed by a signal.
+        code: Option<i32>,
+        /// The stderr of the command.
+        stderr: String,
+    },
+    /// An argument template can't be rendered.
+    TemplateError(TemplateError),
+    /// The sandbox of the command can't be set up on this system.
+    SandboxError,
+}
+
+#[derive(Debug, Serialize)]
+/// The struct of the local node error.
+pub struct LocalNodeError {
+    error_type: LocalNodeErrorType,
+    message: String,
+}
+
+impl LocalNodeError {
+    /// Create a new LocalNodeError.
+    pub fn new(error_type: LocalNodeErrorType, message: String) -> LocalNodeError {
+        LocalNodeError {
+            error_type,
+            message,
+        }
+    }
+    /// Get the type of the error.
+    pub fn get_error_type(&self) -> &LocalNodeErrorType {
+        &self.error_type
+    }
+    /// Get the message of the error.
+    pub fn get_message(&self) -> &str {
+        &self.message
+    }
+}
+
+impl std::fmt::Display for LocalNodeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.error_type {
+            LocalNodeErrorType::SpawnError => write!(f, "SpawnError: {}", self.message),
+            LocalNodeErrorType::IoError => write!(f, "IoError: {}", self.message),
+            LocalNodeErrorType::Timeout => write!(f, "Timeout: {}", self.message),
+            LocalNodeErrorType::ExitError { code, stderr } => {
+                write!(f, "ExitError: {}", self.message)?;
+                if let Some(code) = code {
+                    write!(f, "\n  exit code: {}", code)?;
+                }
+                if !stderr.is_empty() {
+                    write!(f, "\n  stderr: {}", stderr.trim_end())?;
+                }
+                Ok(())
+            }
+            LocalNodeErrorType::TemplateError(e) => {
+                write!(f, "TemplateError: {}\n{}", self.message, e)
+            }
+            LocalNodeErrorType::SandboxError => write!(f, "SandboxError: {}", self.message),
+        }
+    }
+}
+
+pub type LocalNodeResult<T> = Result<T, LocalNodeError>;
@@ -0,0 +1,55 @@
+//! # Map Node Error
+//!
+//! This module defines all errors that will happen in map node.
+
+use super::PilotError;
+
+use serde::Serialize;
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "snake_case")]
+/// The enum of the map node error type.
+pub enum MapNodeErrorType {
+    /// The input is not a json array.
+    InvalidInput,
+    /// The subworkflow failed on an item.
+    ItemFailed(Box<PilotError>),
+}
+
+#[derive(Debug, Serialize)]
+/// The struct of the map node error.
+pub struct MapNodeError {
+    error_type: MapNodeErrorType,
+    message: String,
+}
+
+impl MapNodeError {
+    /// Create a new MapNodeError.
+    pub fn new(error_type: MapNodeErrorType, message: String) -> MapNodeError {
+        MapNodeError {
+            error_type,
+            message,
+        }
+    }
+    /// Get the type of the error.
+    pub fn get_error_type(&self) -> &MapNodeErrorType {
+        &self.error_type
+    }
+    /// Get the message of the error.
+    pub fn get_message(&self) -> &str {
+        &self.message
+    }
+}
+
+impl std::fmt::Display for MapNodeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.error_type {
+            MapNodeErrorType::InvalidInput => write!(f, "InvalidInput: {}", self.message),
+            MapNodeErrorType::ItemFailed(e) => {
+                write!(f, "ItemFailed: {}\n{}", self.message, e)
+            }
+        }
+    }
+}
+
+pub type MapNodeResult<T> = Result<T, MapNodeError>;
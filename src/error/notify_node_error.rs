@@ -0,0 +1,55 @@
+//! # Notify Node Error
+//!
+//! This module defines all errors that will happen in notify node.
+
+use super::template_error::TemplateError;
+
+use serde::Serialize;
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "snake_case")]
+/// The enum of the notify node error type.
+pub enum NotifyNodeErrorType {
+    /// The message or the subject template can't be rendered.
+    TemplateError(TemplateError),
+    /// The notification can't be sent to some of the channels.
+    SendError,
+}
+
+#[derive(Debug, Serialize)]
+/// The struct of the notify node error.
+pub struct NotifyNodeError {
+    error_type: NotifyNodeErrorType,
+    message: String,
+}
+
+impl NotifyNodeError {
+    /// Create a new NotifyNodeError.
+    pub fn new(error_type: NotifyNodeErrorType, message: String) -> NotifyNodeError {
+        NotifyNodeError {
+            error_type,
+            message,
+        }
+    }
+    /// Get the type of the error.
+    pub fn get_error_type(&self) -> &NotifyNodeErrorType {
+        &self.error_type
+    }
+    /// Get the message of the error.
+    pub fn get_message(&self) -> &str {
+        &self.message
+    }
+}
+
+impl std::fmt::Display for NotifyNodeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.error_type {
+            NotifyNodeErrorType::TemplateError(e) => {
+                write!(f, "TemplateError: {}\n{}", self.message, e)
+            }
+            NotifyNodeErrorType::SendError => write!(f, "SendError: {}", self.message),
+        }
+    }
+}
+
+pub type NotifyNodeResult<T> = Result<T, NotifyNodeError>;
@@ -0,0 +1,59 @@
+//! # Planner Node Error
+//!
+//! This module defines all errors that will happen in planner node.
+
+use super::ai_node_error::AINodeError;
+use super::PilotError;
+
+use serde::Serialize;
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "snake_case")]
+/// The enum of the planner node error type.
+pub enum PlannerNodeErrorType {
+    /// The AI service fails to give a plan.
+    PlanError(Box<AINodeError>),
+    /// The plan can't be run, like a step with an unknown tool.
+    InvalidPlan,
+    /// A step of the plan fails.
+    StepFailed(Box<PilotError>),
+}
+
+#[derive(Debug, Serialize)]
+/// The struct of the planner node error.
+pub struct PlannerNodeError {
+    error_type: PlannerNodeErrorType,
+    message: String,
+}
+
+impl PlannerNodeError {
+    /// Create a new PlannerNodeError.
+    pub fn new(error_type: PlannerNodeErrorType, message: String) -> PlannerNodeError {
+        PlannerNodeError {
+            error_type,
+            message,
+        }
+    }
+    /// Get the type of the error.
+    pub fn get_error_type(&self) -> &PlannerNodeErrorType {
+        &self.error_type
+    }
+    /// Get the message of the error.
+    pub fn get_message(&self) -> &str {
+        &self.message
+    }
+}
+
+impl std::fmt::Display for PlannerNodeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.error_type {
+            PlannerNodeErrorType::PlanError(e) => write!(f, "PlanError: {}\n{}", self.message, e),
+            PlannerNodeErrorType::InvalidPlan => write!(f, "InvalidPlan: {}", self.message),
+            PlannerNodeErrorType::StepFailed(e) => {
+                write!(f, "StepFailed: {}\n{}", self.message, e)
+            }
+        }
+    }
+}
+
+pub type PlannerNodeResult<T> = Result<T, PlannerNodeError>;
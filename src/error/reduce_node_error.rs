@@ -0,0 +1,58 @@
+//! # Reduce Node Error
+//!
+//! This module defines all errors that will happen in reduce node.
+
+use super::ai_node_error::AINodeError;
+
+use serde::Serialize;
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "snake_case")]
+/// The enum of the reduce node error type.
+pub enum ReduceNodeErrorType {
+    /// The outputs can't be merged as json.
+    MergeError,
+    /// The custom reducer failed.
+    ReducerError,
+    /// The AI service failed to summarize the outputs.
+    SummaryError(Box<AINodeError>),
+}
+
+#[derive(Debug, Serialize)]
+/// The struct of the reduce node error.
+pub struct ReduceNodeError {
+    error_type: ReduceNodeErrorType,
+    message: String,
+}
+
+impl ReduceNodeError {
+    /// Create a new ReduceNodeError.
+    pub fn new(error_type: ReduceNodeErrorType, message: String) -> ReduceNodeError {
+        ReduceNodeError {
+            error_type,
+            message,
+        }
+    }
+    /// Get the type of the error.
+    pub fn get_error_type(&self) -> &ReduceNodeErrorType {
+        &self.error_type
+    }
+    /// Get the message of the error.
+    pub fn get_message(&self) -> &str {
+        &self.message
+    }
+}
+
+impl std::fmt::Display for ReduceNodeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.error_type {
+            ReduceNodeErrorType::MergeError => write!(f, "MergeError: {}", self.message),
+            ReduceNodeErrorType::ReducerError => write!(f, "ReducerError: {}", self.message),
+            ReduceNodeErrorType::SummaryError(e) => {
+                write!(f, "SummaryError: {}\n{}", self.message, e)
+            }
+        }
+    }
+}
+
+pub type ReduceNodeResult<T> = Result<T, ReduceNodeError>;
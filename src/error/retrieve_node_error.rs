@@ -0,0 +1,64 @@
+//! # Retrieve Node Error
+//!
+//! This module defines all errors that will happen in retrieve node.
+
+use super::ai_node_error::AINodeError;
+use super::template_error::TemplateError;
+use super::vector_store_error::VectorStoreError;
+
+use serde::Serialize;
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "snake_case")]
+/// The enum of the retrieve node error type.
+pub enum RetrieveNodeErrorType {
+    /// The query can't be embedded.
+    EmbeddingError(Box<AINodeError>),
+    /// The vector store can't be queried.
+    StoreError(VectorStoreError),
+    /// The query or the snippet template can't be rendered.
+    TemplateError(TemplateError),
+}
+
+#[derive(Debug, Serialize)]
+/// The struct of the retrieve node error.
+pub struct RetrieveNodeError {
+    error_type: RetrieveNodeErrorType,
+    message: String,
+}
+
+impl RetrieveNodeError {
+    /// Create a new RetrieveNodeError.
+    pub fn new(error_type: RetrieveNodeErrorType, message: String) -> RetrieveNodeError {
+        RetrieveNodeError {
+            error_type,
+            message,
+        }
+    }
+    /// Get the type of the error.
+    pub fn get_error_type(&self) -> &RetrieveNodeErrorType {
+        &self.error_type
+    }
+    /// Get the message of the error.
+    pub fn get_message(&self) -> &str {
+        &self.message
+    }
+}
+
+impl std::fmt::Display for RetrieveNodeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.error_type {
+            RetrieveNodeErrorType::EmbeddingError(e) => {
+                write!(f, "EmbeddingError: {}\n{}", self.message, e)
+            }
+            RetrieveNodeErrorType::StoreError(e) => {
+                write!(f, "StoreError: {}\n{}", self.message, e)
+            }
+            RetrieveNodeErrorType::TemplateError(e) => {
+                write!(f, "TemplateError: {}\n{}", self.message, e)
+            }
+        }
+    }
+}
+
+pub type RetrieveNodeResult<T> = Result<T, RetrieveNodeError>;
@@ -0,0 +1,54 @@
+//! # Script Node Error
+//!
+//! This module defines all errors that will happen in script node.
+
+use serde::Serialize;
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "snake_case")]
+/// The enum of the script node error type.
+pub enum ScriptNodeErrorType {
+    /// The script has a syntax error.
+    CompileError,
+    /// The script failed while it ran, like by throwing or exceeding its operations.
+    RuntimeError,
+    /// The value of the script, or a value it wrote to the context, can't be converted.
+    OutputError,
+}
+
+#[derive(Debug, Serialize)]
+/// The struct of the script node error.
+pub struct ScriptNodeError {
+    error_type: ScriptNodeErrorType,
+    message: String,
+}
+
+impl ScriptNodeError {
+    /// Create a new ScriptNodeError.
+    pub fn new(error_type: ScriptNodeErrorType, message: String) -> ScriptNodeError {
+        ScriptNodeError {
+            error_type,
+            message,
+        }
+    }
+    /// Get the type of the error.
+    pub fn get_error_type(&self) -> &ScriptNodeErrorType {
+        &self.error_type
+    }
+    /// Get the message of the error.
+    pub fn get_message(&self) -> &str {
+        &self.message
+    }
+}
+
+impl std::fmt::Display for ScriptNodeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.error_type {
+            ScriptNodeErrorType::CompileError => write!(f, "CompileError: {}", self.message),
+            ScriptNodeErrorType::RuntimeError => write!(f, "RuntimeError: {}", self.message),
+            ScriptNodeErrorType::OutputError => write!(f, "OutputError: {}", self.message),
+        }
+    }
+}
+
+pub type ScriptNodeResult<T> = Result<T, ScriptNodeError>;
@@ -0,0 +1,56 @@
+//! # Storage Error
+//!
+//! This module defines all errors that will happen when the engine keeps its state on the
+//! disk, like the checkpoints of the runs.
+
+use serde::Serialize;
+
+#[derive(Debug, Serialize)]
+#[allow(clippy::enum_variant_names)]
+#[serde(rename_all = "snake_case")]
+/// The enum of the storage error type.
+pub enum StorageErrorType {
+    /// The data can't be read from the storage.
+    ReadError,
+    /// The data can't be written to the storage.
+    WriteError,
+    /// The data in the storage is not valid.
+    InvalidData,
+}
+
+#[derive(Debug, Serialize)]
+/// The struct of the storage error.
+pub struct StorageError {
+    error_type: StorageErrorType,
+    message: String,
+}
+
+impl StorageError {
+    /// Create a new StorageError.
+    pub fn new(error_type: StorageErrorType, message: String) -> StorageError {
+        StorageError {
+            error_type,
+            message,
+        }
+    }
+    /// Get the type of the error.
+    pub fn get_error_type(&self) -> &StorageErrorType {
+        &self.error_type
+    }
+    /// Get the message of the error.
+    pub fn get_message(&self) -> &str {
+        &self.message
+    }
+}
+
+impl std::fmt::Display for StorageError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.error_type {
+            StorageErrorType::ReadError => write!(f, "ReadError: {}", self.message),
+            StorageErrorType::WriteError => write!(f, "WriteError: {}", self.message),
+            StorageErrorType::InvalidData => write!(f, "InvalidData: {}", self.message),
+        }
+    }
+}
+
+pub type StorageResult<T> = Result<T, StorageError>;
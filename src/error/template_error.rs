@@ -0,0 +1,49 @@
+//! # Template Error
+//!
+//! This module defines all errors that will happen when rendering a template.
+
+use serde::Serialize;
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "snake_case")]
+/// The enum of the template error type.
+pub enum TemplateErrorType {
+    /// The template is not well formed, like a `{{` without `}}`.
+    SyntaxError,
+    /// The template uses a variable that is not given.
+    MissingVariable,
+}
+
+#[derive(Debug, Serialize)]
+/// The struct of the template error.
+pub struct TemplateError {
+    error_type: TemplateErrorType,
+    message: String,
+}
+
+impl TemplateError {
+    /// Create a new TemplateError.
+    pub fn new(error_type: TemplateErrorType, message: String) -> TemplateError {
+        TemplateError {
+            error_type,
+            message,
+        }
+    }
+    /// Get the type of the error.
+    pub fn get_error_type(&self) -> &TemplateErrorType {
+        &self.error_type
+    }
+}
+
+impl std::fmt::Display for TemplateError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.error_type {
+            TemplateErrorType::SyntaxError => write!(f, "SyntaxError: {}", self.message),
+            TemplateErrorType::MissingVariable => {
+                write!(f, "MissingVariable: {}", self.message)
+            }
+        }
+    }
+}
+
+pub type TemplateResult<T> = Result<T, TemplateError>;
@@ -0,0 +1,86 @@
+//! # Timeout Error
+//!
+//! This module defines the error of something that took too long, like a request that can't
+//! connect or a node that doesn't finish in time.
+//!
+//! The type tells which limit was hit, so a caller can tell a server that can't be reached
+//! from one that is slow to answer, and the error carries the time that had passed and the
+//! limit, if it is known.
+
+use serde::Serialize;
+
+use std::time::Duration;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+/// The enum of the timeout error type.
+pub enum TimeoutErrorType {
+    /// The connection can't be made in time.
+    Connect,
+    /// The answer stopped coming in for too long.
+    Read,
+    /// The whole work didn't finish in time.
+    Total,
+}
+
+#[derive(Debug, Serialize)]
+/// The struct of the timeout error.
+pub struct TimeoutError {
+    error_type: TimeoutErrorType,
+    message: String,
+    /// The time that had passed when it timed out.
+    elapsed: Duration,
+    /// The limit that was hit, if it is known.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    limit: Option<Duration>,
+}
+
+impl TimeoutError {
+    /// Create a new TimeoutError.
+    pub fn new(error_type: TimeoutErrorType, message: String, elapsed: Duration) -> TimeoutError {
+        TimeoutError {
+            error_type,
+            message,
+            elapsed,
+            limit: None,
+        }
+    }
+    /// Set the limit that was hit as builder.
+    pub fn limit(mut self, limit: Option<Duration>) -> Self {
+        self.limit = limit;
+        self
+    }
+    /// Get the type of the error.
+    pub fn get_error_type(&self) -> &TimeoutErrorType {
+        &self.error_type
+    }
+    /// Get the message of the error.
+    pub fn get_message(&self) -> &str {
+        &self.message
+    }
+    /// Get the time that had passed when it timed out.
+    pub fn get_elapsed(&self) -> Duration {
+        self.elapsed
+    }
+    /// Get the limit that was hit.
+    pub fn get_limit(&self) -> Option<Duration> {
+        self.limit
+    }
+}
+
+impl std::fmt::Display for TimeoutError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.error_type {
+            TimeoutErrorType::Connect => write!(f, "Connect: {}", self.message)?,
+            TimeoutErrorType::Read => write!(f, "Read: {}", self.message)?,
+            TimeoutErrorType::Total => write!(f, "Total: {}", self.message)?,
+        }
+        write!(f, "\n  elapsed: {:?}", self.elapsed)?;
+        if let Some(limit) = self.limit {
+            write!(f, "\n  limit: {:?}", limit)?;
+        }
+        Ok(())
+    }
+}
+
+pub type TimeoutResult<T> = Result<T, TimeoutError>;
@@ -0,0 +1,57 @@
+//! # Transform Node Error
+//!
+//! This module defines all errors that will happen in json transform node.
+
+use serde::Serialize;
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "snake_case")]
+/// The enum of the transform node error type.
+pub enum TransformNodeErrorType {
+    /// The JSONPath or the filter has a syntax error.
+    ParseError,
+    /// The filter failed on the input, like by indexing a number.
+    EvalError,
+    /// The input is not valid json.
+    InvalidInput,
+    /// The expression gave no result.
+    NoResult,
+}
+
+#[derive(Debug, Serialize)]
+/// The struct of the transform node error.
+pub struct TransformNodeError {
+    error_type: TransformNodeErrorType,
+    message: String,
+}
+
+impl TransformNodeError {
+    /// Create a new TransformNodeError.
+    pub fn new(error_type: TransformNodeErrorType, message: String) -> TransformNodeError {
+        TransformNodeError {
+            error_type,
+            message,
+        }
+    }
+    /// Get the type of the error.
+    pub fn get_error_type(&self) -> &TransformNodeErrorType {
+        &self.error_type
+    }
+    /// Get the message of the error.
+    pub fn get_message(&self) -> &str {
+        &self.message
+    }
+}
+
+impl std::fmt::Display for TransformNodeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.error_type {
+            TransformNodeErrorType::ParseError => write!(f, "ParseError: {}", self.message),
+            TransformNodeErrorType::EvalError => write!(f, "EvalError: {}", self.message),
+            TransformNodeErrorType::InvalidInput => write!(f, "InvalidInput: {}", self.message),
+            TransformNodeErrorType::NoResult => write!(f, "NoResult: {}", self.message),
+        }
+    }
+}
+
+pub type TransformNodeResult<T> = Result<T, TransformNodeError>;
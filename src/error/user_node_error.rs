@@ -0,0 +1,61 @@
+//! # User Node Error
+//!
+//! This module defines all errors that will happen in user node.
+
+use super::template_error::TemplateError;
+
+use serde::Serialize;
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "snake_case")]
+/// The enum of the user node error type.
+pub enum UserNodeErrorType {
+    /// The prompt can't be written, or the answer can't be read.
+    IoError,
+    /// The user didn't answer in time, and there is no default answer.
+    Timeout,
+    /// The input ended before an answer, and there is no default answer.
+    NoInput,
+    /// The prompt template can't be rendered.
+    TemplateError(TemplateError),
+}
+
+#[derive(Debug, Serialize)]
+/// The struct of the user node error.
+pub struct UserNodeError {
+    error_type: UserNodeErrorType,
+    message: String,
+}
+
+impl UserNodeError {
+    /// Create a new UserNodeError.
+    pub fn new(error_type: UserNodeErrorType, message: String) -> UserNodeError {
+        UserNodeError {
+            error_type,
+            message,
+        }
+    }
+    /// Get the type of the error.
+    pub fn get_error_type(&self) -> &UserNodeErrorType {
+        &self.error_type
+    }
+    /// Get the message of the error.
+    pub fn get_message(&self) -> &str {
+        &self.message
+    }
+}
+
+impl std::fmt::Display for UserNodeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.error_type {
+            UserNodeErrorType::IoError => write!(f, "IoError: {}", self.message),
+            UserNodeErrorType::Timeout => write!(f, "Timeout: {}", self.message),
+            UserNodeErrorType::NoInput => write!(f, "NoInput: {}", self.message),
+            UserNodeErrorType::TemplateError(e) => {
+                write!(f, "TemplateError: {}\n{}", self.message, e)
+            }
+        }
+    }
+}
+
+pub type UserNodeResult<T> = Result<T, UserNodeError>;
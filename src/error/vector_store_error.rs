@@ -0,0 +1,53 @@
+//! # Vector Store Error
+//!
+//! This module defines all errors that will happen in a vector store.
+
+use serde::Serialize;
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "snake_case")]
+/// The enum of the vector store error type.
+pub enum VectorStoreErrorType {
+    /// The vector doesn't have the dimensions of the vectors in the store.
+    DimensionMismatch,
+    /// The store can't be opened, read or written.
+    StorageError,
+}
+
+#[derive(Debug, Serialize)]
+/// The struct of the vector store error.
+pub struct VectorStoreError {
+    error_type: VectorStoreErrorType,
+    message: String,
+}
+
+impl VectorStoreError {
+    /// Create a new VectorStoreError.
+    pub fn new(error_type: VectorStoreErrorType, message: String) -> VectorStoreError {
+        VectorStoreError {
+            error_type,
+            message,
+        }
+    }
+    /// Get the type of the error.
+    pub fn get_error_type(&self) -> &VectorStoreErrorType {
+        &self.error_type
+    }
+    /// Get the message of the error.
+    pub fn get_message(&self) -> &str {
+        &self.message
+    }
+}
+
+impl std::fmt::Display for VectorStoreError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.error_type {
+            VectorStoreErrorType::DimensionMismatch => {
+                write!(f, "DimensionMismatch: {}", self.message)
+            }
+            VectorStoreErrorType::StorageError => write!(f, "StorageError: {}", self.message),
+        }
+    }
+}
+
+pub type VectorStoreResult<T> = Result<T, VectorStoreError>;
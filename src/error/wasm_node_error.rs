@@ -0,0 +1,59 @@
+//! # Wasm Node Error
+//!
+//! This module defines all errors that will happen in wasm node.
+
+use serde::Serialize;
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "snake_case")]
+/// The enum of the wasm node error type.
+pub enum WasmNodeErrorType {
+    /// The plugin can't be read or compiled, or it doesn't export `run`.
+    LoadError,
+    /// The plugin can't be instantiated, like one that imports something unknown.
+    InstantiateError,
+    /// The plugin trapped, ran out of fuel or exceeded its memory.
+    CallError,
+    /// The plugin didn't finish in time, and was stopped.
+    Timeout,
+}
+
+#[derive(Debug, Serialize)]
+/// The struct of the wasm node error.
+pub struct WasmNodeError {
+    error_type: WasmNodeErrorType,
+    message: String,
+}
+
+impl WasmNodeError {
+    /// Create a new WasmNodeError.
+    pub fn new(error_type: WasmNodeErrorType, message: String) -> WasmNodeError {
+        WasmNodeError {
+            error_type,
+            message,
+        }
+    }
+    /// Get the type of the error.
+    pub fn get_error_type(&self) -> &WasmNodeErrorType {
+        &self.error_type
+    }
+    /// Get the message of the error.
+    pub fn get_message(&self) -> &str {
+        &self.message
+    }
+}
+
+impl std::fmt::Display for WasmNodeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.error_type {
+            WasmNodeErrorType::LoadError => write!(f, "LoadError: {}", self.message),
+            WasmNodeErrorType::InstantiateError => {
+                write!(f, "InstantiateError: {}", self.message)
+            }
+            WasmNodeErrorType::CallError => write!(f, "CallError: {}", self.message),
+            WasmNodeErrorType::Timeout => write!(f, "Timeout: {}", self.message),
+        }
+    }
+}
+
+pub type WasmNodeResult<T> = Result<T, WasmNodeError>;
@@ -0,0 +1,481 @@
+//! # Ingest
+//!
+//! This module fills a vector store with the documents of a knowledge base, the pipeline of
+//! the retrieval workflows: the sources are walked, loaded (see [`crate::loader`]), split into
+//! chunks (see [`crate::worknode::chunker`]), embedded (see
+//! [`crate::worknode::ai_node::embedding`]) and upserted in the store (see
+//! [`crate::vector_store`]).
+//!
+//! A source is a file, a directory, whose files of a known format are all ingested, or an
+//! http(s) url, whose format is detected from its path or its content type.
+//!
+//! The ingestion is incremental. Every chunk keeps the `source` of its document and the
+//! `content_hash` of the text of the document and of the chunking, so on a later run, a
+//! document whose hash is unchanged is not embedded again, and the chunks of a changed
+//! document are replaced. With `prune`, the chunks of the files removed from an ingested
+//! directory are deleted too.
+//!
+//! The chunks have the ids `<source>#<index>`, and the metadata of their document, with their
+//! `chunk` index and their `heading` for markdown.
+
+use crate::error::file_node_error::FileNodeError;
+use crate::error::ingest_node_error::{IngestNodeError, IngestNodeErrorType, IngestNodeResult};
+use crate::error::load_node_error::{LoadNodeError, LoadNodeErrorType};
+use crate::error::vector_store_error::VectorStoreError;
+use crate::loader::{self, Format, LoadedDocument};
+use crate::vector_store::{Document, Filter, VectorStore};
+use crate::worknode::ai_node::embedding::EmbeddingClient;
+use crate::worknode::chunker::ChunkerNode;
+use crate::worknode::file::{io_error, too_large, DEFAULT_MAX_SIZE};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+/// The struct of the options of an ingestion.
+pub struct IngestOptions {
+    /// The paths and the urls to ingest when none is given.
+    pub sources: Vec<String>,
+    /// How the documents are split into chunks.
+    pub chunker: ChunkerNode,
+    /// The size in bytes of the largest document that can be ingested, or `None` for no limit.
+    pub max_size: Option<u64>,
+    /// The max number of chunks embedded in one request.
+    pub batch_size: usize,
+    /// Whether the chunks of the files removed from an ingested directory are deleted.
+    pub prune: bool,
+}
+
+impl Default for IngestOptions {
+    fn default() -> Self {
+        IngestOptions {
+            sources: Vec::new(),
+            chunker: ChunkerNode::default(),
+            max_size: Some(DEFAULT_MAX_SIZE),
+            batch_size: 64,
+            prune: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+/// The struct of what an ingestion did.
+pub struct IngestReport {
+    /// The number of documents ingested for the first time.
+    pub added: usize,
+    /// The number of documents changed since they were ingested.
+    pub updated: usize,
+    /// The number of documents unchanged, which were not embedded again.
+    pub unchanged: usize,
+    /// The number of documents removed from the store, as their files were removed.
+    pub removed: usize,
+    /// The number of chunks embedded.
+    pub chunks: usize,
+}
+
+#[derive(Debug, Clone)]
+/// The struct of the pipeline that ingests documents in a vector store.
+pub struct Ingest {
+    /// The client that embeds the chunks.
+    client: EmbeddingClient,
+    /// The store of the chunks.
+    store: Arc<dyn VectorStore>,
+    /// The options.
+    options: IngestOptions,
+}
+
+impl Ingest {
+    /// Create a new Ingest with the default options.
+    pub fn new(client: EmbeddingClient, store: Arc<dyn VectorStore>) -> Self {
+        Ingest {
+            client,
+            store,
+            options: IngestOptions::default(),
+        }
+    }
+    /// Ingest the sources of the options.
+    pub async fn run(&mut self) -> IngestNodeResult<IngestReport> {
+        let sources = self.options.sources.clone();
+        self.ingest(&sources).await
+    }
+    /// Ingest the sources, and skip the documents unchanged since their last ingestion.
+    pub async fn ingest(&mut self, sources: &[String]) -> IngestNodeResult<IngestReport> {
+        let mut report = IngestReport::default();
+        let mut seen = HashSet::new();
+        let mut directories = Vec::new();
+        for source in sources {
+            let source = source.trim();
+            if is_url(source) {
+                let document = fetch(source, self.options.max_size).await?;
+                self.ingest_document(document, &mut report).await?;
+                seen.insert(source.to_string());
+                continue;
+            }
+            let path = PathBuf::from(source);
+            let files = if path.is_dir() {
+                directories.push(path.display().to_string());
+                loader::list_files(&path).map_err(load_error)?
+            } else {
+                vec![path]
+            };
+            for file in files {
+                let max_size = self.options.max_size;
+                let document = tokio::task::spawn_blocking(move || load(&file, max_size))
+                    .await
+                    .map_err(|e| {
+                        load_error(LoadNodeError::new(
+                            LoadNodeErrorType::ParseError,
+                            format!("The loader panicked. {}", e),
+                        ))
+                    })??;
+                if let Some(source) = document.metadata.get("source").and_then(Value::as_str) {
+                    seen.insert(source.to_string());
+                }
+                self.ingest_document(document, &mut report).await?;
+            }
+        }
+        if self.options.prune && !directories.is_empty() {
+            report.removed = self.prune(&directories, &seen)?;
+        }
+        Ok(report)
+    }
+    /// Embed the chunks of the document and replace its previous chunks, unless its hash is
+    /// unchanged.
+    async fn ingest_document(
+        &mut self,
+        document: LoadedDocument,
+        report: &mut IngestReport,
+    ) -> IngestNodeResult<()> {
+        let source = document
+            .metadata
+            .get("source")
+            .and_then(Value::as_str)
+            .unwrap_or_default()
+            .to_string();
+        let hash = self.content_hash(&document.text);
+        let previous = self
+            .store
+            .list(Some(&Filter::eq("source", Value::from(source.clone()))))
+            .map_err(store_error)?;
+        if !previous.is_empty()
+            && previous
+                .iter()
+                .all(|chunk| chunk.metadata.get("content_hash") == Some(&Value::from(hash.clone())))
+        {
+            report.unchanged += 1;
+            return Ok(());
+        }
+        let chunks = self.options.chunker.split(&document.text).map_err(|e| {
+            IngestNodeError::new(
+                IngestNodeErrorType::ChunkError(e),
+                format!("Failed to split the document {}.", source),
+            )
+        })?;
+        let mut documents = Vec::with_capacity(chunks.len());
+        for batch in chunks.chunks(self.options.batch_size.max(1)) {
+            let texts: Vec<String> = batch.iter().map(|chunk| chunk.text.clone()).collect();
+            let vectors = self.client.embed(&texts).await.map_err(|e| {
+                IngestNodeError::new(
+                    IngestNodeErrorType::EmbeddingError(Box::new(e)),
+                    format!("Failed to embed the chunks of the document {}.", source),
+                )
+            })?;
+            for (chunk, vector) in batch.iter().zip(vectors) {
+                let mut stored =
+                    Document::new(&format!("{}#{}", source, chunk.index), &chunk.text, vector);
+                stored.metadata = document.metadata.clone();
+                stored = stored
+                    .meta("chunk", Value::from(chunk.index))
+                    .meta("content_hash", Value::from(hash.clone()));
+                if let Some(heading) = &chunk.heading {
+                    stored = stored.meta("heading", Value::from(heading.clone()));
+                }
+                documents.push(stored);
+            }
+        }
+        let ids: Vec<String> = previous.into_iter().map(|chunk| chunk.id).collect();
+        match ids.is_empty() {
+            true => report.added += 1,
+            false => report.updated += 1,
+        }
+        report.chunks += documents.len();
+        self.store.delete(&ids).map_err(store_error)?;
+        self.store.upsert(documents).map_err(store_error)
+    }
+    /// Delete the chunks of the ingested files under the directories that were not seen, and
+    /// get the number of their documents.
+    fn prune(&self, directories: &[String], seen: &HashSet<String>) -> IngestNodeResult<usize> {
+        let ingested = self
+            .store
+            .list(Some(&Filter::Exists {
+                key: "content_hash".to_string(),
+            }))
+            .map_err(store_error)?;
+        let mut removed = HashSet::new();
+        let mut ids = Vec::new();
+        for chunk in ingested {
+            let Some(source) = chunk.metadata.get("source").and_then(Value::as_str) else {
+                continue;
+            };
+            let under = directories
+                .iter()
+                .any(|directory| Path::new(source).starts_with(directory));
+            if under && !seen.contains(source) {
+                removed.insert(source.to_string());
+                ids.push(chunk.id);
+            }
+        }
+        self.store.delete(&ids).map_err(store_error)?;
+        Ok(removed.len())
+    }
+    /// Get the hash of the text and of the chunking, which changes when the chunks would.
+    fn content_hash(&self, text: &str) -> String {
+        let mut hasher = Sha256::new();
+        // the chunker is plain data, so it always serializes
+        hasher.update(serde_json::to_string(&self.options.chunker).unwrap());
+        hasher.update([0]);
+        hasher.update(text);
+        format!("{:x}", hasher.finalize())
+    }
+    /// Set the options as builder.
+    pub fn options(mut self, options: IngestOptions) -> Self {
+        self.options = options;
+        self
+    }
+    /// Set the options.
+    pub fn set_options(&mut self, options: IngestOptions) {
+        self.options = options;
+    }
+    /// Get the options.
+    pub fn get_options(&self) -> &IngestOptions {
+        &self.options
+    }
+    /// Get the client that embeds the chunks.
+    pub fn get_client(&self) -> &EmbeddingClient {
+        &self.client
+    }
+    /// Get the store of the chunks.
+    pub fn get_store(&self) -> &Arc<dyn VectorStore> {
+        &self.store
+    }
+}
+
+/// Whether the source is an http(s) url.
+fn is_url(source: &str) -> bool {
+    source.starts_with("http://") || source.starts_with("https://")
+}
+
+/// Load the file within the size limit.
+fn load(path: &Path, max_size: Option<u64>) -> IngestNodeResult<LoadedDocument> {
+    if let Some(max_size) = max_size {
+        let size = std::fs::metadata(path)
+            .map_err(|e| file_error(io_error("read", path, e)))?
+            .len();
+        if size > max_size {
+            return Err(file_error(too_large(path, size, max_size)));
+        }
+    }
+    loader::load(path).map_err(load_error)
+}
+
+/// Fetch the document of the url, within the size limit.
+async fn fetch(url: &str, max_size: Option<u64>) -> IngestNodeResult<LoadedDocument> {
+    let fetch_error =
+        |message: String| IngestNodeError::new(IngestNodeErrorType::FetchError, message);
+    let response = reqwest::Client::new()
+        .get(url)
+        .send()
+        .await
+        .map_err(|e| fetch_error(format!("Failed to fetch {}. {}", url, e)))?;
+    if !response.status().is_success() {
+        return Err(fetch_error(format!(
+            "Failed to fetch {}. The status is {}.",
+            url,
+            response.status()
+        )));
+    }
+    let content_type = response
+        .headers()
+        .get(reqwest::header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default()
+        .to_lowercase();
+    let bytes = response
+        .bytes()
+        .await
+        .map_err(|e| fetch_error(format!("Failed to fetch {}. {}", url, e)))?;
+    if let Some(max_size) = max_size {
+        if bytes.len() as u64 > max_size {
+            return Err(fetch_error(format!(
+                "The page {} is {} bytes, more than the limit of {} bytes.",
+                url,
+                bytes.len(),
+                max_size
+            )));
+        }
+    }
+    let path = url.split(['?', '#']).next().unwrap_or(url);
+    let format = Format::detect(path.rsplit('/').next().unwrap_or_default())
+        .unwrap_or_else(|| format_of(&content_type));
+    let document = loader::parse(&bytes, format).map_err(load_error)?;
+    Ok(document.meta("source", Value::from(url)))
+}
+
+/// Get the format of a content type, plain text when it is unknown.
+fn format_of(content_type: &str) -> Format {
+    if content_type.contains("html") {
+        Format::Html
+    } else if content_type.contains("pdf") {
+        Format::Pdf
+    } else if content_type.contains("markdown") {
+        Format::Markdown
+    } else {
+        Format::Text
+    }
+}
+
+/// Create an IngestNodeError of a file that can't be read.
+fn file_error(e: FileNodeError) -> IngestNodeError {
+    load_error(LoadNodeError::new(
+        LoadNodeErrorType::FileError(Box::new(e)),
+        "Failed to load the file.".to_string(),
+    ))
+}
+
+/// Create an IngestNodeError of the loader.
+fn load_error(e: LoadNodeError) -> IngestNodeError {
+    IngestNodeError::new(
+        IngestNodeErrorType::LoadError(Box::new(e)),
+        "Failed to load a document.".to_string(),
+    )
+}
+
+/// Create an IngestNodeError of the vector store.
+fn store_error(e: VectorStoreError) -> IngestNodeError {
+    IngestNodeError::new(
+        IngestNodeErrorType::StoreError(e),
+        "Failed to use the vector store.".to_string(),
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::vector_store::memory::MemoryVectorStore;
+    use crate::worknode::ai_node::embedding::EmbeddingProvider;
+    use crate::worknode::chunker::ChunkStrategy;
+    use crate::worknode::ingest::IngestNode;
+
+    use serde_json::json;
+    use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+    use tokio::net::TcpListener;
+    use tokio::runtime::Runtime;
+    use uuid::Uuid;
+
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// Answer every request with an embedding per input, and count the inputs embedded.
+    async fn server(listener: TcpListener, embedded: Arc<AtomicUsize>) {
+        loop {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut stream = BufReader::new(stream);
+            let mut length = 0;
+            loop {
+                let mut line = String::new();
+                stream.read_line(&mut line).await.unwrap();
+                if line == "\r\n" {
+                    break;
+                }
+                if let Some(value) = line.to_lowercase().strip_prefix("content-length:") {
+                    length = value.trim().parse().unwrap();
+                }
+            }
+            let mut body = vec![0; length];
+            stream.read_exact(&mut body).await.unwrap();
+            let request: Value = serde_json::from_slice(&body).unwrap();
+            let inputs = request["input"].as_array().unwrap().len();
+            embedded.fetch_add(inputs, Ordering::SeqCst);
+            let answer = json!({ "embeddings": vec![vec![1.0, 0.0]; inputs] }).to_string();
+            let response = format!(
+                "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                answer.len(),
+                answer
+            );
+            stream
+                .get_mut()
+                .write_all(response.as_bytes())
+                .await
+                .unwrap();
+        }
+    }
+
+    #[test]
+    fn incremental_ingest() {
+        let rt = Runtime::new().unwrap();
+        let listener = rt.block_on(TcpListener::bind("127.0.0.1:0")).unwrap();
+        let url = format!("http://{}/api/embed", listener.local_addr().unwrap());
+        let embedded = Arc::new(AtomicUsize::new(0));
+        rt.spawn(server(listener, embedded.clone()));
+        let client = EmbeddingClient::new(EmbeddingProvider::Ollama, &url, "nomic-embed-text");
+
+        let dir = std::env::temp_dir().join(format!("aipilot-kb-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("cats.md"), "# Cats\n\nCats purr.").unwrap();
+        std::fs::write(dir.join("dogs.txt"), "Dogs bark.").unwrap();
+        std::fs::write(dir.join("image.png"), "not a document").unwrap();
+        let sources = vec![dir.display().to_string()];
+        let store = Arc::new(MemoryVectorStore::new());
+        let mut ingest = Ingest::new(client.clone(), store.clone()).options(IngestOptions {
+            sources: sources.clone(),
+            chunker: ChunkerNode::new(ChunkStrategy::Markdown),
+            prune: true,
+            ..IngestOptions::default()
+        });
+
+        let report = rt.block_on(ingest.run()).unwrap();
+        assert_eq!((report.added, report.chunks), (2, 2));
+        let cats = format!("{}#0", dir.join("cats.md").display());
+        let chunk = store.get(&cats).unwrap().unwrap();
+        assert_eq!(chunk.metadata["title"], "Cats");
+        assert_eq!(chunk.metadata["heading"], "Cats");
+
+        // nothing changed, so nothing is embedded again
+        let report = rt.block_on(ingest.run()).unwrap();
+        assert_eq!((report.unchanged, report.chunks), (2, 0));
+        assert_eq!(embedded.load(Ordering::SeqCst), 2);
+
+        // a changed file is embedded again, and a removed file is pruned
+        std::fs::write(dir.join("cats.md"), "# Cats\n\nCats purr and sleep.").unwrap();
+        std::fs::remove_file(dir.join("dogs.txt")).unwrap();
+        let report = rt.block_on(ingest.run()).unwrap();
+        assert_eq!(
+            report,
+            IngestReport {
+                updated: 1,
+                removed: 1,
+                chunks: 1,
+                ..IngestReport::default()
+            }
+        );
+        assert_eq!(store.count().unwrap(), 1);
+        assert!(store.get(&cats).unwrap().unwrap().text.contains("sleep"));
+
+        // the prebuilt workflow ingests the sources of its input
+        let mut workflow = IngestNode::new(client, store.clone())
+            .options(ingest.get_options().clone())
+            .into_workflow();
+        let output = rt
+            .block_on(workflow.run(serde_json::to_string(&sources).unwrap()))
+            .unwrap();
+        let report: IngestReport = serde_json::from_str(&output).unwrap();
+        assert_eq!(report.unchanged, 1);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
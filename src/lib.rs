@@ -0,0 +1,21 @@
+//! # AI Pilot
+//!
+//! AI Pilot is a tool that lets ordinary people explore more possibilities of AI. It builds
+//! workflows out of nodes, where each node can call an AI service, run a local script or
+//! wait for user input.
+//!
+//! With the `tui` feature, a run can be watched in a dashboard in the terminal (see `tui`).
+//! With the `server` feature, the workflows can be run over HTTP (see `server`).
+
+pub mod config;
+pub mod error;
+pub mod ingest;
+pub mod loader;
+#[cfg(feature = "server")]
+pub mod server;
+pub mod template;
+#[cfg(feature = "tui")]
+pub mod tui;
+pub mod vector_store;
+pub mod workflow;
+pub mod worknode;
@@ -0,0 +1,370 @@
+//! # Loader
+//!
+//! This module turns files into plain text with metadata, the documents that the chunker
+//! splits and the embed node turns into vectors.
+//!
+//! The format of a file is detected from its extension, or given:
+//! 1. Text: the text as it is.
+//! 2. Markdown: the YAML front matter is moved to the metadata, and the title is the `title`
+//!    of the front matter or the first `# ` heading.
+//! 3. Html: the boilerplate, like scripts, navigation, headers and footers, is stripped, and
+//!    the rest becomes markdown-like text (see [`html`]).
+//! 4. Pdf: the text of the pages, separated by blank lines.
+//! 5. Code: the source as it is, with its language in the metadata.
+//!
+//! A loaded document serializes to `{"text": ..., "metadata": {...}}`, the document input of
+//! the chunker node. The metadata always has the `format`, and when the document is loaded
+//! from a file, its `source` path and `modified` time.
+
+pub mod html;
+
+use crate::error::file_node_error::{FileNodeError, FileNodeErrorType};
+use crate::error::load_node_error::{LoadNodeError, LoadNodeErrorType, LoadNodeResult};
+
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use std::path::{Path, PathBuf};
+
+/// The extensions of the source code, with their language.
+const LANGUAGES: &[(&str, &str)] = &[
+    ("rs", "rust"),
+    ("py", "python"),
+    ("js", "javascript"),
+    ("mjs", "javascript"),
+    ("jsx", "javascript"),
+    ("ts", "typescript"),
+    ("tsx", "typescript"),
+    ("go", "go"),
+    ("java", "java"),
+    ("kt", "kotlin"),
+    ("scala", "scala"),
+    ("c", "c"),
+    ("h", "c"),
+    ("cc", "cpp"),
+    ("cpp", "cpp"),
+    ("hpp", "cpp"),
+    ("cs", "csharp"),
+    ("rb", "ruby"),
+    ("php", "php"),
+    ("swift", "swift"),
+    ("lua", "lua"),
+    ("hs", "haskell"),
+    ("zig", "zig"),
+    ("sh", "shell"),
+    ("bash", "shell"),
+    ("sql", "sql"),
+    ("toml", "toml"),
+    ("yaml", "yaml"),
+    ("yml", "yaml"),
+    ("json", "json"),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+/// The enum of the format of a file.
+pub enum Format {
+    /// Plain text.
+    Text,
+    /// Markdown, with an optional YAML front matter.
+    Markdown,
+    /// An HTML page.
+    Html,
+    /// A PDF document.
+    Pdf,
+    /// Source code.
+    Code,
+}
+
+impl Format {
+    /// Detect the format from the extension of the path, or `None` for an unknown extension.
+    pub fn detect<P: AsRef<Path>>(path: P) -> Option<Self> {
+        let extension = path.as_ref().extension()?.to_str()?.to_lowercase();
+        match extension.as_str() {
+            "txt" | "text" | "log" | "csv" | "rst" => Some(Format::Text),
+            "md" | "markdown" | "mdx" => Some(Format::Markdown),
+            "html" | "htm" | "xhtml" => Some(Format::Html),
+            "pdf" => Some(Format::Pdf),
+            _ => language(&extension).map(|_| Format::Code),
+        }
+    }
+    /// Get the name of the format in the metadata.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Format::Text => "text",
+            Format::Markdown => "markdown",
+            Format::Html => "html",
+            Format::Pdf => "pdf",
+            Format::Code => "code",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+/// The struct of a document loaded from a file.
+pub struct LoadedDocument {
+    /// The plain text of the document.
+    pub text: String,
+    /// The metadata of the document, like its source or its title.
+    #[serde(default)]
+    pub metadata: Map<String, Value>,
+}
+
+impl LoadedDocument {
+    /// Create a new LoadedDocument without metadata.
+    pub fn new(text: &str) -> Self {
+        LoadedDocument {
+            text: text.to_string(),
+            metadata: Map::new(),
+        }
+    }
+    /// Set a value of the metadata as builder.
+    pub fn meta(mut self, key: &str, value: Value) -> Self {
+        self.metadata.insert(key.to_string(), value);
+        self
+    }
+}
+
+/// Load the file, in the format detected from its extension.
+pub fn load<P: AsRef<Path>>(path: P) -> LoadNodeResult<LoadedDocument> {
+    let path = path.as_ref();
+    let format = Format::detect(path).ok_or_else(|| unsupported(path))?;
+    load_as(path, format)
+}
+
+/// Load the file in the format.
+pub fn load_as<P: AsRef<Path>>(path: P, format: Format) -> LoadNodeResult<LoadedDocument> {
+    let path = path.as_ref();
+    let bytes = std::fs::read(path).map_err(|e| read_error(path, e))?;
+    let mut document = parse(&bytes, format)?;
+    if format == Format::Code {
+        if let Some(language) = path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .and_then(|extension| language(&extension.to_lowercase()))
+        {
+            document = document.meta("language", Value::from(language));
+        }
+    }
+    document = document.meta("source", Value::from(path.display().to_string()));
+    if let Ok(modified) = std::fs::metadata(path).and_then(|metadata| metadata.modified()) {
+        let modified: chrono::DateTime<chrono::Utc> = modified.into();
+        document = document.meta("modified", Value::from(modified.to_rfc3339()));
+    }
+    Ok(document)
+}
+
+/// Load all the files of a known format under the directory, in the order of their paths.
+/// The hidden files and directories, whose names start with `.`, are skipped.
+pub fn load_dir<P: AsRef<Path>>(path: P) -> LoadNodeResult<Vec<LoadedDocument>> {
+    list_files(path)?
+        .into_iter()
+        .map(|file| load(&file))
+        .collect()
+}
+
+/// Get the paths of the files of a known format under the directory, sorted, without the
+/// hidden ones.
+pub fn list_files<P: AsRef<Path>>(path: P) -> LoadNodeResult<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    collect_files(path.as_ref(), &mut files)?;
+    files.retain(|file| Format::detect(file).is_some());
+    files.sort();
+    Ok(files)
+}
+
+/// Parse the content of a file in the format.
+pub fn parse(bytes: &[u8], format: Format) -> LoadNodeResult<LoadedDocument> {
+    let mut document = match format {
+        Format::Text | Format::Code => LoadedDocument::new(&String::from_utf8_lossy(bytes)),
+        Format::Markdown => parse_markdown(&String::from_utf8_lossy(bytes))?,
+        Format::Html => {
+            let page = html::to_text(&String::from_utf8_lossy(bytes));
+            let document = LoadedDocument::new(&page.text);
+            match page.title {
+                Some(title) => document.meta("title", Value::from(title)),
+                None => document,
+            }
+        }
+        Format::Pdf => parse_pdf(bytes)?,
+    };
+    document
+        .metadata
+        .insert("format".to_string(), Value::from(format.name()));
+    Ok(document)
+}
+
+/// Get the language of the extension of a source file.
+fn language(extension: &str) -> Option<&'static str> {
+    LANGUAGES
+        .iter()
+        .find(|(known, _)| *known == extension)
+        .map(|(_, language)| *language)
+}
+
+/// Parse markdown, with its front matter as metadata.
+fn parse_markdown(text: &str) -> LoadNodeResult<LoadedDocument> {
+    let mut document = LoadedDocument::new(text);
+    if let Some(rest) = text
+        .strip_prefix("---\n")
+        .or_else(|| text.strip_prefix("---\r\n"))
+    {
+        let end = rest
+            .match_indices("\n---")
+            .map(|(i, _)| i)
+            .find(|&i| matches!(rest[i + 4..].chars().next(), None | Some('\n' | '\r')));
+        if let Some(end) = end {
+            let front_matter: Value = serde_yaml::from_str(&rest[..end]).map_err(|e| {
+                parse_error(format!(
+                    "The front matter of the markdown is not valid. {}",
+                    e
+                ))
+            })?;
+            if let Value::Object(metadata) = front_matter {
+                document.metadata = metadata;
+            }
+            document.text = rest[end + 4..].trim_start_matches(['\r', '\n']).to_string();
+        }
+    }
+    if !document.metadata.contains_key("title") {
+        let title = document
+            .text
+            .lines()
+            .find_map(|line| line.strip_prefix("# "))
+            .map(|title| title.trim().to_string());
+        if let Some(title) = title {
+            document
+                .metadata
+                .insert("title".to_string(), Value::from(title));
+        }
+    }
+    Ok(document)
+}
+
+/// Parse a PDF, with its number of pages as metadata.
+fn parse_pdf(bytes: &[u8]) -> LoadNodeResult<LoadedDocument> {
+    // pdf-extract panics on some malformed documents
+    let pages = std::panic::catch_unwind(|| pdf_extract::extract_text_from_mem_by_pages(bytes))
+        .map_err(|_| parse_error("The PDF is not valid.".to_string()))?
+        .map_err(|e| parse_error(format!("The PDF is not valid. {}", e)))?;
+    let text = pages
+        .iter()
+        .map(|page| page.trim())
+        .filter(|page| !page.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n\n");
+    Ok(LoadedDocument::new(&text).meta("pages", Value::from(pages.len())))
+}
+
+/// Collect the paths of the files under the directory, without the hidden ones.
+fn collect_files(dir: &Path, files: &mut Vec<PathBuf>) -> LoadNodeResult<()> {
+    let entries = std::fs::read_dir(dir).map_err(|e| read_error(dir, e))?;
+    for entry in entries {
+        let path = entry.map_err(|e| read_error(dir, e))?.path();
+        let hidden = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .is_some_and(|name| name.starts_with('.'));
+        if hidden {
+            continue;
+        }
+        if path.is_dir() {
+            collect_files(&path, files)?;
+        } else {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// Create a LoadNodeError of a file that can't be read.
+fn read_error(path: &Path, e: std::io::Error) -> LoadNodeError {
+    LoadNodeError::new(
+        LoadNodeErrorType::FileError(Box::new(FileNodeError::new(
+            FileNodeErrorType::IoError,
+            format!("Failed to read {}. {}", path.display(), e),
+        ))),
+        "Failed to load the file.".to_string(),
+    )
+}
+
+/// Create a LoadNodeError of a file of an unknown format.
+fn unsupported(path: &Path) -> LoadNodeError {
+    LoadNodeError::new(
+        LoadNodeErrorType::UnsupportedFormat,
+        format!("The format of {} is not known.", path.display()),
+    )
+}
+
+/// Create a LoadNodeError of a content that is not valid.
+fn parse_error(message: String) -> LoadNodeError {
+    LoadNodeError::new(LoadNodeErrorType::ParseError, message)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use serde_json::json;
+
+    #[test]
+    fn parse_formats() {
+        assert_eq!(Format::detect("notes/a.MD"), Some(Format::Markdown));
+        assert_eq!(Format::detect("src/main.rs"), Some(Format::Code));
+        assert_eq!(Format::detect("index.htm"), Some(Format::Html));
+        assert_eq!(Format::detect("image.png"), None);
+        assert_eq!(Format::detect("Makefile"), None);
+
+        let markdown = "---\ntitle: Guide\ntags: [a, b]\n---\n\n# Install\n\nRun it.\n";
+        let document = parse(markdown.as_bytes(), Format::Markdown).unwrap();
+        assert_eq!(document.text, "# Install\n\nRun it.\n");
+        assert_eq!(document.metadata["title"], "Guide");
+        assert_eq!(document.metadata["tags"], json!(["a", "b"]));
+        assert_eq!(document.metadata["format"], "markdown");
+        let document = parse(b"Intro\n\n# Usage\nText", Format::Markdown).unwrap();
+        assert_eq!(document.metadata["title"], "Usage");
+        assert!(parse(b"---\n: [\n---\nText", Format::Markdown).is_err());
+
+        let document = parse(b"plain\ntext", Format::Text).unwrap();
+        assert_eq!(document.text, "plain\ntext");
+        assert!(matches!(
+            parse(b"not a pdf", Format::Pdf)
+                .unwrap_err()
+                .get_error_type(),
+            LoadNodeErrorType::ParseError
+        ));
+    }
+
+    #[test]
+    fn load_files() {
+        let dir = std::env::temp_dir().join(format!("aipilot-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(dir.join("src")).unwrap();
+        std::fs::create_dir_all(dir.join(".git")).unwrap();
+        std::fs::write(dir.join("README.md"), "# Readme\n").unwrap();
+        std::fs::write(dir.join("src/lib.rs"), "fn main() {}\n").unwrap();
+        std::fs::write(dir.join("logo.png"), [0x89, 0x50]).unwrap();
+        std::fs::write(dir.join(".git/HEAD.txt"), "ref").unwrap();
+
+        let document = load(dir.join("src/lib.rs")).unwrap();
+        assert_eq!(document.metadata["language"], "rust");
+        assert_eq!(document.metadata["format"], "code");
+        assert!(document.metadata["source"]
+            .as_str()
+            .unwrap()
+            .ends_with("lib.rs"));
+        assert!(document.metadata.contains_key("modified"));
+        assert!(matches!(
+            load(dir.join("logo.png")).unwrap_err().get_error_type(),
+            LoadNodeErrorType::UnsupportedFormat
+        ));
+        assert!(matches!(
+            load(dir.join("missing.md")).unwrap_err().get_error_type(),
+            LoadNodeErrorType::FileError(_)
+        ));
+
+        let documents = load_dir(&dir).unwrap();
+        let texts: Vec<&str> = documents.iter().map(|d| d.text.as_str()).collect();
+        assert_eq!(texts, ["# Readme\n", "fn main() {}\n"]);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
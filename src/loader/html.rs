@@ -0,0 +1,386 @@
+//! # Html
+//!
+//! This module turns an HTML page into the plain text of its content, for retrieval.
+//!
+//! The boilerplate of the page is stripped: the scripts and styles, and the navigation,
+//! headers, footers, sidebars and forms. When the page has a `<main>` or an `<article>`, only
+//! their text is kept. The structure that matters to a reader stays as markdown: the headings
+//! start with `#`, the items of the lists with `- `, and the preformatted blocks are fenced.
+//! The whitespace is collapsed everywhere else, and the entities are decoded.
+
+use regex::Regex;
+
+use std::sync::LazyLock;
+
+/// The tags whose content is boilerplate.
+const BOILERPLATE: &[&str] = &[
+    "script", "style", "noscript", "template", "svg", "nav", "aside", "form", "iframe", "button",
+    "select", "canvas", "head",
+];
+
+/// The tags whose content is boilerplate outside of the main content.
+const PAGE_BOILERPLATE: &[&str] = &["header", "footer"];
+
+/// The tags of the blocks, which are separated by blank lines.
+const BLOCKS: &[&str] = &[
+    "p",
+    "div",
+    "section",
+    "article",
+    "main",
+    "ul",
+    "ol",
+    "dl",
+    "table",
+    "blockquote",
+    "figure",
+    "hr",
+    "header",
+    "footer",
+    "dd",
+    "dt",
+];
+
+/// The tags that have no content nor closing tag.
+const VOID: &[&str] = &[
+    "area", "base", "br", "col", "embed", "hr", "img", "input", "link", "meta", "source", "wbr",
+];
+
+/// The opening tag of the main content.
+static MAIN: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?i)<(main|article)[\s>]").expect("the regex is valid"));
+
+#[derive(Debug, Clone, Default, PartialEq)]
+/// The struct of the text of an HTML page.
+pub struct Page {
+    /// The title of the page, from its `<title>`.
+    pub title: Option<String>,
+    /// The text of the content of the page.
+    pub text: String,
+}
+
+/// The state of the conversion of a page.
+#[derive(Default)]
+struct Converter {
+    /// Whether the page has a main content.
+    has_main: bool,
+    /// The number of main contents the conversion is in.
+    main_depth: usize,
+    /// The boilerplate tags the conversion is in.
+    skipping: Vec<String>,
+    /// Whether the conversion is in a preformatted block.
+    in_pre: bool,
+    /// The title, while or after it is read.
+    title: Option<String>,
+    /// Whether the conversion is in the title.
+    in_title: bool,
+    /// The text.
+    out: String,
+}
+
+impl Converter {
+    /// Whether the text at this point is kept.
+    fn keeps(&self) -> bool {
+        self.skipping.is_empty() && (!self.has_main || self.main_depth > 0)
+    }
+    /// Handle an opening tag.
+    fn open(&mut self, name: &str, self_closing: bool) {
+        let void = self_closing || VOID.contains(&name);
+        let boilerplate = BOILERPLATE.contains(&name)
+            || (PAGE_BOILERPLATE.contains(&name) && self.main_depth == 0);
+        if boilerplate && !void {
+            self.skipping.push(name.to_string());
+            return;
+        }
+        if name == "title" && !void {
+            self.in_title = true;
+            self.title.get_or_insert_with(String::new);
+            return;
+        }
+        if name == "main" || name == "article" {
+            self.main_depth += 1;
+        }
+        if !self.keeps() {
+            return;
+        }
+        match name {
+            "br" => self.out.push('\n'),
+            "li" => self.out.push_str("\n- "),
+            "tr" => self.out.push('\n'),
+            "td" | "th" => self.out.push(' '),
+            "pre" if !void => {
+                self.out.push_str("\n\n```\n");
+                self.in_pre = true;
+            }
+            _ => {
+                if let Some(level) = heading_level(name) {
+                    self.out.push_str("\n\n");
+                    self.out.push_str(&"#".repeat(level));
+                    self.out.push(' ');
+                } else if BLOCKS.contains(&name) {
+                    self.out.push_str("\n\n");
+                }
+            }
+        }
+    }
+    /// Handle a closing tag.
+    fn close(&mut self, name: &str) {
+        if let Some(i) = self.skipping.iter().rposition(|skipped| skipped == name) {
+            self.skipping.truncate(i);
+            return;
+        }
+        if name == "title" {
+            self.in_title = false;
+            return;
+        }
+        let keeps = self.keeps();
+        if (name == "main" || name == "article") && self.main_depth > 0 {
+            self.main_depth -= 1;
+        }
+        if !keeps {
+            return;
+        }
+        if name == "pre" && self.in_pre {
+            self.out.push_str("\n```\n\n");
+            self.in_pre = false;
+        } else if name == "tr" {
+            self.out.push('\n');
+        } else if heading_level(name).is_some() || BLOCKS.contains(&name) {
+            self.out.push_str("\n\n");
+        }
+    }
+    /// Handle the text between tags.
+    fn text(&mut self, text: &str) {
+        let text = decode_entities(text);
+        if self.in_title && self.skipping.iter().all(|skipped| skipped == "head") {
+            if let Some(title) = &mut self.title {
+                title.push_str(&text);
+            }
+            return;
+        }
+        if !self.keeps() {
+            return;
+        }
+        if self.in_pre {
+            self.out.push_str(&text);
+            return;
+        }
+        let mut space = false;
+        for c in text.chars() {
+            if c.is_whitespace() {
+                space = true;
+            } else {
+                if space && !self.out.ends_with([' ', '\n']) && !self.out.is_empty() {
+                    self.out.push(' ');
+                }
+                space = false;
+                self.out.push(c);
+            }
+        }
+        if space && !self.out.ends_with([' ', '\n']) {
+            self.out.push(' ');
+        }
+    }
+}
+
+/// Turn the HTML page into text.
+pub fn to_text(html: &str) -> Page {
+    let mut converter = Converter {
+        has_main: MAIN.is_match(html),
+        ..Converter::default()
+    };
+    let lower = html.to_ascii_lowercase();
+    let mut rest = 0;
+    while rest < html.len() {
+        let Some(start) = html[rest..].find('<').map(|i| rest + i) else {
+            converter.text(&html[rest..]);
+            break;
+        };
+        converter.text(&html[rest..start]);
+        let tag = &html[start..];
+        if tag.starts_with("<!--") {
+            rest = find_after(&lower, start, "-->");
+            continue;
+        }
+        if tag.starts_with("<!") || tag.starts_with("<?") {
+            rest = find_after(&lower, start, ">");
+            continue;
+        }
+        let closing = tag.starts_with("</");
+        let name_start = start + if closing { 2 } else { 1 };
+        let name: String = lower[name_start..]
+            .chars()
+            .take_while(|c| c.is_ascii_alphanumeric())
+            .collect();
+        if name.is_empty() {
+            // a `<` that doesn't start a tag is text
+            converter.text("<");
+            rest = start + 1;
+            continue;
+        }
+        let end = find_after(&lower, start, ">");
+        if closing {
+            converter.close(&name);
+            rest = end;
+            continue;
+        }
+        converter.open(&name, html[start..end].ends_with("/>"));
+        // the content of scripts and styles is not html
+        if name == "script" || name == "style" {
+            let close = lower[end..]
+                .find(&format!("</{}", name))
+                .map_or(html.len(), |i| end + i);
+            converter.close(&name);
+            rest = find_after(&lower, close, ">");
+        } else {
+            rest = end;
+        }
+    }
+    Page {
+        title: converter
+            .title
+            .map(|title| title.split_whitespace().collect::<Vec<_>>().join(" "))
+            .filter(|title| !title.is_empty()),
+        text: clean(&converter.out),
+    }
+}
+
+/// Get the level of a heading tag.
+fn heading_level(name: &str) -> Option<usize> {
+    match name.as_bytes() {
+        [b'h', level @ b'1'..=b'6'] => Some((level - b'0') as usize),
+        _ => None,
+    }
+}
+
+/// Get the index after the pattern found from the start, or the end of the text.
+fn find_after(text: &str, start: usize, pattern: &str) -> usize {
+    text[start..]
+        .find(pattern)
+        .map_or(text.len(), |i| start + i + pattern.len())
+}
+
+/// Decode the entities of the text.
+fn decode_entities(text: &str) -> String {
+    if !text.contains('&') {
+        return text.to_string();
+    }
+    let mut decoded = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(i) = rest.find('&') {
+        decoded.push_str(&rest[..i]);
+        rest = &rest[i..];
+        let entity = rest[1..]
+            .find(';')
+            .filter(|&end| end <= 10)
+            .and_then(|end| decode_entity(&rest[1..end + 1]).map(|c| (c, end + 2)));
+        match entity {
+            Some((c, length)) => {
+                decoded.push(c);
+                rest = &rest[length..];
+            }
+            None => {
+                decoded.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    decoded.push_str(rest);
+    decoded
+}
+
+/// Decode an entity without its `&` and `;`.
+fn decode_entity(entity: &str) -> Option<char> {
+    if let Some(number) = entity.strip_prefix('#') {
+        let code = match number.strip_prefix(['x', 'X']) {
+            Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+            None => number.parse().ok()?,
+        };
+        return char::from_u32(code);
+    }
+    Some(match entity {
+        "amp" => '&',
+        "lt" => '<',
+        "gt" => '>',
+        "quot" => '"',
+        "apos" => '\'',
+        "nbsp" => ' ',
+        "ndash" => '–',
+        "mdash" => '—',
+        "hellip" => '…',
+        "lsquo" => '‘',
+        "rsquo" => '’',
+        "ldquo" => '“',
+        "rdquo" => '”',
+        "copy" => '©',
+        "reg" => '®',
+        "trade" => '™',
+        _ => return None,
+    })
+}
+
+/// Trim the lines outside of the fences, and keep at most one blank line between blocks.
+fn clean(text: &str) -> String {
+    let mut lines: Vec<&str> = Vec::new();
+    let mut in_fence = false;
+    for line in text.lines() {
+        let line = match in_fence {
+            true => line.trim_end(),
+            false => line.trim(),
+        };
+        if line == "```" {
+            in_fence = !in_fence;
+        }
+        if line.is_empty() && !in_fence && lines.last().is_none_or(|last| last.is_empty()) {
+            continue;
+        }
+        lines.push(line);
+    }
+    while lines.last().is_some_and(|last| last.is_empty()) {
+        lines.pop();
+    }
+    lines.join("\n")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn strip_boilerplate() {
+        let page = to_text(
+            r#"<!DOCTYPE html>
+<html><head><title> The  Guide </title><style>p { color: red; }</style>
+<script>if (a < b) { document.write("<p>x</p>"); }</script></head>
+<body>
+  <nav><a href="/">Home</a> <a href="/docs">Docs</a></nav>
+  <header>Site banner</header>
+  <main>
+    <article>
+      <header><h1>Getting   started</h1></header>
+      <!-- a comment -->
+      <p>Install the <b>tool</b> &amp; run it.<br>Then wait&hellip;</p>
+      <ul><li>Fast</li><li>Safe &#x2713;</li></ul>
+      <pre>fn main() {
+    run();
+}</pre>
+      <form><input name="q"><button>Search</button></form>
+    </article>
+  </main>
+  <aside>Related posts</aside>
+  <footer>&copy; 2024</footer>
+</body></html>"#,
+        );
+        assert_eq!(page.title.as_deref(), Some("The Guide"));
+        assert_eq!(
+            page.text,
+            "# Getting started\n\nInstall the tool & run it.\nThen wait…\n\n- Fast\n- Safe ✓\n\n\
+             ```\nfn main() {\n    run();\n}\n```"
+        );
+
+        // without a main content, the body is kept without its boilerplate
+        let page = to_text("<body><nav>Menu</nav><div>1 < 2</div><p>Text</p></body>");
+        assert_eq!(page.title, None);
+        assert_eq!(page.text, "1 < 2\n\nText");
+    }
+}
@@ -1,6 +0,0 @@
-mod error;
-mod worknode;
-
-fn main() {
-    println!("Hello, world!");
-}
@@ -0,0 +1,771 @@
+//! # Server
+//!
+//! This module serves workflows over HTTP, so AI Pilot can be a small workflow backend. Every
+//! workflow registered to a `WorkflowServer` by name can be run by a request, and the run goes
+//! on in the background while the client polls its status:
+//! - `GET /workflows`: the names of the workflows.
+//! - `POST /workflows/{name}/runs`: start a run of the workflow. The body is a json object
+//!   with the `input` of the start node and optionally the values of the run `context`. The
+//!   answer is `202 Accepted` with the state of the run, and its url in `Location`.
+//! - `GET /runs/{id}`: the state of the run: its status (`running`, `completed`, `failed` or
+//!   `cancelled`), and when it ended, its output or error and its report (see
+//!   [`crate::workflow::run::RunReport`]).
+//! - `GET /runs/{id}/events`: the events of the run as server-sent events, so a browser can
+//!   show the progress live. The name of an event is its type, like `node_started`, and its
+//!   data is a json object with the fields of the event (see
+//!   [`crate::workflow::event::RunEvent`]), the durations in milliseconds. The pieces of the
+//!   answers of the AI nodes are `node_output` events. A client that connects late gets the
+//!   events since the start first, and the stream ends after the `run_finished` event. The
+//!   `node_output` events of a node are not kept once it finished, as its `node_finished`
+//!   event has the whole answer. A client that doesn't keep up with the events is dropped.
+//! - `POST /runs/{id}/cancel`: cancel the run.
+//!
+//! Every run gets its own copy of the workflow, so the runs of one workflow don't share
+//! histories. A workflow can also be registered with its `WorkflowWatcher`, so a run uses the
+//! version of the file when it starts (see [`crate::workflow::reload`]).
+//!
+//! The AI and agent nodes of the runs stream their answers, unless `stream_output` is turned
+//! off. The finished runs are kept for polling with their events, at most `max_finished` of
+//! them. The oldest are dropped first. The errors are answered as a json object with an `error` field.
+
+use crate::workflow::context::RunContext;
+use crate::workflow::event::RunEvent;
+use crate::workflow::execution_log::millis;
+use crate::workflow::reload::WorkflowWatcher;
+use crate::workflow::run::{RunHandle, RunStatus};
+use crate::workflow::Workflow;
+
+use axum::body::Bytes;
+use axum::extract::{Path, State};
+use axum::http::{header, StatusCode};
+use axum::response::sse::{Event, KeepAlive, Sse};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tokio::net::TcpListener;
+use tokio::sync::mpsc::{Sender, UnboundedReceiver};
+use tokio_stream::wrappers::ReceiverStream;
+use tokio_stream::StreamExt;
+use tokio_util::sync::CancellationToken;
+use uuid::Uuid;
+
+use std::collections::{HashMap, VecDeque};
+use std::convert::Infallible;
+use std::sync::{Arc, Mutex, PoisonError};
+
+/// The enum of a workflow registered to the server.
+enum Registered {
+    /// A workflow that doesn't change.
+    Fixed(Box<Workflow>),
+    /// A workflow reloaded when its file changes.
+    Watched(WorkflowWatcher),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+/// The enum of the status of a run of the server.
+pub enum RunState {
+    /// The run is going on.
+    Running,
+    /// The run completed.
+    Completed,
+    /// The run failed.
+    Failed,
+    /// The run was cancelled.
+    Cancelled,
+}
+
+#[derive(Debug, Clone, Serialize)]
+/// The struct of the state of a run of the server, as it is answered to the clients.
+pub struct RunInfo {
+    /// The id of the run.
+    pub id: Uuid,
+    /// The name of the workflow.
+    pub workflow: String,
+    /// The status of the run.
+    pub status: RunState,
+    /// When the run started.
+    pub started_at: DateTime<Utc>,
+    /// When the run ended.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub finished_at: Option<DateTime<Utc>>,
+    /// The output of the end node, if the run completed.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub output: Option<String>,
+    /// The error of the run, if it failed or was cancelled.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// The report of the run, when it ended.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub report: Option<serde_json::Value>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+/// The struct of the body of a request that starts a run.
+struct RunRequest {
+    /// The input of the start node.
+    #[serde(default)]
+    input: String,
+    /// The values put in the run context before the run.
+    #[serde(default)]
+    context: serde_json::Map<String, serde_json::Value>,
+}
+
+/// The struct of a run kept by the server.
+struct RunEntry {
+    /// The state of the run.
+    info: RunInfo,
+    /// The token that cancels the run.
+    token: CancellationToken,
+    /// The events of the run so far, with the node of the `node_output` events.
+    events: Vec<(Option<Uuid>, Event)>,
+    /// The clients that the events are relayed to, until the run ended.
+    subscribers: Vec<Sender<Event>>,
+    /// Whether the last event of the run is relayed.
+    relayed: bool,
+}
+
+/// The struct of the runs kept by the server.
+struct Runs {
+    /// The runs by id.
+    runs: HashMap<Uuid, RunEntry>,
+    /// The ids of the finished runs, the oldest first.
+    finished: VecDeque<Uuid>,
+    /// The most finished runs that are kept.
+    max_finished: usize,
+}
+
+/// The struct of the state shared by the handlers.
+#[derive(Clone)]
+struct ServerState {
+    /// The workflows by name.
+    workflows: Arc<HashMap<String, Registered>>,
+    /// The runs.
+    runs: Arc<Mutex<Runs>>,
+    /// Whether the nodes of the runs stream their answers.
+    stream_output: bool,
+}
+
+/// The struct of the HTTP server of the workflows.
+pub struct WorkflowServer {
+    /// The workflows by name.
+    workflows: HashMap<String, Registered>,
+    /// The most finished runs that are kept for polling.
+    max_finished: usize,
+    /// Whether the AI and agent nodes of the runs stream their answers.
+    stream_output: bool,
+}
+
+impl std::fmt::Debug for WorkflowServer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("WorkflowServer")
+            .field("workflows", &self.get_names())
+            .field("max_finished", &self.max_finished)
+            .field("stream_output", &self.stream_output)
+            .finish()
+    }
+}
+
+impl Default for WorkflowServer {
+    fn default() -> Self {
+        WorkflowServer {
+            workflows: HashMap::new(),
+            max_finished: Self::default_max_finished(),
+            stream_output: true,
+        }
+    }
+}
+
+impl Registered {
+    /// Get a copy of the workflow to run.
+    fn current(&self) -> Workflow {
+        match self {
+            Registered::Fixed(workflow) => workflow.as_ref().clone(),
+            Registered::Watched(watcher) => watcher.current(),
+        }
+    }
+}
+
+impl From<RunStatus> for RunState {
+    fn from(status: RunStatus) -> Self {
+        match status {
+            RunStatus::Completed => RunState::Completed,
+            RunStatus::Failed => RunState::Failed,
+            RunStatus::Cancelled => RunState::Cancelled,
+        }
+    }
+}
+
+impl Runs {
+    /// Mark the run as finished, and drop the oldest finished runs over the limit.
+    fn finish(&mut self, id: Uuid) {
+        self.finished.push_back(id);
+        while self.finished.len() > self.max_finished {
+            if let Some(oldest) = self.finished.pop_front() {
+                self.runs.remove(&oldest);
+            }
+        }
+    }
+}
+
+impl WorkflowServer {
+    /// Create a new WorkflowServer without workflows.
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Register the workflow with the name as builder. A workflow of the same name is
+    /// replaced.
+    pub fn workflow(mut self, name: &str, workflow: Workflow) -> Self {
+        self.workflows
+            .insert(name.to_string(), Registered::Fixed(Box::new(workflow)));
+        self
+    }
+    /// Register the workflow of the watcher with the name as builder, so the runs use the
+    /// current version of its file.
+    pub fn watcher(mut self, name: &str, watcher: WorkflowWatcher) -> Self {
+        self.workflows
+            .insert(name.to_string(), Registered::Watched(watcher));
+        self
+    }
+    /// Get the names of the workflows, sorted.
+    pub fn get_names(&self) -> Vec<&str> {
+        let mut names: Vec<&str> = self.workflows.keys().map(String::as_str).collect();
+        names.sort();
+        names
+    }
+    /// Set the most finished runs that are kept as builder.
+    pub fn max_finished(mut self, max_finished: usize) -> Self {
+        self.max_finished = max_finished;
+        self
+    }
+    /// Set the most finished runs that are kept for polling.
+    pub fn set_max_finished(&mut self, max_finished: usize) {
+        self.max_finished = max_finished;
+    }
+    /// Get the most finished runs that are kept.
+    pub fn get_max_finished(&self) -> usize {
+        self.max_finished
+    }
+    /// Get the default of the most finished runs that are kept.
+    pub fn default_max_finished() -> usize {
+        1000
+    }
+    /// Set whether the nodes stream their answers as builder.
+    pub fn stream_output(mut self, stream_output: bool) -> Self {
+        self.stream_output = stream_output;
+        self
+    }
+    /// Set whether the AI and agent nodes of the runs stream their answers, and relay the
+    /// pieces as `node_output` events. It is on by default.
+    pub fn set_stream_output(&mut self, stream_output: bool) {
+        self.stream_output = stream_output;
+    }
+    /// Get whether the nodes of the runs stream their answers.
+    pub fn get_stream_output(&self) -> bool {
+        self.stream_output
+    }
+    /// Build the router of the endpoints, to serve it or to nest it in another router.
+    pub fn router(self) -> Router {
+        let state = ServerState {
+            workflows: Arc::new(self.workflows),
+            runs: Arc::new(Mutex::new(Runs {
+                runs: HashMap::new(),
+                finished: VecDeque::new(),
+                max_finished: self.max_finished,
+            })),
+            stream_output: self.stream_output,
+        };
+        Router::new()
+            .route("/workflows", get(list_workflows))
+            .route("/workflows/{name}/runs", post(start_run))
+            .route("/runs/{id}", get(get_run))
+            .route("/runs/{id}/events", get(run_events))
+            .route("/runs/{id}/cancel", post(cancel_run))
+            .with_state(state)
+    }
+    /// Serve the endpoints on the listener, until the process ends.
+    pub async fn serve(self, listener: TcpListener) -> std::io::Result<()> {
+        if let Ok(addr) = listener.local_addr() {
+            log::info!("Serving the workflows {:?} on {}.", self.get_names(), addr);
+        }
+        axum::serve(listener, self.router()).await
+    }
+}
+
+/// Answer the error with the status.
+fn error_response(status: StatusCode, message: String) -> Response {
+    (status, Json(serde_json::json!({ "error": message }))).into_response()
+}
+
+/// List the names of the workflows.
+async fn list_workflows(State(state): State<ServerState>) -> Json<Vec<String>> {
+    let mut names: Vec<String> = state.workflows.keys().cloned().collect();
+    names.sort();
+    Json(names)
+}
+
+/// Start a run of the workflow of the name.
+async fn start_run(
+    State(state): State<ServerState>,
+    Path(name): Path<String>,
+    body: Bytes,
+) -> Response {
+    let Some(registered) = state.workflows.get(&name) else {
+        return error_response(
+            StatusCode::NOT_FOUND,
+            format!("There is no workflow {}.", name),
+        );
+    };
+    // a request without a body runs with an empty input
+    let request = if body.is_empty() {
+        RunRequest::default()
+    } else {
+        match serde_json::from_slice::<RunRequest>(&body) {
+            Ok(request) => request,
+            Err(e) => {
+                return error_response(
+                    StatusCode::BAD_REQUEST,
+                    format!("The body of the request is not valid. {}", e),
+                )
+            }
+        }
+    };
+    let context = RunContext::new();
+    for (key, value) in request.context {
+        context.set_value(&key, value);
+    }
+    // the events wait in the channel until the run is registered and relayed
+    let (sender, receiver) = tokio::sync::mpsc::unbounded_channel();
+    let handle = registered
+        .current()
+        .events(Some(sender))
+        .stream_output(state.stream_output)
+        .run_detached_with_context(request.input, context);
+    let id = Uuid::new_v4();
+    let info = RunInfo {
+        id,
+        workflow: name,
+        status: RunState::Running,
+        started_at: Utc::now(),
+        finished_at: None,
+        output: None,
+        error: None,
+        report: None,
+    };
+    state
+        .runs
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .runs
+        .insert(
+            id,
+            RunEntry {
+                info: info.clone(),
+                token: handle.get_token(),
+                events: Vec::new(),
+                subscribers: Vec::new(),
+                relayed: false,
+            },
+        );
+    tokio::spawn(relay_run(state.runs.clone(), id, handle, receiver));
+    (
+        StatusCode::ACCEPTED,
+        [(header::LOCATION, format!("/runs/{}", id))],
+        Json(info),
+    )
+        .into_response()
+}
+
+/// Relay the events of the run to its clients until it ends, and record its result. The
+/// result is recorded before the last event is relayed, so a client that sees the end of the
+/// run also finds its result.
+async fn relay_run(
+    runs: Arc<Mutex<Runs>>,
+    id: Uuid,
+    handle: RunHandle,
+    mut receiver: UnboundedReceiver<RunEvent>,
+) {
+    let mut last = None;
+    while let Some(event) = receiver.recv().await {
+        if matches!(event, RunEvent::RunFinished { .. }) {
+            last = Some(event);
+            break;
+        }
+        relay(&runs, id, &event);
+    }
+    let (_, report) = handle.join().await;
+    {
+        let mut runs = runs.lock().unwrap_or_else(PoisonError::into_inner);
+        if let Some(entry) = runs.runs.get_mut(&id) {
+            entry.info.status = report.get_status().into();
+            entry.info.finished_at = Some(Utc::now());
+            entry.info.output = report.get_output().map(str::to_string);
+            entry.info.error = report.get_error().map(|e| e.to_string());
+            entry.info.report = serde_json::to_value(&report).ok();
+        }
+    }
+    if let Some(event) = last {
+        relay(&runs, id, &event);
+    }
+    let mut runs = runs.lock().unwrap_or_else(PoisonError::into_inner);
+    if let Some(entry) = runs.runs.get_mut(&id) {
+        // the streams of the clients end when their senders are dropped
+        entry.subscribers.clear();
+        entry.relayed = true;
+    }
+    runs.finish(id);
+}
+
+/// The most events waiting to be sent to a client, besides the events of the run so far.
+const SUBSCRIBER_CAPACITY: usize = 1024;
+
+/// Keep the event of the run and send it to its clients. A client whose events are full is
+/// dropped.
+fn relay(runs: &Mutex<Runs>, id: Uuid, event: &RunEvent) {
+    let sse = sse_event(event);
+    let mut runs = runs.lock().unwrap_or_else(PoisonError::into_inner);
+    if let Some(entry) = runs.runs.get_mut(&id) {
+        entry
+            .subscribers
+            .retain(|subscriber| subscriber.try_send(sse.clone()).is_ok());
+        match event {
+            RunEvent::NodeOutput { node, .. } => entry.events.push((Some(*node), sse)),
+            RunEvent::NodeFinished { node, .. } | RunEvent::NodeFailed { node, .. } => {
+                // the pieces of the answer are not needed any more
+                entry
+                    .events
+                    .retain(|(output, _)| output.as_ref() != Some(node));
+                entry.events.push((None, sse));
+            }
+            _ => entry.events.push((None, sse)),
+        }
+    }
+}
+
+/// Get the name and the json data of the event.
+fn event_json(event: &RunEvent) -> (&'static str, serde_json::Value) {
+    use serde_json::json;
+
+    match event {
+        RunEvent::NodeStarted { node, kind } => {
+            ("node_started", json!({ "node": node, "kind": kind }))
+        }
+        RunEvent::NodeOutput { node, delta } => {
+            ("node_output", json!({ "node": node, "delta": delta }))
+        }
+        RunEvent::NodeFinished {
+            node,
+            output,
+            usage,
+            duration,
+        } => (
+            "node_finished",
+            json!({
+                "node": node,
+                "output": output,
+                "usage": usage,
+                "duration_ms": millis(*duration),
+            }),
+        ),
+        RunEvent::NodeSkipped { node } => ("node_skipped", json!({ "node": node })),
+        RunEvent::ApprovalRequested(request) => (
+            "approval_requested",
+            json!({
+                "node": request.node,
+                "message": request.message,
+                "input": request.input,
+            }),
+        ),
+        RunEvent::NodeFailed {
+            node,
+            error,
+            duration,
+        } => (
+            "node_failed",
+            json!({ "node": node, "error": error, "duration_ms": millis(*duration) }),
+        ),
+        RunEvent::RunFinished { status, duration } => (
+            "run_finished",
+            json!({ "status": RunState::from(*status), "duration_ms": millis(*duration) }),
+        ),
+    }
+}
+
+/// Get the server-sent event of the event of the run.
+fn sse_event(event: &RunEvent) -> Event {
+    let (name, data) = event_json(event);
+    Event::default().event(name).data(data.to_string())
+}
+
+/// Stream the events of the run, the ones so far first.
+async fn run_events(State(state): State<ServerState>, Path(id): Path<Uuid>) -> Response {
+    let mut runs = state.runs.lock().unwrap_or_else(PoisonError::into_inner);
+    let Some(entry) = runs.runs.get_mut(&id) else {
+        return error_response(StatusCode::NOT_FOUND, format!("There is no run {}.", id));
+    };
+    let (sender, receiver) = tokio::sync::mpsc::channel(entry.events.len() + SUBSCRIBER_CAPACITY);
+    for (_, event) in &entry.events {
+        let _ = sender.try_send(event.clone());
+    }
+    // a finished run has no more events, so its stream ends after the ones so far
+    if !entry.relayed {
+        entry.subscribers.push(sender);
+    }
+    let stream = ReceiverStream::new(receiver).map(Ok::<Event, Infallible>);
+    Sse::new(stream)
+        .keep_alive(KeepAlive::default())
+        .into_response()
+}
+
+/// Get the state of the run.
+async fn get_run(State(state): State<ServerState>, Path(id): Path<Uuid>) -> Response {
+    let runs = state.runs.lock().unwrap_or_else(PoisonError::into_inner);
+    match runs.runs.get(&id) {
+        Some(entry) => Json(entry.info.clone()).into_response(),
+        None => error_response(StatusCode::NOT_FOUND, format!("There is no run {}.", id)),
+    }
+}
+
+/// Cancel the run, and get its state.
+async fn cancel_run(State(state): State<ServerState>, Path(id): Path<Uuid>) -> Response {
+    let runs = state.runs.lock().unwrap_or_else(PoisonError::into_inner);
+    match runs.runs.get(&id) {
+        Some(entry) => {
+            entry.token.cancel();
+            Json(entry.info.clone()).into_response()
+        }
+        None => error_response(StatusCode::NOT_FOUND, format!("There is no run {}.", id)),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::worknode::{Worknode, Worknodecore};
+
+    use tokio::runtime::Runtime;
+
+    #[test]
+    fn serve_runs() {
+        let mut workflow = Workflow::new();
+        let start = workflow.add_node(Worknode::new(Worknodecore::Start));
+        let end = workflow.add_node(Worknode::new(Worknodecore::End));
+        workflow.add_edge(start, end).unwrap();
+        let server = WorkflowServer::new()
+            .workflow("echo", workflow)
+            .max_finished(1);
+
+        let rt = Runtime::new().unwrap();
+        let listener = rt.block_on(TcpListener::bind("127.0.0.1:0")).unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        rt.spawn(server.serve(listener));
+        let client = reqwest::Client::new();
+        let json = |response: reqwest::Response| async move {
+            serde_json::from_str::<serde_json::Value>(&response.text().await.unwrap()).unwrap()
+        };
+        rt.block_on(async {
+            let names = json(
+                client
+                    .get(format!("{}/workflows", url))
+                    .send()
+                    .await
+                    .unwrap(),
+            )
+            .await;
+            assert_eq!(names, serde_json::json!(["echo"]));
+
+            let start_run = |input: &str| {
+                client
+                    .post(format!("{}/workflows/echo/runs", url))
+                    .body(serde_json::json!({ "input": input }).to_string())
+                    .send()
+            };
+            let response = start_run("hi").await.unwrap();
+            assert_eq!(response.status(), 202);
+            let location = response.headers()["location"].to_str().unwrap().to_string();
+            let run = json(response).await;
+            assert_eq!(location, format!("/runs/{}", run["id"].as_str().unwrap()));
+            assert_eq!(run["workflow"], "echo");
+
+            // poll until the run ends
+            let run = loop {
+                let run = json(
+                    client
+                        .get(format!("{}{}", url, location))
+                        .send()
+                        .await
+                        .unwrap(),
+                )
+                .await;
+                if run["status"] != "running" {
+                    break run;
+                }
+                tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+            };
+            assert_eq!(run["status"], "completed");
+            assert_eq!(run["output"], "hi");
+            assert_eq!(run["report"]["status"], "completed");
+
+            let missing = client
+                .post(format!("{}/workflows/nope/runs", url))
+                .send()
+                .await
+                .unwrap();
+            assert_eq!(missing.status(), 404);
+            assert_eq!(json(missing).await["error"], "There is no workflow nope.");
+            let invalid = client
+                .post(format!("{}/workflows/echo/runs", url))
+                .body("{\"inputs\": 1}")
+                .send()
+                .await
+                .unwrap();
+            assert_eq!(invalid.status(), 400);
+
+            // only the last finished run is kept
+            let second = start_run("again").await.unwrap();
+            let second = json(second).await;
+            loop {
+                let response = client.get(format!("{}{}", url, location)).send().await;
+                if response.unwrap().status() == 404 {
+                    break;
+                }
+                tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+            }
+            let second = client
+                .get(format!("{}/runs/{}", url, second["id"].as_str().unwrap()))
+                .send()
+                .await
+                .unwrap();
+            assert_eq!(second.status(), 200);
+        });
+    }
+
+    #[test]
+    fn relay_events() {
+        use crate::worknode::delay::DelayNode;
+
+        let mut workflow = Workflow::new();
+        let start = workflow.add_node(Worknode::new(Worknodecore::Start));
+        let delay = workflow.add_node(Worknode::new(Worknodecore::Delay(DelayNode::duration(
+            std::time::Duration::from_millis(200),
+        ))));
+        let end = workflow.add_node(Worknode::new(Worknodecore::End));
+        workflow.add_edge(start, delay).unwrap();
+        workflow.add_edge(delay, end).unwrap();
+        let server = WorkflowServer::new().workflow("slow", workflow);
+
+        let rt = Runtime::new().unwrap();
+        let listener = rt.block_on(TcpListener::bind("127.0.0.1:0")).unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        rt.spawn(server.serve(listener));
+        let client = reqwest::Client::new();
+        rt.block_on(async {
+            let response = client
+                .post(format!("{}/workflows/slow/runs", url))
+                .body(r#"{"input": "hi"}"#)
+                .send()
+                .await
+                .unwrap();
+            let location = response.headers()["location"].to_str().unwrap().to_string();
+            // the client connects while the run goes on, and the stream ends with the run
+            let events = client
+                .get(format!("{}{}/events", url, location))
+                .send()
+                .await
+                .unwrap();
+            assert_eq!(events.headers()["content-type"], "text/event-stream");
+            let text = events.text().await.unwrap();
+            let names: Vec<&str> = text
+                .lines()
+                .filter_map(|line| line.strip_prefix("event: "))
+                .collect();
+            assert_eq!(
+                names,
+                [
+                    "node_started",
+                    "node_finished",
+                    "node_started",
+                    "node_finished",
+                    "node_started",
+                    "node_finished",
+                    "run_finished"
+                ]
+            );
+            assert!(text.contains(r#""status":"completed""#));
+
+            // the result is there once the end is relayed, and a late client gets all events
+            let run = client.get(format!("{}{}", url, location)).send().await;
+            assert!(run
+                .unwrap()
+                .text()
+                .await
+                .unwrap()
+                .contains(r#""output":"hi""#));
+            let again = client
+                .get(format!("{}{}/events", url, location))
+                .send()
+                .await
+                .unwrap();
+            assert_eq!(again.text().await.unwrap(), text);
+        });
+
+        let (name, data) = event_json(&RunEvent::NodeOutput {
+            node: delay,
+            delta: "Hel".to_string(),
+        });
+        assert_eq!(name, "node_output");
+        assert_eq!(data["delta"], "Hel");
+
+        // the pieces of a finished node are not kept, and a client that is full is dropped
+        let id = Uuid::new_v4();
+        let info = RunInfo {
+            id,
+            workflow: "slow".to_string(),
+            status: RunState::Running,
+            started_at: Utc::now(),
+            finished_at: None,
+            output: None,
+            error: None,
+            report: None,
+        };
+        let (sender, mut receiver) = tokio::sync::mpsc::channel(1);
+        let runs = Mutex::new(Runs {
+            runs: HashMap::from([(
+                id,
+                RunEntry {
+                    info,
+                    token: CancellationToken::new(),
+                    events: Vec::new(),
+                    subscribers: vec![sender],
+                    relayed: false,
+                },
+            )]),
+            finished: VecDeque::new(),
+            max_finished: 1,
+        });
+        for delta in ["Hel", "lo"] {
+            let delta = delta.to_string();
+            relay(&runs, id, &RunEvent::NodeOutput { node: delay, delta });
+        }
+        {
+            let runs = runs.lock().unwrap();
+            assert_eq!(runs.runs[&id].events.len(), 2);
+            assert!(runs.runs[&id].subscribers.is_empty());
+        }
+        assert!(receiver.try_recv().is_ok());
+        relay(
+            &runs,
+            id,
+            &RunEvent::NodeFinished {
+                node: delay,
+                output: "Hello".to_string(),
+                usage: None,
+                duration: std::time::Duration::ZERO,
+            },
+        );
+        assert_eq!(runs.lock().unwrap().runs[&id].events.len(), 1);
+    }
+}
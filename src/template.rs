@@ -0,0 +1,125 @@
+//! # Template
+//!
+//! This module renders the simple templates used in prompts and node configurations.
+//!
+//! A template is a text with variables written as `{{name}}` (spaces inside the braces are
+//! allowed). A variable can have a default value, used when the variable is not given:
+//! `{{name | default: some text}}`. A variable without default that is not given is an error.
+//!
+//! Some variables are always available:
+//! - `cwd`: the current working directory.
+//! - `date`: the current local date, like `2025-01-31`.
+//! - `time`: the current local time, like `2025-01-31 08:00:00`.
+
+use crate::error::template_error::{TemplateError, TemplateErrorType, TemplateResult};
+
+use std::collections::HashMap;
+
+/// The variables to render a template with.
+pub type Variables = HashMap<String, String>;
+
+/// Render the template with the given variables and the built-in variables. The given
+/// variables shadow the built-in ones.
+pub fn render(template: &str, variables: &Variables) -> TemplateResult<String> {
+    let mut output = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find("{{") {
+        output.push_str(&rest[..start]);
+        let after = &rest[start + 2..];
+        let end = after.find("}}").ok_or(TemplateError::new(
+            TemplateErrorType::SyntaxError,
+            format!("Unclosed variable in template: {}", &rest[start..]),
+        ))?;
+        output.push_str(&resolve(&after[..end], variables)?);
+        rest = &after[end + 2..];
+    }
+    output.push_str(rest);
+    Ok(output)
+}
+
+/// Whether the text contains any variable.
+pub fn is_template(text: &str) -> bool {
+    text.contains("{{")
+}
+
+/// Get the value of one variable expression, the text between `{{` and `}}`.
+fn resolve(expression: &str, variables: &Variables) -> TemplateResult<String> {
+    let (name, default) = match expression.split_once('|') {
+        Some((name, filter)) => {
+            let default = filter
+                .trim()
+                .strip_prefix("default:")
+                .ok_or(TemplateError::new(
+                    TemplateErrorType::SyntaxError,
+                    format!("Unknown filter: {}", filter.trim()),
+                ))?;
+            (name.trim(), Some(default.trim()))
+        }
+        None => (expression.trim(), None),
+    };
+    if name.is_empty() {
+        return Err(TemplateError::new(
+            TemplateErrorType::SyntaxError,
+            "Empty variable name in template.".to_string(),
+        ));
+    }
+    if let Some(value) = variables.get(name) {
+        return Ok(value.clone());
+    }
+    if let Some(value) = builtin(name) {
+        return Ok(value);
+    }
+    default.map(|d| d.to_string()).ok_or(TemplateError::new(
+        TemplateErrorType::MissingVariable,
+        format!("Variable {} is not given.", name),
+    ))
+}
+
+/// Get the value of a built-in variable.
+fn builtin(name: &str) -> Option<String> {
+    match name {
+        "cwd" => std::env::current_dir()
+            .ok()
+            .map(|dir| dir.display().to_string()),
+        "date" => Some(chrono::Local::now().format("%Y-%m-%d").to_string()),
+        "time" => Some(chrono::Local::now().format("%Y-%m-%d %H:%M:%S").to_string()),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn render_variables() {
+        let mut variables = Variables::new();
+        variables.insert("name".to_string(), "Alice".to_string());
+        assert_eq!(
+            render("Hello, {{name}}! {{ name }}", &variables).unwrap(),
+            "Hello, Alice! Alice"
+        );
+        assert_eq!(
+            render("{{mood | default: happy}}", &variables).unwrap(),
+            "happy"
+        );
+        assert!(!render("{{cwd}}", &variables).unwrap().is_empty());
+        assert_eq!(render("no variable", &variables).unwrap(), "no variable");
+    }
+
+    #[test]
+    fn render_errors() {
+        let variables = Variables::new();
+        assert!(matches!(
+            render("{{missing}}", &variables)
+                .unwrap_err()
+                .get_error_type(),
+            TemplateErrorType::MissingVariable
+        ));
+        assert!(matches!(
+            render("{{open", &variables).unwrap_err().get_error_type(),
+            TemplateErrorType::SyntaxError
+        ));
+        assert!(render("{{name | upper}}", &variables).is_err());
+    }
+}
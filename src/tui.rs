@@ -0,0 +1,659 @@
+//! # TUI
+//!
+//! This module is a dashboard in the terminal that watches a run, for the people who babysit
+//! a long agent run. It shows the graph of the workflow with the status and the duration of
+//! every node, the answer of an AI node while it is generated, and the tokens and the cost
+//! spent so far.
+//!
+//! The dashboard is driven by the events of the run (see [`crate::workflow::event`]).
+//! `RunMonitor` folds the events into the state of the run, and `draw` renders the state, so
+//! a program can show the dashboard in its own terminal. `monitor` runs a workflow with the
+//! dashboard in the terminal of the process:
+//! - `q` or Esc cancels the run, and closes the dashboard after the run ended. Ctrl-C
+//!   cancels the run too.
+//! - The arrow keys select the node whose output is shown, and `f` follows the node that
+//!   gives an output again.
+//!
+//! The dashboard takes the terminal, so the user nodes of the workflow must be answered
+//! through the `UserInput` of the run context instead.
+
+use crate::workflow::context::RunContext;
+use crate::workflow::event::RunEvent;
+use crate::workflow::render::SHORT_UID_LEN;
+use crate::workflow::run::{Pricing, RunReport, RunStatus};
+use crate::workflow::Workflow;
+use crate::worknode::ai_node::deepseek::DeepSeekUsage;
+
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+use ratatui::layout::{Constraint, Layout, Rect};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, Cell, Paragraph, Row, Table};
+use ratatui::{DefaultTerminal, Frame};
+use tokio::sync::mpsc::{UnboundedReceiver, UnboundedSender};
+use tokio_util::sync::CancellationToken;
+use uuid::Uuid;
+
+use std::collections::HashMap;
+use std::io;
+use std::time::{Duration, Instant};
+
+/// How often the dashboard is drawn while no event comes.
+const TICK: Duration = Duration::from_millis(100);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// The enum of the status of a node in the dashboard.
+pub enum NodeState {
+    /// The node has not started.
+    Pending,
+    /// The node is running.
+    Running,
+    /// The approval node waits for a decision.
+    Waiting,
+    /// The node finished.
+    Finished,
+    /// The node failed.
+    Failed,
+    /// The node is skipped.
+    Skipped,
+}
+
+#[derive(Debug, Clone)]
+/// The struct of a node in the dashboard.
+pub struct NodeRow {
+    /// The uid of the node.
+    pub uid: Uuid,
+    /// The type of the node, like `ai_node`.
+    pub kind: &'static str,
+    /// The provider of the AI service of the node.
+    pub provider: Option<String>,
+    /// The uids of the nodes that its edges go to.
+    pub next: Vec<Uuid>,
+    /// The status of the node.
+    pub state: NodeState,
+    /// When the node started.
+    pub started: Option<Instant>,
+    /// The time the node took, when it ended.
+    pub duration: Option<Duration>,
+    /// The usage of the node, when it finished.
+    pub usage: Option<DeepSeekUsage>,
+    /// The output of the node, or the part of the answer generated so far.
+    pub output: String,
+    /// The error of the node, when it failed.
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+/// The struct of the state of a run in the dashboard, updated by its events.
+pub struct RunMonitor {
+    /// The nodes, in the order they run.
+    nodes: Vec<NodeRow>,
+    /// The prices of the providers.
+    prices: HashMap<String, Pricing>,
+    /// When the run started.
+    started: Instant,
+    /// How the run ended and the time it took, when it ended.
+    finished: Option<(RunStatus, Duration)>,
+    /// Whether the run is asked to stop.
+    cancelling: bool,
+    /// The tokens used so far.
+    tokens: i64,
+    /// The cost of the nodes whose price is known.
+    cost: f64,
+    /// The node whose output is shown, chosen by the user.
+    selected: Option<usize>,
+    /// The node that gave an output last, shown when no node is chosen.
+    followed: Option<usize>,
+}
+
+impl NodeState {
+    /// Get the name of the status.
+    pub fn name(&self) -> &'static str {
+        match self {
+            NodeState::Pending => "pending",
+            NodeState::Running => "running",
+            NodeState::Waiting => "waiting",
+            NodeState::Finished => "finished",
+            NodeState::Failed => "failed",
+            NodeState::Skipped => "skipped",
+        }
+    }
+    /// Get the color of the status.
+    fn color(&self) -> Color {
+        match self {
+            NodeState::Pending | NodeState::Skipped => Color::DarkGray,
+            NodeState::Running => Color::Yellow,
+            NodeState::Waiting => Color::Magenta,
+            NodeState::Finished => Color::Green,
+            NodeState::Failed => Color::Red,
+        }
+    }
+}
+
+impl NodeRow {
+    /// Get the label of the node: its type and the first part of its uid.
+    pub fn label(&self) -> String {
+        format!("{} {}", self.kind, short_uid(self.uid))
+    }
+    /// Get the time the node took, or has taken so far while it runs.
+    pub fn elapsed(&self) -> Option<Duration> {
+        self.duration
+            .or_else(|| self.started.map(|started| started.elapsed()))
+    }
+}
+
+impl RunMonitor {
+    /// Create a new RunMonitor of the nodes of the workflow, before its run starts.
+    pub fn new(workflow: &Workflow) -> Self {
+        let mut order = workflow
+            .check_start_end()
+            .and_then(|(start, _)| workflow.execution_order(start))
+            .unwrap_or_default();
+        for node in workflow.get_nodes() {
+            if !order.contains(&node.get_uid()) {
+                order.push(node.get_uid());
+            }
+        }
+        let nodes = order
+            .into_iter()
+            .filter_map(|uid| workflow.get_node(uid))
+            .map(|node| NodeRow {
+                uid: node.get_uid(),
+                kind: node.get_node().kind_name(),
+                provider: node.get_node().get_provider().map(str::to_string),
+                next: workflow
+                    .get_edges()
+                    .iter()
+                    .filter(|edge| edge.get_from() == node.get_uid())
+                    .map(|edge| edge.get_to())
+                    .collect(),
+                state: NodeState::Pending,
+                started: None,
+                duration: None,
+                usage: None,
+                output: String::new(),
+                error: None,
+            })
+            .collect::<Vec<NodeRow>>();
+        let prices = nodes
+            .iter()
+            .filter_map(|row| row.provider.as_deref())
+            .filter_map(|provider| {
+                workflow
+                    .get_price(provider)
+                    .map(|price| (provider.to_string(), *price))
+            })
+            .collect();
+        RunMonitor {
+            nodes,
+            prices,
+            started: Instant::now(),
+            finished: None,
+            cancelling: false,
+            tokens: 0,
+            cost: 0.0,
+            selected: None,
+            followed: None,
+        }
+    }
+    /// Update the state with an event of the run.
+    pub fn update(&mut self, event: &RunEvent) {
+        match event {
+            RunEvent::NodeStarted { node, .. } => {
+                if let Some(index) = self.index_of(*node) {
+                    let row = &mut self.nodes[index];
+                    row.state = NodeState::Running;
+                    row.started = Some(Instant::now());
+                    row.duration = None;
+                    row.output.clear();
+                    row.error = None;
+                }
+            }
+            RunEvent::NodeOutput { node, delta } => {
+                if let Some(index) = self.index_of(*node) {
+                    self.nodes[index].output.push_str(delta);
+                    self.followed = Some(index);
+                }
+            }
+            RunEvent::NodeFinished {
+                node,
+                output,
+                usage,
+                duration,
+            } => {
+                if let Some(index) = self.index_of(*node) {
+                    let row = &mut self.nodes[index];
+                    row.state = NodeState::Finished;
+                    row.duration = Some(*duration);
+                    row.usage = *usage;
+                    row.output = output.clone();
+                    if let Some(usage) = usage {
+                        self.tokens += usage.get_total_tokens();
+                        if let Some(price) = row
+                            .provider
+                            .as_deref()
+                            .and_then(|provider| self.prices.get(provider))
+                        {
+                            self.cost += price.cost(usage);
+                        }
+                        self.followed = Some(index);
+                    }
+                }
+            }
+            RunEvent::NodeSkipped { node } => {
+                if let Some(index) = self.index_of(*node) {
+                    self.nodes[index].state = NodeState::Skipped;
+                }
+            }
+            RunEvent::ApprovalRequested(request) => {
+                if let Some(index) = self.index_of(request.node) {
+                    self.nodes[index].state = NodeState::Waiting;
+                    self.nodes[index].output = request.message.clone();
+                    self.followed = Some(index);
+                }
+            }
+            RunEvent::NodeFailed {
+                node,
+                error,
+                duration,
+            } => {
+                if let Some(index) = self.index_of(*node) {
+                    let row = &mut self.nodes[index];
+                    row.state = NodeState::Failed;
+                    row.duration = Some(*duration);
+                    row.error = Some(error.clone());
+                    self.followed = Some(index);
+                }
+            }
+            RunEvent::RunFinished { status, duration } => {
+                self.finished = Some((*status, *duration));
+            }
+        }
+    }
+    /// Get the nodes, in the order they run.
+    pub fn get_nodes(&self) -> &Vec<NodeRow> {
+        &self.nodes
+    }
+    /// Get the node of the uid.
+    pub fn get_node(&self, uid: Uuid) -> Option<&NodeRow> {
+        self.nodes.iter().find(|row| row.uid == uid)
+    }
+    /// Get how the run ended, or `None` while it runs.
+    pub fn get_status(&self) -> Option<RunStatus> {
+        self.finished.map(|(status, _)| status)
+    }
+    /// Get the tokens used so far.
+    pub fn get_tokens(&self) -> i64 {
+        self.tokens
+    }
+    /// Get the cost of the nodes whose price is known, so far.
+    pub fn get_cost(&self) -> f64 {
+        self.cost
+    }
+    /// Get the time the run took, or has taken so far while it runs.
+    pub fn elapsed(&self) -> Duration {
+        self.finished
+            .map_or_else(|| self.started.elapsed(), |(_, duration)| duration)
+    }
+    /// Get the node whose output is shown: the chosen one, or else the one that gave an
+    /// output last.
+    pub fn shown(&self) -> Option<&NodeRow> {
+        self.selected
+            .or(self.followed)
+            .and_then(|index| self.nodes.get(index))
+    }
+    /// Choose the node before the shown one.
+    pub fn select_previous(&mut self) {
+        let current = self.selected.or(self.followed).unwrap_or(0);
+        self.selected = Some(current.saturating_sub(1));
+    }
+    /// Choose the node after the shown one.
+    pub fn select_next(&mut self) {
+        let last = self.nodes.len().saturating_sub(1);
+        self.selected = Some(match self.selected.or(self.followed) {
+            Some(current) => (current + 1).min(last),
+            None => 0,
+        });
+    }
+    /// Show the node that gave an output last again.
+    pub fn follow(&mut self) {
+        self.selected = None;
+    }
+    /// Get the index of the node of the uid.
+    fn index_of(&self, uid: Uuid) -> Option<usize> {
+        self.nodes.iter().position(|row| row.uid == uid)
+    }
+}
+
+/// Draw the dashboard of the run into the frame.
+pub fn draw(frame: &mut Frame, monitor: &RunMonitor) {
+    let [header, body, footer] = Layout::vertical([
+        Constraint::Length(3),
+        Constraint::Min(0),
+        Constraint::Length(1),
+    ])
+    .areas(frame.area());
+    let [graph, output] =
+        Layout::horizontal([Constraint::Percentage(60), Constraint::Percentage(40)]).areas(body);
+    draw_header(frame, header, monitor);
+    draw_graph(frame, graph, monitor);
+    draw_output(frame, output, monitor);
+    let help = match monitor.finished {
+        Some(_) => "q quit  ↑↓ select node  f follow output",
+        None => "q cancel  ↑↓ select node  f follow output",
+    };
+    frame.render_widget(
+        Paragraph::new(help).style(Style::default().fg(Color::DarkGray)),
+        footer,
+    );
+}
+
+/// Draw the status, the time, the progress, the tokens and the cost of the run.
+fn draw_header(frame: &mut Frame, area: Rect, monitor: &RunMonitor) {
+    let (status, color) = match monitor.finished {
+        Some((RunStatus::Completed, _)) => ("completed", Color::Green),
+        Some((RunStatus::Failed, _)) => ("failed", Color::Red),
+        Some((RunStatus::Cancelled, _)) => ("cancelled", Color::DarkGray),
+        None if monitor.cancelling => ("cancelling", Color::Yellow),
+        None => ("running", Color::Yellow),
+    };
+    let done = monitor
+        .nodes
+        .iter()
+        .filter(|row| {
+            matches!(
+                row.state,
+                NodeState::Finished | NodeState::Failed | NodeState::Skipped
+            )
+        })
+        .count();
+    let mut summary = format!(
+        "{:.1?}  nodes {}/{}  tokens {}",
+        monitor.elapsed(),
+        done,
+        monitor.nodes.len(),
+        monitor.tokens
+    );
+    if !monitor.prices.is_empty() {
+        summary.push_str(&format!("  cost {:.6}", monitor.cost));
+    }
+    let line = Line::from(vec![
+        Span::styled(
+            status,
+            Style::default().fg(color).add_modifier(Modifier::BOLD),
+        ),
+        Span::raw(format!("  {}", summary)),
+    ]);
+    frame.render_widget(
+        Paragraph::new(line).block(Block::bordered().title(" aipilot ")),
+        area,
+    );
+}
+
+/// Draw the nodes with their status, time, tokens and the nodes they lead to.
+fn draw_graph(frame: &mut Frame, area: Rect, monitor: &RunMonitor) {
+    let shown = monitor.shown().map(|row| row.uid);
+    let rows = monitor.nodes.iter().map(|row| {
+        let next = row
+            .next
+            .iter()
+            .map(|uid| short_uid(*uid))
+            .collect::<Vec<String>>()
+            .join(" ");
+        let mut style = Style::default();
+        if Some(row.uid) == shown {
+            style = style.add_modifier(Modifier::REVERSED);
+        }
+        Row::new(vec![
+            Cell::from(row.label()),
+            Cell::from(row.provider.clone().unwrap_or_default()),
+            Cell::from(row.state.name()).style(Style::default().fg(row.state.color())),
+            Cell::from(
+                row.elapsed()
+                    .map_or(String::new(), |elapsed| format!("{:.1?}", elapsed)),
+            ),
+            Cell::from(
+                row.usage
+                    .map_or(String::new(), |usage| usage.get_total_tokens().to_string()),
+            ),
+            Cell::from(if next.is_empty() {
+                String::new()
+            } else {
+                format!("→ {}", next)
+            }),
+        ])
+        .style(style)
+    });
+    let table = Table::new(
+        rows,
+        [
+            Constraint::Length(22),
+            Constraint::Length(10),
+            Constraint::Length(8),
+            Constraint::Length(7),
+            Constraint::Length(6),
+            Constraint::Min(0),
+        ],
+    )
+    .header(
+        Row::new(vec!["NODE", "PROVIDER", "STATUS", "TIME", "TOKENS", "NEXT"])
+            .style(Style::default().add_modifier(Modifier::BOLD)),
+    )
+    .block(Block::bordered().title(" graph "));
+    frame.render_widget(table, area);
+}
+
+/// Draw the output of the shown node, its last lines when it doesn't fit.
+fn draw_output(frame: &mut Frame, area: Rect, monitor: &RunMonitor) {
+    let Some(row) = monitor.shown() else {
+        frame.render_widget(
+            Paragraph::new("No output yet.").block(Block::bordered().title(" output ")),
+            area,
+        );
+        return;
+    };
+    let title = format!(" {} ", row.label());
+    let (text, style) = match &row.error {
+        Some(error) => (error.as_str(), Style::default().fg(Color::Red)),
+        None => (row.output.as_str(), Style::default()),
+    };
+    let width = area.width.saturating_sub(2) as usize;
+    let height = area.height.saturating_sub(2) as usize;
+    let lines: Vec<Line> = tail_lines(text, width, height)
+        .into_iter()
+        .map(Line::from)
+        .collect();
+    frame.render_widget(
+        Paragraph::new(lines)
+            .style(style)
+            .block(Block::bordered().title(title)),
+        area,
+    );
+}
+
+/// Wrap the text at the width, and get its last lines that fit in the height.
+fn tail_lines(text: &str, width: usize, height: usize) -> Vec<String> {
+    if width == 0 || height == 0 {
+        return Vec::new();
+    }
+    let mut lines = Vec::new();
+    for line in text.lines() {
+        let chars: Vec<char> = line.chars().collect();
+        if chars.is_empty() {
+            lines.push(String::new());
+        }
+        for piece in chars.chunks(width) {
+            lines.push(piece.iter().collect());
+        }
+    }
+    let skip = lines.len().saturating_sub(height);
+    lines.split_off(skip)
+}
+
+/// Get the first part of the uid, like in the diagrams of the workflow.
+fn short_uid(uid: Uuid) -> String {
+    uid.simple().to_string()[..SHORT_UID_LEN].to_string()
+}
+
+/// Run the workflow with the dashboard in the terminal, and get the report of the run. The
+/// nodes stream their answers to the dashboard, and the events are also sent to the event
+/// channel of the workflow, which is put back at the end. The run is cancelled with the
+/// token, or with `q` in the dashboard.
+pub async fn monitor(
+    workflow: &mut Workflow,
+    input: String,
+    context: &RunContext,
+    token: &CancellationToken,
+) -> io::Result<RunReport> {
+    let events = workflow.get_events().cloned();
+    let stream_output = workflow.get_stream_output();
+    let (sender, receiver) = tokio::sync::mpsc::unbounded_channel();
+    let state = RunMonitor::new(workflow);
+    workflow.set_events(Some(sender));
+    workflow.set_stream_output(true);
+    let result = match ratatui::try_init() {
+        Ok(mut terminal) => {
+            let result = run_dashboard(
+                &mut terminal,
+                workflow,
+                input,
+                context,
+                token,
+                state,
+                receiver,
+                events.as_ref(),
+            )
+            .await;
+            ratatui::restore();
+            result
+        }
+        Err(e) => Err(e),
+    };
+    workflow.set_events(events);
+    workflow.set_stream_output(stream_output);
+    result
+}
+
+/// Run the workflow and draw the dashboard until the run ended and the user quits.
+#[allow(clippy::too_many_arguments)]
+async fn run_dashboard(
+    terminal: &mut DefaultTerminal,
+    workflow: &mut Workflow,
+    input: String,
+    context: &RunContext,
+    token: &CancellationToken,
+    mut state: RunMonitor,
+    mut receiver: UnboundedReceiver<RunEvent>,
+    forward: Option<&UnboundedSender<RunEvent>>,
+) -> io::Result<RunReport> {
+    let run = workflow.run_report(input, context, token);
+    tokio::pin!(run);
+    let mut report = None;
+    let mut quit = false;
+    let mut tick = tokio::time::interval(TICK);
+    loop {
+        tokio::select! {
+            finished = &mut run, if report.is_none() => report = Some(finished),
+            Some(event) = receiver.recv() => {
+                state.update(&event);
+                if let Some(forward) = forward {
+                    let _ = forward.send(event);
+                }
+            }
+            _ = tick.tick() => {}
+        }
+        terminal.draw(|frame| draw(frame, &state))?;
+        while event::poll(Duration::ZERO)? {
+            let Event::Key(key) = event::read()? else {
+                continue;
+            };
+            if key.kind != KeyEventKind::Press {
+                continue;
+            }
+            let ctrl_c =
+                key.code == KeyCode::Char('c') && key.modifiers.contains(KeyModifiers::CONTROL);
+            match key.code {
+                KeyCode::Char('q') | KeyCode::Esc => quit = true,
+                _ if ctrl_c => quit = true,
+                KeyCode::Up | KeyCode::Char('k') => state.select_previous(),
+                KeyCode::Down | KeyCode::Char('j') => state.select_next(),
+                KeyCode::Char('f') => state.follow(),
+                _ => {}
+            }
+        }
+        if quit && report.is_none() && !state.cancelling {
+            state.cancelling = true;
+            token.cancel();
+        }
+        if quit {
+            if let Some(report) = report.take() {
+                return Ok(report);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::worknode::{Worknode, Worknodecore};
+
+    use ratatui::backend::TestBackend;
+    use ratatui::Terminal;
+
+    #[test]
+    fn monitor_events() {
+        let mut workflow = Workflow::new();
+        let start = workflow.add_node(Worknode::new(Worknodecore::Start));
+        let end = workflow.add_node(Worknode::new(Worknodecore::End));
+        workflow.add_edge(start, end).unwrap();
+        let mut monitor = RunMonitor::new(&workflow);
+        assert_eq!(monitor.get_nodes()[0].uid, start);
+        assert_eq!(monitor.get_nodes()[0].next, vec![end]);
+
+        monitor.update(&RunEvent::NodeStarted {
+            node: end,
+            kind: "end",
+        });
+        monitor.update(&RunEvent::NodeOutput {
+            node: end,
+            delta: "Hel".to_string(),
+        });
+        monitor.update(&RunEvent::NodeOutput {
+            node: end,
+            delta: "lo".to_string(),
+        });
+        assert_eq!(monitor.get_node(end).unwrap().state, NodeState::Running);
+        assert_eq!(monitor.shown().unwrap().output, "Hello");
+        monitor.update(&RunEvent::NodeFinished {
+            node: end,
+            output: "Hello!".to_string(),
+            usage: Some(DeepSeekUsage::new()),
+            duration: Duration::from_millis(1500),
+        });
+        monitor.update(&RunEvent::RunFinished {
+            status: RunStatus::Completed,
+            duration: Duration::from_secs(2),
+        });
+        assert_eq!(monitor.get_status(), Some(RunStatus::Completed));
+        assert_eq!(monitor.elapsed(), Duration::from_secs(2));
+        monitor.select_previous();
+        assert_eq!(monitor.shown().unwrap().uid, start);
+        monitor.follow();
+        assert_eq!(monitor.shown().unwrap().uid, end);
+
+        let mut terminal = Terminal::new(TestBackend::new(120, 12)).unwrap();
+        terminal.draw(|frame| draw(frame, &monitor)).unwrap();
+        let screen: String = terminal
+            .backend()
+            .buffer()
+            .content()
+            .iter()
+            .map(|cell| cell.symbol())
+            .collect();
+        assert!(screen.contains("completed"));
+        assert!(screen.contains("finished"));
+        assert!(screen.contains("Hello!"));
+        assert_eq!(tail_lines("abcdef\ngh", 4, 2), ["ef", "gh"]);
+    }
+}
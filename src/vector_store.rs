@@ -0,0 +1,256 @@
+//! # Vector Store
+//!
+//! This module stores documents with their embedding vectors, and finds the documents whose
+//! vectors are the most similar to a query vector, for the retrieval workflows.
+//!
+//! A `VectorStore` upserts documents by id, queries them by cosine similarity with an
+//! optional filter on their metadata, and deletes them. There are two stores, so a retrieval
+//! workflow doesn't depend on any external infrastructure:
+//! 1. [`memory::MemoryVectorStore`]: in memory, for tests and small or short-lived sets.
+//! 2. [`sqlite::SqliteVectorStore`]: in a SQLite file, with the distances computed by the
+//!    sqlite-vec extension, for the sets that outlive the process.
+//!
+//! All the vectors of a store have the same dimensions, those of the first vector upserted.
+
+pub mod memory;
+pub mod sqlite;
+
+use crate::error::vector_store_error::{VectorStoreError, VectorStoreErrorType, VectorStoreResult};
+
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+/// The struct of a document in a vector store.
+pub struct Document {
+    /// The id of the document, unique in the store.
+    pub id: String,
+    /// The text of the document.
+    pub text: String,
+    /// The embedding vector of the text.
+    pub vector: Vec<f32>,
+    /// The metadata of the document, like its source or its date.
+    #[serde(default, skip_serializing_if = "Map::is_empty")]
+    pub metadata: Map<String, Value>,
+}
+
+impl Document {
+    /// Create a new Document without metadata.
+    pub fn new(id: &str, text: &str, vector: Vec<f32>) -> Self {
+        Document {
+            id: id.to_string(),
+            text: text.to_string(),
+            vector,
+            metadata: Map::new(),
+        }
+    }
+    /// Set a value of the metadata as builder.
+    pub fn meta(mut self, key: &str, value: Value) -> Self {
+        self.metadata.insert(key.to_string(), value);
+        self
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+/// The struct of a document found by a query.
+pub struct Match {
+    /// The document.
+    pub document: Document,
+    /// The cosine similarity of the vector of the document to the query vector, from -1 to 1.
+    pub score: f32,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+/// The enum of a filter on the metadata of the documents.
+pub enum Filter {
+    /// The value of the key is the value.
+    Eq {
+        /// The key of the metadata.
+        key: String,
+        /// The value.
+        value: Value,
+    },
+    /// The value of the key is one of the values.
+    In {
+        /// The key of the metadata.
+        key: String,
+        /// The values.
+        values: Vec<Value>,
+    },
+    /// The metadata has the key.
+    Exists {
+        /// The key of the metadata.
+        key: String,
+    },
+    /// All the filters match.
+    And(Vec<Filter>),
+    /// Any of the filters matches.
+    Or(Vec<Filter>),
+    /// The filter doesn't match.
+    Not(Box<Filter>),
+}
+
+impl Filter {
+    /// Create a filter of a key equal to the value.
+    pub fn eq(key: &str, value: Value) -> Self {
+        Filter::Eq {
+            key: key.to_string(),
+            value,
+        }
+    }
+    /// Whether the metadata matches the filter.
+    pub fn matches(&self, metadata: &Map<String, Value>) -> bool {
+        match self {
+            Filter::Eq { key, value } => metadata.get(key) == Some(value),
+            Filter::In { key, values } => metadata.get(key).is_some_and(|v| values.contains(v)),
+            Filter::Exists { key } => metadata.contains_key(key),
+            Filter::And(filters) => filters.iter().all(|filter| filter.matches(metadata)),
+            Filter::Or(filters) => filters.iter().any(|filter| filter.matches(metadata)),
+            Filter::Not(filter) => !filter.matches(metadata),
+        }
+    }
+}
+
+/// The trait of a store of documents with embedding vectors.
+pub trait VectorStore: std::fmt::Debug + Send + Sync {
+    /// Insert the documents, or replace the documents with the same ids.
+    fn upsert(&self, documents: Vec<Document>) -> VectorStoreResult<()>;
+    /// Get the `top_k` documents most similar to the vector that match the filter, the most
+    /// similar first.
+    fn query(
+        &self,
+        vector: &[f32],
+        top_k: usize,
+        filter: Option<&Filter>,
+    ) -> VectorStoreResult<Vec<Match>>;
+    /// Get the documents that match the filter, in no particular order.
+    fn list(&self, filter: Option<&Filter>) -> VectorStoreResult<Vec<Document>>;
+    /// Get the document of the id.
+    fn get(&self, id: &str) -> VectorStoreResult<Option<Document>>;
+    /// Delete the documents of the ids, and get the number of documents deleted.
+    fn delete(&self, ids: &[String]) -> VectorStoreResult<usize>;
+    /// Get the number of documents in the store.
+    fn count(&self) -> VectorStoreResult<usize>;
+}
+
+/// Get the cosine similarity of two vectors of the same dimensions, 0 if one of them is zero.
+pub fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
+    let dot: f32 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+    let norm = |v: &[f32]| v.iter().map(|x| x * x).sum::<f32>().sqrt();
+    let norms = norm(a) * norm(b);
+    if norms == 0.0 {
+        0.0
+    } else {
+        dot / norms
+    }
+}
+
+/// Check that all the vectors have the dimensions, or the dimensions of the first vector if
+/// the store is empty, and get the dimensions.
+fn check_dimensions<'a>(
+    dimensions: Option<usize>,
+    vectors: impl IntoIterator<Item = &'a [f32]>,
+) -> VectorStoreResult<Option<usize>> {
+    let mut dimensions = dimensions;
+    for vector in vectors {
+        match dimensions {
+            Some(expected) if expected != vector.len() => {
+                return Err(VectorStoreError::new(
+                    VectorStoreErrorType::DimensionMismatch,
+                    format!(
+                        "The vector has {} dimensions, not the {} of the store.",
+                        vector.len(),
+                        expected
+                    ),
+                ))
+            }
+            Some(_) => {}
+            None => dimensions = Some(vector.len()),
+        }
+    }
+    Ok(dimensions)
+}
+
+#[cfg(test)]
+mod test {
+    use super::memory::MemoryVectorStore;
+    use super::sqlite::SqliteVectorStore;
+    use super::*;
+
+    use serde_json::json;
+    use uuid::Uuid;
+
+    /// Check the behavior that every store must have.
+    fn check_store(store: &dyn VectorStore) {
+        store
+            .upsert(vec![
+                Document::new("a", "cats", vec![1.0, 0.0]).meta("lang", json!("en")),
+                Document::new("b", "dogs", vec![0.8, 0.6]).meta("lang", json!("en")),
+                Document::new("c", "chats", vec![0.9, 0.1]).meta("lang", json!("fr")),
+            ])
+            .unwrap();
+        assert_eq!(store.count().unwrap(), 3);
+        let ids = |matches: Vec<Match>| -> Vec<String> {
+            matches.into_iter().map(|m| m.document.id).collect()
+        };
+        let matches = store.query(&[1.0, 0.0], 2, None).unwrap();
+        assert!((matches[0].score - 1.0).abs() < 1e-6);
+        assert_eq!(ids(matches), ["a", "c"]);
+        let english = Filter::eq("lang", json!("en"));
+        assert_eq!(
+            ids(store.query(&[1.0, 0.0], 5, Some(&english)).unwrap()),
+            ["a", "b"]
+        );
+        let not_english = Filter::Not(Box::new(english));
+        assert_eq!(
+            ids(store.query(&[0.0, 1.0], 5, Some(&not_english)).unwrap()),
+            ["c"]
+        );
+        let mut listed: Vec<String> = store
+            .list(Some(&Filter::eq("lang", json!("en"))))
+            .unwrap()
+            .into_iter()
+            .map(|document| document.id)
+            .collect();
+        listed.sort();
+        assert_eq!(listed, ["a", "b"]);
+        assert_eq!(store.list(None).unwrap().len(), 3);
+
+        // an upsert replaces the document with the same id
+        store
+            .upsert(vec![Document::new("a", "kittens", vec![0.0, 1.0])])
+            .unwrap();
+        assert_eq!(store.get("a").unwrap().unwrap().text, "kittens");
+        assert!(store.get("a").unwrap().unwrap().metadata.is_empty());
+        assert_eq!(ids(store.query(&[0.0, 1.0], 1, None).unwrap()), ["a"]);
+
+        assert!(matches!(
+            store
+                .upsert(vec![Document::new("d", "birds", vec![1.0])])
+                .unwrap_err()
+                .get_error_type(),
+            VectorStoreErrorType::DimensionMismatch
+        ));
+        assert!(store.query(&[1.0, 0.0, 0.0], 1, None).is_err());
+        assert_eq!(
+            store.delete(&["a".to_string(), "x".to_string()]).unwrap(),
+            1
+        );
+        assert_eq!(store.get("a").unwrap(), None);
+        assert_eq!(store.count().unwrap(), 2);
+    }
+
+    #[test]
+    fn vector_stores() {
+        check_store(&MemoryVectorStore::new());
+
+        let path = std::env::temp_dir().join(format!("aipilot-{}.db", Uuid::new_v4()));
+        check_store(&SqliteVectorStore::open(&path).unwrap());
+        // the documents outlive the store
+        let store = SqliteVectorStore::open(&path).unwrap();
+        assert_eq!(store.count().unwrap(), 2);
+        assert_eq!(store.get("c").unwrap().unwrap().metadata["lang"], "fr");
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,97 @@
+//! # Memory
+//!
+//! This module keeps the documents of a vector store in memory, and compares the query
+//! vector to every one of them.
+
+use super::{check_dimensions, cosine_similarity, Document, Filter, Match, VectorStore};
+use crate::error::vector_store_error::VectorStoreResult;
+
+use std::collections::HashMap;
+use std::sync::{PoisonError, RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+#[derive(Debug, Default)]
+/// The struct of the vector store in memory.
+pub struct MemoryVectorStore {
+    /// The documents by id.
+    documents: RwLock<HashMap<String, Document>>,
+}
+
+impl MemoryVectorStore {
+    /// Create a new empty MemoryVectorStore.
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Get the dimensions of the vectors in the store.
+    fn dimensions(documents: &HashMap<String, Document>) -> Option<usize> {
+        documents
+            .values()
+            .next()
+            .map(|document| document.vector.len())
+    }
+    /// Lock the documents for reading. A panic in another thread doesn't make the documents
+    /// invalid, so a poisoned lock is still used.
+    fn read(&self) -> RwLockReadGuard<'_, HashMap<String, Document>> {
+        self.documents
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+    /// Lock the documents for writing.
+    fn write(&self) -> RwLockWriteGuard<'_, HashMap<String, Document>> {
+        self.documents
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl VectorStore for MemoryVectorStore {
+    fn upsert(&self, documents: Vec<Document>) -> VectorStoreResult<()> {
+        let mut stored = self.write();
+        check_dimensions(
+            Self::dimensions(&stored),
+            documents.iter().map(|document| document.vector.as_slice()),
+        )?;
+        for document in documents {
+            stored.insert(document.id.clone(), document);
+        }
+        Ok(())
+    }
+    fn query(
+        &self,
+        vector: &[f32],
+        top_k: usize,
+        filter: Option<&Filter>,
+    ) -> VectorStoreResult<Vec<Match>> {
+        let stored = self.read();
+        check_dimensions(Self::dimensions(&stored), [vector])?;
+        let mut matches: Vec<Match> = stored
+            .values()
+            .filter(|document| filter.is_none_or(|filter| filter.matches(&document.metadata)))
+            .map(|document| Match {
+                score: cosine_similarity(vector, &document.vector),
+                document: document.clone(),
+            })
+            .collect();
+        matches.sort_by(|a, b| b.score.total_cmp(&a.score));
+        matches.truncate(top_k);
+        Ok(matches)
+    }
+    fn list(&self, filter: Option<&Filter>) -> VectorStoreResult<Vec<Document>> {
+        Ok(self
+            .read()
+            .values()
+            .filter(|document| filter.is_none_or(|filter| filter.matches(&document.metadata)))
+            .cloned()
+            .collect())
+    }
+    fn get(&self, id: &str) -> VectorStoreResult<Option<Document>> {
+        let stored = self.read();
+        Ok(stored.get(id).cloned())
+    }
+    fn delete(&self, ids: &[String]) -> VectorStoreResult<usize> {
+        let mut stored = self.write();
+        Ok(ids.iter().filter(|id| stored.remove(*id).is_some()).count())
+    }
+    fn count(&self) -> VectorStoreResult<usize> {
+        Ok(self.read().len())
+    }
+}
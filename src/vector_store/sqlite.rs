@@ -0,0 +1,239 @@
+//! # SQLite
+//!
+//! This module keeps the documents of a vector store in a SQLite file, so they outlive the
+//! process.
+//!
+//! The vectors are stored as blobs of little endian `f32`, the format of the sqlite-vec
+//! extension, which computes the cosine distances in the query. The metadata is stored as
+//! json, and the filter is checked on the documents in the order of their distances, until
+//! `top_k` of them match.
+
+use super::{check_dimensions, Document, Filter, Match, VectorStore};
+use crate::error::vector_store_error::{VectorStoreError, VectorStoreErrorType, VectorStoreResult};
+
+use rusqlite::{params, Connection, OptionalExtension};
+use serde_json::{Map, Value};
+
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard, Once, PoisonError};
+
+/// Register the sqlite-vec extension in every connection opened after it.
+static REGISTER_EXTENSION: Once = Once::new();
+
+#[derive(Debug)]
+/// The struct of the vector store in a SQLite file.
+pub struct SqliteVectorStore {
+    /// The connection to the database.
+    connection: Mutex<Connection>,
+    /// The path of the database file.
+    path: PathBuf,
+}
+
+impl SqliteVectorStore {
+    /// Open the store in the file, and create the file if it doesn't exist.
+    pub fn open<P: AsRef<Path>>(path: P) -> VectorStoreResult<Self> {
+        REGISTER_EXTENSION.call_once(|| {
+            // the entry point of sqlite-vec has the signature sqlite expects of an extension,
+            // it is only declared without arguments by the crate
+            unsafe {
+                rusqlite::ffi::sqlite3_auto_extension(Some(std::mem::transmute::<
+                    *const (),
+                    unsafe extern "C" fn(
+                        *mut rusqlite::ffi::sqlite3,
+                        *mut *mut std::os::raw::c_char,
+                        *const rusqlite::ffi::sqlite3_api_routines,
+                    ) -> std::os::raw::c_int,
+                >(
+                    sqlite_vec::sqlite3_vec_init as *const (),
+                )));
+            }
+        });
+        let path = path.as_ref().to_path_buf();
+        let connection = Connection::open(&path).map_err(|e| {
+            storage_error(format!(
+                "Failed to open the store {}. {}",
+                path.display(),
+                e
+            ))
+        })?;
+        connection
+            .execute_batch(
+                "CREATE TABLE IF NOT EXISTS documents (
+                    id TEXT PRIMARY KEY,
+                    text TEXT NOT NULL,
+                    metadata TEXT NOT NULL,
+                    embedding BLOB NOT NULL
+                )",
+            )
+            .map_err(|e| storage_error(format!("Failed to create the table. {}", e)))?;
+        Ok(SqliteVectorStore {
+            connection: Mutex::new(connection),
+            path,
+        })
+    }
+    /// Get the path of the database file.
+    pub fn get_path(&self) -> &Path {
+        &self.path
+    }
+    /// Lock the connection. A panic in another thread rolls its transaction back, so a
+    /// poisoned lock is still used.
+    fn lock(&self) -> MutexGuard<'_, Connection> {
+        self.connection
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+    /// Get the dimensions of the vectors in the store.
+    fn dimensions(connection: &Connection) -> VectorStoreResult<Option<usize>> {
+        connection
+            .query_row(
+                "SELECT vec_length(embedding) FROM documents LIMIT 1",
+                [],
+                |row| row.get::<_, i64>(0),
+            )
+            .optional()
+            .map(|dimensions| dimensions.map(|dimensions| dimensions as usize))
+            .map_err(|e| storage_error(format!("Failed to read the store. {}", e)))
+    }
+}
+
+impl VectorStore for SqliteVectorStore {
+    fn upsert(&self, documents: Vec<Document>) -> VectorStoreResult<()> {
+        let mut connection = self.lock();
+        check_dimensions(
+            Self::dimensions(&connection)?,
+            documents.iter().map(|document| document.vector.as_slice()),
+        )?;
+        let write_error =
+            |e: rusqlite::Error| storage_error(format!("Failed to write the store. {}", e));
+        let transaction = connection.transaction().map_err(write_error)?;
+        for document in &documents {
+            transaction
+                .execute(
+                    "INSERT OR REPLACE INTO documents (id, text, metadata, embedding)
+                    VALUES (?1, ?2, ?3, ?4)",
+                    params![
+                        document.id,
+                        document.text,
+                        Value::Object(document.metadata.clone()).to_string(),
+                        to_blob(&document.vector),
+                    ],
+                )
+                .map_err(write_error)?;
+        }
+        transaction.commit().map_err(write_error)
+    }
+    fn query(
+        &self,
+        vector: &[f32],
+        top_k: usize,
+        filter: Option<&Filter>,
+    ) -> VectorStoreResult<Vec<Match>> {
+        let connection = self.lock();
+        check_dimensions(Self::dimensions(&connection)?, [vector])?;
+        let read_error =
+            |e: rusqlite::Error| storage_error(format!("Failed to read the store. {}", e));
+        let mut statement = connection
+            .prepare(
+                "SELECT id, text, metadata, embedding, vec_distance_cosine(embedding, ?1) AS distance
+                FROM documents ORDER BY distance",
+            )
+            .map_err(read_error)?;
+        let mut rows = statement.query([to_blob(vector)]).map_err(read_error)?;
+        let mut matches = Vec::new();
+        while matches.len() < top_k {
+            let Some(row) = rows.next().map_err(read_error)? else {
+                break;
+            };
+            let document = to_document(row)?;
+            if filter.is_none_or(|filter| filter.matches(&document.metadata)) {
+                let distance: f64 = row.get(4).map_err(read_error)?;
+                matches.push(Match {
+                    document,
+                    score: (1.0 - distance) as f32,
+                });
+            }
+        }
+        Ok(matches)
+    }
+    fn list(&self, filter: Option<&Filter>) -> VectorStoreResult<Vec<Document>> {
+        let connection = self.lock();
+        let read_error =
+            |e: rusqlite::Error| storage_error(format!("Failed to read the store. {}", e));
+        let mut statement = connection
+            .prepare("SELECT id, text, metadata, embedding FROM documents")
+            .map_err(read_error)?;
+        let mut rows = statement.query([]).map_err(read_error)?;
+        let mut documents = Vec::new();
+        while let Some(row) = rows.next().map_err(read_error)? {
+            let document = to_document(row)?;
+            if filter.is_none_or(|filter| filter.matches(&document.metadata)) {
+                documents.push(document);
+            }
+        }
+        Ok(documents)
+    }
+    fn get(&self, id: &str) -> VectorStoreResult<Option<Document>> {
+        let connection = self.lock();
+        let read_error =
+            |e: rusqlite::Error| storage_error(format!("Failed to read the store. {}", e));
+        let mut statement = connection
+            .prepare("SELECT id, text, metadata, embedding FROM documents WHERE id = ?1")
+            .map_err(read_error)?;
+        let mut rows = statement.query([id]).map_err(read_error)?;
+        match rows.next().map_err(read_error)? {
+            Some(row) => Ok(Some(to_document(row)?)),
+            None => Ok(None),
+        }
+    }
+    fn delete(&self, ids: &[String]) -> VectorStoreResult<usize> {
+        let mut connection = self.lock();
+        let write_error =
+            |e: rusqlite::Error| storage_error(format!("Failed to write the store. {}", e));
+        let transaction = connection.transaction().map_err(write_error)?;
+        let mut deleted = 0;
+        for id in ids {
+            deleted += transaction
+                .execute("DELETE FROM documents WHERE id = ?1", [id])
+                .map_err(write_error)?;
+        }
+        transaction.commit().map_err(write_error)?;
+        Ok(deleted)
+    }
+    fn count(&self) -> VectorStoreResult<usize> {
+        self.lock()
+            .query_row("SELECT COUNT(*) FROM documents", [], |row| {
+                row.get::<_, i64>(0)
+            })
+            .map(|count| count as usize)
+            .map_err(|e| storage_error(format!("Failed to read the store. {}", e)))
+    }
+}
+
+/// Get the blob of a vector.
+fn to_blob(vector: &[f32]) -> Vec<u8> {
+    vector.iter().flat_map(|x| x.to_le_bytes()).collect()
+}
+
+/// Get the document of a row of `id, text, metadata, embedding`.
+fn to_document(row: &rusqlite::Row) -> VectorStoreResult<Document> {
+    let read_error = |e: rusqlite::Error| storage_error(format!("Failed to read the store. {}", e));
+    let metadata: String = row.get(2).map_err(read_error)?;
+    let blob: Vec<u8> = row.get(3).map_err(read_error)?;
+    Ok(Document {
+        id: row.get(0).map_err(read_error)?,
+        text: row.get(1).map_err(read_error)?,
+        vector: blob
+            .chunks_exact(4)
+            // a chunk of 4 bytes is always an array of 4 bytes
+            .map(|bytes| f32::from_le_bytes(bytes.try_into().unwrap()))
+            .collect(),
+        metadata: serde_json::from_str::<Map<String, Value>>(&metadata).map_err(|e| {
+            storage_error(format!("The metadata of a document is not valid. {}", e))
+        })?,
+    })
+}
+
+/// Create a VectorStoreError of the storage.
+fn storage_error(message: String) -> VectorStoreError {
+    VectorStoreError::new(VectorStoreErrorType::StorageError, message)
+}
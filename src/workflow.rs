@@ -0,0 +1,2222 @@
+//! # Workflow
+//!
+//! This module is for the workflow graph, which connects the worknodes with edges and runs
+//! them from the start node to the end node.
+//!
+//! ## Graph
+//!
+//! A workflow has exactly one start node and one end node. An edge goes from one node to
+//! another, and the output of the source node becomes the input of the target node.
+//!
+//! An edge can be wired into a named input port of the target node (see
+//! [`crate::worknode::ai_node::port`]). When a node has more than one incoming edge, or an edge
+//! with a port, its input is a json object with one field per port, and the edges without a
+//! port go into the `input` port. Several outputs wired into the same port are joined with a
+//! new line, in the order of the edges.
+//!
+//! A workflow can be saved to and loaded from a YAML or JSON file, see [`definition`], and
+//! reloaded when its file changes, see [`reload`].
+//!
+//! `validate` checks the whole graph before a run and reports every problem it finds, and
+//! [`render`] draws it as a Graphviz DOT or Mermaid diagram. `preflight` checks that the
+//! providers and the stores of the nodes are reachable, before any paid request is sent (see
+//! [`preflight`]).
+//!
+//! ## Execution
+//!
+//! `run` executes the nodes reachable from the start node, and every node runs after all of
+//! its predecessors. A node with several outgoing edges fans out: its successors run
+//! concurrently as tokio tasks, at most `max_parallelism` at a time. The nodes of a provider
+//! can be limited further with `provider_limit`, so a wide fan-out doesn't send more requests
+//! to one AI service than it accepts at once. A join node fans in: it
+//! gets the outputs of all its branches and merges them (see [`crate::worknode::join`]). A
+//! reduce node fans in the same way, with more ways to merge (see
+//! [`crate::worknode::reduce`]). The output of the end node is the output of the workflow.
+//!
+//! An approval node pauses its branch until a human decides (see
+//! [`crate::worknode::approval`]). When it is rejected, the rejected edges of the node are
+//! followed instead of the normal ones. A router node asks an AI service to classify its
+//! input, and only its route edges of the chosen label are followed (see
+//! [`crate::worknode::router`]). In the same way, a node with on_error edges doesn't
+//! fail the run: its on_error edges carry a [`NodeFailure`] to a fallback node, like a
+//! cheaper model or a canned response, and its normal edges are not followed. A node that
+//! panics fails with a `NodePanicked` error instead of taking the run down. A node is
+//! skipped when none of its incoming edges is
+//! followed, and so are the nodes after it that aren't reached another way.
+//!
+//! The progress of a run is emitted as [`event::RunEvent`]s to the event channel of the
+//! workflow, if there is one. With `stream_output`, the AI and agent nodes also emit the
+//! pieces of their answers while they are generated. With [`Metrics`], the finished nodes are counted for Prometheus
+//! (see [`metrics`]), and with an [`ExecutionLog`], every node event and tool call is appended
+//! to a JSON Lines file (see [`execution_log`]).
+//!
+//! `run_detached` runs the workflow in the background, and its handle can cancel the run.
+//! `run_report` gives a [`run::RunReport`] instead of only the output: the trace of every
+//! node with its input, output, attempts, duration, token usage and cost, which can be saved
+//! as json or rendered as Markdown (see [`run`]).
+//!
+//! With a [`cost::Budget`], a run may spend at most so many tokens or so much money on the
+//! nodes that call an AI service. Such a node doesn't start when the budget is already spent,
+//! and fails when its usage goes over the budget. An agent node also checks the budget before
+//! each of its steps, so a runaway agent loop stops early. The usage of a node is what all
+//! the requests of its execution used, with its tool calls, repairs and retries. Like
+//! any failure, it is routed to the on_error edges of the node if there are some, and
+//! otherwise fails the run.
+//!
+//! The [`AlertSink`]s of the workflow are alerted when a run fails or spends its budget, so
+//! an unattended run doesn't fail silently (see [`alert`]).
+//!
+//! With a deadline, a run may take at most that much wall-clock time. The time left is the
+//! timeout of every node that starts, so a node that is still running at the deadline is
+//! stopped and fails, and no node starts after the deadline.
+//!
+//! With a checkpoint path, the state of the run is saved after every completed node, and
+//! `resume` continues an interrupted run from it (see [`checkpoint`]).
+//!
+//! With a [`Recording`], the requests of the AI and agent nodes are recorded to a file, or
+//! replayed from it without calling the api, so the logic of a workflow can be tested offline
+//! (see [`crate::worknode::ai_node::recording`]).
+//!
+//! Every [`NodeHook`] of the workflow is called before and after every node, and when it
+//! fails, so a program can log, clean the inputs or rework the outputs of the nodes (see
+//! [`hook`]).
+//!
+//! The nodes of a run share a [`context::RunContext`]. A node with a context key stores its
+//! output in the context, and the templates of the AI nodes read the context values.
+//!
+//! ## Tracing
+//!
+//! A run is traced with [`tracing`] spans, so any subscriber can collect structured logs of
+//! it: a `workflow.run` span holds a `worknode` span for every node with its uid, kind and
+//! provider, which holds a `worknode.attempt` span for every attempt. The AI nodes add an
+//! `ai_node.execute` span, and the DeepSeek client a `deepseek.request` span with the model
+//! and the token counts of the answer.
+
+pub mod alert;
+pub mod checkpoint;
+pub mod context;
+pub mod cost;
+pub mod definition;
+pub mod event;
+pub mod execution_log;
+pub mod hook;
+pub mod metrics;
+pub mod preflight;
+pub mod reload;
+pub mod render;
+pub mod run;
+pub mod validate;
+
+use crate::config::ConfigOrigin;
+use crate::error::ai_node_error::AINodeErrorType;
+use crate::error::graph_error::{GraphError, GraphErrorType};
+use crate::error::{PilotError, PilotErrorType, PilotResult};
+use crate::worknode::ai_node::deepseek::{DeepSeekUsage, RequestCount};
+use crate::worknode::ai_node::recording::Recording;
+use crate::worknode::retry::ErrorClass;
+use crate::worknode::{Worknode, Worknodecore};
+use alert::{Alert, AlertKind, AlertSink};
+use checkpoint::Checkpoint;
+use context::RunContext;
+use cost::{Budget, BudgetGuard, CostEntry};
+use event::RunEvent;
+use execution_log::{ExecutionLog, LogEvent};
+use hook::NodeHook;
+use metrics::Metrics;
+use run::{NodeReport, NodeStatus, Pricing, RunReport, RunStatus};
+
+use chrono::Utc;
+use json::JsonValue;
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc::UnboundedSender;
+use tokio::task::JoinSet;
+use tokio_util::sync::CancellationToken;
+use tracing::Instrument;
+use uuid::Uuid;
+
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::future::Future;
+use std::panic::AssertUnwindSafe;
+use std::path::PathBuf;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+
+/// The port that the edges without a port go into.
+const DEFAULT_PORT: &str = "input";
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+/// The enum of when an edge is followed.
+pub enum EdgeKind {
+    /// When the source node succeeds, or is approved for an approval node.
+    #[default]
+    Normal,
+    /// When the source approval node is rejected.
+    Rejected,
+    /// When the source node fails after all its retries. The target node gets a
+    /// [`NodeFailure`] as json instead of the output.
+    OnError,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+/// The struct of the error payload that the on_error edges of a failed node carry.
+pub struct NodeFailure {
+    /// The uid of the failed node.
+    pub node: Uuid,
+    /// The type of the failed node, like `ai_node`.
+    pub kind: String,
+    /// Where the error happened, `ai_node`, `graph`, `local_node`, `wasm_node`,
+    /// `script_node`, `file_node`, `user_node`, `transform_node`, `map_node`, `reduce_node`,
+    /// `delay_node`, `assert_node`, `notify_node`, `chunker_node`, `load_node`,
+    /// `retrieve_node`, `ingest_node`, `planner_node`, `config`, `storage` or `timeout`.
+    pub source: String,
+    /// The summary of the error.
+    pub message: String,
+    /// The details of the error.
+    pub detail: String,
+    /// The class of the error, like `rate_limit` or `auth`.
+    #[serde(default)]
+    pub class: ErrorClass,
+    /// Whether the error is transient, so the fallback may try again later.
+    #[serde(default)]
+    pub retryable: bool,
+    /// What was being done when the error happened, innermost first.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub context: Vec<String>,
+}
+
+impl NodeFailure {
+    /// Create the NodeFailure of the error of a node.
+    pub fn new(node: Uuid, kind: &str, error: &PilotError) -> Self {
+        let (source, detail) = match error.get_error_type() {
+            PilotErrorType::AINodeErr(e) => ("ai_node", e.to_string()),
+            PilotErrorType::GraphErr(e) => ("graph", e.to_string()),
+            PilotErrorType::LocalNodeErr(e) => ("local_node", e.to_string()),
+            PilotErrorType::WasmNodeErr(e) => ("wasm_node", e.to_string()),
+            PilotErrorType::ScriptNodeErr(e) => ("script_node", e.to_string()),
+            PilotErrorType::FileNodeErr(e) => ("file_node", e.to_string()),
+            PilotErrorType::UserNodeErr(e) => ("user_node", e.to_string()),
+            PilotErrorType::TransformNodeErr(e) => ("transform_node", e.to_string()),
+            PilotErrorType::MapNodeErr(e) => ("map_node", e.to_string()),
+            PilotErrorType::ReduceNodeErr(e) => ("reduce_node", e.to_string()),
+            PilotErrorType::DelayNodeErr(e) => ("delay_node", e.to_string()),
+            PilotErrorType::AssertNodeErr(e) => ("assert_node", e.to_string()),
+            PilotErrorType::NotifyNodeErr(e) => ("notify_node", e.to_string()),
+            PilotErrorType::ChunkerNodeErr(e) => ("chunker_node", e.to_string()),
+            PilotErrorType::LoadNodeErr(e) => ("load_node", e.to_string()),
+            PilotErrorType::RetrieveNodeErr(e) => ("retrieve_node", e.to_string()),
+            PilotErrorType::IngestNodeErr(e) => ("ingest_node", e.to_string()),
+            PilotErrorType::PlannerNodeErr(e) => ("planner_node", e.to_string()),
+            PilotErrorType::ConfigErr(e) => ("config", e.to_string()),
+            PilotErrorType::StorageErr(e) => ("storage", e.to_string()),
+            PilotErrorType::TimeoutErr(e) => ("timeout", e.to_string()),
+        };
+        NodeFailure {
+            node,
+            kind: kind.to_string(),
+            source: source.to_string(),
+            message: error.get_message().to_string(),
+            detail,
+            class: error.class(),
+            retryable: error.is_retryable(),
+            context: error.get_context().to_vec(),
+        }
+    }
+    /// Read the NodeFailure from the input of a node after an on_error edge.
+    pub fn from_json(text: &str) -> Option<Self> {
+        serde_json::from_str(text).ok()
+    }
+    /// Write the NodeFailure as json.
+    pub fn to_json(&self) -> String {
+        // the fields are all strings, uids and plain enums, so it can't fail
+        serde_json::to_string(self).unwrap_or_default()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// The struct of an edge of the workflow graph.
+pub struct Edge {
+    /// The uid of the source node.
+    from: Uuid,
+    /// The uid of the target node.
+    to: Uuid,
+    /// The input port of the target node that the output goes into.
+    port: Option<String>,
+    /// When the edge is followed.
+    kind: EdgeKind,
+    /// The label of the source router node that the edge is followed for.
+    route: Option<String>,
+}
+
+impl Edge {
+    /// Get the uid of the source node.
+    pub fn get_from(&self) -> Uuid {
+        self.from
+    }
+    /// Get the uid of the target node.
+    pub fn get_to(&self) -> Uuid {
+        self.to
+    }
+    /// Get the input port of the target node.
+    pub fn get_port(&self) -> Option<&str> {
+        self.port.as_deref()
+    }
+    /// Get when the edge is followed.
+    pub fn get_kind(&self) -> EdgeKind {
+        self.kind
+    }
+    /// Get the label of the source router node that the edge is followed for.
+    pub fn get_route(&self) -> Option<&str> {
+        self.route.as_deref()
+    }
+}
+
+#[derive(Debug, Clone)]
+/// The struct of the workflow graph.
+pub struct Workflow {
+    /// The nodes of the graph, in the order they are added.
+    nodes: Vec<Worknode>,
+    /// The edges of the graph, in the order they are added.
+    edges: Vec<Edge>,
+    /// The max number of nodes running at the same time.
+    max_parallelism: usize,
+    /// The max number of nodes of each provider running at the same time.
+    provider_limits: HashMap<String, usize>,
+    /// The file that the checkpoint of a run is saved to.
+    checkpoint_path: Option<PathBuf>,
+    /// The wall-clock time that a run may take.
+    deadline: Option<Duration>,
+    /// The prices of the providers, to get the cost of the runs.
+    prices: HashMap<String, Pricing>,
+    /// The recording that the requests of the nodes are recorded to or replayed from.
+    recording: Option<Recording>,
+    /// The channel that the events of the runs are emitted to.
+    events: Option<UnboundedSender<RunEvent>>,
+    /// Whether the AI and agent nodes stream their answers to the event channel.
+    stream_output: bool,
+    /// The metrics that the finished nodes are recorded to.
+    metrics: Option<Metrics>,
+    /// The log that the events of the runs are appended to.
+    execution_log: Option<ExecutionLog>,
+    /// The most that a run may spend on the AI services.
+    budget: Option<Budget>,
+    /// The hooks called around the execution of the nodes, in order.
+    hooks: Vec<Arc<dyn NodeHook>>,
+    /// The sinks that the alerts of the runs are sent to.
+    alert_sinks: Vec<AlertSink>,
+    /// Where the settings of the workflow were read from, recorded in the reports.
+    config_origin: Option<ConfigOrigin>,
+}
+
+impl Default for Workflow {
+    fn default() -> Self {
+        Workflow {
+            nodes: Vec::new(),
+            edges: Vec::new(),
+            max_parallelism: Self::default_max_parallelism(),
+            provider_limits: HashMap::new(),
+            checkpoint_path: None,
+            deadline: None,
+            prices: HashMap::new(),
+            recording: None,
+            events: None,
+            stream_output: false,
+            metrics: None,
+            execution_log: None,
+            budget: None,
+            hooks: Vec::new(),
+            alert_sinks: Vec::new(),
+            config_origin: None,
+        }
+    }
+}
+
+impl Workflow {
+    /// Create a new empty Workflow.
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Add a node to the workflow and get its uid.
+    pub fn add_node(&mut self, node: Worknode) -> Uuid {
+        let uid = node.get_uid();
+        self.nodes.push(node);
+        uid
+    }
+    /// Add an edge that sends the output of `from` to the input of `to`.
+    pub fn add_edge(&mut self, from: Uuid, to: Uuid) -> PilotResult<()> {
+        self.push_edge(from, to, None, EdgeKind::Normal, None)
+    }
+    /// Add an edge that sends the output of `from` to the named input port of `to`.
+    pub fn add_port_edge(&mut self, from: Uuid, to: Uuid, port: &str) -> PilotResult<()> {
+        self.push_edge(from, to, Some(port.to_string()), EdgeKind::Normal, None)
+    }
+    /// Add an edge that sends the reason of the rejection of the approval node `from` to the
+    /// input of `to`. It is followed only when the approval is rejected.
+    pub fn add_rejected_edge(&mut self, from: Uuid, to: Uuid) -> PilotResult<()> {
+        self.push_edge(from, to, None, EdgeKind::Rejected, None)
+    }
+    /// Add an edge that is followed when the source node fails, so the target node can
+    /// handle the error instead of the run failing. The target node gets a [`NodeFailure`].
+    pub fn add_error_edge(&mut self, from: Uuid, to: Uuid) -> PilotResult<()> {
+        self.push_edge(from, to, None, EdgeKind::OnError, None)
+    }
+    /// Add an edge that sends the input of the router node `from` to the input of `to`. It is
+    /// followed only when the router chooses the label.
+    pub fn add_route_edge(&mut self, from: Uuid, to: Uuid, label: &str) -> PilotResult<()> {
+        self.push_edge(from, to, None, EdgeKind::Normal, Some(label.to_string()))
+    }
+    /// Add an edge after checking that both nodes are in the workflow.
+    fn push_edge(
+        &mut self,
+        from: Uuid,
+        to: Uuid,
+        port: Option<String>,
+        kind: EdgeKind,
+        route: Option<String>,
+    ) -> PilotResult<()> {
+        for uid in [from, to] {
+            if self.get_node(uid).is_none() {
+                return Err(graph_error(
+                    GraphErrorType::NodeNotFound,
+                    format!("The node {} is not in the workflow.", uid),
+                ));
+            }
+        }
+        self.edges.push(Edge {
+            from,
+            to,
+            port,
+            kind,
+            route,
+        });
+        Ok(())
+    }
+    /// Get a node by its uid.
+    pub fn get_node(&self, uid: Uuid) -> Option<&Worknode> {
+        self.nodes.iter().find(|node| node.get_uid() == uid)
+    }
+    /// Get a mutable node by its uid.
+    pub fn get_node_mut(&mut self, uid: Uuid) -> Option<&mut Worknode> {
+        self.nodes.iter_mut().find(|node| node.get_uid() == uid)
+    }
+    /// Get the nodes of the workflow.
+    pub fn get_nodes(&self) -> &Vec<Worknode> {
+        &self.nodes
+    }
+    /// Get the edges of the workflow.
+    pub fn get_edges(&self) -> &Vec<Edge> {
+        &self.edges
+    }
+    /// Get the uids of the start node and the end node. There must be exactly one of each.
+    pub fn check_start_end(&self) -> PilotResult<(Uuid, Uuid)> {
+        let find = |is_kind: fn(&Worknodecore) -> bool, kind: &str| {
+            let found: Vec<Uuid> = self
+                .nodes
+                .iter()
+                .filter(|node| is_kind(node.get_node()))
+                .map(|node| node.get_uid())
+                .collect();
+            match found.as_slice() {
+                [uid] => Ok(*uid),
+                _ => Err(graph_error(
+                    GraphErrorType::StartEndError,
+                    format!(
+                        "The workflow should have exactly one {} node, but it has {}.",
+                        kind,
+                        found.len()
+                    ),
+                )),
+            }
+        };
+        let start = find(|node| matches!(node, Worknodecore::Start), "start")?;
+        let end = find(|node| matches!(node, Worknodecore::End), "end")?;
+        Ok((start, end))
+    }
+    /// Get the nodes reachable from the start node in topological order.
+    pub(crate) fn execution_order(&self, start: Uuid) -> PilotResult<Vec<Uuid>> {
+        // find the reachable nodes first, so the unreachable ones don't block the others
+        let mut reachable = vec![start];
+        let mut queue = VecDeque::from([start]);
+        while let Some(uid) = queue.pop_front() {
+            for edge in self.edges.iter().filter(|edge| edge.from == uid) {
+                if !reachable.contains(&edge.to) {
+                    reachable.push(edge.to);
+                    queue.push_back(edge.to);
+                }
+            }
+        }
+        let mut in_degree: HashMap<Uuid, usize> = reachable.iter().map(|&uid| (uid, 0)).collect();
+        for edge in &self.edges {
+            if reachable.contains(&edge.from) {
+                *in_degree.get_mut(&edge.to).unwrap() += 1;
+            }
+        }
+        let mut order = Vec::new();
+        // an edge back into the start node always closes a cycle
+        let mut ready = if in_degree[&start] == 0 {
+            VecDeque::from([start])
+        } else {
+            VecDeque::new()
+        };
+        while let Some(uid) = ready.pop_front() {
+            order.push(uid);
+            for edge in self.edges.iter().filter(|edge| edge.from == uid) {
+                let degree = in_degree.get_mut(&edge.to).unwrap();
+                *degree -= 1;
+                if *degree == 0 {
+                    ready.push_back(edge.to);
+                }
+            }
+        }
+        if order.len() < reachable.len() {
+            return Err(graph_error(
+                GraphErrorType::CycleError,
+                "The workflow has a cycle.".to_string(),
+            ));
+        }
+        Ok(order)
+    }
+    /// Build the input of a node from the outputs of its predecessors, through the edges that
+    /// are followed.
+    fn gather_input(&self, uid: Uuid, state: &Checkpoint) -> String {
+        let outputs = &state.outputs;
+        let incoming: Vec<&Edge> = self
+            .edges
+            .iter()
+            .filter(|edge| edge.to == uid && state.is_followed(edge))
+            .collect();
+        if let [edge] = incoming.as_slice() {
+            if edge.port.is_none() {
+                return outputs[&edge.from].clone();
+            }
+        }
+        let mut ports = JsonValue::new_object();
+        for edge in incoming {
+            let port = edge.port.as_deref().unwrap_or(DEFAULT_PORT);
+            let output = outputs[&edge.from].as_str();
+            ports[port] = match ports[port].as_str() {
+                Some(joined) => format!("{}\n{}", joined, output).into(),
+                None => output.into(),
+            };
+        }
+        ports.dump()
+    }
+    /// Build the input of a join or reduce node, which is a json array of the outputs of its
+    /// predecessors in the order of the edges.
+    fn gather_branches(&self, uid: Uuid, state: &Checkpoint) -> String {
+        JsonValue::Array(
+            self.edges
+                .iter()
+                .filter(|edge| edge.to == uid && state.is_followed(edge))
+                .filter_map(|edge| state.outputs.get(&edge.from))
+                .map(|output| output.as_str().into())
+                .collect(),
+        )
+        .dump()
+    }
+    /// Run the workflow with the input and get the output of the end node.
+    ///
+    /// Every node whose predecessors are all finished is spawned as a tokio task, so the
+    /// branches of a fan-out run concurrently, at most `max_parallelism` nodes at a time and
+    /// at most the limit of its provider for the AI and agent nodes.
+    pub async fn run(&mut self, input: String) -> PilotResult<String> {
+        self.run_with_context(input, &RunContext::new()).await
+    }
+    /// Run the workflow in the run context, which the nodes read and write, and get the
+    /// output of the end node. The context keeps the values after the run.
+    pub async fn run_with_context(
+        &mut self,
+        input: String,
+        context: &RunContext,
+    ) -> PilotResult<String> {
+        self.run_with_token(input, context, &CancellationToken::new())
+            .await
+    }
+    /// Run the workflow in the run context until the end node is reached or the token is
+    /// cancelled.
+    pub async fn run_with_token(
+        &mut self,
+        input: String,
+        context: &RunContext,
+        token: &CancellationToken,
+    ) -> PilotResult<String> {
+        self.run_report(input, context, token).await.into_result()
+    }
+    /// Run the workflow like `run_with_token`, and get the report of the run with the trace
+    /// of every node.
+    pub async fn run_report(
+        &mut self,
+        input: String,
+        context: &RunContext,
+        token: &CancellationToken,
+    ) -> RunReport {
+        let state = Checkpoint {
+            input,
+            ..Checkpoint::default()
+        };
+        self.run_from(state, context, token).await
+    }
+    /// Continue a run from its checkpoint. The completed nodes are not run again, and the
+    /// histories and the run context are restored as they were.
+    pub async fn resume(&mut self, checkpoint: Checkpoint) -> PilotResult<String> {
+        self.resume_with_context(checkpoint, &RunContext::new())
+            .await
+    }
+    /// Continue a run from its checkpoint in the run context. The values of the checkpoint
+    /// are put in the context first.
+    pub async fn resume_with_context(
+        &mut self,
+        mut checkpoint: Checkpoint,
+        context: &RunContext,
+    ) -> PilotResult<String> {
+        for (key, value) in std::mem::take(&mut checkpoint.context) {
+            context.set_value(&key, value);
+        }
+        for (uid, history) in std::mem::take(&mut checkpoint.histories) {
+            if let Some(node) = self.get_node_mut(uid) {
+                node.get_node_mut().set_history(history);
+            }
+        }
+        self.run_from(checkpoint, context, &CancellationToken::new())
+            .await
+            .into_result()
+    }
+    /// Run the nodes that are not completed in the state, and get the report of the run.
+    async fn run_from(
+        &mut self,
+        mut state: Checkpoint,
+        context: &RunContext,
+        token: &CancellationToken,
+    ) -> RunReport {
+        if state.run.is_nil() {
+            state.run = Uuid::new_v4();
+        }
+        let run = state.run;
+        let started = Instant::now();
+        let mut trace = Vec::new();
+        let span = tracing::info_span!("workflow.run", nodes = self.nodes.len());
+        let result = self
+            .run_graph(state, context, token, &mut trace)
+            .instrument(span)
+            .await;
+        let duration = started.elapsed();
+        self.log(
+            run,
+            LogEvent::RunFinished {
+                status: RunStatus::of(&result),
+                duration_ms: execution_log::millis(duration),
+            },
+        );
+        self.emit(RunEvent::RunFinished {
+            status: RunStatus::of(&result),
+            duration,
+        });
+        if let (RunStatus::Failed, Err(e)) = (RunStatus::of(&result), &result) {
+            self.alert(Alert::new(AlertKind::RunFailed, run, None, e.to_string()))
+                .await;
+        }
+        RunReport::new(result, duration, trace, self.config_origin.clone())
+    }
+    /// Check the graph, and run the nodes that are not completed in the state.
+    async fn run_graph(
+        &mut self,
+        state: Checkpoint,
+        context: &RunContext,
+        token: &CancellationToken,
+        trace: &mut Vec<NodeReport>,
+    ) -> PilotResult<String> {
+        let (start, end) = self.check_start_end()?;
+        let order = self.execution_order(start)?;
+        if !order.contains(&end) {
+            return Err(graph_error(
+                GraphErrorType::UnreachableEnd,
+                "The end node can't be reached from the start node.".to_string(),
+            ));
+        }
+        if let Some(recording) = &self.recording {
+            for node in &mut self.nodes {
+                node.get_node_mut().set_recording(Some(recording.clone()));
+            }
+        }
+        let node_events = self.events.clone().filter(|_| self.stream_output);
+        for node in &mut self.nodes {
+            node.get_node_mut().set_events(node_events.clone());
+        }
+        // the nodes are moved into the tasks while they run, and put back at the end
+        let uids: Vec<Uuid> = self.nodes.iter().map(|node| node.get_uid()).collect();
+        let mut nodes: HashMap<Uuid, Worknode> = std::mem::take(&mut self.nodes)
+            .into_iter()
+            .map(|node| (node.get_uid(), node))
+            .collect();
+        let result = self
+            .run_nodes(&mut nodes, &order, start, state, context, token, trace)
+            .await;
+        self.nodes = uids
+            .into_iter()
+            .filter_map(|uid| nodes.remove(&uid))
+            .collect();
+        let mut outputs = result?;
+        Ok(outputs.remove(&end).unwrap_or_default())
+    }
+    /// Run the nodes in the order that are not completed in the state, and get the outputs of
+    /// all nodes. With a checkpoint path, the state is saved after every completed node. The
+    /// report of every finished or skipped node is put in the trace.
+    #[allow(clippy::too_many_arguments)]
+    async fn run_nodes(
+        &self,
+        nodes: &mut HashMap<Uuid, Worknode>,
+        order: &[Uuid],
+        start: Uuid,
+        mut state: Checkpoint,
+        context: &RunContext,
+        token: &CancellationToken,
+        trace: &mut Vec<NodeReport>,
+    ) -> PilotResult<HashMap<Uuid, String>> {
+        let deadline = self
+            .deadline
+            .map(|deadline| tokio::time::Instant::now() + deadline);
+        state.outputs.retain(|uid, _| order.contains(uid));
+        let mut waiting: HashMap<Uuid, usize> = order
+            .iter()
+            .map(|&uid| {
+                let count = self
+                    .edges
+                    .iter()
+                    .filter(|edge| {
+                        edge.to == uid
+                            && order.contains(&edge.from)
+                            && !state.is_finished(edge.from)
+                    })
+                    .count();
+                (uid, count)
+            })
+            .collect();
+        let mut ready = VecDeque::new();
+        let settled = order
+            .iter()
+            .filter(|&uid| waiting[uid] == 0 && !state.is_finished(*uid))
+            .copied()
+            .collect();
+        self.settle(settled, start, &mut waiting, &mut ready, &mut state);
+        let mut tasks = JoinSet::new();
+        let mut in_flight: Vec<Uuid> = Vec::new();
+        let mut running: HashMap<String, usize> = HashMap::new();
+        let mut error = None;
+        // what the run spent, and the nodes that were refused by the budget or by a hook
+        let mut spent = CostEntry::default();
+        let mut refused = HashSet::new();
+        let mut budget_alerted = false;
+        loop {
+            if token.is_cancelled() && error.is_none() {
+                error = Some(cancelled_error());
+            }
+            // a node isn't started when there is no time left for it
+            let expired = deadline.is_some_and(|deadline| deadline <= tokio::time::Instant::now());
+            if expired && !ready.is_empty() && error.is_none() {
+                error = Some(deadline_error());
+            }
+            while error.is_none() && tasks.len() < self.max_parallelism.max(1) {
+                let uid = match self.next_ready(&ready, nodes, &running) {
+                    Some(index) => ready.remove(index).unwrap(),
+                    None => break,
+                };
+                // the node is in the order, so it must be in the workflow
+                let mut node = nodes.remove(&uid).unwrap();
+                if let Some(provider) = node.get_node().get_provider() {
+                    *running.entry(provider.to_string()).or_default() += 1;
+                }
+                let mut node_input = if uid == start {
+                    state.input.clone()
+                } else if matches!(
+                    node.get_node(),
+                    Worknodecore::Join(_) | Worknodecore::Reduce(_)
+                ) {
+                    self.gather_branches(uid, &state)
+                } else {
+                    self.gather_input(uid, &state)
+                };
+                // a hook that fails the node stops the next hooks
+                let hooked = self
+                    .hooks
+                    .iter()
+                    .try_for_each(|hook| hook.before_execute(&node, &mut node_input));
+                in_flight.push(uid);
+                if let Worknodecore::Delay(delay) = node.get_node_mut() {
+                    // the moment is saved before the node waits, so a resumed run wakes at it
+                    if let Some(&wake) = state.wakes.get(&uid) {
+                        delay.set_wake(Some(wake));
+                    } else if let Ok(wake) = delay.wake_at(Utc::now()) {
+                        delay.set_wake(Some(wake));
+                        state.wakes.insert(uid, wake);
+                        self.save_checkpoint(&mut state, &in_flight, &ready, context);
+                    }
+                }
+                self.emit(RunEvent::NodeStarted {
+                    node: uid,
+                    kind: node.get_node().kind_name(),
+                });
+                self.log(
+                    state.run,
+                    LogEvent::NodeStarted {
+                        node: uid,
+                        kind: node.get_node().kind_name().to_string(),
+                        input: node_input.clone(),
+                    },
+                );
+                // the nodes that call an AI service have a provider or a usage
+                let calls_service = node.get_node().get_provider().is_some()
+                    || node.get_node().get_total_usage().is_some();
+                let over_budget = calls_service
+                    && self
+                        .budget
+                        .as_ref()
+                        .is_some_and(|budget| !budget.allows(&spent));
+                let refusal = match hooked {
+                    Err(e) => Some(e),
+                    Ok(()) if over_budget => Some(budget_error()),
+                    Ok(()) => None,
+                };
+                if refusal.is_some() {
+                    refused.insert(uid);
+                }
+                if let (Worknodecore::Approval(approval), None) = (node.get_node(), &refusal) {
+                    // the request is put in the context before it is announced, so it can be
+                    // decided as soon as the event is seen. A refused node never waits for it
+                    match approval.request_for(&node_input, context) {
+                        Ok(request) => {
+                            context.approvals().request(request.clone());
+                            self.emit(RunEvent::ApprovalRequested(request));
+                        }
+                        Err(e) => log::warn!("Failed to announce the approval. {}", e),
+                    }
+                }
+                if over_budget && !budget_alerted {
+                    budget_alerted = true;
+                    self.alert_budget(state.run, uid, &spent).await;
+                }
+                let price = node
+                    .get_node()
+                    .get_provider()
+                    .and_then(|provider| self.prices.get(provider))
+                    .copied();
+                let since = node.get_node().get_total_usage().unwrap_or_default();
+                if let Worknodecore::Agent(agent) = node.get_node_mut() {
+                    agent.set_budget(self.budget.map(|budget| BudgetGuard {
+                        budget,
+                        spent,
+                        price,
+                        since,
+                    }));
+                }
+                let context = context.clone();
+                let token = token.clone();
+                let traced_input = node_input.clone();
+                let span = tracing::info_span!(
+                    "worknode",
+                    node = %uid,
+                    kind = node.get_node().kind_name(),
+                    provider = node.get_node().get_provider(),
+                );
+                tasks.spawn(
+                    async move {
+                        let started_at = Utc::now();
+                        let started = Instant::now();
+                        let before = node
+                            .get_node()
+                            .get_service()
+                            .map(|service| (service.get_total_usage(), service.get_requests()));
+                        // dropping the execution stops the node, including its requests, so the
+                        // rest of the deadline is the timeout of the node
+                        let result = match refusal {
+                            Some(e) => Err(e),
+                            None => tokio::select! {
+                                result = CatchPanic(Box::pin(node.excute_in(node_input, &context))) => result,
+                                _ = token.cancelled() => Err(cancelled_error()),
+                                _ = sleep_until(deadline) => Err(deadline_error()),
+                            },
+                        };
+                        let duration = started.elapsed();
+                        (
+                            uid,
+                            node,
+                            traced_input,
+                            result,
+                            started_at,
+                            duration,
+                            before,
+                        )
+                    }
+                    .instrument(span),
+                );
+            }
+            let (uid, node, input, mut result, started_at, duration, before) =
+                match tasks.join_next().await {
+                    Some(Ok(finished)) => finished,
+                    // the panics of the nodes are caught in their tasks, so this is only a
+                    // task that was aborted
+                    Some(Err(e)) => {
+                        error.get_or_insert_with(|| {
+                            graph_error(
+                                GraphErrorType::NodePanicked,
+                                format!("The task of a node failed. {}", e),
+                            )
+                        });
+                        continue;
+                    }
+                    None => break,
+                };
+            if let Some(provider) = node.get_node().get_provider() {
+                if let Some(count) = running.get_mut(provider) {
+                    *count -= 1;
+                }
+            }
+            in_flight.retain(|&running| running != uid);
+            // a refused node sent no request, and a cache node that hit sent none either
+            let hit = matches!(node.get_node(), Worknodecore::Cache(cache) if cache.is_hit());
+            let refused_node = refused.remove(&uid);
+            let (usage, requests) = match (before, node.get_node().get_service()) {
+                (Some((usage, requests)), Some(service)) if !refused_node => (
+                    Some(service.get_total_usage() - usage).filter(|_| !hit),
+                    service.get_requests() - requests,
+                ),
+                _ => (None, RequestCount::default()),
+            };
+            let price = node
+                .get_node()
+                .get_provider()
+                .and_then(|provider| self.prices.get(provider));
+            let cost = usage.zip(price).map(|(usage, price)| price.cost(&usage));
+            if let Some(usage) = usage {
+                spent.add(usage, cost);
+                let exceeded = self
+                    .budget
+                    .as_ref()
+                    .is_some_and(|budget| budget.is_exceeded(&spent));
+                // an agent stopped by its budget guard fails like a node over the budget
+                let stopped = result.as_ref().is_err_and(is_budget_stop);
+                if (exceeded && result.is_ok()) || stopped {
+                    result = Err(budget_error());
+                }
+                if (exceeded || stopped) && !budget_alerted {
+                    budget_alerted = true;
+                    self.alert_budget(state.run, uid, &spent).await;
+                }
+            }
+            if let Ok(output) = &mut result {
+                let hooked = self
+                    .hooks
+                    .iter()
+                    .try_for_each(|hook| hook.after_execute(&node, output));
+                if let Err(e) = hooked {
+                    result = Err(e);
+                }
+            }
+            if let Err(e) = &result {
+                for hook in &self.hooks {
+                    hook.on_error(&node, e);
+                }
+            }
+            if let Some(metrics) = &self.metrics {
+                metrics.record_node(&node, duration, usage, requests);
+            }
+            self.emit(match &result {
+                Ok(output) => RunEvent::NodeFinished {
+                    node: uid,
+                    output: output.clone(),
+                    usage,
+                    duration,
+                },
+                Err(e) => RunEvent::NodeFailed {
+                    node: uid,
+                    error: e.to_string(),
+                    duration,
+                },
+            });
+            self.log_node(state.run, &node, &result, usage, started_at, duration);
+            trace.push(NodeReport {
+                node: uid,
+                kind: node.get_node().kind_name().to_string(),
+                provider: node.get_node().get_provider().map(str::to_string),
+                status: match result {
+                    Ok(_) => NodeStatus::Completed,
+                    Err(_) => NodeStatus::Failed,
+                },
+                input: Some(input),
+                output: result.as_ref().ok().cloned(),
+                error: result.as_ref().err().map(|e| e.to_string()),
+                error_code: result.as_ref().err().map(PilotError::code),
+                attempts: node.get_attempts(),
+                duration,
+                usage,
+                cost,
+            });
+            if let (Ok(_), Some(history)) = (&result, node.get_node().get_history()) {
+                state.histories.insert(uid, history.clone());
+            }
+            if let Worknodecore::Approval(approval) = node.get_node() {
+                if result.is_ok() && approval.is_rejected() {
+                    state.routes.insert(uid, EdgeKind::Rejected);
+                }
+            }
+            if let Worknodecore::Router(router) = node.get_node() {
+                if let (Ok(_), Some(label)) = (&result, router.get_label()) {
+                    state.labels.insert(uid, label.to_string());
+                }
+            }
+            // a failure with on_error edges is routed to them, unless the run is cancelled
+            let result = match result {
+                Err(e) if !token.is_cancelled() && self.has_error_edge(uid) => {
+                    state.routes.insert(uid, EdgeKind::OnError);
+                    Ok(NodeFailure::new(uid, node.get_node().kind_name(), &e).to_json())
+                }
+                result => result,
+            };
+            nodes.insert(uid, node);
+            match result {
+                Ok(output) => {
+                    state.outputs.insert(uid, output);
+                    let settled = self.release(uid, &mut waiting);
+                    self.settle(settled, start, &mut waiting, &mut ready, &mut state);
+                    self.save_checkpoint(&mut state, &in_flight, &ready, context);
+                }
+                // keep the first error, and wait for the running nodes to come back
+                Err(e) => {
+                    error.get_or_insert(e);
+                }
+            }
+        }
+        trace.extend(
+            state
+                .skipped
+                .iter()
+                .map(|&uid| NodeReport::skipped(uid, &nodes[&uid])),
+        );
+        match error {
+            Some(e) => Err(e),
+            None => Ok(state.outputs),
+        }
+    }
+    /// Save the state to the checkpoint path, if there is one, with the nodes that are
+    /// running or ready as the pending ones.
+    fn save_checkpoint(
+        &self,
+        state: &mut Checkpoint,
+        in_flight: &[Uuid],
+        ready: &VecDeque<Uuid>,
+        context: &RunContext,
+    ) {
+        if let Some(path) = &self.checkpoint_path {
+            state.pending = in_flight.iter().chain(ready.iter()).copied().collect();
+            state.context = context.snapshot();
+            if let Err(e) = state.save(path) {
+                log::warn!("Failed to save the checkpoint of the workflow. {}", e);
+            }
+        }
+    }
+    /// Whether the node has an on_error edge.
+    fn has_error_edge(&self, uid: Uuid) -> bool {
+        self.edges
+            .iter()
+            .any(|edge| edge.from == uid && edge.kind == EdgeKind::OnError)
+    }
+    /// Count down the successors of a finished node, and get the ones that wait for nothing
+    /// anymore.
+    fn release(&self, uid: Uuid, waiting: &mut HashMap<Uuid, usize>) -> VecDeque<Uuid> {
+        let mut settled = VecDeque::new();
+        for edge in self.edges.iter().filter(|edge| edge.from == uid) {
+            let count = waiting.get_mut(&edge.to).unwrap();
+            *count -= 1;
+            if *count == 0 {
+                settled.push_back(edge.to);
+            }
+        }
+        settled
+    }
+    /// Decide whether the nodes that wait for nothing run or are skipped. A node runs when at
+    /// least one of its incoming edges is followed, and is skipped otherwise, like the nodes
+    /// after the normal edges of a rejected approval or of a failed node, or after the route
+    /// edges of the labels that a router didn't choose. The successors of a skipped node are
+    /// settled in turn.
+    fn settle(
+        &self,
+        mut settled: VecDeque<Uuid>,
+        start: Uuid,
+        waiting: &mut HashMap<Uuid, usize>,
+        ready: &mut VecDeque<Uuid>,
+        state: &mut Checkpoint,
+    ) {
+        while let Some(uid) = settled.pop_front() {
+            let followed = self
+                .edges
+                .iter()
+                .any(|edge| edge.to == uid && state.is_followed(edge));
+            if uid == start || followed {
+                ready.push_back(uid);
+            } else {
+                state.skipped.push(uid);
+                self.emit(RunEvent::NodeSkipped { node: uid });
+                self.log(state.run, LogEvent::NodeSkipped { node: uid });
+                settled.extend(self.release(uid, waiting));
+            }
+        }
+    }
+    /// Get the index of the first ready node that can be spawned, which is the first one whose
+    /// provider has not hit its limit.
+    fn next_ready(
+        &self,
+        ready: &VecDeque<Uuid>,
+        nodes: &HashMap<Uuid, Worknode>,
+        running: &HashMap<String, usize>,
+    ) -> Option<usize> {
+        ready.iter().position(|uid| {
+            match nodes
+                .get(uid)
+                .and_then(|node| node.get_node().get_provider())
+            {
+                Some(provider) => match self.get_provider_limit(provider) {
+                    Some(limit) => running.get(provider).copied().unwrap_or(0) < limit.max(1),
+                    None => true,
+                },
+                None => true,
+            }
+        })
+    }
+    /// Set the max number of nodes running at the same time as builder.
+    pub fn max_parallelism(mut self, max_parallelism: usize) -> Self {
+        self.max_parallelism = max_parallelism;
+        self
+    }
+    /// Set the max number of nodes running at the same time.
+    pub fn set_max_parallelism(&mut self, max_parallelism: usize) {
+        self.max_parallelism = max_parallelism;
+    }
+    /// Get the max number of nodes running at the same time.
+    pub fn get_max_parallelism(&self) -> usize {
+        self.max_parallelism
+    }
+    /// The default max number of nodes running at the same time.
+    pub fn default_max_parallelism() -> usize {
+        8
+    }
+    /// Set the max number of nodes of the provider running at the same time as builder.
+    pub fn provider_limit(mut self, provider: &str, limit: usize) -> Self {
+        self.set_provider_limit(provider, limit);
+        self
+    }
+    /// Set the max number of nodes of the provider running at the same time.
+    pub fn set_provider_limit(&mut self, provider: &str, limit: usize) {
+        self.provider_limits.insert(provider.to_string(), limit);
+    }
+    /// Remove the limit of the provider, so only `max_parallelism` limits its nodes.
+    pub fn remove_provider_limit(&mut self, provider: &str) -> Option<usize> {
+        self.provider_limits.remove(provider)
+    }
+    /// Get the max number of nodes of the provider running at the same time.
+    pub fn get_provider_limit(&self, provider: &str) -> Option<usize> {
+        self.provider_limits.get(provider).copied()
+    }
+    /// Get the limits of all providers.
+    pub fn get_provider_limits(&self) -> &HashMap<String, usize> {
+        &self.provider_limits
+    }
+    /// Send the event to the channel. An event is dropped if nobody listens anymore.
+    fn emit(&self, event: RunEvent) {
+        if let Some(events) = &self.events {
+            let _ = events.send(event);
+        }
+    }
+    /// Append the event of the run to the execution log, if there is one.
+    fn log(&self, run: Uuid, event: LogEvent) {
+        if let Some(execution_log) = &self.execution_log {
+            execution_log.write(run, event);
+        }
+    }
+    /// Append the tool calls and the end of a node that came back to the execution log, if
+    /// there is one.
+    fn log_node(
+        &self,
+        run: Uuid,
+        node: &Worknode,
+        result: &PilotResult<String>,
+        usage: Option<DeepSeekUsage>,
+        started_at: chrono::DateTime<Utc>,
+        duration: Duration,
+    ) {
+        let Some(execution_log) = &self.execution_log else {
+            return;
+        };
+        let uid = node.get_uid();
+        if let Some(history) = node.get_node().get_history() {
+            for (call, result) in execution_log::tool_calls(history, started_at) {
+                execution_log.write(
+                    run,
+                    LogEvent::ToolCall {
+                        node: uid,
+                        tool: call.name,
+                        arguments: call.arguments,
+                        result,
+                    },
+                );
+            }
+        }
+        let kind = node.get_node().kind_name().to_string();
+        let attempts = node.get_attempts();
+        let duration_ms = execution_log::millis(duration);
+        execution_log.write(
+            run,
+            match result {
+                Ok(output) => LogEvent::NodeFinished {
+                    node: uid,
+                    kind,
+                    output: output.clone(),
+                    attempts,
+                    duration_ms,
+                    usage,
+                },
+                Err(e) => LogEvent::NodeFailed {
+                    node: uid,
+                    kind,
+                    code: e.code(),
+                    error: e.to_string(),
+                    attempts,
+                    duration_ms,
+                },
+            },
+        );
+    }
+    /// Send the alert to the sinks of the workflow.
+    async fn alert(&self, alert: Alert) {
+        alert::send_all(&self.alert_sinks, &alert).await;
+    }
+    /// Send the alert of the run whose spending at the node went over the budget.
+    async fn alert_budget(&self, run: Uuid, node: Uuid, spent: &CostEntry) {
+        let message = format!(
+            "The run spent {} tokens and a cost of {}, over the budget.",
+            spent.usage.get_total_tokens(),
+            spent.cost.unwrap_or_default()
+        );
+        self.alert(Alert::new(
+            AlertKind::BudgetExceeded,
+            run,
+            Some(node),
+            message,
+        ))
+        .await;
+    }
+    /// Set the execution log of the runs as builder.
+    pub fn execution_log(mut self, execution_log: Option<ExecutionLog>) -> Self {
+        self.execution_log = execution_log;
+        self
+    }
+    /// Set the execution log that the events of the runs are appended to.
+    pub fn set_execution_log(&mut self, execution_log: Option<ExecutionLog>) {
+        self.execution_log = execution_log;
+    }
+    /// Get the execution log of the runs.
+    pub fn get_execution_log(&self) -> Option<&ExecutionLog> {
+        self.execution_log.as_ref()
+    }
+    /// Set the event channel as builder.
+    pub fn events(mut self, events: Option<UnboundedSender<RunEvent>>) -> Self {
+        self.events = events;
+        self
+    }
+    /// Set the event channel.
+    pub fn set_events(&mut self, events: Option<UnboundedSender<RunEvent>>) {
+        self.events = events;
+    }
+    /// Get the event channel.
+    pub fn get_events(&self) -> Option<&UnboundedSender<RunEvent>> {
+        self.events.as_ref()
+    }
+    /// Set whether the nodes stream their answers as builder.
+    pub fn stream_output(mut self, stream_output: bool) -> Self {
+        self.stream_output = stream_output;
+        self
+    }
+    /// Set whether the AI and agent nodes stream their answers, and emit the pieces as
+    /// `RunEvent::NodeOutput` events to the event channel. The requests of a recording and
+    /// the ones that call tools are not streamed.
+    pub fn set_stream_output(&mut self, stream_output: bool) {
+        self.stream_output = stream_output;
+    }
+    /// Get whether the nodes stream their answers.
+    pub fn get_stream_output(&self) -> bool {
+        self.stream_output
+    }
+    /// Set the metrics that the finished nodes are recorded to as builder.
+    pub fn metrics(mut self, metrics: Option<Metrics>) -> Self {
+        self.metrics = metrics;
+        self
+    }
+    /// Set the metrics that the finished nodes are recorded to.
+    pub fn set_metrics(&mut self, metrics: Option<Metrics>) {
+        self.metrics = metrics;
+    }
+    /// Get the metrics that the finished nodes are recorded to.
+    pub fn get_metrics(&self) -> Option<&Metrics> {
+        self.metrics.as_ref()
+    }
+    /// Set the file that the checkpoint of a run is saved to as builder.
+    pub fn checkpoint_path(mut self, checkpoint_path: Option<PathBuf>) -> Self {
+        self.checkpoint_path = checkpoint_path;
+        self
+    }
+    /// Set the file that the checkpoint of a run is saved to.
+    pub fn set_checkpoint_path(&mut self, checkpoint_path: Option<PathBuf>) {
+        self.checkpoint_path = checkpoint_path;
+    }
+    /// Get the file that the checkpoint of a run is saved to.
+    pub fn get_checkpoint_path(&self) -> Option<&PathBuf> {
+        self.checkpoint_path.as_ref()
+    }
+    /// Set the most that a run may spend on the AI services as builder.
+    pub fn budget(mut self, budget: Option<Budget>) -> Self {
+        self.budget = budget;
+        self
+    }
+    /// Set the most that a run may spend on the AI services.
+    pub fn set_budget(&mut self, budget: Option<Budget>) {
+        self.budget = budget;
+    }
+    /// Get the most that a run may spend on the AI services.
+    pub fn get_budget(&self) -> Option<&Budget> {
+        self.budget.as_ref()
+    }
+    /// Set where the settings of the workflow were read from as builder.
+    pub fn config_origin(mut self, config_origin: Option<ConfigOrigin>) -> Self {
+        self.config_origin = config_origin;
+        self
+    }
+    /// Set where the settings of the workflow were read from.
+    pub fn set_config_origin(&mut self, config_origin: Option<ConfigOrigin>) {
+        self.config_origin = config_origin;
+    }
+    /// Get where the settings of the workflow were read from.
+    pub fn get_config_origin(&self) -> Option<&ConfigOrigin> {
+        self.config_origin.as_ref()
+    }
+    /// Add a hook called around the execution of the nodes as builder.
+    pub fn hook(mut self, hook: Arc<dyn NodeHook>) -> Self {
+        self.add_hook(hook);
+        self
+    }
+    /// Add a hook called around the execution of the nodes, after the ones already added.
+    pub fn add_hook(&mut self, hook: Arc<dyn NodeHook>) {
+        self.hooks.push(hook);
+    }
+    /// Get the hooks called around the execution of the nodes.
+    pub fn get_hooks(&self) -> &Vec<Arc<dyn NodeHook>> {
+        &self.hooks
+    }
+    /// Add a sink that the alerts of the runs are sent to as builder.
+    pub fn alert_sink(mut self, sink: AlertSink) -> Self {
+        self.alert_sinks.push(sink);
+        self
+    }
+    /// Add a sink that the alerts of the runs are sent to.
+    pub fn add_alert_sink(&mut self, sink: AlertSink) {
+        self.alert_sinks.push(sink);
+    }
+    /// Set the sinks that the alerts of the runs are sent to.
+    pub fn set_alert_sinks(&mut self, sinks: Vec<AlertSink>) {
+        self.alert_sinks = sinks;
+    }
+    /// Get the sinks that the alerts of the runs are sent to.
+    pub fn get_alert_sinks(&self) -> &Vec<AlertSink> {
+        &self.alert_sinks
+    }
+    /// Set the wall-clock time that a run may take as builder.
+    pub fn deadline(mut self, deadline: Option<Duration>) -> Self {
+        self.deadline = deadline;
+        self
+    }
+    /// Set the wall-clock time that a run may take.
+    pub fn set_deadline(&mut self, deadline: Option<Duration>) {
+        self.deadline = deadline;
+    }
+    /// Get the wall-clock time that a run may take.
+    pub fn get_deadline(&self) -> Option<Duration> {
+        self.deadline
+    }
+    /// Set the price of the provider as builder.
+    pub fn price(mut self, provider: &str, price: Pricing) -> Self {
+        self.set_price(provider, price);
+        self
+    }
+    /// Set the price of the provider, so the report of a run has the cost of its nodes.
+    pub fn set_price(&mut self, provider: &str, price: Pricing) {
+        self.prices.insert(provider.to_string(), price);
+    }
+    /// Get the price of the provider.
+    pub fn get_price(&self, provider: &str) -> Option<&Pricing> {
+        self.prices.get(provider)
+    }
+    /// Set the recording of the runs as builder.
+    pub fn recording(mut self, recording: Option<Recording>) -> Self {
+        self.recording = recording;
+        self
+    }
+    /// Set the recording of the runs. Every AI and agent node records its requests to it, or
+    /// replays them from it, when a run starts.
+    pub fn set_recording(&mut self, recording: Option<Recording>) {
+        self.recording = recording;
+    }
+    /// Get the recording of the runs.
+    pub fn get_recording(&self) -> Option<&Recording> {
+        self.recording.as_ref()
+    }
+}
+
+/// Wait until the deadline, or forever without one.
+async fn sleep_until(deadline: Option<tokio::time::Instant>) {
+    match deadline {
+        Some(deadline) => tokio::time::sleep_until(deadline).await,
+        None => std::future::pending().await,
+    }
+}
+
+/// Create the PilotError of a run that passed its deadline.
+fn deadline_error() -> PilotError {
+    graph_error(
+        GraphErrorType::DeadlineExceeded,
+        "The deadline of the run has passed.".to_string(),
+    )
+}
+
+/// Create the PilotError of a run that spent its budget.
+fn budget_error() -> PilotError {
+    graph_error(
+        GraphErrorType::BudgetExceeded,
+        "The budget of the run is spent.".to_string(),
+    )
+}
+
+/// Whether the error is the one of an agent stopped by its budget guard.
+fn is_budget_stop(error: &PilotError) -> bool {
+    matches!(
+        error.get_error_type(),
+        PilotErrorType::AINodeErr(e) if matches!(e.get_error_type(), AINodeErrorType::BudgetExceeded)
+    )
+}
+
+/// Create the PilotError of a cancelled run.
+fn cancelled_error() -> PilotError {
+    graph_error(
+        GraphErrorType::Cancelled,
+        "The run was cancelled.".to_string(),
+    )
+}
+
+/// The future of a node that gives a panic of the node as its error, so one node can't take the
+/// whole run down.
+struct CatchPanic<'a>(Pin<Box<dyn Future<Output = PilotResult<String>> + Send + 'a>>);
+
+impl Future for CatchPanic<'_> {
+    type Output = PilotResult<String>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let future = self.0.as_mut();
+        match std::panic::catch_unwind(AssertUnwindSafe(|| future.poll(cx))) {
+            Ok(poll) => poll,
+            Err(panic) => {
+                let message = match panic.downcast_ref::<&str>() {
+                    Some(message) => message.to_string(),
+                    None => panic.downcast_ref::<String>().cloned().unwrap_or_default(),
+                };
+                Poll::Ready(Err(graph_error(
+                    GraphErrorType::NodePanicked,
+                    format!("The node panicked. {}", message),
+                )))
+            }
+        }
+    }
+}
+
+/// Create a PilotError of the graph.
+fn graph_error(error_type: GraphErrorType, message: String) -> PilotError {
+    GraphError::new(error_type, message).into()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::worknode::ai_node::deepseek::{
+        DeepSeekClient, DeepSeekModel, ResponseFormat, DEEPSEEK_API_URL,
+    };
+    use crate::worknode::ai_node::{AINode, AIService, Chat, RequestOverrides, Role};
+    use crate::worknode::approval::ApprovalNode;
+    use crate::worknode::delay::DelayNode;
+    use crate::worknode::join::{JoinNode, JoinStrategy};
+    use crate::worknode::local::LocalNode;
+    use crate::worknode::router::RouterNode;
+    use crate::worknode::user::{UserInput, UserNode};
+
+    use tokio::runtime::Runtime;
+
+    fn graph_error_type(error: &PilotError) -> &GraphErrorType {
+        match error.get_error_type() {
+            PilotErrorType::GraphErr(e) => e.get_error_type(),
+            _ => panic!("Unexpected error: {}", error),
+        }
+    }
+
+    #[test]
+    fn run_start_to_end() {
+        let mut workflow = Workflow::new();
+        let start = workflow.add_node(Worknode::new(Worknodecore::Start));
+        let end = workflow.add_node(Worknode::new(Worknodecore::End));
+        workflow.add_edge(start, end).unwrap();
+        let rt = Runtime::new().unwrap();
+        let output = rt.block_on(workflow.run("hello".to_string())).unwrap();
+        assert_eq!(output, "hello");
+    }
+
+    #[test]
+    fn gather_inputs_by_port() {
+        let mut workflow = Workflow::new();
+        let a = workflow.add_node(Worknode::new(Worknodecore::Start));
+        let b = workflow.add_node(Worknode::new(Worknodecore::Local(LocalNode::default())));
+        let c = workflow.add_node(Worknode::new(Worknodecore::End));
+        workflow.add_edge(a, c).unwrap();
+        workflow.add_port_edge(b, c, "context").unwrap();
+        workflow.add_edge(b, c).unwrap();
+        let state = Checkpoint {
+            outputs: HashMap::from([(a, "one".to_string()), (b, "two".to_string())]),
+            ..Checkpoint::default()
+        };
+        let input = json::parse(&workflow.gather_input(c, &state)).unwrap();
+        assert_eq!(input["input"], "one\ntwo");
+        assert_eq!(input["context"], "two");
+        assert_eq!(workflow.gather_input(b, &state), "{}");
+    }
+
+    #[test]
+    fn invalid_graphs() {
+        let rt = Runtime::new().unwrap();
+        let mut workflow = Workflow::new();
+        let start = workflow.add_node(Worknode::new(Worknodecore::Start));
+        let error = rt.block_on(workflow.run(String::new())).unwrap_err();
+        assert!(matches!(
+            graph_error_type(&error),
+            GraphErrorType::StartEndError
+        ));
+
+        let end = workflow.add_node(Worknode::new(Worknodecore::End));
+        let error = rt.block_on(workflow.run(String::new())).unwrap_err();
+        assert!(matches!(
+            graph_error_type(&error),
+            GraphErrorType::UnreachableEnd
+        ));
+
+        let a = workflow.add_node(Worknode::new(Worknodecore::Local(LocalNode::default())));
+        let b = workflow.add_node(Worknode::new(Worknodecore::Local(LocalNode::default())));
+        workflow.add_edge(start, a).unwrap();
+        workflow.add_edge(a, b).unwrap();
+        workflow.add_edge(b, a).unwrap();
+        workflow.add_edge(b, end).unwrap();
+        let error = rt.block_on(workflow.run(String::new())).unwrap_err();
+        assert!(matches!(
+            graph_error_type(&error),
+            GraphErrorType::CycleError
+        ));
+
+        let error = workflow.add_edge(start, Uuid::new_v4()).unwrap_err();
+        assert!(matches!(
+            graph_error_type(&error),
+            GraphErrorType::NodeNotFound
+        ));
+    }
+
+    #[test]
+    fn fan_out_and_join() {
+        let mut workflow = Workflow::new().max_parallelism(2);
+        let start = workflow.add_node(Worknode::new(Worknodecore::Start));
+        let a = workflow.add_node(Worknode::new(Worknodecore::Join(JoinNode::default())));
+        let b = workflow.add_node(Worknode::new(Worknodecore::Join(JoinNode::new(
+            JoinStrategy::JsonArray,
+        ))));
+        let join = workflow.add_node(Worknode::new(Worknodecore::Join(JoinNode::new(
+            JoinStrategy::Concat("|".to_string()),
+        ))));
+        let end = workflow.add_node(Worknode::new(Worknodecore::End));
+        workflow.add_edge(start, a).unwrap();
+        workflow.add_edge(start, b).unwrap();
+        workflow.add_edge(a, join).unwrap();
+        workflow.add_edge(b, join).unwrap();
+        workflow.add_edge(join, end).unwrap();
+        let rt = Runtime::new().unwrap();
+        let output = rt.block_on(workflow.run("x".to_string())).unwrap();
+        assert_eq!(output, r#"x|["x"]"#);
+        // the nodes are put back after the run
+        assert_eq!(workflow.get_nodes().len(), 5);
+        assert!(workflow.get_node(join).is_some());
+    }
+
+    #[test]
+    fn run_context() {
+        let mut workflow = Workflow::new();
+        let start = workflow
+            .add_node(Worknode::new(Worknodecore::Start).context_key(Some("question".to_string())));
+        let end = workflow
+            .add_node(Worknode::new(Worknodecore::End).context_key(Some("answer".to_string())));
+        workflow.add_edge(start, end).unwrap();
+        let context = RunContext::new();
+        context.set("seed", 1).unwrap();
+        let rt = Runtime::new().unwrap();
+        rt.block_on(workflow.run_with_context(r#"{"n": 42}"#.to_string(), &context))
+            .unwrap();
+        assert_eq!(context.get_value("question").unwrap()["n"], 42);
+        assert_eq!(context.keys(), vec!["answer", "question", "seed"]);
+    }
+
+    #[test]
+    fn checkpoint_and_resume() {
+        let path = std::env::temp_dir().join(format!("aipilot-{}.json", Uuid::new_v4()));
+        let mut workflow = Workflow::new().checkpoint_path(Some(path.clone()));
+        let start = workflow.add_node(Worknode::new(Worknodecore::Start));
+        let a = workflow.add_node(Worknode::new(Worknodecore::Join(JoinNode::new(
+            JoinStrategy::JsonArray,
+        ))));
+        let end = workflow.add_node(Worknode::new(Worknodecore::End));
+        workflow.add_edge(start, a).unwrap();
+        workflow.add_edge(a, end).unwrap();
+        let rt = Runtime::new().unwrap();
+        let context = RunContext::new();
+        context.set("seed", 1).unwrap();
+        rt.block_on(workflow.run_with_context("x".to_string(), &context))
+            .unwrap();
+        let checkpoint = Checkpoint::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert!(checkpoint.is_completed(end));
+        assert_eq!(checkpoint.get_context()["seed"], 1);
+
+        // pretend the run stopped after `a`, with an output that shows it is not run again
+        let mut interrupted = checkpoint.clone();
+        interrupted.outputs.remove(&end);
+        interrupted.outputs.insert(a, "done".to_string());
+        let mut workflow = workflow.checkpoint_path(None);
+        let output = rt.block_on(workflow.resume(interrupted)).unwrap();
+        assert_eq!(output, "done");
+        assert_eq!(
+            rt.block_on(workflow.resume(checkpoint.clone())).unwrap(),
+            r#"["x"]"#
+        );
+
+        // the checkpoints that can't be kept are storage errors
+        let error = checkpoint
+            .save(std::env::temp_dir().join(format!("aipilot-{}/run.json", Uuid::new_v4())))
+            .unwrap_err();
+        assert_eq!(error.code(), "storage.write_error");
+        std::fs::write(&path, "not a checkpoint").unwrap();
+        let error = Checkpoint::load(&path).unwrap_err();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(error.code(), "storage.invalid_data");
+        assert!(error.to_string().starts_with("StorageError: "));
+        let failure = NodeFailure::new(a, "join", &error);
+        assert_eq!(failure.source, "storage");
+        assert_eq!(failure.class, ErrorClass::Other);
+        assert!(!failure.retryable);
+        let error: PilotError = crate::config::Config::parse("[limits]\nmax_paralelism = 4")
+            .unwrap_err()
+            .into();
+        assert_eq!(error.code(), "config.parse_error");
+        assert_eq!(NodeFailure::new(a, "join", &error).source, "config");
+    }
+
+    #[test]
+    fn resume_delay() {
+        let path = std::env::temp_dir().join(format!("aipilot-{}.json", Uuid::new_v4()));
+        let mut workflow = Workflow::new()
+            .checkpoint_path(Some(path.clone()))
+            .deadline(Some(Duration::from_millis(100)));
+        let start = workflow.add_node(Worknode::new(Worknodecore::Start));
+        let delay = workflow.add_node(Worknode::new(Worknodecore::Delay(DelayNode::duration(
+            Duration::from_secs(3600),
+        ))));
+        let end = workflow.add_node(Worknode::new(Worknodecore::End));
+        workflow.add_edge(start, delay).unwrap();
+        workflow.add_edge(delay, end).unwrap();
+        let rt = Runtime::new().unwrap();
+        let started = Utc::now();
+        assert!(rt.block_on(workflow.run("x".to_string())).is_err());
+        let mut checkpoint = Checkpoint::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        // the moment is saved when the node starts, not when it finishes
+        assert!(checkpoint.get_pending().contains(&delay));
+        let wake = checkpoint.get_wakes()[&delay];
+        assert!(wake >= started + Duration::from_secs(3600));
+
+        // the resumed run wakes at the saved moment, which has passed here
+        checkpoint
+            .wakes
+            .insert(delay, started - Duration::from_secs(1));
+        let mut workflow = workflow.checkpoint_path(None);
+        assert_eq!(rt.block_on(workflow.resume(checkpoint)).unwrap(), "x");
+    }
+
+    #[test]
+    fn stream_node_output() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+        use tokio::net::TcpListener;
+
+        let rt = Runtime::new().unwrap();
+        let listener = rt.block_on(TcpListener::bind("127.0.0.1:0")).unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        rt.spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut request = [0; 8192];
+            let _ = stream.read(&mut request).await.unwrap();
+            let body = concat!(
+                "data: {\"choices\": [{\"delta\": {\"content\": \"Hel\"}}]}\n\n",
+                "data: {\"choices\": [{\"delta\": {\"content\": \"lo\"}}]}\n\n",
+                "data: {\"choices\": [], \"usage\": {\"prompt_tokens\": 3, \"completion_tokens\": 2, \"prompt_cache_hit_tokens\": 0, \"prompt_cache_miss_tokens\": 3, \"total_tokens\": 5}}\n\n",
+                "data: [DONE]\n\n",
+            );
+            let response = format!(
+                "HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                body.len(),
+                body
+            );
+            stream.write_all(response.as_bytes()).await.unwrap();
+        });
+        let mut client = DeepSeekClient::new(&url, DeepSeekModel::DeepseekChat);
+        client.set_api_key(Some("key".to_string()));
+        let (sender, mut receiver) = tokio::sync::mpsc::unbounded_channel();
+        let mut workflow = Workflow::new().events(Some(sender)).stream_output(true);
+        let start = workflow.add_node(Worknode::new(Worknodecore::Start));
+        let ai = workflow.add_node(Worknode::new(Worknodecore::AINode(AINode::new(
+            AIService::new_deepseek(client),
+        ))));
+        let end = workflow.add_node(Worknode::new(Worknodecore::End));
+        workflow.add_edge(start, ai).unwrap();
+        workflow.add_edge(ai, end).unwrap();
+        assert_eq!(
+            rt.block_on(workflow.run("Hi".to_string())).unwrap(),
+            "Hello"
+        );
+        let deltas: Vec<String> = std::iter::from_fn(|| receiver.try_recv().ok())
+            .filter_map(|event| match event {
+                RunEvent::NodeOutput { node, delta } if node == ai => Some(delta),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(deltas, ["Hel", "lo"]);
+        let history = workflow
+            .get_node(ai)
+            .unwrap()
+            .get_node()
+            .get_history()
+            .unwrap();
+        assert_eq!(history.last().unwrap().get_content().as_text(), "Hello");
+    }
+
+    #[test]
+    fn run_events() {
+        let (sender, mut receiver) = tokio::sync::mpsc::unbounded_channel();
+        let path = std::env::temp_dir().join(format!("aipilot-run-{}.jsonl", Uuid::new_v4()));
+        let mut workflow = Workflow::new()
+            .events(Some(sender))
+            .execution_log(Some(ExecutionLog::new(&path)));
+        let start = workflow.add_node(Worknode::new(Worknodecore::Start));
+        let end = workflow.add_node(Worknode::new(Worknodecore::End));
+        workflow.add_edge(start, end).unwrap();
+        let rt = Runtime::new().unwrap();
+        rt.block_on(workflow.run("hi".to_string())).unwrap();
+        let events: Vec<RunEvent> = std::iter::from_fn(|| receiver.try_recv().ok()).collect();
+        assert_eq!(events.len(), 5);
+        assert_eq!(
+            events[0],
+            RunEvent::NodeStarted {
+                node: start,
+                kind: "start"
+            }
+        );
+        assert!(matches!(
+            &events[3],
+            RunEvent::NodeFinished { node, output, usage: None, .. } if *node == end && output == "hi"
+        ));
+        assert!(matches!(
+            events[4],
+            RunEvent::RunFinished {
+                status: RunStatus::Completed,
+                ..
+            }
+        ));
+
+        let log = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let records: Vec<execution_log::LogRecord> = log
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(records.len(), 5);
+        assert!(records.iter().all(|record| record.run == records[0].run));
+        assert!(matches!(
+            &records[3].event,
+            LogEvent::NodeFinished { node, output, attempts: 1, .. } if *node == end && output == "hi"
+        ));
+    }
+
+    #[test]
+    fn approval_routes() {
+        let (sender, mut receiver) = tokio::sync::mpsc::unbounded_channel();
+        let mut workflow = Workflow::new().events(Some(sender));
+        let start = workflow.add_node(Worknode::new(Worknodecore::Start));
+        let approval = workflow.add_node(Worknode::new(Worknodecore::Approval(ApprovalNode::new(
+            "Run {{input}}?",
+        ))));
+        let approved = workflow.add_node(Worknode::new(Worknodecore::Join(JoinNode::default())));
+        let rejected = workflow.add_node(Worknode::new(Worknodecore::Join(JoinNode::default())));
+        let end = workflow.add_node(Worknode::new(Worknodecore::End));
+        workflow.add_edge(start, approval).unwrap();
+        workflow.add_edge(approval, approved).unwrap();
+        workflow.add_rejected_edge(approval, rejected).unwrap();
+        workflow.add_edge(approved, end).unwrap();
+        workflow.add_edge(rejected, end).unwrap();
+        let rt = Runtime::new().unwrap();
+        let (_, report) = rt.block_on(async move {
+            let handle = workflow.run_detached("rm -rf /".to_string());
+            while let Some(event) = receiver.recv().await {
+                if let RunEvent::ApprovalRequested(request) = &event {
+                    assert_eq!(request.message, "Run rm -rf /?");
+                    handle.reject(request.node, "too dangerous").unwrap();
+                }
+                if let RunEvent::NodeSkipped { node } = event {
+                    assert_eq!(node, approved);
+                }
+                if matches!(event, RunEvent::RunFinished { .. }) {
+                    break;
+                }
+            }
+            handle.join().await
+        });
+        assert_eq!(report.get_output(), Some("too dangerous"));
+    }
+
+    #[test]
+    fn error_routes() {
+        let mut workflow = Workflow::new();
+        let start = workflow.add_node(Worknode::new(Worknodecore::Start));
+        // the message can't be rendered, so the node fails without any request
+        let failing = workflow.add_node(Worknode::new(Worknodecore::Approval(ApprovalNode::new(
+            "{{missing}}",
+        ))));
+        let next = workflow.add_node(Worknode::new(Worknodecore::Join(JoinNode::default())));
+        let end = workflow.add_node(Worknode::new(Worknodecore::End));
+        workflow.add_edge(start, failing).unwrap();
+        workflow.add_edge(failing, next).unwrap();
+        workflow.add_edge(next, end).unwrap();
+        workflow.add_error_edge(failing, end).unwrap();
+        let rt = Runtime::new().unwrap();
+        let output = rt.block_on(workflow.run("hi".to_string())).unwrap();
+        let failure = NodeFailure::from_json(&output).unwrap();
+        assert_eq!(failure.node, failing);
+        assert_eq!(failure.kind, "approval");
+        assert_eq!(failure.source, "graph");
+        assert_eq!(
+            failure.context,
+            [format!(
+                "while executing node {} (approval, attempt 1)",
+                failing
+            )]
+        );
+
+        // a node that panics fails like any other node, and the run goes on
+        let mut workflow = Workflow::new();
+        let start = workflow.add_node(Worknode::new(Worknodecore::Start));
+        let panicking = workflow.add_node(Worknode::new(Worknodecore::User(UserNode::new(
+            "{{input}}",
+        ))));
+        let end = workflow.add_node(Worknode::new(Worknodecore::End));
+        workflow.add_edge(start, panicking).unwrap();
+        workflow.add_edge(panicking, end).unwrap();
+        workflow.add_error_edge(panicking, end).unwrap();
+        let context = RunContext::new();
+        context.set_user_input(Some(UserInput::new(|_| async {
+            panic!("the frontend is gone")
+        })));
+        let output = rt
+            .block_on(workflow.run_with_context("hi".to_string(), &context))
+            .unwrap();
+        let failure = NodeFailure::from_json(&output).unwrap();
+        assert_eq!(failure.node, panicking);
+        assert_eq!(failure.source, "graph");
+        assert!(failure.detail.contains("the frontend is gone"));
+    }
+
+    #[test]
+    fn router_routes() {
+        let client = DeepSeekClient::new(DEEPSEEK_API_URL, DeepSeekModel::DeepseekChat);
+        let router = RouterNode::new(AINode::new(AIService::new_deepseek(client.clone())))
+            .route("billing", None)
+            .route("support", None);
+        let chats = router.chats("refund please");
+        let recording = Recording::replay(vec![client.exchange(
+            &chats,
+            &RouterNode::overrides(),
+            "Billing",
+        )]);
+        let mut workflow = Workflow::new().recording(Some(recording));
+        let start = workflow.add_node(Worknode::new(Worknodecore::Start));
+        let router = workflow.add_node(Worknode::new(Worknodecore::Router(router)));
+        let billing = workflow.add_node(Worknode::new(Worknodecore::Join(JoinNode::default())));
+        let support = workflow.add_node(Worknode::new(Worknodecore::Join(JoinNode::default())));
+        let end = workflow.add_node(Worknode::new(Worknodecore::End));
+        workflow.add_edge(start, router).unwrap();
+        workflow.add_route_edge(router, billing, "billing").unwrap();
+        workflow.add_route_edge(router, support, "support").unwrap();
+        workflow.add_edge(billing, end).unwrap();
+        workflow.add_edge(support, end).unwrap();
+        assert!(workflow.validate().is_ok());
+        let rt = Runtime::new().unwrap();
+        let report = rt.block_on(workflow.run_report(
+            "refund please".to_string(),
+            &RunContext::new(),
+            &CancellationToken::new(),
+        ));
+        let status = |uid| report.get_node(uid).unwrap().status;
+        assert_eq!(status(billing), NodeStatus::Completed);
+        assert_eq!(status(support), NodeStatus::Skipped);
+        assert_eq!(report.into_result().unwrap(), "refund please");
+    }
+
+    #[test]
+    fn budget() {
+        let client = DeepSeekClient::new(DEEPSEEK_API_URL, DeepSeekModel::DeepseekChat);
+        let router = RouterNode::new(AINode::new(AIService::new_deepseek(client.clone())))
+            .route("billing", None);
+        let exchange =
+            client.exchange(&router.chats("refund"), &RouterNode::overrides(), "billing");
+        let alerts = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let mut workflow = Workflow::new()
+            .recording(Some(Recording::replay(vec![exchange])))
+            .budget(Some(Budget::new().max_tokens(Some(1))))
+            .alert_sink(AlertSink::callback({
+                let alerts = alerts.clone();
+                move |alert: &Alert| alerts.lock().unwrap().push(alert.kind)
+            }));
+        let start = workflow.add_node(Worknode::new(Worknodecore::Start));
+        let router = workflow.add_node(Worknode::new(Worknodecore::Router(router)));
+        let end = workflow.add_node(Worknode::new(Worknodecore::End));
+        workflow.add_edge(start, router).unwrap();
+        workflow.add_route_edge(router, end, "billing").unwrap();
+        let rt = Runtime::new().unwrap();
+        let run = |workflow: &mut Workflow| {
+            rt.block_on(workflow.run_report(
+                "refund".to_string(),
+                &RunContext::new(),
+                &CancellationToken::new(),
+            ))
+        };
+        // the router spends 2 tokens, over the budget
+        let report = run(&mut workflow);
+        assert_eq!(report.get_node(router).unwrap().status, NodeStatus::Failed);
+        assert_eq!(report.get_ledger().total().usage.get_total_tokens(), 2);
+        assert!(matches!(
+            graph_error_type(report.get_error().unwrap()),
+            GraphErrorType::BudgetExceeded
+        ));
+        assert_eq!(
+            *alerts.lock().unwrap(),
+            vec![AlertKind::BudgetExceeded, AlertKind::RunFailed]
+        );
+        assert!(workflow.to_definition().is_err());
+
+        // a spent budget doesn't let the router start
+        workflow.set_budget(Some(Budget::new().max_tokens(Some(0))));
+        let report = run(&mut workflow);
+        assert_eq!(report.get_node(router).unwrap().usage, None);
+        assert!(matches!(
+            graph_error_type(&report.into_result().unwrap_err()),
+            GraphErrorType::BudgetExceeded
+        ));
+
+        // the usage of a node is the one of all the requests of its execution, like a repair
+        let client = DeepSeekClient::new(DEEPSEEK_API_URL, DeepSeekModel::DeepseekChat)
+            .response_format(Some(ResponseFormat::Json));
+        let node = AINode::new(AIService::new_deepseek(client.clone()));
+        let repair = AINode::json_repair_prompt(&node.check_output("{").unwrap_err());
+        let prompt = Chat::new(Role::User, "\nrefund\n".to_string());
+        let retry = vec![
+            prompt.clone(),
+            Chat::new(Role::Assistant, "{".to_string()),
+            Chat::new(Role::User, format!("\n{}\n", repair)),
+        ];
+        let overrides = RequestOverrides::default();
+        let mut workflow = Workflow::new()
+            .recording(Some(Recording::replay(vec![
+                client.exchange(&vec![prompt], &overrides, "{"),
+                client.exchange(&retry, &overrides, "{\"refund\": true}"),
+            ])))
+            .budget(Some(Budget::new().max_tokens(Some(100))));
+        let start = workflow.add_node(Worknode::new(Worknodecore::Start));
+        let node = workflow.add_node(Worknode::new(Worknodecore::AINode(node)));
+        let end = workflow.add_node(Worknode::new(Worknodecore::End));
+        workflow.add_edge(start, node).unwrap();
+        workflow.add_edge(node, end).unwrap();
+        let report = run(&mut workflow);
+        assert_eq!(report.get_output(), Some("{\"refund\": true}"));
+        let usage = report.get_node(node).unwrap().usage.unwrap();
+        assert_eq!(usage.get_total_tokens(), 4);
+        assert_eq!(report.get_ledger().total().usage.get_total_tokens(), 4);
+    }
+
+    #[derive(Debug, Default)]
+    struct Redact {
+        failed: std::sync::Mutex<Vec<&'static str>>,
+    }
+
+    impl NodeHook for Redact {
+        fn before_execute(&self, _node: &Worknode, input: &mut String) -> PilotResult<()> {
+            if input.is_empty() {
+                return Err(graph_error(
+                    GraphErrorType::ContextError,
+                    "The input is empty.".to_string(),
+                ));
+            }
+            *input = input.replace("secret", "***");
+            Ok(())
+        }
+        fn after_execute(&self, node: &Worknode, output: &mut String) -> PilotResult<()> {
+            if matches!(node.get_node(), Worknodecore::End) {
+                output.push('.');
+            }
+            Ok(())
+        }
+        fn on_error(&self, node: &Worknode, _error: &PilotError) {
+            self.failed
+                .lock()
+                .unwrap()
+                .push(node.get_node().kind_name());
+        }
+    }
+
+    #[test]
+    fn hooks() {
+        let hook = Arc::new(Redact::default());
+        let mut workflow = Workflow::new().hook(hook.clone());
+        let start = workflow.add_node(Worknode::new(Worknodecore::Start));
+        let end = workflow.add_node(Worknode::new(Worknodecore::End));
+        workflow.add_edge(start, end).unwrap();
+        assert_eq!(workflow.get_hooks().len(), 1);
+        let rt = Runtime::new().unwrap();
+        let output = rt.block_on(workflow.run("my secret".to_string())).unwrap();
+        assert_eq!(output, "my ***.");
+        assert!(hook.failed.lock().unwrap().is_empty());
+
+        // the hook refuses the input of the start node, which fails the run
+        let error = rt.block_on(workflow.run(String::new())).unwrap_err();
+        assert!(matches!(
+            graph_error_type(&error),
+            GraphErrorType::ContextError
+        ));
+        assert_eq!(*hook.failed.lock().unwrap(), vec!["start"]);
+
+        // an approval node refused by a hook doesn't ask for a decision
+        let (sender, mut receiver) = tokio::sync::mpsc::unbounded_channel();
+        let mut workflow = Workflow::new()
+            .hook(Arc::new(NoApprovals))
+            .events(Some(sender));
+        let start = workflow.add_node(Worknode::new(Worknodecore::Start));
+        let approval = workflow.add_node(Worknode::new(Worknodecore::Approval(ApprovalNode::new(
+            "Run {{input}}?",
+        ))));
+        let end = workflow.add_node(Worknode::new(Worknodecore::End));
+        workflow.add_edge(start, approval).unwrap();
+        workflow.add_edge(approval, end).unwrap();
+        let context = RunContext::new();
+        let result = rt.block_on(workflow.run_with_context("ls".to_string(), &context));
+        assert!(result.is_err());
+        assert!(context.approvals().pending().is_empty());
+        while let Ok(event) = receiver.try_recv() {
+            assert!(!matches!(event, RunEvent::ApprovalRequested(_)));
+        }
+    }
+
+    #[derive(Debug)]
+    struct NoApprovals;
+
+    impl NodeHook for NoApprovals {
+        fn before_execute(&self, node: &Worknode, _input: &mut String) -> PilotResult<()> {
+            match node.get_node() {
+                Worknodecore::Approval(_) => Err(graph_error(
+                    GraphErrorType::ContextError,
+                    "No approval is allowed.".to_string(),
+                )),
+                _ => Ok(()),
+            }
+        }
+    }
+
+    #[test]
+    fn deadline() {
+        let mut workflow = Workflow::new().deadline(Some(Duration::from_millis(50)));
+        let start = workflow.add_node(Worknode::new(Worknodecore::Start));
+        // nobody decides the approval, so it waits until the deadline
+        let approval = workflow.add_node(Worknode::new(Worknodecore::Approval(ApprovalNode::new(
+            "Go?",
+        ))));
+        let end = workflow.add_node(Worknode::new(Worknodecore::End));
+        workflow.add_edge(start, approval).unwrap();
+        workflow.add_edge(approval, end).unwrap();
+        let rt = Runtime::new().unwrap();
+        let error = rt.block_on(workflow.run("hi".to_string())).unwrap_err();
+        assert!(matches!(
+            graph_error_type(&error),
+            GraphErrorType::DeadlineExceeded
+        ));
+
+        workflow.set_deadline(Some(Duration::ZERO));
+        let error = rt.block_on(workflow.run("hi".to_string())).unwrap_err();
+        assert!(matches!(
+            graph_error_type(&error),
+            GraphErrorType::DeadlineExceeded
+        ));
+    }
+
+    #[test]
+    fn provider_limits() {
+        let client = DeepSeekClient::new(DEEPSEEK_API_URL, DeepSeekModel::DeepseekChat);
+        let ai_node = |provider: &str| {
+            Worknode::new(Worknodecore::AINode(
+                AINode::new(AIService::new_deepseek(client.clone()))
+                    .provider(Some(provider.to_string())),
+            ))
+        };
+        let mut workflow = Workflow::new().provider_limit("deepseek", 1);
+        let a = workflow.add_node(ai_node("deepseek"));
+        let b = workflow.add_node(ai_node("other"));
+        let c = workflow.add_node(Worknode::new(Worknodecore::Local(LocalNode::default())));
+        let nodes: HashMap<Uuid, Worknode> = workflow
+            .get_nodes()
+            .iter()
+            .map(|node| (node.get_uid(), node.clone()))
+            .collect();
+        let ready = VecDeque::from([a, b, c]);
+        let mut running = HashMap::new();
+        assert_eq!(workflow.next_ready(&ready, &nodes, &running), Some(0));
+        running.insert("deepseek".to_string(), 1);
+        assert_eq!(workflow.next_ready(&ready, &nodes, &running), Some(1));
+        assert_eq!(
+            workflow.next_ready(&VecDeque::from([a]), &nodes, &running),
+            None
+        );
+        workflow.remove_provider_limit("deepseek");
+        assert_eq!(workflow.next_ready(&ready, &nodes, &running), Some(0));
+    }
+
+    /// A subscriber that keeps every span as its name and its fields, for the spans of a
+    /// single thread.
+    #[derive(Clone, Default)]
+    struct SpanRecorder {
+        spans: Arc<std::sync::Mutex<Vec<(&'static tracing::Metadata<'static>, String)>>>,
+        entered: Arc<std::sync::Mutex<Vec<tracing::span::Id>>>,
+    }
+
+    impl SpanRecorder {
+        /// Get the spans of the name.
+        fn spans(&self, name: &str) -> Vec<String> {
+            let spans = self.spans.lock().unwrap();
+            spans
+                .iter()
+                .filter(|(metadata, _)| metadata.name() == name)
+                .map(|(_, span)| span.clone())
+                .collect()
+        }
+    }
+
+    /// The fields of a span, written after its name.
+    struct Fields<'a>(&'a mut String);
+
+    impl tracing::field::Visit for Fields<'_> {
+        fn record_debug(&mut self, field: &tracing::field::Field, value: &dyn std::fmt::Debug) {
+            self.0.push_str(&format!(" {}={:?}", field.name(), value));
+        }
+        fn record_str(&mut self, field: &tracing::field::Field, value: &str) {
+            self.0.push_str(&format!(" {}={}", field.name(), value));
+        }
+    }
+
+    impl tracing::Subscriber for SpanRecorder {
+        fn enabled(&self, _metadata: &tracing::Metadata<'_>) -> bool {
+            true
+        }
+        fn new_span(&self, span: &tracing::span::Attributes<'_>) -> tracing::span::Id {
+            let mut spans = self.spans.lock().unwrap();
+            let mut entry = span.metadata().name().to_string();
+            span.record(&mut Fields(&mut entry));
+            spans.push((span.metadata(), entry));
+            tracing::span::Id::from_u64(spans.len() as u64)
+        }
+        fn record(&self, span: &tracing::span::Id, values: &tracing::span::Record<'_>) {
+            let mut spans = self.spans.lock().unwrap();
+            values.record(&mut Fields(&mut spans[span.into_u64() as usize - 1].1));
+        }
+        fn record_follows_from(&self, _span: &tracing::span::Id, _follows: &tracing::span::Id) {}
+        fn event(&self, _event: &tracing::Event<'_>) {}
+        fn enter(&self, span: &tracing::span::Id) {
+            self.entered.lock().unwrap().push(span.clone());
+        }
+        fn exit(&self, _span: &tracing::span::Id) {
+            self.entered.lock().unwrap().pop();
+        }
+        fn current_span(&self) -> tracing_core::span::Current {
+            match self.entered.lock().unwrap().last() {
+                Some(span) => {
+                    let metadata = self.spans.lock().unwrap()[span.into_u64() as usize - 1].0;
+                    tracing_core::span::Current::new(span.clone(), metadata)
+                }
+                None => tracing_core::span::Current::none(),
+            }
+        }
+    }
+
+    #[test]
+    fn tracing_spans() {
+        let client = DeepSeekClient::new(DEEPSEEK_API_URL, DeepSeekModel::DeepseekChat);
+        let prompt = Chat::new(Role::User, "\nhi\n".to_string());
+        let recording = Recording::replay(vec![client.exchange(
+            &vec![prompt],
+            &RequestOverrides::default(),
+            "Hello",
+        )]);
+        let mut workflow = Workflow::new().recording(Some(recording));
+        let start = workflow.add_node(Worknode::new(Worknodecore::Start));
+        let node = workflow.add_node(Worknode::new(Worknodecore::AINode(
+            AINode::new(AIService::new_deepseek(client)).provider(Some("deepseek".to_string())),
+        )));
+        let end = workflow.add_node(Worknode::new(Worknodecore::End));
+        workflow.add_edge(start, node).unwrap();
+        workflow.add_edge(node, end).unwrap();
+        // the spans of the tasks are kept by the subscriber of the thread
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        let recorder = SpanRecorder::default();
+        let output = tracing::subscriber::with_default(recorder.clone(), || {
+            rt.block_on(workflow.run("hi".to_string()))
+        })
+        .unwrap();
+        assert_eq!(output, "Hello");
+
+        assert_eq!(recorder.spans("workflow.run"), ["workflow.run nodes=3"]);
+        assert_eq!(recorder.spans("worknode").len(), 3);
+        assert!(recorder.spans("worknode").contains(&format!(
+            "worknode node={} kind=ai_node provider=deepseek",
+            node
+        )));
+        assert!(recorder
+            .spans("worknode.attempt")
+            .contains(&"worknode.attempt attempt=1".to_string()));
+        assert_eq!(recorder.spans("ai_node.execute").len(), 1);
+        assert!(recorder.spans("ai_node.execute")[0].ends_with("provider=deepseek"));
+        let request = recorder.spans("deepseek.request");
+        assert_eq!(request.len(), 1);
+        assert!(request[0].contains("replay=true"));
+        assert!(request[0].ends_with("prompt_tokens=1 completion_tokens=1 total_tokens=2"));
+    }
+}
@@ -0,0 +1,258 @@
+//! # Alert
+//!
+//! This module tells someone when a run goes wrong, so the workflows that run unattended,
+//! like the scheduled ones, don't fail silently.
+//!
+//! The `AlertSink`s of a workflow (see `Workflow::alert_sink`) get an `Alert` when:
+//! - `run_failed`: a run fails. A cancelled run is not alerted.
+//! - `budget_exceeded`: a run spends its budget (see [`super::cost::Budget`]), once per run,
+//!   even when the failure is routed to on_error edges.
+//!
+//! The sinks are:
+//! - webhook: a POST of the alert as json to the url, with the headers.
+//! - command: a program run with the arguments, with the alert as json on its stdin.
+//! - callback: a function of the program, which can't be saved to a workflow file.
+//!
+//! The alert is sent to every sink, even when some of them fail. A sink that fails is only
+//! logged with a warning, and never changes the result of the run.
+
+use crate::worknode::notify;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+use uuid::Uuid;
+
+use std::collections::BTreeMap;
+use std::process::Stdio;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// The time to wait for a sink to take an alert.
+const ALERT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// A function of the program that takes the alerts.
+pub type AlertCallback = Arc<dyn Fn(&Alert) + Send + Sync>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+/// The enum of what went wrong.
+pub enum AlertKind {
+    /// The run failed.
+    RunFailed,
+    /// The run spent its budget.
+    BudgetExceeded,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+/// The struct of an alert.
+pub struct Alert {
+    /// What went wrong.
+    pub kind: AlertKind,
+    /// The id of the run.
+    pub run: Uuid,
+    /// The node where it went wrong, if it is known.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub node: Option<Uuid>,
+    /// The description of what went wrong.
+    pub message: String,
+    /// When it went wrong.
+    pub at: DateTime<Utc>,
+}
+
+impl Alert {
+    /// Create a new Alert of the run that happens now.
+    pub fn new(kind: AlertKind, run: Uuid, node: Option<Uuid>, message: String) -> Self {
+        Alert {
+            kind,
+            run,
+            node,
+            message,
+            at: Utc::now(),
+        }
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(tag = "sink", rename_all = "snake_case")]
+/// The enum of where the alerts are sent.
+pub enum AlertSink {
+    /// A POST of the alert as json.
+    Webhook {
+        /// The url of the webhook.
+        url: String,
+        /// The headers of the request, like the authorization.
+        #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+        headers: BTreeMap<String, String>,
+    },
+    /// A program that reads the alert as json on its stdin.
+    Command {
+        /// The program.
+        program: String,
+        /// The arguments of the program.
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        args: Vec<String>,
+    },
+    /// A function of the program.
+    #[serde(skip)]
+    Callback(AlertCallback),
+}
+
+impl std::fmt::Debug for AlertSink {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AlertSink::Webhook { url, headers } => f
+                .debug_struct("Webhook")
+                .field("url", url)
+                .field("headers", headers)
+                .finish(),
+            AlertSink::Command { program, args } => f
+                .debug_struct("Command")
+                .field("program", program)
+                .field("args", args)
+                .finish(),
+            AlertSink::Callback(_) => f.write_str("Callback"),
+        }
+    }
+}
+
+impl AlertSink {
+    /// Create a new webhook sink without headers.
+    pub fn webhook(url: &str) -> Self {
+        AlertSink::Webhook {
+            url: url.to_string(),
+            headers: BTreeMap::new(),
+        }
+    }
+    /// Create a new command sink.
+    pub fn command(program: &str, args: Vec<String>) -> Self {
+        AlertSink::Command {
+            program: program.to_string(),
+            args,
+        }
+    }
+    /// Create a new callback sink.
+    pub fn callback<F: Fn(&Alert) + Send + Sync + 'static>(callback: F) -> Self {
+        AlertSink::Callback(Arc::new(callback))
+    }
+    /// Get the name of the sink.
+    pub fn name(&self) -> &'static str {
+        match self {
+            AlertSink::Webhook { .. } => "webhook",
+            AlertSink::Command { .. } => "command",
+            AlertSink::Callback(_) => "callback",
+        }
+    }
+    /// Send the alert to the sink. The error is a message of what failed.
+    pub async fn send(&self, alert: &Alert) -> Result<(), String> {
+        // an alert only holds strings, uids and a time
+        let body = serde_json::to_value(alert).unwrap();
+        match self {
+            AlertSink::Webhook { url, headers } => {
+                // the client only fails to build without a tls backend
+                let client = reqwest::Client::builder()
+                    .timeout(ALERT_TIMEOUT)
+                    .build()
+                    .unwrap();
+                notify::post(&client, url, headers, body).await
+            }
+            AlertSink::Command { program, args } => {
+                let run = async {
+                    let mut child = Command::new(program)
+                        .args(args)
+                        .stdin(Stdio::piped())
+                        .stdout(Stdio::null())
+                        .kill_on_drop(true)
+                        .spawn()
+                        .map_err(|e| format!("Failed to start {}. {}", program, e))?;
+                    // the stdin is piped, so it is there
+                    let mut stdin = child.stdin.take().unwrap();
+                    stdin
+                        .write_all(body.to_string().as_bytes())
+                        .await
+                        .map_err(|e| format!("Failed to write the alert to {}. {}", program, e))?;
+                    drop(stdin);
+                    let status = child
+                        .wait()
+                        .await
+                        .map_err(|e| format!("Failed to wait for {}. {}", program, e))?;
+                    if status.success() {
+                        Ok(())
+                    } else {
+                        Err(format!("{} exited with {}.", program, status))
+                    }
+                };
+                tokio::time::timeout(ALERT_TIMEOUT, run)
+                    .await
+                    .unwrap_or_else(|_| Err(format!("{} didn't exit in time.", program)))
+            }
+            AlertSink::Callback(callback) => {
+                callback(alert);
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Send the alert to all the sinks, and log the ones that fail.
+pub(super) async fn send_all(sinks: &[AlertSink], alert: &Alert) {
+    for sink in sinks {
+        if let Err(e) = sink.send(alert).await {
+            log::warn!(
+                "Failed to send the alert to the {} sink. {}",
+                sink.name(),
+                e
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use tokio::runtime::Runtime;
+
+    use std::sync::Mutex;
+
+    #[test]
+    fn send_to_sinks() {
+        let alert = Alert::new(
+            AlertKind::BudgetExceeded,
+            Uuid::new_v4(),
+            Some(Uuid::new_v4()),
+            "The budget of the run is spent.".to_string(),
+        );
+        let path = std::env::temp_dir().join(format!("aipilot-alert-{}.json", Uuid::new_v4()));
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let sinks = vec![
+            AlertSink::command("false", Vec::new()),
+            AlertSink::command(
+                "sh",
+                vec!["-c".to_string(), format!("cat > {}", path.display())],
+            ),
+            AlertSink::callback({
+                let received = received.clone();
+                move |alert: &Alert| received.lock().unwrap().push(alert.clone())
+            }),
+        ];
+        let rt = Runtime::new().unwrap();
+        assert!(rt.block_on(sinks[0].send(&alert)).is_err());
+        // the failing sink doesn't stop the others
+        rt.block_on(send_all(&sinks, &alert));
+        let written: Alert =
+            serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(written, alert);
+        assert_eq!(*received.lock().unwrap(), vec![alert]);
+
+        let json = serde_json::to_value(&sinks[1]).unwrap();
+        assert_eq!(json["sink"], "command");
+        assert!(serde_json::to_value(&sinks[2]).is_err());
+        let sink: AlertSink =
+            serde_json::from_value(serde_json::json!({"sink": "webhook", "url": "http://x"}))
+                .unwrap();
+        assert_eq!(sink.name(), "webhook");
+    }
+}
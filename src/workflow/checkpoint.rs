@@ -0,0 +1,162 @@
+//! # Checkpoint
+//!
+//! This module saves the state of a workflow run, so a run that crashed or was interrupted can
+//! continue from the last completed node instead of running every node again.
+//!
+//! When a workflow has a checkpoint path, a checkpoint is written to the path after every
+//! completed node. It holds the input of the run, the outputs of the completed nodes, the
+//! nodes that were pending, the values of the run context and the histories of the completed
+//! AI and agent nodes, the skipped nodes, the edges followed after the approval nodes and
+//! the failed nodes, the labels chosen by the router nodes and the moments the started delay
+//! nodes wake at. A checkpoint is also written when a delay node starts, so its moment is
+//! kept however long it waits. `Workflow::resume` takes the checkpoint and runs the remaining
+//! nodes.
+
+use super::{Edge, EdgeKind};
+use crate::error::storage_error::{StorageError, StorageErrorType};
+use crate::error::{PilotError, PilotErrorType, PilotResult};
+use crate::worknode::ai_node::Chat;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use std::collections::HashMap;
+use std::path::Path;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+/// The struct of the state of a workflow run.
+pub struct Checkpoint {
+    /// The input of the run.
+    pub(super) input: String,
+    /// The outputs of the completed nodes.
+    pub(super) outputs: HashMap<Uuid, String>,
+    /// The nodes that were ready or running when the checkpoint was taken.
+    pub(super) pending: Vec<Uuid>,
+    /// The values of the run context.
+    pub(super) context: HashMap<String, serde_json::Value>,
+    /// The histories of the completed AI and agent nodes.
+    pub(super) histories: HashMap<Uuid, Vec<Chat>>,
+    /// The nodes that are skipped because none of their incoming edges is followed.
+    #[serde(default)]
+    pub(super) skipped: Vec<Uuid>,
+    /// The kind of the outgoing edges followed after the completed nodes, when it is not
+    /// the normal one.
+    #[serde(default)]
+    pub(super) routes: HashMap<Uuid, EdgeKind>,
+    /// The labels chosen by the completed router nodes.
+    #[serde(default)]
+    pub(super) labels: HashMap<Uuid, String>,
+    /// The moments the started delay nodes wake at.
+    #[serde(default)]
+    pub(super) wakes: HashMap<Uuid, DateTime<Utc>>,
+    /// The id of the run, which a resumed run keeps.
+    #[serde(default)]
+    pub(super) run: Uuid,
+}
+
+impl Checkpoint {
+    /// Get the id of the run.
+    pub fn get_run(&self) -> Uuid {
+        self.run
+    }
+    /// Get the input of the run.
+    pub fn get_input(&self) -> &str {
+        &self.input
+    }
+    /// Get the outputs of the completed nodes.
+    pub fn get_outputs(&self) -> &HashMap<Uuid, String> {
+        &self.outputs
+    }
+    /// Whether the node was completed.
+    pub fn is_completed(&self, uid: Uuid) -> bool {
+        self.outputs.contains_key(&uid)
+    }
+    /// Whether the node was completed or skipped.
+    pub fn is_finished(&self, uid: Uuid) -> bool {
+        self.outputs.contains_key(&uid) || self.skipped.contains(&uid)
+    }
+    /// Whether the edge is followed: its source node was completed, the kind of the edge is
+    /// the one followed after the source node, and the label of a route edge is the one
+    /// chosen by the source router node.
+    pub fn is_followed(&self, edge: &Edge) -> bool {
+        self.outputs.contains_key(&edge.get_from())
+            && self
+                .routes
+                .get(&edge.get_from())
+                .copied()
+                .unwrap_or_default()
+                == edge.get_kind()
+            && edge.get_route().is_none_or(|label| {
+                self.labels.get(&edge.get_from()).map(String::as_str) == Some(label)
+            })
+    }
+    /// Get the labels chosen by the completed router nodes.
+    pub fn get_labels(&self) -> &HashMap<Uuid, String> {
+        &self.labels
+    }
+    /// Get the moments the started delay nodes wake at.
+    pub fn get_wakes(&self) -> &HashMap<Uuid, DateTime<Utc>> {
+        &self.wakes
+    }
+    /// Get the nodes that are skipped.
+    pub fn get_skipped(&self) -> &Vec<Uuid> {
+        &self.skipped
+    }
+    /// Get the nodes that were ready or running when the checkpoint was taken.
+    pub fn get_pending(&self) -> &Vec<Uuid> {
+        &self.pending
+    }
+    /// Get the values of the run context.
+    pub fn get_context(&self) -> &HashMap<String, serde_json::Value> {
+        &self.context
+    }
+    /// Get the histories of the completed AI and agent nodes.
+    pub fn get_histories(&self) -> &HashMap<Uuid, Vec<Chat>> {
+        &self.histories
+    }
+    /// Save the checkpoint to a json file. The file is written to a temporary file first and
+    /// then renamed, so a crash while saving doesn't leave a broken checkpoint.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> PilotResult<()> {
+        let path = path.as_ref();
+        let text = serde_json::to_string(self).map_err(|e| {
+            checkpoint_error(
+                StorageErrorType::InvalidData,
+                format!("Can't write the checkpoint. {}", e),
+            )
+        })?;
+        let temporary = path.with_extension("tmp");
+        std::fs::write(&temporary, text)
+            .and_then(|_| std::fs::rename(&temporary, path))
+            .map_err(|e| {
+                checkpoint_error(
+                    StorageErrorType::WriteError,
+                    format!("Can't write the checkpoint file {}. {}", path.display(), e),
+                )
+            })
+    }
+    /// Load a checkpoint from a json file.
+    pub fn load<P: AsRef<Path>>(path: P) -> PilotResult<Self> {
+        let path = path.as_ref();
+        let text = std::fs::read_to_string(path).map_err(|e| {
+            checkpoint_error(
+                StorageErrorType::ReadError,
+                format!("Can't read the checkpoint file {}. {}", path.display(), e),
+            )
+        })?;
+        serde_json::from_str(&text).map_err(|e| {
+            checkpoint_error(
+                StorageErrorType::InvalidData,
+                format!("Can't read the checkpoint. {}", e),
+            )
+        })
+    }
+}
+
+/// Create a PilotError of a checkpoint.
+fn checkpoint_error(error_type: StorageErrorType, message: String) -> PilotError {
+    PilotError::new(
+        PilotErrorType::StorageErr(StorageError::new(error_type, message)),
+        "The checkpoint failed".to_string(),
+    )
+}
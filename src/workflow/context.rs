@@ -0,0 +1,181 @@
+//! # Context
+//!
+//! This module defines the run context, a key-value blackboard shared by all nodes of one run.
+//!
+//! A node reads the context through its templates: every key is available as the variable
+//! `context.<key>`, so a prompt can say `{{context.customer}}`. A node writes its output into
+//! the context when it has a context key (see [`crate::worknode::Worknode::context_key`]). The
+//! program can also read and write the context before and after a run, like to seed some
+//! values or to collect what the nodes found.
+//!
+//! The context also holds the [`Approvals`] of the run, where the approval nodes wait for the
+//! decisions of a human, and the [`UserInput`] that answers the user nodes.
+//!
+//! The values are stored as json, so any serde type can be put in the context and taken out
+//! again. A context is cheap to clone, and the clones share the same values.
+
+use crate::error::graph_error::{GraphError, GraphErrorType};
+use crate::error::{PilotError, PilotErrorType, PilotResult};
+use crate::template::Variables;
+use crate::worknode::approval::Approvals;
+use crate::worknode::user::UserInput;
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use std::collections::HashMap;
+use std::sync::{Arc, PoisonError, RwLock};
+
+/// The prefix of the template variables of the context keys.
+pub const CONTEXT_VARIABLE_PREFIX: &str = "context.";
+
+#[derive(Debug, Clone, Default)]
+/// The struct of the values shared by the nodes of a run.
+pub struct RunContext {
+    values: Arc<RwLock<HashMap<String, serde_json::Value>>>,
+    /// The decisions that the approval nodes of the run wait for.
+    approvals: Approvals,
+    /// The source of the answers of the user nodes, instead of the terminal.
+    user_input: Arc<RwLock<Option<UserInput>>>,
+}
+
+impl RunContext {
+    /// Create a new empty RunContext.
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Get the approvals of the run, to see the pending requests and to decide them.
+    pub fn approvals(&self) -> &Approvals {
+        &self.approvals
+    }
+    /// Set the source of the answers of the user nodes. Without it, they ask in the terminal.
+    pub fn set_user_input(&self, user_input: Option<UserInput>) {
+        *self
+            .user_input
+            .write()
+            .unwrap_or_else(PoisonError::into_inner) = user_input;
+    }
+    /// Get the source of the answers of the user nodes.
+    pub fn get_user_input(&self) -> Option<UserInput> {
+        self.user_input
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+    /// Put a value in the context. A value with the same key is replaced.
+    pub fn set<T: Serialize>(&self, key: &str, value: T) -> PilotResult<()> {
+        let value = serde_json::to_value(value).map_err(|e| {
+            context_error(format!(
+                "Can't store the value of {} in the context. {}",
+                key, e
+            ))
+        })?;
+        self.set_value(key, value);
+        Ok(())
+    }
+    /// Take a value out of the context. Return `None` if there is no value of the key, and an
+    /// error if the value is not of the type.
+    pub fn get<T: DeserializeOwned>(&self, key: &str) -> PilotResult<Option<T>> {
+        match self.get_value(key) {
+            Some(value) => serde_json::from_value(value).map(Some).map_err(|e| {
+                context_error(format!("The value of {} has another type. {}", key, e))
+            }),
+            None => Ok(None),
+        }
+    }
+    /// Put a json value in the context.
+    pub fn set_value(&self, key: &str, value: serde_json::Value) {
+        self.write().insert(key.to_string(), value);
+    }
+    /// Get the json value of the key.
+    pub fn get_value(&self, key: &str) -> Option<serde_json::Value> {
+        self.read().get(key).cloned()
+    }
+    /// Change the value of the key in place, so the nodes running at the same time don't lose
+    /// each other's changes. The function gets the old value, if any.
+    pub fn update<F>(&self, key: &str, f: F)
+    where
+        F: FnOnce(Option<serde_json::Value>) -> serde_json::Value,
+    {
+        let mut values = self.write();
+        let value = f(values.remove(key));
+        values.insert(key.to_string(), value);
+    }
+    /// Remove a value from the context.
+    pub fn remove(&self, key: &str) -> Option<serde_json::Value> {
+        self.write().remove(key)
+    }
+    /// Whether the context has a value of the key.
+    pub fn contains(&self, key: &str) -> bool {
+        self.read().contains_key(key)
+    }
+    /// Get the keys of the context, sorted.
+    pub fn keys(&self) -> Vec<String> {
+        let mut keys: Vec<String> = self.read().keys().cloned().collect();
+        keys.sort();
+        keys
+    }
+    /// Get a copy of all values.
+    pub fn snapshot(&self) -> HashMap<String, serde_json::Value> {
+        self.read().clone()
+    }
+    /// Get the values as template variables named `context.<key>`. A string is given as it
+    /// is, and the other values as json.
+    pub fn to_variables(&self) -> Variables {
+        self.read()
+            .iter()
+            .map(|(key, value)| {
+                let value = match value {
+                    serde_json::Value::String(text) => text.clone(),
+                    value => value.to_string(),
+                };
+                (format!("{}{}", CONTEXT_VARIABLE_PREFIX, key), value)
+            })
+            .collect()
+    }
+    /// Lock the values for reading. A panic in another node doesn't make the values invalid,
+    /// so a poisoned lock is still used.
+    fn read(&self) -> std::sync::RwLockReadGuard<'_, HashMap<String, serde_json::Value>> {
+        self.values.read().unwrap_or_else(PoisonError::into_inner)
+    }
+    /// Lock the values for writing.
+    fn write(&self) -> std::sync::RwLockWriteGuard<'_, HashMap<String, serde_json::Value>> {
+        self.values.write().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// Create a PilotError of the run context.
+fn context_error(message: String) -> PilotError {
+    PilotError::new(
+        PilotErrorType::GraphErr(GraphError::new(GraphErrorType::ContextError, message)),
+        "The run context failed".to_string(),
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn typed_values() {
+        let context = RunContext::new();
+        context.set("entities", vec!["cat", "dog"]).unwrap();
+        context.set("name", "Tom").unwrap();
+        let shared = context.clone();
+        shared.update("cost", |cost| {
+            serde_json::json!(cost.and_then(|c| c.as_u64()).unwrap_or(0) + 3)
+        });
+        assert_eq!(
+            context.get::<Vec<String>>("entities").unwrap().unwrap(),
+            vec!["cat", "dog"]
+        );
+        assert_eq!(context.get::<u64>("cost").unwrap(), Some(3));
+        assert!(context.get::<u64>("name").is_err());
+        assert!(context.get::<u64>("missing").unwrap().is_none());
+        assert_eq!(context.keys(), vec!["cost", "entities", "name"]);
+
+        let variables = context.to_variables();
+        assert_eq!(variables["context.name"], "Tom");
+        assert_eq!(variables["context.entities"], r#"["cat","dog"]"#);
+    }
+}
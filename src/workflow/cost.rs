@@ -0,0 +1,286 @@
+//! # Cost
+//!
+//! This module adds up the token usage and the cost of a run by node, by provider and for
+//! the whole run, so the spending can be put on the steps of a workflow.
+//!
+//! The `CostLedger` of a run is built from the trace of its report (see [`super::run`]): every
+//! node that called an AI service adds its usage, and its cost when the workflow has the
+//! `Pricing` of its provider. The ledgers of several runs can be merged to follow the spending
+//! of a workflow over time.
+//!
+//! A `Budget` caps the tokens and the cost of a run, checked by the workflow before and after
+//! every node that calls an AI service (see [`super`]). An agent node also checks it between
+//! its steps with a `BudgetGuard`. The cost only counts the usage whose price is known.
+
+use super::run::{NodeReport, Pricing};
+use crate::worknode::ai_node::deepseek::DeepSeekUsage;
+
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use std::collections::BTreeMap;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+/// The struct of the usage and the cost of some executions.
+pub struct CostEntry {
+    /// The number of executions that used an AI service.
+    pub executions: usize,
+    /// The token usage of the executions.
+    pub usage: DeepSeekUsage,
+    /// The cost of the usage whose price is known, or `None` if no price is known.
+    pub cost: Option<f64>,
+}
+
+impl CostEntry {
+    /// Add the usage and the cost of an execution.
+    pub fn add(&mut self, usage: DeepSeekUsage, cost: Option<f64>) {
+        self.executions += 1;
+        self.usage = self.usage + usage;
+        self.add_cost(cost);
+    }
+    /// Add all the executions of another entry.
+    pub fn merge(&mut self, other: &CostEntry) {
+        self.executions += other.executions;
+        self.usage = self.usage + other.usage;
+        self.add_cost(other.cost);
+    }
+    /// Add a cost, which is known if either is known.
+    fn add_cost(&mut self, cost: Option<f64>) {
+        self.cost = match (self.cost, cost) {
+            (Some(a), Some(b)) => Some(a + b),
+            (a, b) => a.or(b),
+        };
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+/// The struct of the most that a run may spend.
+pub struct Budget {
+    /// The max number of tokens.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_tokens: Option<i64>,
+    /// The max cost.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_cost: Option<f64>,
+}
+
+impl Budget {
+    /// Create a new Budget without any cap.
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Set the max number of tokens as builder.
+    pub fn max_tokens(mut self, max_tokens: Option<i64>) -> Self {
+        self.max_tokens = max_tokens;
+        self
+    }
+    /// Set the max cost as builder.
+    pub fn max_cost(mut self, max_cost: Option<f64>) -> Self {
+        self.max_cost = max_cost;
+        self
+    }
+    /// Whether a node may still start after the spending, which is under every cap.
+    pub fn allows(&self, spent: &CostEntry) -> bool {
+        self.max_tokens
+            .is_none_or(|max| spent.usage.get_total_tokens() < max)
+            && self
+                .max_cost
+                .is_none_or(|max| spent.cost.unwrap_or_default() < max)
+    }
+    /// Whether the spending is over a cap.
+    pub fn is_exceeded(&self, spent: &CostEntry) -> bool {
+        self.max_tokens
+            .is_some_and(|max| spent.usage.get_total_tokens() > max)
+            || self
+                .max_cost
+                .is_some_and(|max| spent.cost.unwrap_or_default() > max)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+/// The struct of the budget left to a node while it runs, so an agent node can stop between
+/// its steps.
+pub struct BudgetGuard {
+    /// The budget of the run.
+    pub budget: Budget,
+    /// What the run spent when the node started.
+    pub spent: CostEntry,
+    /// The price of the provider of the node.
+    pub price: Option<Pricing>,
+    /// The total usage of the node when it started, so only what it used since is added.
+    pub since: DeepSeekUsage,
+}
+
+impl BudgetGuard {
+    /// Whether the node may send another request, with the total usage of the node now.
+    pub fn allows(&self, total: DeepSeekUsage) -> bool {
+        let usage = total - self.since;
+        let mut spent = self.spent;
+        spent.add(usage, self.price.map(|price| price.cost(&usage)));
+        self.budget.allows(&spent)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+/// The struct of the usage and the cost of a node.
+pub struct NodeCost {
+    /// The type of the node, like `ai_node`.
+    pub kind: String,
+    /// The provider of the AI service of the node.
+    pub provider: Option<String>,
+    /// The usage and the cost of the node.
+    #[serde(flatten)]
+    pub entry: CostEntry,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+/// The struct of the usage and the cost of the nodes of some runs.
+pub struct CostLedger {
+    /// The usage and the cost by node.
+    nodes: BTreeMap<Uuid, NodeCost>,
+}
+
+impl CostLedger {
+    /// Create a new empty CostLedger.
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Build the ledger of the trace of a run.
+    pub fn from_trace(trace: &[NodeReport]) -> Self {
+        let mut ledger = CostLedger::new();
+        for report in trace {
+            if let Some(usage) = report.usage {
+                ledger.record(
+                    report.node,
+                    &report.kind,
+                    report.provider.as_deref(),
+                    usage,
+                    report.cost,
+                );
+            }
+        }
+        ledger
+    }
+    /// Add the usage and the cost of an execution of the node.
+    pub fn record(
+        &mut self,
+        node: Uuid,
+        kind: &str,
+        provider: Option<&str>,
+        usage: DeepSeekUsage,
+        cost: Option<f64>,
+    ) {
+        self.nodes
+            .entry(node)
+            .or_insert_with(|| NodeCost {
+                kind: kind.to_string(),
+                provider: provider.map(str::to_string),
+                entry: CostEntry::default(),
+            })
+            .entry
+            .add(usage, cost);
+    }
+    /// Add all the nodes of another ledger, like the one of a later run of the workflow.
+    pub fn merge(&mut self, other: &CostLedger) {
+        for (node, cost) in &other.nodes {
+            self.nodes
+                .entry(*node)
+                .or_insert_with(|| NodeCost {
+                    entry: CostEntry::default(),
+                    ..cost.clone()
+                })
+                .entry
+                .merge(&cost.entry);
+        }
+    }
+    /// Get the usage and the cost of the node.
+    pub fn get_node(&self, node: Uuid) -> Option<&NodeCost> {
+        self.nodes.get(&node)
+    }
+    /// Get the usage and the cost of all nodes.
+    pub fn get_nodes(&self) -> &BTreeMap<Uuid, NodeCost> {
+        &self.nodes
+    }
+    /// Get the usage and the cost of the nodes of the provider.
+    pub fn get_provider(&self, provider: &str) -> CostEntry {
+        self.by_provider().remove(provider).unwrap_or_default()
+    }
+    /// Get the usage and the cost by provider. The nodes without a provider are left out.
+    pub fn by_provider(&self) -> BTreeMap<String, CostEntry> {
+        let mut providers: BTreeMap<String, CostEntry> = BTreeMap::new();
+        for cost in self.nodes.values() {
+            if let Some(provider) = &cost.provider {
+                providers
+                    .entry(provider.clone())
+                    .or_default()
+                    .merge(&cost.entry);
+            }
+        }
+        providers
+    }
+    /// Get the usage and the cost of all nodes.
+    pub fn total(&self) -> CostEntry {
+        let mut total = CostEntry::default();
+        for cost in self.nodes.values() {
+            total.merge(&cost.entry);
+        }
+        total
+    }
+    /// Whether no node used an AI service.
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn usage(prompt: i64, completion: i64) -> DeepSeekUsage {
+        serde_json::from_value(serde_json::json!({
+            "completion_tokens": completion,
+            "prompt_tokens": prompt,
+            "prompt_cache_hit_tokens": 0,
+            "prompt_cache_miss_tokens": prompt,
+            "total_tokens": prompt + completion
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn ledger_by_node_and_provider() {
+        let (a, b, c) = (Uuid::new_v4(), Uuid::new_v4(), Uuid::new_v4());
+        let mut ledger = CostLedger::new();
+        ledger.record(a, "ai_node", Some("deepseek"), usage(100, 10), Some(0.5));
+        ledger.record(b, "agent", Some("deepseek"), usage(200, 20), None);
+        ledger.record(c, "ai_node", Some("local"), usage(50, 5), None);
+
+        let deepseek = ledger.get_provider("deepseek");
+        assert_eq!(deepseek.executions, 2);
+        assert_eq!(deepseek.usage.get_total_tokens(), 330);
+        assert_eq!(deepseek.cost, Some(0.5));
+        assert_eq!(ledger.get_provider("local").cost, None);
+        assert_eq!(ledger.get_provider("missing"), CostEntry::default());
+
+        let mut later = CostLedger::new();
+        later.record(a, "ai_node", Some("deepseek"), usage(100, 10), Some(0.25));
+        ledger.merge(&later);
+        let node = ledger.get_node(a).unwrap();
+        assert_eq!(node.entry.executions, 2);
+        assert_eq!(node.entry.cost, Some(0.75));
+        assert_eq!(ledger.total().usage.get_total_tokens(), 495);
+
+        let budget = Budget::new().max_tokens(Some(495)).max_cost(Some(1.0));
+        assert!(!budget.allows(&ledger.total()));
+        assert!(!budget.is_exceeded(&ledger.total()));
+        assert!(Budget::new()
+            .max_cost(Some(0.5))
+            .is_exceeded(&ledger.total()));
+        assert!(Budget::new().allows(&ledger.total()));
+
+        let json = serde_json::to_value(&ledger).unwrap();
+        assert_eq!(json["nodes"][a.to_string()]["provider"], "deepseek");
+        assert_eq!(json["nodes"][a.to_string()]["executions"], 2);
+        assert_eq!(serde_json::from_value::<CostLedger>(json).unwrap(), ledger);
+    }
+}
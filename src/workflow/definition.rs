@@ -0,0 +1,1344 @@
+//! # Definition
+//!
+//! This module saves a workflow to a YAML or JSON file and loads it back.
+//!
+//! The file holds the configuration of the nodes and the edges, but not the runtime state like
+//! the histories of the AI nodes. The AI services, the embeddings clients, the tools and the
+//! reducers can't be written to a file (an AI service or an embeddings client holds an api key,
+//! and a tool or a reducer is a closure), so the file refers to them by name, and the names are resolved through a `Registry` when the
+//! workflow is loaded.
+//!
+//! ## Environment variables
+//!
+//! A file loaded with `Workflow::load_with_env` may refer to the environment variables in its
+//! strings, like `role: You answer in ${LANGUAGE:-English}`. `${NAME}` is the value of the
+//! variable, which must be set, `${NAME:-default}` is the default when it is not set, and
+//! `$${` is a plain `${`. A string that is only one variable, like `max_parallelism:
+//! ${PARALLELISM}`, takes the number or the boolean of the value. With the providers, the
+//! tools and the stores of an `aipilot.toml` (see [`crate::config`]), a workflow runs from
+//! its two files with `Config::load_workflow`.
+//!
+//! ## Version
+//!
+//! A file has the version of its format. When the format of a node changes, the version goes
+//! up and a migration is added, which upgrades a definition of the older version to the next
+//! one. A file is upgraded through all the migrations of its version when it is loaded, so
+//! the saved workflows keep working. A file without a version is of version 1, and a file of a
+//! newer version than this crate knows is refused instead of being read wrong.
+//!
+//! ```yaml
+//! version: 1
+//! max_parallelism: 8
+//! nodes:
+//!   - uid: 8d1f...
+//!     type: start
+//!   - uid: 2b7c...
+//!     type: ai_node
+//!     provider: deepseek
+//!     role: You are a helpful assistant
+//!   - uid: 51a0...
+//!     type: end
+//! edges:
+//!   - from: 8d1f...
+//!     to: 2b7c...
+//!   - from: 2b7c...
+//!     to: 51a0...
+//! ```
+
+use super::alert::AlertSink;
+use super::cost::Budget;
+use super::{EdgeKind, Workflow};
+use crate::error::graph_error::{GraphError, GraphErrorType};
+use crate::error::{PilotError, PilotErrorType, PilotResult};
+use crate::ingest::IngestOptions;
+use crate::vector_store::VectorStore;
+use crate::worknode::agent::Agent;
+use crate::worknode::ai_node::embedding::EmbeddingClient;
+use crate::worknode::ai_node::rerank::RerankClient;
+use crate::worknode::ai_node::{AINode, AIService, HistoryPolicy, SamplingParams, ToolRegistry};
+use crate::worknode::approval::ApprovalNode;
+use crate::worknode::assert::AssertNode;
+use crate::worknode::cache::{CacheNode, CacheStore};
+use crate::worknode::chunker::ChunkerNode;
+use crate::worknode::delay::DelayNode;
+use crate::worknode::embed::EmbedNode;
+use crate::worknode::file::{FileReadNode, FileWriteNode};
+use crate::worknode::ingest::IngestNode;
+use crate::worknode::join::{JoinNode, JoinStrategy};
+use crate::worknode::load::LoadNode;
+use crate::worknode::local::sandbox::Sandbox;
+use crate::worknode::local::LocalNode;
+use crate::worknode::map::MapNode;
+use crate::worknode::notify::NotifyNode;
+use crate::worknode::planner::PlannerNode;
+use crate::worknode::reduce::{ReduceNode, ReduceStrategy, Reducer};
+use crate::worknode::rerank::{RerankNode, RerankOptions, Reranker};
+use crate::worknode::retrieve::{RetrieveNode, RetrieveOptions};
+use crate::worknode::retry::RetryPolicy;
+use crate::worknode::router::{Route, RouterNode};
+use crate::worknode::script::ScriptNode;
+use crate::worknode::tool::ToolNode;
+use crate::worknode::transform::JsonTransformNode;
+use crate::worknode::user::UserNode;
+use crate::worknode::wasm::WasmNode;
+use crate::worknode::{Worknode, Worknodecore};
+
+use serde::{Deserialize, Serialize};
+use serde_yaml::with::singleton_map_recursive;
+use uuid::Uuid;
+
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+#[derive(Debug, Clone, Default)]
+/// The struct of the AI services, the embeddings and rerank clients, the vector stores, the
+/// tools and the reducers that a workflow file refers to by name.
+pub struct Registry {
+    /// The AI services by name.
+    services: HashMap<String, AIService>,
+    /// The tools, by their own names.
+    tools: ToolRegistry,
+    /// The reducers, by their own names.
+    reducers: HashMap<String, Reducer>,
+    /// The clients of the embeddings apis by name.
+    embedders: HashMap<String, EmbeddingClient>,
+    /// The vector stores by name.
+    vector_stores: HashMap<String, Arc<dyn VectorStore>>,
+    /// The clients of the rerank apis by name.
+    rerankers: HashMap<String, RerankClient>,
+}
+
+impl Registry {
+    /// Create a new empty Registry.
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Register an AI service as builder.
+    pub fn service(mut self, name: &str, service: AIService) -> Self {
+        self.register_service(name, service);
+        self
+    }
+    /// Register an AI service. A service with the same name is replaced.
+    pub fn register_service(&mut self, name: &str, service: AIService) {
+        self.services.insert(name.to_string(), service);
+    }
+    /// Get an AI service by its name.
+    pub fn get_service(&self, name: &str) -> Option<&AIService> {
+        self.services.get(name)
+    }
+    /// Register a client of an embeddings api as builder.
+    pub fn embedder(mut self, name: &str, client: EmbeddingClient) -> Self {
+        self.register_embedder(name, client);
+        self
+    }
+    /// Register a client of an embeddings api. A client with the same name is replaced.
+    pub fn register_embedder(&mut self, name: &str, client: EmbeddingClient) {
+        self.embedders.insert(name.to_string(), client);
+    }
+    /// Get a client of an embeddings api by its name.
+    pub fn get_embedder(&self, name: &str) -> Option<&EmbeddingClient> {
+        self.embedders.get(name)
+    }
+    /// Register a client of a rerank api as builder.
+    pub fn reranker(mut self, name: &str, client: RerankClient) -> Self {
+        self.register_reranker(name, client);
+        self
+    }
+    /// Register a client of a rerank api. A client with the same name is replaced.
+    pub fn register_reranker(&mut self, name: &str, client: RerankClient) {
+        self.rerankers.insert(name.to_string(), client);
+    }
+    /// Get a client of a rerank api by its name.
+    pub fn get_reranker(&self, name: &str) -> Option<&RerankClient> {
+        self.rerankers.get(name)
+    }
+    /// Register a vector store as builder.
+    pub fn vector_store(mut self, name: &str, store: Arc<dyn VectorStore>) -> Self {
+        self.register_vector_store(name, store);
+        self
+    }
+    /// Register a vector store. A store with the same name is replaced.
+    pub fn register_vector_store(&mut self, name: &str, store: Arc<dyn VectorStore>) {
+        self.vector_stores.insert(name.to_string(), store);
+    }
+    /// Get a vector store by its name.
+    pub fn get_vector_store(&self, name: &str) -> Option<&Arc<dyn VectorStore>> {
+        self.vector_stores.get(name)
+    }
+    /// Set the tools as builder.
+    pub fn tools(mut self, tools: ToolRegistry) -> Self {
+        self.tools = tools;
+        self
+    }
+    /// Get the tools.
+    pub fn get_tools(&self) -> &ToolRegistry {
+        &self.tools
+    }
+    /// Register a reducer as builder.
+    pub fn reducer(mut self, reducer: Reducer) -> Self {
+        self.register_reducer(reducer);
+        self
+    }
+    /// Register a reducer. A reducer with the same name is replaced.
+    pub fn register_reducer(&mut self, reducer: Reducer) {
+        self.reducers
+            .insert(reducer.get_name().to_string(), reducer);
+    }
+    /// Get a reducer by its name.
+    pub fn get_reducer(&self, name: &str) -> Option<&Reducer> {
+        self.reducers.get(name)
+    }
+    /// Get the tools of the names as a new ToolRegistry.
+    fn resolve_tools(&self, names: &[String]) -> PilotResult<ToolRegistry> {
+        let mut tools = ToolRegistry::new();
+        for name in names {
+            match self.tools.get(name) {
+                Some(tool) => tools.insert(tool.clone()),
+                None => {
+                    return Err(definition_error(format!(
+                        "The tool {} is not in the registry.",
+                        name
+                    )))
+                }
+            }
+        }
+        Ok(tools)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// The enum of the file format of a workflow.
+pub enum DefinitionFormat {
+    /// YAML, for the `.yaml` and `.yml` files.
+    Yaml,
+    /// JSON, for every other file.
+    Json,
+}
+
+impl DefinitionFormat {
+    /// Get the format from the extension of the path.
+    pub fn from_path(path: &Path) -> DefinitionFormat {
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("yaml") | Some("yml") => DefinitionFormat::Yaml,
+            _ => DefinitionFormat::Json,
+        }
+    }
+}
+
+/// The version of the format of the files written by this crate.
+pub const DEFINITION_VERSION: u64 = 1;
+
+/// A migration upgrades a definition of one version to the next version.
+pub type Migration = fn(&mut serde_json::Value) -> PilotResult<()>;
+
+/// The migrations of the format. The one at index `i` upgrades the version `i + 1` to `i + 2`.
+const MIGRATIONS: &[Migration] = &[];
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+/// The struct of a workflow as it is written in a file.
+pub struct WorkflowDefinition {
+    /// The version of the format.
+    #[serde(default = "WorkflowDefinition::first_version")]
+    pub version: u64,
+    /// The max number of nodes running at the same time.
+    #[serde(default = "Workflow::default_max_parallelism")]
+    pub max_parallelism: usize,
+    /// The max number of nodes of each provider running at the same time.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub provider_limits: BTreeMap<String, usize>,
+    /// The wall-clock time that a run may take.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub deadline: Option<Duration>,
+    /// The most that a run may spend on the AI services.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub budget: Option<Budget>,
+    /// The sinks that the alerts of the runs are sent to. The callbacks can't be saved.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub alert_sinks: Vec<AlertSink>,
+    /// The nodes.
+    pub nodes: Vec<NodeDefinition>,
+    /// The edges.
+    #[serde(default)]
+    pub edges: Vec<EdgeDefinition>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+/// The struct of a worknode as it is written in a file.
+pub struct NodeDefinition {
+    /// The uid of the node, which the edges refer to.
+    pub uid: Uuid,
+    /// The configuration of the core part of the node.
+    #[serde(flatten)]
+    pub node: NodeConfig,
+    /// The retry policy of the node.
+    #[serde(default, skip_serializing_if = "is_default_retry")]
+    pub retry: RetryPolicy,
+    /// The key of the run context that the output is stored under.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub context_key: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+/// The enum of the configuration of the core part of a worknode.
+pub enum NodeConfig {
+    /// The start node.
+    Start,
+    /// The end node.
+    End,
+    /// The AI node.
+    #[serde(rename = "ai_node")]
+    AINode(AINodeConfig),
+    /// The local node.
+    Local(LocalNode),
+    /// The user node.
+    User(UserNode),
+    /// The agent node.
+    Agent(AgentConfig),
+    /// The join node.
+    Join {
+        /// The way to merge the outputs.
+        strategy: JoinStrategy,
+    },
+    /// The approval node.
+    Approval {
+        /// The template of the message shown to the human.
+        message: String,
+    },
+    /// The wasm node.
+    Wasm(WasmNode),
+    /// The script node.
+    Script(ScriptNode),
+    /// The file read node.
+    FileRead(FileReadNode),
+    /// The file write node.
+    FileWrite(FileWriteNode),
+    /// The json transform node.
+    JsonTransform(JsonTransformNode),
+    /// The router node.
+    Router(RouterConfig),
+    /// The map node.
+    Map(MapConfig),
+    /// The reduce node.
+    Reduce {
+        /// The way to merge the items.
+        strategy: ReduceConfig,
+    },
+    /// The delay node.
+    Delay(DelayNode),
+    /// The cache node.
+    Cache(CacheConfig),
+    /// The assert node.
+    Assert(AssertNode),
+    /// The notify node.
+    Notify(NotifyNode),
+    /// The embed node.
+    Embed {
+        /// The name of the client of the embeddings api in the registry.
+        provider: String,
+    },
+    /// The chunker node.
+    Chunker(ChunkerNode),
+    /// The load node.
+    Load(LoadNode),
+    /// The retrieve node.
+    Retrieve(RetrieveConfig),
+    /// The rerank node.
+    Rerank(RerankConfig),
+    /// The ingest node.
+    Ingest(IngestConfig),
+    /// The tool node.
+    Tool {
+        /// The name of the tool in the registry.
+        tool: String,
+        /// The json template of the arguments.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        arguments: Option<serde_json::Value>,
+    },
+    /// The planner node.
+    Planner(PlannerConfig),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+/// The struct of the configuration of an AI node.
+pub struct AINodeConfig {
+    /// The name of the AI service in the registry.
+    pub provider: String,
+    /// The role of the assistant.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub role: Option<String>,
+    /// The prefix of the prompt.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub prompt_prefix: String,
+    /// The suffix of the prompt.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub prompt_suffix: String,
+    /// The background information put before the input.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub context: String,
+    /// The variables of the templates, sorted so the file is stable.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub variables: BTreeMap<String, String>,
+    /// The json retry budget.
+    #[serde(default = "AINode::default_json_retries")]
+    pub json_retries: usize,
+    /// The json schema that the output must match.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub output_schema: Option<serde_json::Value>,
+    /// The names of the tools in the registry.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tools: Vec<String>,
+    /// The max number of tool calling rounds.
+    #[serde(default = "AINode::default_max_tool_steps")]
+    pub max_tool_steps: usize,
+    /// The sampling parameters of the node, over the ones of the provider.
+    #[serde(default, skip_serializing_if = "SamplingParams::is_empty")]
+    pub params: SamplingParams,
+    /// The policy to trim the history.
+    #[serde(default)]
+    pub history_policy: HistoryPolicy,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+/// The struct of the configuration of an agent node.
+pub struct AgentConfig {
+    /// The configuration of the AI node of the agent.
+    #[serde(flatten)]
+    pub node: AINodeConfig,
+    /// The max number of steps.
+    #[serde(default = "Agent::default_max_steps")]
+    pub max_steps: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+/// The struct of the configuration of a planner node.
+pub struct PlannerConfig {
+    /// The configuration of the AI node that plans, with the tools of the tool steps.
+    #[serde(flatten)]
+    pub node: AINodeConfig,
+    /// The sandbox of the local steps, or none to not plan local steps.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sandbox: Option<Sandbox>,
+    /// The time a local step may take.
+    pub local_timeout: Duration,
+    /// The max number of steps of a plan.
+    #[serde(default = "PlannerNode::default_max_steps")]
+    pub max_steps: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+/// The struct of the configuration of a router node.
+pub struct RouterConfig {
+    /// The configuration of the AI node that classifies the input.
+    #[serde(flatten)]
+    pub node: AINodeConfig,
+    /// The labels that the input can be classified into.
+    pub routes: Vec<Route>,
+    /// The label chosen when the answer matches no label.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fallback: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+/// The struct of the configuration of a map node.
+pub struct MapConfig {
+    /// The subworkflow run on every item.
+    pub workflow: WorkflowDefinition,
+    /// The max number of items running at the same time.
+    #[serde(default = "MapNode::default_max_parallelism")]
+    pub max_parallelism: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+/// The enum of the way a reduce node merges the items, as it is written in a file.
+pub enum ReduceConfig {
+    /// Join the items with the separator.
+    Concat(String),
+    /// Concatenate the json arrays or merge the json objects.
+    JsonMerge,
+    /// The name of the reducer in the registry.
+    Custom(String),
+    /// The configuration of the AI node that summarizes the items.
+    Summarize(Box<AINodeConfig>),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+/// The struct of the configuration of a retrieve node.
+pub struct RetrieveConfig {
+    /// The name of the client of the embeddings api in the registry.
+    pub provider: String,
+    /// The name of the vector store in the registry.
+    pub store: String,
+    /// The options of the node.
+    #[serde(flatten)]
+    pub options: RetrieveOptions,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+/// The struct of the configuration of an ingest node.
+pub struct IngestConfig {
+    /// The name of the client of the embeddings api in the registry.
+    pub provider: String,
+    /// The name of the vector store in the registry.
+    pub store: String,
+    /// The options of the node.
+    #[serde(flatten)]
+    pub options: IngestOptions,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+/// The struct of the configuration of a rerank node.
+pub struct RerankConfig {
+    /// What scores the documents.
+    pub reranker: RerankerConfig,
+    /// The options of the node.
+    #[serde(flatten)]
+    pub options: RerankOptions,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+/// The enum of what scores the documents of a rerank node, as it is written in a file.
+pub enum RerankerConfig {
+    /// The client of a rerank api, by its name in the registry.
+    Api(String),
+    /// An AI node.
+    Llm(Box<AINodeConfig>),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+/// The struct of the configuration of a cache node.
+pub struct CacheConfig {
+    /// The configuration of the child node.
+    pub node: Box<NodeConfig>,
+    /// Where the entries are stored.
+    #[serde(default)]
+    pub store: CacheStoreConfig,
+    /// How long an entry is valid, or forever.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ttl: Option<Duration>,
+    /// The namespace put in the key of the entries.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub namespace: String,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+/// The enum of where a cache node stores the entries, as it is written in a file.
+pub enum CacheStoreConfig {
+    /// In memory, so the entries are lost with the workflow.
+    #[default]
+    Memory,
+    /// In the directory.
+    Disk(PathBuf),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+/// The struct of an edge as it is written in a file.
+pub struct EdgeDefinition {
+    /// The uid of the source node.
+    pub from: Uuid,
+    /// The uid of the target node.
+    pub to: Uuid,
+    /// The input port of the target node.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub port: Option<String>,
+    /// When the edge is followed.
+    #[serde(default, skip_serializing_if = "is_normal_edge")]
+    pub kind: EdgeKind,
+    /// The label of the source router node that the edge is followed for.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub route: Option<String>,
+}
+
+impl NodeConfig {
+    /// Get the configuration of the core part of a worknode.
+    fn from_core(core: &Worknodecore) -> PilotResult<Self> {
+        Ok(match core {
+            Worknodecore::Start => NodeConfig::Start,
+            Worknodecore::End => NodeConfig::End,
+            Worknodecore::AINode(node) => NodeConfig::AINode(AINodeConfig::from_node(node)?),
+            Worknodecore::Local(local) => NodeConfig::Local(local.clone()),
+            Worknodecore::User(user) => NodeConfig::User(user.clone()),
+            Worknodecore::Agent(agent) => NodeConfig::Agent(AgentConfig {
+                node: AINodeConfig::from_node(agent.get_node())?,
+                max_steps: agent.get_max_steps(),
+            }),
+            Worknodecore::Join(join) => NodeConfig::Join {
+                strategy: join.get_strategy().clone(),
+            },
+            Worknodecore::Approval(approval) => NodeConfig::Approval {
+                message: approval.get_message().to_string(),
+            },
+            Worknodecore::Wasm(wasm) => NodeConfig::Wasm(wasm.clone()),
+            Worknodecore::Script(script) => NodeConfig::Script(script.clone()),
+            Worknodecore::FileRead(file) => NodeConfig::FileRead(file.clone()),
+            Worknodecore::FileWrite(file) => NodeConfig::FileWrite(file.clone()),
+            Worknodecore::JsonTransform(transform) => NodeConfig::JsonTransform(transform.clone()),
+            Worknodecore::Router(router) => NodeConfig::Router(RouterConfig {
+                node: AINodeConfig::from_node(router.get_node())?,
+                routes: router.get_routes().clone(),
+                fallback: router.get_fallback().map(str::to_string),
+            }),
+            Worknodecore::Map(map) => NodeConfig::Map(MapConfig {
+                workflow: map.get_workflow().to_definition()?,
+                max_parallelism: map.get_max_parallelism(),
+            }),
+            Worknodecore::Reduce(reduce) => NodeConfig::Reduce {
+                strategy: match reduce.get_strategy() {
+                    ReduceStrategy::Concat(separator) => ReduceConfig::Concat(separator.clone()),
+                    ReduceStrategy::JsonMerge => ReduceConfig::JsonMerge,
+                    ReduceStrategy::Custom(reducer) => {
+                        ReduceConfig::Custom(reducer.get_name().to_string())
+                    }
+                    ReduceStrategy::Summarize(node) => {
+                        ReduceConfig::Summarize(Box::new(AINodeConfig::from_node(node)?))
+                    }
+                },
+            },
+            Worknodecore::Delay(delay) => NodeConfig::Delay(delay.clone()),
+            Worknodecore::Assert(assert) => NodeConfig::Assert(assert.clone()),
+            Worknodecore::Notify(notify) => NodeConfig::Notify(notify.clone()),
+            Worknodecore::Chunker(chunker) => NodeConfig::Chunker(chunker.clone()),
+            Worknodecore::Load(load) => NodeConfig::Load(load.clone()),
+            Worknodecore::Rerank(rerank) => NodeConfig::Rerank(RerankConfig {
+                reranker: match rerank.get_reranker() {
+                    Reranker::Api(_) => RerankerConfig::Api(
+                        rerank
+                            .get_provider()
+                            .ok_or_else(|| {
+                                definition_error(
+                                    "The rerank node has no provider name, so it can't be saved."
+                                        .to_string(),
+                                )
+                            })?
+                            .to_string(),
+                    ),
+                    Reranker::Llm(node) => {
+                        RerankerConfig::Llm(Box::new(AINodeConfig::from_node(node)?))
+                    }
+                },
+                options: rerank.get_options().clone(),
+            }),
+            Worknodecore::Ingest(ingest) => NodeConfig::Ingest(IngestConfig {
+                provider: ingest
+                    .get_provider()
+                    .ok_or_else(|| {
+                        definition_error(
+                            "The ingest node has no provider name, so it can't be saved."
+                                .to_string(),
+                        )
+                    })?
+                    .to_string(),
+                store: ingest
+                    .get_store_name()
+                    .ok_or_else(|| {
+                        definition_error(
+                            "The ingest node has no store name, so it can't be saved.".to_string(),
+                        )
+                    })?
+                    .to_string(),
+                options: ingest.get_options().clone(),
+            }),
+            Worknodecore::Retrieve(retrieve) => NodeConfig::Retrieve(RetrieveConfig {
+                provider: retrieve
+                    .get_provider()
+                    .ok_or_else(|| {
+                        definition_error(
+                            "The retrieve node has no provider name, so it can't be saved."
+                                .to_string(),
+                        )
+                    })?
+                    .to_string(),
+                store: retrieve
+                    .get_store_name()
+                    .ok_or_else(|| {
+                        definition_error(
+                            "The retrieve node has no store name, so it can't be saved."
+                                .to_string(),
+                        )
+                    })?
+                    .to_string(),
+                options: retrieve.get_options().clone(),
+            }),
+            Worknodecore::Embed(embed) => NodeConfig::Embed {
+                provider: embed
+                    .get_provider()
+                    .ok_or_else(|| {
+                        definition_error(
+                            "The embed node has no provider name, so it can't be saved."
+                                .to_string(),
+                        )
+                    })?
+                    .to_string(),
+            },
+            Worknodecore::Tool(tool) => NodeConfig::Tool {
+                tool: tool.get_tool().to_string(),
+                arguments: tool.get_arguments().cloned(),
+            },
+            Worknodecore::Planner(planner) => NodeConfig::Planner(PlannerConfig {
+                node: AINodeConfig::from_node(planner.get_node())?,
+                sandbox: planner.get_sandbox().cloned(),
+                local_timeout: planner.get_local_timeout(),
+                max_steps: planner.get_max_steps(),
+            }),
+            Worknodecore::Cache(cache) => NodeConfig::Cache(CacheConfig {
+                node: Box::new(NodeConfig::from_core(cache.get_node())?),
+                store: match cache.get_store() {
+                    CacheStore::Memory(_) => CacheStoreConfig::Memory,
+                    CacheStore::Disk(dir) => CacheStoreConfig::Disk(dir.clone()),
+                },
+                ttl: cache.get_ttl(),
+                namespace: cache.get_namespace().to_string(),
+            }),
+        })
+    }
+    /// Build the core part of a worknode, resolving the providers, the tools and the
+    /// reducers through the registry.
+    fn to_core(&self, registry: &Registry) -> PilotResult<Worknodecore> {
+        Ok(match self {
+            NodeConfig::Start => Worknodecore::Start,
+            NodeConfig::End => Worknodecore::End,
+            NodeConfig::AINode(config) => Worknodecore::AINode(config.to_node(registry)?),
+            NodeConfig::Local(local) => Worknodecore::Local(local.clone()),
+            NodeConfig::User(user) => Worknodecore::User(user.clone()),
+            NodeConfig::Agent(config) => {
+                let ai_node = config.node.to_node(registry)?;
+                let tools = ai_node.get_tools().clone();
+                Worknodecore::Agent(Agent::new(ai_node, tools).max_steps(config.max_steps))
+            }
+            NodeConfig::Join { strategy } => Worknodecore::Join(JoinNode::new(strategy.clone())),
+            NodeConfig::Approval { message } => Worknodecore::Approval(ApprovalNode::new(message)),
+            NodeConfig::Wasm(wasm) => Worknodecore::Wasm(wasm.clone()),
+            NodeConfig::Script(script) => Worknodecore::Script(script.clone()),
+            NodeConfig::FileRead(file) => Worknodecore::FileRead(file.clone()),
+            NodeConfig::FileWrite(file) => Worknodecore::FileWrite(file.clone()),
+            NodeConfig::JsonTransform(transform) => Worknodecore::JsonTransform(transform.clone()),
+            NodeConfig::Router(config) => Worknodecore::Router(
+                RouterNode::new(config.node.to_node(registry)?)
+                    .routes(config.routes.clone())
+                    .fallback(config.fallback.clone()),
+            ),
+            NodeConfig::Map(config) => Worknodecore::Map(
+                MapNode::new(Workflow::from_definition(&config.workflow, registry)?)
+                    .max_parallelism(config.max_parallelism),
+            ),
+            NodeConfig::Reduce { strategy } => {
+                Worknodecore::Reduce(ReduceNode::new(match strategy {
+                    ReduceConfig::Concat(separator) => ReduceStrategy::Concat(separator.clone()),
+                    ReduceConfig::JsonMerge => ReduceStrategy::JsonMerge,
+                    ReduceConfig::Custom(name) => ReduceStrategy::Custom(
+                        registry.get_reducer(name).cloned().ok_or_else(|| {
+                            definition_error(format!(
+                                "The reducer {} is not in the registry.",
+                                name
+                            ))
+                        })?,
+                    ),
+                    ReduceConfig::Summarize(config) => {
+                        ReduceStrategy::Summarize(config.to_node(registry)?)
+                    }
+                }))
+            }
+            NodeConfig::Delay(delay) => Worknodecore::Delay(delay.clone()),
+            NodeConfig::Assert(assert) => Worknodecore::Assert(assert.clone()),
+            NodeConfig::Notify(notify) => Worknodecore::Notify(notify.clone()),
+            NodeConfig::Chunker(chunker) => Worknodecore::Chunker(chunker.clone()),
+            NodeConfig::Load(load) => Worknodecore::Load(load.clone()),
+            NodeConfig::Rerank(config) => {
+                let reranker = match &config.reranker {
+                    RerankerConfig::Api(provider) => Reranker::Api(
+                        registry.get_reranker(provider).cloned().ok_or_else(|| {
+                            definition_error(format!(
+                                "The rerank client {} is not in the registry.",
+                                provider
+                            ))
+                        })?,
+                    ),
+                    RerankerConfig::Llm(node) => Reranker::Llm(node.to_node(registry)?),
+                };
+                let provider = match &config.reranker {
+                    RerankerConfig::Api(provider) => Some(provider.clone()),
+                    RerankerConfig::Llm(_) => None,
+                };
+                Worknodecore::Rerank(
+                    RerankNode::new(reranker)
+                        .options(config.options.clone())
+                        .provider(provider),
+                )
+            }
+            NodeConfig::Ingest(config) => {
+                let client = registry.get_embedder(&config.provider).ok_or_else(|| {
+                    definition_error(format!(
+                        "The embeddings client {} is not in the registry.",
+                        config.provider
+                    ))
+                })?;
+                let store = registry.get_vector_store(&config.store).ok_or_else(|| {
+                    definition_error(format!(
+                        "The vector store {} is not in the registry.",
+                        config.store
+                    ))
+                })?;
+                Worknodecore::Ingest(
+                    IngestNode::new(client.clone(), store.clone())
+                        .options(config.options.clone())
+                        .provider(Some(config.provider.clone()))
+                        .store_name(Some(config.store.clone())),
+                )
+            }
+            NodeConfig::Retrieve(config) => {
+                let client = registry.get_embedder(&config.provider).ok_or_else(|| {
+                    definition_error(format!(
+                        "The embeddings client {} is not in the registry.",
+                        config.provider
+                    ))
+                })?;
+                let store = registry.get_vector_store(&config.store).ok_or_else(|| {
+                    definition_error(format!(
+                        "The vector store {} is not in the registry.",
+                        config.store
+                    ))
+                })?;
+                Worknodecore::Retrieve(
+                    RetrieveNode::new(client.clone(), store.clone())
+                        .options(config.options.clone())
+                        .provider(Some(config.provider.clone()))
+                        .store_name(Some(config.store.clone())),
+                )
+            }
+            NodeConfig::Embed { provider } => Worknodecore::Embed(
+                EmbedNode::new(registry.get_embedder(provider).cloned().ok_or_else(|| {
+                    definition_error(format!(
+                        "The embeddings client {} is not in the registry.",
+                        provider
+                    ))
+                })?)
+                .provider(Some(provider.clone())),
+            ),
+            NodeConfig::Cache(config) => Worknodecore::Cache(
+                CacheNode::new(config.node.to_core(registry)?)
+                    .store(match &config.store {
+                        CacheStoreConfig::Memory => CacheStore::memory(),
+                        CacheStoreConfig::Disk(dir) => CacheStore::disk(dir),
+                    })
+                    .ttl(config.ttl)
+                    .namespace(&config.namespace),
+            ),
+            NodeConfig::Tool { tool, arguments } => {
+                let tools = registry.resolve_tools(std::slice::from_ref(tool))?;
+                Worknodecore::Tool(ToolNode::new(tools, tool).arguments(arguments.clone()))
+            }
+            NodeConfig::Planner(config) => {
+                let ai_node = config.node.to_node(registry)?;
+                let tools = ai_node.get_tools().clone();
+                Worknodecore::Planner(
+                    PlannerNode::new(ai_node, tools)
+                        .sandbox(config.sandbox.clone())
+                        .local_timeout(config.local_timeout)
+                        .max_steps(config.max_steps),
+                )
+            }
+        })
+    }
+}
+
+impl AINodeConfig {
+    /// Get the configuration of an AI node. The node must have the name of its provider.
+    fn from_node(node: &AINode) -> PilotResult<Self> {
+        let provider = node.get_provider().ok_or_else(|| {
+            definition_error("The AI node has no provider name, so it can't be saved.".to_string())
+        })?;
+        Ok(AINodeConfig {
+            provider: provider.to_string(),
+            role: node.get_role().clone(),
+            prompt_prefix: node.get_prompt_prefix().clone(),
+            prompt_suffix: node.get_prompt_suffix().clone(),
+            context: node.get_context().clone(),
+            variables: node.get_variables().clone().into_iter().collect(),
+            json_retries: node.get_json_retries(),
+            output_schema: node.get_output_schema().cloned(),
+            tools: node.get_tools().names(),
+            max_tool_steps: node.get_max_tool_steps(),
+            params: node.get_params().clone(),
+            history_policy: *node.get_history_policy(),
+        })
+    }
+    /// Build the AI node, with the provider and the tools taken from the registry.
+    fn to_node(&self, registry: &Registry) -> PilotResult<AINode> {
+        let service = registry.get_service(&self.provider).ok_or_else(|| {
+            definition_error(format!(
+                "The provider {} is not in the registry.",
+                self.provider
+            ))
+        })?;
+        Ok(AINode::new(service.clone())
+            .provider(Some(self.provider.clone()))
+            .role(self.role.clone())
+            .prompt_prefix(self.prompt_prefix.clone())
+            .prompt_suffix(self.prompt_suffix.clone())
+            .context(self.context.clone())
+            .variables(self.variables.clone().into_iter().collect())
+            .json_retries(self.json_retries)
+            .output_schema(self.output_schema.clone())
+            .tools(registry.resolve_tools(&self.tools)?)
+            .max_tool_steps(self.max_tool_steps)
+            .params(self.params.clone())
+            .history_policy(self.history_policy))
+    }
+}
+
+impl Workflow {
+    /// Get the definition of the workflow, to be written in a file.
+    pub fn to_definition(&self) -> PilotResult<WorkflowDefinition> {
+        let nodes = self
+            .nodes
+            .iter()
+            .map(|node| {
+                Ok(NodeDefinition {
+                    uid: node.get_uid(),
+                    node: NodeConfig::from_core(node.get_node())?,
+                    retry: node.get_retry_policy().clone(),
+                    context_key: node.get_context_key().map(str::to_string),
+                })
+            })
+            .collect::<PilotResult<Vec<NodeDefinition>>>()?;
+        let edges = self
+            .edges
+            .iter()
+            .map(|edge| EdgeDefinition {
+                from: edge.from,
+                to: edge.to,
+                port: edge.port.clone(),
+                kind: edge.kind,
+                route: edge.route.clone(),
+            })
+            .collect();
+        if self
+            .alert_sinks
+            .iter()
+            .any(|sink| matches!(sink, AlertSink::Callback(_)))
+        {
+            return Err(definition_error(
+                "The workflow has a callback alert sink, so it can't be saved.".to_string(),
+            ));
+        }
+        Ok(WorkflowDefinition {
+            version: DEFINITION_VERSION,
+            max_parallelism: self.max_parallelism,
+            provider_limits: self.provider_limits.clone().into_iter().collect(),
+            deadline: self.deadline,
+            budget: self.budget,
+            alert_sinks: self.alert_sinks.clone(),
+            nodes,
+            edges,
+        })
+    }
+    /// Build a workflow from its definition, resolving the providers, the tools and the
+    /// reducers through the registry.
+    pub fn from_definition(
+        definition: &WorkflowDefinition,
+        registry: &Registry,
+    ) -> PilotResult<Self> {
+        let mut workflow = Workflow::new()
+            .max_parallelism(definition.max_parallelism)
+            .deadline(definition.deadline)
+            .budget(definition.budget);
+        workflow.set_alert_sinks(definition.alert_sinks.clone());
+        for (provider, &limit) in &definition.provider_limits {
+            workflow.set_provider_limit(provider, limit);
+        }
+        for node in &definition.nodes {
+            let core = node.node.to_core(registry)?;
+            workflow.add_node(
+                Worknode::with_uid(node.uid, core)
+                    .retry_policy(node.retry.clone())
+                    .context_key(node.context_key.clone()),
+            );
+        }
+        for edge in &definition.edges {
+            workflow.push_edge(
+                edge.from,
+                edge.to,
+                edge.port.clone(),
+                edge.kind,
+                edge.route.clone(),
+            )?;
+        }
+        Ok(workflow)
+    }
+    /// Write the workflow as YAML. The enums are written as single key maps like
+    /// `sliding_window: 4` instead of YAML tags, so the nodes can be read back.
+    pub fn to_yaml(&self) -> PilotResult<String> {
+        let mut text = Vec::new();
+        singleton_map_recursive::serialize(
+            &self.to_definition()?,
+            &mut serde_yaml::Serializer::new(&mut text),
+        )
+        .map_err(|e| definition_error(format!("Can't write the workflow as YAML. {}", e)))?;
+        String::from_utf8(text)
+            .map_err(|e| definition_error(format!("Can't write the workflow as YAML. {}", e)))
+    }
+    /// Read a workflow from YAML, upgrading it from an older version.
+    pub fn from_yaml(text: &str, registry: &Registry) -> PilotResult<Self> {
+        let value = parse_value(text, DefinitionFormat::Yaml)?;
+        Self::from_definition(&WorkflowDefinition::from_value(value)?, registry)
+    }
+    /// Write the workflow as JSON.
+    pub fn to_json(&self) -> PilotResult<String> {
+        serde_json::to_string_pretty(&self.to_definition()?)
+            .map_err(|e| definition_error(format!("Can't write the workflow as JSON. {}", e)))
+    }
+    /// Read a workflow from JSON, upgrading it from an older version.
+    pub fn from_json(text: &str, registry: &Registry) -> PilotResult<Self> {
+        let value = parse_value(text, DefinitionFormat::Json)?;
+        Self::from_definition(&WorkflowDefinition::from_value(value)?, registry)
+    }
+    /// Save the workflow to a file, in the format given by the extension of the path.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> PilotResult<()> {
+        let path = path.as_ref();
+        let text = match DefinitionFormat::from_path(path) {
+            DefinitionFormat::Yaml => self.to_yaml()?,
+            DefinitionFormat::Json => self.to_json()?,
+        };
+        std::fs::write(path, text).map_err(|e| {
+            definition_error(format!(
+                "Can't write the workflow file {}. {}",
+                path.display(),
+                e
+            ))
+        })
+    }
+    /// Load a workflow from a file, in the format given by the extension of the path.
+    pub fn load<P: AsRef<Path>>(path: P, registry: &Registry) -> PilotResult<Self> {
+        let value = read_value(path.as_ref())?;
+        Self::from_definition(&WorkflowDefinition::from_value(value)?, registry)
+    }
+    /// Load a workflow from a file like `load`, with the environment variables in its strings
+    /// replaced by their values first.
+    pub fn load_with_env<P: AsRef<Path>>(path: P, registry: &Registry) -> PilotResult<Self> {
+        let mut value = read_value(path.as_ref())?;
+        interpolate(&mut value, &|name| std::env::var(name).ok())?;
+        Self::from_definition(&WorkflowDefinition::from_value(value)?, registry)
+    }
+}
+
+/// Read the value of the text of a workflow in the format.
+fn parse_value(text: &str, format: DefinitionFormat) -> PilotResult<serde_json::Value> {
+    match format {
+        DefinitionFormat::Yaml => {
+            let value: serde_yaml::Value = serde_yaml::from_str(text)
+                .map_err(|e| definition_error(format!("Can't read the workflow YAML. {}", e)))?;
+            serde_json::to_value(value)
+                .map_err(|e| definition_error(format!("Can't read the workflow YAML. {}", e)))
+        }
+        DefinitionFormat::Json => serde_json::from_str(text)
+            .map_err(|e| definition_error(format!("Can't read the workflow JSON. {}", e))),
+    }
+}
+
+/// Read the value of a workflow file, in the format given by the extension of the path.
+fn read_value(path: &Path) -> PilotResult<serde_json::Value> {
+    let text = std::fs::read_to_string(path).map_err(|e| {
+        definition_error(format!(
+            "Can't read the workflow file {}. {}",
+            path.display(),
+            e
+        ))
+    })?;
+    parse_value(&text, DefinitionFormat::from_path(path))
+}
+
+/// Replace the variables in the strings of the value with the values that `var` gives for
+/// their names. A string that is only one variable takes the number or the boolean of its
+/// value.
+pub fn interpolate<F>(value: &mut serde_json::Value, var: &F) -> PilotResult<()>
+where
+    F: Fn(&str) -> Option<String>,
+{
+    match value {
+        serde_json::Value::String(text) => {
+            let whole = text.starts_with("${")
+                && text.ends_with('}')
+                && text.find('}') == Some(text.len() - 1);
+            let replaced = interpolate_str(text, var)?;
+            *value = match serde_yaml::from_str::<serde_json::Value>(&replaced) {
+                Ok(typed @ (serde_json::Value::Number(_) | serde_json::Value::Bool(_)))
+                    if whole =>
+                {
+                    typed
+                }
+                _ => serde_json::Value::String(replaced),
+            };
+        }
+        serde_json::Value::Array(values) => {
+            for value in values {
+                interpolate(value, var)?;
+            }
+        }
+        serde_json::Value::Object(values) => {
+            for value in values.values_mut() {
+                interpolate(value, var)?;
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+/// Replace the variables in the text.
+fn interpolate_str<F>(text: &str, var: &F) -> PilotResult<String>
+where
+    F: Fn(&str) -> Option<String>,
+{
+    let mut output = String::new();
+    let mut rest = text;
+    while let Some(start) = rest.find('$') {
+        output.push_str(&rest[..start]);
+        let after = &rest[start..];
+        if let Some(after) = after.strip_prefix("$${") {
+            output.push_str("${");
+            rest = after;
+        } else if let Some(after) = after.strip_prefix("${") {
+            let end = after.find('}').ok_or_else(|| {
+                definition_error(format!("The variable in {} has no closing brace.", text))
+            })?;
+            let (name, default) = match after[..end].split_once(":-") {
+                Some((name, default)) => (name, Some(default)),
+                None => (&after[..end], None),
+            };
+            let value = var(name)
+                .or_else(|| default.map(str::to_string))
+                .ok_or_else(|| {
+                    definition_error(format!("Environment variable {} not found.", name))
+                })?;
+            output.push_str(&value);
+            rest = &after[end + 1..];
+        } else {
+            output.push('$');
+            rest = &after[1..];
+        }
+    }
+    output.push_str(rest);
+    Ok(output)
+}
+
+impl WorkflowDefinition {
+    /// Read a definition from its json value, upgrading it from an older version. A YAML file
+    /// has the same value, as its enums are written as single key maps.
+    pub fn from_value(mut value: serde_json::Value) -> PilotResult<Self> {
+        migrate(&mut value, MIGRATIONS)?;
+        serde_json::from_value(value)
+            .map_err(|e| definition_error(format!("Can't read the workflow definition. {}", e)))
+    }
+    /// The version of the files written before the version was.
+    fn first_version() -> u64 {
+        1
+    }
+}
+
+/// Upgrade the definition to the latest version through the migrations of its version.
+fn migrate(definition: &mut serde_json::Value, migrations: &[Migration]) -> PilotResult<()> {
+    let latest = migrations.len() as u64 + 1;
+    let version = match definition.get("version") {
+        Some(version) => version
+            .as_u64()
+            .ok_or_else(|| definition_error(format!("The version {} is not a number.", version)))?,
+        None => WorkflowDefinition::first_version(),
+    };
+    if version == 0 || version > latest {
+        return Err(definition_error(format!(
+            "The workflow is of version {}, but only the versions 1 to {} can be read.",
+            version, latest
+        )));
+    }
+    for migration in &migrations[version as usize - 1..] {
+        migration(definition)?;
+    }
+    if let Some(definition) = definition.as_object_mut() {
+        definition.insert("version".to_string(), latest.into());
+    }
+    Ok(())
+}
+
+/// Whether the edge is a normal one, which is not written in the file.
+fn is_normal_edge(kind: &EdgeKind) -> bool {
+    *kind == EdgeKind::Normal
+}
+
+/// Whether the retry policy is the default one, which is not written in the file.
+fn is_default_retry(retry: &RetryPolicy) -> bool {
+    *retry == RetryPolicy::default()
+}
+
+/// Create a PilotError of a workflow definition.
+pub(super) fn definition_error(message: String) -> PilotError {
+    PilotError::new(
+        PilotErrorType::GraphErr(GraphError::new(GraphErrorType::DefinitionError, message)),
+        "The workflow definition is not valid".to_string(),
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::worknode::ai_node::deepseek::{DeepSeekClient, DeepSeekModel, DEEPSEEK_API_URL};
+    use crate::worknode::ai_node::TrimStrategy;
+
+    fn registry() -> Registry {
+        let client = DeepSeekClient::new(DEEPSEEK_API_URL, DeepSeekModel::DeepseekChat);
+        Registry::new()
+            .service("deepseek", AIService::new_deepseek(client))
+            .tools(ToolRegistry::new().tool(
+                "echo",
+                "Echo the text",
+                serde_json::json!({ "type": "object" }),
+                |arguments| async move { Ok(arguments.to_string()) },
+            ))
+    }
+
+    fn workflow(registry: &Registry) -> Workflow {
+        let mut workflow = Workflow::new()
+            .max_parallelism(3)
+            .provider_limit("deepseek", 2);
+        let start = workflow.add_node(Worknode::new(Worknodecore::Start));
+        let ai_node = AINode::new(registry.get_service("deepseek").unwrap().clone())
+            .provider(Some("deepseek".to_string()))
+            .role(Some("You are a {{animal}}".to_string()))
+            .variables([("animal".to_string(), "cat".to_string())].into())
+            .tools(registry.get_tools().clone())
+            .history_policy(HistoryPolicy::new(TrimStrategy::SlidingWindow(4)));
+        let ai = workflow.add_node(
+            Worknode::new(Worknodecore::AINode(ai_node))
+                .retry_policy(RetryPolicy::new(3))
+                .context_key(Some("answer".to_string())),
+        );
+        let local = workflow.add_node(Worknode::new(Worknodecore::Local(local_node())));
+        let end = workflow.add_node(Worknode::new(Worknodecore::End));
+        workflow.add_edge(start, ai).unwrap();
+        workflow.add_port_edge(start, ai, "context").unwrap();
+        workflow.add_edge(ai, local).unwrap();
+        workflow.add_edge(local, end).unwrap();
+        workflow
+    }
+
+    fn local_node() -> LocalNode {
+        LocalNode::new("python3")
+            .args(vec!["format.py".to_string()])
+            .env("MODE", "strict")
+            .timeout(Some(std::time::Duration::from_secs(30)))
+    }
+
+    #[test]
+    fn yaml_round_trip() {
+        let registry = registry();
+        let workflow = workflow(&registry);
+        let yaml = workflow.to_yaml().unwrap();
+        let loaded = Workflow::from_yaml(&yaml, &registry).unwrap();
+        assert_eq!(loaded.to_yaml().unwrap(), yaml);
+        assert_eq!(loaded.get_max_parallelism(), 3);
+        assert_eq!(loaded.get_provider_limit("deepseek"), Some(2));
+        assert_eq!(loaded.get_edges(), workflow.get_edges());
+        let node = &loaded.get_nodes()[1];
+        assert_eq!(node.get_uid(), workflow.get_nodes()[1].get_uid());
+        assert_eq!(node.get_retry_policy().get_max_attempts(), 3);
+        assert_eq!(node.get_context_key(), Some("answer"));
+        match node.get_node() {
+            Worknodecore::AINode(ai_node) => {
+                assert_eq!(ai_node.get_node_uid(), Some(node.get_uid()));
+                assert_eq!(ai_node.get_tools().names(), vec!["echo"]);
+                assert_eq!(ai_node.get_variables()["animal"], "cat");
+            }
+            _ => panic!("The node should be an AI node"),
+        }
+        assert!(matches!(
+            loaded.get_nodes()[2].get_node(),
+            Worknodecore::Local(local) if *local == local_node()
+        ));
+        let json = workflow.to_json().unwrap();
+        assert_eq!(
+            Workflow::from_json(&json, &registry)
+                .unwrap()
+                .to_json()
+                .unwrap(),
+            json
+        );
+    }
+
+    #[test]
+    fn versions() {
+        let registry = registry();
+        let text =
+            r#"{"nodes": [{"uid": "8d1f7a6e-0000-4000-8000-000000000000", "type": "start"}]}"#;
+        let workflow = Workflow::from_json(text, &registry).unwrap();
+        assert_eq!(workflow.get_nodes().len(), 1);
+        let text = text.replacen('{', r#"{"version": 99, "#, 1);
+        assert!(Workflow::from_json(&text, &registry).is_err());
+        assert_eq!(
+            workflow.to_definition().unwrap().version,
+            DEFINITION_VERSION
+        );
+
+        // a format change renames `parallelism` to `max_parallelism` in version 2
+        let rename: Migration = |definition| {
+            if let Some(parallelism) = definition.as_object_mut().unwrap().remove("parallelism") {
+                definition["max_parallelism"] = parallelism;
+            }
+            Ok(())
+        };
+        let mut definition = serde_json::json!({"parallelism": 3, "nodes": []});
+        migrate(&mut definition, &[rename]).unwrap();
+        assert_eq!(
+            definition,
+            serde_json::json!({"version": 2, "max_parallelism": 3, "nodes": []})
+        );
+    }
+
+    #[test]
+    fn unresolved_references() {
+        let registry = registry();
+        let yaml = workflow(&registry).to_yaml().unwrap();
+        assert!(Workflow::from_yaml(&yaml, &Registry::new()).is_err());
+        let mut workflow = Workflow::new();
+        let client = DeepSeekClient::new(DEEPSEEK_API_URL, DeepSeekModel::DeepseekChat);
+        workflow.add_node(Worknode::new(Worknodecore::AINode(AINode::new(
+            AIService::new_deepseek(client),
+        ))));
+        assert!(workflow.to_yaml().is_err());
+    }
+
+    #[test]
+    fn interpolate_variables() {
+        let var = |name: &str| match name {
+            "MODEL" => Some("deepseek".to_string()),
+            "PARALLELISM" => Some("4".to_string()),
+            _ => None,
+        };
+        let mut value = serde_json::json!({
+            "max_parallelism": "${PARALLELISM}",
+            "nodes": [{
+                "provider": "${MODEL}",
+                "role": "You answer in ${LANGUAGE:-English}, not $${LANGUAGE} or $5",
+            }],
+        });
+        interpolate(&mut value, &var).unwrap();
+        assert_eq!(
+            value,
+            serde_json::json!({
+                "max_parallelism": 4,
+                "nodes": [{
+                    "provider": "deepseek",
+                    "role": "You answer in English, not ${LANGUAGE} or $5",
+                }],
+            })
+        );
+        let message = interpolate(&mut serde_json::json!(["${LANGUAGE}"]), &var)
+            .unwrap_err()
+            .to_string();
+        assert!(message.contains("Environment variable LANGUAGE not found."));
+        assert!(interpolate(&mut serde_json::json!("${MODEL"), &var).is_err());
+    }
+}
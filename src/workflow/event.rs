@@ -0,0 +1,73 @@
+//! # Event
+//!
+//! This module defines the events that a workflow emits while it runs, so a user interface, a
+//! logger or a test can watch the progress of a run as it happens.
+//!
+//! The events are sent to the event channel of the workflow, if there is one. An event is
+//! dropped if nobody listens anymore, and the run goes on.
+//!
+//! With `Workflow::stream_output`, the AI and agent nodes stream their answers, and every
+//! piece is emitted as a `NodeOutput` event while the node is still running.
+
+use super::run::RunStatus;
+use crate::worknode::ai_node::deepseek::DeepSeekUsage;
+use crate::worknode::approval::ApprovalRequest;
+
+use uuid::Uuid;
+
+use std::time::Duration;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// The enum of what happens in a run.
+pub enum RunEvent {
+    /// A node is spawned.
+    NodeStarted {
+        /// The uid of the node.
+        node: Uuid,
+        /// The type of the node, like `ai_node`.
+        kind: &'static str,
+    },
+    /// A piece of the answer of an AI or agent node, while it is still generated. Only
+    /// emitted when the workflow streams the output of its nodes.
+    NodeOutput {
+        /// The uid of the node.
+        node: Uuid,
+        /// The piece of the answer.
+        delta: String,
+    },
+    /// A node finished.
+    NodeFinished {
+        /// The uid of the node.
+        node: Uuid,
+        /// The output of the node.
+        output: String,
+        /// The usage statistics of the requests of the node, for the AI and agent nodes.
+        usage: Option<DeepSeekUsage>,
+        /// The time the node took, including its retries.
+        duration: Duration,
+    },
+    /// A node is skipped, because none of its incoming edges is followed.
+    NodeSkipped {
+        /// The uid of the node.
+        node: Uuid,
+    },
+    /// An approval node waits for a decision. Decide it through the approvals of the run
+    /// context.
+    ApprovalRequested(ApprovalRequest),
+    /// A node failed after all its retries.
+    NodeFailed {
+        /// The uid of the node.
+        node: Uuid,
+        /// The error of the node.
+        error: String,
+        /// The time the node took, including its retries.
+        duration: Duration,
+    },
+    /// The run ended.
+    RunFinished {
+        /// How the run ended.
+        status: RunStatus,
+        /// The time the run took.
+        duration: Duration,
+    },
+}
@@ -0,0 +1,394 @@
+//! # Execution Log
+//!
+//! This module appends what happens in the runs of a workflow to a JSON Lines file, one json
+//! object per line, so the runs can be analysed by other programs without parsing the free
+//! form logs.
+//!
+//! Every record has the version of its schema `v`, the time `ts` in RFC 3339, the id of the
+//! run `run` (kept when the run is resumed) and the `event`, with the fields of the event:
+//! - `node_started`: `node`, `kind` and `input`.
+//! - `node_finished`: `node`, `kind`, `output`, `attempts`, `duration_ms`, and `usage` for
+//!   the nodes that call an AI service.
+//! - `node_failed`: `node`, `kind`, `code`, `error`, `attempts` and `duration_ms`. The
+//!   `code` is the stable code of the error (see [`crate::error`]).
+//! - `node_skipped`: `node`.
+//! - `tool_call`: `node`, `tool`, `arguments` and `result`, for every tool called by an AI or
+//!   agent node.
+//! - `run_finished`: `status` and `duration_ms`.
+//!
+//! New fields may be added to a schema, but a field is never renamed or removed without the
+//! version going up. A record that can't be written is dropped with a warning, and the run
+//! goes on.
+//!
+//! `ExecutionLog::read` reads the records back, and `ExecutionLog::usage` adds up the token
+//! usage of every run in the log.
+
+use super::run::RunStatus;
+use crate::error::storage_error::{StorageError, StorageErrorType, StorageResult};
+use crate::worknode::ai_node::deepseek::DeepSeekUsage;
+use crate::worknode::ai_node::{Chat, Role, ToolCall};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use std::collections::BTreeMap;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::Duration;
+
+/// The version of the schema of the records.
+pub const LOG_SCHEMA_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+/// The struct of a line of the execution log.
+pub struct LogRecord {
+    /// The version of the schema.
+    pub v: u32,
+    /// When the event happened.
+    pub ts: DateTime<Utc>,
+    /// The id of the run.
+    pub run: Uuid,
+    /// What happened.
+    #[serde(flatten)]
+    pub event: LogEvent,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+/// The enum of what happens in a run, as it is logged.
+pub enum LogEvent {
+    /// A node is spawned.
+    NodeStarted {
+        node: Uuid,
+        kind: String,
+        input: String,
+    },
+    /// A node finished.
+    NodeFinished {
+        node: Uuid,
+        kind: String,
+        output: String,
+        attempts: usize,
+        duration_ms: u64,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        usage: Option<DeepSeekUsage>,
+    },
+    /// A node failed after all its retries.
+    NodeFailed {
+        node: Uuid,
+        kind: String,
+        #[serde(default)]
+        code: String,
+        error: String,
+        attempts: usize,
+        duration_ms: u64,
+    },
+    /// A node is skipped, because none of its incoming edges is followed.
+    NodeSkipped { node: Uuid },
+    /// A node called a tool.
+    ToolCall {
+        node: Uuid,
+        tool: String,
+        arguments: String,
+        result: String,
+    },
+    /// The run ended.
+    RunFinished { status: RunStatus, duration_ms: u64 },
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+/// The struct of the token usage of a run, added up from its records.
+pub struct RunUsage {
+    /// The id of the run.
+    pub run: Uuid,
+    /// The time of the first record of the run.
+    pub started: DateTime<Utc>,
+    /// The number of nodes that finished.
+    pub finished: usize,
+    /// The number of nodes that failed.
+    pub failed: usize,
+    /// The usage of the nodes that call an AI service.
+    pub usage: DeepSeekUsage,
+    /// How the run ended, if it ended.
+    pub status: Option<RunStatus>,
+    /// How long the run took in milliseconds, if it ended.
+    pub duration_ms: Option<u64>,
+}
+
+#[derive(Debug, Clone)]
+/// The struct of the execution log of the runs. The clones append to the same file, one
+/// record at a time.
+pub struct ExecutionLog {
+    /// The JSON Lines file.
+    path: PathBuf,
+    /// Keeps the lines of the clones from mixing.
+    lock: Arc<Mutex<()>>,
+}
+
+impl ExecutionLog {
+    /// Create a new ExecutionLog that appends to the file. The file is created when the first
+    /// record is written.
+    pub fn new<P: Into<PathBuf>>(path: P) -> Self {
+        ExecutionLog {
+            path: path.into(),
+            lock: Arc::new(Mutex::new(())),
+        }
+    }
+    /// Append the event of the run to the file.
+    pub fn write(&self, run: Uuid, event: LogEvent) {
+        let record = LogRecord {
+            v: LOG_SCHEMA_VERSION,
+            ts: Utc::now(),
+            run,
+            event,
+        };
+        // the records only hold strings, numbers and uids
+        let mut line = serde_json::to_string(&record).unwrap();
+        line.push('\n');
+        let _guard = self.lock.lock().unwrap_or_else(PoisonError::into_inner);
+        let written = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .and_then(|mut file| file.write_all(line.as_bytes()));
+        if let Err(e) = written {
+            log::warn!(
+                "Failed to write the execution log {}. {}",
+                self.path.display(),
+                e
+            );
+        }
+    }
+    /// Get the file of the log.
+    pub fn get_path(&self) -> &Path {
+        &self.path
+    }
+    /// Read all the records of the file, in the order they were written.
+    pub fn read(&self) -> StorageResult<Vec<LogRecord>> {
+        let text = std::fs::read_to_string(&self.path).map_err(|e| {
+            StorageError::new(
+                StorageErrorType::ReadError,
+                format!(
+                    "Can't read the execution log {}. {}",
+                    self.path.display(),
+                    e
+                ),
+            )
+        })?;
+        text.lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty())
+            .map(|(number, line)| {
+                serde_json::from_str(line).map_err(|e| {
+                    StorageError::new(
+                        StorageErrorType::InvalidData,
+                        format!(
+                            "The line {} of the execution log is not valid. {}",
+                            number + 1,
+                            e
+                        ),
+                    )
+                })
+            })
+            .collect()
+    }
+    /// Read the file and add up the usage of every run, in the order the runs started.
+    pub fn usage(&self) -> StorageResult<Vec<RunUsage>> {
+        let mut runs: BTreeMap<Uuid, RunUsage> = BTreeMap::new();
+        for record in self.read()? {
+            let run = runs.entry(record.run).or_insert_with(|| RunUsage {
+                run: record.run,
+                started: record.ts,
+                finished: 0,
+                failed: 0,
+                usage: DeepSeekUsage::new(),
+                status: None,
+                duration_ms: None,
+            });
+            match record.event {
+                LogEvent::NodeFinished { usage, .. } => {
+                    run.finished += 1;
+                    if let Some(usage) = usage {
+                        run.usage = run.usage + usage;
+                    }
+                }
+                LogEvent::NodeFailed { .. } => run.failed += 1,
+                LogEvent::RunFinished {
+                    status,
+                    duration_ms,
+                } => {
+                    run.status = Some(status);
+                    run.duration_ms = Some(duration_ms);
+                }
+                _ => {}
+            }
+        }
+        let mut runs: Vec<RunUsage> = runs.into_values().collect();
+        runs.sort_by_key(|run| run.started);
+        Ok(runs)
+    }
+}
+
+/// Get the duration in milliseconds.
+pub(crate) fn millis(duration: Duration) -> u64 {
+    duration.as_millis().try_into().unwrap_or(u64::MAX)
+}
+
+/// Get the tool calls answered in the chats created since the moment, with their results,
+/// in the order they were answered.
+pub(super) fn tool_calls(history: &[Chat], since: DateTime<Utc>) -> Vec<(ToolCall, String)> {
+    let recent: Vec<&Chat> = history
+        .iter()
+        .filter(|chat| {
+            chat.get_metadata()
+                .created_at
+                .is_some_and(|created| created >= since)
+        })
+        .collect();
+    recent
+        .iter()
+        .filter(|chat| chat.get_role() == Role::Tool)
+        .filter_map(|chat| {
+            let id = chat.get_metadata().tool_call_id.as_ref()?;
+            let call = recent
+                .iter()
+                .flat_map(|chat| &chat.get_metadata().tool_calls)
+                .find(|call| &call.id == id)?;
+            Some((call.clone(), chat.get_content().as_text()))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn write_records_and_tool_calls() {
+        let since = Utc::now();
+        let call = ToolCall {
+            id: "call_0".to_string(),
+            name: "add".to_string(),
+            arguments: r#"{"a": 1, "b": 2}"#.to_string(),
+        };
+        let history = vec![
+            Chat::new(Role::User, "1 + 2?".to_string()),
+            Chat::new(Role::Assistant, String::new()).tool_calls(vec![call.clone()]),
+            Chat::new(Role::Tool, "3".to_string()).tool_call_id(Some("call_0".to_string())),
+            Chat::new(Role::Assistant, "3".to_string()),
+        ];
+        assert_eq!(tool_calls(&history, since), vec![(call, "3".to_string())]);
+        assert!(tool_calls(&history, Utc::now()).is_empty());
+
+        let path = std::env::temp_dir().join(format!("aipilot-log-{}.jsonl", Uuid::new_v4()));
+        let log = ExecutionLog::new(&path);
+        let run = Uuid::new_v4();
+        let node = Uuid::new_v4();
+        log.write(run, LogEvent::NodeSkipped { node });
+        log.clone().write(
+            run,
+            LogEvent::RunFinished {
+                status: RunStatus::Completed,
+                duration_ms: millis(Duration::from_secs(2)),
+            },
+        );
+        let text = std::fs::read_to_string(&path).unwrap();
+        let usage = log.usage().unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(usage.len(), 1);
+        assert_eq!(usage[0].run, run);
+        assert_eq!(usage[0].status, Some(RunStatus::Completed));
+        assert_eq!(usage[0].duration_ms, Some(2000));
+        assert!(log.read().is_err());
+        let lines: Vec<serde_json::Value> = text
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0]["v"], 1);
+        assert_eq!(lines[0]["event"], "node_skipped");
+        assert_eq!(lines[0]["node"], node.to_string());
+        assert_eq!(lines[1]["status"], "completed");
+        assert_eq!(lines[1]["duration_ms"], 2000);
+        let record: LogRecord = serde_json::from_value(lines[1].clone()).unwrap();
+        assert_eq!(record.run, run);
+    }
+
+    #[test]
+    fn read_usage_of_runs() {
+        let path = std::env::temp_dir().join(format!("aipilot-log-{}.jsonl", Uuid::new_v4()));
+        let log = ExecutionLog::new(&path);
+        // the first run has the greater id, so the runs are sorted by their start
+        let (first, second) = (Uuid::from_u128(2), Uuid::from_u128(1));
+        let usage: DeepSeekUsage = serde_json::from_value(serde_json::json!({
+            "completion_tokens": 1,
+            "prompt_tokens": 2,
+            "prompt_cache_hit_tokens": 0,
+            "prompt_cache_miss_tokens": 2,
+            "total_tokens": 3
+        }))
+        .unwrap();
+        let finished = |usage| LogEvent::NodeFinished {
+            node: Uuid::new_v4(),
+            kind: "ai".to_string(),
+            output: String::new(),
+            attempts: 1,
+            duration_ms: 10,
+            usage,
+        };
+        log.write(first, finished(Some(usage)));
+        std::thread::sleep(Duration::from_millis(2));
+        log.write(second, finished(None));
+        log.write(first, finished(Some(usage)));
+        log.write(
+            first,
+            LogEvent::NodeFailed {
+                node: Uuid::new_v4(),
+                kind: "ai".to_string(),
+                code: "ai_node.request_error".to_string(),
+                error: "Failed".to_string(),
+                attempts: 2,
+                duration_ms: 20,
+            },
+        );
+        log.write(
+            first,
+            LogEvent::RunFinished {
+                status: RunStatus::Failed,
+                duration_ms: 30,
+            },
+        );
+
+        let records = log.read().unwrap();
+        let runs: Vec<Uuid> = records.iter().map(|record| record.run).collect();
+        assert_eq!(runs, [first, second, first, first, first]);
+        let usage = log.usage().unwrap();
+        assert_eq!(usage.len(), 2);
+        assert_eq!(usage[0].run, first);
+        assert_eq!(usage[0].finished, 2);
+        assert_eq!(usage[0].failed, 1);
+        assert_eq!(usage[0].usage.get_total_tokens(), 6);
+        assert_eq!(usage[0].usage.get_prompt_tokens(), 4);
+        assert_eq!(usage[0].status, Some(RunStatus::Failed));
+        assert_eq!(usage[0].duration_ms, Some(30));
+        assert_eq!(usage[1].run, second);
+        assert_eq!(usage[1].finished, 1);
+        assert_eq!(usage[1].usage, DeepSeekUsage::new());
+        assert_eq!(usage[1].status, None);
+
+        // a line that is not a record fails the whole read, with its number
+        let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(b"\nnot json\n").unwrap();
+        let error = log.read().unwrap_err();
+        assert!(log.usage().is_err());
+        std::fs::remove_file(&path).unwrap();
+        assert!(matches!(
+            error.get_error_type(),
+            StorageErrorType::InvalidData
+        ));
+        assert!(error.get_message().contains("line 7"));
+    }
+}
@@ -0,0 +1,35 @@
+//! # Hook
+//!
+//! This module lets a program step in around every node of a run, to log what the nodes do,
+//! clean their inputs or rework their outputs, without changing the engine.
+//!
+//! A `NodeHook` is added to a workflow with `Workflow::hook`. For every node of a run, the
+//! workflow calls `before_execute` of every hook with the input, before the node starts; then
+//! `after_execute` with the output when the node succeeds, or `on_error` with the error when
+//! it fails. The hooks are called in the order they were added, and every method does nothing
+//! by default.
+//!
+//! A hook may change the input and the output. When `before_execute` or `after_execute`
+//! returns an error, the node fails with it, like it failed by itself: it is routed to the
+//! on_error edges of the node if there are some, and otherwise fails the run.
+
+use crate::error::{PilotError, PilotResult};
+use crate::worknode::Worknode;
+
+/// The trait of the hooks called around the execution of the nodes.
+pub trait NodeHook: std::fmt::Debug + Send + Sync {
+    /// Called before the node starts, with its input.
+    fn before_execute(&self, node: &Worknode, input: &mut String) -> PilotResult<()> {
+        let _ = (node, input);
+        Ok(())
+    }
+    /// Called after the node succeeded, with its output.
+    fn after_execute(&self, node: &Worknode, output: &mut String) -> PilotResult<()> {
+        let _ = (node, output);
+        Ok(())
+    }
+    /// Called after the node failed, with its error.
+    fn on_error(&self, node: &Worknode, error: &PilotError) {
+        let _ = (node, error);
+    }
+}
@@ -0,0 +1,387 @@
+//! # Metrics
+//!
+//! This module counts what the runs of the workflows do, and exposes it in the text format of
+//! Prometheus, so a deployed workflow service can be monitored.
+//!
+//! A workflow with `Metrics` records every node that finishes:
+//! - `aipilot_requests_total{provider, status}`: the requests that the nodes sent to the AI
+//!   services, with their tool calls, repairs and retries, by provider and by `ok` or `error`.
+//!   The requests are counted by the clients (see
+//!   [`crate::worknode::ai_node::deepseek::RequestCount`]).
+//! - `aipilot_tokens_total{provider, direction}`: the tokens of the requests, `in` for the
+//!   prompt and `out` for the completion.
+//! - `aipilot_node_duration_seconds{kind}`: a histogram of the time the nodes took, including
+//!   their retries.
+//! - `aipilot_node_retries_total{kind}`: the attempts after the first one.
+//! - `aipilot_cache_total{result}`: the executions of the cache nodes, by `hit` or `miss`.
+//!
+//! The clones of a `Metrics` share the same counters, so one `Metrics` can be given to many
+//! workflows. `render` gets the text to scrape, and `serve` answers the scrapes of
+//! `GET /metrics` on an address.
+
+use crate::worknode::ai_node::deepseek::{DeepSeekUsage, RequestCount};
+use crate::worknode::{Worknode, Worknodecore};
+
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::Duration;
+
+/// The upper bounds of the buckets of the durations, in seconds.
+const DURATION_BUCKETS: [f64; 12] = [
+    0.01, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0, 300.0,
+];
+
+/// The max size of the head of a scrape request.
+const MAX_REQUEST_HEAD: usize = 8192;
+
+#[derive(Debug, Clone, Default)]
+/// The struct of a histogram of durations.
+struct Histogram {
+    /// The number of observations in each bucket, not cumulative.
+    buckets: [u64; DURATION_BUCKETS.len()],
+    /// The sum of the observations, in seconds.
+    sum: f64,
+    /// The number of observations.
+    count: u64,
+}
+
+impl Histogram {
+    /// Add an observation.
+    fn observe(&mut self, seconds: f64) {
+        if let Some(bucket) = DURATION_BUCKETS.iter().position(|&bound| seconds <= bound) {
+            self.buckets[bucket] += 1;
+        }
+        self.sum += seconds;
+        self.count += 1;
+    }
+}
+
+#[derive(Debug, Default)]
+/// The struct of the counters of the metrics.
+struct MetricsState {
+    /// The requests by provider and status.
+    requests: BTreeMap<(String, &'static str), u64>,
+    /// The tokens by provider and direction.
+    tokens: BTreeMap<(String, &'static str), u64>,
+    /// The durations of the nodes by kind.
+    durations: BTreeMap<&'static str, Histogram>,
+    /// The retries of the nodes by kind.
+    retries: BTreeMap<&'static str, u64>,
+    /// The executions of the cache nodes by result.
+    cache: BTreeMap<&'static str, u64>,
+}
+
+#[derive(Debug, Clone, Default)]
+/// The struct of the metrics of the runs. The clones share the same counters.
+pub struct Metrics {
+    state: Arc<Mutex<MetricsState>>,
+}
+
+impl Metrics {
+    /// Create a new Metrics with every counter at zero.
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Record a node that finished, successfully or not, after the time it took, with the
+    /// usage and the requests of its execution.
+    pub fn record_node(
+        &self,
+        node: &Worknode,
+        duration: Duration,
+        usage: Option<DeepSeekUsage>,
+        requests: RequestCount,
+    ) {
+        let core = node.get_node();
+        let kind = core.kind_name();
+        let mut state = self.lock();
+        if let Some(provider) = core.get_provider() {
+            for (status, count) in [("ok", requests.succeeded), ("error", requests.failed)] {
+                if count > 0 {
+                    *state
+                        .requests
+                        .entry((provider.to_string(), status))
+                        .or_default() += count;
+                }
+            }
+            if let Some(usage) = usage {
+                *state
+                    .tokens
+                    .entry((provider.to_string(), "in"))
+                    .or_default() += usage.get_prompt_tokens().max(0) as u64;
+                *state
+                    .tokens
+                    .entry((provider.to_string(), "out"))
+                    .or_default() += usage.get_completion_tokens().max(0) as u64;
+            }
+        }
+        state
+            .durations
+            .entry(kind)
+            .or_default()
+            .observe(duration.as_secs_f64());
+        *state.retries.entry(kind).or_default() += node.get_attempts().saturating_sub(1) as u64;
+        if let Worknodecore::Cache(cache) = core {
+            let result = if cache.is_hit() { "hit" } else { "miss" };
+            *state.cache.entry(result).or_default() += 1;
+        }
+    }
+    /// Get the metrics in the text format of Prometheus.
+    pub fn render(&self) -> String {
+        let state = self.lock();
+        let mut text = String::new();
+        // writing to a string never fails
+        header(
+            &mut text,
+            "aipilot_requests_total",
+            "counter",
+            "The requests sent to the AI services, by provider and status.",
+        );
+        for ((provider, status), count) in &state.requests {
+            writeln!(
+                text,
+                "aipilot_requests_total{{provider=\"{}\",status=\"{}\"}} {}",
+                escape(provider),
+                status,
+                count
+            )
+            .unwrap();
+        }
+        header(
+            &mut text,
+            "aipilot_tokens_total",
+            "counter",
+            "The tokens of the requests, by provider and direction.",
+        );
+        for ((provider, direction), count) in &state.tokens {
+            writeln!(
+                text,
+                "aipilot_tokens_total{{provider=\"{}\",direction=\"{}\"}} {}",
+                escape(provider),
+                direction,
+                count
+            )
+            .unwrap();
+        }
+        header(
+            &mut text,
+            "aipilot_node_duration_seconds",
+            "histogram",
+            "The time the nodes took, including their retries, by kind.",
+        );
+        for (kind, histogram) in &state.durations {
+            let mut cumulative = 0;
+            for (bound, count) in DURATION_BUCKETS.iter().zip(histogram.buckets) {
+                cumulative += count;
+                writeln!(
+                    text,
+                    "aipilot_node_duration_seconds_bucket{{kind=\"{}\",le=\"{}\"}} {}",
+                    kind, bound, cumulative
+                )
+                .unwrap();
+            }
+            writeln!(
+                text,
+                "aipilot_node_duration_seconds_bucket{{kind=\"{}\",le=\"+Inf\"}} {}",
+                kind, histogram.count
+            )
+            .unwrap();
+            writeln!(
+                text,
+                "aipilot_node_duration_seconds_sum{{kind=\"{}\"}} {}",
+                kind, histogram.sum
+            )
+            .unwrap();
+            writeln!(
+                text,
+                "aipilot_node_duration_seconds_count{{kind=\"{}\"}} {}",
+                kind, histogram.count
+            )
+            .unwrap();
+        }
+        header(
+            &mut text,
+            "aipilot_node_retries_total",
+            "counter",
+            "The attempts of the nodes after the first one, by kind.",
+        );
+        for (kind, count) in &state.retries {
+            writeln!(
+                text,
+                "aipilot_node_retries_total{{kind=\"{}\"}} {}",
+                kind, count
+            )
+            .unwrap();
+        }
+        header(
+            &mut text,
+            "aipilot_cache_total",
+            "counter",
+            "The executions of the cache nodes, by hit or miss.",
+        );
+        for (result, count) in &state.cache {
+            writeln!(
+                text,
+                "aipilot_cache_total{{result=\"{}\"}} {}",
+                result, count
+            )
+            .unwrap();
+        }
+        text
+    }
+    /// Answer the scrapes of `GET /metrics` on the address, like `0.0.0.0:9100`, until the
+    /// future is dropped. Only fails when the address can't be bound.
+    pub async fn serve(&self, address: &str) -> std::io::Result<()> {
+        let listener = TcpListener::bind(address).await?;
+        loop {
+            let (stream, _) = match listener.accept().await {
+                Ok(accepted) => accepted,
+                Err(e) => {
+                    log::warn!("Failed to accept a scrape of the metrics. {}", e);
+                    continue;
+                }
+            };
+            let metrics = self.clone();
+            tokio::spawn(async move {
+                if let Err(e) = metrics.answer(stream).await {
+                    log::warn!("Failed to answer a scrape of the metrics. {}", e);
+                }
+            });
+        }
+    }
+    /// Read one request of the stream and answer it.
+    async fn answer(&self, mut stream: TcpStream) -> std::io::Result<()> {
+        let mut head = Vec::new();
+        let mut buffer = [0; 1024];
+        while !head.windows(4).any(|window| window == b"\r\n\r\n") {
+            let read = stream.read(&mut buffer).await?;
+            if read == 0 || head.len() > MAX_REQUEST_HEAD {
+                break;
+            }
+            head.extend_from_slice(&buffer[..read]);
+        }
+        let head = String::from_utf8_lossy(&head);
+        let mut request_line = head.lines().next().unwrap_or_default().split_whitespace();
+        let response = match (request_line.next(), request_line.next()) {
+            (Some("GET"), Some("/metrics")) => {
+                let body = self.render();
+                format!(
+                    "HTTP/1.1 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    body.len(),
+                    body
+                )
+            }
+            _ => "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+                .to_string(),
+        };
+        stream.write_all(response.as_bytes()).await?;
+        stream.shutdown().await
+    }
+    /// Lock the counters. A panic in another thread doesn't make them invalid.
+    fn lock(&self) -> std::sync::MutexGuard<'_, MetricsState> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// Write the help and the type of a metric.
+fn header(text: &mut String, name: &str, kind: &str, help: &str) {
+    writeln!(text, "# HELP {} {}", name, help).unwrap();
+    writeln!(text, "# TYPE {} {}", name, kind).unwrap();
+}
+
+/// Escape the value of a label.
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::worknode::ai_node::deepseek::{DeepSeekClient, DeepSeekModel, DEEPSEEK_API_URL};
+    use crate::worknode::ai_node::{AINode, AIService};
+    use crate::worknode::cache::CacheNode;
+
+    use tokio::runtime::Runtime;
+
+    #[test]
+    fn record_and_scrape() {
+        let metrics = Metrics::new();
+        metrics.record_node(
+            &Worknode::new(Worknodecore::Start),
+            Duration::from_millis(30),
+            None,
+            RequestCount::default(),
+        );
+        metrics.record_node(
+            &Worknode::new(Worknodecore::Cache(CacheNode::new(Worknodecore::Start))),
+            Duration::from_secs(2),
+            None,
+            RequestCount::default(),
+        );
+        // an AI node whose execution sent three requests, one of them failed
+        let client = DeepSeekClient::new(DEEPSEEK_API_URL, DeepSeekModel::DeepseekChat);
+        let node =
+            AINode::new(AIService::new_deepseek(client)).provider(Some("deepseek".to_string()));
+        let usage = DeepSeekUsage::from_json(&json::object! {
+            completion_tokens: 5,
+            prompt_tokens: 20,
+            prompt_cache_hit_tokens: 0,
+            prompt_cache_miss_tokens: 20,
+            total_tokens: 25
+        })
+        .unwrap();
+        metrics.record_node(
+            &Worknode::new(Worknodecore::AINode(node)),
+            Duration::from_secs(1),
+            Some(usage),
+            RequestCount {
+                succeeded: 2,
+                failed: 1,
+            },
+        );
+        let text = metrics.render();
+        assert!(text.contains("# TYPE aipilot_node_duration_seconds histogram"));
+        assert!(text.contains("aipilot_node_duration_seconds_bucket{kind=\"start\",le=\"0.01\"} 0"));
+        assert!(text.contains("aipilot_node_duration_seconds_bucket{kind=\"start\",le=\"0.05\"} 1"));
+        assert!(text.contains("aipilot_node_duration_seconds_count{kind=\"cache\"} 1"));
+        assert!(text.contains("aipilot_cache_total{result=\"miss\"} 1"));
+        assert!(text.contains("aipilot_node_retries_total{kind=\"start\"} 0"));
+        assert!(text.contains("aipilot_requests_total{provider=\"deepseek\",status=\"ok\"} 2"));
+        assert!(text.contains("aipilot_requests_total{provider=\"deepseek\",status=\"error\"} 1"));
+        assert!(text.contains("aipilot_tokens_total{provider=\"deepseek\",direction=\"in\"} 20"));
+        assert_eq!(escape("a\"b\\"), "a\\\"b\\\\");
+
+        let rt = Runtime::new().unwrap();
+        let scraped = rt.block_on(async {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let address = listener.local_addr().unwrap();
+            drop(listener);
+            let server = tokio::spawn({
+                let metrics = metrics.clone();
+                async move { metrics.serve(&address.to_string()).await }
+            });
+            let mut stream = loop {
+                match TcpStream::connect(address).await {
+                    Ok(stream) => break stream,
+                    Err(_) => tokio::task::yield_now().await,
+                }
+            };
+            stream
+                .write_all(b"GET /metrics HTTP/1.1\r\nHost: localhost\r\n\r\n")
+                .await
+                .unwrap();
+            let mut response = String::new();
+            stream.read_to_string(&mut response).await.unwrap();
+            server.abort();
+            response
+        });
+        assert!(scraped.starts_with("HTTP/1.1 200 OK"));
+        assert!(scraped.ends_with(&metrics.render()));
+    }
+}
@@ -0,0 +1,243 @@
+//! # Preflight
+//!
+//! This module checks, before a run, that the providers and the vector stores that the nodes
+//! of a workflow use can be reached and accept their api keys, so a wrong key or a server
+//! that is down is found before the first paid node runs instead of in the middle of a run.
+//!
+//! Every AI service, embeddings api and rerank api is sent the cheapest request of its api
+//! (see `AIService::healthcheck`), and every vector store is counted. A provider or a store
+//! used by several nodes, also in the subworkflow of a map node or as the child of a cache
+//! node, is checked once, and its problem lists all the nodes that use it. The AI services
+//! are not checked when the workflow replays a recording, since a replayed run sends nothing.
+//!
+//! Like `validate`, `preflight` reports every problem it finds as a `Diagnostic`.
+
+use super::validate::{Diagnostic, DiagnosticKind};
+use super::Workflow;
+use crate::vector_store::VectorStore;
+use crate::worknode::ai_node::embedding::EmbeddingClient;
+use crate::worknode::ai_node::recording::Recording;
+use crate::worknode::ai_node::rerank::RerankClient;
+use crate::worknode::ai_node::AIService;
+use crate::worknode::rerank::Reranker;
+use crate::worknode::Worknodecore;
+
+use uuid::Uuid;
+
+use std::sync::Arc;
+
+/// The enum of what a check is sent to.
+enum Check<'a> {
+    /// An AI service.
+    Service(&'a AIService),
+    /// An embeddings api.
+    Embedding(&'a EmbeddingClient),
+    /// A rerank api.
+    Rerank(&'a RerankClient),
+    /// A vector store.
+    Store(&'a Arc<dyn VectorStore>),
+}
+
+/// The struct of one provider or store to check, with the nodes that use it.
+struct Target<'a> {
+    /// The key that the nodes of the same provider or store share.
+    key: String,
+    /// The name of the provider or the store in the messages.
+    name: String,
+    /// The check.
+    check: Check<'a>,
+    /// The uids of the nodes that use it.
+    nodes: Vec<Uuid>,
+}
+
+impl Workflow {
+    /// Check that the providers and the stores of the nodes can be reached, and get all the
+    /// problems found.
+    pub async fn preflight(&self) -> Result<(), Vec<Diagnostic>> {
+        let mut targets = Vec::new();
+        self.collect_targets(false, &mut targets);
+        let mut diagnostics = Vec::new();
+        for target in targets {
+            let (kind, result) = match target.check {
+                Check::Service(service) => (
+                    DiagnosticKind::ProviderUnavailable,
+                    service.healthcheck().await.map_err(|e| e.to_string()),
+                ),
+                Check::Embedding(client) => (
+                    DiagnosticKind::ProviderUnavailable,
+                    client.healthcheck().await.map_err(|e| e.to_string()),
+                ),
+                Check::Rerank(client) => (
+                    DiagnosticKind::ProviderUnavailable,
+                    client.healthcheck().await.map_err(|e| e.to_string()),
+                ),
+                Check::Store(store) => (
+                    DiagnosticKind::StoreUnavailable,
+                    store.count().map(|_| ()).map_err(|e| e.to_string()),
+                ),
+            };
+            if let Err(e) = result {
+                let message = match kind {
+                    DiagnosticKind::StoreUnavailable => {
+                        format!("The {} can't be read. {}", target.name, e)
+                    }
+                    _ => format!("The {} can't be reached. {}", target.name, e),
+                };
+                diagnostics.push(Diagnostic::new(kind, target.nodes, message));
+            }
+        }
+        if diagnostics.is_empty() {
+            Ok(())
+        } else {
+            Err(diagnostics)
+        }
+    }
+    /// Collect the providers and the stores of the nodes, also of the subworkflows.
+    fn collect_targets<'a>(&'a self, replay: bool, targets: &mut Vec<Target<'a>>) {
+        let replay = replay || self.recording.as_ref().is_some_and(Recording::is_replay);
+        for node in &self.nodes {
+            collect_node(node.get_node(), node.get_uid(), replay, targets);
+        }
+    }
+}
+
+/// Collect the providers and the stores of the node of the uid.
+fn collect_node<'a>(
+    node: &'a Worknodecore,
+    uid: Uuid,
+    replay: bool,
+    targets: &mut Vec<Target<'a>>,
+) {
+    let provider = node.get_provider();
+    let mut add = |check: Check<'a>, store_name: Option<&str>| {
+        if matches!(check, Check::Service(_)) && replay {
+            return;
+        }
+        add_target(targets, check, uid, provider, store_name);
+    };
+    match node {
+        Worknodecore::AINode(node) => add(Check::Service(node.get_service()), None),
+        Worknodecore::Agent(agent) => add(Check::Service(agent.get_node().get_service()), None),
+        Worknodecore::Router(router) => add(Check::Service(router.get_node().get_service()), None),
+        Worknodecore::Planner(planner) => {
+            add(Check::Service(planner.get_node().get_service()), None)
+        }
+        Worknodecore::Reduce(reduce) => {
+            if let Some(node) = reduce.get_node() {
+                add(Check::Service(node.get_service()), None);
+            }
+        }
+        Worknodecore::Rerank(rerank) => match rerank.get_reranker() {
+            Reranker::Api(client) => add(Check::Rerank(client), None),
+            Reranker::Llm(node) => add(Check::Service(node.get_service()), None),
+        },
+        Worknodecore::Embed(embed) => add(Check::Embedding(embed.get_client()), None),
+        Worknodecore::Retrieve(retrieve) => {
+            add(Check::Embedding(retrieve.get_client()), None);
+            add(
+                Check::Store(retrieve.get_store()),
+                retrieve.get_store_name(),
+            );
+        }
+        Worknodecore::Ingest(ingest) => {
+            add(Check::Embedding(ingest.get_ingest().get_client()), None);
+            add(
+                Check::Store(ingest.get_ingest().get_store()),
+                ingest.get_store_name(),
+            );
+        }
+        Worknodecore::Cache(cache) => collect_node(cache.get_node(), uid, replay, targets),
+        Worknodecore::Map(map) => map.get_workflow().collect_targets(replay, targets),
+        _ => {}
+    }
+}
+
+/// Add the check of the node of the uid, or add the node to the target of the same provider
+/// or store.
+fn add_target<'a>(
+    targets: &mut Vec<Target<'a>>,
+    check: Check<'a>,
+    uid: Uuid,
+    provider: Option<&str>,
+    store_name: Option<&str>,
+) {
+    let (key, name) = match (&check, store_name, provider) {
+        (Check::Store(_), Some(name), _) => (format!("store {}", name), format!("store {}", name)),
+        (Check::Store(store), None, _) => (
+            format!("store {:p}", Arc::as_ptr(store)),
+            format!("store of the node {}", uid),
+        ),
+        (_, _, Some(name)) => (format!("provider {}", name), format!("provider {}", name)),
+        (_, _, None) => (
+            format!("provider of {}", uid),
+            format!("provider of the node {}", uid),
+        ),
+    };
+    match targets.iter_mut().find(|target| target.key == key) {
+        Some(target) if target.nodes.contains(&uid) => {}
+        Some(target) => target.nodes.push(uid),
+        None => targets.push(Target {
+            key,
+            name,
+            check,
+            nodes: vec![uid],
+        }),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::vector_store::memory::MemoryVectorStore;
+    use crate::worknode::ai_node::deepseek::{DeepSeekClient, DeepSeekModel, DEEPSEEK_API_URL};
+    use crate::worknode::ai_node::AINode;
+    use crate::worknode::retrieve::RetrieveNode;
+    use crate::worknode::Worknode;
+
+    use tokio::runtime::Runtime;
+
+    #[test]
+    fn preflight_providers() {
+        let service = AIService::new_deepseek(DeepSeekClient::new(
+            DEEPSEEK_API_URL,
+            DeepSeekModel::DeepseekChat,
+        ));
+        let node = || {
+            Worknode::new(Worknodecore::AINode(
+                AINode::new(service.clone()).provider(Some("deepseek".to_string())),
+            ))
+        };
+        let mut workflow = Workflow::new();
+        let first = workflow.add_node(node());
+        let second = workflow.add_node(node());
+        let retrieve = workflow.add_node(Worknode::new(Worknodecore::Retrieve(
+            RetrieveNode::new(
+                EmbeddingClient::openai("text-embedding-3-small"),
+                Arc::new(MemoryVectorStore::new()),
+            )
+            .provider(Some("embed".to_string()))
+            .store_name(Some("docs".to_string())),
+        )));
+
+        let rt = Runtime::new().unwrap();
+        // the clients have no api key, so they fail before sending anything
+        let diagnostics = rt.block_on(workflow.preflight()).unwrap_err();
+        assert_eq!(diagnostics.len(), 2);
+        assert_eq!(
+            diagnostics[0].get_kind(),
+            DiagnosticKind::ProviderUnavailable
+        );
+        assert_eq!(diagnostics[0].get_nodes(), &[first, second]);
+        assert!(diagnostics[0]
+            .get_message()
+            .starts_with("The provider deepseek can't be reached."));
+        assert_eq!(diagnostics[1].get_nodes(), &[retrieve]);
+        assert!(diagnostics[1].get_message().contains("provider embed"));
+
+        // a replayed run sends nothing to the AI services
+        workflow.set_recording(Some(Recording::replay(Vec::new())));
+        let diagnostics = rt.block_on(workflow.preflight()).unwrap_err();
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].get_nodes(), &[retrieve]);
+    }
+}
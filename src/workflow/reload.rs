@@ -0,0 +1,307 @@
+//! # Reload
+//!
+//! This module reloads a workflow when its file changes, so the prompts of a workflow can be
+//! changed without restarting the program.
+//!
+//! A `WorkflowWatcher` holds the workflow loaded from a file, and with `watch` it watches the
+//! file. When the file changes, it is loaded and validated again, and the new workflow
+//! replaces the current one only when it has no problem, so a file saved half edited doesn't
+//! break the program: the last good workflow stays, and the error is logged and kept for
+//! `get_last_error`. A file saved again without change is not reloaded.
+//!
+//! `current` gives a copy of the current workflow to run. A run that started before a reload
+//! finishes on the workflow it got, and only the runs started after it use the new one. Every
+//! reload bumps the version of the watcher, so the caller can tell which version a run used.
+//!
+//! The directory of the file is watched rather than the file, so the editors that save by
+//! replacing the file are seen too.
+
+use super::definition::{definition_error, Registry};
+use super::Workflow;
+use crate::error::PilotResult;
+
+use notify::{RecommendedWatcher, RecursiveMode, Watcher};
+
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, PoisonError, RwLock};
+
+/// The function that loads a workflow from its file.
+pub type WorkflowLoader = Arc<dyn Fn(&Path) -> PilotResult<Workflow> + Send + Sync>;
+
+/// The struct of the state shared with the thread of the watcher.
+struct Shared {
+    /// The workflow file.
+    path: PathBuf,
+    /// The function that loads the file.
+    loader: WorkflowLoader,
+    /// The current workflow, with its version.
+    current: RwLock<(Arc<Workflow>, u64)>,
+    /// The content of the file that the current workflow was loaded from.
+    content: Mutex<Vec<u8>>,
+    /// The error of the last reload that failed, cleared by a reload that succeeds.
+    last_error: Mutex<Option<String>>,
+}
+
+/// The struct of a workflow reloaded when its file changes.
+pub struct WorkflowWatcher {
+    /// The state shared with the thread of the watcher.
+    shared: Arc<Shared>,
+    /// The watcher of the directory of the file, which stops watching when it is dropped.
+    watcher: Option<RecommendedWatcher>,
+}
+
+impl std::fmt::Debug for WorkflowWatcher {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("WorkflowWatcher")
+            .field("path", &self.shared.path)
+            .field("version", &self.get_version())
+            .field("watching", &self.watcher.is_some())
+            .finish()
+    }
+}
+
+impl WorkflowWatcher {
+    /// Load the workflow from the file with the loader, and validate it. The file is not
+    /// watched until `watch` is called.
+    pub fn new<P, F>(path: P, loader: F) -> PilotResult<Self>
+    where
+        P: AsRef<Path>,
+        F: Fn(&Path) -> PilotResult<Workflow> + Send + Sync + 'static,
+    {
+        let path = path.as_ref().to_path_buf();
+        let content = read_file(&path)?;
+        let workflow = load(&path, &loader)?;
+        Ok(WorkflowWatcher {
+            shared: Arc::new(Shared {
+                path,
+                loader: Arc::new(loader),
+                current: RwLock::new((Arc::new(workflow), 1)),
+                content: Mutex::new(content),
+                last_error: Mutex::new(None),
+            }),
+            watcher: None,
+        })
+    }
+    /// Load the workflow from the file with the environment variables in its strings, and
+    /// with the providers, the tools and the reducers of the registry.
+    pub fn with_registry<P: AsRef<Path>>(path: P, registry: Registry) -> PilotResult<Self> {
+        Self::new(path, move |path| Workflow::load_with_env(path, &registry))
+    }
+    /// Start watching the file. Watching a file that is already watched does nothing.
+    pub fn watch(&mut self) -> PilotResult<()> {
+        if self.watcher.is_some() {
+            return Ok(());
+        }
+        let shared = self.shared.clone();
+        let mut watcher =
+            notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+                match event {
+                    Ok(event) if shared.is_changed_by(&event) => {
+                        // the error is logged and kept by the reload
+                        let _ = shared.reload();
+                    }
+                    Ok(_) => {}
+                    Err(e) => log::warn!(
+                        "Failed to watch the workflow file {}. {}",
+                        shared.path.display(),
+                        e
+                    ),
+                }
+            })
+            .map_err(|e| self.watch_error(e))?;
+        let dir = match self.shared.path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir.to_path_buf(),
+            _ => PathBuf::from("."),
+        };
+        watcher
+            .watch(&dir, RecursiveMode::NonRecursive)
+            .map_err(|e| self.watch_error(e))?;
+        self.watcher = Some(watcher);
+        Ok(())
+    }
+    /// Stop watching the file. The current workflow stays.
+    pub fn unwatch(&mut self) {
+        self.watcher = None;
+    }
+    /// Whether the file is watched.
+    pub fn is_watching(&self) -> bool {
+        self.watcher.is_some()
+    }
+    /// Load the file again now, and get the version of the current workflow. The current
+    /// workflow is only replaced when the file changed and the new workflow has no problem.
+    pub fn reload(&self) -> PilotResult<u64> {
+        self.shared.reload()
+    }
+    /// Get a copy of the current workflow to run.
+    pub fn current(&self) -> Workflow {
+        self.shared
+            .current
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .0
+            .as_ref()
+            .clone()
+    }
+    /// Get the version of the current workflow, which starts at 1 and goes up with every
+    /// reload.
+    pub fn get_version(&self) -> u64 {
+        self.shared
+            .current
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .1
+    }
+    /// Get the workflow file.
+    pub fn get_path(&self) -> &Path {
+        &self.shared.path
+    }
+    /// Get the error of the last reload, if it failed.
+    pub fn get_last_error(&self) -> Option<String> {
+        self.shared
+            .last_error
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+    /// The error of a watcher that can't watch the file.
+    fn watch_error(&self, e: notify::Error) -> crate::error::PilotError {
+        definition_error(format!(
+            "Can't watch the workflow file {}. {}",
+            self.shared.path.display(),
+            e
+        ))
+    }
+}
+
+impl Shared {
+    /// Whether the event changed the workflow file.
+    fn is_changed_by(&self, event: &notify::Event) -> bool {
+        (event.kind.is_create() || event.kind.is_modify())
+            && event
+                .paths
+                .iter()
+                .any(|path| path.file_name() == self.path.file_name())
+    }
+    /// Load the file again if it changed, and replace the current workflow. The error is
+    /// logged and kept.
+    fn reload(&self) -> PilotResult<u64> {
+        let result = self.try_reload();
+        let mut last_error = self
+            .last_error
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        match &result {
+            Ok(_) => *last_error = None,
+            Err(e) => {
+                log::warn!(
+                    "Failed to reload the workflow file {}, the last version is kept. {}",
+                    self.path.display(),
+                    e
+                );
+                *last_error = Some(e.to_string());
+            }
+        }
+        result
+    }
+    /// Reload the file, without keeping the error.
+    fn try_reload(&self) -> PilotResult<u64> {
+        let content = read_file(&self.path)?;
+        let mut last = self.content.lock().unwrap_or_else(PoisonError::into_inner);
+        if *last == content {
+            return Ok(self
+                .current
+                .read()
+                .unwrap_or_else(PoisonError::into_inner)
+                .1);
+        }
+        let workflow = load(&self.path, self.loader.as_ref())?;
+        *last = content;
+        let mut current = self.current.write().unwrap_or_else(PoisonError::into_inner);
+        *current = (Arc::new(workflow), current.1 + 1);
+        log::info!(
+            "Reloaded the workflow file {} as version {}.",
+            self.path.display(),
+            current.1
+        );
+        Ok(current.1)
+    }
+}
+
+/// Load the workflow with the loader, and check that it has no problem.
+fn load<F>(path: &Path, loader: &F) -> PilotResult<Workflow>
+where
+    F: Fn(&Path) -> PilotResult<Workflow> + ?Sized,
+{
+    let workflow = loader(path)?;
+    workflow.validate().map_err(|diagnostics| {
+        let diagnostics: Vec<String> = diagnostics.iter().map(ToString::to_string).collect();
+        definition_error(format!(
+            "The workflow file {} is not valid: {}.",
+            path.display(),
+            diagnostics.join("; ")
+        ))
+    })?;
+    Ok(workflow)
+}
+
+/// Read the content of the workflow file.
+fn read_file(path: &Path) -> PilotResult<Vec<u8>> {
+    std::fs::read(path).map_err(|e| {
+        definition_error(format!(
+            "Can't read the workflow file {}. {}",
+            path.display(),
+            e
+        ))
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::worknode::{Worknode, Worknodecore};
+
+    use std::time::{Duration, Instant};
+
+    /// A workflow of a start node and an end node, with the max parallelism.
+    fn definition(max_parallelism: usize) -> String {
+        let mut workflow = Workflow::new().max_parallelism(max_parallelism);
+        let start = workflow.add_node(Worknode::new(Worknodecore::Start));
+        let end = workflow.add_node(Worknode::new(Worknodecore::End));
+        workflow.add_edge(start, end).unwrap();
+        workflow.to_yaml().unwrap()
+    }
+
+    #[test]
+    fn reload_on_change() {
+        let dir = std::env::temp_dir().join(format!("aipilot-reload-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir(&dir).unwrap();
+        let path = dir.join("workflow.yaml");
+        std::fs::write(&path, definition(2)).unwrap();
+        let mut watcher = WorkflowWatcher::with_registry(&path, Registry::new()).unwrap();
+        assert_eq!(watcher.get_version(), 1);
+        let running = watcher.current();
+
+        // a saved file without change is not reloaded
+        assert_eq!(watcher.reload().unwrap(), 1);
+
+        // a file that is not valid keeps the last version
+        std::fs::write(&path, "nodes: []").unwrap();
+        assert!(watcher.reload().is_err());
+        assert!(watcher.get_last_error().unwrap().contains("not valid"));
+        assert_eq!(watcher.current().get_max_parallelism(), 2);
+
+        watcher.watch().unwrap();
+        std::fs::write(&path, definition(5)).unwrap();
+        let started = Instant::now();
+        while watcher.get_version() == 1 && started.elapsed() < Duration::from_secs(10) {
+            std::thread::sleep(Duration::from_millis(20));
+        }
+        assert_eq!(watcher.get_version(), 2);
+        assert_eq!(watcher.current().get_max_parallelism(), 5);
+        assert_eq!(watcher.get_last_error(), None);
+        // the run that got the old version keeps it
+        assert_eq!(running.get_max_parallelism(), 2);
+        watcher.unwatch();
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
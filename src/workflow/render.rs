@@ -0,0 +1,201 @@
+//! # Render
+//!
+//! This module draws a workflow as a diagram, in the Graphviz DOT format or in the Mermaid
+//! format, so a graph can be looked at and put in the documents.
+//!
+//! Every node is labeled with its type, the provider of its AI service if it has one, and the
+//! first part of its uid. An edge wired into a port is labeled with the port, a route edge with
+//! its label, and the rejected edges of the approval nodes are dashed.
+
+use super::{Edge, EdgeKind, Workflow};
+use crate::worknode::{Worknode, Worknodecore};
+
+use uuid::Uuid;
+
+use std::collections::HashMap;
+
+/// The number of characters of the uid shown in a label.
+pub(crate) const SHORT_UID_LEN: usize = 8;
+
+/// The enum of the shape of a node in a diagram.
+enum Shape {
+    /// The start and end nodes.
+    Terminal,
+    /// The join nodes.
+    Join,
+    /// The approval and router nodes.
+    Gate,
+    /// The other nodes.
+    Task,
+}
+
+impl Shape {
+    /// Get the shape of the node.
+    fn of(node: &Worknodecore) -> Shape {
+        match node {
+            Worknodecore::Start | Worknodecore::End => Shape::Terminal,
+            Worknodecore::Join(_) | Worknodecore::Reduce(_) => Shape::Join,
+            Worknodecore::Approval(_) | Worknodecore::Router(_) => Shape::Gate,
+            _ => Shape::Task,
+        }
+    }
+}
+
+/// Get the label of the edge: its route label or its port, or the kind of the edge if it is
+/// not a normal one.
+fn edge_label(edge: &Edge) -> Option<&str> {
+    match edge.kind {
+        EdgeKind::Normal => edge.route.as_deref().or(edge.port.as_deref()),
+        EdgeKind::Rejected => Some("rejected"),
+        EdgeKind::OnError => Some("on_error"),
+    }
+}
+
+/// Get the lines of the label of the node.
+fn label_lines(node: &Worknode) -> Vec<String> {
+    let mut lines = vec![node.get_node().kind_name().to_string()];
+    if let Some(provider) = node.get_node().get_provider() {
+        lines.push(provider.to_string());
+    }
+    let uid = node.get_uid().simple().to_string();
+    lines.push(uid[..SHORT_UID_LEN].to_string());
+    lines
+}
+
+impl Workflow {
+    /// Draw the workflow in the Graphviz DOT format.
+    pub fn to_dot(&self) -> String {
+        let escape = |text: &str| text.replace('\\', "\\\\").replace('"', "\\\"");
+        let mut dot = String::from("digraph workflow {\n    rankdir=TB;\n");
+        for (index, node) in self.nodes.iter().enumerate() {
+            let label: Vec<String> = label_lines(node).iter().map(|l| escape(l)).collect();
+            let shape = match Shape::of(node.get_node()) {
+                Shape::Terminal => "oval",
+                Shape::Join => "diamond",
+                Shape::Gate => "hexagon",
+                Shape::Task => "box",
+            };
+            dot.push_str(&format!(
+                "    n{} [label=\"{}\", shape={}];\n",
+                index,
+                label.join("\\n"),
+                shape
+            ));
+        }
+        let indices = self.node_indices();
+        for edge in &self.edges {
+            let (Some(from), Some(to)) = (indices.get(&edge.from), indices.get(&edge.to)) else {
+                continue;
+            };
+            let mut attributes = Vec::new();
+            if let Some(label) = edge_label(edge) {
+                attributes.push(format!("label=\"{}\"", escape(label)));
+            }
+            if edge.kind != EdgeKind::Normal {
+                attributes.push("style=dashed".to_string());
+            }
+            if attributes.is_empty() {
+                dot.push_str(&format!("    n{} -> n{};\n", from, to));
+            } else {
+                dot.push_str(&format!(
+                    "    n{} -> n{} [{}];\n",
+                    from,
+                    to,
+                    attributes.join(", ")
+                ));
+            }
+        }
+        dot.push_str("}\n");
+        dot
+    }
+    /// Draw the workflow in the Mermaid flowchart format.
+    pub fn to_mermaid(&self) -> String {
+        let escape = |text: &str| text.replace('"', "#quot;").replace('|', "#124;");
+        let mut mermaid = String::from("flowchart TD\n");
+        for (index, node) in self.nodes.iter().enumerate() {
+            let label: Vec<String> = label_lines(node).iter().map(|l| escape(l)).collect();
+            let label = label.join("<br/>");
+            let shape = match Shape::of(node.get_node()) {
+                Shape::Terminal => format!("([\"{}\"])", label),
+                Shape::Join => format!("{{\"{}\"}}", label),
+                Shape::Gate => format!("{{{{\"{}\"}}}}", label),
+                Shape::Task => format!("[\"{}\"]", label),
+            };
+            mermaid.push_str(&format!("    n{}{}\n", index, shape));
+        }
+        let indices = self.node_indices();
+        for edge in &self.edges {
+            let (Some(from), Some(to)) = (indices.get(&edge.from), indices.get(&edge.to)) else {
+                continue;
+            };
+            let arrow = match edge.kind {
+                EdgeKind::Normal => "-->",
+                EdgeKind::Rejected | EdgeKind::OnError => "-.->",
+            };
+            match edge_label(edge) {
+                Some(label) => mermaid.push_str(&format!(
+                    "    n{} {}|{}| n{}\n",
+                    from,
+                    arrow,
+                    escape(label),
+                    to
+                )),
+                None => mermaid.push_str(&format!("    n{} {} n{}\n", from, arrow, to)),
+            }
+        }
+        mermaid
+    }
+    /// Get the index of every node by its uid, which names the node in a diagram.
+    fn node_indices(&self) -> HashMap<Uuid, usize> {
+        self.nodes
+            .iter()
+            .enumerate()
+            .map(|(index, node)| (node.get_uid(), index))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::worknode::join::{JoinNode, JoinStrategy};
+
+    fn workflow() -> Workflow {
+        let mut workflow = Workflow::new();
+        let start = workflow.add_node(Worknode::new(Worknodecore::Start));
+        let join = workflow.add_node(Worknode::new(Worknodecore::Join(JoinNode::new(
+            JoinStrategy::JsonArray,
+        ))));
+        let end = workflow.add_node(Worknode::new(Worknodecore::End));
+        workflow.add_edge(start, join).unwrap();
+        workflow.add_port_edge(join, end, "input").unwrap();
+        workflow
+    }
+
+    #[test]
+    fn render_dot() {
+        let workflow = workflow();
+        let dot = workflow.to_dot();
+        let uid = workflow.get_nodes()[0].get_uid().simple().to_string();
+        assert!(dot.starts_with("digraph workflow {"));
+        assert!(dot.contains(&format!(
+            "n0 [label=\"start\\n{}\", shape=oval];",
+            &uid[..8]
+        )));
+        assert!(dot.contains("shape=diamond"));
+        assert!(dot.contains("n0 -> n1;"));
+        assert!(dot.contains("n1 -> n2 [label=\"input\"];"));
+        assert!(dot.trim_end().ends_with('}'));
+    }
+
+    #[test]
+    fn render_mermaid() {
+        let workflow = workflow();
+        let mermaid = workflow.to_mermaid();
+        let uid = workflow.get_nodes()[1].get_uid().simple().to_string();
+        assert!(mermaid.starts_with("flowchart TD\n"));
+        assert!(mermaid.contains(&format!("n1{{\"join<br/>{}\"}}", &uid[..8])));
+        assert!(mermaid.contains("n0 --> n1\n"));
+        assert!(mermaid.contains("n1 -->|input| n2\n"));
+    }
+}
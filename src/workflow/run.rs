@@ -0,0 +1,541 @@
+//! # Run
+//!
+//! This module runs a workflow in the background and lets the caller cancel it.
+//!
+//! `Workflow::run_detached` moves the workflow into a tokio task and returns a `RunHandle`.
+//! `RunHandle::cancel` cancels the token of the run: the scheduler stops spawning nodes, and
+//! every running node is stopped at once, including the requests it is sending and the waits
+//! between its retries. `RunHandle::join` gives the workflow back with a `RunReport`, which
+//! tells whether the run completed, failed or was cancelled. The handle also decides the
+//! requests of the approval nodes of the run.
+//!
+//! The report also has the trace of the run: a `NodeReport` for every node that finished or
+//! was skipped, with its input, output, error, attempts, duration, token usage and cost. The
+//! cost is known for the providers that have a `Pricing` in the workflow. The usage and the
+//! cost are also added up by node and by provider in the `CostLedger` of the report (see
+//! [`super::cost`]). A report can be saved as json to audit a run later, or rendered as
+//! Markdown for a human. In the json, the error of the run is the whole error with its code
+//! (see [`crate::error`]), and the nodes that failed have the code of their error. A workflow
+//! configured by a config also records the profile and the overrides of the config (see
+//! [`crate::config`]).
+
+use super::context::RunContext;
+use super::cost::CostLedger;
+use super::render::SHORT_UID_LEN;
+use super::Workflow;
+use crate::config::ConfigOrigin;
+use crate::error::graph_error::GraphErrorType;
+use crate::error::{PilotError, PilotErrorType, PilotResult};
+use crate::worknode::ai_node::deepseek::DeepSeekUsage;
+use crate::worknode::Worknode;
+
+use serde::{Deserialize, Serialize};
+use tokio::task::JoinHandle;
+use tokio_util::sync::CancellationToken;
+use uuid::Uuid;
+
+use std::time::Duration;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+/// The enum of how a run ended.
+pub enum RunStatus {
+    /// The end node was reached.
+    Completed,
+    /// A node failed, or the workflow is not valid.
+    Failed,
+    /// The run was cancelled.
+    Cancelled,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+/// The enum of how a node ended.
+pub enum NodeStatus {
+    /// The node gave an output.
+    Completed,
+    /// The node failed after all its retries, or was stopped.
+    Failed,
+    /// The node didn't run, because none of its incoming edges was followed.
+    Skipped,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+/// The struct of the price of a provider, in any currency per million tokens.
+pub struct Pricing {
+    /// The price of the request tokens that hit the cache.
+    pub prompt_cache_hit: f64,
+    /// The price of the request tokens that miss the cache.
+    pub prompt_cache_miss: f64,
+    /// The price of the response tokens.
+    pub completion: f64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+/// The struct of the trace of a node in a run.
+pub struct NodeReport {
+    /// The uid of the node.
+    pub node: Uuid,
+    /// The type of the node, like `ai_node`.
+    pub kind: String,
+    /// The provider of the AI service of the node.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub provider: Option<String>,
+    /// How the node ended.
+    pub status: NodeStatus,
+    /// The input of the node, if it ran.
+    pub input: Option<String>,
+    /// The output of the node, if it completed.
+    pub output: Option<String>,
+    /// The error of the node, if it failed.
+    pub error: Option<String>,
+    /// The code of the error of the node, if it failed (see [`PilotError::code`]).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error_code: Option<String>,
+    /// The number of attempts, including the retries.
+    pub attempts: usize,
+    /// The time the node took, including its retries.
+    pub duration: Duration,
+    /// The usage statistics of the requests of the node, for the AI and agent nodes.
+    pub usage: Option<DeepSeekUsage>,
+    /// The cost of the usage, if the price of the provider is known.
+    pub cost: Option<f64>,
+}
+
+#[derive(Debug, Serialize)]
+/// The struct of the result of a run.
+pub struct RunReport {
+    /// How the run ended.
+    status: RunStatus,
+    /// The output of the end node, if the run completed.
+    output: Option<String>,
+    /// The error of the run, if it failed or was cancelled.
+    error: Option<PilotError>,
+    /// The time the run took.
+    duration: Duration,
+    /// The trace of the nodes, in the order they finished.
+    nodes: Vec<NodeReport>,
+    /// The usage and the cost of the nodes.
+    ledger: CostLedger,
+    /// Where the settings of the workflow were read from, if it was configured by a config.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    config: Option<ConfigOrigin>,
+}
+
+impl Pricing {
+    /// Create a new Pricing with the prices per million tokens.
+    pub fn new(prompt_cache_hit: f64, prompt_cache_miss: f64, completion: f64) -> Self {
+        Pricing {
+            prompt_cache_hit,
+            prompt_cache_miss,
+            completion,
+        }
+    }
+    /// Get the cost of the usage.
+    pub fn cost(&self, usage: &DeepSeekUsage) -> f64 {
+        (usage.get_prompt_cache_hit_tokens() as f64 * self.prompt_cache_hit
+            + usage.get_prompt_cache_miss_tokens() as f64 * self.prompt_cache_miss
+            + usage.get_completion_tokens() as f64 * self.completion)
+            / 1_000_000.0
+    }
+}
+
+impl NodeReport {
+    /// Create the NodeReport of a skipped node.
+    pub(super) fn skipped(node: Uuid, worknode: &Worknode) -> Self {
+        NodeReport {
+            node,
+            kind: worknode.get_node().kind_name().to_string(),
+            provider: worknode.get_node().get_provider().map(str::to_string),
+            status: NodeStatus::Skipped,
+            input: None,
+            output: None,
+            error: None,
+            error_code: None,
+            attempts: 0,
+            duration: Duration::ZERO,
+            usage: None,
+            cost: None,
+        }
+    }
+}
+
+impl RunStatus {
+    /// Get how the run ended from its result.
+    pub fn of<T>(result: &Result<T, PilotError>) -> RunStatus {
+        match result {
+            Ok(_) => RunStatus::Completed,
+            Err(error) if is_cancelled_error(error) => RunStatus::Cancelled,
+            Err(_) => RunStatus::Failed,
+        }
+    }
+}
+
+impl RunReport {
+    /// Create the report of a run from its result and the trace of its nodes.
+    pub(super) fn new(
+        result: Result<String, PilotError>,
+        duration: Duration,
+        nodes: Vec<NodeReport>,
+        config: Option<ConfigOrigin>,
+    ) -> Self {
+        let status = RunStatus::of(&result);
+        let (output, error) = match result {
+            Ok(output) => (Some(output), None),
+            Err(error) => (None, Some(error)),
+        };
+        RunReport {
+            status,
+            output,
+            error,
+            duration,
+            ledger: CostLedger::from_trace(&nodes),
+            nodes,
+            config,
+        }
+    }
+    /// Get how the run ended.
+    pub fn get_status(&self) -> RunStatus {
+        self.status
+    }
+    /// Whether the run was cancelled.
+    pub fn is_cancelled(&self) -> bool {
+        self.status == RunStatus::Cancelled
+    }
+    /// Get the output of the end node.
+    pub fn get_output(&self) -> Option<&str> {
+        self.output.as_deref()
+    }
+    /// Get the error of the run.
+    pub fn get_error(&self) -> Option<&PilotError> {
+        self.error.as_ref()
+    }
+    /// Get the time the run took.
+    pub fn get_duration(&self) -> Duration {
+        self.duration
+    }
+    /// Get the trace of the nodes, in the order they finished.
+    pub fn get_nodes(&self) -> &Vec<NodeReport> {
+        &self.nodes
+    }
+    /// Get the trace of the node.
+    pub fn get_node(&self, node: Uuid) -> Option<&NodeReport> {
+        self.nodes.iter().find(|report| report.node == node)
+    }
+    /// Get the usage and the cost of the nodes by node and by provider.
+    pub fn get_ledger(&self) -> &CostLedger {
+        &self.ledger
+    }
+    /// Get where the settings of the workflow were read from.
+    pub fn get_config(&self) -> Option<&ConfigOrigin> {
+        self.config.as_ref()
+    }
+    /// Get the total number of tokens used by the nodes.
+    pub fn total_tokens(&self) -> i64 {
+        self.nodes
+            .iter()
+            .filter_map(|report| report.usage)
+            .map(|usage| usage.get_total_tokens())
+            .sum()
+    }
+    /// Get the total cost of the nodes whose price is known.
+    pub fn total_cost(&self) -> f64 {
+        self.nodes.iter().filter_map(|report| report.cost).sum()
+    }
+    /// Write the report as json.
+    pub fn to_json(&self) -> String {
+        // the keys are all strings, so it can't fail
+        serde_json::to_string_pretty(self).unwrap_or_default()
+    }
+    /// Render the report as Markdown: a summary, a table of the nodes, and the input and the
+    /// output of every node that ran.
+    pub fn to_markdown(&self) -> String {
+        let mut markdown = String::from("# Run report\n\n");
+        markdown.push_str(&format!("- Status: {}\n", status_name(&self.status)));
+        markdown.push_str(&format!("- Duration: {:.2?}\n", self.duration));
+        markdown.push_str(&format!("- Tokens: {}\n", self.total_tokens()));
+        if self.nodes.iter().any(|report| report.cost.is_some()) {
+            markdown.push_str(&format!("- Cost: {:.6}\n", self.total_cost()));
+        }
+        if let Some(profile) = self
+            .config
+            .as_ref()
+            .and_then(|config| config.profile.as_ref())
+        {
+            markdown.push_str(&format!("- Profile: {}\n", profile));
+        }
+        if let Some(output) = &self.output {
+            markdown.push_str(&format!("\n## Output\n\n{}", code_block(output)));
+        }
+        if let Some(error) = &self.error {
+            markdown.push_str(&format!("\n## Error\n\n{}", code_block(&error.to_string())));
+        }
+        markdown.push_str("\n## Nodes\n\n");
+        markdown.push_str("| Node | Type | Status | Attempts | Duration | Tokens | Cost |\n");
+        markdown.push_str("| --- | --- | --- | --- | --- | --- | --- |\n");
+        for report in &self.nodes {
+            markdown.push_str(&format!(
+                "| `{}` | {} | {} | {} | {:.2?} | {} | {} |\n",
+                short_uid(report.node),
+                report.kind,
+                node_status_name(&report.status),
+                report.attempts,
+                report.duration,
+                report
+                    .usage
+                    .map(|usage| usage.get_total_tokens().to_string())
+                    .unwrap_or_default(),
+                report
+                    .cost
+                    .map(|cost| format!("{:.6}", cost))
+                    .unwrap_or_default()
+            ));
+        }
+        let providers = self.ledger.by_provider();
+        if !providers.is_empty() {
+            markdown.push_str("\n## Providers\n\n");
+            markdown.push_str("| Provider | Executions | Tokens | Cost |\n");
+            markdown.push_str("| --- | --- | --- | --- |\n");
+            for (provider, entry) in providers {
+                markdown.push_str(&format!(
+                    "| {} | {} | {} | {} |\n",
+                    provider,
+                    entry.executions,
+                    entry.usage.get_total_tokens(),
+                    entry
+                        .cost
+                        .map(|cost| format!("{:.6}", cost))
+                        .unwrap_or_default()
+                ));
+            }
+        }
+        for report in self.nodes.iter().filter(|report| report.input.is_some()) {
+            markdown.push_str(&format!(
+                "\n### `{}` {}\n",
+                short_uid(report.node),
+                report.kind
+            ));
+            let sections = [
+                ("Input", &report.input),
+                ("Output", &report.output),
+                ("Error", &report.error),
+            ];
+            for (title, text) in sections {
+                if let Some(text) = text {
+                    markdown.push_str(&format!("\n{}:\n\n{}", title, code_block(text)));
+                }
+            }
+        }
+        markdown
+    }
+    /// Get the result of the run.
+    pub fn into_result(self) -> Result<String, PilotError> {
+        match self.error {
+            Some(error) => Err(error),
+            None => Ok(self.output.unwrap_or_default()),
+        }
+    }
+}
+
+/// The struct of a run in the background.
+pub struct RunHandle {
+    /// The token that cancels the run.
+    token: CancellationToken,
+    /// The run context shared by the nodes.
+    context: RunContext,
+    /// The task that runs the workflow.
+    task: JoinHandle<(Workflow, RunReport)>,
+}
+
+impl RunHandle {
+    /// Cancel the run. The nodes that are running are stopped at once.
+    pub fn cancel(&self) {
+        self.token.cancel();
+    }
+    /// Whether the run is cancelled.
+    pub fn is_cancelled(&self) -> bool {
+        self.token.is_cancelled()
+    }
+    /// Get the token that cancels the run, so other tasks can cancel it or wait for it.
+    pub fn get_token(&self) -> CancellationToken {
+        self.token.clone()
+    }
+    /// Get the run context shared by the nodes.
+    pub fn get_context(&self) -> &RunContext {
+        &self.context
+    }
+    /// Approve the request of the approval node.
+    pub fn approve(&self, node: Uuid) -> PilotResult<()> {
+        self.context.approvals().approve(node)
+    }
+    /// Reject the request of the approval node with the reason.
+    pub fn reject(&self, node: Uuid, reason: &str) -> PilotResult<()> {
+        self.context.approvals().reject(node, reason)
+    }
+    /// Whether the run is finished.
+    pub fn is_finished(&self) -> bool {
+        self.task.is_finished()
+    }
+    /// Wait for the run to finish, and get the workflow back with the report of the run.
+    pub async fn join(self) -> (Workflow, RunReport) {
+        match self.task.await {
+            Ok(finished) => finished,
+            Err(e) => std::panic::resume_unwind(e.into_panic()),
+        }
+    }
+}
+
+impl Workflow {
+    /// Run the workflow in the background, and get a handle to cancel or join the run. It must
+    /// be called in a tokio runtime.
+    pub fn run_detached(self, input: String) -> RunHandle {
+        self.run_detached_with_context(input, RunContext::new())
+    }
+    /// Run the workflow in the background in the run context.
+    pub fn run_detached_with_context(mut self, input: String, context: RunContext) -> RunHandle {
+        let token = CancellationToken::new();
+        let run_token = token.clone();
+        let run_context = context.clone();
+        let task = tokio::spawn(async move {
+            let report = self.run_report(input, &run_context, &run_token).await;
+            (self, report)
+        });
+        RunHandle {
+            token,
+            context,
+            task,
+        }
+    }
+}
+
+/// Get the name of the status of a run.
+fn status_name(status: &RunStatus) -> &'static str {
+    match status {
+        RunStatus::Completed => "completed",
+        RunStatus::Failed => "failed",
+        RunStatus::Cancelled => "cancelled",
+    }
+}
+
+/// Get the name of the status of a node.
+fn node_status_name(status: &NodeStatus) -> &'static str {
+    match status {
+        NodeStatus::Completed => "completed",
+        NodeStatus::Failed => "failed",
+        NodeStatus::Skipped => "skipped",
+    }
+}
+
+/// Get the first characters of the uid, which are enough to tell the nodes apart.
+fn short_uid(uid: Uuid) -> String {
+    uid.simple().to_string()[..SHORT_UID_LEN].to_string()
+}
+
+/// Put the text in a Markdown code block, with a fence longer than any run of backticks in
+/// the text.
+fn code_block(text: &str) -> String {
+    let longest = text.split(|c| c != '`').map(str::len).max().unwrap_or(0);
+    let fence = "`".repeat(longest.max(2) + 1);
+    format!(
+        "{}text\n{}\n{}\n",
+        fence,
+        text.trim_end_matches('\n'),
+        fence
+    )
+}
+
+/// Whether the error is the one of a cancelled run.
+fn is_cancelled_error(error: &PilotError) -> bool {
+    matches!(
+        error.get_error_type(),
+        PilotErrorType::GraphErr(e) if matches!(e.get_error_type(), GraphErrorType::Cancelled)
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::worknode::approval::ApprovalNode;
+    use crate::worknode::join::JoinNode;
+    use crate::worknode::Worknodecore;
+
+    fn workflow() -> Workflow {
+        let mut workflow = Workflow::new();
+        let start = workflow.add_node(Worknode::new(Worknodecore::Start));
+        let end = workflow.add_node(Worknode::new(Worknodecore::End));
+        workflow.add_edge(start, end).unwrap();
+        workflow
+    }
+
+    #[test]
+    fn run_and_cancel() {
+        // a single thread runtime doesn't start the run before it is cancelled
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        let (workflow, report) =
+            rt.block_on(async { workflow().run_detached("hi".to_string()).join().await });
+        assert_eq!(report.get_status(), RunStatus::Completed);
+        assert_eq!(report.get_output(), Some("hi"));
+
+        let (_, report) = rt.block_on(async move {
+            let handle = workflow.run_detached("hi".to_string());
+            handle.cancel();
+            handle.join().await
+        });
+        assert!(report.is_cancelled());
+        assert!(report.into_result().is_err());
+    }
+
+    #[test]
+    fn node_trace() {
+        let mut workflow = Workflow::new();
+        let start = workflow.add_node(Worknode::new(Worknodecore::Start));
+        let failing = workflow.add_node(Worknode::new(Worknodecore::Approval(ApprovalNode::new(
+            "{{missing}}",
+        ))));
+        let next = workflow.add_node(Worknode::new(Worknodecore::Join(JoinNode::default())));
+        let end = workflow.add_node(Worknode::new(Worknodecore::End));
+        workflow.add_edge(start, failing).unwrap();
+        workflow.add_edge(failing, next).unwrap();
+        workflow.add_edge(next, end).unwrap();
+        workflow.add_error_edge(failing, end).unwrap();
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        let report = rt.block_on(workflow.run_report(
+            "hi".to_string(),
+            &RunContext::new(),
+            &CancellationToken::new(),
+        ));
+        assert_eq!(report.get_status(), RunStatus::Completed);
+        assert_eq!(report.get_nodes().len(), 4);
+        let trace = report.get_node(start).unwrap();
+        assert_eq!(trace.status, NodeStatus::Completed);
+        assert_eq!(trace.input.as_deref(), Some("hi"));
+        assert_eq!(trace.attempts, 1);
+        assert_eq!(report.get_node(failing).unwrap().status, NodeStatus::Failed);
+        assert_eq!(report.get_node(next).unwrap().status, NodeStatus::Skipped);
+
+        let json: serde_json::Value = serde_json::from_str(&report.to_json()).unwrap();
+        assert_eq!(json["status"], "completed");
+        assert_eq!(json["nodes"][0]["kind"], "start");
+        let markdown = report.to_markdown();
+        assert!(markdown.contains("- Status: completed"));
+        assert!(markdown.contains(&format!("| `{}` | join | skipped |", short_uid(next))));
+    }
+
+    #[test]
+    fn pricing_cost() {
+        let usage: DeepSeekUsage = serde_json::from_value(serde_json::json!({
+            "completion_tokens": 1000,
+            "prompt_tokens": 3000,
+            "prompt_cache_hit_tokens": 1000,
+            "prompt_cache_miss_tokens": 2000,
+            "total_tokens": 4000
+        }))
+        .unwrap();
+        let cost = Pricing::new(0.5, 2.0, 8.0).cost(&usage);
+        assert!((cost - 0.0125).abs() < 1e-12);
+        assert_eq!(code_block("a ``` b"), "````text\na ``` b\n````\n");
+    }
+}
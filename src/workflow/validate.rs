@@ -0,0 +1,333 @@
+//! # Validate
+//!
+//! This module checks a workflow graph before it runs, and reports every problem found as a
+//! `Diagnostic` instead of failing in the middle of a run.
+//!
+//! The checks are:
+//! - there is exactly one start node and one end node;
+//! - there is no cycle;
+//! - every node can be reached from the start node;
+//! - every edge connects two nodes of the workflow, doesn't go into the start node and doesn't
+//!   leave the end node;
+//! - every port of an edge is an input port of its target node.
+
+use super::Workflow;
+use crate::worknode::Worknodecore;
+
+use uuid::Uuid;
+
+use std::collections::{HashSet, VecDeque};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// The enum of the kind of a problem in the workflow.
+pub enum DiagnosticKind {
+    /// There is no start node.
+    MissingStart,
+    /// There are more than one start nodes.
+    MultipleStart,
+    /// There is no end node.
+    MissingEnd,
+    /// There are more than one end nodes.
+    MultipleEnd,
+    /// Some nodes form a cycle.
+    Cycle,
+    /// A node can't be reached from the start node.
+    Unreachable,
+    /// An edge has a missing endpoint, goes into the start node or leaves the end node.
+    DanglingEdge,
+    /// The port of an edge is not an input port of its target node.
+    IncompatiblePort,
+    /// The label of a route edge is not one that its source router node can choose.
+    UnknownRoute,
+    /// A provider can't be reached or refuses its api key, found by `preflight`.
+    ProviderUnavailable,
+    /// A vector store can't be read, found by `preflight`.
+    StoreUnavailable,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// The struct of one problem in the workflow.
+pub struct Diagnostic {
+    /// The kind of the problem.
+    kind: DiagnosticKind,
+    /// The uids of the nodes involved.
+    nodes: Vec<Uuid>,
+    /// What is wrong.
+    message: String,
+}
+
+impl Diagnostic {
+    /// Create a new Diagnostic.
+    pub fn new(kind: DiagnosticKind, nodes: Vec<Uuid>, message: String) -> Self {
+        Diagnostic {
+            kind,
+            nodes,
+            message,
+        }
+    }
+    /// Get the kind of the problem.
+    pub fn get_kind(&self) -> DiagnosticKind {
+        self.kind
+    }
+    /// Get the uids of the nodes involved.
+    pub fn get_nodes(&self) -> &[Uuid] {
+        &self.nodes
+    }
+    /// Get the message.
+    pub fn get_message(&self) -> &str {
+        &self.message
+    }
+}
+
+impl std::fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}: {}", self.kind, self.message)
+    }
+}
+
+impl Workflow {
+    /// Check the workflow and get all the problems found.
+    pub fn validate(&self) -> Result<(), Vec<Diagnostic>> {
+        let mut diagnostics = Vec::new();
+        self.validate_start_end(&mut diagnostics);
+        self.validate_edges(&mut diagnostics);
+        self.validate_cycles(&mut diagnostics);
+        self.validate_reachability(&mut diagnostics);
+        if diagnostics.is_empty() {
+            Ok(())
+        } else {
+            Err(diagnostics)
+        }
+    }
+    /// Check that there is exactly one start node and one end node.
+    fn validate_start_end(&self, diagnostics: &mut Vec<Diagnostic>) {
+        let starts = self.find_nodes(|node| matches!(node, Worknodecore::Start));
+        match starts.len() {
+            0 => diagnostics.push(Diagnostic::new(
+                DiagnosticKind::MissingStart,
+                starts,
+                "The workflow has no start node.".to_string(),
+            )),
+            1 => {}
+            n => diagnostics.push(Diagnostic::new(
+                DiagnosticKind::MultipleStart,
+                starts,
+                format!("The workflow has {} start nodes.", n),
+            )),
+        }
+        let ends = self.find_nodes(|node| matches!(node, Worknodecore::End));
+        match ends.len() {
+            0 => diagnostics.push(Diagnostic::new(
+                DiagnosticKind::MissingEnd,
+                ends,
+                "The workflow has no end node.".to_string(),
+            )),
+            1 => {}
+            n => diagnostics.push(Diagnostic::new(
+                DiagnosticKind::MultipleEnd,
+                ends,
+                format!("The workflow has {} end nodes.", n),
+            )),
+        }
+    }
+    /// Get the uids of the nodes of a kind.
+    fn find_nodes(&self, is_kind: fn(&Worknodecore) -> bool) -> Vec<Uuid> {
+        self.nodes
+            .iter()
+            .filter(|node| is_kind(node.get_node()))
+            .map(|node| node.get_uid())
+            .collect()
+    }
+    /// Check the endpoints, the ports and the labels of the edges.
+    fn validate_edges(&self, diagnostics: &mut Vec<Diagnostic>) {
+        for edge in &self.edges {
+            let (from, to) = match (self.get_node(edge.from), self.get_node(edge.to)) {
+                (Some(from), Some(to)) => (from, to),
+                _ => {
+                    diagnostics.push(Diagnostic::new(
+                        DiagnosticKind::DanglingEdge,
+                        vec![edge.from, edge.to],
+                        format!(
+                            "The edge from {} to {} has a node that is not in the workflow.",
+                            edge.from, edge.to
+                        ),
+                    ));
+                    continue;
+                }
+            };
+            if matches!(to.get_node(), Worknodecore::Start) {
+                diagnostics.push(Diagnostic::new(
+                    DiagnosticKind::DanglingEdge,
+                    vec![edge.from, edge.to],
+                    format!("The edge from {} goes into the start node.", edge.from),
+                ));
+            }
+            if matches!(from.get_node(), Worknodecore::End) {
+                diagnostics.push(Diagnostic::new(
+                    DiagnosticKind::DanglingEdge,
+                    vec![edge.from, edge.to],
+                    format!("The edge to {} leaves the end node.", edge.to),
+                ));
+            }
+            if let Some(port) = &edge.port {
+                let accepted = to.get_node().input_ports();
+                if !accepted.contains(&port.as_str()) {
+                    diagnostics.push(Diagnostic::new(
+                        DiagnosticKind::IncompatiblePort,
+                        vec![edge.from, edge.to],
+                        format!(
+                            "The node {} has no input port {}, the ports are [{}].",
+                            edge.to,
+                            port,
+                            accepted.join(", ")
+                        ),
+                    ));
+                }
+            }
+            if let Some(label) = &edge.route {
+                let message = match from.get_node() {
+                    Worknodecore::Router(router) if router.has_label(label) => None,
+                    Worknodecore::Router(router) => Some(format!(
+                        "The router {} has no label {}, the labels are [{}].",
+                        edge.from,
+                        label,
+                        router.labels().join(", ")
+                    )),
+                    _ => Some(format!(
+                        "The edge from {} has the label {}, but the node is not a router.",
+                        edge.from, label
+                    )),
+                };
+                if let Some(message) = message {
+                    diagnostics.push(Diagnostic::new(
+                        DiagnosticKind::UnknownRoute,
+                        vec![edge.from, edge.to],
+                        message,
+                    ));
+                }
+            }
+        }
+    }
+    /// Check that no node is in a cycle. Every cycle is reported once.
+    fn validate_cycles(&self, diagnostics: &mut Vec<Diagnostic>) {
+        let mut reported = HashSet::new();
+        for node in &self.nodes {
+            let uid = node.get_uid();
+            if reported.contains(&uid) {
+                continue;
+            }
+            let reachable = self.reachable_from(uid);
+            if !reachable.contains(&uid) {
+                continue;
+            }
+            // the nodes in the same cycle reach each other
+            let cycle: Vec<Uuid> = self
+                .nodes
+                .iter()
+                .map(|node| node.get_uid())
+                .filter(|other| {
+                    reachable.contains(other) && self.reachable_from(*other).contains(&uid)
+                })
+                .collect();
+            reported.extend(cycle.iter().copied());
+            diagnostics.push(Diagnostic::new(
+                DiagnosticKind::Cycle,
+                cycle.clone(),
+                format!(
+                    "The nodes [{}] form a cycle.",
+                    cycle
+                        .iter()
+                        .map(|uid| uid.to_string())
+                        .collect::<Vec<String>>()
+                        .join(", ")
+                ),
+            ));
+        }
+    }
+    /// Check that every node can be reached from the start node. Skipped when there isn't
+    /// exactly one start node, since that is already reported.
+    fn validate_reachability(&self, diagnostics: &mut Vec<Diagnostic>) {
+        let starts = self.find_nodes(|node| matches!(node, Worknodecore::Start));
+        let start = match starts.as_slice() {
+            [start] => *start,
+            _ => return,
+        };
+        let reachable = self.reachable_from(start);
+        for node in &self.nodes {
+            let uid = node.get_uid();
+            if uid != start && !reachable.contains(&uid) {
+                diagnostics.push(Diagnostic::new(
+                    DiagnosticKind::Unreachable,
+                    vec![uid],
+                    format!("The node {} can't be reached from the start node.", uid),
+                ));
+            }
+        }
+    }
+    /// Get the nodes reachable from the node through at least one edge.
+    fn reachable_from(&self, uid: Uuid) -> HashSet<Uuid> {
+        let mut reachable = HashSet::new();
+        let mut queue = VecDeque::from([uid]);
+        while let Some(current) = queue.pop_front() {
+            for edge in self.edges.iter().filter(|edge| edge.from == current) {
+                if reachable.insert(edge.to) {
+                    queue.push_back(edge.to);
+                }
+            }
+        }
+        reachable
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::worknode::local::LocalNode;
+    use crate::worknode::Worknode;
+
+    fn kinds(workflow: &Workflow) -> Vec<DiagnosticKind> {
+        match workflow.validate() {
+            Ok(()) => Vec::new(),
+            Err(diagnostics) => diagnostics.iter().map(|d| d.get_kind()).collect(),
+        }
+    }
+
+    #[test]
+    fn valid_workflow() {
+        let mut workflow = Workflow::new();
+        let start = workflow.add_node(Worknode::new(Worknodecore::Start));
+        let end = workflow.add_node(Worknode::new(Worknodecore::End));
+        workflow.add_edge(start, end).unwrap();
+        assert!(workflow.validate().is_ok());
+    }
+
+    #[test]
+    fn report_all_problems() {
+        let mut workflow = Workflow::new();
+        let start = workflow.add_node(Worknode::new(Worknodecore::Start));
+        let a = workflow.add_node(Worknode::new(Worknodecore::Local(LocalNode::default())));
+        let b = workflow.add_node(Worknode::new(Worknodecore::Local(LocalNode::default())));
+        let lonely = workflow.add_node(Worknode::new(Worknodecore::Local(LocalNode::default())));
+        workflow.add_edge(start, a).unwrap();
+        workflow.add_edge(a, b).unwrap();
+        workflow.add_edge(b, a).unwrap();
+        workflow.add_port_edge(b, start, "context").unwrap();
+        let diagnostics = workflow.validate().unwrap_err();
+        assert_eq!(
+            kinds(&workflow),
+            vec![
+                DiagnosticKind::MissingEnd,
+                DiagnosticKind::DanglingEdge,
+                DiagnosticKind::IncompatiblePort,
+                DiagnosticKind::Cycle,
+                DiagnosticKind::Unreachable,
+            ]
+        );
+        let mut cycle = diagnostics[3].get_nodes().to_vec();
+        cycle.sort();
+        let mut expected = vec![start, a, b];
+        expected.sort();
+        assert_eq!(cycle, expected);
+        assert_eq!(diagnostics[4].get_nodes(), &[lonely]);
+    }
+}
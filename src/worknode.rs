@@ -4,20 +4,84 @@
 //!
 //! ## Type of Worknode
 //!
-//! There are five types of worknode currently (there may be more in the future):
+//! There are twenty-eight types of worknode currently (there may be more in the future):
 //! 1. Start node: The start point of the workflow graph.
 //! 2. End node: The end point of the workflow graph.
 //! 3. AI node: The node that call the AI service.
-//! 4. local node: The node that run a local script.
-//! 5. user node: The node that wait for user input.
+//! 4. local node: The node that runs a local command.
+//! 5. user node: The node that asks the user and waits for the answer.
+//! 6. agent node: The node that runs a reason–act–observe loop with tools.
+//! 7. join node: The node that waits for parallel branches and merges their outputs.
+//! 8. approval node: The node that waits for a human to approve or reject its input.
+//! 9. wasm node: The node that runs a WebAssembly plugin.
+//! 10. script node: The node that runs a Rhai script.
+//! 11. file read node: The node that reads a file.
+//! 12. file write node: The node that writes its input to a file.
+//! 13. json transform node: The node that reshapes its json input with a JSONPath or a filter.
+//! 14. router node: The node that asks an AI service which branch its input goes down.
+//! 15. map node: The node that runs a subworkflow on every item of its json array input.
+//! 16. reduce node: The node that merges the outputs of parallel branches or map items.
+//! 17. delay node: The node that waits for a while, until a moment or a cron schedule.
+//! 18. cache node: The node that remembers the outputs of its child node by input.
+//! 19. assert node: The node that checks its input against rules before passing it on.
+//! 20. notify node: The node that sends its input or the run context to webhooks, chat or email.
+//! 21. embed node: The node that turns its text input into embedding vectors.
+//! 22. chunker node: The node that splits long documents into chunks for retrieval.
+//! 23. load node: The node that turns files into plain text with metadata for retrieval.
+//! 24. retrieve node: The node that puts the documents relevant to its input in the run context.
+//! 25. rerank node: The node that re-orders the retrieved documents by their relevance.
+//! 26. ingest node: The node that loads, chunks, embeds and stores the documents of its sources.
+//! 27. tool node: The node that calls a tool of a tool registry without asking an AI service.
+//! 28. planner node: The node that asks an AI service for the steps of a goal and runs them.
+//!
+//! ## Retry
+//!
+//! A worknode can carry a retry policy, so a transient failure of the AI service is retried
+//! with backoff instead of failing the whole workflow. The policy may also limit the time of
+//! every attempt, and an attempt that takes longer fails with a timeout error. See the
+//! `retry` module.
 
+pub mod agent;
 pub mod ai_node;
+pub mod approval;
+pub mod assert;
+pub mod cache;
+pub mod chunker;
+pub mod delay;
+pub mod embed;
+pub mod file;
+pub mod ingest;
+pub mod join;
+pub mod load;
+pub mod local;
+pub mod map;
+pub mod notify;
+pub mod planner;
+pub mod reduce;
+pub mod rerank;
+pub mod retrieve;
+pub mod retry;
+pub mod router;
+pub mod script;
+pub mod tool;
+pub mod transform;
+pub mod user;
+pub mod wasm;
 
-use crate::error::{PilotError, PilotErrorType, PilotResult};
+use crate::error::timeout_error::{TimeoutError, TimeoutErrorType};
+use crate::error::PilotResult;
+use crate::workflow::context::RunContext;
+use crate::workflow::event::RunEvent;
+use retry::RetryPolicy;
 
+use tokio::sync::mpsc::UnboundedSender;
+use tracing::Instrument;
 use uuid::Uuid;
 
+use std::time::Instant;
+
 #[derive(Debug, Clone)]
+#[allow(clippy::large_enum_variant)]
 /// The enum of the worknode type. This is the core part of the node.
 pub enum Worknodecore {
     /// The start node of the workflow graph.
@@ -27,22 +91,318 @@ pub enum Worknodecore {
     /// The AI node of the workflow graph.
     AINode(ai_node::AINode),
     /// The local node of the workflow graph.
-    Local,
+    Local(local::LocalNode),
     /// The user node of the workflow graph.
-    User,
+    User(user::UserNode),
+    /// The agent node of the workflow graph.
+    Agent(agent::Agent),
+    /// The join node of the workflow graph.
+    Join(join::JoinNode),
+    /// The approval node of the workflow graph.
+    Approval(approval::ApprovalNode),
+    /// The wasm node of the workflow graph.
+    Wasm(wasm::WasmNode),
+    /// The script node of the workflow graph.
+    Script(script::ScriptNode),
+    /// The file read node of the workflow graph.
+    FileRead(file::FileReadNode),
+    /// The file write node of the workflow graph.
+    FileWrite(file::FileWriteNode),
+    /// The json transform node of the workflow graph.
+    JsonTransform(transform::JsonTransformNode),
+    /// The router node of the workflow graph.
+    Router(router::RouterNode),
+    /// The map node of the workflow graph.
+    Map(map::MapNode),
+    /// The reduce node of the workflow graph.
+    Reduce(reduce::ReduceNode),
+    /// The delay node of the workflow graph.
+    Delay(delay::DelayNode),
+    /// The cache node of the workflow graph.
+    Cache(cache::CacheNode),
+    /// The assert node of the workflow graph.
+    Assert(assert::AssertNode),
+    /// The notify node of the workflow graph.
+    Notify(notify::NotifyNode),
+    /// The embed node of the workflow graph.
+    Embed(embed::EmbedNode),
+    /// The chunker node of the workflow graph.
+    Chunker(chunker::ChunkerNode),
+    /// The load node of the workflow graph.
+    Load(load::LoadNode),
+    /// The retrieve node of the workflow graph.
+    Retrieve(retrieve::RetrieveNode),
+    /// The rerank node of the workflow graph.
+    Rerank(rerank::RerankNode),
+    /// The ingest node of the workflow graph.
+    Ingest(ingest::IngestNode),
+    /// The tool node of the workflow graph.
+    Tool(tool::ToolNode),
+    /// The planner node of the workflow graph.
+    Planner(planner::PlannerNode),
 }
 
 impl Worknodecore {
+    /// Get the names of the input ports that the edges of the graph can be wired into. A node
+    /// without ports only takes the plain input.
+    pub fn input_ports(&self) -> &'static [&'static str] {
+        match self {
+            Self::AINode(_) | Self::Agent(_) => &ai_node::AINodeInput::PORTS,
+            Self::Cache(cache) => cache.get_node().input_ports(),
+            _ => &[],
+        }
+    }
+    /// Get the name of the provider of the AI service, for the AI, agent, router and planner
+    /// nodes, the reduce node that summarizes, the embed, retrieve, rerank and ingest nodes and
+    /// the cache node of one of them.
+    pub fn get_provider(&self) -> Option<&str> {
+        match self {
+            Self::AINode(node) => node.get_provider(),
+            Self::Agent(agent) => agent.get_node().get_provider(),
+            Self::Router(router) => router.get_node().get_provider(),
+            Self::Reduce(reduce) => reduce.get_node().and_then(|node| node.get_provider()),
+            Self::Embed(embed) => embed.get_provider(),
+            Self::Retrieve(retrieve) => retrieve.get_provider(),
+            Self::Rerank(rerank) => rerank.get_provider(),
+            Self::Ingest(ingest) => ingest.get_provider(),
+            Self::Planner(planner) => planner.get_node().get_provider(),
+            Self::Cache(cache) => cache.get_node().get_provider(),
+            _ => None,
+        }
+    }
+    /// Get the AI service of the AI, agent and router nodes, the reduce node that summarizes,
+    /// the rerank node of an AI node, the planner node and the cache node of one of them.
+    pub fn get_service(&self) -> Option<&ai_node::AIService> {
+        match self {
+            Self::AINode(node) => Some(node.get_service()),
+            Self::Agent(agent) => Some(agent.get_node().get_service()),
+            Self::Router(router) => Some(router.get_node().get_service()),
+            Self::Planner(planner) => Some(planner.get_node().get_service()),
+            Self::Reduce(reduce) => reduce.get_node().map(|node| node.get_service()),
+            Self::Cache(cache) => cache.get_node().get_service(),
+            Self::Rerank(rerank) => match rerank.get_reranker() {
+                rerank::Reranker::Llm(node) => Some(node.get_service()),
+                rerank::Reranker::Api(_) => None,
+            },
+            _ => None,
+        }
+    }
+    /// Get the usage statistics of the last request of the nodes that have an AI service,
+    /// except a cache node that hit.
+    pub fn get_last_usage(&self) -> Option<ai_node::deepseek::DeepSeekUsage> {
+        match self {
+            Self::Cache(cache) if cache.is_hit() => None,
+            _ => self.get_service().map(|service| service.get_last_usage()),
+        }
+    }
+    /// Get the total usage statistics of all requests of the nodes that have an AI service,
+    /// so the usage of an execution is the difference of the totals before and after it. A
+    /// cache node gives the total of its node, also when it hit.
+    pub fn get_total_usage(&self) -> Option<ai_node::deepseek::DeepSeekUsage> {
+        self.get_service().map(|service| service.get_total_usage())
+    }
+    /// Get the history of the AI and agent nodes, also in a cache node.
+    pub fn get_history(&self) -> Option<&Vec<ai_node::Chat>> {
+        match self {
+            Self::AINode(node) => Some(node.get_history()),
+            Self::Agent(agent) => Some(agent.get_node().get_history()),
+            Self::Cache(cache) => cache.get_node().get_history(),
+            _ => None,
+        }
+    }
+    /// Set the history of the AI and agent nodes, also in a cache node. The other nodes have
+    /// no history.
+    pub fn set_history(&mut self, history: Vec<ai_node::Chat>) {
+        match self {
+            Self::AINode(node) => node.set_history(history),
+            Self::Agent(agent) => agent.get_node_mut().set_history(history),
+            Self::Cache(cache) => cache.get_node_mut().set_history(history),
+            _ => {}
+        }
+    }
+    /// Set the recording that the requests of the AI, agent, router, planner and summarizing
+    /// reduce nodes are recorded to or replayed from, also in the subworkflow of a map node and the
+    /// child of a cache node. The other nodes send no request.
+    pub fn set_recording(&mut self, recording: Option<ai_node::recording::Recording>) {
+        match self {
+            Self::AINode(node) => node.set_recording(recording),
+            Self::Agent(agent) => agent.get_node_mut().set_recording(recording),
+            Self::Router(router) => router.get_node_mut().set_recording(recording),
+            Self::Planner(planner) => planner.get_node_mut().set_recording(recording),
+            Self::Map(map) => map.get_workflow_mut().set_recording(recording),
+            Self::Reduce(reduce) => {
+                if let Some(node) = reduce.get_node_mut() {
+                    node.set_recording(recording);
+                }
+            }
+            Self::Cache(cache) => cache.get_node_mut().set_recording(recording),
+            _ => {}
+        }
+    }
+    /// Set the channel that the AI and agent nodes, also the child of a cache node, emit the
+    /// pieces of their answers to. The other nodes give no answer piece by piece.
+    pub fn set_events(&mut self, events: Option<UnboundedSender<RunEvent>>) {
+        match self {
+            Self::AINode(node) => node.set_events(events),
+            Self::Agent(agent) => agent.get_node_mut().set_events(events),
+            Self::Cache(cache) => cache.get_node_mut().set_events(events),
+            _ => {}
+        }
+    }
+    /// Get the name of the node type, as it is written in a workflow file.
+    pub fn kind_name(&self) -> &'static str {
+        match self {
+            Self::Start => "start",
+            Self::End => "end",
+            Self::AINode(_) => "ai_node",
+            Self::Local(_) => "local",
+            Self::User(_) => "user",
+            Self::Agent(_) => "agent",
+            Self::Join(_) => "join",
+            Self::Approval(_) => "approval",
+            Self::Wasm(_) => "wasm",
+            Self::Script(_) => "script",
+            Self::FileRead(_) => "file_read",
+            Self::FileWrite(_) => "file_write",
+            Self::JsonTransform(_) => "json_transform",
+            Self::Router(_) => "router",
+            Self::Map(_) => "map",
+            Self::Reduce(_) => "reduce",
+            Self::Delay(_) => "delay",
+            Self::Cache(_) => "cache",
+            Self::Assert(_) => "assert",
+            Self::Notify(_) => "notify",
+            Self::Embed(_) => "embed",
+            Self::Chunker(_) => "chunker",
+            Self::Load(_) => "load",
+            Self::Retrieve(_) => "retrieve",
+            Self::Rerank(_) => "rerank",
+            Self::Ingest(_) => "ingest",
+            Self::Tool(_) => "tool",
+            Self::Planner(_) => "planner",
+        }
+    }
+    /// Tell the core part the uid of the worknode that holds it.
+    fn set_node_uid(&mut self, node_uid: Option<Uuid>) {
+        match self {
+            Self::AINode(node) => node.set_node_uid(node_uid),
+            Self::Agent(agent) => agent.get_node_mut().set_node_uid(node_uid),
+            Self::Approval(approval) => approval.set_node_uid(node_uid),
+            Self::User(user) => user.set_node_uid(node_uid),
+            Self::Router(router) => router.set_node_uid(node_uid),
+            Self::Reduce(reduce) => reduce.set_node_uid(node_uid),
+            Self::Cache(cache) => cache.get_node_mut().set_node_uid(node_uid),
+            Self::Rerank(rerank) => rerank.set_node_uid(node_uid),
+            Self::Planner(planner) => planner.get_node_mut().set_node_uid(node_uid),
+            _ => {}
+        }
+    }
     /// Excute the worknode.
     pub async fn excute(&mut self, input: String) -> PilotResult<String> {
+        self.excute_with(input, &RetryPolicy::default()).await
+    }
+    /// Excute the worknode, and retry it as the policy says when it fails.
+    pub async fn excute_with(
+        &mut self,
+        input: String,
+        policy: &RetryPolicy,
+    ) -> PilotResult<String> {
+        self.excute_in(input, policy, &RunContext::new()).await
+    }
+    /// Excute the worknode in the run context, and retry it as the policy says when it fails.
+    /// The templates of the AI and agent nodes can use the values of the context.
+    pub async fn excute_in(
+        &mut self,
+        input: String,
+        policy: &RetryPolicy,
+        context: &RunContext,
+    ) -> PilotResult<String> {
+        self.excute_counted(input, policy, context, &mut 0).await
+    }
+    /// Excute the worknode in the run context with retries, and count the attempts that are
+    /// started, so the count is right even if the execution is stopped halfway.
+    async fn excute_counted(
+        &mut self,
+        input: String,
+        policy: &RetryPolicy,
+        context: &RunContext,
+        attempts: &mut usize,
+    ) -> PilotResult<String> {
+        *attempts = 1;
+        loop {
+            let span = tracing::info_span!("worknode.attempt", attempt = *attempts);
+            let attempt = self.excute_once(input.clone(), context).instrument(span);
+            let result = match policy.get_timeout() {
+                Some(timeout) => {
+                    let started = Instant::now();
+                    tokio::time::timeout(timeout, attempt)
+                        .await
+                        .unwrap_or_else(|_| {
+                            Err(TimeoutError::new(
+                                TimeoutErrorType::Total,
+                                format!("The attempt didn't finish in {:?}.", timeout),
+                                started.elapsed(),
+                            )
+                            .limit(Some(timeout))
+                            .into())
+                        })
+                }
+                None => attempt.await,
+            };
+            match result {
+                Err(e) if policy.should_retry(&e, *attempts) => {
+                    let delay = policy.get_backoff().delay(*attempts);
+                    log::warn!(
+                        "Worknode failed on attempt {}, retry in {:?}. {}",
+                        attempts,
+                        delay,
+                        e
+                    );
+                    tokio::time::sleep(delay).await;
+                    *attempts += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+    /// Excute the worknode once.
+    async fn excute_once(&mut self, input: String, context: &RunContext) -> PilotResult<String> {
         match self {
-            Self::AINode(node) => node.execute(input).await.map_err(|e| {
-                PilotError::new(
-                    PilotErrorType::AINodeErr(e),
-                    "AI node failed to execute".to_string(),
-                )
-            }),
-            _ => Ok("".to_string()),
+            Self::AINode(node) => {
+                node.set_context_variables(context);
+                Ok(node.execute(input).await?)
+            }
+            Self::Agent(agent) => {
+                agent.get_node_mut().set_context_variables(context);
+                agent.set_approvals(context.approvals().clone());
+                Ok(agent.execute(input).await?)
+            }
+            // the start and end nodes pass the input through
+            Self::Start | Self::End => Ok(input),
+            Self::Join(join) => Ok(join.execute(input)),
+            Self::Approval(approval) => approval.execute(input, context).await,
+            Self::Local(local) => Ok(local.execute(input, context).await?),
+            Self::Wasm(wasm) => Ok(wasm.execute(input).await?),
+            Self::User(user) => Ok(user.execute(input, context).await?),
+            Self::Script(script) => Ok(script.execute(input, context)?),
+            Self::FileRead(file) => Ok(file.execute(input, context).await?),
+            Self::FileWrite(file) => Ok(file.execute(input, context).await?),
+            Self::JsonTransform(transform) => Ok(transform.execute(input)?),
+            Self::Router(router) => Ok(router.execute(input).await?),
+            Self::Map(map) => Ok(map.execute(input, context).await?),
+            Self::Reduce(reduce) => Ok(reduce.execute(input).await?),
+            Self::Delay(delay) => Ok(delay.execute(input).await?),
+            Self::Cache(cache) => cache.execute(input, context).await,
+            Self::Assert(assert) => Ok(assert.execute(input)?),
+            Self::Notify(notify) => Ok(notify.execute(input, context).await?),
+            Self::Embed(embed) => Ok(embed.execute(input).await?),
+            Self::Chunker(chunker) => Ok(chunker.execute(input)?),
+            Self::Load(load) => Ok(load.execute(input, context).await?),
+            Self::Retrieve(retrieve) => Ok(retrieve.execute(input, context).await?),
+            Self::Rerank(rerank) => Ok(rerank.execute(input, context).await?),
+            Self::Ingest(ingest) => Ok(ingest.execute(input).await?),
+            Self::Tool(tool) => Ok(tool.execute(input, context).await?),
+            Self::Planner(planner) => Ok(planner.execute(input, context).await?),
         }
     }
 }
@@ -54,19 +414,100 @@ pub struct Worknode {
     uid: Uuid,
     /// The core part of the worknode.
     node: Worknodecore,
+    /// The retry policy of the worknode.
+    retry_policy: RetryPolicy,
+    /// The key of the run context that the output of the worknode is stored under.
+    context_key: Option<String>,
+    /// The number of attempts of the last execution.
+    attempts: usize,
 }
 
 impl Worknode {
     /// Create a new worknode.
     pub fn new(node: Worknodecore) -> Self {
-        Self {
+        let mut worknode = Self {
             uid: Uuid::new_v4(),
             node,
-        }
+            retry_policy: RetryPolicy::default(),
+            context_key: None,
+            attempts: 0,
+        };
+        worknode.bind_uid();
+        worknode
+    }
+    /// Create a worknode with a known uid, like one loaded from a workflow file.
+    pub fn with_uid(uid: Uuid, node: Worknodecore) -> Self {
+        let mut worknode = Self {
+            uid,
+            node,
+            retry_policy: RetryPolicy::default(),
+            context_key: None,
+            attempts: 0,
+        };
+        worknode.bind_uid();
+        worknode
     }
     /// Excute the worknode.
     pub async fn excute(&mut self, input: String) -> PilotResult<String> {
-        self.node.excute(input).await
+        self.excute_in(input, &RunContext::new()).await
+    }
+    /// Excute the worknode in the run context. If the worknode has a context key, its output
+    /// is stored in the context, as json if it is valid json and as a string otherwise. An
+    /// error tells the node, its provider and the attempt that failed in its context.
+    pub async fn excute_in(&mut self, input: String, context: &RunContext) -> PilotResult<String> {
+        let output = self
+            .node
+            .excute_counted(input, &self.retry_policy, context, &mut self.attempts)
+            .await
+            .map_err(|e| {
+                let provider = match self.node.get_provider() {
+                    Some(provider) => format!(", provider {}", provider),
+                    None => String::new(),
+                };
+                e.context(format!(
+                    "while executing node {} ({}{}, attempt {})",
+                    self.uid,
+                    self.node.kind_name(),
+                    provider,
+                    self.attempts
+                ))
+            })?;
+        if let Some(key) = &self.context_key {
+            let value = serde_json::from_str(&output)
+                .unwrap_or_else(|_| serde_json::Value::String(output.clone()));
+            context.set_value(key, value);
+        }
+        Ok(output)
+    }
+    /// Set the retry policy as builder.
+    pub fn retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.retry_policy = retry_policy;
+        self
+    }
+    /// Set the retry policy.
+    pub fn set_retry_policy(&mut self, retry_policy: RetryPolicy) {
+        self.retry_policy = retry_policy;
+    }
+    /// Get the retry policy.
+    pub fn get_retry_policy(&self) -> &RetryPolicy {
+        &self.retry_policy
+    }
+    /// Set the context key that the output is stored under as builder.
+    pub fn context_key(mut self, context_key: Option<String>) -> Self {
+        self.context_key = context_key;
+        self
+    }
+    /// Set the context key that the output is stored under.
+    pub fn set_context_key(&mut self, context_key: Option<String>) {
+        self.context_key = context_key;
+    }
+    /// Get the context key that the output is stored under.
+    pub fn get_context_key(&self) -> Option<&str> {
+        self.context_key.as_deref()
+    }
+    /// Get the number of attempts of the last execution, including the retries.
+    pub fn get_attempts(&self) -> usize {
+        self.attempts
     }
     /// Get the uid of the worknode.
     pub fn get_uid(&self) -> Uuid {
@@ -76,30 +517,37 @@ impl Worknode {
     pub fn get_node(&self) -> &Worknodecore {
         &self.node
     }
+    /// Get the mutable core part of the worknode.
+    pub fn get_node_mut(&mut self) -> &mut Worknodecore {
+        &mut self.node
+    }
     /// Set the core part of the worknode
     pub fn set_node(&mut self, node: Worknodecore) {
         self.node = node;
+        self.bind_uid();
+    }
+    /// Tell the core part the uid of the worknode that holds it.
+    fn bind_uid(&mut self) {
+        self.node.set_node_uid(Some(self.uid));
     }
 }
 
 #[cfg(test)]
 mod test {
     use super::ai_node::{
-        deepseek::{DeepSeekClient, DeepSeekModel},
+        deepseek::{DeepSeekClient, DeepSeekModel, DEEPSEEK_API_URL},
         AINode, AIService,
     };
     use super::*;
+    use crate::error::PilotErrorType;
 
     use tokio::runtime::Runtime;
 
     #[test]
     fn ai_worknode_execute_simple() {
-        let deepseek_client = DeepSeekClient::new(
-            "https://api.deepseek.ai/v1/chat/completions",
-            DeepSeekModel::DeepseekChat,
-        )
-        .api_key_from_file("./api_key.txt")
-        .unwrap();
+        let deepseek_client = DeepSeekClient::new(DEEPSEEK_API_URL, DeepSeekModel::DeepseekChat)
+            .api_key_from_file("./api_key.txt")
+            .unwrap();
 
         let ai_node = AINode::new(AIService::DeepSeek {
             client: deepseek_client,
@@ -118,12 +566,9 @@ mod test {
 
     #[test]
     fn ai_worknode_execute() {
-        let deepseek_client = DeepSeekClient::new(
-            "https://api.deepseek.ai/v1/chat/completions",
-            DeepSeekModel::DeepseekChat,
-        )
-        .api_key_from_file("./api_key.txt")
-        .unwrap();
+        let deepseek_client = DeepSeekClient::new(DEEPSEEK_API_URL, DeepSeekModel::DeepseekChat)
+            .api_key_from_file("./api_key.txt")
+            .unwrap();
 
         let ai_node = AINode::new(AIService::DeepSeek {
             client: deepseek_client,
@@ -145,4 +590,70 @@ mod test {
             Err(e) => panic!("Error: {}", e),
         }
     }
+
+    #[test]
+    fn node_errors_convert() {
+        let context = RunContext::new();
+        let rt = Runtime::new().unwrap();
+        let mut file = Worknode::new(Worknodecore::FileRead(file::FileReadNode::new(
+            "/nonexistent/aipilot/input.txt",
+        )));
+        let error = rt
+            .block_on(file.excute_in(String::new(), &context))
+            .unwrap_err();
+        assert!(matches!(
+            error.get_error_type(),
+            PilotErrorType::FileNodeErr(_)
+        ));
+        assert_eq!(error.get_message(), "File node failed to execute");
+
+        let mut tool = Worknode::new(Worknodecore::Tool(tool::ToolNode::new(
+            ai_node::tool::ToolRegistry::new(),
+            "missing",
+        )));
+        let error = rt
+            .block_on(tool.excute_in("{}".to_string(), &context))
+            .unwrap_err();
+        assert!(matches!(
+            error.get_error_type(),
+            PilotErrorType::AINodeErr(_)
+        ));
+        assert_eq!(error.get_message(), "AI node failed to execute");
+    }
+
+    #[test]
+    fn error_context_names_the_node() {
+        use retry::{Backoff, ErrorClass};
+
+        // the node without an api key fails on every attempt
+        let client = DeepSeekClient::new(DEEPSEEK_API_URL, DeepSeekModel::DeepseekChat);
+        let ai_node =
+            AINode::new(AIService::new_deepseek(client)).provider(Some("deepseek".to_string()));
+        let mut worknode = Worknode::new(Worknodecore::AINode(ai_node)).retry_policy(
+            RetryPolicy::new(2)
+                .backoff(Backoff::None)
+                .retry_on(vec![ErrorClass::Auth]),
+        );
+        let rt = Runtime::new().unwrap();
+        let error = rt
+            .block_on(worknode.excute_in("hi".to_string(), &RunContext::new()))
+            .unwrap_err();
+        let breadcrumb = format!(
+            "while executing node {} (ai_node, provider deepseek, attempt 2)",
+            worknode.get_uid()
+        );
+        assert_eq!(error.get_context(), [breadcrumb.as_str()]);
+        assert!(error.to_string().ends_with(&breadcrumb));
+
+        let mut file = Worknode::new(Worknodecore::FileRead(file::FileReadNode::new(
+            "/nonexistent/aipilot/input.txt",
+        )));
+        let error = rt
+            .block_on(file.excute_in(String::new(), &RunContext::new()))
+            .unwrap_err();
+        assert!(error.to_string().ends_with(&format!(
+            "while executing node {} (file_read, attempt 1)",
+            file.get_uid()
+        )));
+    }
 }
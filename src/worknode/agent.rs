@@ -0,0 +1,359 @@
+//! # Agent
+//!
+//! This node runs a bounded reason–act–observe loop on top of an AI node.
+//!
+//! In each step the AI service reasons about the task and may call some tools; the tools are
+//! invoked and their results are observed in the next step. The loop ends when the AI service
+//! answers without calling any tool, or fails when the step limit is hit.
+//!
+//! Every intermediate thought, action and observation is emitted as an `AgentEvent` to the
+//! event channel of the agent, if there is one, so the progress can be watched.
+//!
+//! A call of a tool marked `confirm` (see [`ToolPolicy`]) suspends the loop: an
+//! `ApprovalRequest` with the proposed arguments is put in the approvals of the agent and
+//! emitted as `AgentEvent::ApprovalRequested`, and the tool is only called when the request
+//! is approved. A rejection is sent back to the model as the result of the call, with its
+//! reason. In a workflow the agent waits on the approvals of the run context, so the calls
+//! are decided like the approval nodes, under the uid of the agent node.
+//!
+//! In a workflow with a budget, the agent gets a `BudgetGuard` and checks it before every
+//! step, so a long loop stops as soon as the budget of the run is spent.
+
+use super::ai_node::{AINode, AINodeInput, RequestOverrides, ToolCall, ToolPolicy, ToolRegistry};
+use super::approval::{ApprovalRequest, Approvals, Decision};
+use crate::error::ai_node_error::{AINodeError, AINodeErrorType, AINodeResult};
+use crate::workflow::cost::BudgetGuard;
+
+use tokio::sync::mpsc::UnboundedSender;
+use uuid::Uuid;
+
+/// The instruction that makes the AI service reason before acting.
+const REACT_PROMPT: &str = "Solve the task step by step. Before calling a tool, explain \
+briefly what you are going to do and why. When you have enough information, answer without \
+calling any tool.";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// The enum of what happens in one step of the agent.
+pub enum AgentEvent {
+    /// The reasoning of the AI service before it acts.
+    Thought { step: usize, text: String },
+    /// A tool call requested by the AI service.
+    Action {
+        step: usize,
+        tool: String,
+        arguments: String,
+    },
+    /// A tool call that waits for the approval of a human. Decide it through the approvals
+    /// of the agent with the uid of the request.
+    ApprovalRequested {
+        step: usize,
+        tool: String,
+        arguments: String,
+        request: ApprovalRequest,
+    },
+    /// The result of a tool call.
+    Observation {
+        step: usize,
+        tool: String,
+        result: String,
+    },
+    /// The final answer of the agent.
+    Answer { step: usize, text: String },
+}
+
+#[derive(Debug, Clone)]
+/// The struct of the agent node.
+pub struct Agent {
+    /// The AI node that reasons and chooses the tools.
+    node: AINode,
+    /// The max number of steps in one execution.
+    max_steps: usize,
+    /// The channel that the events are emitted to.
+    events: Option<UnboundedSender<AgentEvent>>,
+    /// The approvals that the calls of the confirmed tools wait on.
+    approvals: Approvals,
+    /// The budget checked before every step.
+    budget: Option<BudgetGuard>,
+}
+
+impl Agent {
+    /// Create a new Agent with the AI node and the tools it can use.
+    pub fn new(node: AINode, tools: ToolRegistry) -> Self {
+        Agent {
+            node: node.tools(tools),
+            max_steps: Self::default_max_steps(),
+            events: None,
+            approvals: Approvals::new(),
+            budget: None,
+        }
+    }
+    /// Execute the agent with the task and get the final answer.
+    pub async fn execute(&mut self, input: String) -> AINodeResult<String> {
+        self.node.prepare(AINodeInput::parse(input)?).await?;
+        self.node.push_prompt()?;
+        let overrides = RequestOverrides::default();
+        for step in 1..=self.max_steps {
+            let total = self.node.get_service().get_total_usage();
+            if self.budget.is_some_and(|budget| !budget.allows(total)) {
+                return Err(AINodeError::new(
+                    AINodeErrorType::BudgetExceeded,
+                    format!("The budget of the run is spent after {} steps.", step - 1),
+                ));
+            }
+            let (content, tool_calls) = self.node.turn(&overrides).await?;
+            if tool_calls.is_empty() {
+                self.emit(AgentEvent::Answer {
+                    step,
+                    text: content.clone(),
+                });
+                return Ok(content);
+            }
+            if !content.trim().is_empty() {
+                self.emit(AgentEvent::Thought {
+                    step,
+                    text: content,
+                });
+            }
+            for call in &tool_calls {
+                self.emit(AgentEvent::Action {
+                    step,
+                    tool: call.name.clone(),
+                    arguments: call.arguments.clone(),
+                });
+            }
+            for call in tool_calls {
+                let result = self.call_tool(step, &call).await;
+                self.emit(AgentEvent::Observation {
+                    step,
+                    tool: call.name,
+                    result,
+                });
+            }
+        }
+        Err(AINodeError::new(
+            AINodeErrorType::ToolError,
+            format!(
+                "The agent doesn't finish the task in {} steps.",
+                self.max_steps
+            ),
+        ))
+    }
+    /// Call a tool as its policy says, asking for the approval of a human first if it must
+    /// be confirmed, and push the result to the history.
+    async fn call_tool(&mut self, step: usize, call: &ToolCall) -> String {
+        let tools = self.node.get_tools();
+        let result = match tools.get_policy(&call.name) {
+            Some(ToolPolicy::Confirm) => {
+                let request = ApprovalRequest {
+                    // without a worknode, every request gets its own uid
+                    node: self.node.get_node_uid().unwrap_or_else(Uuid::new_v4),
+                    message: format!("Call the tool {} with {}?", call.name, call.arguments),
+                    input: call.arguments.clone(),
+                };
+                let node = request.node;
+                self.approvals.request(request.clone());
+                self.emit(AgentEvent::ApprovalRequested {
+                    step,
+                    tool: call.name.clone(),
+                    arguments: call.arguments.clone(),
+                    request,
+                });
+                match self.approvals.wait(node).await {
+                    Decision::Approve => self.node.get_tools().call_approved(call).await,
+                    Decision::Reject(reason) => format!(
+                        "Error: the call of the tool {} was rejected by the user. {}",
+                        call.name, reason
+                    ),
+                }
+            }
+            _ => tools.call(call).await,
+        };
+        self.node.push_tool_result(call, result.clone());
+        result
+    }
+    /// Send the event to the channel. An event is dropped if nobody listens anymore.
+    fn emit(&self, event: AgentEvent) {
+        if let Some(events) = &self.events {
+            let _ = events.send(event);
+        }
+    }
+    /// Set the max number of steps as builder.
+    pub fn max_steps(mut self, max_steps: usize) -> Self {
+        self.max_steps = max_steps;
+        self
+    }
+    /// Set the max number of steps.
+    pub fn set_max_steps(&mut self, max_steps: usize) {
+        self.max_steps = max_steps;
+    }
+    /// Get the max number of steps.
+    pub fn get_max_steps(&self) -> usize {
+        self.max_steps
+    }
+    /// The default max number of steps.
+    pub fn default_max_steps() -> usize {
+        10
+    }
+    /// Set the event channel as builder.
+    pub fn events(mut self, events: Option<UnboundedSender<AgentEvent>>) -> Self {
+        self.events = events;
+        self
+    }
+    /// Set the event channel.
+    pub fn set_events(&mut self, events: Option<UnboundedSender<AgentEvent>>) {
+        self.events = events;
+    }
+    /// Set the approvals that the calls of the confirmed tools wait on as builder.
+    pub fn approvals(mut self, approvals: Approvals) -> Self {
+        self.approvals = approvals;
+        self
+    }
+    /// Set the approvals that the calls of the confirmed tools wait on, like the approvals of
+    /// a run context.
+    pub fn set_approvals(&mut self, approvals: Approvals) {
+        self.approvals = approvals;
+    }
+    /// Get the approvals that the calls of the confirmed tools wait on, to decide them.
+    pub fn get_approvals(&self) -> &Approvals {
+        &self.approvals
+    }
+    /// Set the budget checked before every step as builder.
+    pub fn budget(mut self, budget: Option<BudgetGuard>) -> Self {
+        self.budget = budget;
+        self
+    }
+    /// Set the budget checked before every step.
+    pub fn set_budget(&mut self, budget: Option<BudgetGuard>) {
+        self.budget = budget;
+    }
+    /// Get the budget checked before every step.
+    pub fn get_budget(&self) -> Option<&BudgetGuard> {
+        self.budget.as_ref()
+    }
+    /// Get the AI node of the agent.
+    pub fn get_node(&self) -> &AINode {
+        &self.node
+    }
+    /// Get the mutable AI node of the agent.
+    pub fn get_node_mut(&mut self) -> &mut AINode {
+        &mut self.node
+    }
+    /// The instruction that makes the AI service reason before acting. Add it to the role or
+    /// the prompt suffix of the AI node when the model doesn't explain its actions by itself.
+    pub fn react_prompt() -> &'static str {
+        REACT_PROMPT
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::ai_node::{
+        deepseek::{DeepSeekClient, DeepSeekModel, DeepSeekUsage, DEEPSEEK_API_URL},
+        AIService,
+    };
+    use super::*;
+    use crate::workflow::cost::{Budget, CostEntry};
+
+    use tokio::runtime::Runtime;
+
+    #[test]
+    fn agent_step_limit_and_events() {
+        let client = DeepSeekClient::new(DEEPSEEK_API_URL, DeepSeekModel::DeepseekChat);
+        let (sender, mut receiver) = tokio::sync::mpsc::unbounded_channel();
+        let mut agent = Agent::new(
+            AINode::new(AIService::new_deepseek(client)),
+            ToolRegistry::new(),
+        )
+        .max_steps(0)
+        .events(Some(sender));
+        let rt = Runtime::new().unwrap();
+        let error = rt
+            .block_on(agent.execute("find the answer".to_string()))
+            .unwrap_err();
+        assert!(matches!(error.get_error_type(), AINodeErrorType::ToolError));
+        assert!(receiver.try_recv().is_err());
+
+        // a spent budget stops the agent before it sends a request
+        agent.set_max_steps(3);
+        agent.set_budget(Some(BudgetGuard {
+            budget: Budget::new().max_tokens(Some(10)),
+            spent: CostEntry {
+                executions: 1,
+                usage: DeepSeekUsage::from_json(&json::object! {
+                    completion_tokens: 4,
+                    prompt_tokens: 6,
+                    prompt_cache_hit_tokens: 0,
+                    prompt_cache_miss_tokens: 6,
+                    total_tokens: 10
+                })
+                .unwrap(),
+                cost: None,
+            },
+            price: None,
+            since: DeepSeekUsage::new(),
+        }));
+        let error = rt
+            .block_on(agent.execute("find the answer".to_string()))
+            .unwrap_err();
+        assert!(matches!(
+            error.get_error_type(),
+            AINodeErrorType::BudgetExceeded
+        ));
+        agent.set_budget(None);
+
+        let node = Uuid::new_v4();
+        agent.get_node_mut().set_node_uid(Some(node));
+        agent.get_node_mut().set_tools(
+            ToolRegistry::new()
+                .tool(
+                    "delete",
+                    "Delete a file",
+                    serde_json::json!({}),
+                    |arguments| async move { Ok(format!("deleted {}", arguments["path"])) },
+                )
+                .policy("delete", ToolPolicy::Confirm),
+        );
+        let call = ToolCall {
+            id: "call_0".to_string(),
+            name: "delete".to_string(),
+            arguments: r#"{"path": "a.txt"}"#.to_string(),
+        };
+        let approvals = agent.get_approvals().clone();
+        approvals.request(ApprovalRequest {
+            node,
+            message: String::new(),
+            input: String::new(),
+        });
+        approvals.reject(node, "keep it").unwrap();
+        let result = rt.block_on(agent.call_tool(1, &call));
+        assert!(result.contains("rejected by the user. keep it"));
+        assert!(matches!(
+            receiver.try_recv().unwrap(),
+            AgentEvent::ApprovalRequested { request, .. } if request.input == call.arguments
+        ));
+
+        let decider = std::thread::spawn(move || loop {
+            if approvals.get_pending(node).is_some() {
+                approvals.approve(node).unwrap();
+                break;
+            }
+            std::thread::yield_now();
+        });
+        let result = rt.block_on(agent.call_tool(2, &call));
+        decider.join().unwrap();
+        assert_eq!(result, r#"deleted "a.txt""#);
+        receiver.try_recv().unwrap();
+
+        agent.emit(AgentEvent::Answer {
+            step: 1,
+            text: "42".to_string(),
+        });
+        assert_eq!(
+            receiver.try_recv().unwrap(),
+            AgentEvent::Answer {
+                step: 1,
+                text: "42".to_string()
+            }
+        );
+    }
+}
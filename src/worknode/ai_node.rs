@@ -4,36 +4,219 @@
 //!
 //! ## Input
 //!
-//! There are two inputs of the AI node:
+//! The inputs of the AI node are named ports (see [`port`]):
 //! 1. history: The history of the conversation.
 //! 2. input: The input of the user.
+//! 3. context: The background information put before the input.
+//! 4. role, prompt_prefix, prompt_suffix and variables: The parameters of the prompt.
+//!
+//! A text input is either a json object whose fields are the ports, or the input of the user.
+//!
+//! The role, the prompt prefix and the prompt suffix are templates (see [`crate::template`]),
+//! rendered with the variables of the node right before each request. Variables can be set on
+//! the node, or given at execute time in the `variables` field of a json input. In a workflow,
+//! the values of the run context are the variables `context.<key>` (see
+//! [`crate::workflow::context`]).
 //!
 //! TODO: examples
 //!
 //! ## Output
 //!
-//! There is one output of the AI node:
-//! 1. output: The output of the AI service.
+//! `execute_ports` gives a structured output with the content, the reasoning, the usage and
+//! the content parsed as json. `execute` gives the content only.
+//!
+//! Before each request, the history is trimmed by the `HistoryPolicy` of the node, so long
+//! conversations fit in the context window of the model. With a `Compaction`, the older part
+//! of a long history is replaced by a summary instead.
+//!
+//! With a `Memory`, the node also remembers the facts of past conversations across sessions:
+//! the facts relevant to the input are put before the prompt prefix, and the facts of each
+//! answered exchange are extracted and stored (see [`memory`]).
+//!
+//! The output can also be streamed with `execute_stream`, which gives the answer piece by
+//! piece and a usage summary at the end. A node with an event channel streams every request
+//! that calls no tools, and emits the pieces as `RunEvent::NodeOutput` events, while it
+//! still gives the whole answer like `execute`.
+//!
+//! When the AI service is in json mode, the output is checked to be valid json. If the node has
+//! an output json schema, the output is also validated against it. If the check fails, the node
+//! asks the AI service to fix its answer, at most `json_retries` times (0 disables the repair).
+//!
+//! With a `ToolRegistry`, the AI service can call the tools of the program. The node invokes
+//! the requested tools and asks again, at most `max_tool_steps` rounds per execution. The tools
+//! of an MCP server are given by a `McpClient` (see [`mcp`]).
 //!
 //! ## Supported AI Service
 //! 1. DeepSeek
+//!
+//! The latency and the error rate of the requests of the clients are kept by a
+//! `ProviderStats` (see [`stats`]).
+//!
+//! The embeddings of texts are asked from the OpenAI or the Ollama api (see [`embedding`]).
 
+pub mod chat;
 pub mod deepseek;
+pub mod embedding;
+pub mod history;
+pub mod key;
+pub mod mcp;
+pub mod memory;
+pub mod params;
+pub mod port;
+pub mod recording;
+pub mod rerank;
+pub mod secrets;
+pub mod session;
+pub mod stats;
+pub mod stream;
+pub mod tool;
+pub mod transcript;
 
-use crate::error::ai_node_error::AINodeResult;
-use deepseek::DeepSeekClient;
+pub use chat::{Chat, ChatMetadata, Content, ContentPart, HistoryFormat, Role};
+pub use history::{Compaction, HistoryPolicy, TrimStrategy};
+pub use mcp::{McpClient, McpTransport};
+pub use memory::{Memory, RetentionPolicy};
+pub use params::{EffectiveParams, ParamLayer, SamplingParams};
+pub use port::{AINodeInput, AINodeOutput};
+pub use session::SessionManager;
+pub use stats::{LatencyStats, ProviderStats};
+pub use stream::{ChatStream, StreamEvent};
+pub use tool::{ToolCall, ToolPolicy, ToolRegistry};
+pub use transcript::TranscriptFormat;
 
-#[derive(Debug, Clone)]
-/// The struct of one round of the chat.
-pub struct Chat {
-    role: String,
-    content: String,
+use crate::error::ai_node_error::{AINodeError, AINodeErrorType, AINodeResult, SchemaViolation};
+use crate::template::{self, Variables};
+use crate::workflow::context::RunContext;
+use crate::workflow::event::RunEvent;
+use deepseek::{DeepSeekClient, DeepSeekUsage, RequestCount, ResponseFormat};
+
+use json::JsonValue;
+use serde::de::DeserializeOwned;
+use tokio::sync::mpsc::UnboundedSender;
+use uuid::Uuid;
+
+use std::path::Path;
+
+#[derive(Debug, Clone, Default)]
+/// The parameters that override the configuration of the AI service for one request only.
+/// A field left as `None` keeps the value configured in the client.
+pub struct RequestOverrides {
+    /// Override the temperature.
+    temperature: Option<f64>,
+    /// Override the top p.
+    top_p: Option<f64>,
+    /// Override the maximum tokens of the response.
+    max_tokens: Option<i32>,
+    /// Override the stop sequences.
+    stop: Option<Vec<String>>,
+    /// Override the format of the response.
+    response_format: Option<ResponseFormat>,
+    /// Override the panalty of frequency.
+    frequency_panalty: Option<f64>,
+    /// Override the panalty of presence.
+    presence_penalty: Option<f64>,
+    /// Override the tools, in the OpenAI format.
+    tools: Option<JsonValue>,
+    /// The sampling parameters of the node that sends the request, below the overrides.
+    node_params: SamplingParams,
 }
 
-impl Chat {
-    /// Create a new Chat.
-    pub fn new(role: String, content: String) -> Chat {
-        Chat { role, content }
+impl RequestOverrides {
+    /// Create a new RequestOverrides that overrides nothing.
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Override the temperature as builder.
+    pub fn temperature(mut self, temperature: f64) -> Self {
+        self.temperature = Some(temperature);
+        self
+    }
+    /// Get the overridden temperature.
+    pub fn get_temperature(&self) -> Option<f64> {
+        self.temperature
+    }
+    /// Override the top p as builder.
+    pub fn top_p(mut self, top_p: f64) -> Self {
+        self.top_p = Some(top_p);
+        self
+    }
+    /// Get the overridden top p.
+    pub fn get_top_p(&self) -> Option<f64> {
+        self.top_p
+    }
+    /// Override the maximum tokens as builder.
+    pub fn max_tokens(mut self, max_tokens: i32) -> Self {
+        self.max_tokens = Some(max_tokens);
+        self
+    }
+    /// Get the overridden maximum tokens.
+    pub fn get_max_tokens(&self) -> Option<i32> {
+        self.max_tokens
+    }
+    /// Override the stop sequences as builder.
+    pub fn stop(mut self, stop: Vec<String>) -> Self {
+        self.stop = Some(stop);
+        self
+    }
+    /// Get the overridden stop sequences.
+    pub fn get_stop(&self) -> Option<&Vec<String>> {
+        self.stop.as_ref()
+    }
+    /// Override the response format as builder.
+    pub fn response_format(mut self, response_format: ResponseFormat) -> Self {
+        self.response_format = Some(response_format);
+        self
+    }
+    /// Get the overridden response format.
+    pub fn get_response_format(&self) -> Option<&ResponseFormat> {
+        self.response_format.as_ref()
+    }
+    /// Override the panalty of frequency as builder.
+    pub fn frequency_panalty(mut self, frequency_panalty: f64) -> Self {
+        self.frequency_panalty = Some(frequency_panalty);
+        self
+    }
+    /// Get the overridden panalty of frequency.
+    pub fn get_frequency_panalty(&self) -> Option<f64> {
+        self.frequency_panalty
+    }
+    /// Override the panalty of presence as builder.
+    pub fn presence_penalty(mut self, presence_penalty: f64) -> Self {
+        self.presence_penalty = Some(presence_penalty);
+        self
+    }
+    /// Get the overridden panalty of presence.
+    pub fn get_presence_penalty(&self) -> Option<f64> {
+        self.presence_penalty
+    }
+    /// Override the tools as builder.
+    pub fn tools(mut self, tools: JsonValue) -> Self {
+        self.tools = Some(tools);
+        self
+    }
+    /// Get the overridden tools.
+    pub fn get_tools(&self) -> Option<&JsonValue> {
+        self.tools.as_ref()
+    }
+    /// Set the sampling parameters of the node as builder.
+    pub fn node_params(mut self, node_params: SamplingParams) -> Self {
+        self.node_params = node_params;
+        self
+    }
+    /// Get the sampling parameters of the node.
+    pub fn get_node_params(&self) -> &SamplingParams {
+        &self.node_params
+    }
+    /// Get the sampling parameters of the request.
+    pub fn get_params(&self) -> SamplingParams {
+        SamplingParams {
+            temperature: self.temperature,
+            top_p: self.top_p,
+            max_tokens: self.max_tokens,
+            frequency_penalty: self.frequency_panalty,
+            presence_penalty: self.presence_penalty,
+            stop: self.stop.clone(),
+        }
     }
 }
 
@@ -48,12 +231,22 @@ pub enum AIService {
 pub struct AINode {
     /// The AI service.
     service: AIService,
+    /// The name of the AI service in the registry, used to save the node in a workflow file.
+    provider: Option<String>,
     /// The role of the ai assistant. Usually told by the role `system`,
     /// to tell the assistant what role it should play.
     /// For example, `system` role can be `You are a helpful assistant`.
     role: Option<String>,
     /// The history of the conversation.
     histroy: Vec<Chat>,
+    /// The policy to trim the history before each request.
+    history_policy: HistoryPolicy,
+    /// The configuration to summarize the older history when it grows too long.
+    compaction: Option<Compaction>,
+    /// The long-term memory of the facts of past conversations.
+    memory: Option<Memory>,
+    /// The memories recalled for the current input, put before the prompt prefix.
+    recalled: Vec<String>,
     /// The prefix of the prompt, which will be added in the beginning of the prompt.
     /// Usually used to give some background information to the assistant.
     /// For example, the pwd or the current time.
@@ -64,6 +257,28 @@ pub struct AINode {
     prompt_suffix: String,
     /// The input of the user.
     input: String,
+    /// The background information put before the input, like a retrieved document.
+    context: String,
+    /// The reasoning of the last answer, given by the reasoning models.
+    reasoning: Option<String>,
+    /// The variables to render the role, the prompt prefix and the prompt suffix with.
+    variables: Variables,
+    /// How many times the node asks the AI service to fix its answer when the output is
+    /// required to be json but is not valid json.
+    json_retries: usize,
+    /// The json schema that the output must match.
+    output_schema: Option<serde_json::Value>,
+    /// The tools that the AI service can call.
+    tools: ToolRegistry,
+    /// The max number of tool calling rounds in one execution.
+    max_tool_steps: usize,
+    /// The sampling parameters of the node, which replace the ones of the AI service and are
+    /// replaced by the ones of a request (see [`params`]).
+    params: SamplingParams,
+    /// The uid of the worknode that holds this AI node, recorded in the produced chats.
+    node_uid: Option<Uuid>,
+    /// The channel that the pieces of the answers are emitted to while they are generated.
+    events: Option<UnboundedSender<RunEvent>>,
 }
 
 impl AIService {
@@ -71,6 +286,63 @@ impl AIService {
     pub fn new_deepseek(client: DeepSeekClient) -> AIService {
         AIService::DeepSeek { client }
     }
+    /// Whether the AI service is required to answer in json format.
+    pub fn is_json_mode(&self) -> bool {
+        match self {
+            AIService::DeepSeek { client } => {
+                matches!(client.get_response_format(), Some(ResponseFormat::Json))
+            }
+        }
+    }
+    /// Get the usage statistics of the last request.
+    pub fn get_last_usage(&self) -> DeepSeekUsage {
+        match self {
+            AIService::DeepSeek { client } => client.get_last_usage(),
+        }
+    }
+    /// Get the total usage statistics of all requests sent by the AI service.
+    pub fn get_total_usage(&self) -> DeepSeekUsage {
+        match self {
+            AIService::DeepSeek { client } => client.get_total_usage(),
+        }
+    }
+    /// Get the numbers of requests sent by the AI service.
+    pub fn get_requests(&self) -> RequestCount {
+        match self {
+            AIService::DeepSeek { client } => client.get_requests(),
+        }
+    }
+    /// Set the recording that the requests are recorded to or replayed from.
+    pub fn set_recording(&mut self, recording: Option<recording::Recording>) {
+        match self {
+            AIService::DeepSeek { client } => client.set_recording(recording),
+        }
+    }
+    /// Whether the requests can be streamed. The requests of a recording are not, since a
+    /// stream can't be recorded or replayed.
+    pub fn can_stream(&self) -> bool {
+        match self {
+            AIService::DeepSeek { client } => client.get_recording().is_none(),
+        }
+    }
+    /// Check that the AI service is reachable and accepts the api key, with the cheapest
+    /// request of its api.
+    pub async fn healthcheck(&self) -> AINodeResult<()> {
+        match self {
+            AIService::DeepSeek { client } => Ok(client.healthcheck().await?),
+        }
+    }
+    /// Send the chats to the AI service and get the content of the answer, without touching
+    /// any history.
+    pub async fn complete(
+        &mut self,
+        chats: &Vec<Chat>,
+        overrides: &RequestOverrides,
+    ) -> AINodeResult<String> {
+        match self {
+            AIService::DeepSeek { client } => client.complete(chats, overrides).await,
+        }
+    }
 }
 
 impl AINode {
@@ -78,66 +350,430 @@ impl AINode {
     pub fn new(service: AIService) -> Self {
         AINode {
             service,
+            provider: None,
             role: None,
             histroy: Vec::new(),
+            history_policy: HistoryPolicy::default(),
+            compaction: None,
+            memory: None,
+            recalled: Vec::new(),
             prompt_prefix: String::new(),
             prompt_suffix: String::new(),
             input: String::new(),
+            context: String::new(),
+            reasoning: None,
+            variables: Variables::new(),
+            json_retries: Self::default_json_retries(),
+            output_schema: None,
+            tools: ToolRegistry::new(),
+            max_tool_steps: Self::default_max_tool_steps(),
+            params: SamplingParams::new(),
+            node_uid: None,
+            events: None,
         }
     }
     /// Execute the AI service and get the output with input params.
     pub async fn execute(&mut self, input: String) -> AINodeResult<String> {
-        if let Ok(params) = json::parse(self.input.as_str()) {
-            if params["history"].is_array() {
-                self.histroy = params["history"]
-                    .members()
-                    .map(|x| Chat::new(x["role"].to_string(), x["content"].to_string()))
-                    .collect();
-            }
-            if params["prompt_prefix"].is_string() {
-                self.prompt_prefix = params["prompt_prefix"].to_string();
+        self.execute_with(input, &RequestOverrides::default()).await
+    }
+    /// Execute the AI service with parameters overridden for this request only, the
+    /// configuration of the AI service is not changed. The execution is traced as an
+    /// `ai_node.execute` span with the uid of the node and its provider.
+    #[tracing::instrument(
+        name = "ai_node.execute",
+        skip_all,
+        fields(node = ?self.node_uid, provider = self.provider.as_deref())
+    )]
+    pub async fn execute_with(
+        &mut self,
+        input: String,
+        overrides: &RequestOverrides,
+    ) -> AINodeResult<String> {
+        self.prepare(AINodeInput::parse(input)?).await?;
+        let input = self.input.clone();
+        let output = self.execute_raw(overrides).await?;
+        self.remember(input, &output).await?;
+        Ok(output)
+    }
+    /// Execute the AI service with the input ports and get the structured output.
+    pub async fn execute_ports(&mut self, input: AINodeInput) -> AINodeResult<AINodeOutput> {
+        self.execute_ports_with(input, &RequestOverrides::default())
+            .await
+    }
+    /// Same as `execute_ports`, with parameters overridden for this request only.
+    pub async fn execute_ports_with(
+        &mut self,
+        input: AINodeInput,
+        overrides: &RequestOverrides,
+    ) -> AINodeResult<AINodeOutput> {
+        let before = self.service.get_total_usage();
+        self.prepare(input).await?;
+        self.reasoning = None;
+        let input = self.input.clone();
+        let content = self.execute_raw(overrides).await?;
+        self.remember(input, &content).await?;
+        Ok(AINodeOutput::new(
+            content,
+            self.reasoning.take(),
+            Some(self.service.get_total_usage() - before),
+        ))
+    }
+    /// Execute the AI service and get a stream of the output. The answer is added to the
+    /// history when the stream is finished.
+    pub async fn execute_stream(
+        &mut self,
+        input: String,
+        overrides: &RequestOverrides,
+    ) -> AINodeResult<ChatStream<'_>> {
+        self.prepare(AINodeInput::parse(input)?).await?;
+        self.push_prompt()?;
+        let chats = self.history_policy.apply(&self.histroy);
+        let overrides = self.with_params(overrides);
+        let stream = match &mut self.service {
+            AIService::DeepSeek { client } => {
+                client.send_request_stream(&chats, &overrides).await?
             }
-            if params["prompt_suffix"].is_string() {
-                self.prompt_suffix = params["prompt_suffix"].to_string();
+        };
+        Ok(ChatStream::new(self, stream))
+    }
+    /// Send the chats to the AI service of the node and get the content of the answer,
+    /// without touching the history.
+    pub async fn complete(
+        &mut self,
+        chats: &Vec<Chat>,
+        overrides: &RequestOverrides,
+    ) -> AINodeResult<String> {
+        let overrides = self.with_params(overrides);
+        self.service.complete(chats, &overrides).await
+    }
+    /// Get the overrides of a request with the sampling parameters of the node under them.
+    fn with_params(&self, overrides: &RequestOverrides) -> RequestOverrides {
+        let overrides = overrides.clone();
+        if overrides.get_node_params().is_empty() {
+            overrides.node_params(self.params.clone())
+        } else {
+            overrides
+        }
+    }
+    /// Read the input, compact the history and recall the memories, before the prompt is
+    /// sent.
+    pub(crate) async fn prepare(&mut self, input: AINodeInput) -> AINodeResult<()> {
+        self.apply_ports(input);
+        self.compact_history().await?;
+        self.recall().await
+    }
+    /// Recall the memories relevant to the input, if the node has a memory.
+    async fn recall(&mut self) -> AINodeResult<()> {
+        self.recalled = match &mut self.memory {
+            Some(memory) => memory.recall(&self.input).await?,
+            None => Vec::new(),
+        };
+        Ok(())
+    }
+    /// Extract the facts of the answered exchange into the memory, if the node has one.
+    pub(crate) async fn remember(&mut self, input: String, answer: &str) -> AINodeResult<()> {
+        if let Some(memory) = &mut self.memory {
+            let exchange = [
+                Chat::new(Role::User, input),
+                Chat::new(Role::Assistant, answer.to_string()),
+            ];
+            memory.remember(&exchange).await?;
+        }
+        Ok(())
+    }
+    /// Build the prompt and push it to the history as a user message.
+    pub(crate) fn push_prompt(&mut self) -> AINodeResult<()> {
+        let prompt = self.build_prompt()?;
+        self.histroy
+            .push(Chat::new(Role::User, prompt).node_uid(self.node_uid));
+        Ok(())
+    }
+    /// Send the history to the AI service once, and get the content and the tool calls of
+    /// the answer. The answer is pushed to the history.
+    pub(crate) async fn turn(
+        &mut self,
+        overrides: &RequestOverrides,
+    ) -> AINodeResult<(String, Vec<ToolCall>)> {
+        let mut overrides = self.with_params(overrides);
+        if !self.tools.is_empty() && overrides.get_tools().is_none() {
+            overrides = overrides.tools(self.tools.to_json());
+        }
+        if let Some(events) = self.events.clone() {
+            if overrides.get_tools().is_none() && self.service.can_stream() {
+                return self.stream_turn(&overrides, &events).await;
             }
-            if params["input"].is_string() {
-                self.input = params["input"].to_string();
+        }
+        match &mut self.service {
+            AIService::DeepSeek { client: _ } => self.deepseek_turn(&overrides).await,
+        }
+    }
+    /// Send the history to the AI service in stream mode, and emit the pieces of the answer
+    /// to the event channel. The answer is added to the history like in a normal turn.
+    async fn stream_turn(
+        &mut self,
+        overrides: &RequestOverrides,
+        events: &UnboundedSender<RunEvent>,
+    ) -> AINodeResult<(String, Vec<ToolCall>)> {
+        let chats = self.history_policy.apply(&self.histroy);
+        let stream = match &mut self.service {
+            AIService::DeepSeek { client } => client.send_request_stream(&chats, overrides).await?,
+        };
+        let node = self.node_uid.unwrap_or_default();
+        let mut reasoning = String::new();
+        let mut stream = ChatStream::new(self, stream);
+        let mut content = String::new();
+        while let Some(event) = stream.next().await {
+            match event? {
+                StreamEvent::Delta(delta) => {
+                    let _ = events.send(RunEvent::NodeOutput { node, delta });
+                }
+                StreamEvent::Reasoning(piece) => reasoning.push_str(&piece),
+                StreamEvent::Done {
+                    content: answer, ..
+                } => content = answer,
             }
-            // role must be set after history, because the role is the first message in the history.
-            if params["role"].is_object() {
-                self.set_role(Some(params["role"].to_string()));
+        }
+        if !reasoning.is_empty() {
+            self.reasoning = Some(reasoning);
+        }
+        Ok((content, Vec::new()))
+    }
+    /// Invoke the tools of the calls, and push their results to the history as tool messages.
+    /// The results are returned in the order of the calls.
+    pub(crate) async fn call_tools(&mut self, tool_calls: &[ToolCall]) -> Vec<String> {
+        let mut results = Vec::new();
+        for call in tool_calls {
+            let result = self.tools.call(call).await;
+            self.push_tool_result(call, result.clone());
+            results.push(result);
+        }
+        results
+    }
+    /// Push the result of a tool call to the history as a tool message.
+    pub(crate) fn push_tool_result(&mut self, call: &ToolCall, result: String) {
+        self.histroy.push(
+            Chat::new(Role::Tool, result)
+                .node_uid(self.node_uid)
+                .tool_call_id(Some(call.id.clone())),
+        );
+    }
+    /// Set the parameters of the node from the input ports.
+    fn apply_ports(&mut self, input: AINodeInput) {
+        if let Some(history) = input.history {
+            self.histroy = history;
+        }
+        if let Some(prompt_prefix) = input.prompt_prefix {
+            self.prompt_prefix = prompt_prefix;
+        }
+        if let Some(prompt_suffix) = input.prompt_suffix {
+            self.prompt_suffix = prompt_suffix;
+        }
+        if let Some(user_input) = input.input {
+            self.input = user_input;
+        }
+        if let Some(context) = input.context {
+            self.context = context;
+        }
+        self.variables.extend(input.variables);
+        // role must be set after history, because the role is the first message in the history.
+        if let Some(role) = input.role {
+            self.set_role(Some(role));
+        }
+    }
+    /// Build the prompt from the prefix, the input and the suffix, and render the role into
+    /// the system message.
+    fn build_prompt(&mut self) -> AINodeResult<String> {
+        let mut prefix = self.render(&self.prompt_prefix)?;
+        if !self.recalled.is_empty() {
+            prefix = format!("{}\n{}", Memory::prompt(&self.recalled), prefix);
+        }
+        let suffix = self.render(&self.prompt_suffix)?;
+        if let Some(role) = &self.role {
+            if template::is_template(role) {
+                let rendered = self.render(role)?;
+                if let Some(first) = self.histroy.first_mut() {
+                    if first.get_role() == Role::System {
+                        *first = Chat::new(Role::System, rendered);
+                    }
+                }
             }
+        }
+        if self.context.is_empty() {
+            Ok(format!("{}\n{}\n{}", prefix, self.input, suffix))
         } else {
-            self.input = input;
+            Ok(format!(
+                "{}\n{}\n{}\n{}",
+                prefix, self.context, self.input, suffix
+            ))
         }
-        self.execute_raw().await
+    }
+    /// Render a template with the variables of the node.
+    fn render(&self, text: &str) -> AINodeResult<String> {
+        template::render(text, &self.variables).map_err(|e| {
+            AINodeError::new(
+                AINodeErrorType::TemplateError(e),
+                "Failed to render the prompt".to_string(),
+            )
+        })
+    }
+    /// Execute the AI service and parse the output as json.
+    pub async fn execute_json(&mut self, input: String) -> AINodeResult<JsonValue> {
+        let output = self.execute(input).await?;
+        json::parse(&output).map_err(|e| {
+            AINodeError::new(
+                AINodeErrorType::InvalidJsonOutput,
+                format!("The output is not valid json. {}", e),
+            )
+        })
+    }
+    /// Execute the AI service in json mode and deserialize the output into `T`. When the
+    /// output doesn't match `T`, the AI service is asked to fix it with the error of serde,
+    /// at most `json_retries` times.
+    pub async fn execute_typed<T: DeserializeOwned>(&mut self, input: String) -> AINodeResult<T> {
+        let overrides = RequestOverrides::new().response_format(ResponseFormat::Json);
+        self.prepare(AINodeInput::parse(input)?).await?;
+        let input = self.input.clone();
+        // json mode of the AI services requires the prompt to ask for json explicitly
+        self.input = format!("{}\nAnswer in JSON.", input);
+        let result = self.execute_checked(&overrides, check_type::<T>).await;
+        self.input = input;
+        let text = result?;
+        self.remember(self.input.clone(), &text).await?;
+        serde_json::from_str(&text).map_err(type_error)
     }
     /// Execute the AI service and get the output.
-    async fn execute_raw(&mut self) -> AINodeResult<String> {
-        match &mut self.service {
-            AIService::DeepSeek { client: _ } => self.deepseek_execute().await,
+    /// In json mode, or with an output schema, the AI service will be asked to fix its answer
+    /// until the output passes the check or the retry budget is used up.
+    async fn execute_raw(&mut self, overrides: &RequestOverrides) -> AINodeResult<String> {
+        self.execute_checked(overrides, |_| Ok(())).await
+    }
+    /// Same as `execute_raw`, with one more check of the output in json mode, whose error is
+    /// sent back to the AI service like the others.
+    async fn execute_checked(
+        &mut self,
+        overrides: &RequestOverrides,
+        check: fn(&str) -> AINodeResult<()>,
+    ) -> AINodeResult<String> {
+        let mut output = self.execute_service(overrides).await?;
+        let json_mode = match overrides.get_response_format() {
+            Some(response_format) => matches!(response_format, ResponseFormat::Json),
+            None => self.service.is_json_mode(),
+        };
+        if !json_mode && self.output_schema.is_none() {
+            return Ok(output);
         }
+        let input = self.input.clone();
+        let mut retries = 0;
+        let result = loop {
+            let error = match self.check_output(&output).and_then(|()| check(&output)) {
+                Ok(()) => break Ok(output),
+                Err(e) => e,
+            };
+            if retries >= self.json_retries {
+                break Err(error);
+            }
+            retries += 1;
+            self.input = Self::json_repair_prompt(&error);
+            output = match self.execute_service(overrides).await {
+                Ok(output) => output,
+                Err(e) => break Err(e),
+            };
+        };
+        self.input = input;
+        result
     }
-    /// Set the role of teh assistant as builder.
-    pub fn role(mut self, role: Option<String>) -> Self {
-        let original_role_is_none = self.role.is_none();
-        self.role = role;
-        if self.role.is_none() {
-            return self;
-        }
-        if original_role_is_none {
-            self.histroy.insert(
-                0,
-                Chat::new("system".to_string(), self.role.clone().unwrap()),
-            );
+    /// Check that the output is valid json and matches the output schema.
+    pub(crate) fn check_output(&self, output: &str) -> AINodeResult<()> {
+        let value: serde_json::Value = serde_json::from_str(output).map_err(|e| {
+            AINodeError::new(
+                AINodeErrorType::InvalidJsonOutput,
+                format!("The output is not valid json. {}", e),
+            )
+        })?;
+        let schema = match &self.output_schema {
+            Some(schema) => schema,
+            None => return Ok(()),
+        };
+        let validator = jsonschema::validator_for(schema).map_err(|e| {
+            AINodeError::new(
+                AINodeErrorType::InvalidInput,
+                format!("The output schema is not valid. {}", e),
+            )
+        })?;
+        let violations: Vec<SchemaViolation> = validator
+            .iter_errors(&value)
+            .map(|e| SchemaViolation {
+                path: e.instance_path().to_string(),
+                message: e.to_string(),
+            })
+            .collect();
+        if violations.is_empty() {
+            Ok(())
         } else {
-            self.histroy[0] = Chat::new("system".to_string(), self.role.clone().unwrap());
+            Err(AINodeError::new(
+                AINodeErrorType::SchemaViolation(violations),
+                "The output doesn't match the schema.".to_string(),
+            ))
+        }
+    }
+    /// Send the current input to the AI service once.
+    /// The tools requested by the AI service are invoked, at most `max_tool_steps` rounds.
+    async fn execute_service(&mut self, overrides: &RequestOverrides) -> AINodeResult<String> {
+        self.push_prompt()?;
+        let mut steps = 0;
+        loop {
+            let (content, tool_calls) = self.turn(overrides).await?;
+            if tool_calls.is_empty() {
+                return Ok(content);
+            }
+            if steps >= self.max_tool_steps {
+                return Err(AINodeError::new(
+                    AINodeErrorType::ToolError,
+                    format!(
+                        "The AI service still calls tools after {} rounds.",
+                        self.max_tool_steps
+                    ),
+                ));
+            }
+            steps += 1;
+            self.call_tools(&tool_calls).await;
         }
+    }
+    /// The prompt to ask the AI service to fix an answer that is not valid json, or doesn't
+    /// match the expected type.
+    pub(crate) fn json_repair_prompt(error: &impl std::fmt::Display) -> String {
+        format!(
+            "Your previous answer can't be accepted ({}). Please answer again with valid JSON only.",
+            error
+        )
+    }
+    /// Set the role of teh assistant as builder.
+    pub fn role(mut self, role: Option<String>) -> Self {
+        self.set_role(role);
         self
     }
-    /// Set the role of the assistant.
+    /// Set the role of the assistant. The role is kept as the first message of the history,
+    /// replacing the system message that is already there, and `None` removes the message of
+    /// the old role.
     pub fn set_role(&mut self, role: Option<String>) {
+        let had_role = self.role.is_some();
         self.role = role;
+        // the history may have lost the chat of the old role, or got a system chat of its own
+        // when it was replaced
+        let has_system = self
+            .histroy
+            .first()
+            .is_some_and(|first| first.get_role() == Role::System);
+        match (&self.role, has_system) {
+            (Some(role), true) => self.histroy[0] = Chat::new(Role::System, role.clone()),
+            (Some(role), false) => self
+                .histroy
+                .insert(0, Chat::new(Role::System, role.clone())),
+            (None, true) if had_role => {
+                self.histroy.remove(0);
+            }
+            (None, _) => {}
+        }
     }
     /// Get the role of the assistant.
     pub fn get_role(&self) -> &Option<String> {
@@ -156,10 +792,125 @@ impl AINode {
     pub fn get_history(&self) -> &Vec<Chat> {
         &self.histroy
     }
+    /// Add few-shot examples as builder. Every example is a pair of a user message and the
+    /// expected answer of the assistant, inserted after the system message and pinned, so the
+    /// history policy never trims them.
+    pub fn few_shot(mut self, examples: Vec<(String, String)>) -> Self {
+        self.insert_few_shot(examples);
+        self
+    }
+    /// Replace the few-shot examples.
+    pub fn set_few_shot(&mut self, examples: Vec<(String, String)>) {
+        self.histroy
+            .retain(|chat| chat.get_role() == Role::System || !chat.get_metadata().pinned);
+        self.insert_few_shot(examples);
+    }
+    /// Insert few-shot examples after the system message and the existing examples.
+    fn insert_few_shot(&mut self, examples: Vec<(String, String)>) {
+        let position = self
+            .histroy
+            .iter()
+            .take_while(|chat| chat.get_role() == Role::System || chat.get_metadata().pinned)
+            .count();
+        let chats = examples.into_iter().flat_map(|(question, answer)| {
+            [
+                Chat::new(Role::User, question).pinned(true),
+                Chat::new(Role::Assistant, answer).pinned(true),
+            ]
+        });
+        self.histroy.splice(position..position, chats);
+    }
     /// Push a chat to the history.
     pub fn push_history(&mut self, chat: Chat) {
         self.histroy.push(chat);
     }
+    /// Summarize the older history if it exceeds the threshold of the compaction.
+    pub async fn compact_history(&mut self) -> AINodeResult<()> {
+        if let Some(compaction) = &mut self.compaction {
+            if let Some(history) = compaction.compact(&self.histroy).await? {
+                self.histroy = history;
+            }
+        }
+        Ok(())
+    }
+    /// Set the compaction as builder.
+    pub fn compaction(mut self, compaction: Option<Compaction>) -> Self {
+        self.compaction = compaction;
+        self
+    }
+    /// Set the compaction.
+    pub fn set_compaction(&mut self, compaction: Option<Compaction>) {
+        self.compaction = compaction;
+    }
+    /// Get the compaction.
+    pub fn get_compaction(&self) -> &Option<Compaction> {
+        &self.compaction
+    }
+    /// Set the memory as builder.
+    pub fn memory(mut self, memory: Option<Memory>) -> Self {
+        self.memory = memory;
+        self
+    }
+    /// Set the memory.
+    pub fn set_memory(&mut self, memory: Option<Memory>) {
+        self.memory = memory;
+    }
+    /// Get the memory.
+    pub fn get_memory(&self) -> &Option<Memory> {
+        &self.memory
+    }
+    /// Set the history policy as builder.
+    pub fn history_policy(mut self, history_policy: HistoryPolicy) -> Self {
+        self.history_policy = history_policy;
+        self
+    }
+    /// Set the history policy.
+    pub fn set_history_policy(&mut self, history_policy: HistoryPolicy) {
+        self.history_policy = history_policy;
+    }
+    /// Get the history policy.
+    pub fn get_history_policy(&self) -> &HistoryPolicy {
+        &self.history_policy
+    }
+    /// Fork the node with the history truncated to the first `index` chats, to explore
+    /// another continuation of the conversation. The original node is not changed.
+    pub fn fork_at(&self, index: usize) -> AINodeResult<Self> {
+        if index > self.histroy.len() {
+            return Err(AINodeError::new(
+                AINodeErrorType::InvalidInput,
+                format!(
+                    "Can't fork at chat {}, the history only has {} chats.",
+                    index,
+                    self.histroy.len()
+                ),
+            ));
+        }
+        let mut node = self.clone();
+        node.histroy.truncate(index);
+        if node.histroy.is_empty() {
+            node.role = None;
+        }
+        Ok(node)
+    }
+    /// Render the history as a transcript.
+    pub fn export_transcript(&self, format: TranscriptFormat) -> String {
+        transcript::render(&self.histroy, format)
+    }
+    /// Save the history to a file. A path ending with `.jsonl` is saved as json lines,
+    /// otherwise as a json array.
+    pub fn save_history<P: AsRef<Path>>(&self, path: P) -> AINodeResult<()> {
+        chat::save_chats(path.as_ref(), &self.histroy)
+    }
+    /// Load the history from a file saved by `save_history`, replacing the current history.
+    /// If the first chat is a system message, it becomes the role of the assistant.
+    pub fn load_history<P: AsRef<Path>>(&mut self, path: P) -> AINodeResult<()> {
+        self.histroy = chat::load_chats(path.as_ref())?;
+        self.role = match self.histroy.first() {
+            Some(chat) if chat.get_role() == Role::System => Some(chat.get_content().as_text()),
+            _ => None,
+        };
+        Ok(())
+    }
     /// Set the prompt prefix as builder.
     pub fn prompt_prefix(mut self, prompt_prefix: String) -> Self {
         self.prompt_prefix = prompt_prefix;
@@ -199,6 +950,40 @@ impl AINode {
     pub fn get_input(&self) -> &String {
         &self.input
     }
+    /// Set the context as builder.
+    pub fn context(mut self, context: String) -> Self {
+        self.context = context;
+        self
+    }
+    /// Set the context.
+    pub fn set_context(&mut self, context: String) {
+        self.context = context;
+    }
+    /// Get the context.
+    pub fn get_context(&self) -> &String {
+        &self.context
+    }
+    /// Set the variables of the templates as builder.
+    pub fn variables(mut self, variables: Variables) -> Self {
+        self.variables = variables;
+        self
+    }
+    /// Set the variables of the templates.
+    pub fn set_variables(&mut self, variables: Variables) {
+        self.variables = variables;
+    }
+    /// Set one variable of the templates.
+    pub fn set_variable(&mut self, name: &str, value: String) {
+        self.variables.insert(name.to_string(), value);
+    }
+    /// Set the values of the run context as the variables `context.<key>` of the templates.
+    pub fn set_context_variables(&mut self, context: &RunContext) {
+        self.variables.extend(context.to_variables());
+    }
+    /// Get the variables of the templates.
+    pub fn get_variables(&self) -> &Variables {
+        &self.variables
+    }
     /// Get the AI service.
     pub fn get_service(&self) -> &AIService {
         &self.service
@@ -207,11 +992,139 @@ impl AINode {
     pub fn set_service(&mut self, service: AIService) {
         self.service = service;
     }
+    /// Set the recording that the requests of the AI service are recorded to or replayed
+    /// from.
+    pub fn set_recording(&mut self, recording: Option<recording::Recording>) {
+        self.service.set_recording(recording);
+    }
+    /// Set the json retry budget as builder.
+    pub fn json_retries(mut self, json_retries: usize) -> Self {
+        self.json_retries = json_retries;
+        self
+    }
+    /// Set the json retry budget.
+    pub fn set_json_retries(&mut self, json_retries: usize) {
+        self.json_retries = json_retries;
+    }
+    /// Get the json retry budget.
+    pub fn get_json_retries(&self) -> usize {
+        self.json_retries
+    }
+    /// Set the output json schema as builder.
+    pub fn output_schema(mut self, output_schema: Option<serde_json::Value>) -> Self {
+        self.output_schema = output_schema;
+        self
+    }
+    /// Set the output json schema.
+    pub fn set_output_schema(&mut self, output_schema: Option<serde_json::Value>) {
+        self.output_schema = output_schema;
+    }
+    /// Get the output json schema.
+    pub fn get_output_schema(&self) -> Option<&serde_json::Value> {
+        self.output_schema.as_ref()
+    }
+    /// Set the name of the AI service in the registry as builder.
+    pub fn provider(mut self, provider: Option<String>) -> Self {
+        self.provider = provider;
+        self
+    }
+    /// Set the name of the AI service in the registry.
+    pub fn set_provider(&mut self, provider: Option<String>) {
+        self.provider = provider;
+    }
+    /// Get the name of the AI service in the registry.
+    pub fn get_provider(&self) -> Option<&str> {
+        self.provider.as_deref()
+    }
+    /// Set the tools as builder.
+    pub fn tools(mut self, tools: ToolRegistry) -> Self {
+        self.tools = tools;
+        self
+    }
+    /// Set the tools.
+    pub fn set_tools(&mut self, tools: ToolRegistry) {
+        self.tools = tools;
+    }
+    /// Get the tools.
+    pub fn get_tools(&self) -> &ToolRegistry {
+        &self.tools
+    }
+    /// Set the max number of tool calling rounds as builder.
+    pub fn max_tool_steps(mut self, max_tool_steps: usize) -> Self {
+        self.max_tool_steps = max_tool_steps;
+        self
+    }
+    /// Set the max number of tool calling rounds.
+    pub fn set_max_tool_steps(&mut self, max_tool_steps: usize) {
+        self.max_tool_steps = max_tool_steps;
+    }
+    /// Get the max number of tool calling rounds.
+    pub fn get_max_tool_steps(&self) -> usize {
+        self.max_tool_steps
+    }
+    /// Set the sampling parameters of the node as builder.
+    pub fn params(mut self, params: SamplingParams) -> Self {
+        self.params = params;
+        self
+    }
+    /// Set the sampling parameters of the node.
+    pub fn set_params(&mut self, params: SamplingParams) {
+        self.params = params;
+    }
+    /// Get the sampling parameters of the node.
+    pub fn get_params(&self) -> &SamplingParams {
+        &self.params
+    }
+    /// The default max number of tool calling rounds.
+    pub fn default_max_tool_steps() -> usize {
+        8
+    }
+    /// The default json retry budget.
+    pub fn default_json_retries() -> usize {
+        2
+    }
+    /// Set the uid of the worknode that holds this AI node.
+    pub fn set_node_uid(&mut self, node_uid: Option<Uuid>) {
+        self.node_uid = node_uid;
+    }
+    /// Get the uid of the worknode that holds this AI node.
+    pub fn get_node_uid(&self) -> Option<Uuid> {
+        self.node_uid
+    }
+    /// Set the event channel as builder.
+    pub fn events(mut self, events: Option<UnboundedSender<RunEvent>>) -> Self {
+        self.events = events;
+        self
+    }
+    /// Set the channel that the pieces of the answers are emitted to. With a channel, the
+    /// requests that call no tools are streamed.
+    pub fn set_events(&mut self, events: Option<UnboundedSender<RunEvent>>) {
+        self.events = events;
+    }
+    /// Get the event channel.
+    pub fn get_events(&self) -> Option<&UnboundedSender<RunEvent>> {
+        self.events.as_ref()
+    }
+}
+
+/// Check that the output deserializes into `T`.
+fn check_type<T: DeserializeOwned>(output: &str) -> AINodeResult<()> {
+    serde_json::from_str::<T>(output)
+        .map(|_| ())
+        .map_err(type_error)
+}
+
+/// Create the error of an output that doesn't match the expected type.
+fn type_error(e: serde_json::Error) -> AINodeError {
+    AINodeError::new(
+        AINodeErrorType::InvalidJsonOutput,
+        format!("The output doesn't match the expected type. {}", e),
+    )
 }
 
 #[cfg(test)]
 mod test {
-    use super::deepseek::{DeepSeekClient, DeepSeekModel, DEEPSEEK_API_URL};
+    use super::deepseek::{DeepSeekClient, DeepSeekModel, ResponseFormat, DEEPSEEK_API_URL};
     use super::*;
     use tokio::runtime::Runtime;
 
@@ -227,7 +1140,8 @@ mod test {
                 "你是一个可爱的猫娘，请每一句话都使用猫娘的语气，并一定以“喵”结尾。".to_string(),
             ))
             .input("早上好".to_string());
-        let result = ai_node.execute_raw();
+        let overrides = RequestOverrides::default();
+        let result = ai_node.execute_raw(&overrides);
         let rt = Runtime::new().unwrap();
         let result = rt.block_on(result);
         match result {
@@ -239,4 +1153,259 @@ mod test {
             }
         }
     }
+
+    #[test]
+    fn json_mode_detection() {
+        let client = DeepSeekClient::new(DEEPSEEK_API_URL, DeepSeekModel::DeepseekChat);
+        assert!(!AIService::new_deepseek(client.clone()).is_json_mode());
+        let client = client.response_format(Some(ResponseFormat::Json));
+        assert!(AIService::new_deepseek(client).is_json_mode());
+    }
+
+    #[test]
+    fn missing_api_key_is_an_error() {
+        use crate::error::ai_node_error::deepseek_error::DeepSeekErrorType;
+        let client = DeepSeekClient::new(DEEPSEEK_API_URL, DeepSeekModel::DeepseekChat);
+        let mut ai_node = AINode::new(AIService::new_deepseek(client));
+        let rt = Runtime::new().unwrap();
+        let error = rt.block_on(ai_node.execute("hi".to_string())).unwrap_err();
+        let AINodeErrorType::DeepSeekError(e) = error.get_error_type() else {
+            panic!("Error: {}", error);
+        };
+        assert!(matches!(e.get_error_type(), DeepSeekErrorType::ApiKeyError));
+        assert_eq!(e.get_message(), "The api key is not set.");
+
+        // the stream needs the api key as well
+        let overrides = RequestOverrides::default();
+        let Some(error) = rt
+            .block_on(ai_node.execute_stream("hi".to_string(), &overrides))
+            .err()
+        else {
+            panic!("The stream started without an api key.");
+        };
+        assert!(matches!(
+            error.get_error_type(),
+            AINodeErrorType::DeepSeekError(e) if matches!(e.get_error_type(), DeepSeekErrorType::ApiKeyError)
+        ));
+    }
+
+    #[test]
+    fn typed_repair_prompt_contains_error() {
+        #[derive(serde::Deserialize, Debug)]
+        #[allow(dead_code)]
+        struct Answer {
+            answer: i64,
+        }
+        let error = serde_json::from_str::<Answer>(r#"{"answer": "one"}"#).unwrap_err();
+        let prompt = AINode::json_repair_prompt(&error);
+        assert!(prompt.contains("invalid type"));
+
+        // one repair loop checks both the json and the type, so one retry is two requests
+        let client = DeepSeekClient::new(DEEPSEEK_API_URL, DeepSeekModel::DeepseekChat);
+        let overrides = RequestOverrides::new().response_format(ResponseFormat::Json);
+        let wrong = r#"{"answer": "one"}"#;
+        let repair = AINode::json_repair_prompt(&check_type::<Answer>(wrong).unwrap_err());
+        let prompt = Chat::new(Role::User, "\nsplit\nAnswer in JSON.\n".to_string());
+        let retry = vec![
+            prompt.clone(),
+            Chat::new(Role::Assistant, wrong.to_string()),
+            Chat::new(Role::User, format!("\n{}\n", repair)),
+        ];
+        let typed_node = |answer: &str| {
+            let recording = recording::Recording::replay(vec![
+                client.exchange(&vec![prompt.clone()], &overrides, wrong),
+                client.exchange(&retry, &overrides, answer),
+            ]);
+            AINode::new(AIService::new_deepseek(
+                client.clone().recording(Some(recording)),
+            ))
+            .json_retries(1)
+        };
+        let rt = Runtime::new().unwrap();
+        let answer: Answer = rt
+            .block_on(typed_node(r#"{"answer": 1}"#).execute_typed("split".to_string()))
+            .unwrap();
+        assert_eq!(answer.answer, 1);
+        let error = rt
+            .block_on(typed_node(wrong).execute_typed::<Answer>("split".to_string()))
+            .unwrap_err();
+        assert!(matches!(
+            error.get_error_type(),
+            AINodeErrorType::InvalidJsonOutput
+        ));
+    }
+
+    #[test]
+    fn json_repair_prompt_contains_error() {
+        let error = json::parse("{\"answer\": ").unwrap_err();
+        let prompt = AINode::json_repair_prompt(&error);
+        assert!(prompt.contains(&error.to_string()));
+
+        // the invalid answer is sent back with its error, and the repaired answer is accepted
+        let client = DeepSeekClient::new(DEEPSEEK_API_URL, DeepSeekModel::DeepseekChat)
+            .response_format(Some(ResponseFormat::Json));
+        let overrides = RequestOverrides::default();
+        let node = AINode::new(AIService::new_deepseek(client.clone()));
+        let repair = AINode::json_repair_prompt(&node.check_output("{").unwrap_err());
+        let prompt = Chat::new(Role::User, "\nrefund\n".to_string());
+        let retry = vec![
+            prompt.clone(),
+            Chat::new(Role::Assistant, "{".to_string()),
+            Chat::new(Role::User, format!("\n{}\n", repair)),
+        ];
+        let json_node = |answer: &str, json_retries: usize| {
+            let recording = recording::Recording::replay(vec![
+                client.exchange(&vec![prompt.clone()], &overrides, "{"),
+                client.exchange(&retry, &overrides, answer),
+            ]);
+            AINode::new(AIService::new_deepseek(
+                client.clone().recording(Some(recording)),
+            ))
+            .json_retries(json_retries)
+        };
+        let rt = Runtime::new().unwrap();
+        let output = rt
+            .block_on(json_node("{\"refund\": true}", 1).execute("refund".to_string()))
+            .unwrap();
+        assert_eq!(output, "{\"refund\": true}");
+
+        // the retries are used up, and the last error is given
+        for json_retries in [0, 1] {
+            let error = rt
+                .block_on(json_node("{", json_retries).execute("refund".to_string()))
+                .unwrap_err();
+            assert!(matches!(
+                error.get_error_type(),
+                AINodeErrorType::InvalidJsonOutput
+            ));
+        }
+    }
+
+    #[test]
+    fn fork_at_truncates_history() {
+        let client = DeepSeekClient::new(DEEPSEEK_API_URL, DeepSeekModel::DeepseekChat);
+        let mut ai_node =
+            AINode::new(AIService::new_deepseek(client)).role(Some("You are a cat".to_string()));
+        ai_node.push_history(Chat::new(Role::User, "Hi".to_string()));
+        ai_node.push_history(Chat::new(Role::Assistant, "Meow".to_string()));
+        let fork = ai_node.fork_at(2).unwrap();
+        assert_eq!(fork.get_history().len(), 2);
+        assert_eq!(ai_node.get_history().len(), 3);
+        assert_eq!(fork.get_role().as_deref(), Some("You are a cat"));
+        assert!(ai_node.fork_at(0).unwrap().get_role().is_none());
+        assert!(ai_node.fork_at(4).is_err());
+
+        // a history without the chat of the role gets it back, instead of losing a chat
+        ai_node.set_history(Vec::new());
+        ai_node.set_role(Some("You are a dog".to_string()));
+        ai_node.set_role(Some("You are a fox".to_string()));
+        assert_eq!(ai_node.get_history().len(), 1);
+        assert_eq!(
+            ai_node.get_history()[0].get_content().as_text(),
+            "You are a fox"
+        );
+        ai_node.set_role(None);
+        assert!(ai_node.get_history().is_empty());
+
+        // the system chat of a replaced history is the one of the role, not a second one
+        let mut ai_node = AINode::new(AIService::new_deepseek(DeepSeekClient::new(
+            DEEPSEEK_API_URL,
+            DeepSeekModel::DeepseekChat,
+        )));
+        ai_node.set_history(vec![
+            Chat::new(Role::System, "You are a cat".to_string()),
+            Chat::new(Role::User, "Hi".to_string()),
+        ]);
+        ai_node.set_role(None);
+        assert_eq!(ai_node.get_history().len(), 2);
+        ai_node.set_role(Some("You are a dog".to_string()));
+        assert_eq!(ai_node.get_history().len(), 2);
+        assert_eq!(ai_node.get_history()[0].get_role(), Role::System);
+        assert_eq!(
+            ai_node.get_history()[0].get_content().as_text(),
+            "You are a dog"
+        );
+        ai_node.set_role(None);
+        assert_eq!(ai_node.get_history().len(), 1);
+        assert_eq!(ai_node.get_history()[0].get_role(), Role::User);
+    }
+
+    #[test]
+    fn build_prompt_renders_templates() {
+        let client = DeepSeekClient::new(DEEPSEEK_API_URL, DeepSeekModel::DeepseekChat);
+        let mut ai_node = AINode::new(AIService::new_deepseek(client))
+            .role(Some("You are {{name}}".to_string()))
+            .prompt_prefix("Today is {{day}}.".to_string())
+            .prompt_suffix("Answer in {{language | default: English}}.".to_string());
+        ai_node.apply_ports(
+            AINodeInput::parse(
+                r#"{"input": "Hi", "variables": {"name": "a cat", "day": "Monday"}}"#.to_string(),
+            )
+            .unwrap(),
+        );
+        let prompt = ai_node.build_prompt().unwrap();
+        assert_eq!(prompt, "Today is Monday.\nHi\nAnswer in English.");
+        assert_eq!(
+            ai_node.get_history()[0].get_content().as_text(),
+            "You are a cat"
+        );
+        ai_node.set_variables(Variables::new());
+        assert!(ai_node.build_prompt().is_err());
+    }
+
+    #[test]
+    fn few_shot_examples_after_role() {
+        let client = DeepSeekClient::new(DEEPSEEK_API_URL, DeepSeekModel::DeepseekChat);
+        let mut ai_node = AINode::new(AIService::new_deepseek(client))
+            .role(Some("Translate to French".to_string()))
+            .few_shot(vec![("cat".to_string(), "chat".to_string())]);
+        ai_node.push_history(Chat::new(Role::User, "dog".to_string()));
+        ai_node.set_few_shot(vec![
+            ("one".to_string(), "un".to_string()),
+            ("two".to_string(), "deux".to_string()),
+        ]);
+        let contents: Vec<String> = ai_node
+            .get_history()
+            .iter()
+            .map(|chat| chat.get_content().as_text())
+            .collect();
+        assert_eq!(
+            contents,
+            vec!["Translate to French", "one", "un", "two", "deux", "dog"]
+        );
+        assert!(ai_node.get_history()[1].get_metadata().pinned);
+    }
+
+    #[test]
+    fn check_output_against_schema() {
+        let client = DeepSeekClient::new(DEEPSEEK_API_URL, DeepSeekModel::DeepseekChat);
+        let ai_node =
+            AINode::new(AIService::new_deepseek(client)).output_schema(Some(serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "name": { "type": "string" },
+                    "age": { "type": "integer", "minimum": 0 }
+                },
+                "required": ["name", "age"]
+            })));
+        assert!(ai_node.check_output(r#"{"name": "Tom", "age": 3}"#).is_ok());
+        assert!(matches!(
+            ai_node
+                .check_output("not json")
+                .unwrap_err()
+                .get_error_type(),
+            AINodeErrorType::InvalidJsonOutput
+        ));
+        let error = ai_node
+            .check_output(r#"{"name": 1, "age": -1}"#)
+            .unwrap_err();
+        match error.get_error_type() {
+            AINodeErrorType::SchemaViolation(violations) => {
+                let mut paths: Vec<&str> = violations.iter().map(|v| v.path.as_str()).collect();
+                paths.sort();
+                assert_eq!(paths, vec!["/age", "/name"]);
+            }
+            _ => panic!("Unexpected error: {}", error),
+        }
+    }
 }
@@ -0,0 +1,594 @@
+//! # Chat
+//!
+//! This module contains the messages of a conversation with the AI service.
+//!
+//! The content of a message is either plain text, or a list of parts mixing text and images
+//! for the AI services that support vision.
+//!
+//! A list of chats can be saved to and loaded from a file, either as one json array or as
+//! json lines (one chat per line), chosen by the extension of the file.
+
+use super::tool::ToolCall;
+use crate::error::ai_node_error::{AINodeError, AINodeErrorType, AINodeResult};
+
+use chrono::{DateTime, Utc};
+use json::{object, JsonValue};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use std::path::Path;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+/// The enum of the role who sends a message in the chat.
+pub enum Role {
+    /// The message that tells the assistant how to behave.
+    System,
+    /// The message from the user.
+    User,
+    /// The message from the assistant.
+    Assistant,
+    /// The message that carries the result of a tool call.
+    Tool,
+}
+
+impl std::fmt::Display for Role {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Role::System => write!(f, "system"),
+            Role::User => write!(f, "user"),
+            Role::Assistant => write!(f, "assistant"),
+            Role::Tool => write!(f, "tool"),
+        }
+    }
+}
+
+impl std::str::FromStr for Role {
+    type Err = AINodeError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "system" => Ok(Role::System),
+            "user" => Ok(Role::User),
+            "assistant" => Ok(Role::Assistant),
+            "tool" => Ok(Role::Tool),
+            _ => Err(AINodeError::new(
+                AINodeErrorType::InvalidInput,
+                format!("Unknown role: {}", s),
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+/// The enum of one part of a multimodal message.
+pub enum ContentPart {
+    /// A piece of text.
+    Text { text: String },
+    /// An image that the AI service downloads from the url.
+    ImageUrl {
+        /// The url of the image.
+        url: String,
+        /// The detail level of the image, like `low`, `high` or `auto`.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        detail: Option<String>,
+    },
+    /// An image embedded in the message.
+    ImageBase64 {
+        /// The media type of the image, like `image/png`.
+        media_type: String,
+        /// The base64 encoded data of the image.
+        data: String,
+    },
+}
+
+impl ContentPart {
+    /// Whether the part is an image.
+    pub fn is_image(&self) -> bool {
+        !matches!(self, ContentPart::Text { .. })
+    }
+    /// Convert the part to the json format used by OpenAI compatible vision apis.
+    pub fn to_json(&self) -> JsonValue {
+        match self {
+            ContentPart::Text { text } => object! {
+                "type": "text",
+                text: text.clone(),
+            },
+            ContentPart::ImageUrl { url, detail } => {
+                let mut image_url = object! { url: url.clone() };
+                if let Some(detail) = detail {
+                    image_url["detail"] = detail.clone().into();
+                }
+                object! {
+                    "type": "image_url",
+                    image_url: image_url,
+                }
+            }
+            ContentPart::ImageBase64 { media_type, data } => object! {
+                "type": "image_url",
+                image_url: object! {
+                    url: format!("data:{};base64,{}", media_type, data),
+                },
+            },
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+/// The enum of the content of a message.
+pub enum Content {
+    /// Plain text.
+    Text(String),
+    /// A list of parts, which may contain images.
+    Parts(Vec<ContentPart>),
+}
+
+impl Content {
+    /// Whether the content contains any image.
+    pub fn has_image(&self) -> bool {
+        match self {
+            Content::Text(_) => false,
+            Content::Parts(parts) => parts.iter().any(|part| part.is_image()),
+        }
+    }
+    /// Get the text of the content. The text parts are joined with a new line and the
+    /// images are skipped.
+    pub fn as_text(&self) -> String {
+        match self {
+            Content::Text(text) => text.clone(),
+            Content::Parts(parts) => parts
+                .iter()
+                .filter_map(|part| match part {
+                    ContentPart::Text { text } => Some(text.as_str()),
+                    _ => None,
+                })
+                .collect::<Vec<&str>>()
+                .join("\n"),
+        }
+    }
+    /// Convert the content to the json format used by OpenAI compatible vision apis.
+    /// Plain text is kept as a string.
+    pub fn to_json(&self) -> JsonValue {
+        match self {
+            Content::Text(text) => text.clone().into(),
+            Content::Parts(parts) => {
+                JsonValue::Array(parts.iter().map(|part| part.to_json()).collect())
+            }
+        }
+    }
+}
+
+impl From<String> for Content {
+    fn from(text: String) -> Self {
+        Content::Text(text)
+    }
+}
+
+impl std::fmt::Display for Content {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.as_text())
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+/// The optional information about a message, used to inspect, trim and audit the history.
+pub struct ChatMetadata {
+    /// When the message was created.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub created_at: Option<DateTime<Utc>>,
+    /// The number of prompt tokens of the request that produced the message.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub prompt_tokens: Option<i64>,
+    /// The number of completion tokens of the message.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub completion_tokens: Option<i64>,
+    /// The name of the author, sent to the AI service to tell participants apart.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    /// The uid of the worknode that produced the message.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub node_uid: Option<Uuid>,
+    /// Whether the message is never trimmed or summarized, like the few-shot examples.
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub pinned: bool,
+    /// The tools that the assistant asks to call in the message.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub tool_calls: Vec<ToolCall>,
+    /// The id of the tool call that a tool message answers.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tool_call_id: Option<String>,
+}
+
+impl ChatMetadata {
+    /// Add the fields that the OpenAI format carries (name and tool calls) to a message.
+    pub fn add_to_json(&self, message: &mut JsonValue) {
+        if let Some(name) = &self.name {
+            message["name"] = name.clone().into();
+        }
+        if !self.tool_calls.is_empty() {
+            message["tool_calls"] =
+                JsonValue::Array(self.tool_calls.iter().map(ToolCall::to_json).collect());
+        }
+        if let Some(tool_call_id) = &self.tool_call_id {
+            message["tool_call_id"] = tool_call_id.clone().into();
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+/// The struct of one round of the chat.
+pub struct Chat {
+    pub(super) role: Role,
+    pub(super) content: Content,
+    #[serde(default)]
+    pub(super) metadata: ChatMetadata,
+}
+
+impl Chat {
+    /// Create a new Chat with text content.
+    pub fn new(role: Role, content: String) -> Chat {
+        Chat {
+            role,
+            content: Content::Text(content),
+            metadata: ChatMetadata {
+                created_at: Some(Utc::now()),
+                ..Default::default()
+            },
+        }
+    }
+    /// Create a new Chat with multimodal content.
+    pub fn with_parts(role: Role, parts: Vec<ContentPart>) -> Chat {
+        Chat {
+            role,
+            content: Content::Parts(parts),
+            metadata: ChatMetadata {
+                created_at: Some(Utc::now()),
+                ..Default::default()
+            },
+        }
+    }
+    /// Set the name of the author as builder.
+    pub fn name(mut self, name: Option<String>) -> Self {
+        self.metadata.name = name;
+        self
+    }
+    /// Set the uid of the worknode that produced the chat as builder.
+    pub fn node_uid(mut self, node_uid: Option<Uuid>) -> Self {
+        self.metadata.node_uid = node_uid;
+        self
+    }
+    /// Set whether the chat is pinned as builder.
+    pub fn pinned(mut self, pinned: bool) -> Self {
+        self.metadata.pinned = pinned;
+        self
+    }
+    /// Set the tool calls of an assistant message as builder.
+    pub fn tool_calls(mut self, tool_calls: Vec<ToolCall>) -> Self {
+        self.metadata.tool_calls = tool_calls;
+        self
+    }
+    /// Set the id of the tool call that a tool message answers as builder.
+    pub fn tool_call_id(mut self, tool_call_id: Option<String>) -> Self {
+        self.metadata.tool_call_id = tool_call_id;
+        self
+    }
+    /// Set the token counts as builder.
+    pub fn tokens(mut self, prompt_tokens: Option<i64>, completion_tokens: Option<i64>) -> Self {
+        self.metadata.prompt_tokens = prompt_tokens;
+        self.metadata.completion_tokens = completion_tokens;
+        self
+    }
+    /// Get the metadata of the chat.
+    pub fn get_metadata(&self) -> &ChatMetadata {
+        &self.metadata
+    }
+    /// Set the metadata of the chat.
+    pub fn set_metadata(&mut self, metadata: ChatMetadata) {
+        self.metadata = metadata;
+    }
+    /// Parse an OpenAI format message array, like the `messages` of a chat completion
+    /// request. An object with a `messages` field is accepted as well.
+    pub fn from_openai_json(text: &str) -> AINodeResult<Vec<Chat>> {
+        let value = json::parse(text).map_err(|e| {
+            AINodeError::new(
+                AINodeErrorType::InvalidInput,
+                format!("The messages are not valid json. {}", e),
+            )
+        })?;
+        let messages = if value["messages"].is_array() {
+            &value["messages"]
+        } else {
+            &value
+        };
+        if !messages.is_array() {
+            return Err(AINodeError::new(
+                AINodeErrorType::InvalidInput,
+                "The messages should be an array.".to_string(),
+            ));
+        }
+        messages.members().map(Self::from_openai_message).collect()
+    }
+    /// Convert the chats to an OpenAI format message array.
+    pub fn to_openai_json(chats: &[Chat]) -> String {
+        JsonValue::Array(
+            chats
+                .iter()
+                .map(|chat| {
+                    let mut message = object! {
+                        role: chat.role.to_string(),
+                        content: chat.content.to_json(),
+                    };
+                    chat.metadata.add_to_json(&mut message);
+                    message
+                })
+                .collect(),
+        )
+        .dump()
+    }
+    /// Parse one OpenAI format message.
+    fn from_openai_message(message: &JsonValue) -> AINodeResult<Chat> {
+        let role = match message["role"].as_str() {
+            // newer OpenAI models call the system message `developer`
+            Some("developer") => Role::System,
+            Some("function") => Role::Tool,
+            Some(role) => role.parse()?,
+            None => {
+                return Err(AINodeError::new(
+                    AINodeErrorType::InvalidInput,
+                    "The message doesn't have a role.".to_string(),
+                ))
+            }
+        };
+        let content = &message["content"];
+        let chat = if content.is_array() {
+            let parts = content
+                .members()
+                .map(Self::from_openai_part)
+                .collect::<AINodeResult<Vec<ContentPart>>>()?;
+            Chat::with_parts(role, parts)
+        } else if content.is_null() {
+            Chat::new(role, String::new())
+        } else {
+            Chat::new(role, content.to_string())
+        };
+        let tool_calls = message["tool_calls"]
+            .members()
+            .map(ToolCall::from_json)
+            .collect::<AINodeResult<Vec<ToolCall>>>()?;
+        Ok(chat
+            .name(message["name"].as_str().map(|name| name.to_string()))
+            .tool_calls(tool_calls)
+            .tool_call_id(message["tool_call_id"].as_str().map(|id| id.to_string())))
+    }
+    /// Parse one part of an OpenAI format multimodal message.
+    fn from_openai_part(part: &JsonValue) -> AINodeResult<ContentPart> {
+        match part["type"].as_str() {
+            Some("text") => Ok(ContentPart::Text {
+                text: part["text"].to_string(),
+            }),
+            Some("image_url") => {
+                let url = part["image_url"]["url"].to_string();
+                let detail = part["image_url"]["detail"].as_str().map(|d| d.to_string());
+                match url
+                    .strip_prefix("data:")
+                    .and_then(|data_url| data_url.split_once(";base64,"))
+                {
+                    Some((media_type, data)) => Ok(ContentPart::ImageBase64 {
+                        media_type: media_type.to_string(),
+                        data: data.to_string(),
+                    }),
+                    None => Ok(ContentPart::ImageUrl { url, detail }),
+                }
+            }
+            _ => Err(AINodeError::new(
+                AINodeErrorType::InvalidInput,
+                format!("Unsupported message part: {}", part.dump()),
+            )),
+        }
+    }
+    /// Get the role of the chat.
+    pub fn get_role(&self) -> Role {
+        self.role
+    }
+    /// Get the content of the chat.
+    pub fn get_content(&self) -> &Content {
+        &self.content
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// The enum of the file format of a saved history.
+pub enum HistoryFormat {
+    /// One json array of chats.
+    Json,
+    /// One chat per line.
+    Jsonl,
+}
+
+impl HistoryFormat {
+    /// Get the format from the extension of the path, `.jsonl` means json lines and
+    /// everything else means json.
+    pub fn from_path(path: &Path) -> HistoryFormat {
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("jsonl") => HistoryFormat::Jsonl,
+            _ => HistoryFormat::Json,
+        }
+    }
+}
+
+/// Save the chats to a file.
+pub fn save_chats(path: &Path, chats: &[Chat]) -> AINodeResult<()> {
+    let text = match HistoryFormat::from_path(path) {
+        HistoryFormat::Json => serde_json::to_string_pretty(chats).map_err(history_error)?,
+        HistoryFormat::Jsonl => {
+            let mut text = String::new();
+            for chat in chats {
+                text.push_str(&serde_json::to_string(chat).map_err(history_error)?);
+                text.push('\n');
+            }
+            text
+        }
+    };
+    std::fs::write(path, text).map_err(|e| {
+        AINodeError::new(
+            AINodeErrorType::HistoryError,
+            format!("Can't write the history file {}. {}", path.display(), e),
+        )
+    })
+}
+
+/// Load the chats from a file.
+pub fn load_chats(path: &Path) -> AINodeResult<Vec<Chat>> {
+    let text = std::fs::read_to_string(path).map_err(|e| {
+        AINodeError::new(
+            AINodeErrorType::HistoryError,
+            format!("Can't read the history file {}. {}", path.display(), e),
+        )
+    })?;
+    match HistoryFormat::from_path(path) {
+        HistoryFormat::Json => serde_json::from_str(&text).map_err(history_error),
+        HistoryFormat::Jsonl => text
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| serde_json::from_str(line).map_err(history_error))
+            .collect(),
+    }
+}
+
+fn history_error(e: serde_json::Error) -> AINodeError {
+    AINodeError::new(
+        AINodeErrorType::HistoryError,
+        format!("The history is not valid. {}", e),
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn role_round_trip() {
+        for role in [Role::System, Role::User, Role::Assistant, Role::Tool] {
+            assert_eq!(role.to_string().parse::<Role>().unwrap(), role);
+        }
+        assert!("assistent".parse::<Role>().is_err());
+    }
+
+    #[test]
+    fn multimodal_content_to_json() {
+        let chat = Chat::with_parts(
+            Role::User,
+            vec![
+                ContentPart::Text {
+                    text: "What is in the picture?".to_string(),
+                },
+                ContentPart::ImageBase64 {
+                    media_type: "image/png".to_string(),
+                    data: "aGVsbG8=".to_string(),
+                },
+            ],
+        );
+        assert!(chat.get_content().has_image());
+        assert_eq!(chat.get_content().as_text(), "What is in the picture?");
+        let content = chat.get_content().to_json();
+        assert_eq!(content[0]["type"], "text");
+        assert_eq!(
+            content[1]["image_url"]["url"],
+            "data:image/png;base64,aGVsbG8="
+        );
+        let text = Chat::new(Role::User, "Hi".to_string());
+        assert_eq!(text.get_content().to_json(), "Hi");
+    }
+
+    #[test]
+    fn chat_metadata() {
+        let uid = Uuid::new_v4();
+        let chat = Chat::new(Role::Assistant, "Hello".to_string())
+            .name(Some("cat".to_string()))
+            .node_uid(Some(uid))
+            .tokens(Some(10), Some(3));
+        let metadata = chat.get_metadata();
+        assert!(metadata.created_at.is_some());
+        assert_eq!(metadata.name.as_deref(), Some("cat"));
+        assert_eq!(metadata.node_uid, Some(uid));
+        assert_eq!(metadata.prompt_tokens, Some(10));
+        assert_eq!(metadata.completion_tokens, Some(3));
+    }
+
+    #[test]
+    fn chat_serde() {
+        let chat = Chat::with_parts(
+            Role::User,
+            vec![
+                ContentPart::Text {
+                    text: "Look".to_string(),
+                },
+                ContentPart::ImageUrl {
+                    url: "https://example.com/cat.png".to_string(),
+                    detail: None,
+                },
+            ],
+        )
+        .name(Some("alice".to_string()));
+        let text = serde_json::to_string(&chat).unwrap();
+        let parsed: Chat = serde_json::from_str(&text).unwrap();
+        assert_eq!(parsed.get_role(), Role::User);
+        assert_eq!(parsed.get_content(), chat.get_content());
+        assert_eq!(parsed.get_metadata().name.as_deref(), Some("alice"));
+
+        let parsed: Chat = serde_json::from_str(r#"{"role":"system","content":"Hi"}"#).unwrap();
+        assert_eq!(parsed.get_content(), &Content::Text("Hi".to_string()));
+        assert!(parsed.get_metadata().created_at.is_none());
+    }
+
+    #[test]
+    fn save_and_load_chats() {
+        let chats = vec![
+            Chat::new(Role::System, "You are a cat".to_string()),
+            Chat::new(Role::User, "Hi".to_string()),
+            Chat::new(Role::Assistant, "Meow".to_string()).tokens(Some(5), Some(1)),
+        ];
+        for extension in ["json", "jsonl"] {
+            let path = std::env::temp_dir().join(format!(
+                "aipilot-history-{}.{}",
+                Uuid::new_v4(),
+                extension
+            ));
+            save_chats(&path, &chats).unwrap();
+            let loaded = load_chats(&path).unwrap();
+            std::fs::remove_file(&path).unwrap();
+            assert_eq!(loaded.len(), 3);
+            assert_eq!(loaded[2].get_role(), Role::Assistant);
+            assert_eq!(loaded[2].get_content().as_text(), "Meow");
+            assert_eq!(loaded[2].get_metadata().completion_tokens, Some(1));
+        }
+    }
+
+    #[test]
+    fn openai_json_round_trip() {
+        let chats = Chat::from_openai_json(
+            r#"{"messages": [
+                {"role": "developer", "content": "You are a cat"},
+                {"role": "user", "name": "alice", "content": [
+                    {"type": "text", "text": "Look"},
+                    {"type": "image_url", "image_url": {"url": "data:image/png;base64,aGVsbG8="}}
+                ]},
+                {"role": "assistant", "content": "Meow"}
+            ]}"#,
+        )
+        .unwrap();
+        assert_eq!(chats.len(), 3);
+        assert_eq!(chats[0].get_role(), Role::System);
+        assert_eq!(chats[1].get_metadata().name.as_deref(), Some("alice"));
+        assert!(chats[1].get_content().has_image());
+
+        let text = Chat::to_openai_json(&chats);
+        let again = Chat::from_openai_json(&text).unwrap();
+        assert_eq!(again[1].get_content(), chats[1].get_content());
+        assert_eq!(again[2].get_content().as_text(), "Meow");
+        assert!(Chat::from_openai_json(r#"[{"role": "robot", "content": ""}]"#).is_err());
+    }
+}
@@ -1,15 +1,29 @@
 //! # DeepSeek AI Node
 //!
 //! This module containes the supporting functions to use the DeepSeek api service.
+//!
+//! A client may limit the time to connect, the time between two reads of the answer and the
+//! total time of a request. A request that hits one of them fails with a `TimeoutError` that
+//! tells which one, instead of a plain `RequestError`. The total time doesn't apply to the
+//! streams, which may take as long as the answer is.
 
-use super::Chat;
+use super::key::{ApiKey, KeySource};
+use super::params::{EffectiveParams, ParamLayer, SamplingParams};
+use super::recording::Recording;
+use super::secrets::SecretsProvider;
+use super::stats::ProviderStats;
+use super::{Chat, RequestOverrides, Role, ToolCall};
 use crate::error::ai_node_error::deepseek_error::{
-    DeepSeekError, DeepSeekErrorType, DeepSeekResult,
+    ApiErrorKind, DeepSeekError, DeepSeekErrorType, DeepSeekResult, ParamViolation,
 };
+use crate::error::timeout_error::{TimeoutError, TimeoutErrorType};
 
 use json::{object, JsonValue};
 
 use reqwest::Response;
+use serde::{Deserialize, Serialize};
+
+use std::time::{Duration, Instant};
 
 pub const DEEPSEEK_API_URL: &str = "https://api.deepseek.com/chat/completions";
 
@@ -17,7 +31,7 @@ pub const DEEPSEEK_API_URL: &str = "https://api.deepseek.com/chat/completions";
 pub enum ResponseFormat {
     /// The response format is text.
     Text,
-    /// The response format is json.
+    /// The response format is json, sent to the api as `json_object`.
     Json,
 }
 
@@ -27,13 +41,20 @@ pub struct StreamOption {
     include_usage: bool,
 }
 
+impl StreamOption {
+    /// Create a new StreamOption.
+    pub fn new(include_usage: bool) -> Self {
+        StreamOption { include_usage }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub enum DeepSeekModel {
     DeepseekChat,
     DeepseekReasoner,
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
 /// The struct of usage statistics.
 pub struct DeepSeekUsage {
     /// The number of tokens used in the response.
@@ -49,6 +70,68 @@ pub struct DeepSeekUsage {
 }
 
 impl DeepSeekUsage {
+    /// Get the number of tokens used in the response.
+    pub fn get_completion_tokens(&self) -> i64 {
+        self.completion_tokens
+    }
+    /// Get the number of tokens used in the request.
+    pub fn get_prompt_tokens(&self) -> i64 {
+        self.prompt_tokens
+    }
+    /// Get the number of tokens used in the request that hits the cache.
+    pub fn get_prompt_cache_hit_tokens(&self) -> i64 {
+        self.prompt_cache_hit_tokens
+    }
+    /// Get the number of tokens used in the request that misses the cache.
+    pub fn get_prompt_cache_miss_tokens(&self) -> i64 {
+        self.prompt_cache_miss_tokens
+    }
+    /// Get the total number of tokens used.
+    pub fn get_total_tokens(&self) -> i64 {
+        self.total_tokens
+    }
+    /// Parse the usage statistics from the `usage` field of a response.
+    pub fn from_json(usage: &JsonValue) -> DeepSeekResult<Self> {
+        if usage.is_null() {
+            return Err(DeepSeekError::new(
+                DeepSeekErrorType::ResponseError,
+                "The response does not contain usage statistics.".to_string(),
+            ));
+        } else if usage.is_empty() {
+            return Err(DeepSeekError::new(
+                DeepSeekErrorType::ResponseError,
+                "The usage statistics is empty.".to_string(),
+            ));
+        }
+        Ok(DeepSeekUsage {
+            completion_tokens: usage["completion_tokens"]
+                .as_i64()
+                .ok_or(DeepSeekError::new(
+                    DeepSeekErrorType::ResponseError,
+                    "The response does not contain completion tokens.".to_string(),
+                ))?,
+            prompt_tokens: usage["prompt_tokens"].as_i64().ok_or(DeepSeekError::new(
+                DeepSeekErrorType::ResponseError,
+                "The response does not contain prompt tokens.".to_string(),
+            ))?,
+            prompt_cache_hit_tokens: usage["prompt_cache_hit_tokens"].as_i64().ok_or(
+                DeepSeekError::new(
+                    DeepSeekErrorType::ResponseError,
+                    "The response does not contain prompt cache hit tokens.".to_string(),
+                ),
+            )?,
+            prompt_cache_miss_tokens: usage["prompt_cache_miss_tokens"].as_i64().ok_or(
+                DeepSeekError::new(
+                    DeepSeekErrorType::ResponseError,
+                    "The response does not contain prompt cache miss tokens.".to_string(),
+                ),
+            )?,
+            total_tokens: usage["total_tokens"].as_i64().ok_or(DeepSeekError::new(
+                DeepSeekErrorType::ResponseError,
+                "The response does not contain total tokens.".to_string(),
+            ))?,
+        })
+    }
     /// Create a new DeepSeekUsage.
     pub fn new() -> Self {
         DeepSeekUsage {
@@ -76,13 +159,49 @@ impl std::ops::Add for DeepSeekUsage {
     }
 }
 
+impl std::ops::Sub for DeepSeekUsage {
+    type Output = Self;
+
+    fn sub(self, other: Self) -> Self {
+        DeepSeekUsage {
+            completion_tokens: self.completion_tokens - other.completion_tokens,
+            prompt_tokens: self.prompt_tokens - other.prompt_tokens,
+            prompt_cache_hit_tokens: self.prompt_cache_hit_tokens - other.prompt_cache_hit_tokens,
+            prompt_cache_miss_tokens: self.prompt_cache_miss_tokens
+                - other.prompt_cache_miss_tokens,
+            total_tokens: self.total_tokens - other.total_tokens,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+/// The struct of the numbers of requests that a client sent. The replayed requests are not
+/// sent, so they are not counted.
+pub struct RequestCount {
+    /// The requests that got a response.
+    pub succeeded: u64,
+    /// The requests that failed before they got a response.
+    pub failed: u64,
+}
+
+impl std::ops::Sub for RequestCount {
+    type Output = Self;
+
+    fn sub(self, other: Self) -> Self {
+        RequestCount {
+            succeeded: self.succeeded - other.succeeded,
+            failed: self.failed - other.failed,
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 /// The struct of the DeepSeek client.
 pub struct DeepSeekClient {
     /// The url of the DeepSeek API.
     url: String,
     /// The api key of the DeepSeek API.
-    api_key: Option<String>,
+    api_key: Option<ApiKey>,
     /// The model of DeepSeek.
     model: DeepSeekModel,
     /// The panalty of frequency, if this value is larger than 0, deepseek will get panalty
@@ -112,8 +231,14 @@ pub struct DeepSeekClient {
     /// Don't use this with temperature.
     /// The value should be between 0 and 1, default is 1.
     top_p: Option<f64>,
-    // tools： not supported yet
-    // tool choice: not supported yet
+    /// The tools that the model may call, in the OpenAI format. The model chooses whether
+    /// to call them.
+    tools: Option<JsonValue>,
+    /// Up to 16 sequences where the API will stop generating further tokens.
+    stop: Option<Vec<String>>,
+    /// The sampling parameters used when the client doesn't set them, like the ones shared by
+    /// all the providers of a config.
+    defaults: SamplingParams,
     /// Whether use logprobs in the response, default is false.
     logprobs: bool,
     /// Return the top n tokens in every position. Can only be used when logprobs is true.
@@ -122,6 +247,18 @@ pub struct DeepSeekClient {
     total_usage: DeepSeekUsage,
     /// The last usage statistics of the client.
     last_usage: DeepSeekUsage,
+    /// The numbers of requests sent by the client.
+    requests: RequestCount,
+    /// The recording that the requests are recorded to or replayed from.
+    recording: Option<Recording>,
+    /// The stats that the latency of the requests is recorded to.
+    stats: Option<ProviderStats>,
+    /// The max time to connect to the API.
+    connect_timeout: Option<Duration>,
+    /// The max time between two reads of the answer.
+    read_timeout: Option<Duration>,
+    /// The max total time of a request that is not streamed.
+    timeout: Option<Duration>,
 }
 
 impl DeepSeekClient {
@@ -138,10 +275,19 @@ impl DeepSeekClient {
             stream_option: None,
             temperature: None,
             top_p: None,
+            tools: None,
+            stop: None,
+            defaults: SamplingParams::new(),
             logprobs: false,
             top_logprobs: None,
             total_usage: DeepSeekUsage::new(),
             last_usage: DeepSeekUsage::new(),
+            requests: RequestCount::default(),
+            recording: None,
+            stats: None,
+            connect_timeout: None,
+            read_timeout: None,
+            timeout: None,
         }
     }
     /// Get a request string from the client and history chats, and send the request
@@ -149,148 +295,345 @@ impl DeepSeekClient {
     /// The request string is in json format.
     /// This function garantees that the request consist the response message.
     pub async fn send_request(&mut self, chats: &Vec<Chat>) -> DeepSeekResult<JsonValue> {
-        if !self.check_params() {
-            return Err(DeepSeekError::new(
-                DeepSeekErrorType::RequestParamError,
-                "The parameters are not valid.".to_string(),
-            ));
-        }
-        let request = self.into_request_string(Self::chats_to_json(chats));
-        // api key is already checked in check_params, so unwrap is safe here
-        let api_key = self.api_key.clone().unwrap();
-        let response = Self::send_request_raw(request, api_key).await?;
-        let response_text = json::parse(
-            response
-                .text()
-                .await
-                .map_err(|e| {
-                    DeepSeekError::new(
-                        DeepSeekErrorType::RequestError,
-                        format!("Failed to read response text. {}", e),
-                    )
-                })?
-                .as_str(),
+        self.send_request_with(chats, &RequestOverrides::default())
+            .await
+    }
+    /// Same as `send_request`, but the parameters in `overrides` replace the ones of the
+    /// client for this request only. The request is traced as a `deepseek.request` span with
+    /// the model and the token counts of the answer.
+    #[tracing::instrument(
+        name = "deepseek.request",
+        skip_all,
+        fields(
+            model = %self.model,
+            replay = self.recording.as_ref().is_some_and(|r| r.is_replay()),
+            prompt_tokens = tracing::field::Empty,
+            completion_tokens = tracing::field::Empty,
+            total_tokens = tracing::field::Empty,
         )
-        .map_err(|e| {
+    )]
+    pub async fn send_request_with(
+        &mut self,
+        chats: &Vec<Chat>,
+        overrides: &RequestOverrides,
+    ) -> DeepSeekResult<JsonValue> {
+        let effective = self.with_overrides(overrides);
+        effective.validate().map_err(param_error)?;
+        // a replayed request is never sent, so it doesn't need the api key
+        let replay = self.recording.as_ref().filter(|r| r.is_replay());
+        let request = effective.to_request_string(Self::chats_to_json(chats)?);
+        let text = match replay {
+            Some(recording) => recording.answer(&request)?,
+            None => {
+                let api_key = self.require_api_key()?;
+                let started = Instant::now();
+                let text = match self
+                    .send_request_raw(request.clone(), api_key, self.timeout, started)
+                    .await
+                {
+                    Ok(response) => response.text().await.map_err(|e| {
+                        self.request_error(
+                            e,
+                            started,
+                            self.timeout,
+                            "Failed to read response text.",
+                        )
+                    }),
+                    Err(e) => Err(e),
+                };
+                self.record_request(&effective.model, started, text.is_ok());
+                let text = text?;
+                if let Some(recording) = &self.recording {
+                    recording.push(&request, &text);
+                }
+                text
+            }
+        };
+        let response_text = json::parse(&text).map_err(|e| {
             DeepSeekError::new(
                 DeepSeekErrorType::RequestError,
                 format!("Failed to parse response text. {}", e),
             )
         })?;
-        // check response
-        if response_text["choices"][0]["message"]["content"].is_null() {
+        // check response, the content may be empty when the model calls tools
+        let message = &response_text["choices"][0]["message"];
+        let has_tool_calls = !message["tool_calls"].is_empty();
+        if !has_tool_calls && message["content"].is_null() {
             return Err(DeepSeekError::new(
                 DeepSeekErrorType::ResponseError,
                 "The response format is not valid.".to_string(),
             ));
-        } else if response_text["choices"][0]["message"]["content"].is_empty() {
+        } else if !has_tool_calls && message["content"].is_empty() {
             return Err(DeepSeekError::new(
                 DeepSeekErrorType::ResponseError,
                 "The response is empty.".to_string(),
             ));
         }
         // dump the usage statistics
-        let usage = response_text["usage"].clone();
-        if usage.is_null() {
-            return Err(DeepSeekError::new(
-                DeepSeekErrorType::ResponseError,
-                "The response does not contain usage statistics.".to_string(),
-            ));
-        } else if usage.is_empty() {
+        let usage = DeepSeekUsage::from_json(&response_text["usage"])?;
+        let span = tracing::Span::current();
+        span.record("prompt_tokens", usage.get_prompt_tokens());
+        span.record("completion_tokens", usage.get_completion_tokens());
+        span.record("total_tokens", usage.get_total_tokens());
+        self.record_usage(usage);
+        Ok(response_text)
+    }
+    /// Send the request in stream mode, and get a stream of the chunks of the response.
+    /// The usage statistics are requested in the last chunk, and should be recorded by
+    /// `record_usage` when they arrive.
+    pub async fn send_request_stream(
+        &mut self,
+        chats: &Vec<Chat>,
+        overrides: &RequestOverrides,
+    ) -> DeepSeekResult<DeepSeekStream> {
+        if self.recording.as_ref().is_some_and(Recording::is_replay) {
             return Err(DeepSeekError::new(
-                DeepSeekErrorType::ResponseError,
-                "The usage statistics is empty.".to_string(),
+                DeepSeekErrorType::ReplayError,
+                "A stream request can't be replayed.".to_string(),
             ));
         }
-        self.last_usage = DeepSeekUsage {
-            completion_tokens: usage["completion_tokens"]
-                .as_i64()
-                .ok_or(DeepSeekError::new(
-                    DeepSeekErrorType::ResponseError,
-                    "The response does not contain completion tokens.".to_string(),
-                ))?,
-            prompt_tokens: usage["prompt_tokens"].as_i64().ok_or(DeepSeekError::new(
-                DeepSeekErrorType::ResponseError,
-                "The response does not contain prompt tokens.".to_string(),
-            ))?,
-            prompt_cache_hit_tokens: usage["prompt_cache_hit_tokens"].as_i64().ok_or(
-                DeepSeekError::new(
-                    DeepSeekErrorType::ResponseError,
-                    "The response does not contain prompt cache hit tokens.".to_string(),
-                ),
-            )?,
-            prompt_cache_miss_tokens: usage["prompt_cache_miss_tokens"].as_i64().ok_or(
-                DeepSeekError::new(
-                    DeepSeekErrorType::ResponseError,
-                    "The response does not contain prompt cache miss tokens.".to_string(),
-                ),
-            )?,
-            total_tokens: usage["total_tokens"].as_i64().ok_or(DeepSeekError::new(
-                DeepSeekErrorType::ResponseError,
-                "The response does not contain total tokens.".to_string(),
-            ))?,
-        };
-        self.total_usage = self.total_usage + self.last_usage;
-        Ok(response_text)
+        let mut effective = self.with_overrides(overrides);
+        effective.stream = Some(true);
+        effective.stream_option = Some(StreamOption::new(true));
+        effective.validate().map_err(param_error)?;
+        let request = effective.to_request_string(Self::chats_to_json(chats)?);
+        let api_key = self.require_api_key()?;
+        // the latency of a stream is the time to its first chunk
+        let started = Instant::now();
+        let response = self.send_request_raw(request, api_key, None, started).await;
+        self.record_request(&effective.model, started, response.is_ok());
+        Ok(DeepSeekStream::new(response?))
+    }
+    /// Count a request sent since the moment, and record its latency to the stats, if there
+    /// are some.
+    fn record_request(&mut self, model: &DeepSeekModel, started: Instant, succeeded: bool) {
+        match succeeded {
+            true => self.requests.succeeded += 1,
+            false => self.requests.failed += 1,
+        }
+        if let Some(stats) = &self.stats {
+            stats.record("deepseek", &model.to_string(), started.elapsed(), succeeded);
+        }
     }
-    /// Send the request to the DeepSeek API. This function is asynchronous.
-    async fn send_request_raw(request: String, api_key: String) -> DeepSeekResult<Response> {
-        let client = reqwest::Client::new();
-        let response = client
-            .post(DEEPSEEK_API_URL)
+    /// Record the usage statistics of a request.
+    pub fn record_usage(&mut self, usage: DeepSeekUsage) {
+        self.last_usage = usage;
+        self.total_usage = self.total_usage + usage;
+    }
+    /// Send the request to the DeepSeek API, which was started at the moment, with the total
+    /// timeout. This function is asynchronous.
+    async fn send_request_raw(
+        &self,
+        request: String,
+        api_key: ApiKey,
+        total: Option<Duration>,
+        started: Instant,
+    ) -> DeepSeekResult<Response> {
+        let url = self.url.as_str();
+        let response = self
+            .http_client(total)?
+            .post(url)
             .header("Content-Type", "application/json")
-            .header("Authorization", format!("Bearer {}", api_key))
+            .header("Authorization", format!("Bearer {}", api_key.expose()))
             .body(request)
             .send()
             .await
-            .map_err(|_| {
-                DeepSeekError::new(
-                    DeepSeekErrorType::RequestError,
-                    "Failed to send request.".to_string(),
-                )
+            .map_err(|e| {
+                self.request_error(e, started, total, "Failed to send request.")
+                    .endpoint(Some(url.to_string()))
+            })?;
+        Self::check_response(response, url).await
+    }
+    /// Check that the api is reachable and accepts the api key, by listing the models. With a
+    /// url that is not the one of the chat completions, a request of one token is sent
+    /// instead. A client that replays a recording sends nothing.
+    pub async fn healthcheck(&self) -> DeepSeekResult<()> {
+        if self.recording.as_ref().is_some_and(Recording::is_replay) {
+            return Ok(());
+        }
+        let api_key = self.require_api_key()?;
+        let started = Instant::now();
+        let Some(base) = self.url.strip_suffix("/chat/completions") else {
+            let client = self.clone().max_tokens(Some(1));
+            let request = client.to_request_string(Self::chats_to_json(&vec![Chat::new(
+                Role::User,
+                "ping".to_string(),
+            )])?);
+            return self
+                .send_request_raw(request, api_key, self.timeout, started)
+                .await
+                .map(|_| ());
+        };
+        let url = format!("{}/models", base);
+        let response = self
+            .http_client(self.timeout)?
+            .get(&url)
+            .header("Authorization", format!("Bearer {}", api_key.expose()))
+            .send()
+            .await
+            .map_err(|e| {
+                self.request_error(e, started, self.timeout, "Failed to list the models.")
+                    .endpoint(Some(url.clone()))
             })?;
+        Self::check_response(response, &url).await.map(|_| ())
+    }
+    /// Build the http client with the timeouts of the client, and the total timeout.
+    fn http_client(&self, total: Option<Duration>) -> DeepSeekResult<reqwest::Client> {
+        let mut client = reqwest::Client::builder();
+        if let Some(timeout) = self.connect_timeout {
+            client = client.connect_timeout(timeout);
+        }
+        if let Some(timeout) = self.read_timeout {
+            client = client.read_timeout(timeout);
+        }
+        if let Some(timeout) = total {
+            client = client.timeout(timeout);
+        }
+        client.build().map_err(|e| {
+            DeepSeekError::new(
+                DeepSeekErrorType::RequestError,
+                format!("Failed to build the http client. {}", e),
+            )
+        })
+    }
+    /// Get the response of the url if it succeeded, or the error that the api answered.
+    async fn check_response(response: Response, url: &str) -> DeepSeekResult<Response> {
         if response.status().is_success() {
-            Ok(response)
+            return Ok(response);
+        }
+        let status = response.status();
+        let request_id = response
+            .headers()
+            .get("x-request-id")
+            .and_then(|id| id.to_str().ok())
+            .map(|id| id.to_string());
+        let body = response.text().await.ok();
+        let error = body
+            .as_deref()
+            .and_then(|body| json::parse(body).ok())
+            .map(|body| body["error"].clone())
+            .unwrap_or(JsonValue::Null);
+        let message = error["message"]
+            .as_str()
+            .map(str::to_string)
+            .unwrap_or_else(|| format!("Request failed with status {}.", status));
+        let api_error = ApiErrorKind::of(
+            status.as_u16(),
+            error["type"].as_str().unwrap_or_default(),
+            error["code"].as_str().unwrap_or_default(),
+            &message,
+        );
+        Err(DeepSeekError::new(DeepSeekErrorType::RequestError, message)
+            .api_error(Some(api_error))
+            .status(Some(status.as_u16()))
+            .request_id(request_id)
+            .body(body)
+            .endpoint(Some(url.to_string())))
+    }
+    /// Get the api key to send a request with, or an error if it is not set.
+    fn require_api_key(&self) -> DeepSeekResult<ApiKey> {
+        self.api_key.clone().ok_or_else(|| {
+            DeepSeekError::new(
+                DeepSeekErrorType::ApiKeyError,
+                "The api key is not set.".to_string(),
+            )
+        })
+    }
+    /// Get the error of a request started at the moment that failed, which is a timeout error
+    /// when it hit one of the limits of the client.
+    fn request_error(
+        &self,
+        e: reqwest::Error,
+        started: Instant,
+        total: Option<Duration>,
+        message: &str,
+    ) -> DeepSeekError {
+        let message = format!("{} {}", message, e);
+        if !e.is_timeout() {
+            return DeepSeekError::new(DeepSeekErrorType::RequestError, message);
+        }
+        let elapsed = started.elapsed();
+        let (error_type, limit) = if e.is_connect() {
+            (TimeoutErrorType::Connect, self.connect_timeout)
+        } else if total.is_some_and(|total| elapsed >= total) {
+            (TimeoutErrorType::Total, total)
         } else {
-            Err(DeepSeekError::new(
-                DeepSeekErrorType::RequestError,
-                format!(
-                    "Request failed with status: {}, {}",
-                    response.status(),
-                    response
-                        .text()
-                        .await
-                        .map(|r| json::parse(&r)
-                            .map(|r| r["error"]["message"].to_string())
-                            .unwrap_or("Failed to parse error message".to_string(),))
-                        .unwrap_or("Failed to read error message".to_string())
-                ),
-            ))
+            (TimeoutErrorType::Read, self.read_timeout)
+        };
+        let timeout = TimeoutError::new(error_type, e.to_string(), elapsed).limit(limit);
+        DeepSeekError::new(DeepSeekErrorType::TimeoutError(Box::new(timeout)), message)
+    }
+    /// Get a copy of the client with the parameters in `overrides` applied. The sampling
+    /// parameters are resolved from all the layers, and logged.
+    fn with_overrides(&self, overrides: &RequestOverrides) -> DeepSeekClient {
+        let mut client = self.clone();
+        let params = self.effective_params(overrides);
+        tracing::debug!(model = %self.model, "Sampling parameters: {}.", params);
+        client.temperature = Some(params.temperature.value);
+        client.top_p = Some(params.top_p.value);
+        client.max_tokens = Some(params.max_tokens.value);
+        client.frequency_panalty = Some(params.frequency_penalty.value);
+        client.presence_penalty = Some(params.presence_penalty.value);
+        client.stop = params.stop.value;
+        if let Some(response_format) = overrides.get_response_format() {
+            client.response_format = Some(response_format.clone());
         }
+        if let Some(tools) = overrides.get_tools() {
+            client.tools = Some(tools.clone());
+        }
+        client
+    }
+    /// Get the sampling parameters that a request with the overrides is sent with, and the
+    /// layer that each one is taken from.
+    pub fn effective_params(&self, overrides: &RequestOverrides) -> EffectiveParams {
+        SamplingParams::resolve(&[
+            (ParamLayer::Request, &overrides.get_params()),
+            (ParamLayer::Node, overrides.get_node_params()),
+            (ParamLayer::Provider, &self.get_params()),
+            (ParamLayer::Global, &self.defaults),
+        ])
     }
     /// Convert the chats to json format.
-    fn chats_to_json(chats: &Vec<Chat>) -> JsonValue {
+    /// DeepSeek does not support vision, so the text parts of a multimodal message are joined
+    /// and a message containing images is rejected.
+    fn chats_to_json(chats: &Vec<Chat>) -> DeepSeekResult<JsonValue> {
         let mut json_chats = Vec::new();
         for chat in chats {
-            json_chats.push(object! {
-                content: chat.content.clone(),
-                role: chat.role.clone(),
-            });
+            if chat.content.has_image() {
+                return Err(DeepSeekError::new(
+                    DeepSeekErrorType::RequestParamError(Box::new(vec![ParamViolation::new(
+                        "messages",
+                        "a message with an image".to_string(),
+                        "text only",
+                    )])),
+                    "DeepSeek does not support image input.".to_string(),
+                ));
+            }
+            let mut json_chat = object! {
+                content: chat.content.as_text(),
+                role: chat.role.to_string(),
+            };
+            chat.metadata.add_to_json(&mut json_chat);
+            json_chats.push(json_chat);
         }
-        json::JsonValue::Array(json_chats)
+        Ok(json::JsonValue::Array(json_chats))
     }
     /// Convert the client and the chats to json format.
-    fn into_request_string(&self, msg: JsonValue) -> String {
+    fn to_request_string(&self, msg: JsonValue) -> String {
+        let params = self.effective_params(&RequestOverrides::default());
         object! {
             messages: msg,
             model: self.model.to_string(),
-            frequency_panalty: self.frequency_panalty.unwrap_or(Self::default_frequency_panalty()),
-            max_tokens: self.max_tokens.unwrap_or(Self::default_max_tokens()),
-            presence_penalty: self.presence_penalty.unwrap_or(Self::default_presence_penalty()),
+            frequency_panalty: params.frequency_penalty.value,
+            max_tokens: params.max_tokens.value,
+            presence_penalty: params.presence_penalty.value,
             response_format: object! {
                 "type": self.response_format.clone().unwrap_or(Self::default_response_format()).to_string(),
             },
-            stop: json::JsonValue::Null,
+            stop: match params.stop.value {
+                Some(stop) => stop.into(),
+                None => json::JsonValue::Null,
+            },
             stream: self.stream.unwrap_or(Self::default_stream()),
             stream_options: if let Some(stream_option) = self.stream_option.clone() {
                 object! {
@@ -299,10 +642,10 @@ impl DeepSeekClient {
             } else {
                 json::JsonValue::Null
             },
-            temperature: self.temperature.unwrap_or(Self::default_temperature()),
-            top_p: self.top_p.unwrap_or(Self::default_top_p()),
-            tools: json::JsonValue::Null,
-            tool_choice: "none",
+            temperature: params.temperature.value,
+            top_p: params.top_p.value,
+            tools: self.tools.clone().unwrap_or(json::JsonValue::Null),
+            tool_choice: if self.tools.is_some() { "auto" } else { "none" },
             logprobs: self.logprobs,
             top_logprobs: self.top_logprobs,
         }.dump()
@@ -314,17 +657,146 @@ impl DeepSeekClient {
     /// - stream_option
     /// - temperature
     /// - top_p
+    /// - stop
     /// - top_logprobs
     /// - api_key
+    ///
+    /// See `validate` for what is wrong with them.
     pub fn check_params(&self) -> bool {
-        self.check_frequency_panalty()
-            && self.check_max_tokens()
-            && self.check_presence_penalty()
-            && self.check_stream_option()
-            && self.check_temperature()
-            && self.check_top_p()
-            && self.check_top_logprobs()
-            && self.api_key.is_some()
+        self.validate().is_ok() && self.api_key.is_some()
+    }
+    /// Validate the parameters of the request, which are all of `check_params` except the
+    /// api key, and get every parameter that is not valid, with its value and the values
+    /// that are allowed.
+    pub fn validate(&self) -> Result<(), Vec<ParamViolation>> {
+        fn value<T: ToString>(value: Option<T>) -> String {
+            value.map(|value| value.to_string()).unwrap_or_default()
+        }
+        let checks = [
+            (
+                self.check_frequency_panalty(),
+                "frequency_penalty",
+                value(self.frequency_panalty),
+                "between -2 and 2",
+            ),
+            (
+                self.check_max_tokens(),
+                "max_tokens",
+                value(self.max_tokens),
+                "between 1 and 8192",
+            ),
+            (
+                self.check_presence_penalty(),
+                "presence_penalty",
+                value(self.presence_penalty),
+                "between -2 and 2",
+            ),
+            (
+                self.check_stream_option(),
+                "stream_options",
+                "set".to_string(),
+                "only set with stream",
+            ),
+            (
+                self.check_temperature(),
+                "temperature",
+                value(self.temperature),
+                "between 0 and 2",
+            ),
+            (
+                self.check_top_p(),
+                "top_p",
+                value(self.top_p),
+                "between 0 and 1",
+            ),
+            (
+                self.check_stop(),
+                "stop",
+                format!("{} sequences", self.stop.as_ref().map_or(0, Vec::len)),
+                "at most 16 sequences",
+            ),
+            (
+                self.check_top_logprobs(),
+                "top_logprobs",
+                value(self.top_logprobs),
+                "only set with logprobs",
+            ),
+        ];
+        let violations: Vec<ParamViolation> = checks
+            .into_iter()
+            .filter(|(valid, ..)| !valid)
+            .map(|(_, field, value, allowed)| ParamViolation::new(field, value, allowed))
+            .collect();
+        if violations.is_empty() {
+            Ok(())
+        } else {
+            Err(violations)
+        }
+    }
+    /// Set the recording that the requests are recorded to or replayed from as builder.
+    pub fn recording(mut self, recording: Option<Recording>) -> Self {
+        self.recording = recording;
+        self
+    }
+    /// Get the recording that the requests are recorded to or replayed from.
+    pub fn get_recording(&self) -> Option<&Recording> {
+        self.recording.as_ref()
+    }
+    /// Set the recording that the requests are recorded to or replayed from.
+    pub fn set_recording(&mut self, recording: Option<Recording>) {
+        self.recording = recording;
+    }
+    /// Set the stats that the latency of the requests is recorded to as builder.
+    pub fn stats(mut self, stats: Option<ProviderStats>) -> Self {
+        self.stats = stats;
+        self
+    }
+    /// Get the stats that the latency of the requests is recorded to.
+    pub fn get_stats(&self) -> Option<&ProviderStats> {
+        self.stats.as_ref()
+    }
+    /// Set the stats that the latency of the requests is recorded to.
+    pub fn set_stats(&mut self, stats: Option<ProviderStats>) {
+        self.stats = stats;
+    }
+    /// Set the max time to connect to the API as builder.
+    pub fn connect_timeout(mut self, connect_timeout: Option<Duration>) -> Self {
+        self.connect_timeout = connect_timeout;
+        self
+    }
+    /// Set the max time to connect to the API.
+    pub fn set_connect_timeout(&mut self, connect_timeout: Option<Duration>) {
+        self.connect_timeout = connect_timeout;
+    }
+    /// Get the max time to connect to the API.
+    pub fn get_connect_timeout(&self) -> Option<Duration> {
+        self.connect_timeout
+    }
+    /// Set the max time between two reads of the answer as builder.
+    pub fn read_timeout(mut self, read_timeout: Option<Duration>) -> Self {
+        self.read_timeout = read_timeout;
+        self
+    }
+    /// Set the max time between two reads of the answer.
+    pub fn set_read_timeout(&mut self, read_timeout: Option<Duration>) {
+        self.read_timeout = read_timeout;
+    }
+    /// Get the max time between two reads of the answer.
+    pub fn get_read_timeout(&self) -> Option<Duration> {
+        self.read_timeout
+    }
+    /// Set the max total time of a request that is not streamed as builder.
+    pub fn timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.timeout = timeout;
+        self
+    }
+    /// Set the max total time of a request that is not streamed.
+    pub fn set_timeout(&mut self, timeout: Option<Duration>) {
+        self.timeout = timeout;
+    }
+    /// Get the max total time of a request that is not streamed.
+    pub fn get_timeout(&self) -> Option<Duration> {
+        self.timeout
     }
     pub fn get_url(&self) -> &str {
         &self.url
@@ -332,33 +804,44 @@ impl DeepSeekClient {
     pub fn set_url(&mut self, url: String) {
         self.url = url;
     }
-    pub fn api_key_from_env(mut self) -> DeepSeekResult<Self> {
-        self.api_key = Some(std::env::var("API_KEY").map_err(|_| {
-            DeepSeekError::new(
-                DeepSeekErrorType::ApiKeyError,
-                "Environment variable API_KEY not found.".to_string(),
-            )
-        })?);
+    /// Set the api key from the environment variable of the name.
+    pub fn api_key_from_env(self, name: &str) -> DeepSeekResult<Self> {
+        self.api_key_from(&KeySource::env(name))
+    }
+    /// Set the api key from the file, without the whitespaces around it.
+    pub fn api_key_from_file(self, file: &str) -> DeepSeekResult<Self> {
+        self.api_key_from(&KeySource::file(file))
+    }
+    /// Set the api key from the entry of the keyring of the OS, see
+    /// [`super::key::store_in_keyring`].
+    pub fn api_key_from_keyring(self, service: &str, user: &str) -> DeepSeekResult<Self> {
+        self.api_key_from(&KeySource::keyring(service, user))
+    }
+    /// Set the api key from the secret of the secrets provider.
+    pub fn api_key_from_secrets(
+        mut self,
+        secrets: &dyn SecretsProvider,
+        name: &str,
+    ) -> DeepSeekResult<Self> {
+        let api_key = secrets
+            .get_secret(name)
+            .map_err(|e| DeepSeekError::new(DeepSeekErrorType::ApiKeyError, e))?;
+        self.api_key = Some(api_key.into());
         Ok(self)
     }
-    pub fn api_key_from_file(mut self, file: &str) -> DeepSeekResult<Self> {
-        let api_key = std::fs::read_to_string(file);
-        match api_key {
-            Ok(api_key) => {
-                self.api_key = Some(api_key);
-                Ok(self)
-            }
-            Err(_) => Err(DeepSeekError::new(
-                DeepSeekErrorType::ApiKeyError,
-                "Can't read the api key file.".to_string(),
-            )),
-        }
+    /// Set the api key from the source.
+    pub fn api_key_from(mut self, source: &KeySource) -> DeepSeekResult<Self> {
+        let api_key = source
+            .resolve()
+            .map_err(|e| DeepSeekError::new(DeepSeekErrorType::ApiKeyError, e))?;
+        self.api_key = Some(api_key);
+        Ok(self)
     }
     pub fn get_api_key(&self) -> Option<&str> {
-        self.api_key.as_deref()
+        self.api_key.as_ref().map(ApiKey::expose)
     }
-    pub fn set_api_key(&mut self, api_key: Option<String>) {
-        self.api_key = api_key;
+    pub fn set_api_key<K: Into<ApiKey>>(&mut self, api_key: Option<K>) {
+        self.api_key = api_key.map(Into::into);
     }
     pub fn get_model(&self) -> &DeepSeekModel {
         &self.model
@@ -376,12 +859,9 @@ impl DeepSeekClient {
     pub fn set_frequency_panalty(&mut self, frequency_panalty: Option<f64>) {
         self.frequency_panalty = frequency_panalty;
     }
-    pub fn default_frequency_panalty() -> f64 {
-        0.0
-    }
     pub fn check_frequency_panalty(&self) -> bool {
         if let Some(frequency_panalty) = self.frequency_panalty {
-            if frequency_panalty < -2.0 || frequency_panalty > 2.0 {
+            if !(-2.0..=2.0).contains(&frequency_panalty) {
                 return false;
             }
         }
@@ -397,12 +877,9 @@ impl DeepSeekClient {
     pub fn set_max_tokens(&mut self, max_tokens: Option<i32>) {
         self.max_tokens = max_tokens;
     }
-    pub fn default_max_tokens() -> i32 {
-        4096
-    }
     pub fn check_max_tokens(&self) -> bool {
         if let Some(max_tokens) = self.max_tokens {
-            if max_tokens < 1 || max_tokens > 8192 {
+            if !(1..=8192).contains(&max_tokens) {
                 return false;
             }
         }
@@ -418,12 +895,9 @@ impl DeepSeekClient {
     pub fn set_presence_penalty(&mut self, presence_penalty: Option<f64>) {
         self.presence_penalty = presence_penalty;
     }
-    pub fn default_presence_penalty() -> f64 {
-        0.0
-    }
     pub fn check_presence_penalty(&self) -> bool {
         if let Some(presence_penalty) = self.presence_penalty {
-            if presence_penalty < -2.0 || presence_penalty > 2.0 {
+            if !(-2.0..=2.0).contains(&presence_penalty) {
                 return false;
             }
         }
@@ -482,12 +956,9 @@ impl DeepSeekClient {
     pub fn set_temperature(&mut self, temperature: Option<f64>) {
         self.temperature = temperature;
     }
-    pub fn default_temperature() -> f64 {
-        1.0
-    }
     pub fn check_temperature(&self) -> bool {
         if let Some(temperature) = self.temperature {
-            if temperature < 0.0 || temperature > 2.0 {
+            if !(0.0..=2.0).contains(&temperature) {
                 return false;
             }
         }
@@ -503,17 +974,75 @@ impl DeepSeekClient {
     pub fn set_top_p(&mut self, top_p: Option<f64>) {
         self.top_p = top_p;
     }
-    pub fn default_top_p() -> f64 {
-        1.0
-    }
     pub fn check_top_p(&self) -> bool {
         if let Some(top_p) = self.top_p {
-            if top_p < 0.0 || top_p > 1.0 {
+            if !(0.0..=1.0).contains(&top_p) {
+                return false;
+            }
+        }
+        true
+    }
+    pub fn tools(mut self, tools: Option<JsonValue>) -> Self {
+        self.tools = tools;
+        self
+    }
+    pub fn get_tools(&self) -> Option<&JsonValue> {
+        self.tools.as_ref()
+    }
+    pub fn set_tools(&mut self, tools: Option<JsonValue>) {
+        self.tools = tools;
+    }
+    pub fn stop(mut self, stop: Option<Vec<String>>) -> Self {
+        self.stop = stop;
+        self
+    }
+    pub fn get_stop(&self) -> Option<&Vec<String>> {
+        self.stop.as_ref()
+    }
+    pub fn set_stop(&mut self, stop: Option<Vec<String>>) {
+        self.stop = stop;
+    }
+    pub fn check_stop(&self) -> bool {
+        if let Some(stop) = &self.stop {
+            if stop.len() > 16 {
                 return false;
             }
         }
         true
     }
+    /// Set the sampling parameters of the client as builder. A parameter that is `None`
+    /// keeps the value of the client.
+    pub fn sampling_params(mut self, params: &SamplingParams) -> Self {
+        self.temperature = params.temperature.or(self.temperature);
+        self.top_p = params.top_p.or(self.top_p);
+        self.max_tokens = params.max_tokens.or(self.max_tokens);
+        self.frequency_panalty = params.frequency_penalty.or(self.frequency_panalty);
+        self.presence_penalty = params.presence_penalty.or(self.presence_penalty);
+        self.stop = params.stop.clone().or(self.stop);
+        self
+    }
+    /// Get the sampling parameters of the client.
+    pub fn get_params(&self) -> SamplingParams {
+        SamplingParams {
+            temperature: self.temperature,
+            top_p: self.top_p,
+            max_tokens: self.max_tokens,
+            frequency_penalty: self.frequency_panalty,
+            presence_penalty: self.presence_penalty,
+            stop: self.stop.clone(),
+        }
+    }
+    /// Set the sampling parameters used when the client doesn't set them as builder.
+    pub fn defaults(mut self, defaults: SamplingParams) -> Self {
+        self.defaults = defaults;
+        self
+    }
+    pub fn get_defaults(&self) -> &SamplingParams {
+        &self.defaults
+    }
+    pub fn set_defaults(&mut self, defaults: SamplingParams) {
+        self.defaults = defaults;
+    }
     pub fn logprobs(mut self, logprobs: bool) -> Self {
         self.logprobs = logprobs;
         self
@@ -537,36 +1066,179 @@ impl DeepSeekClient {
     pub fn check_top_logprobs(&self) -> bool {
         self.logprobs || self.top_logprobs.is_none()
     }
+    /// Get the usage statistics of the last request.
+    pub fn get_last_usage(&self) -> DeepSeekUsage {
+        self.last_usage
+    }
+    /// Get the total usage statistics of all requests sent by the client.
+    pub fn get_total_usage(&self) -> DeepSeekUsage {
+        self.total_usage
+    }
+    /// Get the numbers of requests sent by the client.
+    pub fn get_requests(&self) -> RequestCount {
+        self.requests
+    }
 }
 
-use crate::error::ai_node_error::{AINodeError, AINodeErrorType, AINodeResult};
-impl super::AINode {
-    pub(super) async fn deepseek_execute(&mut self) -> AINodeResult<String> {
-        let client = match &mut self.service {
-            super::AIService::DeepSeek { client: client } => client,
-            _ => {
-                unreachable!()
+/// Create the error of the parameters that are not valid. The message names them, and the
+/// error tells what is wrong with every one of them.
+fn param_error(violations: Vec<ParamViolation>) -> DeepSeekError {
+    let message = format!(
+        "The parameters are not valid: {}.",
+        violations
+            .iter()
+            .map(|violation| violation.field.as_str())
+            .collect::<Vec<_>>()
+            .join(", ")
+    );
+    DeepSeekError::new(
+        DeepSeekErrorType::RequestParamError(Box::new(violations)),
+        message,
+    )
+}
+
+#[derive(Debug)]
+/// The struct of the stream of a response sent as server-sent events.
+pub struct DeepSeekStream {
+    /// The response being read.
+    response: Response,
+    /// The bytes that are received but not yet a complete line.
+    buffer: Vec<u8>,
+    /// The chunks that are parsed but not yet taken.
+    pending: std::collections::VecDeque<JsonValue>,
+    /// Whether the end of the stream is reached.
+    done: bool,
+}
+
+impl DeepSeekStream {
+    fn new(response: Response) -> Self {
+        DeepSeekStream {
+            response,
+            buffer: Vec::new(),
+            pending: std::collections::VecDeque::new(),
+            done: false,
+        }
+    }
+    /// Get the next chunk of the response, or `None` at the end of the stream.
+    pub async fn next_chunk(&mut self) -> DeepSeekResult<Option<JsonValue>> {
+        loop {
+            if let Some(chunk) = self.pending.pop_front() {
+                return Ok(Some(chunk));
+            }
+            if self.done {
+                return Ok(None);
             }
+            let bytes = self.response.chunk().await.map_err(|e| {
+                DeepSeekError::new(
+                    DeepSeekErrorType::RequestError,
+                    format!("Failed to read the stream. {}", e),
+                )
+            })?;
+            match bytes {
+                Some(bytes) => self.buffer.extend_from_slice(&bytes),
+                None => {
+                    self.done = true;
+                    // the last line may not end with a new line
+                    self.buffer.push(b'\n');
+                }
+            }
+            while let Some(position) = self.buffer.iter().position(|b| *b == b'\n') {
+                let line: Vec<u8> = self.buffer.drain(..=position).collect();
+                if let Some(chunk) = Self::parse_line(&String::from_utf8_lossy(&line))? {
+                    self.pending.push_back(chunk);
+                } else if String::from_utf8_lossy(&line).trim() == "data: [DONE]" {
+                    self.done = true;
+                }
+            }
+        }
+    }
+    /// Parse one line of the server-sent events. Empty lines, comments (like keep-alive) and
+    /// the final `[DONE]` give `None`.
+    fn parse_line(line: &str) -> DeepSeekResult<Option<JsonValue>> {
+        let line = line.trim();
+        let data = match line.strip_prefix("data:") {
+            Some(data) => data.trim(),
+            None => return Ok(None),
         };
-        let prompt = format!(
-            "{}\n{}\n{}",
-            self.prompt_prefix, self.input, self.prompt_suffix
-        );
-        self.histroy
-            .push(Chat::new("user".to_string(), prompt.clone()));
-        let response = client.send_request(&self.histroy).await.map_err(|e| {
-            AINodeError::new(
-                AINodeErrorType::DeepSeekError(e),
-                "Failed to send request to DeepSeek".to_string(),
+        if data == "[DONE]" {
+            return Ok(None);
+        }
+        json::parse(data).map(Some).map_err(|e| {
+            DeepSeekError::new(
+                DeepSeekErrorType::ResponseError,
+                format!("Failed to parse the stream chunk. {}", e),
             )
-        })?;
-        let response_text = response["choices"][0]["message"]["content"].to_string();
-        self.histroy.push(Chat::new(
-            "assistant".to_string(),
-            response_text.to_string(),
-        ));
+        })
+    }
+}
+
+use crate::error::ai_node_error::AINodeResult;
+impl DeepSeekClient {
+    /// Build the exchange that answers the request of the chats with the content, so the
+    /// tests can replay the AI service.
+    #[cfg(test)]
+    pub(crate) fn exchange(
+        &self,
+        chats: &Vec<Chat>,
+        overrides: &RequestOverrides,
+        content: &str,
+    ) -> super::recording::Exchange {
+        let request = self
+            .with_overrides(overrides)
+            .to_request_string(Self::chats_to_json(chats).unwrap());
+        super::recording::Exchange {
+            request: serde_json::from_str(&request).unwrap(),
+            response: serde_json::json!({
+                "choices": [{"message": {"role": "assistant", "content": content}}],
+                "usage": {
+                    "completion_tokens": 1,
+                    "prompt_tokens": 1,
+                    "prompt_cache_hit_tokens": 0,
+                    "prompt_cache_miss_tokens": 1,
+                    "total_tokens": 2
+                }
+            }),
+        }
+    }
+    /// Send the chats and get the content of the answer.
+    pub(super) async fn complete(
+        &mut self,
+        chats: &Vec<Chat>,
+        overrides: &RequestOverrides,
+    ) -> AINodeResult<String> {
+        let response = self.send_request_with(chats, overrides).await?;
+        Ok(response["choices"][0]["message"]["content"].to_string())
+    }
+}
 
-        Ok(response_text.to_string())
+impl super::AINode {
+    pub(super) async fn deepseek_turn(
+        &mut self,
+        overrides: &RequestOverrides,
+    ) -> AINodeResult<(String, Vec<ToolCall>)> {
+        let super::AIService::DeepSeek { client } = &mut self.service;
+        let chats = self.history_policy.apply(&self.histroy);
+        let response = client.send_request_with(&chats, overrides).await?;
+        let message = &response["choices"][0]["message"];
+        let response_text = message["content"].as_str().unwrap_or("").to_string();
+        if let Some(reasoning) = message["reasoning_content"].as_str() {
+            self.reasoning = Some(reasoning.to_string());
+        }
+        let tool_calls = message["tool_calls"]
+            .members()
+            .map(ToolCall::from_json)
+            .collect::<AINodeResult<Vec<ToolCall>>>()?;
+        let usage = client.get_last_usage();
+        self.histroy.push(
+            Chat::new(Role::Assistant, response_text.clone())
+                .node_uid(self.node_uid)
+                .tool_calls(tool_calls.clone())
+                .tokens(
+                    Some(usage.get_prompt_tokens()),
+                    Some(usage.get_completion_tokens()),
+                ),
+        );
+        Ok((response_text, tool_calls))
     }
 }
 
@@ -579,17 +1251,31 @@ impl std::fmt::Display for DeepSeekModel {
     }
 }
 
+impl std::str::FromStr for DeepSeekModel {
+    type Err = String;
+
+    /// Parse the name of the model, like `deepseek-chat`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "deepseek-chat" => Ok(DeepSeekModel::DeepseekChat),
+            "deepseek-reasoner" => Ok(DeepSeekModel::DeepseekReasoner),
+            _ => Err(format!("Unknown DeepSeek model {}.", s)),
+        }
+    }
+}
+
 impl std::fmt::Display for ResponseFormat {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             ResponseFormat::Text => write!(f, "text"),
-            ResponseFormat::Json => write!(f, "json"),
+            ResponseFormat::Json => write!(f, "json_object"),
         }
     }
 }
 
 #[cfg(test)]
 mod test {
+    use super::super::ContentPart;
     use super::*;
     use tokio::runtime::Runtime;
     #[test]
@@ -603,7 +1289,7 @@ mod test {
     #[test]
     fn build_deepseek_client() {
         let deepseek_client = DeepSeekClient::new(DEEPSEEK_API_URL, DeepSeekModel::DeepseekChat)
-            .api_key_from_env()
+            .api_key_from_env("API_KEY")
             .unwrap()
             .frequency_panalty(Some(0.5))
             .max_tokens(Some(2048))
@@ -636,7 +1322,7 @@ mod test {
             .top_logprobs(Some(10));
         let request_string = json::parse(
             deepseek_client
-                .into_request_string(
+                .to_request_string(
                     json::parse(
                         r#"[
                                 {
@@ -698,11 +1384,8 @@ mod test {
                 .api_key_from_file("./api_key.txt")
                 .unwrap();
         let chats = vec![
-            Chat::new(
-                "system".to_string(),
-                "You are a helpful assistant".to_string(),
-            ),
-            Chat::new("user".to_string(), "Hi".to_string()),
+            Chat::new(Role::System, "You are a helpful assistant".to_string()),
+            Chat::new(Role::User, "Hi".to_string()),
         ];
         let response = rt.block_on(deepseek_client.send_request(&chats));
         match response {
@@ -727,11 +1410,8 @@ mod test {
                 .logprobs(true)
                 .top_logprobs(Some(3));
         let chats = vec![
-            Chat::new(
-                "system".to_string(),
-                "You are a helpful assistant".to_string(),
-            ),
-            Chat::new("user".to_string(), "Hi".to_string()),
+            Chat::new(Role::System, "You are a helpful assistant".to_string()),
+            Chat::new(Role::User, "Hi".to_string()),
         ];
         let response = rt.block_on(deepseek_client.send_request(&chats));
         match response {
@@ -744,4 +1424,296 @@ mod test {
             }
         }
     }
+
+    #[test]
+    fn request_overrides() {
+        let deepseek_client = DeepSeekClient::new(DEEPSEEK_API_URL, DeepSeekModel::DeepseekChat)
+            .temperature(Some(0.5))
+            .max_tokens(Some(1024));
+        let overrides = RequestOverrides::new()
+            .max_tokens(8192)
+            .stop(vec!["END".to_string()])
+            .response_format(ResponseFormat::Json);
+        let effective = deepseek_client.with_overrides(&overrides);
+        let request = json::parse(&effective.to_request_string(json::array![])).unwrap();
+        assert_eq!(request["temperature"], 0.5);
+        assert_eq!(request["max_tokens"], 8192);
+        assert_eq!(request["stop"][0], "END");
+        assert_eq!(request["response_format"]["type"], "json_object");
+        assert_eq!(deepseek_client.get_max_tokens(), Some(1024));
+        assert!(deepseek_client.get_stop().is_none());
+        // the format of the client is sent the same way, and an override replaces it
+        let json_client = deepseek_client
+            .clone()
+            .response_format(Some(ResponseFormat::Json));
+        let request = json::parse(&json_client.to_request_string(json::array![])).unwrap();
+        assert_eq!(request["response_format"]["type"], "json_object");
+        let overrides = RequestOverrides::new().response_format(ResponseFormat::Text);
+        let request = json_client.with_overrides(&overrides);
+        let request = json::parse(&request.to_request_string(json::array![])).unwrap();
+        assert_eq!(request["response_format"]["type"], "text");
+
+        // the node is over the client, the defaults are under it
+        let deepseek_client = deepseek_client.defaults(
+            SamplingParams::new()
+                .temperature(Some(0.2))
+                .top_p(Some(0.9)),
+        );
+        let overrides =
+            RequestOverrides::new().node_params(SamplingParams::new().temperature(Some(1.5)));
+        let params = deepseek_client.effective_params(&overrides);
+        assert_eq!(params.temperature.value, 1.5);
+        assert_eq!(params.temperature.layer, ParamLayer::Node);
+        assert_eq!(params.top_p.layer, ParamLayer::Global);
+        assert_eq!(params.max_tokens.layer, ParamLayer::Provider);
+        assert_eq!(params.presence_penalty.layer, ParamLayer::BuiltIn);
+        let request = json::parse(
+            &deepseek_client
+                .with_overrides(&overrides.temperature(0.1))
+                .to_request_string(json::array![]),
+        )
+        .unwrap();
+        assert_eq!(request["temperature"], 0.1);
+        assert_eq!(request["top_p"], 0.9);
+    }
+
+    #[test]
+    fn chats_to_json_rejects_images() {
+        let chats = vec![Chat::with_parts(
+            Role::User,
+            vec![ContentPart::ImageUrl {
+                url: "https://example.com/cat.png".to_string(),
+                detail: None,
+            }],
+        )];
+        assert!(DeepSeekClient::chats_to_json(&chats).is_err());
+    }
+
+    #[test]
+    fn parse_stream_lines() {
+        let chunk = DeepSeekStream::parse_line(
+            r#"data: {"choices":[{"index":0,"delta":{"content":"Hi"}}]}"#,
+        )
+        .unwrap()
+        .unwrap();
+        assert_eq!(chunk["choices"][0]["delta"]["content"], "Hi");
+        assert!(DeepSeekStream::parse_line(": keep-alive")
+            .unwrap()
+            .is_none());
+        assert!(DeepSeekStream::parse_line("data: [DONE]")
+            .unwrap()
+            .is_none());
+        assert!(DeepSeekStream::parse_line("data: {").is_err());
+    }
+
+    #[test]
+    fn replay_request() {
+        let chats = vec![Chat::new(Role::User, "Hi".to_string())];
+        let client = DeepSeekClient::new(DEEPSEEK_API_URL, DeepSeekModel::DeepseekChat);
+        let request = client.to_request_string(DeepSeekClient::chats_to_json(&chats).unwrap());
+        let recording = Recording::replay(vec![super::super::recording::Exchange {
+            request: serde_json::from_str(&request).unwrap(),
+            response: serde_json::json!({
+                "choices": [{"message": {"role": "assistant", "content": "Hello"}}],
+                "usage": {
+                    "completion_tokens": 1,
+                    "prompt_tokens": 2,
+                    "prompt_cache_hit_tokens": 0,
+                    "prompt_cache_miss_tokens": 2,
+                    "total_tokens": 3
+                }
+            }),
+        }]);
+        // no api key is needed to replay
+        let mut client = client.recording(Some(recording));
+        let rt = Runtime::new().unwrap();
+        let response = rt.block_on(client.send_request(&chats)).unwrap();
+        assert_eq!(response["choices"][0]["message"]["content"], "Hello");
+        assert_eq!(client.get_last_usage().get_total_tokens(), 3);
+        assert!(rt.block_on(client.send_request(&chats)).is_err());
+    }
+
+    #[test]
+    fn record_latency() {
+        let chats = vec![Chat::new(Role::User, "Hi".to_string())];
+        let stats = ProviderStats::new();
+        // nothing listens on the port, so the request fails
+        let mut client = DeepSeekClient::new("http://127.0.0.1:1", DeepSeekModel::DeepseekChat)
+            .stats(Some(stats.clone()));
+        client.set_api_key(Some("key".to_string()));
+        let rt = Runtime::new().unwrap();
+        assert!(rt.block_on(client.send_request(&chats)).is_err());
+        let chat = stats.get("deepseek", "deepseek-chat").unwrap();
+        assert_eq!((chat.requests, chat.errors), (1, 1));
+        assert_eq!(
+            client.get_requests(),
+            RequestCount {
+                succeeded: 0,
+                failed: 1
+            }
+        );
+    }
+
+    #[test]
+    fn parse_api_errors() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+        use tokio::net::TcpListener;
+
+        let chats = vec![Chat::new(Role::User, "Hi".to_string())];
+        let rt = Runtime::new().unwrap();
+        let listener = rt.block_on(TcpListener::bind("127.0.0.1:0")).unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let body = r#"{"error": {"message": "Insufficient Balance", "type": "unknown_error", "param": null, "code": "invalid_request_error"}}"#;
+        let responses = [
+            ("402 Payment Required", "application/json", body),
+            ("502 Bad Gateway", "text/html", "<html>Bad Gateway</html>"),
+        ];
+        rt.spawn(async move {
+            for (status, content_type, body) in responses {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let mut request = [0; 4096];
+                let _ = stream.read(&mut request).await.unwrap();
+                let response = format!(
+                    "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nx-request-id: req-1\r\nConnection: close\r\n\r\n{}",
+                    status,
+                    content_type,
+                    body.len(),
+                    body
+                );
+                stream.write_all(response.as_bytes()).await.unwrap();
+            }
+        });
+        let mut client = DeepSeekClient::new(&url, DeepSeekModel::DeepseekChat);
+        client.set_api_key(Some("key".to_string()));
+        let error = rt.block_on(client.send_request(&chats)).unwrap_err();
+        assert_eq!(
+            error.get_api_error(),
+            Some(ApiErrorKind::InsufficientBalance)
+        );
+        assert_eq!(error.get_message(), "Insufficient Balance");
+        assert_eq!(error.get_status(), Some(402));
+        assert_eq!(error.get_request_id(), Some("req-1"));
+        assert_eq!(error.get_body(), Some(body));
+        assert_eq!(error.get_endpoint(), Some(url.as_str()));
+
+        // a body that is not an error of the api is still kept
+        let error = rt.block_on(client.send_request(&chats)).unwrap_err();
+        assert_eq!(
+            error.get_message(),
+            "Request failed with status 502 Bad Gateway."
+        );
+        assert_eq!(error.get_status(), Some(502));
+        assert_eq!(error.get_request_id(), Some("req-1"));
+        assert_eq!(error.get_body(), Some("<html>Bad Gateway</html>"));
+
+        let kinds = [
+            (
+                400,
+                "invalid_request_error",
+                "This model's maximum context length is 65536 tokens.",
+                ApiErrorKind::ContextLengthExceeded,
+            ),
+            (
+                400,
+                "invalid_request_error",
+                "Invalid JSON.",
+                ApiErrorKind::InvalidRequest,
+            ),
+            (
+                401,
+                "authentication_error",
+                "Authentication Fails",
+                ApiErrorKind::Authentication,
+            ),
+            (
+                422,
+                "invalid_request_error",
+                "Invalid temperature.",
+                ApiErrorKind::InvalidParameters,
+            ),
+            (
+                429,
+                "rate_limit_error",
+                "Rate Limit Reached",
+                ApiErrorKind::RateLimit,
+            ),
+            (503, "", "Server Overloaded", ApiErrorKind::ServerOverloaded),
+            (418, "", "", ApiErrorKind::Unknown),
+        ];
+        for (status, error_type, message, kind) in kinds {
+            assert_eq!(ApiErrorKind::of(status, error_type, "", message), kind);
+        }
+    }
+
+    #[test]
+    fn validate_params() {
+        let mut client = DeepSeekClient::new(DEEPSEEK_API_URL, DeepSeekModel::DeepseekChat)
+            .temperature(Some(3.0))
+            .top_logprobs(Some(2));
+        client.set_api_key(Some("sk-secret".to_string()));
+        // the key is never printed
+        assert!(!format!("{:?}", client).contains("sk-secret"));
+        assert_eq!(client.get_api_key(), Some("sk-secret"));
+        let violations = client.validate().unwrap_err();
+        assert_eq!(
+            violations,
+            vec![
+                ParamViolation::new("temperature", "3".to_string(), "between 0 and 2"),
+                ParamViolation::new("top_logprobs", "2".to_string(), "only set with logprobs"),
+            ]
+        );
+        assert!(!client.check_params());
+
+        let chats = vec![Chat::new(Role::User, "Hi".to_string())];
+        let rt = Runtime::new().unwrap();
+        let error = rt.block_on(client.send_request(&chats)).unwrap_err();
+        assert_eq!(
+            error.get_message(),
+            "The parameters are not valid: temperature, top_logprobs."
+        );
+        assert!(error
+            .to_string()
+            .contains("temperature is 3, but it must be between 0 and 2"));
+        client.set_temperature(None);
+        client.set_logprobs(true);
+        assert_eq!(client.validate(), Ok(()));
+    }
+
+    #[test]
+    fn distinct_timeouts() {
+        use tokio::net::TcpListener;
+
+        let chats = vec![Chat::new(Role::User, "Hi".to_string())];
+        let rt = Runtime::new().unwrap();
+        // the server takes the connections but never answers
+        let listener = rt.block_on(TcpListener::bind("127.0.0.1:0")).unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        rt.spawn(async move {
+            let mut streams = Vec::new();
+            loop {
+                streams.push(listener.accept().await.unwrap());
+            }
+        });
+        let timeout_type = |client: &mut DeepSeekClient| {
+            let error = rt.block_on(client.send_request(&chats)).unwrap_err();
+            match error.get_error_type() {
+                DeepSeekErrorType::TimeoutError(e) => (*e.get_error_type(), e.get_limit()),
+                _ => panic!("{} is not a timeout", error),
+            }
+        };
+        let limit = Duration::from_millis(100);
+        let mut client =
+            DeepSeekClient::new(&url, DeepSeekModel::DeepseekChat).read_timeout(Some(limit));
+        client.set_api_key(Some("key".to_string()));
+        assert_eq!(
+            timeout_type(&mut client),
+            (TimeoutErrorType::Read, Some(limit))
+        );
+        client.set_read_timeout(None);
+        client.set_timeout(Some(limit));
+        assert_eq!(
+            timeout_type(&mut client),
+            (TimeoutErrorType::Total, Some(limit))
+        );
+    }
 }
@@ -0,0 +1,397 @@
+//! # Embedding
+//!
+//! This module turns texts into embedding vectors with the embeddings api of a provider, as
+//! the foundation of the retrieval features.
+//!
+//! DeepSeek has no embeddings api, so the embeddings are asked from a provider of its own:
+//! 1. OpenAI: the `/v1/embeddings` api, which many other providers and local servers (vLLM,
+//!    LM Studio, ...) also serve, so the url can point to any of them.
+//! 2. Ollama: the `/api/embed` api of a local Ollama server.
+//!
+//! The texts are sent in batches of `batch_size`, and the vectors are given in the order of
+//! the texts.
+
+use super::key::{ApiKey, KeySource};
+use super::stats::ProviderStats;
+use crate::error::ai_node_error::embedding_error::{
+    EmbeddingError, EmbeddingErrorType, EmbeddingResult,
+};
+use crate::error::ai_node_error::AINodeResult;
+
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use std::time::Instant;
+
+pub const OPENAI_EMBEDDINGS_URL: &str = "https://api.openai.com/v1/embeddings";
+pub const OLLAMA_EMBEDDINGS_URL: &str = "http://localhost:11434/api/embed";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+/// The enum of the api the embeddings are asked from.
+pub enum EmbeddingProvider {
+    /// The OpenAI embeddings api.
+    #[serde(rename = "openai")]
+    OpenAI,
+    /// The Ollama embed api.
+    Ollama,
+}
+
+#[derive(Debug, Clone)]
+/// The struct of the client of an embeddings api.
+pub struct EmbeddingClient {
+    /// The api of the provider.
+    provider: EmbeddingProvider,
+    /// The url of the api.
+    url: String,
+    /// The name of the embedding model.
+    model: String,
+    /// The api key, not needed by a local server.
+    api_key: Option<ApiKey>,
+    /// The number of dimensions of the vectors, for the models that can shorten them.
+    dimensions: Option<usize>,
+    /// The max number of texts in one request.
+    batch_size: usize,
+    /// The number of tokens of the texts in the last call of `embed`.
+    last_tokens: i64,
+    /// The stats that the latency of the requests is recorded to.
+    stats: Option<ProviderStats>,
+}
+
+impl EmbeddingClient {
+    /// Create a new EmbeddingClient.
+    pub fn new(provider: EmbeddingProvider, url: &str, model: &str) -> Self {
+        EmbeddingClient {
+            provider,
+            url: url.to_string(),
+            model: model.to_string(),
+            api_key: None,
+            dimensions: None,
+            batch_size: Self::default_batch_size(),
+            last_tokens: 0,
+            stats: None,
+        }
+    }
+    /// Create a new EmbeddingClient of the OpenAI embeddings api.
+    pub fn openai(model: &str) -> Self {
+        Self::new(EmbeddingProvider::OpenAI, OPENAI_EMBEDDINGS_URL, model)
+    }
+    /// Create a new EmbeddingClient of a local Ollama server.
+    pub fn ollama(model: &str) -> Self {
+        Self::new(EmbeddingProvider::Ollama, OLLAMA_EMBEDDINGS_URL, model)
+    }
+    /// Embed the texts, and get one vector per text in the same order.
+    pub async fn embed(&mut self, texts: &[String]) -> AINodeResult<Vec<Vec<f32>>> {
+        let mut vectors = Vec::with_capacity(texts.len());
+        let mut tokens = 0;
+        for batch in texts.chunks(self.batch_size.max(1)) {
+            let started = Instant::now();
+            let embedded = self.embed_batch(batch).await;
+            if let Some(stats) = &self.stats {
+                let provider = match self.provider {
+                    EmbeddingProvider::OpenAI => "openai",
+                    EmbeddingProvider::Ollama => "ollama",
+                };
+                stats.record(provider, &self.model, started.elapsed(), embedded.is_ok());
+            }
+            let (batch_vectors, batch_tokens) = embedded?;
+            vectors.extend(batch_vectors);
+            tokens += batch_tokens;
+        }
+        self.last_tokens = tokens;
+        Ok(vectors)
+    }
+    /// Check that the api is reachable and accepts the api key, by embedding one word.
+    pub async fn healthcheck(&self) -> AINodeResult<()> {
+        self.embed_batch(&["ping".to_string()]).await?;
+        Ok(())
+    }
+    /// Embed one batch of texts, and get the vectors and the number of tokens.
+    async fn embed_batch(&self, texts: &[String]) -> EmbeddingResult<(Vec<Vec<f32>>, i64)> {
+        let mut body = json!({ "model": self.model, "input": texts });
+        if let Some(dimensions) = self.dimensions {
+            body["dimensions"] = json!(dimensions);
+        }
+        let mut request = reqwest::Client::new()
+            .post(&self.url)
+            .header("Content-Type", "application/json")
+            .body(body.to_string());
+        if let Some(api_key) = &self.api_key {
+            request = request.header("Authorization", format!("Bearer {}", api_key.expose()));
+        } else if self.provider == EmbeddingProvider::OpenAI && self.url == OPENAI_EMBEDDINGS_URL {
+            return Err(EmbeddingError::new(
+                EmbeddingErrorType::ApiKeyError,
+                "The OpenAI embeddings api needs an api key.".to_string(),
+            ));
+        }
+        let endpoint = Some(self.url.clone());
+        let response = request.send().await.map_err(|e| {
+            EmbeddingError::new(
+                EmbeddingErrorType::RequestError,
+                format!("Failed to send request. {}", e),
+            )
+            .endpoint(endpoint.clone())
+        })?;
+        let status = response.status();
+        let text = response.text().await.map_err(|e| {
+            EmbeddingError::new(
+                EmbeddingErrorType::RequestError,
+                format!("Failed to read the response. {}", e),
+            )
+            .status(Some(status.as_u16()))
+            .endpoint(endpoint.clone())
+        })?;
+        if !status.is_success() {
+            return Err(EmbeddingError::new(
+                EmbeddingErrorType::ResponseError,
+                format!("The request is refused. {}", text),
+            )
+            .status(Some(status.as_u16()))
+            .endpoint(endpoint));
+        }
+        let response_error = |message: String| {
+            EmbeddingError::new(EmbeddingErrorType::ResponseError, message)
+                .endpoint(endpoint.clone())
+        };
+        let response: Value = serde_json::from_str(&text)
+            .map_err(|e| response_error(format!("The response is not valid json. {}", e)))?;
+        let (vectors, tokens) = match self.provider {
+            EmbeddingProvider::OpenAI => {
+                let mut data: Vec<(u64, &Value)> = response["data"]
+                    .as_array()
+                    .ok_or_else(|| response_error("The response has no data.".to_string()))?
+                    .iter()
+                    .map(|item| (item["index"].as_u64().unwrap_or_default(), item))
+                    .collect();
+                data.sort_by_key(|(index, _)| *index);
+                let vectors = data
+                    .into_iter()
+                    .map(|(_, item)| parse_vector(&item["embedding"]))
+                    .collect::<Option<Vec<Vec<f32>>>>();
+                (vectors, response["usage"]["prompt_tokens"].as_i64())
+            }
+            EmbeddingProvider::Ollama => {
+                let vectors = response["embeddings"]
+                    .as_array()
+                    .ok_or_else(|| response_error("The response has no embeddings.".to_string()))?
+                    .iter()
+                    .map(parse_vector)
+                    .collect::<Option<Vec<Vec<f32>>>>();
+                (vectors, response["prompt_eval_count"].as_i64())
+            }
+        };
+        let vectors =
+            vectors.ok_or_else(|| response_error("An embedding is not a vector.".to_string()))?;
+        if vectors.len() != texts.len() {
+            return Err(response_error(format!(
+                "The response has {} embeddings for {} texts.",
+                vectors.len(),
+                texts.len()
+            )));
+        }
+        Ok((vectors, tokens.unwrap_or_default()))
+    }
+    /// Set the api key from the environment variable of the name.
+    pub fn api_key_from_env(self, name: &str) -> EmbeddingResult<Self> {
+        self.api_key_from(&KeySource::env(name))
+    }
+    /// Set the api key from the source.
+    pub fn api_key_from(mut self, source: &KeySource) -> EmbeddingResult<Self> {
+        let api_key = source
+            .resolve()
+            .map_err(|e| EmbeddingError::new(EmbeddingErrorType::ApiKeyError, e))?;
+        self.api_key = Some(api_key);
+        Ok(self)
+    }
+    /// Set the api key as builder.
+    pub fn api_key<K: Into<ApiKey>>(mut self, api_key: Option<K>) -> Self {
+        self.api_key = api_key.map(Into::into);
+        self
+    }
+    /// Set the api key.
+    pub fn set_api_key<K: Into<ApiKey>>(&mut self, api_key: Option<K>) {
+        self.api_key = api_key.map(Into::into);
+    }
+    /// Get the api key.
+    pub fn get_api_key(&self) -> Option<&str> {
+        self.api_key.as_ref().map(ApiKey::expose)
+    }
+    /// Get the api of the provider.
+    pub fn get_provider(&self) -> EmbeddingProvider {
+        self.provider
+    }
+    /// Get the url of the api.
+    pub fn get_url(&self) -> &str {
+        &self.url
+    }
+    /// Set the url of the api.
+    pub fn set_url(&mut self, url: &str) {
+        self.url = url.to_string();
+    }
+    /// Get the name of the model.
+    pub fn get_model(&self) -> &str {
+        &self.model
+    }
+    /// Set the name of the model.
+    pub fn set_model(&mut self, model: &str) {
+        self.model = model.to_string();
+    }
+    /// Set the number of dimensions as builder.
+    pub fn dimensions(mut self, dimensions: Option<usize>) -> Self {
+        self.dimensions = dimensions;
+        self
+    }
+    /// Set the number of dimensions.
+    pub fn set_dimensions(&mut self, dimensions: Option<usize>) {
+        self.dimensions = dimensions;
+    }
+    /// Get the number of dimensions.
+    pub fn get_dimensions(&self) -> Option<usize> {
+        self.dimensions
+    }
+    /// Set the batch size as builder.
+    pub fn batch_size(mut self, batch_size: usize) -> Self {
+        self.batch_size = batch_size;
+        self
+    }
+    /// Set the batch size.
+    pub fn set_batch_size(&mut self, batch_size: usize) {
+        self.batch_size = batch_size;
+    }
+    /// Get the batch size.
+    pub fn get_batch_size(&self) -> usize {
+        self.batch_size
+    }
+    /// The default batch size.
+    pub fn default_batch_size() -> usize {
+        64
+    }
+    /// Set the stats that the latency of the requests is recorded to as builder.
+    pub fn stats(mut self, stats: Option<ProviderStats>) -> Self {
+        self.stats = stats;
+        self
+    }
+    /// Set the stats that the latency of the requests is recorded to.
+    pub fn set_stats(&mut self, stats: Option<ProviderStats>) {
+        self.stats = stats;
+    }
+    /// Get the stats that the latency of the requests is recorded to.
+    pub fn get_stats(&self) -> Option<&ProviderStats> {
+        self.stats.as_ref()
+    }
+    /// Get the number of tokens of the texts in the last call of `embed`, 0 if the provider
+    /// doesn't tell.
+    pub fn get_last_tokens(&self) -> i64 {
+        self.last_tokens
+    }
+}
+
+/// Parse a json array of numbers into a vector.
+fn parse_vector(value: &Value) -> Option<Vec<f32>> {
+    value
+        .as_array()?
+        .iter()
+        .map(|number| number.as_f64().map(|number| number as f32))
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::error::ai_node_error::AINodeErrorType;
+
+    use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+    use tokio::net::TcpListener;
+    use tokio::runtime::Runtime;
+
+    /// Answer the requests with the bodies, and get the bodies of the requests.
+    async fn server(listener: TcpListener, answers: Vec<Value>) -> Vec<Value> {
+        let mut requests = Vec::new();
+        for answer in answers {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut stream = BufReader::new(stream);
+            let mut length = 0;
+            loop {
+                let mut line = String::new();
+                stream.read_line(&mut line).await.unwrap();
+                if line == "\r\n" {
+                    break;
+                }
+                if let Some(value) = line.to_lowercase().strip_prefix("content-length:") {
+                    length = value.trim().parse().unwrap();
+                }
+            }
+            let mut body = vec![0; length];
+            stream.read_exact(&mut body).await.unwrap();
+            requests.push(serde_json::from_slice(&body).unwrap());
+            let answer = answer.to_string();
+            let response = format!(
+                "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                answer.len(),
+                answer
+            );
+            stream
+                .get_mut()
+                .write_all(response.as_bytes())
+                .await
+                .unwrap();
+        }
+        requests
+    }
+
+    #[test]
+    fn embed_texts() {
+        let rt = Runtime::new().unwrap();
+        let texts: Vec<String> = ["a", "b", "c"].iter().map(|t| t.to_string()).collect();
+
+        let listener = rt.block_on(TcpListener::bind("127.0.0.1:0")).unwrap();
+        let url = format!("http://{}/v1/embeddings", listener.local_addr().unwrap());
+        // the data of a batch may come in any order
+        let answers = vec![
+            json!({"data": [
+                {"index": 1, "embedding": [0.0, 1.0]},
+                {"index": 0, "embedding": [1.0, 0.0]},
+            ], "usage": {"prompt_tokens": 2}}),
+            json!({"data": [{"index": 0, "embedding": [0.5, 0.5]}], "usage": {"prompt_tokens": 1}}),
+        ];
+        let server_task = rt.spawn(server(listener, answers));
+        let mut client = EmbeddingClient::new(EmbeddingProvider::OpenAI, &url, "small")
+            .api_key(Some("key".to_string()))
+            .dimensions(Some(2))
+            .batch_size(2);
+        let vectors = rt.block_on(client.embed(&texts)).unwrap();
+        assert_eq!(
+            vectors,
+            vec![vec![1.0, 0.0], vec![0.0, 1.0], vec![0.5, 0.5]]
+        );
+        assert_eq!(client.get_last_tokens(), 3);
+        let requests = rt.block_on(server_task).unwrap();
+        assert_eq!(
+            requests[0],
+            json!({"model": "small", "input": ["a", "b"], "dimensions": 2})
+        );
+        assert_eq!(requests[1]["input"], json!(["c"]));
+
+        let listener = rt.block_on(TcpListener::bind("127.0.0.1:0")).unwrap();
+        let url = format!("http://{}/api/embed", listener.local_addr().unwrap());
+        // one embedding is missing
+        let answers = vec![json!({"embeddings": [[1.0], [2.0]], "prompt_eval_count": 3})];
+        let server_task = rt.spawn(server(listener, answers));
+        let stats = ProviderStats::new();
+        let mut client = EmbeddingClient::new(EmbeddingProvider::Ollama, &url, "nomic")
+            .stats(Some(stats.clone()));
+        let error = rt.block_on(client.embed(&texts)).unwrap_err();
+        rt.block_on(server_task).unwrap();
+        match error.get_error_type() {
+            AINodeErrorType::EmbeddingError(e) => {
+                assert!(matches!(
+                    e.get_error_type(),
+                    EmbeddingErrorType::ResponseError
+                ))
+            }
+            _ => panic!("{}", error),
+        }
+        let nomic = stats.get("ollama", "nomic").unwrap();
+        assert_eq!((nomic.requests, nomic.errors), (1, 1));
+    }
+}
@@ -0,0 +1,410 @@
+//! # History
+//!
+//! This module decides which part of the history is sent to the AI service, so a long
+//! conversation doesn't exceed the context window of the model.
+//!
+//! The policy only trims the chats that are sent, the history kept in the AI node stays
+//! complete. The last chat, which is the current prompt, and the pinned chats, like the
+//! few-shot examples, are always sent. An assistant message calling tools is never sent
+//! without the tool messages answering it, and the other way around.
+//!
+//! Besides trimming, an AI node can compact its history: when the history grows over a token
+//! threshold, the older chats are summarized by another (usually cheaper) AI service and
+//! replaced by the summary, while the system chats and the recent chats are kept. The summary
+//! of a previous compaction is merged into the new one.
+
+use super::{AIService, Chat, RequestOverrides, Role};
+use crate::error::ai_node_error::AINodeResult;
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+/// The enum of the strategy to trim the history.
+pub enum TrimStrategy {
+    /// Send the whole history.
+    Unlimited,
+    /// Send the last n chats.
+    SlidingWindow(usize),
+    /// Send the latest chats whose estimated tokens fit in the budget.
+    TokenBudget(usize),
+    /// Send the first n chats and the last m chats.
+    KeepFirstLast { first: usize, last: usize },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+/// The struct of the policy to trim the history before each request.
+pub struct HistoryPolicy {
+    /// The strategy to trim the history.
+    strategy: TrimStrategy,
+    /// Whether the system chats are always sent, no matter what the strategy is.
+    keep_system: bool,
+}
+
+impl Default for HistoryPolicy {
+    fn default() -> Self {
+        HistoryPolicy {
+            strategy: TrimStrategy::Unlimited,
+            keep_system: true,
+        }
+    }
+}
+
+impl HistoryPolicy {
+    /// Create a new HistoryPolicy that keeps the system chats.
+    pub fn new(strategy: TrimStrategy) -> Self {
+        HistoryPolicy {
+            strategy,
+            keep_system: true,
+        }
+    }
+    /// Set whether the system chats are always kept as builder.
+    pub fn keep_system(mut self, keep_system: bool) -> Self {
+        self.keep_system = keep_system;
+        self
+    }
+    /// Get the strategy.
+    pub fn get_strategy(&self) -> TrimStrategy {
+        self.strategy
+    }
+    /// Get whether the system chats are always kept.
+    pub fn get_keep_system(&self) -> bool {
+        self.keep_system
+    }
+    /// Apply the policy to the chats and get the chats to send, in the original order.
+    pub fn apply(&self, chats: &[Chat]) -> Vec<Chat> {
+        let pinned: Vec<bool> = chats
+            .iter()
+            .enumerate()
+            .map(|(i, chat)| {
+                i + 1 == chats.len()
+                    || chat.get_metadata().pinned
+                    || (self.keep_system && chat.get_role() == Role::System)
+            })
+            .collect();
+        let rest: Vec<usize> = (0..chats.len()).filter(|&i| !pinned[i]).collect();
+        let mut kept = pinned.clone();
+        match self.strategy {
+            TrimStrategy::Unlimited => rest.iter().for_each(|&i| kept[i] = true),
+            TrimStrategy::SlidingWindow(n) => {
+                // the last chat is pinned and takes one place of the window
+                let n = n.saturating_sub(1);
+                rest.iter().rev().take(n).for_each(|&i| kept[i] = true);
+            }
+            TrimStrategy::TokenBudget(budget) => {
+                let mut used: usize = (0..chats.len())
+                    .filter(|&i| pinned[i])
+                    .map(|i| estimate_chat_tokens(&chats[i]))
+                    .sum();
+                for &i in rest.iter().rev() {
+                    used += estimate_chat_tokens(&chats[i]);
+                    if used > budget {
+                        break;
+                    }
+                    kept[i] = true;
+                }
+            }
+            TrimStrategy::KeepFirstLast { first, last } => {
+                let last = last.saturating_sub(1);
+                rest.iter().take(first).for_each(|&i| kept[i] = true);
+                rest.iter().rev().take(last).for_each(|&i| kept[i] = true);
+            }
+        }
+        // an assistant message with tool calls and the tool messages answering it are sent
+        // together or not at all
+        let starts = group_starts(chats);
+        for i in 0..chats.len() {
+            if chats[i].get_role() == Role::Tool && kept[starts[i]..=i].iter().any(|&k| k) {
+                let end = (i..chats.len())
+                    .take_while(|&j| chats[j].get_role() == Role::Tool)
+                    .last()
+                    .unwrap_or(i);
+                kept[starts[i]..=end].iter_mut().for_each(|k| *k = true);
+            }
+        }
+        chats
+            .iter()
+            .zip(kept)
+            .filter(|(_, kept)| *kept)
+            .map(|(chat, _)| chat.clone())
+            .collect()
+    }
+}
+
+/// Get the index of the first chat of the group of each chat. A tool message is in the group
+/// of the assistant message calling the tool, and any other chat starts a group.
+fn group_starts(chats: &[Chat]) -> Vec<usize> {
+    let mut group_start = 0;
+    (0..chats.len())
+        .map(|i| {
+            if chats[i].get_role() != Role::Tool {
+                group_start = i;
+            }
+            group_start
+        })
+        .collect()
+}
+
+/// The first line of the summary that replaces the older chats.
+const SUMMARY_HEADER: &str = "Summary of the earlier conversation:";
+
+/// The prompt to ask the AI service to summarize the conversation.
+const SUMMARY_PROMPT: &str = "Summarize the following conversation between a user and an \
+assistant. Keep every fact, decision and open question that later turns may rely on. \
+Answer with the summary only.";
+
+#[derive(Debug, Clone)]
+/// The struct of the configuration to compact the history by summarization.
+pub struct Compaction {
+    /// The AI service used to write the summary.
+    service: AIService,
+    /// The history is compacted when its estimated tokens exceed this threshold.
+    threshold: usize,
+    /// The number of recent chats kept as they are.
+    keep_recent: usize,
+}
+
+impl Compaction {
+    /// Create a new Compaction.
+    pub fn new(service: AIService, threshold: usize, keep_recent: usize) -> Self {
+        Compaction {
+            service,
+            threshold,
+            keep_recent,
+        }
+    }
+    /// Get the AI service used to write the summary.
+    pub fn get_service(&self) -> &AIService {
+        &self.service
+    }
+    /// Get the token threshold.
+    pub fn get_threshold(&self) -> usize {
+        self.threshold
+    }
+    /// Get the number of recent chats kept.
+    pub fn get_keep_recent(&self) -> usize {
+        self.keep_recent
+    }
+    /// Compact the chats if they exceed the threshold. Return the new chats, or `None` if
+    /// there is nothing to compact.
+    pub async fn compact(&mut self, chats: &[Chat]) -> AINodeResult<Option<Vec<Chat>>> {
+        let total: usize = chats.iter().map(estimate_chat_tokens).sum();
+        if total <= self.threshold {
+            return Ok(None);
+        }
+        let (head, older, recent) = match split_for_compaction(chats, self.keep_recent) {
+            Some(split) => split,
+            None => return Ok(None),
+        };
+        let request = vec![
+            Chat::new(Role::System, SUMMARY_PROMPT.to_string()),
+            Chat::new(Role::User, transcript(&older)),
+        ];
+        let summary = self
+            .service
+            .complete(&request, &RequestOverrides::default())
+            .await?;
+        let mut compacted = head;
+        compacted.push(Chat::new(
+            Role::System,
+            format!("{}\n{}", SUMMARY_HEADER, summary),
+        ));
+        compacted.extend(recent);
+        Ok(Some(compacted))
+    }
+}
+
+/// Split the chats into the leading system and pinned chats, the older chats to summarize and
+/// the recent chats to keep. Return `None` if there is no older chat.
+///
+/// The summary of a previous compaction is not part of the head, so it is summarized again
+/// with the older chats and the history keeps a single summary. The recent chats never start
+/// in the middle of a group of tool calls.
+pub fn split_for_compaction(
+    chats: &[Chat],
+    keep_recent: usize,
+) -> Option<(Vec<Chat>, Vec<Chat>, Vec<Chat>)> {
+    let head_len = chats
+        .iter()
+        .take_while(|chat| {
+            (chat.get_role() == Role::System || chat.get_metadata().pinned) && !is_summary(chat)
+        })
+        .count();
+    let body = &chats[head_len..];
+    if body.len() <= keep_recent {
+        return None;
+    }
+    let split = group_starts(body)[body.len() - keep_recent];
+    if split == 0 {
+        return None;
+    }
+    let (older, recent) = body.split_at(split);
+    Some((chats[..head_len].to_vec(), older.to_vec(), recent.to_vec()))
+}
+
+/// Whether the chat is the summary written by a compaction.
+fn is_summary(chat: &Chat) -> bool {
+    chat.get_role() == Role::System
+        && chat
+            .get_content()
+            .as_text()
+            .starts_with(&format!("{}\n", SUMMARY_HEADER))
+}
+
+/// Render the chats as a plain text transcript for summarization.
+pub fn transcript(chats: &[Chat]) -> String {
+    chats
+        .iter()
+        .map(|chat| format!("{}: {}", chat.get_role(), chat.get_content().as_text()))
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+/// Estimate the number of tokens of a text. An ascii token is about 4 characters, and
+/// other characters (like chinese) are about one token each.
+pub fn estimate_tokens(text: &str) -> usize {
+    let ascii = text.chars().filter(|c| c.is_ascii()).count();
+    let others = text.chars().count() - ascii;
+    ascii.div_ceil(4) + others
+}
+
+/// Estimate the number of tokens of a chat. The completion tokens recorded by the AI service
+/// are used when there are any.
+pub fn estimate_chat_tokens(chat: &Chat) -> usize {
+    match chat.get_metadata().completion_tokens {
+        Some(tokens) if tokens > 0 => tokens as usize,
+        // every message has a few tokens of overhead for the role
+        _ => estimate_tokens(&chat.get_content().as_text()) + 4,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn conversation() -> Vec<Chat> {
+        let mut chats = vec![Chat::new(Role::System, "You are a cat".to_string())];
+        for i in 0..5 {
+            chats.push(Chat::new(Role::User, format!("question {}", i)));
+            chats.push(Chat::new(Role::Assistant, format!("answer {}", i)));
+        }
+        chats.push(Chat::new(Role::User, "last question".to_string()));
+        chats
+    }
+
+    fn contents(chats: &[Chat]) -> Vec<String> {
+        chats.iter().map(|c| c.get_content().as_text()).collect()
+    }
+
+    #[test]
+    fn sliding_window_keeps_system() {
+        let chats = HistoryPolicy::new(TrimStrategy::SlidingWindow(3)).apply(&conversation());
+        assert_eq!(
+            contents(&chats),
+            vec!["You are a cat", "question 4", "answer 4", "last question"]
+        );
+        let chats = HistoryPolicy::new(TrimStrategy::SlidingWindow(3))
+            .keep_system(false)
+            .apply(&conversation());
+        assert_eq!(
+            contents(&chats),
+            vec!["question 4", "answer 4", "last question"]
+        );
+    }
+
+    #[test]
+    fn keep_first_and_last() {
+        let chats = HistoryPolicy::new(TrimStrategy::KeepFirstLast { first: 2, last: 2 })
+            .apply(&conversation());
+        assert_eq!(
+            contents(&chats),
+            vec![
+                "You are a cat",
+                "question 0",
+                "answer 0",
+                "answer 4",
+                "last question"
+            ]
+        );
+    }
+
+    #[test]
+    fn token_budget() {
+        let chats = conversation();
+        let all = HistoryPolicy::default().apply(&chats);
+        assert_eq!(all.len(), chats.len());
+        let budget = estimate_chat_tokens(&chats[0])
+            + estimate_chat_tokens(&chats[10])
+            + estimate_chat_tokens(&chats[11]);
+        let trimmed = HistoryPolicy::new(TrimStrategy::TokenBudget(budget)).apply(&chats);
+        assert_eq!(
+            contents(&trimmed),
+            vec!["You are a cat", "answer 4", "last question"]
+        );
+    }
+
+    #[test]
+    fn estimate_mixed_text() {
+        assert_eq!(estimate_tokens("abcd"), 1);
+        assert_eq!(estimate_tokens("你好"), 2);
+    }
+
+    #[test]
+    fn split_keeps_system_and_recent() {
+        let chats = conversation();
+        let (head, older, recent) = split_for_compaction(&chats, 3).unwrap();
+        assert_eq!(contents(&head), vec!["You are a cat"]);
+        assert_eq!(older.len(), 8);
+        assert_eq!(
+            contents(&recent),
+            vec!["question 4", "answer 4", "last question"]
+        );
+        assert!(split_for_compaction(&chats, 11).is_none());
+        assert_eq!(
+            transcript(&older[..2]),
+            "user: question 0\nassistant: answer 0"
+        );
+    }
+
+    #[test]
+    fn pinned_chats_are_kept() {
+        let mut chats = conversation();
+        chats.insert(1, Chat::new(Role::User, "example".to_string()).pinned(true));
+        let trimmed = HistoryPolicy::new(TrimStrategy::SlidingWindow(1)).apply(&chats);
+        assert_eq!(
+            contents(&trimmed),
+            vec!["You are a cat", "example", "last question"]
+        );
+        let (head, _, _) = split_for_compaction(&chats, 1).unwrap();
+        assert_eq!(contents(&head), vec!["You are a cat", "example"]);
+
+        // a previous summary is summarized again instead of piling up in the head
+        let summary = format!("{}\nthe user asked 5 questions", SUMMARY_HEADER);
+        chats.insert(2, Chat::new(Role::System, summary.clone()));
+        let (head, older, _) = split_for_compaction(&chats, 1).unwrap();
+        assert_eq!(contents(&head), vec!["You are a cat", "example"]);
+        assert_eq!(contents(&older)[0], summary);
+    }
+
+    #[test]
+    fn tool_messages_are_kept_with_their_call() {
+        let mut chats = conversation();
+        chats.push(Chat::new(Role::Assistant, String::new()));
+        chats.push(Chat::new(Role::Tool, "result 1".to_string()));
+        chats.push(Chat::new(Role::Tool, "result 2".to_string()));
+        let trimmed = HistoryPolicy::new(TrimStrategy::SlidingWindow(1)).apply(&chats);
+        assert_eq!(
+            contents(&trimmed),
+            vec!["You are a cat", "", "result 1", "result 2"]
+        );
+        // the recent chats start at the assistant message calling the tools
+        let (_, older, recent) = split_for_compaction(&chats, 2).unwrap();
+        assert_eq!(contents(&recent), vec!["", "result 1", "result 2"]);
+        assert_eq!(contents(&older).last().unwrap(), "last question");
+        chats.truncate(1);
+        chats.push(Chat::new(Role::Assistant, String::new()));
+        chats.push(Chat::new(Role::Tool, "result".to_string()));
+        assert!(split_for_compaction(&chats, 1).is_none());
+    }
+}
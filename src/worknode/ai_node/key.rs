@@ -0,0 +1,426 @@
+//! # Key
+//!
+//! This module reads the api keys of the AI services, the embeddings and the rerank apis from
+//! where they are kept, so several providers can each have a key of their own.
+//!
+//! A `KeySource` is one of:
+//! - env: the environment variable of the name, like `DEEPSEEK_API_KEY`.
+//! - file: the file of the path. The whitespaces around the key, like the last newline, are
+//!   removed.
+//! - literal: the key itself.
+//! - command: the output of a program, like `pass show deepseek`, without the whitespaces
+//!   around it. The program must exit with success.
+//! - keyring: the keyring of the OS, by a service and a user, so the key isn't left in a
+//!   plain text file. The key is put in the keyring with `store_in_keyring`. It needs the
+//!   `keyring` feature, which is on by default.
+//! - secret: the secret of a secrets manager, like Vault, by the name of its provider in a
+//!   [`SecretsRegistry`] (see [`super::secrets`]). It is only read by `resolve_with`.
+//! - first_of: the sources in order. The key of the first source that has one is used, so a
+//!   key may be read from the environment and else from a file.
+//!
+//! A key that is read is an `ApiKey`, which is wiped from the memory when it is dropped, and
+//! which `Debug` never prints, so a client or a config can be logged without leaking it.
+
+use super::secrets::SecretsRegistry;
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use zeroize::Zeroizing;
+
+use std::path::PathBuf;
+use std::process::Command;
+
+#[derive(Clone, PartialEq, Eq)]
+/// The struct of an api key. It is wiped from the memory when it is dropped, and it is
+/// redacted by `Debug`.
+pub struct ApiKey(Zeroizing<String>);
+
+impl ApiKey {
+    /// Create a new ApiKey.
+    pub fn new(key: String) -> Self {
+        ApiKey(Zeroizing::new(key))
+    }
+    /// Get the key itself, to send it to the api.
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+    /// Whether the key is empty.
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl From<String> for ApiKey {
+    fn from(key: String) -> Self {
+        ApiKey::new(key)
+    }
+}
+
+impl From<&str> for ApiKey {
+    fn from(key: &str) -> Self {
+        ApiKey::new(key.to_string())
+    }
+}
+
+impl std::fmt::Debug for ApiKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("ApiKey(<redacted>)")
+    }
+}
+
+impl Serialize for ApiKey {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.expose())
+    }
+}
+
+impl<'de> Deserialize<'de> for ApiKey {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer).map(ApiKey::new)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+/// The enum of where an api key is read from.
+pub enum KeySource {
+    /// The environment variable of the name.
+    Env(String),
+    /// The file of the path.
+    File(PathBuf),
+    /// The key itself.
+    Literal(ApiKey),
+    /// The output of a program.
+    Command {
+        /// The program.
+        program: String,
+        /// The arguments of the program.
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        args: Vec<String>,
+    },
+    /// The entry of the keyring of the OS.
+    Keyring {
+        /// The service of the entry, like `aipilot`.
+        service: String,
+        /// The user of the entry, like the name of the provider.
+        user: String,
+    },
+    /// The secret of a secrets provider.
+    Secret {
+        /// The name of the provider in the registry.
+        provider: String,
+        /// The name of the secret, like `ai/deepseek#api_key`.
+        name: String,
+    },
+    /// The first of the sources that has a key.
+    FirstOf(Vec<KeySource>),
+}
+
+impl KeySource {
+    /// Create a new source of the environment variable.
+    pub fn env(name: &str) -> Self {
+        KeySource::Env(name.to_string())
+    }
+    /// Create a new source of the file.
+    pub fn file<P: Into<PathBuf>>(path: P) -> Self {
+        KeySource::File(path.into())
+    }
+    /// Create a new source of the output of the program.
+    pub fn command(program: &str, args: Vec<String>) -> Self {
+        KeySource::Command {
+            program: program.to_string(),
+            args,
+        }
+    }
+    /// Create a new source of the entry of the keyring.
+    pub fn keyring(service: &str, user: &str) -> Self {
+        KeySource::Keyring {
+            service: service.to_string(),
+            user: user.to_string(),
+        }
+    }
+    /// Create a new source of the secret of the provider.
+    pub fn secret(provider: &str, name: &str) -> Self {
+        KeySource::Secret {
+            provider: provider.to_string(),
+            name: name.to_string(),
+        }
+    }
+    /// Read the key. The error is a message of why it can't be read. A secret can't be read
+    /// without its provider, see `resolve_with`.
+    pub fn resolve(&self) -> Result<ApiKey, String> {
+        self.resolve_with(&SecretsRegistry::new())
+    }
+    /// Read the key, with the secrets of the providers of the registry.
+    pub fn resolve_with(&self, secrets: &SecretsRegistry) -> Result<ApiKey, String> {
+        let key = match self {
+            KeySource::Env(name) => std::env::var(name)
+                .map_err(|_| format!("Environment variable {} not found.", name))?
+                .into(),
+            KeySource::File(path) => {
+                let text = Zeroizing::new(std::fs::read_to_string(path).map_err(|e| {
+                    format!("Can't read the api key file {}. {}", path.display(), e)
+                })?);
+                text.trim().into()
+            }
+            KeySource::Literal(key) => key.clone(),
+            KeySource::Command { program, args } => {
+                let output = Command::new(program)
+                    .args(args)
+                    .output()
+                    .map_err(|e| format!("Failed to run {}. {}", program, e))?;
+                if !output.status.success() {
+                    return Err(format!("{} exited with {}.", program, output.status));
+                }
+                let stdout = Zeroizing::new(output.stdout);
+                String::from_utf8_lossy(&stdout).trim().into()
+            }
+            KeySource::Keyring { service, user } => read_keyring(service, user)?.into(),
+            KeySource::Secret { provider, name } => secrets.get_secret(provider, name)?.into(),
+            KeySource::FirstOf(sources) => {
+                let mut errors = Vec::new();
+                for source in sources {
+                    match source.resolve_with(secrets) {
+                        Ok(key) => return Ok(key),
+                        Err(e) => errors.push(e),
+                    }
+                }
+                return Err(if errors.is_empty() {
+                    "No source of the api key is given.".to_string()
+                } else {
+                    errors.join(" ")
+                });
+            }
+        };
+        if key.is_empty() {
+            return Err(format!("The api key of {} is empty.", self));
+        }
+        Ok(key)
+    }
+}
+
+impl std::fmt::Display for KeySource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            KeySource::Env(name) => write!(f, "the environment variable {}", name),
+            KeySource::File(path) => write!(f, "the file {}", path.display()),
+            KeySource::Literal(_) => write!(f, "the literal"),
+            KeySource::Command { program, .. } => write!(f, "the command {}", program),
+            KeySource::Keyring { service, user } => {
+                write!(f, "the keyring entry {} of {}", user, service)
+            }
+            KeySource::Secret { provider, name } => {
+                write!(f, "the secret {} of {}", name, provider)
+            }
+            KeySource::FirstOf(_) => write!(f, "the sources"),
+        }
+    }
+}
+
+/// Put the key in the keyring of the OS, as the entry of the service and the user. An entry
+/// that is already there is replaced. The error is a message of why it can't be stored.
+#[cfg(feature = "keyring")]
+pub fn store_in_keyring(service: &str, user: &str, key: &str) -> Result<(), String> {
+    keyring::Entry::new(service, user)
+        .and_then(|entry| entry.set_password(key))
+        .map_err(|e| keyring_error("store", service, user, e))
+}
+
+/// Remove the key of the service and the user from the keyring of the OS.
+#[cfg(feature = "keyring")]
+pub fn delete_from_keyring(service: &str, user: &str) -> Result<(), String> {
+    keyring::Entry::new(service, user)
+        .and_then(|entry| entry.delete_credential())
+        .map_err(|e| keyring_error("delete", service, user, e))
+}
+
+/// Read the key of the service and the user from the keyring of the OS.
+#[cfg(feature = "keyring")]
+fn read_keyring(service: &str, user: &str) -> Result<String, String> {
+    keyring::Entry::new(service, user)
+        .and_then(|entry| entry.get_password())
+        .map_err(|e| keyring_error("read", service, user, e))
+}
+
+#[cfg(not(feature = "keyring"))]
+fn read_keyring(service: &str, user: &str) -> Result<String, String> {
+    Err(format!(
+        "Can't read the keyring entry {} of {}, the keyring feature is off.",
+        user, service
+    ))
+}
+
+/// The message of a keyring error.
+#[cfg(feature = "keyring")]
+fn keyring_error(action: &str, service: &str, user: &str, e: keyring::Error) -> String {
+    format!(
+        "Failed to {} the keyring entry {} of {}. {}",
+        action, user, service, e
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::deepseek::{DeepSeekClient, DeepSeekModel, DEEPSEEK_API_URL};
+    use super::*;
+
+    use uuid::Uuid;
+
+    #[cfg(feature = "keyring")]
+    use std::collections::HashMap;
+    #[cfg(feature = "keyring")]
+    use std::sync::{Arc, Mutex};
+
+    /// The keys of a keyring in memory, by their service and user.
+    #[cfg(feature = "keyring")]
+    type MemoryKeys = Arc<Mutex<HashMap<(String, String), Vec<u8>>>>;
+
+    /// A keyring in memory, which keeps the keys between the entries unlike the mock keyring.
+    #[cfg(feature = "keyring")]
+    #[derive(Debug, Default)]
+    struct MemoryKeyring(MemoryKeys);
+
+    /// An entry of the keyring in memory.
+    #[cfg(feature = "keyring")]
+    #[derive(Debug)]
+    struct MemoryEntry {
+        keys: MemoryKeys,
+        entry: (String, String),
+    }
+
+    #[cfg(feature = "keyring")]
+    impl keyring::credential::CredentialApi for MemoryEntry {
+        fn set_secret(&self, secret: &[u8]) -> keyring::Result<()> {
+            let mut keys = self.keys.lock().unwrap();
+            keys.insert(self.entry.clone(), secret.to_vec());
+            Ok(())
+        }
+        fn get_secret(&self) -> keyring::Result<Vec<u8>> {
+            let keys = self.keys.lock().unwrap();
+            keys.get(&self.entry)
+                .cloned()
+                .ok_or(keyring::Error::NoEntry)
+        }
+        fn delete_credential(&self) -> keyring::Result<()> {
+            let mut keys = self.keys.lock().unwrap();
+            keys.remove(&self.entry)
+                .map(|_| ())
+                .ok_or(keyring::Error::NoEntry)
+        }
+        fn as_any(&self) -> &dyn std::any::Any {
+            self
+        }
+    }
+
+    #[cfg(feature = "keyring")]
+    impl keyring::credential::CredentialBuilderApi for MemoryKeyring {
+        fn build(
+            &self,
+            _target: Option<&str>,
+            service: &str,
+            user: &str,
+        ) -> keyring::Result<Box<keyring::Credential>> {
+            Ok(Box::new(MemoryEntry {
+                keys: self.0.clone(),
+                entry: (service.to_string(), user.to_string()),
+            }))
+        }
+        fn as_any(&self) -> &dyn std::any::Any {
+            self
+        }
+    }
+
+    #[test]
+    fn resolve_sources() {
+        let path = std::env::temp_dir().join(format!("aipilot-key-{}.txt", Uuid::new_v4()));
+        std::fs::write(&path, "  sk-file\n").unwrap();
+        assert_eq!(
+            KeySource::file(&path).resolve().unwrap().expose(),
+            "sk-file"
+        );
+        std::fs::remove_file(&path).unwrap();
+        assert!(KeySource::file(&path).resolve().is_err());
+
+        std::env::set_var("AIPILOT_KEY_TEST", "sk-env");
+        assert_eq!(
+            KeySource::env("AIPILOT_KEY_TEST").resolve().unwrap(),
+            ApiKey::from("sk-env")
+        );
+        assert_eq!(
+            KeySource::command("echo", vec!["sk-command".to_string()])
+                .resolve()
+                .unwrap(),
+            ApiKey::from("sk-command")
+        );
+        assert!(KeySource::command("false", Vec::new()).resolve().is_err());
+        assert!(KeySource::Literal(ApiKey::from("")).resolve().is_err());
+
+        // the first source that has a key is used
+        let chain = KeySource::FirstOf(vec![
+            KeySource::env("AIPILOT_KEY_TEST_UNSET"),
+            KeySource::file(&path),
+            KeySource::Literal("sk-literal".into()),
+            KeySource::env("AIPILOT_KEY_TEST"),
+        ]);
+        assert_eq!(chain.resolve().unwrap().expose(), "sk-literal");
+        assert_eq!(
+            format!("{:?}", chain.resolve().unwrap()),
+            "ApiKey(<redacted>)"
+        );
+        assert!(!format!("{:?}", chain).contains("sk-literal"));
+        let literal = KeySource::Literal("sk-literal".into());
+        assert_eq!(format!("{:?}", literal), "Literal(ApiKey(<redacted>))");
+        let client = DeepSeekClient::new(DEEPSEEK_API_URL, DeepSeekModel::DeepseekChat)
+            .api_key_from(&literal)
+            .unwrap();
+        assert!(!format!("{:?}", client).contains("sk-literal"));
+        assert!(!format!("{:#?}", client).contains("sk-literal"));
+        let error = KeySource::FirstOf(vec![
+            KeySource::env("AIPILOT_KEY_TEST_UNSET"),
+            KeySource::file(&path),
+        ])
+        .resolve()
+        .unwrap_err();
+        assert!(error.contains("AIPILOT_KEY_TEST_UNSET") && error.contains("api key file"));
+
+        let keyring = KeySource::keyring("aipilot-test", "deepseek");
+        #[cfg(feature = "keyring")]
+        {
+            keyring::set_default_credential_builder(Box::new(MemoryKeyring::default()));
+            store_in_keyring("aipilot-test", "deepseek", "sk-keyring").unwrap();
+            assert_eq!(keyring.resolve().unwrap().expose(), "sk-keyring");
+            let client = DeepSeekClient::new(DEEPSEEK_API_URL, DeepSeekModel::DeepseekChat)
+                .api_key_from_keyring("aipilot-test", "deepseek")
+                .unwrap();
+            assert_eq!(client.get_api_key(), Some("sk-keyring"));
+            // a stored key replaces the old one
+            store_in_keyring("aipilot-test", "deepseek", "sk-new").unwrap();
+            assert_eq!(keyring.resolve().unwrap().expose(), "sk-new");
+            delete_from_keyring("aipilot-test", "deepseek").unwrap();
+            let error = keyring.resolve().unwrap_err();
+            assert!(error.starts_with("Failed to read the keyring entry deepseek of aipilot-test"));
+            assert!(delete_from_keyring("aipilot-test", "deepseek").is_err());
+            let chain = KeySource::FirstOf(vec![
+                keyring.clone(),
+                KeySource::Literal("sk-literal".into()),
+            ]);
+            assert_eq!(chain.resolve().unwrap().expose(), "sk-literal");
+        }
+        #[cfg(not(feature = "keyring"))]
+        assert!(keyring.resolve().is_err());
+
+        let source: KeySource = toml::from_str::<toml::Value>(
+            "key = { first_of = [{ env = \"A\" }, { command = { program = \"pass\", args = [\"ai\"] } }] }",
+        )
+        .unwrap()["key"]
+            .clone()
+            .try_into()
+            .unwrap();
+        assert_eq!(
+            source,
+            KeySource::FirstOf(vec![
+                KeySource::env("A"),
+                KeySource::command("pass", vec!["ai".to_string()])
+            ])
+        );
+    }
+}
@@ -0,0 +1,757 @@
+//! # MCP
+//!
+//! This module connects the AI nodes to the servers of the Model Context Protocol, so their
+//! tools can be called by the AI service without writing a tool for each of them.
+//!
+//! A `McpClient` talks json-rpc to a server over one of the transports:
+//! 1. stdio: the server is a process started by the client, and the messages are the lines
+//!    of its standard input and output.
+//! 2. sse: the server is an http endpoint. The messages of the server are the events of a
+//!    stream, whose first `endpoint` event is the url the messages of the client are posted to.
+//!
+//! On connection, the client initializes the session. Then it lists the tools and the
+//! resources of the server, calls the tools and reads the resources. `tools` gives the tools
+//! of the server as a `ToolRegistry` for an AI node or an agent, and, when the server has
+//! resources, a `read_resource` tool that reads them. The names of the tools can be prefixed,
+//! so the tools of several servers don't collide.
+//!
+//! The server process is killed when the last clone of the client is dropped.
+
+use super::tool::ToolRegistry;
+use crate::error::ai_node_error::{AINodeError, AINodeErrorType, AINodeResult};
+
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::process::{Child, ChildStdin, Command};
+use tokio::sync::oneshot;
+use tokio::task::JoinHandle;
+
+use std::collections::HashMap;
+use std::process::Stdio;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, OnceLock, PoisonError};
+use std::time::Duration;
+
+/// The version of the protocol the client speaks.
+const PROTOCOL_VERSION: &str = "2024-11-05";
+
+/// The json-rpc code of an unknown method.
+const METHOD_NOT_FOUND: i64 = -32601;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+/// The enum of how the client talks to the server.
+pub enum McpTransport {
+    /// Start the server process and talk over its standard input and output.
+    Stdio {
+        /// The program of the server.
+        command: String,
+        /// The arguments of the program.
+        #[serde(default)]
+        args: Vec<String>,
+        /// The environment variables set for the server.
+        #[serde(default)]
+        env: HashMap<String, String>,
+    },
+    /// Receive the events of the server at the url, and post the messages to its endpoint.
+    Sse {
+        /// The url of the event stream.
+        url: String,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+/// The struct of a tool of a server.
+pub struct McpTool {
+    /// The name of the tool.
+    pub name: String,
+    /// What the tool does.
+    #[serde(default)]
+    pub description: String,
+    /// The json schema of the arguments.
+    #[serde(default)]
+    pub input_schema: Value,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+/// The struct of a resource of a server.
+pub struct McpResource {
+    /// The uri of the resource.
+    pub uri: String,
+    /// The name of the resource.
+    #[serde(default)]
+    pub name: String,
+    /// What the resource is.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// The mime type of the resource.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mime_type: Option<String>,
+}
+
+/// The enum of where the messages of the client are written.
+enum Writer {
+    /// The standard input of the server process.
+    Stdio(tokio::sync::Mutex<ChildStdin>),
+    /// The endpoint given by the first event of the stream.
+    Sse {
+        client: reqwest::Client,
+        endpoint: OnceLock<String>,
+    },
+}
+
+/// The state shared by the client and the task that reads the messages of the server.
+struct Shared {
+    /// Where the messages are written.
+    writer: Writer,
+    /// The requests waiting for their response, by id.
+    pending: Mutex<HashMap<u64, oneshot::Sender<Value>>>,
+    /// The id of the next request.
+    next_id: AtomicU64,
+    /// Whether the server closed the connection.
+    closed: AtomicBool,
+}
+
+impl Shared {
+    /// Write a message to the server.
+    async fn send(&self, message: &Value) -> AINodeResult<()> {
+        match &self.writer {
+            Writer::Stdio(stdin) => {
+                let mut stdin = stdin.lock().await;
+                let line = format!("{}\n", message);
+                stdin
+                    .write_all(line.as_bytes())
+                    .await
+                    .map_err(|e| mcp_error(format!("Failed to write to the MCP server. {}", e)))?;
+                stdin
+                    .flush()
+                    .await
+                    .map_err(|e| mcp_error(format!("Failed to write to the MCP server. {}", e)))
+            }
+            Writer::Sse { client, endpoint } => {
+                let endpoint = endpoint.get().ok_or_else(|| {
+                    mcp_error("The MCP server has not given its endpoint.".to_string())
+                })?;
+                let response = client
+                    .post(endpoint)
+                    .header(reqwest::header::CONTENT_TYPE, "application/json")
+                    .body(message.to_string())
+                    .send()
+                    .await
+                    .map_err(|e| mcp_error(format!("Failed to post to the MCP server. {}", e)))?;
+                match response.status().is_success() {
+                    true => Ok(()),
+                    false => Err(mcp_error(format!(
+                        "Failed to post to the MCP server. The status is {}.",
+                        response.status()
+                    ))),
+                }
+            }
+        }
+    }
+    /// Handle a message of the server: give a response to its request, or answer a request
+    /// of the server.
+    async fn dispatch(&self, message: Value) {
+        let id = message.get("id").cloned();
+        match (message.get("method").and_then(Value::as_str), id) {
+            // a request of the server, only pings are supported
+            (Some(method), Some(id)) => {
+                let answer = match method {
+                    "ping" => json!({ "jsonrpc": "2.0", "id": id, "result": {} }),
+                    _ => json!({
+                        "jsonrpc": "2.0",
+                        "id": id,
+                        "error": {
+                            "code": METHOD_NOT_FOUND,
+                            "message": format!("The method {} is not supported.", method),
+                        },
+                    }),
+                };
+                // the server stops waiting if the answer can't be written
+                let _ = self.send(&answer).await;
+            }
+            // a notification of the server, like a progress or a log, is ignored
+            (Some(_), None) => {}
+            (None, Some(id)) => {
+                let sender = id.as_u64().and_then(|id| {
+                    self.pending
+                        .lock()
+                        .unwrap_or_else(PoisonError::into_inner)
+                        .remove(&id)
+                });
+                if let Some(sender) = sender {
+                    // the request may have timed out
+                    let _ = sender.send(message);
+                }
+            }
+            (None, None) => {}
+        }
+    }
+}
+
+/// The struct that stops the server when the last clone of the client is dropped.
+struct Connection {
+    /// The task that reads the messages of the server.
+    reader: JoinHandle<()>,
+    /// The server process of the stdio transport, killed on drop.
+    _child: Option<Child>,
+}
+
+impl Drop for Connection {
+    fn drop(&mut self) {
+        self.reader.abort();
+    }
+}
+
+#[derive(Clone)]
+/// The struct of the client of an MCP server.
+pub struct McpClient {
+    /// The state shared with the reader task.
+    shared: Arc<Shared>,
+    /// The connection to the server.
+    _connection: Arc<Connection>,
+    /// The transport, kept to describe the client.
+    transport: McpTransport,
+    /// The result of the initialization, with the `serverInfo` and the `capabilities`.
+    server: Value,
+    /// How long a request waits for its response.
+    timeout: Duration,
+    /// The prefix of the names of the tools.
+    prefix: String,
+}
+
+impl std::fmt::Debug for McpClient {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("McpClient")
+            .field("transport", &self.transport)
+            .field("server", &self.server)
+            .field("timeout", &self.timeout)
+            .field("prefix", &self.prefix)
+            .finish()
+    }
+}
+
+impl McpClient {
+    /// Connect to the server and initialize the session.
+    pub async fn connect(transport: McpTransport) -> AINodeResult<Self> {
+        let timeout = Self::default_timeout();
+        let shared;
+        let connection = match &transport {
+            McpTransport::Stdio { command, args, env } => {
+                let mut child = Command::new(command)
+                    .args(args)
+                    .envs(env)
+                    .stdin(Stdio::piped())
+                    .stdout(Stdio::piped())
+                    .stderr(Stdio::inherit())
+                    .kill_on_drop(true)
+                    .spawn()
+                    .map_err(|e| {
+                        mcp_error(format!("Failed to start the MCP server {}. {}", command, e))
+                    })?;
+                // the pipes were just requested, so they are there
+                let stdin = child.stdin.take().unwrap();
+                let stdout = child.stdout.take().unwrap();
+                shared = Arc::new(Shared::new(Writer::Stdio(tokio::sync::Mutex::new(stdin))));
+                let reader = tokio::spawn(read_lines(shared.clone(), stdout));
+                Connection {
+                    reader,
+                    _child: Some(child),
+                }
+            }
+            McpTransport::Sse { url } => {
+                let client = reqwest::Client::new();
+                let response = client
+                    .get(url)
+                    .header(reqwest::header::ACCEPT, "text/event-stream")
+                    .send()
+                    .await
+                    .map_err(|e| {
+                        mcp_error(format!(
+                            "Failed to connect to the MCP server {}. {}",
+                            url, e
+                        ))
+                    })?;
+                if !response.status().is_success() {
+                    return Err(mcp_error(format!(
+                        "Failed to connect to the MCP server {}. The status is {}.",
+                        url,
+                        response.status()
+                    )));
+                }
+                let base = response.url().clone();
+                shared = Arc::new(Shared::new(Writer::Sse {
+                    client,
+                    endpoint: OnceLock::new(),
+                }));
+                let (ready, endpoint) = oneshot::channel();
+                let reader = tokio::spawn(read_events(shared.clone(), response, base, ready));
+                let connection = Connection {
+                    reader,
+                    _child: None,
+                };
+                match tokio::time::timeout(timeout, endpoint).await {
+                    Ok(Ok(())) => {}
+                    _ => {
+                        return Err(mcp_error(format!(
+                            "The MCP server {} has not given its endpoint.",
+                            url
+                        )))
+                    }
+                }
+                connection
+            }
+        };
+        let mut client = McpClient {
+            shared,
+            _connection: Arc::new(connection),
+            transport,
+            server: Value::Null,
+            timeout,
+            prefix: String::new(),
+        };
+        client.server = client
+            .request(
+                "initialize",
+                json!({
+                    "protocolVersion": PROTOCOL_VERSION,
+                    "capabilities": {},
+                    "clientInfo": { "name": "aipilot", "version": env!("CARGO_PKG_VERSION") },
+                }),
+            )
+            .await?;
+        client.notify("notifications/initialized").await?;
+        Ok(client)
+    }
+    /// Send a request and wait for its result.
+    pub async fn request(&self, method: &str, params: Value) -> AINodeResult<Value> {
+        let id = self.shared.next_id.fetch_add(1, Ordering::SeqCst);
+        let (sender, receiver) = oneshot::channel();
+        self.shared
+            .pending
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .insert(id, sender);
+        if self.shared.closed.load(Ordering::SeqCst) {
+            self.forget(id);
+            return Err(mcp_error(format!(
+                "The MCP server closed the connection before {}.",
+                method
+            )));
+        }
+        let message = json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params });
+        let response = match self.shared.send(&message).await {
+            Ok(()) => tokio::time::timeout(self.timeout, receiver).await,
+            Err(e) => {
+                self.forget(id);
+                return Err(e);
+            }
+        };
+        let response = match response {
+            Ok(Ok(response)) => response,
+            Ok(Err(_)) => {
+                return Err(mcp_error(format!(
+                    "The MCP server closed the connection before answering {}.",
+                    method
+                )))
+            }
+            Err(_) => {
+                self.forget(id);
+                return Err(mcp_error(format!(
+                    "The MCP server has not answered {} in {:?}.",
+                    method, self.timeout
+                )));
+            }
+        };
+        if let Some(error) = response.get("error") {
+            return Err(mcp_error(format!(
+                "The MCP server failed {}. {}",
+                method,
+                error["message"].as_str().unwrap_or_default()
+            )));
+        }
+        Ok(response.get("result").cloned().unwrap_or(Value::Null))
+    }
+    /// Send a notification, which has no response.
+    async fn notify(&self, method: &str) -> AINodeResult<()> {
+        self.shared
+            .send(&json!({ "jsonrpc": "2.0", "method": method }))
+            .await
+    }
+    /// Stop waiting for the response of a request.
+    fn forget(&self, id: u64) {
+        self.shared
+            .pending
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .remove(&id);
+    }
+    /// Get the tools of the server.
+    pub async fn list_tools(&self) -> AINodeResult<Vec<McpTool>> {
+        self.list("tools/list", "tools").await
+    }
+    /// Get the resources of the server, none if the server has no resources.
+    pub async fn list_resources(&self) -> AINodeResult<Vec<McpResource>> {
+        if !self.has_resources() {
+            return Ok(Vec::new());
+        }
+        self.list("resources/list", "resources").await
+    }
+    /// Get all the pages of a list.
+    async fn list<T: serde::de::DeserializeOwned>(
+        &self,
+        method: &str,
+        field: &str,
+    ) -> AINodeResult<Vec<T>> {
+        let mut items = Vec::new();
+        let mut cursor: Option<String> = None;
+        loop {
+            let params = match &cursor {
+                Some(cursor) => json!({ "cursor": cursor }),
+                None => json!({}),
+            };
+            let mut result = self.request(method, params).await?;
+            let page: Vec<T> = serde_json::from_value(result[field].take()).map_err(|e| {
+                mcp_error(format!(
+                    "The answer of the MCP server to {} is not valid. {}",
+                    method, e
+                ))
+            })?;
+            items.extend(page);
+            cursor = result["nextCursor"].as_str().map(str::to_string);
+            if cursor.is_none() {
+                return Ok(items);
+            }
+        }
+    }
+    /// Call a tool of the server and get the text of its result. A result that the server
+    /// marks as an error is an `Err`.
+    pub async fn call_tool(&self, name: &str, arguments: Value) -> AINodeResult<String> {
+        let result = self
+            .request(
+                "tools/call",
+                json!({ "name": name, "arguments": arguments }),
+            )
+            .await?;
+        let text = content_text(&result["content"]);
+        match result["isError"].as_bool().unwrap_or(false) {
+            true => Err(mcp_error(text)),
+            false => Ok(text),
+        }
+    }
+    /// Read a resource of the server and get its text.
+    pub async fn read_resource(&self, uri: &str) -> AINodeResult<String> {
+        let result = self
+            .request("resources/read", json!({ "uri": uri }))
+            .await?;
+        let contents: Vec<String> = result["contents"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .map(|content| match content["text"].as_str() {
+                Some(text) => text.to_string(),
+                None => format!("[binary content of {}]", uri),
+            })
+            .collect();
+        Ok(contents.join("\n"))
+    }
+    /// Get the tools of the server, and the `read_resource` tool if it has resources, to be
+    /// called by the AI service.
+    pub async fn tools(&self) -> AINodeResult<ToolRegistry> {
+        let mut registry = ToolRegistry::new();
+        for tool in self.list_tools().await? {
+            let client = self.clone();
+            let name = tool.name.clone();
+            let parameters = match tool.input_schema {
+                Value::Object(_) => tool.input_schema,
+                _ => json!({ "type": "object", "properties": {} }),
+            };
+            registry.register(
+                &format!("{}{}", self.prefix, tool.name),
+                &tool.description,
+                parameters,
+                move |arguments| {
+                    let client = client.clone();
+                    let name = name.clone();
+                    async move {
+                        client
+                            .call_tool(&name, arguments)
+                            .await
+                            .map_err(|e| e.get_message().to_string())
+                    }
+                },
+            );
+        }
+        let resources = self.list_resources().await?;
+        if !resources.is_empty() {
+            let listed: Vec<String> = resources
+                .iter()
+                .map(|resource| match &resource.description {
+                    Some(description) => {
+                        format!("{} ({}): {}", resource.uri, resource.name, description)
+                    }
+                    None => format!("{} ({})", resource.uri, resource.name),
+                })
+                .collect();
+            let uris: Vec<&str> = resources
+                .iter()
+                .map(|resource| resource.uri.as_str())
+                .collect();
+            let client = self.clone();
+            registry.register(
+                &format!("{}read_resource", self.prefix),
+                &format!("Read a resource. The resources are:\n{}", listed.join("\n")),
+                json!({
+                    "type": "object",
+                    "properties": { "uri": { "type": "string", "enum": uris } },
+                    "required": ["uri"],
+                }),
+                move |arguments| {
+                    let client = client.clone();
+                    async move {
+                        let uri = arguments["uri"]
+                            .as_str()
+                            .ok_or_else(|| "uri should be a string".to_string())?;
+                        client
+                            .read_resource(uri)
+                            .await
+                            .map_err(|e| e.get_message().to_string())
+                    }
+                },
+            );
+        }
+        Ok(registry)
+    }
+    /// Whether the server has resources.
+    fn has_resources(&self) -> bool {
+        self.server["capabilities"]
+            .as_object()
+            .is_some_and(|capabilities| capabilities.contains_key("resources"))
+    }
+    /// Get the name and the version of the server.
+    pub fn get_server_info(&self) -> &Value {
+        &self.server["serverInfo"]
+    }
+    /// Get the transport.
+    pub fn get_transport(&self) -> &McpTransport {
+        &self.transport
+    }
+    /// Set how long a request waits for its response as builder.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+    /// Set how long a request waits for its response.
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout;
+    }
+    /// Get how long a request waits for its response.
+    pub fn get_timeout(&self) -> Duration {
+        self.timeout
+    }
+    /// The default time a request waits for its response.
+    pub fn default_timeout() -> Duration {
+        Duration::from_secs(30)
+    }
+    /// Set the prefix of the names of the tools as builder.
+    pub fn prefix(mut self, prefix: &str) -> Self {
+        self.prefix = prefix.to_string();
+        self
+    }
+    /// Set the prefix of the names of the tools.
+    pub fn set_prefix(&mut self, prefix: &str) {
+        self.prefix = prefix.to_string();
+    }
+    /// Get the prefix of the names of the tools.
+    pub fn get_prefix(&self) -> &str {
+        &self.prefix
+    }
+}
+
+impl Shared {
+    /// Mark the connection as closed, and fail the pending requests.
+    fn close(&self) {
+        self.closed.store(true, Ordering::SeqCst);
+        self.pending
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clear();
+    }
+    /// Create a new Shared without pending request.
+    fn new(writer: Writer) -> Self {
+        Shared {
+            writer,
+            pending: Mutex::new(HashMap::new()),
+            next_id: AtomicU64::new(1),
+            closed: AtomicBool::new(false),
+        }
+    }
+}
+
+/// Read the messages of the stdio transport, one json per line.
+async fn read_lines(shared: Arc<Shared>, stdout: tokio::process::ChildStdout) {
+    let mut lines = BufReader::new(stdout).lines();
+    while let Ok(Some(line)) = lines.next_line().await {
+        // the lines that are not json, like logs, are skipped
+        if let Ok(message) = serde_json::from_str::<Value>(&line) {
+            shared.dispatch(message).await;
+        }
+    }
+    shared.close();
+}
+
+/// Read the events of the sse transport. The first `endpoint` event gives the url of the
+/// messages, the `message` events are the messages.
+async fn read_events(
+    shared: Arc<Shared>,
+    mut response: reqwest::Response,
+    base: reqwest::Url,
+    ready: oneshot::Sender<()>,
+) {
+    let mut ready = Some(ready);
+    let mut buffer = String::new();
+    while let Ok(Some(bytes)) = response.chunk().await {
+        buffer.push_str(&String::from_utf8_lossy(&bytes).replace("\r\n", "\n"));
+        while let Some(end) = buffer.find("\n\n") {
+            let event: String = buffer.drain(..end + 2).collect();
+            let mut kind = "message";
+            let mut data = Vec::new();
+            for line in event.lines() {
+                if let Some(value) = line.strip_prefix("event:") {
+                    kind = value.trim();
+                } else if let Some(value) = line.strip_prefix("data:") {
+                    data.push(value.strip_prefix(' ').unwrap_or(value));
+                }
+            }
+            let data = data.join("\n");
+            match kind {
+                "endpoint" => {
+                    if let (Writer::Sse { endpoint, .. }, Ok(url)) =
+                        (&shared.writer, base.join(data.trim()))
+                    {
+                        let _ = endpoint.set(url.to_string());
+                        if let Some(ready) = ready.take() {
+                            let _ = ready.send(());
+                        }
+                    }
+                }
+                "message" => {
+                    if let Ok(message) = serde_json::from_str::<Value>(&data) {
+                        shared.dispatch(message).await;
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+    shared.close();
+}
+
+/// Get the text of the content of a tool result.
+fn content_text(content: &Value) -> String {
+    let parts: Vec<String> = content
+        .as_array()
+        .into_iter()
+        .flatten()
+        .map(|part| match part["type"].as_str() {
+            Some("text") => part["text"].as_str().unwrap_or_default().to_string(),
+            Some("resource") => match part["resource"]["text"].as_str() {
+                Some(text) => text.to_string(),
+                None => format!(
+                    "[resource {}]",
+                    part["resource"]["uri"].as_str().unwrap_or_default()
+                ),
+            },
+            Some(kind) => format!(
+                "[{} {}]",
+                kind,
+                part["mimeType"].as_str().unwrap_or_default()
+            ),
+            None => part.to_string(),
+        })
+        .collect();
+    parts.join("\n")
+}
+
+/// Create an AINodeError of the MCP client.
+fn mcp_error(message: String) -> AINodeError {
+    AINodeError::new(AINodeErrorType::McpError, message)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::worknode::ai_node::tool::ToolCall;
+
+    use tokio::runtime::Runtime;
+
+    /// A server that answers the requests of the test in order.
+    const SERVER: &str = r#"
+read l; echo '{"jsonrpc":"2.0","id":1,"result":{"protocolVersion":"2024-11-05","capabilities":{"tools":{},"resources":{}},"serverInfo":{"name":"fake","version":"1.0"}}}'
+read l
+read l; echo 'a log line'; echo '{"jsonrpc":"2.0","method":"notifications/message","params":{}}'
+echo '{"jsonrpc":"2.0","id":2,"result":{"tools":[{"name":"echo","description":"Echo the text","inputSchema":{"type":"object","properties":{"text":{"type":"string"}}}}],"nextCursor":"2"}}'
+read l; echo '{"jsonrpc":"2.0","id":3,"result":{"tools":[{"name":"fail"}]}}'
+read l; echo '{"jsonrpc":"2.0","id":4,"result":{"resources":[{"uri":"file:///readme","name":"readme"}]}}'
+read l; case "$l" in *'"arguments":{"text":"hi"}'*) t=hi;; *) t=wrong;; esac
+echo '{"jsonrpc":"2.0","id":5,"result":{"content":[{"type":"text","text":"'$t'"}]}}'
+read l; echo '{"jsonrpc":"2.0","id":6,"result":{"content":[{"type":"text","text":"it failed"}],"isError":true}}'
+read l; echo '{"jsonrpc":"2.0","id":7,"result":{"contents":[{"uri":"file:///readme","text":"Read me"}]}}'
+read l; echo '{"jsonrpc":"2.0","id":8,"error":{"code":-32601,"message":"unknown method"}}'
+"#;
+
+    fn call(name: &str, arguments: &str) -> ToolCall {
+        ToolCall {
+            id: "call_0".to_string(),
+            name: name.to_string(),
+            arguments: arguments.to_string(),
+        }
+    }
+
+    #[test]
+    fn stdio_server_tools() {
+        let rt = Runtime::new().unwrap();
+        rt.block_on(async {
+            let client = McpClient::connect(McpTransport::Stdio {
+                command: "sh".to_string(),
+                args: vec!["-c".to_string(), SERVER.to_string()],
+                env: HashMap::new(),
+            })
+            .await
+            .unwrap()
+            .prefix("fake_");
+            assert_eq!(client.get_server_info()["name"], "fake");
+
+            let tools = client.tools().await.unwrap();
+            assert_eq!(
+                tools.names(),
+                ["fake_echo", "fake_fail", "fake_read_resource"]
+            );
+            assert_eq!(
+                tools.get("fake_fail").unwrap().get_parameters()["type"],
+                "object"
+            );
+            assert_eq!(
+                tools.call(&call("fake_echo", r#"{"text": "hi"}"#)).await,
+                "hi"
+            );
+            assert_eq!(
+                tools.call(&call("fake_fail", "{}")).await,
+                "Error: it failed"
+            );
+            assert_eq!(
+                tools
+                    .call(&call("fake_read_resource", r#"{"uri": "file:///readme"}"#))
+                    .await,
+                "Read me"
+            );
+            let error = client.request("prompts/list", json!({})).await.unwrap_err();
+            assert!(error.get_message().contains("unknown method"));
+            // the server exited
+            assert!(client.list_tools().await.is_err());
+        });
+    }
+}
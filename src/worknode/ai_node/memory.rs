@@ -0,0 +1,693 @@
+//! # Memory
+//!
+//! This module gives an AI node a long-term memory, so an assistant remembers what it learned
+//! about the user in past conversations, across sessions and restarts.
+//!
+//! After each answer, an AI service is asked to extract the salient facts of the exchange,
+//! like the preferences, the decisions or the details of the user, as a json array of short
+//! sentences rated by importance. The facts are embedded (see [`super::embedding`]) and
+//! upserted in a vector store (see [`crate::vector_store`]), with an id derived from the
+//! namespace and the fact, so a fact learned twice is stored once. Before each request, the
+//! facts most similar to the input are recalled and put before the prompt prefix.
+//!
+//! The namespace, like the id of a user, is kept in the metadata of the facts, and only the
+//! facts of the namespace of the memory are recalled, so the memories of users sharing one
+//! store don't mix.
+//!
+//! A `RetentionPolicy` keeps the memory of a long-lived assistant from growing without bound
+//! or going stale. A fact is used when it is learned or recalled. The facts unused for longer
+//! than the ttl are forgotten, and over the max number of facts, those of the lowest retention,
+//! their importance halved every half-life since their last use, are forgotten first. Every
+//! given number of facts learned, the facts of the namespace are consolidated: the AI service
+//! merges the duplicates and drops the outdated ones.
+
+use super::embedding::EmbeddingClient;
+use super::history::transcript;
+use super::{AIService, Chat, RequestOverrides, Role};
+use crate::error::ai_node_error::{AINodeError, AINodeErrorType, AINodeResult};
+use crate::error::vector_store_error::VectorStoreError;
+use crate::vector_store::{Document, Filter, VectorStore};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use sha2::{Digest, Sha256};
+
+use std::sync::Arc;
+use std::time::Duration;
+
+/// The prompt to ask the AI service to extract the facts of a conversation.
+const EXTRACTION_PROMPT: &str = "Extract the facts worth remembering in later conversations \
+from the following exchange between a user and an assistant: the preferences, the decisions, \
+the plans and the personal details of the user. Write each fact as a short sentence that \
+makes sense on its own, and rate how important it is to remember from 1 to 10. Answer with a \
+json array of {\"fact\": ..., \"importance\": ...} objects only, or [] if there is none.";
+
+/// The prompt to ask the AI service to consolidate the facts of a memory.
+const CONSOLIDATION_PROMPT: &str = "The following facts were remembered about a user, the \
+oldest first. Merge the facts that say the same thing, drop the facts contradicted by a newer \
+one, and keep every other fact as it is. Rate how important each fact is to remember from 1 \
+to 10. Answer with a json array of {\"fact\": ..., \"importance\": ...} objects only.";
+
+/// The title of the memories put before the prompt prefix.
+const MEMORIES_TITLE: &str = "Relevant memories from earlier conversations:";
+
+/// The importance of a fact that is not rated, from 0 to 1.
+const DEFAULT_IMPORTANCE: f32 = 0.5;
+
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+#[serde(default)]
+/// The struct of the policy to forget the facts of a memory. The default policy keeps every
+/// fact forever.
+pub struct RetentionPolicy {
+    /// The max number of facts kept in the namespace, or `None` for no limit.
+    max_items: Option<usize>,
+    /// How long a fact is kept after its last use, or `None` to keep it forever.
+    ttl: Option<Duration>,
+    /// The time for the retention of a fact to halve since its last use, or `None` for a
+    /// retention that is the importance of the fact.
+    half_life: Option<Duration>,
+    /// The number of facts learned between two consolidations, or `None` to never
+    /// consolidate.
+    consolidate_every: Option<usize>,
+}
+
+impl RetentionPolicy {
+    /// Create a new RetentionPolicy that keeps every fact forever.
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Set the max number of facts as builder.
+    pub fn max_items(mut self, max_items: Option<usize>) -> Self {
+        self.max_items = max_items;
+        self
+    }
+    /// Set the ttl as builder.
+    pub fn ttl(mut self, ttl: Option<Duration>) -> Self {
+        self.ttl = ttl;
+        self
+    }
+    /// Set the half-life as builder.
+    pub fn half_life(mut self, half_life: Option<Duration>) -> Self {
+        self.half_life = half_life;
+        self
+    }
+    /// Set the number of facts learned between two consolidations as builder.
+    pub fn consolidate_every(mut self, consolidate_every: Option<usize>) -> Self {
+        self.consolidate_every = consolidate_every;
+        self
+    }
+    /// Get the max number of facts.
+    pub fn get_max_items(&self) -> Option<usize> {
+        self.max_items
+    }
+    /// Get the ttl.
+    pub fn get_ttl(&self) -> Option<Duration> {
+        self.ttl
+    }
+    /// Get the half-life.
+    pub fn get_half_life(&self) -> Option<Duration> {
+        self.half_life
+    }
+    /// Get the number of facts learned between two consolidations.
+    pub fn get_consolidate_every(&self) -> Option<usize> {
+        self.consolidate_every
+    }
+    /// Whether the fact of the metadata is unused for longer than the ttl.
+    pub fn is_expired(&self, metadata: &Map<String, Value>, now: DateTime<Utc>) -> bool {
+        self.ttl.is_some_and(|ttl| idle_time(metadata, now) > ttl)
+    }
+    /// Get the retention of the fact of the metadata, its importance decayed since its last
+    /// use.
+    pub fn retention(&self, metadata: &Map<String, Value>, now: DateTime<Utc>) -> f32 {
+        let importance = metadata
+            .get("importance")
+            .and_then(Value::as_f64)
+            .map_or(DEFAULT_IMPORTANCE, |importance| importance as f32);
+        match self.half_life {
+            Some(half_life) if !half_life.is_zero() => {
+                let halvings = idle_time(metadata, now).as_secs_f32() / half_life.as_secs_f32();
+                importance * 0.5f32.powf(halvings)
+            }
+            _ => importance,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+/// The struct of the long-term memory of an AI node.
+pub struct Memory {
+    /// The client that embeds the facts and the inputs.
+    embedder: EmbeddingClient,
+    /// The store of the facts.
+    store: Arc<dyn VectorStore>,
+    /// The AI service that extracts the facts.
+    extractor: AIService,
+    /// The namespace of the facts, or `None` for the facts without namespace.
+    namespace: Option<String>,
+    /// The max number of facts recalled.
+    top_k: usize,
+    /// The lowest score of a fact recalled, or `None` for no threshold.
+    min_score: Option<f32>,
+    /// The policy to forget the facts.
+    retention: RetentionPolicy,
+    /// The number of facts learned since the last consolidation.
+    learned: usize,
+}
+
+impl Memory {
+    /// Create a new Memory.
+    pub fn new(
+        embedder: EmbeddingClient,
+        store: Arc<dyn VectorStore>,
+        extractor: AIService,
+    ) -> Self {
+        Memory {
+            embedder,
+            store,
+            extractor,
+            namespace: None,
+            top_k: Self::default_top_k(),
+            min_score: None,
+            retention: RetentionPolicy::default(),
+            learned: 0,
+        }
+    }
+    /// Get the facts most relevant to the query. The facts recalled are used, so their
+    /// retention is renewed.
+    pub async fn recall(&mut self, query: &str) -> AINodeResult<Vec<String>> {
+        if query.trim().is_empty() || self.store.count().map_err(memory_error)? == 0 {
+            return Ok(Vec::new());
+        }
+        let vector = self
+            .embedder
+            .embed(&[query.to_string()])
+            .await?
+            .pop()
+            .unwrap_or_default();
+        let filter = self.filter();
+        let now = Utc::now();
+        let matches = self
+            .store
+            .query(&vector, self.top_k, Some(&filter))
+            .map_err(memory_error)?;
+        let recalled: Vec<Document> = matches
+            .into_iter()
+            .filter(|m| self.min_score.is_none_or(|min| m.score >= min))
+            .filter(|m| !self.retention.is_expired(&m.document.metadata, now))
+            .map(|m| m.document.meta("recalled", Value::from(now.to_rfc3339())))
+            .collect();
+        let facts = recalled
+            .iter()
+            .map(|document| document.text.clone())
+            .collect();
+        if !recalled.is_empty() {
+            self.store.upsert(recalled).map_err(memory_error)?;
+        }
+        Ok(facts)
+    }
+    /// Extract the facts of the chats and store them, and get the facts. The policy is
+    /// applied to the memory after the facts are stored.
+    pub async fn remember(&mut self, chats: &[Chat]) -> AINodeResult<Vec<String>> {
+        let answer = self.ask(EXTRACTION_PROMPT, transcript(chats)).await?;
+        let facts = Self::parse_facts(&answer)?;
+        if facts.is_empty() {
+            return Ok(Vec::new());
+        }
+        self.store_facts(&facts).await?;
+        self.learned += facts.len();
+        if self
+            .retention
+            .consolidate_every
+            .is_some_and(|every| self.learned >= every)
+        {
+            self.consolidate().await?;
+        }
+        self.forget()?;
+        Ok(facts.into_iter().map(|(fact, _)| fact).collect())
+    }
+    /// Ask the AI service to merge the facts of the namespace, and replace the facts by the
+    /// merged facts. Get the number of facts after the consolidation.
+    pub async fn consolidate(&mut self) -> AINodeResult<usize> {
+        self.learned = 0;
+        let mut documents = self
+            .store
+            .list(Some(&self.filter()))
+            .map_err(memory_error)?;
+        if documents.len() < 2 {
+            return Ok(documents.len());
+        }
+        documents.sort_by_key(|document| used_at(&document.metadata));
+        let listed: Vec<String> = documents
+            .iter()
+            .map(|document| format!("- {}", document.text))
+            .collect();
+        let answer = self.ask(CONSOLIDATION_PROMPT, listed.join("\n")).await?;
+        let facts = Self::parse_facts(&answer)?;
+        let ids: Vec<String> = documents.into_iter().map(|document| document.id).collect();
+        self.store.delete(&ids).map_err(memory_error)?;
+        self.store_facts(&facts).await?;
+        Ok(facts.len())
+    }
+    /// Forget the facts of the namespace that are expired, then the facts of the lowest
+    /// retention over the max number of facts. Get the number of facts forgotten.
+    pub fn forget(&self) -> AINodeResult<usize> {
+        if self.retention.ttl.is_none() && self.retention.max_items.is_none() {
+            return Ok(0);
+        }
+        let now = Utc::now();
+        let documents = self
+            .store
+            .list(Some(&self.filter()))
+            .map_err(memory_error)?;
+        let (expired, mut kept): (Vec<Document>, Vec<Document>) = documents
+            .into_iter()
+            .partition(|document| self.retention.is_expired(&document.metadata, now));
+        let mut forgotten: Vec<String> = expired.into_iter().map(|document| document.id).collect();
+        if let Some(max_items) = self.retention.max_items {
+            if kept.len() > max_items {
+                kept.sort_by(|a, b| {
+                    let a = self.retention.retention(&a.metadata, now);
+                    let b = self.retention.retention(&b.metadata, now);
+                    b.total_cmp(&a)
+                });
+                forgotten.extend(kept.drain(max_items..).map(|document| document.id));
+            }
+        }
+        if forgotten.is_empty() {
+            return Ok(0);
+        }
+        self.store.delete(&forgotten).map_err(memory_error)
+    }
+    /// Get the text of the memories put before the prompt prefix, empty without memory.
+    pub fn prompt(memories: &[String]) -> String {
+        if memories.is_empty() {
+            return String::new();
+        }
+        let mut prompt = MEMORIES_TITLE.to_string();
+        for memory in memories {
+            prompt.push_str("\n- ");
+            prompt.push_str(memory);
+        }
+        prompt
+    }
+    /// Send the instruction and the text to the AI service and get the answer.
+    async fn ask(&mut self, instruction: &str, text: String) -> AINodeResult<String> {
+        let request = vec![
+            Chat::new(Role::System, instruction.to_string()),
+            Chat::new(Role::User, text),
+        ];
+        self.extractor
+            .complete(&request, &RequestOverrides::new().temperature(0.0))
+            .await
+    }
+    /// Embed the facts with their importance and upsert them in the store.
+    async fn store_facts(&mut self, facts: &[(String, f32)]) -> AINodeResult<()> {
+        if facts.is_empty() {
+            return Ok(());
+        }
+        let texts: Vec<String> = facts.iter().map(|(fact, _)| fact.clone()).collect();
+        let vectors = self.embedder.embed(&texts).await?;
+        let created = Utc::now().to_rfc3339();
+        let documents = facts
+            .iter()
+            .zip(vectors)
+            .map(|((fact, importance), vector)| {
+                let mut document = Document::new(&self.fact_id(fact), fact, vector)
+                    .meta("created", Value::from(created.clone()))
+                    .meta("importance", Value::from(*importance));
+                if let Some(namespace) = &self.namespace {
+                    document = document.meta("namespace", Value::from(namespace.clone()));
+                }
+                document
+            })
+            .collect();
+        self.store.upsert(documents).map_err(memory_error)
+    }
+    /// Get the facts and their importance, from 0 to 1, of an answer of the AI service: a
+    /// json array of texts or of `{"fact", "importance"}` objects, maybe surrounded by other
+    /// words.
+    fn parse_facts(answer: &str) -> AINodeResult<Vec<(String, f32)>> {
+        let invalid = || {
+            AINodeError::new(
+                AINodeErrorType::InvalidJsonOutput,
+                format!(
+                    "The extracted facts `{}` are not a json array of facts.",
+                    answer.trim()
+                ),
+            )
+        };
+        let start = answer.find('[').ok_or_else(invalid)?;
+        let end = answer
+            .rfind(']')
+            .filter(|&end| end > start)
+            .ok_or_else(invalid)?;
+        let items: Vec<Value> =
+            serde_json::from_str(&answer[start..=end]).map_err(|_| invalid())?;
+        let mut facts = Vec::new();
+        for item in items {
+            let (fact, importance) = match &item {
+                Value::String(fact) => (fact.as_str(), DEFAULT_IMPORTANCE),
+                Value::Object(object) => (
+                    object
+                        .get("fact")
+                        .and_then(Value::as_str)
+                        .ok_or_else(invalid)?,
+                    object
+                        .get("importance")
+                        .and_then(Value::as_f64)
+                        .map_or(DEFAULT_IMPORTANCE, |importance| {
+                            (importance as f32 / 10.0).clamp(0.0, 1.0)
+                        }),
+                ),
+                _ => return Err(invalid()),
+            };
+            let fact = fact.trim();
+            if !fact.is_empty() {
+                facts.push((fact.to_string(), importance));
+            }
+        }
+        Ok(facts)
+    }
+    /// Get the id of a fact in the namespace.
+    fn fact_id(&self, fact: &str) -> String {
+        let mut hasher = Sha256::new();
+        hasher.update(self.namespace.as_deref().unwrap_or_default());
+        hasher.update([0]);
+        hasher.update(fact.to_lowercase());
+        format!("{:x}", hasher.finalize())
+    }
+    /// Get the filter of the facts of the namespace.
+    fn filter(&self) -> Filter {
+        match &self.namespace {
+            Some(namespace) => Filter::eq("namespace", Value::from(namespace.clone())),
+            None => Filter::Not(Box::new(Filter::Exists {
+                key: "namespace".to_string(),
+            })),
+        }
+    }
+    /// Set the namespace as builder.
+    pub fn namespace(mut self, namespace: Option<String>) -> Self {
+        self.namespace = namespace;
+        self
+    }
+    /// Set the namespace.
+    pub fn set_namespace(&mut self, namespace: Option<String>) {
+        self.namespace = namespace;
+    }
+    /// Get the namespace.
+    pub fn get_namespace(&self) -> Option<&str> {
+        self.namespace.as_deref()
+    }
+    /// Set the max number of facts recalled as builder.
+    pub fn top_k(mut self, top_k: usize) -> Self {
+        self.top_k = top_k;
+        self
+    }
+    /// Set the max number of facts recalled.
+    pub fn set_top_k(&mut self, top_k: usize) {
+        self.top_k = top_k;
+    }
+    /// Get the max number of facts recalled.
+    pub fn get_top_k(&self) -> usize {
+        self.top_k
+    }
+    /// The default max number of facts recalled.
+    pub fn default_top_k() -> usize {
+        5
+    }
+    /// Set the lowest score of a fact recalled as builder.
+    pub fn min_score(mut self, min_score: Option<f32>) -> Self {
+        self.min_score = min_score;
+        self
+    }
+    /// Set the lowest score of a fact recalled.
+    pub fn set_min_score(&mut self, min_score: Option<f32>) {
+        self.min_score = min_score;
+    }
+    /// Get the lowest score of a fact recalled.
+    pub fn get_min_score(&self) -> Option<f32> {
+        self.min_score
+    }
+    /// Set the retention policy as builder.
+    pub fn retention(mut self, retention: RetentionPolicy) -> Self {
+        self.retention = retention;
+        self
+    }
+    /// Set the retention policy.
+    pub fn set_retention(&mut self, retention: RetentionPolicy) {
+        self.retention = retention;
+    }
+    /// Get the retention policy.
+    pub fn get_retention(&self) -> &RetentionPolicy {
+        &self.retention
+    }
+    /// Get the store of the facts.
+    pub fn get_store(&self) -> &Arc<dyn VectorStore> {
+        &self.store
+    }
+    /// Get the client that embeds the facts.
+    pub fn get_embedder(&self) -> &EmbeddingClient {
+        &self.embedder
+    }
+    /// Get the AI service that extracts the facts.
+    pub fn get_extractor(&self) -> &AIService {
+        &self.extractor
+    }
+}
+
+/// Get the time of the last use of the fact of the metadata: when it was last recalled, or
+/// else learned. A fact without time was never used.
+fn used_at(metadata: &Map<String, Value>) -> Option<DateTime<Utc>> {
+    ["recalled", "created"]
+        .iter()
+        .filter_map(|key| metadata.get(*key).and_then(Value::as_str))
+        .filter_map(|time| DateTime::parse_from_rfc3339(time).ok())
+        .map(|time| time.with_timezone(&Utc))
+        .max()
+}
+
+/// Get the time since the last use of the fact of the metadata.
+fn idle_time(metadata: &Map<String, Value>, now: DateTime<Utc>) -> Duration {
+    used_at(metadata)
+        .and_then(|used| (now - used).to_std().ok())
+        .unwrap_or_default()
+}
+
+/// Create an AINodeError of the store of the memory.
+fn memory_error(e: VectorStoreError) -> AINodeError {
+    AINodeError::new(
+        AINodeErrorType::MemoryError(e),
+        "Failed to use the store of the memory".to_string(),
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::vector_store::memory::MemoryVectorStore;
+    use crate::worknode::ai_node::deepseek::{DeepSeekClient, DeepSeekModel, DEEPSEEK_API_URL};
+    use crate::worknode::ai_node::embedding::EmbeddingProvider;
+    use crate::worknode::ai_node::recording::Recording;
+    use crate::worknode::ai_node::{AINode, AINodeInput};
+
+    use serde_json::json;
+    use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+    use tokio::net::TcpListener;
+    use tokio::runtime::Runtime;
+
+    /// Answer every request with an embedding per input, about cats or about something else.
+    async fn server(listener: TcpListener) {
+        loop {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut stream = BufReader::new(stream);
+            let mut length = 0;
+            loop {
+                let mut line = String::new();
+                stream.read_line(&mut line).await.unwrap();
+                if line == "\r\n" {
+                    break;
+                }
+                if let Some(value) = line.to_lowercase().strip_prefix("content-length:") {
+                    length = value.trim().parse().unwrap();
+                }
+            }
+            let mut body = vec![0; length];
+            stream.read_exact(&mut body).await.unwrap();
+            let request: Value = serde_json::from_slice(&body).unwrap();
+            let embeddings: Vec<Vec<f32>> = request["input"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|input| match input.as_str().unwrap().contains("cat") {
+                    true => vec![1.0, 0.0],
+                    false => vec![0.0, 1.0],
+                })
+                .collect();
+            let answer = json!({ "embeddings": embeddings }).to_string();
+            let response = format!(
+                "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                answer.len(),
+                answer
+            );
+            stream
+                .get_mut()
+                .write_all(response.as_bytes())
+                .await
+                .unwrap();
+        }
+    }
+
+    #[test]
+    fn remember_and_recall() {
+        let rt = Runtime::new().unwrap();
+        let listener = rt.block_on(TcpListener::bind("127.0.0.1:0")).unwrap();
+        let url = format!("http://{}/api/embed", listener.local_addr().unwrap());
+        rt.spawn(server(listener));
+        let embedder = EmbeddingClient::new(EmbeddingProvider::Ollama, &url, "nomic-embed-text");
+
+        let exchange = vec![
+            Chat::new(Role::User, "My cat is called Tom.".to_string()),
+            Chat::new(Role::Assistant, "Nice name!".to_string()),
+        ];
+        let request = vec![
+            Chat::new(Role::System, EXTRACTION_PROMPT.to_string()),
+            Chat::new(Role::User, transcript(&exchange)),
+        ];
+        let client = DeepSeekClient::new(DEEPSEEK_API_URL, DeepSeekModel::DeepseekChat);
+        let overrides = RequestOverrides::new().temperature(0.0);
+        let recording = Recording::replay(vec![
+            client.exchange(
+                &request,
+                &overrides,
+                r#"["The cat of the user is Tom.", "Likes tea"]"#,
+            ),
+            client.exchange(
+                &request,
+                &overrides,
+                r#"Facts: ["The cat of the user is Tom."]"#,
+            ),
+        ]);
+        let extractor = AIService::DeepSeek {
+            client: client.recording(Some(recording)),
+        };
+        let store = Arc::new(MemoryVectorStore::new());
+        let mut memory = Memory::new(embedder, store.clone(), extractor)
+            .namespace(Some("alice".to_string()))
+            .top_k(1);
+
+        let facts = rt.block_on(memory.remember(&exchange)).unwrap();
+        assert_eq!(facts, vec!["The cat of the user is Tom.", "Likes tea"]);
+        // the same fact learned again is stored once
+        rt.block_on(memory.remember(&exchange)).unwrap();
+        assert_eq!(store.count().unwrap(), 2);
+
+        let recalled = rt
+            .block_on(memory.recall("What is my cat called?"))
+            .unwrap();
+        assert_eq!(recalled, vec!["The cat of the user is Tom."]);
+        assert_eq!(
+            Memory::prompt(&recalled),
+            "Relevant memories from earlier conversations:\n- The cat of the user is Tom."
+        );
+
+        // the facts of another namespace are not recalled
+        let mut other = memory.clone().namespace(Some("bob".to_string()));
+        assert!(rt.block_on(other.recall("my cat")).unwrap().is_empty());
+
+        // the memories are put before the prompt prefix of the AI node
+        let mut node = AINode::new(AIService::new_deepseek(DeepSeekClient::new(
+            DEEPSEEK_API_URL,
+            DeepSeekModel::DeepseekChat,
+        )))
+        .memory(Some(memory))
+        .prompt_prefix("Be brief.".to_string());
+        rt.block_on(node.prepare(AINodeInput::parse("Is my cat fine?".to_string()).unwrap()))
+            .unwrap();
+        assert_eq!(
+            node.build_prompt().unwrap(),
+            "Relevant memories from earlier conversations:\n- The cat of the user is Tom.\n\
+             Be brief.\nIs my cat fine?\n"
+        );
+    }
+
+    #[test]
+    fn retention_policy() {
+        let rt = Runtime::new().unwrap();
+        let listener = rt.block_on(TcpListener::bind("127.0.0.1:0")).unwrap();
+        let url = format!("http://{}/api/embed", listener.local_addr().unwrap());
+        rt.spawn(server(listener));
+        let embedder = EmbeddingClient::new(EmbeddingProvider::Ollama, &url, "nomic-embed-text");
+
+        let hours_ago =
+            |hours: i64| Value::from((Utc::now() - chrono::Duration::hours(hours)).to_rfc3339());
+        let fact = |id: &str, text: &str, importance: f32, hours: i64| {
+            Document::new(id, text, vec![1.0, 0.0])
+                .meta("importance", Value::from(importance))
+                .meta("created", hours_ago(hours))
+        };
+        let store = Arc::new(MemoryVectorStore::new());
+        store
+            .upsert(vec![
+                fact("a", "The user has a cat.", 0.9, 48),
+                fact("b", "The user likes tea.", 0.8, 1).meta("recalled", hours_ago(0)),
+                fact("c", "The user asked about the weather.", 0.3, 2),
+                fact("d", "The user lived in Paris.", 1.0, 24 * 30),
+                fact("e", "Another user likes coffee.", 0.1, 24 * 30)
+                    .meta("namespace", Value::from("bob")),
+            ])
+            .unwrap();
+
+        // the retention halves every half-life since the last use
+        let policy = RetentionPolicy::new().half_life(Some(Duration::from_secs(24 * 3600)));
+        let now = Utc::now();
+        let retention = policy.retention(&store.get("a").unwrap().unwrap().metadata, now);
+        assert!((retention - 0.225).abs() < 0.01);
+        assert!((policy.retention(&Map::new(), now) - DEFAULT_IMPORTANCE).abs() < 1e-6);
+
+        // the expired fact and the fact of the lowest retention are forgotten, in the
+        // namespace of the memory only
+        let client = DeepSeekClient::new(DEEPSEEK_API_URL, DeepSeekModel::DeepseekChat);
+        let memory = Memory::new(
+            embedder.clone(),
+            store.clone(),
+            AIService::new_deepseek(client.clone()),
+        )
+        .retention(
+            policy
+                .ttl(Some(Duration::from_secs(7 * 24 * 3600)))
+                .max_items(Some(2)),
+        );
+        assert_eq!(memory.forget().unwrap(), 2);
+        assert!(store.get("d").unwrap().is_none());
+        assert!(store.get("a").unwrap().is_none());
+        assert!(store.get("e").unwrap().is_some());
+
+        // the consolidation replaces the facts of the namespace by the merged facts
+        let request = vec![
+            Chat::new(Role::System, CONSOLIDATION_PROMPT.to_string()),
+            Chat::new(
+                Role::User,
+                "- The user asked about the weather.\n- The user likes tea.".to_string(),
+            ),
+        ];
+        let recording = Recording::replay(vec![client.exchange(
+            &request,
+            &RequestOverrides::new().temperature(0.0),
+            r#"[{"fact": "The user likes tea.", "importance": 7}]"#,
+        )]);
+        let mut memory = Memory::new(
+            embedder,
+            store.clone(),
+            AIService::DeepSeek {
+                client: client.recording(Some(recording)),
+            },
+        );
+        assert_eq!(rt.block_on(memory.consolidate()).unwrap(), 1);
+        assert_eq!(store.count().unwrap(), 2);
+        let tea = store.list(Some(&memory.filter())).unwrap().pop().unwrap();
+        assert_eq!(tea.text, "The user likes tea.");
+        assert_eq!(tea.metadata["importance"].as_f64().unwrap() as f32, 0.7);
+    }
+}
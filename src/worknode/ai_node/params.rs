@@ -0,0 +1,248 @@
+//! # Params
+//!
+//! This module resolves the sampling parameters of a request from the layers they can be set
+//! at. A parameter is taken from the first of these that has it:
+//! 1. request: the `RequestOverrides` of one request.
+//! 2. node: the params of the AI node that sends the request.
+//! 3. provider: the params of the client, like the ones of a provider of `aipilot.toml`.
+//! 4. global: the defaults of the client, like the `[params]` of `aipilot.toml`, which all
+//!    the providers share.
+//! 5. built-in: `SamplingParams::builtin`, the defaults of the api.
+//!
+//! The values are resolved when the request is sent, into `EffectiveParams` which keep the
+//! layer of every value. They are logged at the debug level, so it can be seen why a request
+//! was sent with a temperature.
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+/// The struct of the sampling parameters of one layer. A parameter left as `None` is taken
+/// from the layer below.
+pub struct SamplingParams {
+    /// The temperature, between 0 and 2.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<f64>,
+    /// The top p, between 0 and 1.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub top_p: Option<f64>,
+    /// The maximum tokens of the response.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_tokens: Option<i32>,
+    /// The penalty of frequency, between -2 and 2.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub frequency_penalty: Option<f64>,
+    /// The penalty of presence, between -2 and 2.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub presence_penalty: Option<f64>,
+    /// The sequences where the model stops generating.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stop: Option<Vec<String>>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+/// The enum of the layer that a sampling parameter is set at.
+pub enum ParamLayer {
+    /// The overrides of one request.
+    Request,
+    /// The AI node.
+    Node,
+    /// The client of the provider.
+    Provider,
+    /// The defaults of the client shared by all the providers.
+    Global,
+    /// The defaults of the api.
+    BuiltIn,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+/// The struct of a resolved parameter with the layer it was taken from.
+pub struct Effective<T> {
+    /// The value.
+    pub value: T,
+    /// The layer of the value.
+    pub layer: ParamLayer,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+/// The struct of the sampling parameters that a request is sent with.
+pub struct EffectiveParams {
+    pub temperature: Effective<f64>,
+    pub top_p: Effective<f64>,
+    pub max_tokens: Effective<i32>,
+    pub frequency_penalty: Effective<f64>,
+    pub presence_penalty: Effective<f64>,
+    pub stop: Effective<Option<Vec<String>>>,
+}
+
+impl SamplingParams {
+    /// Create a new SamplingParams that sets nothing.
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// The defaults of the api, used when no layer sets a parameter.
+    pub fn builtin() -> Self {
+        SamplingParams {
+            temperature: Some(1.0),
+            top_p: Some(1.0),
+            max_tokens: Some(4096),
+            frequency_penalty: Some(0.0),
+            presence_penalty: Some(0.0),
+            stop: None,
+        }
+    }
+    /// Set the temperature as builder.
+    pub fn temperature(mut self, temperature: Option<f64>) -> Self {
+        self.temperature = temperature;
+        self
+    }
+    /// Set the top p as builder.
+    pub fn top_p(mut self, top_p: Option<f64>) -> Self {
+        self.top_p = top_p;
+        self
+    }
+    /// Set the maximum tokens as builder.
+    pub fn max_tokens(mut self, max_tokens: Option<i32>) -> Self {
+        self.max_tokens = max_tokens;
+        self
+    }
+    /// Set the penalty of frequency as builder.
+    pub fn frequency_penalty(mut self, frequency_penalty: Option<f64>) -> Self {
+        self.frequency_penalty = frequency_penalty;
+        self
+    }
+    /// Set the penalty of presence as builder.
+    pub fn presence_penalty(mut self, presence_penalty: Option<f64>) -> Self {
+        self.presence_penalty = presence_penalty;
+        self
+    }
+    /// Set the stop sequences as builder.
+    pub fn stop(mut self, stop: Option<Vec<String>>) -> Self {
+        self.stop = stop;
+        self
+    }
+    /// Whether the params set nothing.
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+    /// Resolve the params of the layers, from the highest to the lowest. The built-in
+    /// defaults are the last layer.
+    pub fn resolve(layers: &[(ParamLayer, &SamplingParams)]) -> EffectiveParams {
+        let builtin = Self::builtin();
+        let layers: Vec<(ParamLayer, &SamplingParams)> = layers
+            .iter()
+            .copied()
+            .chain([(ParamLayer::BuiltIn, &builtin)])
+            .collect();
+        fn pick<T: Clone>(
+            layers: &[(ParamLayer, &SamplingParams)],
+            field: impl Fn(&SamplingParams) -> &Option<T>,
+        ) -> Effective<Option<T>> {
+            layers
+                .iter()
+                .find_map(|(layer, params)| {
+                    field(params).clone().map(|value| Effective {
+                        value: Some(value),
+                        layer: *layer,
+                    })
+                })
+                .unwrap_or(Effective {
+                    value: None,
+                    layer: ParamLayer::BuiltIn,
+                })
+        }
+        fn some<T>(effective: Effective<Option<T>>, builtin: T) -> Effective<T> {
+            Effective {
+                value: effective.value.unwrap_or(builtin),
+                layer: effective.layer,
+            }
+        }
+        EffectiveParams {
+            temperature: some(pick(&layers, |p| &p.temperature), 1.0),
+            top_p: some(pick(&layers, |p| &p.top_p), 1.0),
+            max_tokens: some(pick(&layers, |p| &p.max_tokens), 4096),
+            frequency_penalty: some(pick(&layers, |p| &p.frequency_penalty), 0.0),
+            presence_penalty: some(pick(&layers, |p| &p.presence_penalty), 0.0),
+            stop: pick(&layers, |p| &p.stop),
+        }
+    }
+}
+
+impl std::fmt::Display for ParamLayer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            ParamLayer::Request => "request",
+            ParamLayer::Node => "node",
+            ParamLayer::Provider => "provider",
+            ParamLayer::Global => "global",
+            ParamLayer::BuiltIn => "built-in",
+        };
+        f.write_str(name)
+    }
+}
+
+impl std::fmt::Display for EffectiveParams {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "temperature {} ({}), top_p {} ({}), max_tokens {} ({}), frequency_penalty {} ({}), presence_penalty {} ({}), stop {} ({})",
+            self.temperature.value,
+            self.temperature.layer,
+            self.top_p.value,
+            self.top_p.layer,
+            self.max_tokens.value,
+            self.max_tokens.layer,
+            self.frequency_penalty.value,
+            self.frequency_penalty.layer,
+            self.presence_penalty.value,
+            self.presence_penalty.layer,
+            match &self.stop.value {
+                Some(stop) => format!("{:?}", stop),
+                None => "none".to_string(),
+            },
+            self.stop.layer,
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn resolve_layers() {
+        let global = SamplingParams::new()
+            .temperature(Some(0.5))
+            .max_tokens(Some(1024));
+        let provider = SamplingParams::new().temperature(Some(0.7));
+        let node = SamplingParams::new().stop(Some(vec!["END".to_string()]));
+        let request = SamplingParams::new().max_tokens(Some(64));
+        let effective = SamplingParams::resolve(&[
+            (ParamLayer::Request, &request),
+            (ParamLayer::Node, &node),
+            (ParamLayer::Provider, &provider),
+            (ParamLayer::Global, &global),
+        ]);
+        assert_eq!(
+            effective.temperature,
+            Effective {
+                value: 0.7,
+                layer: ParamLayer::Provider
+            }
+        );
+        assert_eq!(effective.max_tokens.value, 64);
+        assert_eq!(effective.max_tokens.layer, ParamLayer::Request);
+        assert_eq!(effective.stop.layer, ParamLayer::Node);
+        assert_eq!(effective.top_p.layer, ParamLayer::BuiltIn);
+        assert_eq!(
+            effective.to_string(),
+            "temperature 0.7 (provider), top_p 1 (built-in), max_tokens 64 (request), frequency_penalty 0 (built-in), presence_penalty 0 (built-in), stop [\"END\"] (node)"
+        );
+        assert!(SamplingParams::new().is_empty());
+        assert_eq!(
+            SamplingParams::resolve(&[]).max_tokens.value,
+            SamplingParams::builtin().max_tokens.unwrap()
+        );
+    }
+}
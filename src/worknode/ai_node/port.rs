@@ -0,0 +1,278 @@
+//! # Port
+//!
+//! This module defines the named inputs and the structured output of the AI node, so the graph
+//! can wire a specific upstream output into a specific input of the node.
+//!
+//! The input ports are:
+//! - `history`: the history of the conversation, as an OpenAI format message array.
+//! - `input`: the input of the user.
+//! - `context`: the background information put before the input, like a retrieved document.
+//! - `role`: the role of the assistant.
+//! - `prompt_prefix` and `prompt_suffix`: the prefix and the suffix of the prompt.
+//! - `variables`: the variables to render the templates with, as a json object.
+//!
+//! The output ports are `content`, `reasoning`, `usage` and `json`.
+
+use super::chat::Chat;
+use super::deepseek::DeepSeekUsage;
+use crate::error::ai_node_error::{AINodeError, AINodeErrorType, AINodeResult};
+use crate::template::Variables;
+
+use json::object;
+
+#[derive(Debug, Clone, Default)]
+/// The struct of the inputs of the AI node. A port left as `None` keeps the value of the node.
+pub struct AINodeInput {
+    pub(super) history: Option<Vec<Chat>>,
+    pub(super) input: Option<String>,
+    pub(super) context: Option<String>,
+    pub(super) role: Option<String>,
+    pub(super) prompt_prefix: Option<String>,
+    pub(super) prompt_suffix: Option<String>,
+    pub(super) variables: Variables,
+}
+
+impl AINodeInput {
+    /// The names of the input ports.
+    pub const PORTS: [&'static str; 7] = [
+        "history",
+        "input",
+        "context",
+        "role",
+        "prompt_prefix",
+        "prompt_suffix",
+        "variables",
+    ];
+    /// Create a new AINodeInput that sets nothing.
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Parse a text input. If the text is a json object, its fields are the ports, otherwise
+    /// the whole text is the `input` port.
+    pub fn parse(text: String) -> AINodeResult<Self> {
+        let params = match json::parse(text.as_str()) {
+            Ok(params) if params.is_object() => params,
+            _ => return Ok(Self::new().input(text)),
+        };
+        let mut input = Self::new();
+        for (port, value) in params.entries() {
+            if value.is_null() {
+                continue;
+            }
+            let value = match value.as_str() {
+                Some(text) => text.to_string(),
+                None => value.dump(),
+            };
+            input.set_port(port, value)?;
+        }
+        Ok(input)
+    }
+    /// Set a port by its name. The `history` and `variables` ports take json, and the other
+    /// ports take text.
+    pub fn set_port(&mut self, port: &str, value: String) -> AINodeResult<()> {
+        match port {
+            "history" => self.history = Some(Chat::from_openai_json(&value)?),
+            "input" => self.input = Some(value),
+            "context" => self.context = Some(value),
+            "role" => self.role = Some(value),
+            "prompt_prefix" => self.prompt_prefix = Some(value),
+            "prompt_suffix" => self.prompt_suffix = Some(value),
+            "variables" => {
+                let variables = json::parse(&value)
+                    .ok()
+                    .filter(|variables| variables.is_object())
+                    .ok_or_else(|| {
+                        AINodeError::new(
+                            AINodeErrorType::InvalidInput,
+                            format!("The variables should be a json object: {}", value),
+                        )
+                    })?;
+                for (name, value) in variables.entries() {
+                    self.variables.insert(name.to_string(), value.to_string());
+                }
+            }
+            _ => {
+                return Err(AINodeError::new(
+                    AINodeErrorType::InvalidInput,
+                    format!("Unknown input port: {}", port),
+                ))
+            }
+        }
+        Ok(())
+    }
+    /// Set the history port as builder.
+    pub fn history(mut self, history: Vec<Chat>) -> Self {
+        self.history = Some(history);
+        self
+    }
+    /// Set the input port as builder.
+    pub fn input(mut self, input: String) -> Self {
+        self.input = Some(input);
+        self
+    }
+    /// Set the context port as builder.
+    pub fn context(mut self, context: String) -> Self {
+        self.context = Some(context);
+        self
+    }
+    /// Set the role port as builder.
+    pub fn role(mut self, role: String) -> Self {
+        self.role = Some(role);
+        self
+    }
+    /// Set the prompt prefix port as builder.
+    pub fn prompt_prefix(mut self, prompt_prefix: String) -> Self {
+        self.prompt_prefix = Some(prompt_prefix);
+        self
+    }
+    /// Set the prompt suffix port as builder.
+    pub fn prompt_suffix(mut self, prompt_suffix: String) -> Self {
+        self.prompt_suffix = Some(prompt_suffix);
+        self
+    }
+    /// Set one variable as builder.
+    pub fn variable(mut self, name: &str, value: String) -> Self {
+        self.variables.insert(name.to_string(), value);
+        self
+    }
+    /// Get the history port.
+    pub fn get_history(&self) -> Option<&Vec<Chat>> {
+        self.history.as_ref()
+    }
+    /// Get the input port.
+    pub fn get_input(&self) -> Option<&str> {
+        self.input.as_deref()
+    }
+    /// Get the context port.
+    pub fn get_context(&self) -> Option<&str> {
+        self.context.as_deref()
+    }
+    /// Get the role port.
+    pub fn get_role(&self) -> Option<&str> {
+        self.role.as_deref()
+    }
+    /// Get the prompt prefix port.
+    pub fn get_prompt_prefix(&self) -> Option<&str> {
+        self.prompt_prefix.as_deref()
+    }
+    /// Get the prompt suffix port.
+    pub fn get_prompt_suffix(&self) -> Option<&str> {
+        self.prompt_suffix.as_deref()
+    }
+    /// Get the variables port.
+    pub fn get_variables(&self) -> &Variables {
+        &self.variables
+    }
+}
+
+impl From<String> for AINodeInput {
+    fn from(input: String) -> Self {
+        Self::new().input(input)
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+/// The struct of the output of the AI node.
+pub struct AINodeOutput {
+    /// The content of the answer.
+    content: String,
+    /// The reasoning of the answer, given by the reasoning models.
+    reasoning: Option<String>,
+    /// The usage statistics of the requests of the execution.
+    usage: Option<DeepSeekUsage>,
+    /// The content parsed as json, if it is valid json.
+    json: Option<serde_json::Value>,
+}
+
+impl AINodeOutput {
+    /// The names of the output ports.
+    pub const PORTS: [&'static str; 4] = ["content", "reasoning", "usage", "json"];
+    /// Create a new AINodeOutput. The json port is parsed from the content.
+    pub fn new(content: String, reasoning: Option<String>, usage: Option<DeepSeekUsage>) -> Self {
+        let json = serde_json::from_str(&content).ok();
+        AINodeOutput {
+            content,
+            reasoning,
+            usage,
+            json,
+        }
+    }
+    /// Get the content of the answer.
+    pub fn get_content(&self) -> &str {
+        &self.content
+    }
+    /// Get the reasoning of the answer.
+    pub fn get_reasoning(&self) -> Option<&str> {
+        self.reasoning.as_deref()
+    }
+    /// Get the usage statistics.
+    pub fn get_usage(&self) -> Option<DeepSeekUsage> {
+        self.usage
+    }
+    /// Get the content parsed as json.
+    pub fn get_json(&self) -> Option<&serde_json::Value> {
+        self.json.as_ref()
+    }
+    /// Get an output port as text by its name. Return `None` if the port is empty or unknown.
+    pub fn get_port(&self, port: &str) -> Option<String> {
+        match port {
+            "content" => Some(self.content.clone()),
+            "reasoning" => self.reasoning.clone(),
+            "usage" => self.usage.map(|usage| {
+                object! {
+                    prompt_tokens: usage.get_prompt_tokens(),
+                    completion_tokens: usage.get_completion_tokens(),
+                    total_tokens: usage.get_total_tokens(),
+                }
+                .dump()
+            }),
+            "json" => self.json.as_ref().map(|json| json.to_string()),
+            _ => None,
+        }
+    }
+    /// Take the content of the answer.
+    pub fn into_content(self) -> String {
+        self.content
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_json_ports() {
+        let input = AINodeInput::parse(
+            r#"{
+                "history": [{"role": "user", "content": "hi"}],
+                "input": "who are you",
+                "context": "you are at home",
+                "variables": {"name": "Tom"}
+            }"#
+            .to_string(),
+        )
+        .unwrap();
+        assert_eq!(input.get_history().unwrap().len(), 1);
+        assert_eq!(input.get_input(), Some("who are you"));
+        assert_eq!(input.get_context(), Some("you are at home"));
+        assert_eq!(input.get_variables()["name"], "Tom");
+        assert!(input.get_role().is_none());
+
+        let plain = AINodeInput::parse("hello".to_string()).unwrap();
+        assert_eq!(plain.get_input(), Some("hello"));
+        assert!(AINodeInput::new()
+            .set_port("unknown", String::new())
+            .is_err());
+    }
+
+    #[test]
+    fn output_ports() {
+        let output = AINodeOutput::new(r#"{"answer": 42}"#.to_string(), None, None);
+        assert_eq!(output.get_json().unwrap()["answer"], 42);
+        assert_eq!(output.get_port("json").unwrap(), r#"{"answer":42}"#);
+        assert!(output.get_port("reasoning").is_none());
+        assert!(AINodeOutput::new("plain".to_string(), None, None)
+            .get_json()
+            .is_none());
+    }
+}
@@ -0,0 +1,199 @@
+//! # Recording
+//!
+//! This module records the requests sent to the AI service and the responses it gave, so a
+//! run can be replayed later without calling the api.
+//!
+//! A `Recording` in the record mode is given to the clients (see
+//! [`super::deepseek::DeepSeekClient::recording`], or `Workflow::recording` for all nodes of a
+//! workflow). Every request and its response are appended to it, and `save` writes them to a
+//! json file. A recording loaded from the file is in the replay mode: a client answers every
+//! request with the recorded response of the same request, and no api key is needed. This
+//! makes the tests of the logic of a workflow deterministic and offline.
+//!
+//! The requests are matched by their json value, so the order of the nodes of a fan-out
+//! doesn't matter. The same request sent several times gets its responses in the recorded
+//! order. The stream requests are not recorded, and can't be replayed.
+
+use crate::error::ai_node_error::deepseek_error::{
+    DeepSeekError, DeepSeekErrorType, DeepSeekResult,
+};
+use crate::error::ai_node_error::{AINodeError, AINodeErrorType, AINodeResult};
+
+use serde::{Deserialize, Serialize};
+
+use std::path::Path;
+use std::sync::{Arc, Mutex, PoisonError};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// The enum of what a recording does with the requests.
+pub enum RecordingMode {
+    /// Send the requests, and record them with their responses.
+    Record,
+    /// Answer the requests with the recorded responses.
+    Replay,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+/// The struct of a request and the response of the AI service.
+pub struct Exchange {
+    /// The body of the request.
+    pub request: serde_json::Value,
+    /// The body of the response. A response that is not json is kept as a string.
+    pub response: serde_json::Value,
+}
+
+#[derive(Debug, Default)]
+/// The struct of the exchanges of a recording.
+struct RecordingState {
+    /// The exchanges, in the order they happened.
+    exchanges: Vec<Exchange>,
+    /// Whether each exchange is replayed already.
+    replayed: Vec<bool>,
+}
+
+#[derive(Debug, Clone)]
+/// The struct of the recording of the exchanges with the AI service. The clones share the
+/// same exchanges.
+pub struct Recording {
+    mode: RecordingMode,
+    state: Arc<Mutex<RecordingState>>,
+}
+
+impl Recording {
+    /// Create a new empty Recording in the record mode.
+    pub fn record() -> Self {
+        Recording {
+            mode: RecordingMode::Record,
+            state: Arc::default(),
+        }
+    }
+    /// Create a Recording in the replay mode with the exchanges.
+    pub fn replay(exchanges: Vec<Exchange>) -> Self {
+        let replayed = vec![false; exchanges.len()];
+        Recording {
+            mode: RecordingMode::Replay,
+            state: Arc::new(Mutex::new(RecordingState {
+                exchanges,
+                replayed,
+            })),
+        }
+    }
+    /// Load a recording saved by `save`, in the replay mode.
+    pub fn load<P: AsRef<Path>>(path: P) -> AINodeResult<Self> {
+        let path = path.as_ref();
+        let text = std::fs::read_to_string(path).map_err(|e| {
+            recording_error(format!(
+                "Can't read the recording file {}. {}",
+                path.display(),
+                e
+            ))
+        })?;
+        let exchanges = serde_json::from_str(&text)
+            .map_err(|e| recording_error(format!("The recording is not valid. {}", e)))?;
+        Ok(Self::replay(exchanges))
+    }
+    /// Save the exchanges to a json file.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> AINodeResult<()> {
+        let path = path.as_ref();
+        let text = serde_json::to_string_pretty(&self.lock().exchanges)
+            .map_err(|e| recording_error(format!("Can't write the recording. {}", e)))?;
+        std::fs::write(path, text).map_err(|e| {
+            recording_error(format!(
+                "Can't write the recording file {}. {}",
+                path.display(),
+                e
+            ))
+        })
+    }
+    /// Get what the recording does with the requests.
+    pub fn get_mode(&self) -> RecordingMode {
+        self.mode
+    }
+    /// Whether the recording answers the requests.
+    pub fn is_replay(&self) -> bool {
+        self.mode == RecordingMode::Replay
+    }
+    /// Get a copy of the exchanges.
+    pub fn get_exchanges(&self) -> Vec<Exchange> {
+        self.lock().exchanges.clone()
+    }
+    /// Get the number of exchanges that are not replayed yet.
+    pub fn remaining(&self) -> usize {
+        self.lock().replayed.iter().filter(|&&done| !done).count()
+    }
+    /// Record a request and its response.
+    pub(super) fn push(&self, request: &str, response: &str) {
+        let exchange = Exchange {
+            request: to_value(request),
+            response: to_value(response),
+        };
+        let mut state = self.lock();
+        state.exchanges.push(exchange);
+        state.replayed.push(false);
+    }
+    /// Get the recorded response of the request, which is the first one that is not replayed.
+    pub(super) fn answer(&self, request: &str) -> DeepSeekResult<String> {
+        let request = to_value(request);
+        let mut state = self.lock();
+        let state = &mut *state;
+        let index = state
+            .exchanges
+            .iter()
+            .zip(&state.replayed)
+            .position(|(exchange, &done)| !done && exchange.request == request)
+            .ok_or_else(|| {
+                DeepSeekError::new(
+                    DeepSeekErrorType::ReplayError,
+                    "No recorded response matches the request.".to_string(),
+                )
+                .body(Some(request.to_string()))
+            })?;
+        state.replayed[index] = true;
+        Ok(match &state.exchanges[index].response {
+            serde_json::Value::String(text) => text.clone(),
+            response => response.to_string(),
+        })
+    }
+    /// Lock the exchanges. A panic in another node doesn't make them invalid.
+    fn lock(&self) -> std::sync::MutexGuard<'_, RecordingState> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// Parse the body as json, or keep it as a string.
+fn to_value(body: &str) -> serde_json::Value {
+    serde_json::from_str(body).unwrap_or_else(|_| serde_json::Value::String(body.to_string()))
+}
+
+/// Create an AINodeError of a recording.
+fn recording_error(message: String) -> AINodeError {
+    AINodeError::new(AINodeErrorType::RecordingError, message)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn record_and_answer() {
+        let recording = Recording::record();
+        recording.push(
+            r#"{"messages":[],"model":"deepseek-chat"}"#,
+            r#"{"id":"1"}"#,
+        );
+        recording.push(r#"{"messages":[],"model":"deepseek-chat"}"#, "not json");
+        let path = std::env::temp_dir().join(format!("recording-{}.json", uuid::Uuid::new_v4()));
+        recording.save(&path).unwrap();
+        let replay = Recording::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(replay.is_replay());
+        assert_eq!(replay.remaining(), 2);
+        // the same json with another layout matches
+        let request = r#"{ "model": "deepseek-chat", "messages": [] }"#;
+        assert_eq!(replay.answer(request).unwrap(), r#"{"id":"1"}"#);
+        assert_eq!(replay.answer(request).unwrap(), "not json");
+        assert!(replay.answer(request).is_err());
+        assert_eq!(replay.remaining(), 0);
+    }
+}
@@ -0,0 +1,190 @@
+//! # Rerank
+//!
+//! This module scores documents by their relevance to a query with the rerank api of a
+//! provider, whose cross-encoder models read the query and each document together, so they
+//! order the candidates of a retrieval better than the similarity of their embeddings.
+//!
+//! The providers share the same request and response:
+//! 1. Cohere: the `/v2/rerank` api.
+//! 2. Jina: the `/v1/rerank` api, which some local servers (like the text embeddings
+//!    inference of Hugging Face) also serve, so the url can point to any of them.
+
+use super::key::{ApiKey, KeySource};
+use crate::error::ai_node_error::rerank_error::{RerankError, RerankErrorType, RerankResult};
+use crate::error::ai_node_error::AINodeResult;
+
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+pub const COHERE_RERANK_URL: &str = "https://api.cohere.com/v2/rerank";
+pub const JINA_RERANK_URL: &str = "https://api.jina.ai/v1/rerank";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+/// The enum of the api the scores are asked from.
+pub enum RerankProvider {
+    /// The Cohere rerank api.
+    Cohere,
+    /// The Jina rerank api.
+    Jina,
+}
+
+#[derive(Debug, Clone)]
+/// The struct of the client of a rerank api.
+pub struct RerankClient {
+    /// The api of the provider.
+    provider: RerankProvider,
+    /// The url of the api.
+    url: String,
+    /// The name of the rerank model.
+    model: String,
+    /// The api key, not needed by a local server.
+    api_key: Option<ApiKey>,
+}
+
+impl RerankClient {
+    /// Create a new RerankClient.
+    pub fn new(provider: RerankProvider, url: &str, model: &str) -> Self {
+        RerankClient {
+            provider,
+            url: url.to_string(),
+            model: model.to_string(),
+            api_key: None,
+        }
+    }
+    /// Create a new RerankClient of the Cohere rerank api.
+    pub fn cohere(model: &str) -> Self {
+        Self::new(RerankProvider::Cohere, COHERE_RERANK_URL, model)
+    }
+    /// Create a new RerankClient of the Jina rerank api.
+    pub fn jina(model: &str) -> Self {
+        Self::new(RerankProvider::Jina, JINA_RERANK_URL, model)
+    }
+    /// Score the documents by their relevance to the query, and get the indices of the
+    /// documents with their scores, the most relevant first.
+    pub async fn rerank(
+        &self,
+        query: &str,
+        documents: &[String],
+    ) -> AINodeResult<Vec<(usize, f32)>> {
+        if documents.is_empty() {
+            return Ok(Vec::new());
+        }
+        Ok(self.request(query, documents).await?)
+    }
+    /// Check that the api is reachable and accepts the api key, by scoring one document.
+    pub async fn healthcheck(&self) -> AINodeResult<()> {
+        self.request("ping", &["ping".to_string()]).await?;
+        Ok(())
+    }
+    /// Send the request of the query and the documents, and get the scores.
+    async fn request(&self, query: &str, documents: &[String]) -> RerankResult<Vec<(usize, f32)>> {
+        let body = json!({
+            "model": self.model,
+            "query": query,
+            "documents": documents,
+            "top_n": documents.len(),
+        });
+        let mut request = reqwest::Client::new()
+            .post(&self.url)
+            .header("Content-Type", "application/json")
+            .body(body.to_string());
+        let hosted = self.url == COHERE_RERANK_URL || self.url == JINA_RERANK_URL;
+        if let Some(api_key) = &self.api_key {
+            request = request.header("Authorization", format!("Bearer {}", api_key.expose()));
+        } else if hosted {
+            return Err(RerankError::new(
+                RerankErrorType::ApiKeyError,
+                format!("The {:?} rerank api needs an api key.", self.provider),
+            ));
+        }
+        let endpoint = Some(self.url.clone());
+        let response = request.send().await.map_err(|e| {
+            RerankError::new(
+                RerankErrorType::RequestError,
+                format!("Failed to send request. {}", e),
+            )
+            .endpoint(endpoint.clone())
+        })?;
+        let status = response.status();
+        let text = response.text().await.map_err(|e| {
+            RerankError::new(
+                RerankErrorType::RequestError,
+                format!("Failed to read the response. {}", e),
+            )
+            .status(Some(status.as_u16()))
+            .endpoint(endpoint.clone())
+        })?;
+        if !status.is_success() {
+            return Err(RerankError::new(
+                RerankErrorType::ResponseError,
+                format!("The request is refused. {}", text),
+            )
+            .status(Some(status.as_u16()))
+            .endpoint(endpoint));
+        }
+        let response_error = |message: String| {
+            RerankError::new(RerankErrorType::ResponseError, message).endpoint(endpoint.clone())
+        };
+        let response: Value = serde_json::from_str(&text)
+            .map_err(|e| response_error(format!("The response is not valid json. {}", e)))?;
+        let mut scores = response["results"]
+            .as_array()
+            .ok_or_else(|| response_error("The response has no results.".to_string()))?
+            .iter()
+            .map(|result| {
+                let index = result["index"].as_u64()? as usize;
+                let score = result["relevance_score"].as_f64()? as f32;
+                (index < documents.len()).then_some((index, score))
+            })
+            .collect::<Option<Vec<(usize, f32)>>>()
+            .ok_or_else(|| response_error("A result is not valid.".to_string()))?;
+        scores.sort_by(|a, b| b.1.total_cmp(&a.1));
+        Ok(scores)
+    }
+    /// Set the api key from the environment variable of the name.
+    pub fn api_key_from_env(self, name: &str) -> RerankResult<Self> {
+        self.api_key_from(&KeySource::env(name))
+    }
+    /// Set the api key from the source.
+    pub fn api_key_from(mut self, source: &KeySource) -> RerankResult<Self> {
+        let api_key = source
+            .resolve()
+            .map_err(|e| RerankError::new(RerankErrorType::ApiKeyError, e))?;
+        self.api_key = Some(api_key);
+        Ok(self)
+    }
+    /// Set the api key as builder.
+    pub fn api_key<K: Into<ApiKey>>(mut self, api_key: Option<K>) -> Self {
+        self.api_key = api_key.map(Into::into);
+        self
+    }
+    /// Set the api key.
+    pub fn set_api_key<K: Into<ApiKey>>(&mut self, api_key: Option<K>) {
+        self.api_key = api_key.map(Into::into);
+    }
+    /// Get the api key.
+    pub fn get_api_key(&self) -> Option<&str> {
+        self.api_key.as_ref().map(ApiKey::expose)
+    }
+    /// Get the api of the provider.
+    pub fn get_provider(&self) -> RerankProvider {
+        self.provider
+    }
+    /// Get the url of the api.
+    pub fn get_url(&self) -> &str {
+        &self.url
+    }
+    /// Set the url of the api.
+    pub fn set_url(&mut self, url: &str) {
+        self.url = url.to_string();
+    }
+    /// Get the name of the model.
+    pub fn get_model(&self) -> &str {
+        &self.model
+    }
+    /// Set the name of the model.
+    pub fn set_model(&mut self, model: &str) {
+        self.model = model.to_string();
+    }
+}
@@ -0,0 +1,142 @@
+//! # Secrets
+//!
+//! This module reads the api keys from a secrets manager, for the teams that don't keep any
+//! credential on the machines that run the workflows.
+//!
+//! A `SecretsProvider` gets a secret by its name. There are two providers:
+//! 1. [`vault::VaultSecrets`]: the KV version 2 engine of HashiCorp Vault.
+//! 2. [`aws::AwsSecretsManager`]: AWS Secrets Manager.
+//!
+//! The name of a secret may end with `#field`, to take the field of a secret that holds
+//! several values, like `ai/deepseek#api_key`.
+//!
+//! The providers are registered by name in a `SecretsRegistry`, and a key source refers to
+//! them by that name (see [`super::key::KeySource::Secret`]). A client can also read its key
+//! from a provider with `api_key_from_secrets`. The providers are called when the clients
+//! are built, which is not async, so they send their requests on a thread of their own.
+
+pub mod aws;
+pub mod vault;
+
+pub use aws::AwsSecretsManager;
+pub use vault::VaultSecrets;
+
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::Arc;
+
+/// The trait of the stores the secrets are read from.
+pub trait SecretsProvider: std::fmt::Debug + Send + Sync {
+    /// Get the secret of the name. The error is a message of why it can't be read.
+    fn get_secret(&self, name: &str) -> Result<String, String>;
+}
+
+#[derive(Debug, Clone, Default)]
+/// The struct of the secrets providers by name.
+pub struct SecretsRegistry {
+    providers: HashMap<String, Arc<dyn SecretsProvider>>,
+}
+
+impl SecretsRegistry {
+    /// Create a new empty SecretsRegistry.
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Register a provider as builder.
+    pub fn provider(mut self, name: &str, provider: Arc<dyn SecretsProvider>) -> Self {
+        self.register(name, provider);
+        self
+    }
+    /// Register a provider. A provider with the same name is replaced.
+    pub fn register(&mut self, name: &str, provider: Arc<dyn SecretsProvider>) {
+        self.providers.insert(name.to_string(), provider);
+    }
+    /// Get a provider by its name.
+    pub fn get(&self, name: &str) -> Option<&Arc<dyn SecretsProvider>> {
+        self.providers.get(name)
+    }
+    /// Get the secret of the name from the provider of the name.
+    pub fn get_secret(&self, provider: &str, name: &str) -> Result<String, String> {
+        self.get(provider)
+            .ok_or_else(|| format!("No secrets provider is named {}.", provider))?
+            .get_secret(name)
+    }
+}
+
+/// Split the name of a secret into its path and its field, like `ai/deepseek#api_key`.
+pub(crate) fn split_field(name: &str) -> (&str, Option<&str>) {
+    match name.split_once('#') {
+        Some((path, field)) => (path, Some(field)),
+        None => (name, None),
+    }
+}
+
+/// Get the field of a secret that holds a json object.
+pub(crate) fn json_field(
+    secret: &serde_json::Value,
+    name: &str,
+    field: &str,
+) -> Result<String, String> {
+    secret
+        .get(field)
+        .and_then(serde_json::Value::as_str)
+        .map(str::to_string)
+        .ok_or_else(|| format!("The secret {} has no field {}.", name, field))
+}
+
+/// Run the request to its end on a thread of its own, with a runtime of its own, so it can
+/// be sent from a function that is not async, inside a runtime or not.
+pub(crate) fn block_on<F>(request: F) -> Result<String, String>
+where
+    F: Future<Output = Result<String, String>> + Send,
+{
+    std::thread::scope(|scope| {
+        scope
+            .spawn(|| {
+                tokio::runtime::Builder::new_current_thread()
+                    .enable_all()
+                    .build()
+                    .map_err(|e| format!("Failed to start the runtime of the request. {}", e))?
+                    .block_on(request)
+            })
+            .join()
+            .unwrap_or_else(|_| Err("The request of the secret panicked.".to_string()))
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[derive(Debug)]
+    struct Fixed;
+
+    impl SecretsProvider for Fixed {
+        fn get_secret(&self, name: &str) -> Result<String, String> {
+            match split_field(name) {
+                ("deepseek", Some("api_key")) => Ok("sk-secret".to_string()),
+                _ => Err(format!("No secret {}.", name)),
+            }
+        }
+    }
+
+    #[test]
+    fn registry_and_fields() {
+        let secrets = SecretsRegistry::new().provider("fixed", Arc::new(Fixed));
+        assert_eq!(
+            secrets.get_secret("fixed", "deepseek#api_key").unwrap(),
+            "sk-secret"
+        );
+        assert!(secrets.get_secret("fixed", "deepseek").is_err());
+        assert_eq!(
+            secrets.get_secret("vault", "deepseek").unwrap_err(),
+            "No secrets provider is named vault."
+        );
+        assert_eq!(split_field("a/b"), ("a/b", None));
+
+        // the requests are sent inside a runtime too
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        let secret = rt.block_on(async { block_on(async { Ok("inside".to_string()) }) });
+        assert_eq!(secret.unwrap(), "inside");
+    }
+}
@@ -0,0 +1,272 @@
+//! # AWS
+//!
+//! This module reads the secrets from AWS Secrets Manager, with the `GetSecretValue` api.
+//!
+//! The requests are signed with the AWS Signature Version 4 of the credentials. The secret
+//! `prod/deepseek#api_key` is the field `api_key` of the secret `prod/deepseek`, whose
+//! string is a json object. A secret without a field is the whole string.
+
+use super::super::key::ApiKey;
+use super::{block_on, json_field, split_field, SecretsProvider};
+
+use chrono::Utc;
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+
+/// The name of the service in the signatures.
+const SERVICE: &str = "secretsmanager";
+/// The target of the `GetSecretValue` api.
+const TARGET: &str = "secretsmanager.GetSecretValue";
+/// The type of the requests.
+const CONTENT_TYPE: &str = "application/x-amz-json-1.1";
+
+#[derive(Debug, Clone)]
+/// The struct of the AWS Secrets Manager provider.
+pub struct AwsSecretsManager {
+    /// The region, like `us-east-1`.
+    region: String,
+    /// The access key id of the credentials.
+    access_key_id: String,
+    /// The secret access key of the credentials.
+    secret_access_key: ApiKey,
+    /// The session token of the temporary credentials.
+    session_token: Option<ApiKey>,
+    /// The url of the api, instead of the one of the region.
+    endpoint: Option<String>,
+}
+
+impl AwsSecretsManager {
+    /// Create a new AwsSecretsManager of the region with the credentials.
+    pub fn new(region: &str, access_key_id: &str, secret_access_key: &str) -> Self {
+        AwsSecretsManager {
+            region: region.to_string(),
+            access_key_id: access_key_id.to_string(),
+            secret_access_key: secret_access_key.into(),
+            session_token: None,
+            endpoint: None,
+        }
+    }
+    /// Create a new AwsSecretsManager of the region and the credentials of the environment
+    /// variables `AWS_REGION`, `AWS_ACCESS_KEY_ID`, `AWS_SECRET_ACCESS_KEY` and
+    /// `AWS_SESSION_TOKEN`, like the AWS cli.
+    pub fn from_env() -> Result<Self, String> {
+        let var = |name: &str| {
+            std::env::var(name).map_err(|_| format!("Environment variable {} not found.", name))
+        };
+        let region = var("AWS_REGION").or_else(|_| var("AWS_DEFAULT_REGION"))?;
+        Ok(Self::new(
+            &region,
+            &var("AWS_ACCESS_KEY_ID")?,
+            &var("AWS_SECRET_ACCESS_KEY")?,
+        )
+        .session_token(std::env::var("AWS_SESSION_TOKEN").ok()))
+    }
+    /// Set the session token of temporary credentials as builder.
+    pub fn session_token(mut self, session_token: Option<String>) -> Self {
+        self.session_token = session_token.map(ApiKey::new);
+        self
+    }
+    /// Set the url of the api as builder, like the one of a local mock. By default it is the
+    /// one of the region.
+    pub fn endpoint(mut self, endpoint: Option<String>) -> Self {
+        self.endpoint = endpoint;
+        self
+    }
+    /// Get the region.
+    pub fn get_region(&self) -> &str {
+        &self.region
+    }
+    /// Get the url of the api.
+    pub fn get_endpoint(&self) -> String {
+        self.endpoint
+            .clone()
+            .unwrap_or_else(|| format!("https://{}.{}.amazonaws.com", SERVICE, self.region))
+    }
+    /// Get the headers of the signed request of the body, sent to the host at the time
+    /// `amz_date`, like `20250101T000000Z`.
+    fn sign(&self, host: &str, amz_date: &str, body: &str) -> Vec<(&'static str, String)> {
+        let date = &amz_date[..8];
+        let mut headers = vec![
+            ("content-type", CONTENT_TYPE.to_string()),
+            ("host", host.to_string()),
+            ("x-amz-date", amz_date.to_string()),
+        ];
+        if let Some(token) = &self.session_token {
+            headers.push(("x-amz-security-token", token.expose().to_string()));
+        }
+        headers.push(("x-amz-target", TARGET.to_string()));
+        let signed: Vec<&str> = headers.iter().map(|(name, _)| *name).collect();
+        let signed = signed.join(";");
+        let canonical_headers: String = headers
+            .iter()
+            .map(|(name, value)| format!("{}:{}\n", name, value))
+            .collect();
+        let canonical = format!(
+            "POST\n/\n\n{}\n{}\n{}",
+            canonical_headers,
+            signed,
+            hex(&Sha256::digest(body))
+        );
+        let scope = format!("{}/{}/{}/aws4_request", date, self.region, SERVICE);
+        let string_to_sign = format!(
+            "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+            amz_date,
+            scope,
+            hex(&Sha256::digest(canonical))
+        );
+        let key = [date, &self.region, SERVICE, "aws4_request"].iter().fold(
+            format!("AWS4{}", self.secret_access_key.expose()).into_bytes(),
+            |key, part| hmac(&key, part.as_bytes()),
+        );
+        let signature = hex(&hmac(&key, string_to_sign.as_bytes()));
+        headers.push((
+            "authorization",
+            format!(
+                "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+                self.access_key_id, scope, signed, signature
+            ),
+        ));
+        headers.retain(|(name, _)| *name != "host");
+        headers
+    }
+    /// Read the secret from the api.
+    async fn read(&self, name: &str) -> Result<String, String> {
+        let (id, field) = split_field(name);
+        let endpoint = self.get_endpoint();
+        let url = reqwest::Url::parse(&endpoint)
+            .map_err(|e| format!("The endpoint {} is not valid. {}", endpoint, e))?;
+        let host = match (url.host_str(), url.port()) {
+            (Some(host), Some(port)) => format!("{}:{}", host, port),
+            (Some(host), None) => host.to_string(),
+            (None, _) => return Err(format!("The endpoint {} has no host.", endpoint)),
+        };
+        let body = json!({ "SecretId": id }).to_string();
+        let amz_date = Utc::now().format("%Y%m%dT%H%M%SZ").to_string();
+        let mut request = reqwest::Client::new().post(url);
+        for (name, value) in self.sign(&host, &amz_date, &body) {
+            request = request.header(name, value);
+        }
+        let response = request
+            .body(body)
+            .send()
+            .await
+            .map_err(|e| format!("Failed to reach AWS Secrets Manager. {}", e))?;
+        let status = response.status();
+        let answer: Value = response
+            .text()
+            .await
+            .ok()
+            .and_then(|text| serde_json::from_str(&text).ok())
+            .ok_or_else(|| "The answer of AWS Secrets Manager is not valid json.".to_string())?;
+        if !status.is_success() {
+            return Err(format!(
+                "AWS Secrets Manager answered {} for the secret {}. {}",
+                status,
+                id,
+                answer["message"]
+                    .as_str()
+                    .or(answer["Message"].as_str())
+                    .unwrap_or_default()
+            ));
+        }
+        let secret = answer["SecretString"]
+            .as_str()
+            .ok_or_else(|| format!("The secret {} has no string.", id))?;
+        match field {
+            Some(field) => {
+                let object: Value = serde_json::from_str(secret)
+                    .map_err(|_| format!("The secret {} is not a json object.", id))?;
+                json_field(&object, id, field)
+            }
+            None => Ok(secret.to_string()),
+        }
+    }
+}
+
+impl SecretsProvider for AwsSecretsManager {
+    fn get_secret(&self, name: &str) -> Result<String, String> {
+        block_on(self.read(name))
+    }
+}
+
+/// Get the HMAC-SHA256 of the data with the key.
+fn hmac(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut block = [0; 64];
+    if key.len() > block.len() {
+        block[..32].copy_from_slice(&Sha256::digest(key));
+    } else {
+        block[..key.len()].copy_from_slice(key);
+    }
+    let mut inner = Sha256::new();
+    inner.update(block.map(|byte| byte ^ 0x36));
+    inner.update(data);
+    let mut outer = Sha256::new();
+    outer.update(block.map(|byte| byte ^ 0x5c));
+    outer.update(inner.finalize());
+    outer.finalize().to_vec()
+}
+
+/// Get the bytes in lowercase hex.
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+    use tokio::runtime::Runtime;
+
+    #[test]
+    fn sign_and_read() {
+        // RFC 4231, test case 2
+        assert_eq!(
+            hex(&hmac(b"Jefe", b"what do ya want for nothing?")),
+            "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+        let aws = AwsSecretsManager::new("us-east-1", "AKIDEXAMPLE", "secret")
+            .session_token(Some("token".to_string()));
+        let headers = aws.sign(
+            "secretsmanager.us-east-1.amazonaws.com",
+            "20250101T000000Z",
+            "{}",
+        );
+        let authorization = &headers.last().unwrap().1;
+        assert!(authorization.starts_with(
+            "AWS4-HMAC-SHA256 Credential=AKIDEXAMPLE/20250101/us-east-1/secretsmanager/aws4_request, SignedHeaders=content-type;host;x-amz-date;x-amz-security-token;x-amz-target, Signature="
+        ));
+        assert_eq!(
+            aws.sign(
+                "secretsmanager.us-east-1.amazonaws.com",
+                "20250101T000000Z",
+                "{}"
+            ),
+            headers
+        );
+        assert!(!format!("{:?}", aws).contains("secret\""));
+
+        let rt = Runtime::new().unwrap();
+        let listener = rt.block_on(TcpListener::bind("127.0.0.1:0")).unwrap();
+        let endpoint = format!("http://{}", listener.local_addr().unwrap());
+        let server = rt.spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut request = [0; 4096];
+            let read = stream.read(&mut request).await.unwrap();
+            let body = r#"{"Name": "prod/deepseek", "SecretString": "{\"api_key\": \"sk-aws\"}"}"#;
+            let response = format!(
+                "HTTP/1.1 200 OK\r\nContent-Type: application/x-amz-json-1.1\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                body.len(),
+                body
+            );
+            stream.write_all(response.as_bytes()).await.unwrap();
+            String::from_utf8_lossy(&request[..read]).to_lowercase()
+        });
+        let aws = aws.endpoint(Some(endpoint));
+        assert_eq!(aws.get_secret("prod/deepseek#api_key").unwrap(), "sk-aws");
+        let request = rt.block_on(server).unwrap();
+        assert!(request.contains("x-amz-target: secretsmanager.getsecretvalue"));
+        assert!(request.contains(r#"{"secretid":"prod/deepseek"}"#));
+    }
+}
@@ -0,0 +1,156 @@
+//! # Vault
+//!
+//! This module reads the secrets from the KV version 2 engine of HashiCorp Vault.
+//!
+//! The secret `ai/deepseek#api_key` is the field `api_key` of the secret at the path
+//! `ai/deepseek` of the engine mounted at `mount`, `secret` by default. A secret without a
+//! field is the field `value`.
+
+use super::super::key::ApiKey;
+use super::{block_on, json_field, split_field, SecretsProvider};
+
+use serde_json::Value;
+
+/// The field of a secret without a field.
+const DEFAULT_FIELD: &str = "value";
+
+#[derive(Debug, Clone)]
+/// The struct of the Vault secrets provider.
+pub struct VaultSecrets {
+    /// The address of the server, like `https://vault.example.com:8200`.
+    address: String,
+    /// The token of the requests.
+    token: ApiKey,
+    /// Where the KV engine is mounted.
+    mount: String,
+    /// The namespace of Vault Enterprise.
+    namespace: Option<String>,
+}
+
+impl VaultSecrets {
+    /// Create a new VaultSecrets of the server with the token.
+    pub fn new(address: &str, token: &str) -> Self {
+        VaultSecrets {
+            address: address.trim_end_matches('/').to_string(),
+            token: token.into(),
+            mount: Self::default_mount(),
+            namespace: None,
+        }
+    }
+    /// Create a new VaultSecrets of the server and the token of the environment variables
+    /// `VAULT_ADDR` and `VAULT_TOKEN`, like the Vault cli.
+    pub fn from_env() -> Result<Self, String> {
+        let var = |name: &str| {
+            std::env::var(name).map_err(|_| format!("Environment variable {} not found.", name))
+        };
+        Ok(Self::new(&var("VAULT_ADDR")?, &var("VAULT_TOKEN")?)
+            .namespace(std::env::var("VAULT_NAMESPACE").ok()))
+    }
+    /// Set where the KV engine is mounted as builder.
+    pub fn mount(mut self, mount: &str) -> Self {
+        self.mount = mount.trim_matches('/').to_string();
+        self
+    }
+    /// Get where the KV engine is mounted.
+    pub fn get_mount(&self) -> &str {
+        &self.mount
+    }
+    /// The default mount is `secret`.
+    pub fn default_mount() -> String {
+        "secret".to_string()
+    }
+    /// Set the namespace as builder.
+    pub fn namespace(mut self, namespace: Option<String>) -> Self {
+        self.namespace = namespace;
+        self
+    }
+    /// Get the address of the server.
+    pub fn get_address(&self) -> &str {
+        &self.address
+    }
+    /// Read the secret from the server.
+    async fn read(&self, name: &str) -> Result<String, String> {
+        let (path, field) = split_field(name);
+        let url = format!(
+            "{}/v1/{}/data/{}",
+            self.address,
+            self.mount,
+            path.trim_start_matches('/')
+        );
+        let mut request = reqwest::Client::new()
+            .get(&url)
+            .header("X-Vault-Token", self.token.expose());
+        if let Some(namespace) = &self.namespace {
+            request = request.header("X-Vault-Namespace", namespace);
+        }
+        let response = request
+            .send()
+            .await
+            .map_err(|e| format!("Failed to reach Vault at {}. {}", self.address, e))?;
+        let status = response.status();
+        if !status.is_success() {
+            return Err(format!(
+                "Vault answered {} for the secret {}.",
+                status, path
+            ));
+        }
+        let body: Value = response
+            .text()
+            .await
+            .ok()
+            .and_then(|text| serde_json::from_str(&text).ok())
+            .ok_or_else(|| "The answer of Vault is not valid json.".to_string())?;
+        json_field(&body["data"]["data"], path, field.unwrap_or(DEFAULT_FIELD))
+    }
+}
+
+impl SecretsProvider for VaultSecrets {
+    fn get_secret(&self, name: &str) -> Result<String, String> {
+        block_on(self.read(name))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+    use tokio::runtime::Runtime;
+
+    #[test]
+    fn read_kv_secret() {
+        let rt = Runtime::new().unwrap();
+        let listener = rt.block_on(TcpListener::bind("127.0.0.1:0")).unwrap();
+        let address = format!("http://{}", listener.local_addr().unwrap());
+        let server = rt.spawn(async move {
+            let mut requests = Vec::new();
+            for _ in 0..2 {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let mut request = [0; 4096];
+                let read = stream.read(&mut request).await.unwrap();
+                requests.push(String::from_utf8_lossy(&request[..read]).to_string());
+                let body = r#"{"data": {"data": {"api_key": "sk-vault"}, "metadata": {}}}"#;
+                let response = format!(
+                    "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    body.len(),
+                    body
+                );
+                stream.write_all(response.as_bytes()).await.unwrap();
+            }
+            requests
+        });
+        let vault = VaultSecrets::new(&address, "s.token").mount("kv");
+        assert_eq!(vault.get_secret("ai/deepseek#api_key").unwrap(), "sk-vault");
+        assert_eq!(
+            vault.get_secret("ai/deepseek").unwrap_err(),
+            "The secret ai/deepseek has no field value."
+        );
+        let requests = rt.block_on(server).unwrap();
+        assert!(requests[0].starts_with("GET /v1/kv/data/ai/deepseek "));
+        assert!(requests[0]
+            .to_lowercase()
+            .contains("x-vault-token: s.token"));
+        assert!(!format!("{:?}", vault).contains("s.token"));
+    }
+}
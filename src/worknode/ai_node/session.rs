@@ -0,0 +1,150 @@
+//! # Session
+//!
+//! This module manages many named conversations in one process. Every session is an AI node
+//! cloned from a template, so it has its own history and its own copy of the provider
+//! configuration, and sessions can be used concurrently.
+
+use super::AINode;
+use crate::error::ai_node_error::{AINodeError, AINodeErrorType, AINodeResult};
+
+use std::collections::HashMap;
+use std::sync::{Arc, PoisonError, RwLock, RwLockReadGuard, RwLockWriteGuard};
+use tokio::sync::Mutex;
+
+/// A session shared between tasks.
+pub type Session = Arc<Mutex<AINode>>;
+
+#[derive(Debug)]
+/// The struct of the manager of the sessions.
+pub struct SessionManager {
+    /// The AI node that new sessions are cloned from.
+    template: AINode,
+    /// The sessions by name.
+    sessions: RwLock<HashMap<String, Session>>,
+}
+
+impl SessionManager {
+    /// Create a new SessionManager.
+    pub fn new(template: AINode) -> Self {
+        SessionManager {
+            template,
+            sessions: RwLock::new(HashMap::new()),
+        }
+    }
+    /// Get the template of the sessions.
+    pub fn get_template(&self) -> &AINode {
+        &self.template
+    }
+    /// Create a new session from the template.
+    pub fn create(&self, name: &str) -> AINodeResult<Session> {
+        self.create_with(name, self.template.clone())
+    }
+    /// Create a new session with a given AI node, for a session that needs a different
+    /// provider configuration than the template.
+    pub fn create_with(&self, name: &str, node: AINode) -> AINodeResult<Session> {
+        let mut sessions = self.write();
+        if sessions.contains_key(name) {
+            return Err(AINodeError::new(
+                AINodeErrorType::SessionError,
+                format!("Session {} already exists.", name),
+            ));
+        }
+        let session = Arc::new(Mutex::new(node));
+        sessions.insert(name.to_string(), session.clone());
+        Ok(session)
+    }
+    /// Get a session by name.
+    pub fn get(&self, name: &str) -> Option<Session> {
+        self.read().get(name).cloned()
+    }
+    /// Get a session by name, or create it from the template if it doesn't exist.
+    pub fn get_or_create(&self, name: &str) -> Session {
+        let mut sessions = self.write();
+        sessions
+            .entry(name.to_string())
+            .or_insert_with(|| Arc::new(Mutex::new(self.template.clone())))
+            .clone()
+    }
+    /// Fork a session into a new one with a copy of its current history.
+    pub async fn fork(&self, from: &str, to: &str) -> AINodeResult<Session> {
+        let source = self.get(from).ok_or(AINodeError::new(
+            AINodeErrorType::SessionError,
+            format!("Session {} doesn't exist.", from),
+        ))?;
+        let node = source.lock().await.clone();
+        self.create_with(to, node)
+    }
+    /// Close a session and get it back.
+    pub fn close(&self, name: &str) -> Option<Session> {
+        self.write().remove(name)
+    }
+    /// Get the names of all sessions.
+    pub fn names(&self) -> Vec<String> {
+        self.read().keys().cloned().collect()
+    }
+    /// Lock the sessions to read them. A panic in another thread doesn't make them invalid.
+    fn read(&self) -> RwLockReadGuard<'_, HashMap<String, Session>> {
+        self.sessions.read().unwrap_or_else(PoisonError::into_inner)
+    }
+    /// Lock the sessions to change them.
+    fn write(&self) -> RwLockWriteGuard<'_, HashMap<String, Session>> {
+        self.sessions
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+    /// Execute the AI node of a session with the input.
+    pub async fn execute(&self, name: &str, input: String) -> AINodeResult<String> {
+        let session = self.get(name).ok_or(AINodeError::new(
+            AINodeErrorType::SessionError,
+            format!("Session {} doesn't exist.", name),
+        ))?;
+        let mut node = session.lock().await;
+        node.execute(input).await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::deepseek::{DeepSeekClient, DeepSeekModel, DEEPSEEK_API_URL};
+    use super::super::{AIService, Chat, Role};
+    use super::*;
+    use tokio::runtime::Runtime;
+
+    fn manager() -> SessionManager {
+        let client = DeepSeekClient::new(DEEPSEEK_API_URL, DeepSeekModel::DeepseekChat);
+        SessionManager::new(
+            AINode::new(AIService::new_deepseek(client)).role(Some("You are a cat".to_string())),
+        )
+    }
+
+    #[test]
+    fn create_fork_close() {
+        let rt = Runtime::new().unwrap();
+        let manager = manager();
+        let alice = manager.create("alice").unwrap();
+        assert!(manager.create("alice").is_err());
+        rt.block_on(alice.lock())
+            .push_history(Chat::new(Role::User, "Hi".to_string()));
+
+        let fork = rt.block_on(manager.fork("alice", "bob")).unwrap();
+        assert_eq!(rt.block_on(fork.lock()).get_history().len(), 2);
+        rt.block_on(fork.lock())
+            .push_history(Chat::new(Role::User, "Bye".to_string()));
+        assert_eq!(rt.block_on(alice.lock()).get_history().len(), 2);
+
+        let mut names = manager.names();
+        names.sort();
+        assert_eq!(names, vec!["alice", "bob"]);
+        assert!(manager.close("alice").is_some());
+        assert!(manager.get("alice").is_none());
+        assert_eq!(
+            manager
+                .get_or_create("carol")
+                .try_lock()
+                .unwrap()
+                .get_history()
+                .len(),
+            1
+        );
+    }
+}
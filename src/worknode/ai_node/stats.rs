@@ -0,0 +1,251 @@
+//! # Stats
+//!
+//! This module keeps the latency and the reliability of the requests sent to the AI services,
+//! by provider and model, so a program can see which service is slow or failing, and choose
+//! where to send the next requests with real data.
+//!
+//! A `ProviderStats` is given to the clients (see
+//! [`super::deepseek::DeepSeekClient::stats`] and
+//! [`super::embedding::EmbeddingClient::stats`]), which record the time of every request they
+//! send and whether it failed. The replayed requests are not recorded. The clones of a
+//! `ProviderStats` share the same samples, so one `ProviderStats` can be given to many
+//! clients.
+//!
+//! The samples are rolling: only the requests of the last `window` are kept, and at most
+//! `MAX_SAMPLES` of them per provider and model. `get` sums them up as a `LatencyStats`, with
+//! the percentiles of the latency, the error rate and the throughput.
+
+use serde::{Deserialize, Serialize};
+
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{Duration, Instant};
+
+/// The max number of samples kept for a provider and a model.
+pub const MAX_SAMPLES: usize = 1024;
+
+#[derive(Debug, Clone, Copy)]
+/// The struct of a request that was sent.
+struct Sample {
+    /// When the request ended.
+    at: Instant,
+    /// The time the request took.
+    latency: Duration,
+    /// Whether the request succeeded.
+    succeeded: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+/// The struct of the latency and the reliability of the recent requests of a provider and a
+/// model.
+pub struct LatencyStats {
+    /// The provider, like `deepseek`.
+    pub provider: String,
+    /// The model.
+    pub model: String,
+    /// The number of requests.
+    pub requests: usize,
+    /// The number of failed requests.
+    pub errors: usize,
+    /// The share of the requests that failed, between 0 and 1.
+    pub error_rate: f64,
+    /// The requests per second over the window.
+    pub throughput: f64,
+    /// The mean latency.
+    pub mean: Duration,
+    /// The median latency.
+    pub p50: Duration,
+    /// The 90th percentile of the latency.
+    pub p90: Duration,
+    /// The 99th percentile of the latency.
+    pub p99: Duration,
+}
+
+#[derive(Debug)]
+/// The struct of the samples of the stats.
+struct StatsState {
+    /// How long the samples are kept.
+    window: Duration,
+    /// The samples by provider and model, oldest first.
+    samples: BTreeMap<(String, String), VecDeque<Sample>>,
+}
+
+#[derive(Debug, Clone)]
+/// The struct of the latency and reliability stats of the AI services. The clones share the
+/// same samples.
+pub struct ProviderStats {
+    state: Arc<Mutex<StatsState>>,
+}
+
+impl Default for ProviderStats {
+    fn default() -> Self {
+        ProviderStats {
+            state: Arc::new(Mutex::new(StatsState {
+                window: Self::default_window(),
+                samples: BTreeMap::new(),
+            })),
+        }
+    }
+}
+
+impl ProviderStats {
+    /// Create a new ProviderStats without any sample.
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Set how long the samples are kept as builder.
+    pub fn window(self, window: Duration) -> Self {
+        self.set_window(window);
+        self
+    }
+    /// Set how long the samples are kept.
+    pub fn set_window(&self, window: Duration) {
+        self.lock().window = window;
+    }
+    /// Get how long the samples are kept.
+    pub fn get_window(&self) -> Duration {
+        self.lock().window
+    }
+    /// The default window is 5 minutes.
+    pub fn default_window() -> Duration {
+        Duration::from_secs(300)
+    }
+    /// Record a request of the provider and the model that took the latency.
+    pub fn record(&self, provider: &str, model: &str, latency: Duration, succeeded: bool) {
+        let now = Instant::now();
+        let mut state = self.lock();
+        let window = state.window;
+        let samples = state
+            .samples
+            .entry((provider.to_string(), model.to_string()))
+            .or_default();
+        samples.push_back(Sample {
+            at: now,
+            latency,
+            succeeded,
+        });
+        if samples.len() > MAX_SAMPLES {
+            samples.pop_front();
+        }
+        prune(samples, now, window);
+    }
+    /// Get the stats of the recent requests of the provider and the model, or `None` if there
+    /// is no recent request.
+    pub fn get(&self, provider: &str, model: &str) -> Option<LatencyStats> {
+        let now = Instant::now();
+        let mut state = self.lock();
+        let window = state.window;
+        let samples = state
+            .samples
+            .get_mut(&(provider.to_string(), model.to_string()))?;
+        prune(samples, now, window);
+        summarize(provider, model, samples, window)
+    }
+    /// Get the stats of the recent requests of every provider and model.
+    pub fn get_all(&self) -> Vec<LatencyStats> {
+        let now = Instant::now();
+        let mut state = self.lock();
+        let window = state.window;
+        state
+            .samples
+            .iter_mut()
+            .filter_map(|((provider, model), samples)| {
+                prune(samples, now, window);
+                summarize(provider, model, samples, window)
+            })
+            .collect()
+    }
+    /// Forget every sample.
+    pub fn clear(&self) {
+        self.lock().samples.clear();
+    }
+    /// Lock the samples. A panic in another thread doesn't make them invalid.
+    fn lock(&self) -> std::sync::MutexGuard<'_, StatsState> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// Drop the samples older than the window.
+fn prune(samples: &mut VecDeque<Sample>, now: Instant, window: Duration) {
+    while samples
+        .front()
+        .is_some_and(|sample| now.duration_since(sample.at) > window)
+    {
+        samples.pop_front();
+    }
+}
+
+/// Sum up the samples, or `None` if there is none.
+fn summarize(
+    provider: &str,
+    model: &str,
+    samples: &VecDeque<Sample>,
+    window: Duration,
+) -> Option<LatencyStats> {
+    if samples.is_empty() {
+        return None;
+    }
+    let requests = samples.len();
+    let errors = samples.iter().filter(|sample| !sample.succeeded).count();
+    let mut latencies: Vec<Duration> = samples.iter().map(|sample| sample.latency).collect();
+    latencies.sort();
+    let total: Duration = latencies.iter().sum();
+    Some(LatencyStats {
+        provider: provider.to_string(),
+        model: model.to_string(),
+        requests,
+        errors,
+        error_rate: errors as f64 / requests as f64,
+        throughput: requests as f64 / window.as_secs_f64().max(f64::EPSILON),
+        mean: total / requests as u32,
+        p50: percentile(&latencies, 50),
+        p90: percentile(&latencies, 90),
+        p99: percentile(&latencies, 99),
+    })
+}
+
+/// Get the percentile of the sorted latencies, by the nearest rank.
+fn percentile(sorted: &[Duration], percent: usize) -> Duration {
+    let rank = (sorted.len() * percent).div_ceil(100).max(1);
+    sorted[rank - 1]
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn rolling_stats() {
+        let stats = ProviderStats::new();
+        for millis in 1..=100 {
+            stats.record(
+                "deepseek",
+                "deepseek-chat",
+                Duration::from_millis(millis),
+                millis % 10 != 0,
+            );
+        }
+        stats
+            .clone()
+            .record("ollama", "nomic", Duration::from_secs(1), false);
+
+        let chat = stats.get("deepseek", "deepseek-chat").unwrap();
+        assert_eq!(chat.requests, 100);
+        assert_eq!(chat.errors, 10);
+        assert_eq!(chat.error_rate, 0.1);
+        assert_eq!(chat.p50, Duration::from_millis(50));
+        assert_eq!(chat.p90, Duration::from_millis(90));
+        assert_eq!(chat.p99, Duration::from_millis(99));
+        assert_eq!(chat.mean, Duration::from_micros(50_500));
+        assert_eq!(chat.throughput, 100.0 / 300.0);
+        assert_eq!(stats.get("ollama", "nomic").unwrap().error_rate, 1.0);
+        assert_eq!(stats.get("deepseek", "deepseek-reasoner"), None);
+        assert_eq!(stats.get_all().len(), 2);
+
+        // the samples older than the window are dropped
+        stats.set_window(Duration::ZERO);
+        std::thread::sleep(Duration::from_millis(5));
+        assert_eq!(stats.get("deepseek", "deepseek-chat"), None);
+        assert!(stats.get_all().is_empty());
+    }
+}
@@ -0,0 +1,124 @@
+//! # Stream
+//!
+//! This module lets an AI node give its output piece by piece while the AI service is still
+//! generating it, so interactive frontends can show the tokens as they arrive.
+//!
+//! The answer is added to the history of the node when the stream is finished. Json output
+//! is not validated in stream mode.
+
+use super::deepseek::{DeepSeekStream, DeepSeekUsage};
+use super::{AINode, Chat, Role};
+use crate::error::ai_node_error::{AINodeError, AINodeErrorType, AINodeResult};
+
+#[derive(Debug, Clone)]
+/// The enum of the events of a stream.
+pub enum StreamEvent {
+    /// A piece of the answer.
+    Delta(String),
+    /// A piece of the reasoning, only given by reasoning models.
+    Reasoning(String),
+    /// The end of the stream, with the whole answer and the usage statistics.
+    Done {
+        content: String,
+        usage: Option<DeepSeekUsage>,
+    },
+}
+
+#[derive(Debug)]
+/// The struct of the stream of the output of an AI node.
+pub struct ChatStream<'a> {
+    /// The AI node that the answer belongs to.
+    node: &'a mut AINode,
+    /// The stream of the AI service.
+    stream: DeepSeekStream,
+    /// The answer received so far.
+    content: String,
+    /// The usage statistics, given in the last chunk.
+    usage: Option<DeepSeekUsage>,
+    /// Whether the `Done` event or an error is given.
+    finished: bool,
+}
+
+impl<'a> ChatStream<'a> {
+    pub(super) fn new(node: &'a mut AINode, stream: DeepSeekStream) -> Self {
+        ChatStream {
+            node,
+            stream,
+            content: String::new(),
+            usage: None,
+            finished: false,
+        }
+    }
+    /// Get the next event, or `None` after the `Done` event or an error.
+    pub async fn next(&mut self) -> Option<AINodeResult<StreamEvent>> {
+        if self.finished {
+            return None;
+        }
+        loop {
+            let chunk = match self.stream.next_chunk().await {
+                Ok(Some(chunk)) => chunk,
+                Ok(None) => return Some(Ok(self.finish())),
+                Err(e) => {
+                    self.finished = true;
+                    return Some(Err(AINodeError::new(
+                        AINodeErrorType::DeepSeekError(Box::new(e)),
+                        "Failed to read the stream from DeepSeek".to_string(),
+                    )));
+                }
+            };
+            if !chunk["usage"].is_null() {
+                match DeepSeekUsage::from_json(&chunk["usage"]) {
+                    Ok(usage) => self.usage = Some(usage),
+                    Err(e) => {
+                        self.finished = true;
+                        return Some(Err(AINodeError::new(
+                            AINodeErrorType::DeepSeekError(Box::new(e)),
+                            "Failed to read the usage from the stream".to_string(),
+                        )));
+                    }
+                }
+            }
+            let delta = &chunk["choices"][0]["delta"];
+            if let Some(reasoning) = delta["reasoning_content"].as_str() {
+                if !reasoning.is_empty() {
+                    return Some(Ok(StreamEvent::Reasoning(reasoning.to_string())));
+                }
+            }
+            if let Some(content) = delta["content"].as_str() {
+                if !content.is_empty() {
+                    self.content.push_str(content);
+                    return Some(Ok(StreamEvent::Delta(content.to_string())));
+                }
+            }
+        }
+    }
+    /// Collect the rest of the stream and get the whole answer.
+    pub async fn collect(mut self) -> AINodeResult<String> {
+        while let Some(event) = self.next().await {
+            if let StreamEvent::Done { content, .. } = event? {
+                return Ok(content);
+            }
+        }
+        Ok(self.content)
+    }
+    /// Add the answer to the history and record the usage statistics.
+    fn finish(&mut self) -> StreamEvent {
+        self.finished = true;
+        let mut chat =
+            Chat::new(Role::Assistant, self.content.clone()).node_uid(self.node.node_uid);
+        if let Some(usage) = self.usage {
+            chat = chat.tokens(
+                Some(usage.get_prompt_tokens()),
+                Some(usage.get_completion_tokens()),
+            );
+            match &mut self.node.service {
+                super::AIService::DeepSeek { client } => client.record_usage(usage),
+            }
+        }
+        self.node.histroy.push(chat);
+        StreamEvent::Done {
+            content: self.content.clone(),
+            usage: self.usage,
+        }
+    }
+}
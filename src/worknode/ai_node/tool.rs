@@ -0,0 +1,380 @@
+//! # Tool
+//!
+//! This module lets the AI service call the functions of the program.
+//!
+//! A tool is a named async closure with a json schema of its parameters. The tools are
+//! registered in a `ToolRegistry` and sent with the request. When the model answers with tool
+//! calls, the AI node invokes the tools, appends their results to the history as tool
+//! messages and asks again, until the model gives a final answer or the step limit is hit.
+//!
+//! A tool returns `Err` with a message when it fails. The message is sent back to the model
+//! as the result of the call, so the model can correct its arguments.
+//!
+//! Every tool has a [`ToolPolicy`]. An `auto` tool is called whenever the model asks, a
+//! `deny` tool is never called, and a `confirm` tool is only called when a human approves the
+//! proposed arguments, which the agent asks for (see [`crate::worknode::agent`]). A tool that
+//! does something destructive, like writing files or sending messages, should be marked
+//! `confirm`. Outside an agent nobody is asked, so a `confirm` tool is refused.
+//!
+//! Some tools are built in, and registered in a registry by their module:
+//! 1. [`web_search`]: search the web.
+//! 2. [`run_code`]: run a python or shell script in a sandbox.
+//! 3. [`calculate`]: evaluate an arithmetic or date expression.
+//! 4. [`filesystem`]: read, list and write the files below some root directories.
+
+pub mod calculate;
+pub mod filesystem;
+pub mod run_code;
+pub mod web_search;
+
+use crate::error::ai_node_error::{AINodeError, AINodeErrorType, AINodeResult};
+
+use json::{object, JsonValue};
+use serde::{Deserialize, Serialize};
+
+use std::collections::HashMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+
+/// The future returned by a tool.
+pub type ToolFuture = Pin<Box<dyn Future<Output = Result<String, String>> + Send>>;
+
+/// The function of a tool, which takes the parsed arguments.
+pub type ToolHandler = Arc<dyn Fn(serde_json::Value) -> ToolFuture + Send + Sync>;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+/// The enum of when a tool may be called.
+pub enum ToolPolicy {
+    /// Call the tool whenever the model asks.
+    #[default]
+    Auto,
+    /// Call the tool only when a human approves the arguments.
+    Confirm,
+    /// Never call the tool.
+    Deny,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+/// The struct of a tool call requested by the model.
+pub struct ToolCall {
+    /// The id of the call, which the tool message answers.
+    pub id: String,
+    /// The name of the tool.
+    pub name: String,
+    /// The arguments of the call, as a json string.
+    pub arguments: String,
+}
+
+impl ToolCall {
+    /// Parse a tool call in the OpenAI format.
+    pub fn from_json(call: &JsonValue) -> AINodeResult<Self> {
+        match (call["id"].as_str(), call["function"]["name"].as_str()) {
+            (Some(id), Some(name)) => Ok(ToolCall {
+                id: id.to_string(),
+                name: name.to_string(),
+                arguments: call["function"]["arguments"]
+                    .as_str()
+                    .unwrap_or("{}")
+                    .to_string(),
+            }),
+            _ => Err(AINodeError::new(
+                AINodeErrorType::ToolError,
+                format!("The tool call is not valid: {}", call.dump()),
+            )),
+        }
+    }
+    /// Convert the tool call to the OpenAI format.
+    pub fn to_json(&self) -> JsonValue {
+        object! {
+            id: self.id.clone(),
+            "type": "function",
+            function: object! {
+                name: self.name.clone(),
+                arguments: self.arguments.clone(),
+            },
+        }
+    }
+}
+
+#[derive(Clone)]
+/// The struct of a tool.
+pub struct Tool {
+    /// The name of the tool.
+    name: String,
+    /// What the tool does, told to the model.
+    description: String,
+    /// The json schema of the parameters.
+    parameters: serde_json::Value,
+    /// The function of the tool.
+    handler: ToolHandler,
+    /// When the tool may be called.
+    policy: ToolPolicy,
+}
+
+impl Tool {
+    /// Get the name of the tool.
+    pub fn get_name(&self) -> &str {
+        &self.name
+    }
+    /// Get the description of the tool.
+    pub fn get_description(&self) -> &str {
+        &self.description
+    }
+    /// Get the json schema of the parameters.
+    pub fn get_parameters(&self) -> &serde_json::Value {
+        &self.parameters
+    }
+    /// Get when the tool may be called.
+    pub fn get_policy(&self) -> ToolPolicy {
+        self.policy
+    }
+    /// Convert the tool to the OpenAI format of the `tools` field of a request.
+    pub fn to_json(&self) -> JsonValue {
+        object! {
+            "type": "function",
+            function: object! {
+                name: self.name.clone(),
+                description: self.description.clone(),
+                parameters: json::parse(&self.parameters.to_string()).unwrap_or(JsonValue::Null),
+            },
+        }
+    }
+}
+
+impl std::fmt::Debug for Tool {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Tool")
+            .field("name", &self.name)
+            .field("description", &self.description)
+            .field("parameters", &self.parameters)
+            .field("policy", &self.policy)
+            .finish()
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+/// The struct of the tools that the AI service can call.
+pub struct ToolRegistry {
+    tools: HashMap<String, Tool>,
+}
+
+impl ToolRegistry {
+    /// Create a new empty ToolRegistry.
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Register a tool as builder. A tool with the same name is replaced.
+    pub fn tool<F, Fut>(
+        mut self,
+        name: &str,
+        description: &str,
+        parameters: serde_json::Value,
+        handler: F,
+    ) -> Self
+    where
+        F: Fn(serde_json::Value) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<String, String>> + Send + 'static,
+    {
+        self.register(name, description, parameters, handler);
+        self
+    }
+    /// Register a tool. A tool with the same name is replaced.
+    pub fn register<F, Fut>(
+        &mut self,
+        name: &str,
+        description: &str,
+        parameters: serde_json::Value,
+        handler: F,
+    ) where
+        F: Fn(serde_json::Value) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<String, String>> + Send + 'static,
+    {
+        let handler: ToolHandler = Arc::new(move |arguments| Box::pin(handler(arguments)));
+        self.tools.insert(
+            name.to_string(),
+            Tool {
+                name: name.to_string(),
+                description: description.to_string(),
+                parameters,
+                handler,
+                policy: ToolPolicy::default(),
+            },
+        );
+    }
+    /// Set when a registered tool may be called as builder. An unknown tool is ignored.
+    pub fn policy(mut self, name: &str, policy: ToolPolicy) -> Self {
+        self.set_policy(name, policy);
+        self
+    }
+    /// Set when a registered tool may be called, and get whether the tool is registered.
+    pub fn set_policy(&mut self, name: &str, policy: ToolPolicy) -> bool {
+        match self.tools.get_mut(name) {
+            Some(tool) => {
+                tool.policy = policy;
+                true
+            }
+            None => false,
+        }
+    }
+    /// Get when a tool may be called, or `None` if there is no such tool.
+    pub fn get_policy(&self, name: &str) -> Option<ToolPolicy> {
+        self.tools.get(name).map(Tool::get_policy)
+    }
+    /// Add a tool that is already built, like one taken from another registry.
+    pub fn insert(&mut self, tool: Tool) {
+        self.tools.insert(tool.name.clone(), tool);
+    }
+    /// Add all the tools of another registry. A tool with the same name is replaced.
+    pub fn extend(&mut self, other: ToolRegistry) {
+        self.tools.extend(other.tools);
+    }
+    /// Get the names of the tools, sorted.
+    pub fn names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.tools.keys().cloned().collect();
+        names.sort();
+        names
+    }
+    /// Remove a tool.
+    pub fn unregister(&mut self, name: &str) -> Option<Tool> {
+        self.tools.remove(name)
+    }
+    /// Get a tool by its name.
+    pub fn get(&self, name: &str) -> Option<&Tool> {
+        self.tools.get(name)
+    }
+    /// Whether there is no tool.
+    pub fn is_empty(&self) -> bool {
+        self.tools.is_empty()
+    }
+    /// Convert the tools to the OpenAI format of the `tools` field of a request, sorted by
+    /// name so the request is stable.
+    pub fn to_json(&self) -> JsonValue {
+        let mut tools: Vec<&Tool> = self.tools.values().collect();
+        tools.sort_by(|a, b| a.name.cmp(&b.name));
+        JsonValue::Array(tools.into_iter().map(Tool::to_json).collect())
+    }
+    /// Invoke the tool of the call and get the content of the tool message. The failures,
+    /// including an unknown tool or invalid arguments, are reported in the content. A tool
+    /// that must be confirmed is refused, since nobody approved the call.
+    pub async fn call(&self, call: &ToolCall) -> String {
+        if self.get_policy(&call.name) == Some(ToolPolicy::Confirm) {
+            return format!(
+                "Error: the tool {} needs the approval of a human, which is not asked here.",
+                call.name
+            );
+        }
+        self.call_approved(call).await
+    }
+    /// Invoke the tool of the call that a human approved, and get the content of the tool
+    /// message.
+    pub async fn call_approved(&self, call: &ToolCall) -> String {
+        let arguments = match serde_json::from_str(&call.arguments) {
+            Ok(arguments) => arguments,
+            Err(e) => return format!("Error: the arguments are not valid json. {}", e),
+        };
+        match self.invoke(&call.name, arguments).await {
+            Ok(result) => result,
+            Err(e) => format!("Error: {}", e),
+        }
+    }
+    /// Invoke a tool with the parsed arguments, and get its result or its failure. A denied
+    /// tool is refused.
+    pub async fn invoke(&self, name: &str, arguments: serde_json::Value) -> Result<String, String> {
+        match self.tools.get(name) {
+            Some(tool) if tool.policy == ToolPolicy::Deny => {
+                Err(format!("the tool {} is not allowed to be called.", name))
+            }
+            Some(tool) => (tool.handler)(arguments).await,
+            None => Err(format!("there is no tool named {}.", name)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use tokio::runtime::Runtime;
+
+    fn registry() -> ToolRegistry {
+        ToolRegistry::new().tool(
+            "add",
+            "Add two numbers",
+            serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "a": { "type": "number" },
+                    "b": { "type": "number" }
+                },
+                "required": ["a", "b"]
+            }),
+            |arguments| async move {
+                match (arguments["a"].as_f64(), arguments["b"].as_f64()) {
+                    (Some(a), Some(b)) => Ok((a + b).to_string()),
+                    _ => Err("a and b should be numbers".to_string()),
+                }
+            },
+        )
+    }
+
+    fn call(name: &str, arguments: &str) -> ToolCall {
+        ToolCall {
+            id: "call_0".to_string(),
+            name: name.to_string(),
+            arguments: arguments.to_string(),
+        }
+    }
+
+    #[test]
+    fn call_registered_tool() {
+        let registry = registry();
+        let rt = Runtime::new().unwrap();
+        assert_eq!(
+            rt.block_on(registry.call(&call("add", r#"{"a": 1, "b": 2}"#))),
+            "3"
+        );
+        assert_eq!(
+            rt.block_on(registry.call(&call("add", r#"{"a": "x"}"#))),
+            "Error: a and b should be numbers"
+        );
+        assert!(rt
+            .block_on(registry.call(&call("sub", "{}")))
+            .starts_with("Error: there is no tool"));
+        assert!(rt
+            .block_on(registry.call(&call("add", "not json")))
+            .starts_with("Error: the arguments"));
+    }
+
+    #[test]
+    fn tool_policies() {
+        let mut registry = registry().policy("add", ToolPolicy::Confirm);
+        assert!(!registry.set_policy("sub", ToolPolicy::Deny));
+        assert_eq!(registry.get_policy("add"), Some(ToolPolicy::Confirm));
+        let rt = Runtime::new().unwrap();
+        let add = call("add", r#"{"a": 1, "b": 2}"#);
+        assert!(rt
+            .block_on(registry.call(&add))
+            .contains("needs the approval"));
+        assert_eq!(rt.block_on(registry.call_approved(&add)), "3");
+
+        registry.set_policy("add", ToolPolicy::Deny);
+        assert!(rt
+            .block_on(registry.call_approved(&add))
+            .contains("not allowed"));
+        assert!(rt
+            .block_on(registry.invoke("add", serde_json::json!({"a": 1, "b": 2})))
+            .is_err());
+    }
+
+    #[test]
+    fn tool_call_json() {
+        let tools = registry().to_json();
+        assert_eq!(tools[0]["function"]["name"], "add");
+        assert_eq!(tools[0]["function"]["parameters"]["required"][1], "b");
+        let call = call("add", r#"{"a": 1, "b": 2}"#);
+        assert_eq!(ToolCall::from_json(&call.to_json()).unwrap(), call);
+        assert!(ToolCall::from_json(&object! { id: "x" }).is_err());
+    }
+}
@@ -0,0 +1,602 @@
+//! # Calculate
+//!
+//! This module gives the AI service a `calculate` tool, so the numbers of an answer are
+//! computed exactly instead of guessed, without running a script.
+//!
+//! The tool evaluates an expression, which can't do anything but compute:
+//! - Numbers, like `12`, `0.5` and `1e-3`, with `+ - * / %`, `^` for the power (`2^-1` is
+//!   `0.5`), `!` for the factorial, and the parentheses.
+//! - The constants `pi` and `e`, and the functions `sqrt`, `abs`, `floor`, `ceil`, `round(x)`
+//!   and `round(x, digits)`, `exp`, `ln`, `log(x)` (base 10) and `log(x, base)`, `sin`, `cos`,
+//!   `tan`, `asin`, `acos`, `atan`, `pow(x, y)`, `min(...)` and `max(...)`.
+//! - Dates in quotes, like `'2024-02-28'` or `'2024-02-28 12:30'`, and `today` and `now`.
+//!   The difference of two dates is a duration, and a duration can be added to a date.
+//! - Durations made by `weeks(n)`, `days(n)`, `hours(n)`, `minutes(n)` and `seconds(n)`, which
+//!   can be added, multiplied and divided. The same functions of a duration give the number
+//!   of their unit, like `days('2025-01-01' - '2024-01-01')`.
+//! - The functions of dates `year`, `month`, `day`, `weekday` (1 for Monday) and
+//!   `add_months(date, n)`.
+
+use super::ToolRegistry;
+
+use chrono::{Datelike, Local, Months, NaiveDate, NaiveDateTime, TimeDelta};
+use serde_json::json;
+
+use std::fmt;
+
+/// The formats of the dates in quotes.
+const DATE_FORMATS: [&str; 4] = [
+    "%Y-%m-%d %H:%M:%S",
+    "%Y-%m-%dT%H:%M:%S",
+    "%Y-%m-%d %H:%M",
+    "%Y-%m-%dT%H:%M",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+/// The enum of the value of an expression.
+pub enum CalcValue {
+    Number(f64),
+    Date(NaiveDateTime),
+    Duration(TimeDelta),
+}
+
+impl fmt::Display for CalcValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CalcValue::Number(number) => write!(f, "{}", format_number(*number)),
+            CalcValue::Date(date) if date.time() == chrono::NaiveTime::MIN => {
+                write!(f, "{}", date.format("%Y-%m-%d"))
+            }
+            CalcValue::Date(date) => write!(f, "{}", date.format("%Y-%m-%d %H:%M:%S")),
+            CalcValue::Duration(duration) => {
+                let sign = if *duration < TimeDelta::zero() {
+                    "-"
+                } else {
+                    ""
+                };
+                let millis = duration.num_milliseconds().unsigned_abs();
+                let parts: Vec<String> = [
+                    (millis / 86_400_000, "day"),
+                    (millis / 3_600_000 % 24, "hour"),
+                    (millis / 60_000 % 60, "minute"),
+                ]
+                .into_iter()
+                .filter(|(count, _)| *count > 0)
+                .map(|(count, unit)| plural(count as f64, unit))
+                .chain(
+                    Some(millis % 60_000)
+                        .filter(|seconds| *seconds > 0 || millis == 0)
+                        .map(|seconds| plural(seconds as f64 / 1000.0, "second")),
+                )
+                .collect();
+                write!(f, "{}{}", sign, parts.join(" "))
+            }
+        }
+    }
+}
+
+/// Evaluate the expression, with `today` and `now` at the local time.
+pub fn evaluate(expression: &str) -> Result<CalcValue, String> {
+    evaluate_at(expression, Local::now().naive_local())
+}
+
+/// Evaluate the expression, with `today` and `now` at the time given.
+pub fn evaluate_at(expression: &str, now: NaiveDateTime) -> Result<CalcValue, String> {
+    let mut parser = Parser {
+        tokens: lex(expression)?,
+        pos: 0,
+        now,
+    };
+    let value = parser.additive()?;
+    match parser.peek() {
+        None => Ok(value),
+        Some(token) => Err(format!("Unexpected {}.", token)),
+    }
+}
+
+/// Register the `calculate` tool in the registry.
+pub fn register(registry: &mut ToolRegistry) {
+    registry.register(
+        "calculate",
+        "Evaluate an arithmetic or date expression exactly. Supports + - * / % ^ !, pi, e, \
+         sqrt, abs, floor, ceil, round(x, digits), exp, ln, log(x, base), sin, cos, tan, asin, \
+         acos, atan, pow, min and max; dates in quotes like '2024-02-28' or '2024-02-28 \
+         12:30', today and now; durations weeks(n), days(n), hours(n), minutes(n) and \
+         seconds(n), which also convert a duration to a number, like days(today - \
+         '2024-01-01'); and year, month, day, weekday and add_months(date, n).",
+        json!({
+            "type": "object",
+            "properties": {
+                "expression": {
+                    "type": "string",
+                    "description": "The expression, like (1 + 2) * 3 or '2024-03-01' - days(30)."
+                }
+            },
+            "required": ["expression"]
+        }),
+        |arguments| async move {
+            let expression = arguments["expression"]
+                .as_str()
+                .ok_or_else(|| "expression should be a string".to_string())?;
+            evaluate(expression).map(|value| value.to_string())
+        },
+    );
+}
+
+#[derive(Debug, Clone, PartialEq)]
+/// The enum of a token of an expression.
+enum Token {
+    Number(f64),
+    /// A date in quotes.
+    Date(String),
+    /// A name of a constant or a function.
+    Ident(String),
+    /// An operator, a parenthesis or a comma.
+    Punct(char),
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Token::Number(number) => write!(f, "`{}`", number),
+            Token::Date(date) => write!(f, "`'{}'`", date),
+            Token::Ident(name) => write!(f, "`{}`", name),
+            Token::Punct(punct) => write!(f, "`{}`", punct),
+        }
+    }
+}
+
+/// Split the expression into tokens.
+fn lex(expression: &str) -> Result<Vec<Token>, String> {
+    let chars: Vec<char> = expression.chars().collect();
+    let mut tokens = Vec::new();
+    let mut pos = 0;
+    while pos < chars.len() {
+        let c = chars[pos];
+        if c.is_whitespace() {
+            pos += 1;
+        } else if c == '\'' || c == '"' {
+            let end = chars[pos + 1..]
+                .iter()
+                .position(|&next| next == c)
+                .ok_or_else(|| "A date is not closed.".to_string())?;
+            tokens.push(Token::Date(chars[pos + 1..pos + 1 + end].iter().collect()));
+            pos += end + 2;
+        } else if c.is_ascii_digit() || c == '.' {
+            let start = pos;
+            while pos < chars.len()
+                && (chars[pos].is_ascii_digit()
+                    || chars[pos] == '.'
+                    || matches!(chars[pos], 'e' | 'E')
+                    || (matches!(chars[pos], '+' | '-') && matches!(chars[pos - 1], 'e' | 'E')))
+            {
+                pos += 1;
+            }
+            let text: String = chars[start..pos].iter().collect();
+            let number = text
+                .parse()
+                .map_err(|_| format!("Invalid number {}.", text))?;
+            tokens.push(Token::Number(number));
+        } else if c.is_alphabetic() || c == '_' {
+            let start = pos;
+            while pos < chars.len() && (chars[pos].is_alphanumeric() || chars[pos] == '_') {
+                pos += 1;
+            }
+            tokens.push(Token::Ident(chars[start..pos].iter().collect()));
+        } else if "+-*/%^!(),".contains(c) {
+            tokens.push(Token::Punct(c));
+            pos += 1;
+        } else {
+            return Err(format!("Unexpected character `{}`.", c));
+        }
+    }
+    Ok(tokens)
+}
+
+/// The parser of an expression, which evaluates it while parsing.
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+    now: NaiveDateTime,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+    fn eat(&mut self, punct: char) -> bool {
+        let found = self.peek() == Some(&Token::Punct(punct));
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+    fn expect(&mut self, punct: char) -> Result<(), String> {
+        match self.eat(punct) {
+            true => Ok(()),
+            false => match self.peek() {
+                Some(token) => Err(format!("Expected `{}`, found {}.", punct, token)),
+                None => Err(format!("Expected `{}` at the end.", punct)),
+            },
+        }
+    }
+    /// Parse the terms joined with `+` and `-`.
+    fn additive(&mut self) -> Result<CalcValue, String> {
+        let mut value = self.multiplicative()?;
+        loop {
+            let op = match () {
+                _ if self.eat('+') => '+',
+                _ if self.eat('-') => '-',
+                _ => return Ok(value),
+            };
+            value = binary(op, value, self.multiplicative()?)?;
+        }
+    }
+    /// Parse the factors joined with `*`, `/` and `%`.
+    fn multiplicative(&mut self) -> Result<CalcValue, String> {
+        let mut value = self.unary()?;
+        loop {
+            let op = match () {
+                _ if self.eat('*') => '*',
+                _ if self.eat('/') => '/',
+                _ if self.eat('%') => '%',
+                _ => return Ok(value),
+            };
+            value = binary(op, value, self.unary()?)?;
+        }
+    }
+    /// Parse a factor with its signs, so `-2^2` is `-4`.
+    fn unary(&mut self) -> Result<CalcValue, String> {
+        if self.eat('-') {
+            return binary('*', CalcValue::Number(-1.0), self.unary()?);
+        }
+        if self.eat('+') {
+            return self.unary();
+        }
+        self.power()
+    }
+    /// Parse a power, which groups from the right, so `2^3^2` is `2^9`.
+    fn power(&mut self) -> Result<CalcValue, String> {
+        let base = self.postfix()?;
+        match self.eat('^') {
+            true => binary('^', base, self.unary()?),
+            false => Ok(base),
+        }
+    }
+    /// Parse a term with its factorials.
+    fn postfix(&mut self) -> Result<CalcValue, String> {
+        let mut value = self.term()?;
+        while self.eat('!') {
+            let n = number(&value, "!")?;
+            if n < 0.0 || n.fract() != 0.0 || n > 170.0 {
+                return Err(format!("The factorial of {} is not computed.", n));
+            }
+            value = CalcValue::Number((1..=n as u64).map(|i| i as f64).product());
+        }
+        Ok(value)
+    }
+    /// Parse a number, a date, a constant, a function call or an expression in parentheses.
+    fn term(&mut self) -> Result<CalcValue, String> {
+        match self.next() {
+            Some(Token::Number(number)) => Ok(CalcValue::Number(number)),
+            Some(Token::Date(date)) => parse_date(&date).map(CalcValue::Date),
+            Some(Token::Punct('(')) => {
+                let value = self.additive()?;
+                self.expect(')')?;
+                Ok(value)
+            }
+            Some(Token::Ident(name)) if self.eat('(') => {
+                let mut args = Vec::new();
+                if !self.eat(')') {
+                    loop {
+                        args.push(self.additive()?);
+                        if self.eat(')') {
+                            break;
+                        }
+                        self.expect(',')?;
+                    }
+                }
+                call(&name, &args)
+            }
+            Some(Token::Ident(name)) => match name.as_str() {
+                "pi" => Ok(CalcValue::Number(std::f64::consts::PI)),
+                "e" => Ok(CalcValue::Number(std::f64::consts::E)),
+                "now" => Ok(CalcValue::Date(self.now)),
+                "today" => Ok(CalcValue::Date(self.now.date().into())),
+                _ => Err(format!("Unknown constant `{}`.", name)),
+            },
+            Some(token) => Err(format!("Unexpected {}.", token)),
+            None => Err("The expression ends too early.".to_string()),
+        }
+    }
+}
+
+/// Apply a binary operator.
+fn binary(op: char, left: CalcValue, right: CalcValue) -> Result<CalcValue, String> {
+    use CalcValue::*;
+    let value = match (op, left, right) {
+        ('+', Number(a), Number(b)) => Number(a + b),
+        ('-', Number(a), Number(b)) => Number(a - b),
+        ('*', Number(a), Number(b)) => Number(a * b),
+        ('/', Number(_), Number(b)) | ('%', Number(_), Number(b)) if b == 0.0 => {
+            return Err("Division by zero.".to_string())
+        }
+        ('/', Number(a), Number(b)) => Number(a / b),
+        ('%', Number(a), Number(b)) => Number(a % b),
+        ('^', Number(a), Number(b)) => Number(a.powf(b)),
+        ('-', Date(a), Date(b)) => Duration(a - b),
+        ('+', Date(a), Duration(b)) | ('+', Duration(b), Date(a)) => Date(
+            a.checked_add_signed(b)
+                .ok_or_else(|| "The date is out of range.".to_string())?,
+        ),
+        ('-', Date(a), Duration(b)) => Date(
+            a.checked_sub_signed(b)
+                .ok_or_else(|| "The date is out of range.".to_string())?,
+        ),
+        ('+', Duration(a), Duration(b)) => Duration(
+            a.checked_add(&b)
+                .ok_or_else(|| "The duration is out of range.".to_string())?,
+        ),
+        ('-', Duration(a), Duration(b)) => Duration(
+            a.checked_sub(&b)
+                .ok_or_else(|| "The duration is out of range.".to_string())?,
+        ),
+        ('*', Duration(a), Number(b)) | ('*', Number(b), Duration(a)) => scale(a, b)?,
+        ('/', Duration(_), Number(0.0)) => return Err("Division by zero.".to_string()),
+        ('/', Duration(a), Number(b)) => scale(a, 1.0 / b)?,
+        ('/', Duration(_), Duration(b)) if b.is_zero() => {
+            return Err("Division by zero.".to_string())
+        }
+        ('/', Duration(a), Duration(b)) => {
+            Number(a.num_milliseconds() as f64 / b.num_milliseconds() as f64)
+        }
+        (op, left, right) => {
+            return Err(format!(
+                "`{}` can't be applied to {} and {}.",
+                op,
+                kind(&left),
+                kind(&right)
+            ))
+        }
+    };
+    finite(value)
+}
+
+/// Call a function.
+fn call(name: &str, args: &[CalcValue]) -> Result<CalcValue, String> {
+    let arity = |count: usize| match args.len() == count {
+        true => Ok(()),
+        false => Err(format!("`{}` takes {} arguments.", name, count)),
+    };
+    let unit = match name {
+        "weeks" => Some(604_800_000.0),
+        "days" => Some(86_400_000.0),
+        "hours" => Some(3_600_000.0),
+        "minutes" => Some(60_000.0),
+        "seconds" => Some(1_000.0),
+        _ => None,
+    };
+    if let Some(unit) = unit {
+        arity(1)?;
+        return match args[0] {
+            CalcValue::Duration(duration) => {
+                Ok(CalcValue::Number(duration.num_milliseconds() as f64 / unit))
+            }
+            value => scale(TimeDelta::milliseconds(1), number(&value, name)? * unit),
+        };
+    }
+    let value = match name {
+        "min" | "max" if !args.is_empty() => {
+            let numbers = args
+                .iter()
+                .map(|arg| number(arg, name))
+                .collect::<Result<Vec<f64>, String>>()?;
+            let fold = if name == "min" { f64::min } else { f64::max };
+            CalcValue::Number(numbers.into_iter().reduce(fold).unwrap())
+        }
+        "round" if args.len() == 2 => {
+            let factor = 10f64.powf(number(&args[1], name)?.trunc());
+            CalcValue::Number((number(&args[0], name)? * factor).round() / factor)
+        }
+        "log" if args.len() == 2 => {
+            CalcValue::Number(number(&args[0], name)?.log(number(&args[1], name)?))
+        }
+        "pow" => {
+            arity(2)?;
+            CalcValue::Number(number(&args[0], name)?.powf(number(&args[1], name)?))
+        }
+        "add_months" => {
+            arity(2)?;
+            let months = number(&args[1], name)?;
+            if months.fract() != 0.0 {
+                return Err("`add_months` takes a whole number of months.".to_string());
+            }
+            let date = date(&args[0], name)?;
+            let months = Months::new(months.abs() as u32);
+            let date = match args[1] {
+                CalcValue::Number(n) if n < 0.0 => date.checked_sub_months(months),
+                _ => date.checked_add_months(months),
+            };
+            CalcValue::Date(date.ok_or_else(|| "The date is out of range.".to_string())?)
+        }
+        "year" | "month" | "day" | "weekday" => {
+            arity(1)?;
+            let date = date(&args[0], name)?;
+            CalcValue::Number(match name {
+                "year" => date.year() as f64,
+                "month" => date.month() as f64,
+                "day" => date.day() as f64,
+                _ => date.weekday().number_from_monday() as f64,
+            })
+        }
+        _ => {
+            let f: fn(f64) -> f64 = match name {
+                "sqrt" => f64::sqrt,
+                "abs" => f64::abs,
+                "floor" => f64::floor,
+                "ceil" => f64::ceil,
+                "round" => f64::round,
+                "exp" => f64::exp,
+                "ln" => f64::ln,
+                "log" => f64::log10,
+                "sin" => f64::sin,
+                "cos" => f64::cos,
+                "tan" => f64::tan,
+                "asin" => f64::asin,
+                "acos" => f64::acos,
+                "atan" => f64::atan,
+                _ => return Err(format!("Unknown function `{}`.", name)),
+            };
+            arity(1)?;
+            CalcValue::Number(f(number(&args[0], name)?))
+        }
+    };
+    finite(value)
+}
+
+/// Parse a date in quotes.
+fn parse_date(text: &str) -> Result<NaiveDateTime, String> {
+    let text = text.trim();
+    DATE_FORMATS
+        .iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(text, format).ok())
+        .or_else(|| {
+            NaiveDate::parse_from_str(text, "%Y-%m-%d")
+                .ok()
+                .map(NaiveDateTime::from)
+        })
+        .ok_or_else(|| {
+            format!(
+                "Invalid date '{}', use YYYY-MM-DD or YYYY-MM-DD HH:MM.",
+                text
+            )
+        })
+}
+
+/// Multiply a duration, to the millisecond.
+fn scale(duration: TimeDelta, factor: f64) -> Result<CalcValue, String> {
+    let millis = (duration.num_milliseconds() as f64 * factor).round();
+    // the bounds of a duration are a bit below the ones of i64 milliseconds
+    if !millis.is_finite() || millis.abs() >= i64::MAX as f64 {
+        return Err("The duration is out of range.".to_string());
+    }
+    TimeDelta::try_milliseconds(millis as i64)
+        .map(CalcValue::Duration)
+        .ok_or_else(|| "The duration is out of range.".to_string())
+}
+
+/// Check that a number is not infinite or not a number, like the square root of -1.
+fn finite(value: CalcValue) -> Result<CalcValue, String> {
+    match value {
+        CalcValue::Number(number) if !number.is_finite() => {
+            Err("The result is not a finite number.".to_string())
+        }
+        value => Ok(value),
+    }
+}
+
+/// Get the number of an argument of an operation.
+fn number(value: &CalcValue, operation: &str) -> Result<f64, String> {
+    match value {
+        CalcValue::Number(number) => Ok(*number),
+        value => Err(format!(
+            "`{}` takes a number, not {}.",
+            operation,
+            kind(value)
+        )),
+    }
+}
+
+/// Get the date of an argument of an operation.
+fn date(value: &CalcValue, operation: &str) -> Result<NaiveDateTime, String> {
+    match value {
+        CalcValue::Date(date) => Ok(*date),
+        value => Err(format!(
+            "`{}` takes a date, not {}.",
+            operation,
+            kind(value)
+        )),
+    }
+}
+
+/// Get the kind of a value for the errors.
+fn kind(value: &CalcValue) -> &'static str {
+    match value {
+        CalcValue::Number(_) => "a number",
+        CalcValue::Date(_) => "a date",
+        CalcValue::Duration(_) => "a duration",
+    }
+}
+
+/// Format a number without the noise of the floating point, like `0.1 + 0.2`.
+fn format_number(number: f64) -> String {
+    if number.fract() == 0.0 && number.abs() < 1e15 {
+        return format!("{}", number as i64);
+    }
+    if number.abs() >= 1e15 || number.abs() < 1e-6 {
+        return format!("{:e}", number);
+    }
+    let text = format!("{:.12}", number);
+    text.trim_end_matches('0').trim_end_matches('.').to_string()
+}
+
+/// Format a count of a unit, like `1 day` or `2 days`.
+fn plural(count: f64, unit: &str) -> String {
+    match count == 1.0 {
+        true => format!("1 {}", unit),
+        false => format!("{} {}s", format_number(count), unit),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn evaluate_expressions() {
+        let now = parse_date("2024-02-28 15:30").unwrap();
+        let eval = |expression: &str| match evaluate_at(expression, now) {
+            Ok(value) => value.to_string(),
+            Err(e) => format!("Error: {}", e),
+        };
+        assert_eq!(eval("(1 + 2) * 3 - 4 / 8"), "8.5");
+        assert_eq!(eval("0.1 + 0.2"), "0.3");
+        assert_eq!(eval("-2^2 + 2^3^2 + 5!"), "628");
+        assert_eq!(eval("round(sqrt(2), 3) + max(1, 7, 3) % 4"), "4.414");
+        assert_eq!(eval("log(1000) + log(8, 2) + round(cos(pi))"), "5");
+        assert_eq!(eval("1 / 0"), "Error: Division by zero.");
+        assert_eq!(
+            eval("sqrt(-1)"),
+            "Error: The result is not a finite number."
+        );
+        assert_eq!(eval("2 +"), "Error: The expression ends too early.");
+        assert_eq!(eval("foo(1)"), "Error: Unknown function `foo`.");
+
+        assert_eq!(eval("today + days(2)"), "2024-03-01");
+        assert_eq!(eval("'2024-03-01' - '2024-01-01'"), "60 days");
+        assert_eq!(eval("days('2025-01-01' - today)"), "308");
+        assert_eq!(eval("now - today"), "15 hours 30 minutes");
+        assert_eq!(
+            eval("hours(1.5) * 3 + seconds(0.5)"),
+            "4 hours 30 minutes 0.5 seconds"
+        );
+        assert_eq!(eval("add_months('2024-01-31', 1)"), "2024-02-29");
+        assert_eq!(eval("weekday(today) + year(now)"), "2027");
+        assert_eq!(
+            eval("today * 2"),
+            "Error: `*` can't be applied to a date and a number."
+        );
+        for expression in [
+            "weeks(1e10) * 2",
+            "weeks(1e10) + weeks(1e10)",
+            "weeks(1e10) - weeks(-1e10)",
+        ] {
+            assert_eq!(eval(expression), "Error: The duration is out of range.");
+        }
+    }
+}
@@ -0,0 +1,422 @@
+//! # Filesystem
+//!
+//! This module gives the AI service the `read_file`, `list_dir` and `write_file` tools, so an
+//! agent can browse and edit a repository without a shell.
+//!
+//! The tools only reach the files below the root directories. A relative path is below the
+//! first root, and an absolute path must be below one of the roots. The paths are resolved
+//! with their symbolic links, so a link can't lead out of the roots.
+//!
+//! The files read and written, and the listings, are capped in size. `write_file` is only
+//! registered when the writes are allowed, and each write can be checked first by an approval
+//! hook, like one that asks a human with the [`crate::worknode::approval`] decisions.
+
+use super::ToolRegistry;
+use crate::worknode::approval::Decision;
+
+use serde_json::json;
+
+use std::future::Future;
+use std::path::{Component, Path, PathBuf};
+use std::pin::Pin;
+use std::sync::Arc;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// The struct of a write that waits for its approval.
+pub struct WriteRequest {
+    /// The resolved path of the file.
+    pub path: PathBuf,
+    /// The new content of the file.
+    pub content: String,
+    /// Whether the write replaces a file.
+    pub exists: bool,
+}
+
+/// The future of the decision on a write.
+pub type WriteApprovalFuture = Pin<Box<dyn Future<Output = Decision> + Send>>;
+
+/// The function that approves or rejects the writes.
+pub type WriteApprovalHandler = Arc<dyn Fn(WriteRequest) -> WriteApprovalFuture + Send + Sync>;
+
+#[derive(Clone)]
+/// The struct of the files that the tools can reach.
+pub struct FileSystem {
+    /// The directories that the tools can reach, with everything below them.
+    roots: Vec<PathBuf>,
+    /// The bytes of a file given back by `read_file`, the rest is cut.
+    max_read: u64,
+    /// The bytes of the largest file written by `write_file`.
+    max_write: usize,
+    /// The entries given back by `list_dir`, the rest is cut.
+    max_entries: usize,
+    /// Whether `write_file` is registered.
+    writable: bool,
+    /// The hook that approves the writes, or `None` to allow them all.
+    approval: Option<WriteApprovalHandler>,
+}
+
+impl std::fmt::Debug for FileSystem {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("FileSystem")
+            .field("roots", &self.roots)
+            .field("max_read", &self.max_read)
+            .field("max_write", &self.max_write)
+            .field("max_entries", &self.max_entries)
+            .field("writable", &self.writable)
+            .field("approval", &self.approval.is_some())
+            .finish()
+    }
+}
+
+impl FileSystem {
+    /// Create a new read-only FileSystem of the root directory.
+    pub fn new<P: AsRef<Path>>(root: P) -> Self {
+        FileSystem {
+            roots: vec![root.as_ref().to_path_buf()],
+            max_read: 1024 * 1024,
+            max_write: 1024 * 1024,
+            max_entries: 1000,
+            writable: false,
+            approval: None,
+        }
+    }
+    /// Read a text file, cut to `max_read` bytes.
+    pub async fn read_file(&self, path: &str) -> Result<String, String> {
+        let resolved = self.resolve(path)?;
+        let file = tokio::fs::File::open(&resolved)
+            .await
+            .map_err(|e| format!("Failed to open {}. {}", path, e))?;
+        let size = file
+            .metadata()
+            .await
+            .map_err(|e| format!("Failed to read {}. {}", path, e))?
+            .len();
+        let mut bytes = Vec::new();
+        tokio::io::AsyncReadExt::read_to_end(
+            &mut tokio::io::AsyncReadExt::take(file, self.max_read),
+            &mut bytes,
+        )
+        .await
+        .map_err(|e| format!("Failed to read {}. {}", path, e))?;
+        if bytes.contains(&0) {
+            return Err(format!("{} is not a text file.", path));
+        }
+        let mut text = String::from_utf8_lossy(&bytes).to_string();
+        if size > self.max_read {
+            text.push_str(&format!(
+                "\n... (the file has {} bytes, only the first {} are shown)",
+                size, self.max_read
+            ));
+        }
+        Ok(text)
+    }
+    /// List a directory, sorted, with a `/` after the directories and the sizes of the files.
+    /// The symbolic links are listed with their name only.
+    pub async fn list_dir(&self, path: &str) -> Result<String, String> {
+        let resolved = self.resolve(path)?;
+        let mut reader = tokio::fs::read_dir(&resolved)
+            .await
+            .map_err(|e| format!("Failed to list {}. {}", path, e))?;
+        let mut entries = Vec::new();
+        while let Some(entry) = reader
+            .next_entry()
+            .await
+            .map_err(|e| format!("Failed to list {}. {}", path, e))?
+        {
+            let name = entry.file_name().to_string_lossy().to_string();
+            // the links are not followed, they may lead out of the roots
+            entries.push(match entry.metadata().await {
+                Ok(metadata) if metadata.is_dir() => format!("{}/", name),
+                Ok(metadata) if metadata.is_file() => {
+                    format!("{} ({} bytes)", name, metadata.len())
+                }
+                _ => name,
+            });
+        }
+        if entries.is_empty() {
+            return Ok(format!("{} is empty.", path));
+        }
+        entries.sort();
+        let more = entries.len().saturating_sub(self.max_entries);
+        entries.truncate(self.max_entries);
+        if more > 0 {
+            entries.push(format!("... ({} more entries)", more));
+        }
+        Ok(entries.join("\n"))
+    }
+    /// Write a text file after its approval, with the missing parent directories.
+    pub async fn write_file(&self, path: &str, content: &str) -> Result<String, String> {
+        if !self.writable {
+            return Err("The files can't be written.".to_string());
+        }
+        if content.len() > self.max_write {
+            return Err(format!(
+                "The content has {} bytes, more than the {} bytes allowed.",
+                content.len(),
+                self.max_write
+            ));
+        }
+        let resolved = self.resolve(path)?;
+        if resolved.is_dir() {
+            return Err(format!("{} is a directory.", path));
+        }
+        if let Some(approval) = &self.approval {
+            let request = WriteRequest {
+                path: resolved.clone(),
+                content: content.to_string(),
+                exists: resolved.exists(),
+            };
+            if let Decision::Reject(reason) = approval(request).await {
+                return Err(format!("The write of {} is rejected. {}", path, reason));
+            }
+        }
+        if let Some(parent) = resolved.parent() {
+            tokio::fs::create_dir_all(parent)
+                .await
+                .map_err(|e| format!("Failed to create the directory of {}. {}", path, e))?;
+        }
+        tokio::fs::write(&resolved, content)
+            .await
+            .map_err(|e| format!("Failed to write {}. {}", path, e))?;
+        Ok(format!("Wrote {} bytes to {}.", content.len(), path))
+    }
+    /// Register the tools in the registry, `write_file` only if the writes are allowed.
+    pub fn register(&self, registry: &mut ToolRegistry) {
+        let path = json!({
+            "type": "string",
+            "description": "The path of the file, relative to the root directory."
+        });
+        let fs = self.clone();
+        registry.register(
+            "read_file",
+            "Read a text file.",
+            json!({"type": "object", "properties": {"path": path}, "required": ["path"]}),
+            move |arguments| {
+                let fs = fs.clone();
+                async move { fs.read_file(&string(&arguments, "path")?).await }
+            },
+        );
+        let fs = self.clone();
+        registry.register(
+            "list_dir",
+            "List the entries of a directory, with a / after the directories and the sizes of \
+             the files. The path defaults to the root directory.",
+            json!({"type": "object", "properties": {"path": path}}),
+            move |arguments| {
+                let fs = fs.clone();
+                async move {
+                    let path = arguments["path"].as_str().unwrap_or(".");
+                    fs.list_dir(path).await
+                }
+            },
+        );
+        if !self.writable {
+            return;
+        }
+        let fs = self.clone();
+        registry.register(
+            "write_file",
+            "Write a text file, replacing it if it exists.",
+            json!({
+                "type": "object",
+                "properties": {
+                    "path": path,
+                    "content": { "type": "string", "description": "The new content of the file." }
+                },
+                "required": ["path", "content"]
+            }),
+            move |arguments| {
+                let fs = fs.clone();
+                async move {
+                    let path = string(&arguments, "path")?;
+                    fs.write_file(&path, &string(&arguments, "content")?).await
+                }
+            },
+        );
+    }
+    /// Resolve a path with its symbolic links, and check that it is below a root. The path
+    /// may not exist yet, like a file to write.
+    fn resolve(&self, path: &str) -> Result<PathBuf, String> {
+        let denied = || format!("{} is outside of the root directories.", path);
+        let roots: Vec<PathBuf> = self
+            .roots
+            .iter()
+            .filter_map(|root| root.canonicalize().ok())
+            .collect();
+        let first = roots
+            .first()
+            .ok_or_else(|| "The root directories don't exist.".to_string())?;
+        let mut normalized = PathBuf::new();
+        for component in first.join(path).components() {
+            match component {
+                Component::ParentDir => {
+                    if !normalized.pop() {
+                        return Err(denied());
+                    }
+                }
+                Component::CurDir => {}
+                component => normalized.push(component),
+            }
+        }
+        // the part that exists is resolved, the rest has no links and no `..`
+        let mut existing = normalized.as_path();
+        let mut missing = Vec::new();
+        let resolved = loop {
+            match existing.canonicalize() {
+                Ok(resolved) => break resolved,
+                // a link to a file that doesn't exist yet can't be resolved
+                Err(_) if existing.symlink_metadata().is_ok() => return Err(denied()),
+                Err(_) => {
+                    missing.extend(existing.file_name());
+                    existing = existing.parent().ok_or_else(denied)?;
+                }
+            }
+        };
+        let resolved = missing
+            .into_iter()
+            .rev()
+            .fold(resolved, |path, name| path.join(name));
+        match roots.iter().any(|root| resolved.starts_with(root)) {
+            true => Ok(resolved),
+            false => Err(denied()),
+        }
+    }
+    /// Add a root directory as builder.
+    pub fn root<P: AsRef<Path>>(mut self, root: P) -> Self {
+        self.roots.push(root.as_ref().to_path_buf());
+        self
+    }
+    /// Get the root directories.
+    pub fn get_roots(&self) -> &Vec<PathBuf> {
+        &self.roots
+    }
+    /// Set the bytes of a file given back by `read_file` as builder.
+    pub fn max_read(mut self, max_read: u64) -> Self {
+        self.max_read = max_read;
+        self
+    }
+    /// Get the bytes of a file given back by `read_file`.
+    pub fn get_max_read(&self) -> u64 {
+        self.max_read
+    }
+    /// Set the bytes of the largest file written as builder.
+    pub fn max_write(mut self, max_write: usize) -> Self {
+        self.max_write = max_write;
+        self
+    }
+    /// Get the bytes of the largest file written.
+    pub fn get_max_write(&self) -> usize {
+        self.max_write
+    }
+    /// Set the entries given back by `list_dir` as builder.
+    pub fn max_entries(mut self, max_entries: usize) -> Self {
+        self.max_entries = max_entries;
+        self
+    }
+    /// Get the entries given back by `list_dir`.
+    pub fn get_max_entries(&self) -> usize {
+        self.max_entries
+    }
+    /// Set whether the files can be written as builder.
+    pub fn writable(mut self, writable: bool) -> Self {
+        self.writable = writable;
+        self
+    }
+    /// Get whether the files can be written.
+    pub fn get_writable(&self) -> bool {
+        self.writable
+    }
+    /// Set the hook that approves the writes as builder.
+    pub fn approval<F, Fut>(mut self, approval: F) -> Self
+    where
+        F: Fn(WriteRequest) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Decision> + Send + 'static,
+    {
+        self.approval = Some(Arc::new(move |request| Box::pin(approval(request))));
+        self
+    }
+}
+
+/// Get a string argument of a call.
+fn string(arguments: &serde_json::Value, name: &str) -> Result<String, String> {
+    arguments[name]
+        .as_str()
+        .map(str::to_string)
+        .ok_or_else(|| format!("{} should be a string", name))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::worknode::ai_node::tool::ToolCall;
+
+    use tokio::runtime::Runtime;
+
+    #[test]
+    fn filesystem_tools() {
+        let rt = Runtime::new().unwrap();
+        let dir = std::env::temp_dir().join(format!("filesystem-{}", uuid::Uuid::new_v4()));
+        let root = dir.join("repo");
+        std::fs::create_dir_all(root.join("src")).unwrap();
+        std::fs::write(root.join("README.md"), "# Repo\nHello").unwrap();
+        std::fs::write(dir.join("secret.txt"), "secret").unwrap();
+        std::os::unix::fs::symlink(dir.join("secret.txt"), root.join("link")).unwrap();
+        std::os::unix::fs::symlink(dir.join("pwned.txt"), root.join("dangling")).unwrap();
+
+        let mut registry = ToolRegistry::new();
+        FileSystem::new(&root)
+            .writable(true)
+            .max_read(6)
+            .approval(|request: WriteRequest| async move {
+                match request.content.contains("rm -rf") {
+                    true => Decision::Reject("Dangerous.".to_string()),
+                    false => Decision::Approve,
+                }
+            })
+            .register(&mut registry);
+        let call = |name: &str, arguments: serde_json::Value| {
+            rt.block_on(registry.call(&ToolCall {
+                id: "call_0".to_string(),
+                name: name.to_string(),
+                arguments: arguments.to_string(),
+            }))
+        };
+
+        assert_eq!(
+            call("read_file", json!({"path": "README.md"})),
+            "# Repo\n... (the file has 12 bytes, only the first 6 are shown)"
+        );
+        for path in [
+            "../secret.txt",
+            "link",
+            dir.join("secret.txt").to_str().unwrap(),
+        ] {
+            let output = call("read_file", json!({ "path": path }));
+            assert!(output.ends_with("is outside of the root directories."));
+        }
+        let output = call(
+            "write_file",
+            json!({"path": "run.sh", "content": "rm -rf /"}),
+        );
+        assert_eq!(output, "Error: The write of run.sh is rejected. Dangerous.");
+        for path in ["dangling", "dangling/file"] {
+            let output = call("write_file", json!({"path": path, "content": "pwned"}));
+            assert!(output.ends_with("is outside of the root directories."));
+        }
+        assert!(!dir.join("pwned.txt").exists());
+        let output = call(
+            "write_file",
+            json!({"path": "src/lib/mod.rs", "content": "fn a() {}"}),
+        );
+        assert_eq!(output, "Wrote 9 bytes to src/lib/mod.rs.");
+        assert_eq!(
+            call("list_dir", json!({})),
+            "README.md (12 bytes)\ndangling\nlink\nsrc/"
+        );
+        assert_eq!(
+            call("list_dir", json!({"path": "src/lib"})),
+            "mod.rs (9 bytes)"
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
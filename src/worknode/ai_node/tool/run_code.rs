@@ -0,0 +1,292 @@
+//! # Run Code
+//!
+//! This module gives the AI service a `run_code` tool, so an agent can write code, run it and
+//! fix it from what it printed.
+//!
+//! The code is a python script or a shell script. It runs as the command of a local node (see
+//! [`crate::worknode::local`]) in its sandbox, in a new empty directory that is removed after
+//! the run. By default the sandbox is [`Sandbox::strict`]: the code has no network and can only
+//! write to the directory of the run, with a restricted environment and limits on the CPU
+//! time, the memory and the size of the files written. Where the kernel has no Landlock, the
+//! filesystem is not restricted, with a warning.
+//!
+//! The tool gives the exit code, the stdout and the stderr of the code, truncated to
+//! `max_output` characters each. A code that fails is still a result, so the model sees its
+//! errors; a code that can't be started, or runs longer than the timeout, fails the call.
+
+use super::ToolRegistry;
+use crate::error::local_node_error::LocalNodeErrorType;
+use crate::workflow::context::RunContext;
+use crate::worknode::local::sandbox::Sandbox;
+use crate::worknode::local::{LocalNode, LocalOutput};
+
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use std::time::Duration;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+/// The enum of the languages of the code.
+pub enum CodeLanguage {
+    /// A python script, run by the python interpreter.
+    Python,
+    /// A shell script, run by `sh`.
+    Shell,
+}
+
+#[derive(Debug, Clone)]
+/// The struct of the runner of the code of the model.
+pub struct RunCode {
+    /// The languages the model may use.
+    languages: Vec<CodeLanguage>,
+    /// The python interpreter.
+    python: String,
+    /// The limits and the isolation of the code.
+    sandbox: Sandbox,
+    /// The time the code may take before it is killed.
+    timeout: Duration,
+    /// The characters of the stdout and of the stderr given back to the model.
+    max_output: usize,
+}
+
+impl Default for RunCode {
+    fn default() -> Self {
+        RunCode {
+            languages: vec![CodeLanguage::Python, CodeLanguage::Shell],
+            python: "python3".to_string(),
+            sandbox: default_sandbox(),
+            timeout: Duration::from_secs(30),
+            max_output: 10_000,
+        }
+    }
+}
+
+impl RunCode {
+    /// Create a new RunCode with the default limits.
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Run the code in a new directory, and get what it printed and how it exited.
+    pub async fn run(&self, language: CodeLanguage, code: &str) -> Result<LocalOutput, String> {
+        if !self.languages.contains(&language) {
+            return Err(format!("The {:?} code is not allowed.", language));
+        }
+        let dir = std::env::temp_dir().join(format!("run-code-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir(&dir)
+            .map_err(|e| format!("Failed to create the directory of the code. {}", e))?;
+        // the code is given on the stdin, so it needs no file and no quoting
+        let node = match language {
+            CodeLanguage::Python => LocalNode::new(&self.python).args(vec!["-".to_string()]),
+            CodeLanguage::Shell => LocalNode::new("sh").args(vec!["-s".to_string()]),
+        };
+        let sandbox = match self.sandbox.get_restrict_filesystem() {
+            true => self.sandbox.clone().read_write_path(&dir),
+            false => self.sandbox.clone(),
+        };
+        let mut node = node
+            .working_dir(Some(dir.clone()))
+            .timeout(Some(self.timeout))
+            .sandbox(sandbox);
+        let result = node.execute(code.to_string(), &RunContext::new()).await;
+        let _ = std::fs::remove_dir_all(&dir);
+        match result {
+            // the node keeps the output of a command that exited, even with a failure
+            Ok(_) => output(&node),
+            Err(e) if matches!(e.get_error_type(), LocalNodeErrorType::ExitError { .. }) => {
+                output(&node)
+            }
+            Err(e) => Err(e.get_message().to_string()),
+        }
+    }
+    /// Register the `run_code` tool in the registry.
+    pub fn register(&self, registry: &mut ToolRegistry) {
+        let runner = self.clone();
+        let languages: Vec<_> = self
+            .languages
+            .iter()
+            .map(|language| json!(language))
+            .collect();
+        registry.register(
+            "run_code",
+            "Run a script in a sandbox and get its exit code, stdout and stderr. Each run \
+             starts in a new empty directory.",
+            json!({
+                "type": "object",
+                "properties": {
+                    "language": {
+                        "type": "string",
+                        "enum": languages,
+                        "description": "The language of the code."
+                    },
+                    "code": { "type": "string", "description": "The code to run." }
+                },
+                "required": ["language", "code"]
+            }),
+            move |arguments| {
+                let runner = runner.clone();
+                async move {
+                    let language = serde_json::from_value(arguments["language"].clone())
+                        .map_err(|_| "language should be python or shell".to_string())?;
+                    let code = arguments["code"]
+                        .as_str()
+                        .ok_or_else(|| "code should be a string".to_string())?;
+                    let output = runner.run(language, code).await?;
+                    Ok(runner.format_output(&output))
+                }
+            },
+        );
+    }
+    /// Get the text of the output for the AI service.
+    pub fn format_output(&self, output: &LocalOutput) -> String {
+        let exit_code = match output.exit_code {
+            Some(code) => code.to_string(),
+            None => "killed".to_string(),
+        };
+        format!(
+            "exit code: {}\nstdout:\n{}\nstderr:\n{}",
+            exit_code,
+            truncate(&output.stdout, self.max_output),
+            truncate(&output.stderr, self.max_output)
+        )
+    }
+    /// Set the languages the model may use as builder.
+    pub fn languages(mut self, languages: Vec<CodeLanguage>) -> Self {
+        self.languages = languages;
+        self
+    }
+    /// Set the languages the model may use.
+    pub fn set_languages(&mut self, languages: Vec<CodeLanguage>) {
+        self.languages = languages;
+    }
+    /// Get the languages the model may use.
+    pub fn get_languages(&self) -> &Vec<CodeLanguage> {
+        &self.languages
+    }
+    /// Set the python interpreter as builder.
+    pub fn python(mut self, python: &str) -> Self {
+        self.python = python.to_string();
+        self
+    }
+    /// Get the python interpreter.
+    pub fn get_python(&self) -> &str {
+        &self.python
+    }
+    /// Set the sandbox as builder.
+    pub fn sandbox(mut self, sandbox: Sandbox) -> Self {
+        self.sandbox = sandbox;
+        self
+    }
+    /// Set the sandbox.
+    pub fn set_sandbox(&mut self, sandbox: Sandbox) {
+        self.sandbox = sandbox;
+    }
+    /// Get the sandbox.
+    pub fn get_sandbox(&self) -> &Sandbox {
+        &self.sandbox
+    }
+    /// Set the timeout as builder.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+    /// Get the timeout.
+    pub fn get_timeout(&self) -> Duration {
+        self.timeout
+    }
+    /// Set the characters of each output given back as builder.
+    pub fn max_output(mut self, max_output: usize) -> Self {
+        self.max_output = max_output;
+        self
+    }
+    /// Get the characters of each output given back.
+    pub fn get_max_output(&self) -> usize {
+        self.max_output
+    }
+}
+
+/// Get the strict sandbox of the code, without the filesystem restriction where it is not
+/// supported.
+fn default_sandbox() -> Sandbox {
+    let sandbox = Sandbox::strict(Vec::new())
+        .read_write_path("/dev/null")
+        .cpu_time(Some(Duration::from_secs(10)))
+        .memory(Some(512 * 1024 * 1024))
+        .file_size(Some(16 * 1024 * 1024));
+    if Sandbox::can_restrict_filesystem() {
+        return sandbox;
+    }
+    log::warn!("Landlock is not available, the code can access the whole filesystem.");
+    sandbox.restrict_filesystem(false)
+}
+
+/// Get the output that the node kept of its command.
+fn output(node: &LocalNode) -> Result<LocalOutput, String> {
+    node.get_last_output()
+        .cloned()
+        .ok_or_else(|| "The code didn't give any output.".to_string())
+}
+
+/// Keep the first characters of the text, and tell how many were cut.
+fn truncate(text: &str, max: usize) -> String {
+    match text.char_indices().nth(max) {
+        Some((end, _)) => format!(
+            "{}\n... ({} more characters)",
+            &text[..end],
+            text[end..].chars().count()
+        ),
+        None => text.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::worknode::ai_node::tool::ToolCall;
+
+    use tokio::runtime::Runtime;
+
+    #[test]
+    fn run_code_tool() {
+        let rt = Runtime::new().unwrap();
+        let mut registry = ToolRegistry::new();
+        RunCode::new()
+            .languages(vec![CodeLanguage::Shell])
+            .max_output(10)
+            .timeout(Duration::from_millis(500))
+            .register(&mut registry);
+        let call = |arguments: serde_json::Value| ToolCall {
+            id: "call_0".to_string(),
+            name: "run_code".to_string(),
+            arguments: arguments.to_string(),
+        };
+
+        let code = "touch made; ls; echo ${CARGO_PKG_NAME:-none}; echo oops >&2; exit 2";
+        let output = rt.block_on(registry.call(&call(json!({"language": "shell", "code": code}))));
+        assert_eq!(
+            output,
+            "exit code: 2\nstdout:\nmade\nnone\n\nstderr:\noops\n"
+        );
+
+        // only the directory of the run is writable
+        let outside = std::env::temp_dir().join(format!("run-code-{}", uuid::Uuid::new_v4()));
+        let code = format!("echo hi > /dev/null; touch {}", outside.display());
+        let output = rt.block_on(registry.call(&call(json!({"language": "shell", "code": code}))));
+        if Sandbox::can_restrict_filesystem() {
+            assert!(output.starts_with("exit code: 1\n"), "{}", output);
+            assert!(!outside.exists());
+        }
+
+        let output =
+            rt.block_on(registry.call(&call(json!({"language": "shell", "code": "seq 1 100"}))));
+        assert!(output.contains("1\n2\n3\n4\n5\n\n... (282 more characters)"));
+
+        let output =
+            rt.block_on(registry.call(&call(json!({"language": "python", "code": "print(1)"}))));
+        assert!(output.starts_with("Error: ") && output.contains("not allowed"));
+
+        let output =
+            rt.block_on(registry.call(&call(json!({"language": "shell", "code": "sleep 5"}))));
+        assert!(output.starts_with("Error: ") && output.contains("didn't finish"));
+    }
+}
@@ -0,0 +1,330 @@
+//! # Web Search
+//!
+//! This module gives the AI service a `web_search` tool, so an agent can ground its answers
+//! in current information from the web.
+//!
+//! The search is asked from one of the backends:
+//! 1. SearxNG: the json api of a SearxNG instance, usually self-hosted, so it needs no key.
+//! 2. Brave: the web search api of Brave.
+//! 3. Bing: the web search api of Bing.
+//!
+//! The tool takes the `query` and an optional `count` of results, and gives the results
+//! numbered, each with its title, its url and its snippet.
+
+use super::super::key::ApiKey;
+use super::ToolRegistry;
+use crate::error::ai_node_error::{AINodeError, AINodeErrorType, AINodeResult};
+
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+pub const BRAVE_SEARCH_URL: &str = "https://api.search.brave.com/res/v1/web/search";
+pub const BING_SEARCH_URL: &str = "https://api.bing.microsoft.com/v7.0/search";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+/// The enum of the api the search is asked from.
+pub enum SearchBackend {
+    /// The json api of a SearxNG instance.
+    Searxng,
+    /// The Brave web search api.
+    Brave,
+    /// The Bing web search api.
+    Bing,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+/// The struct of a result of a search.
+pub struct SearchResult {
+    /// The title of the page.
+    pub title: String,
+    /// The url of the page.
+    pub url: String,
+    /// The text of the page around the query.
+    pub snippet: String,
+}
+
+#[derive(Debug, Clone)]
+/// The struct of the client of a web search api.
+pub struct WebSearch {
+    /// The api of the backend.
+    backend: SearchBackend,
+    /// The url of the api, the root of the instance for SearxNG.
+    url: String,
+    /// The api key, not needed by SearxNG.
+    api_key: Option<ApiKey>,
+    /// The number of results when the call doesn't give one.
+    max_results: usize,
+}
+
+impl WebSearch {
+    /// Create a new WebSearch.
+    pub fn new(backend: SearchBackend, url: &str) -> Self {
+        WebSearch {
+            backend,
+            url: url.trim_end_matches('/').to_string(),
+            api_key: None,
+            max_results: Self::default_max_results(),
+        }
+    }
+    /// Create a new WebSearch of the SearxNG instance at the url.
+    pub fn searxng(url: &str) -> Self {
+        Self::new(SearchBackend::Searxng, url)
+    }
+    /// Create a new WebSearch of the Brave api.
+    pub fn brave(api_key: &str) -> Self {
+        Self::new(SearchBackend::Brave, BRAVE_SEARCH_URL).api_key(Some(api_key.to_string()))
+    }
+    /// Create a new WebSearch of the Bing api.
+    pub fn bing(api_key: &str) -> Self {
+        Self::new(SearchBackend::Bing, BING_SEARCH_URL).api_key(Some(api_key.to_string()))
+    }
+    /// Search the web and get at most `count` results.
+    pub async fn search(&self, query: &str, count: usize) -> AINodeResult<Vec<SearchResult>> {
+        let count = count.to_string();
+        let client = reqwest::Client::new();
+        let request = match self.backend {
+            SearchBackend::Searxng => client
+                .get(format!("{}/search", self.url))
+                .query(&[("q", query), ("format", "json")]),
+            SearchBackend::Brave => client
+                .get(&self.url)
+                .query(&[("q", query), ("count", &count)])
+                .header("X-Subscription-Token", self.key()?),
+            SearchBackend::Bing => client
+                .get(&self.url)
+                .query(&[("q", query), ("count", &count)])
+                .header("Ocp-Apim-Subscription-Key", self.key()?),
+        };
+        let response = request
+            .header("Accept", "application/json")
+            .send()
+            .await
+            .map_err(|e| search_error(format!("Failed to send the search request. {}", e)))?;
+        let status = response.status();
+        let text = response
+            .text()
+            .await
+            .map_err(|e| search_error(format!("Failed to read the search response. {}", e)))?;
+        if !status.is_success() {
+            return Err(search_error(format!(
+                "The search request is refused with the status {}. {}",
+                status, text
+            )));
+        }
+        let response: Value = serde_json::from_str(&text)
+            .map_err(|e| search_error(format!("The search response is not valid json. {}", e)))?;
+        let (results, title, snippet) = match self.backend {
+            SearchBackend::Searxng => (&response["results"], "title", "content"),
+            SearchBackend::Brave => (&response["web"]["results"], "title", "description"),
+            SearchBackend::Bing => (&response["webPages"]["value"], "name", "snippet"),
+        };
+        Ok(results
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|result| {
+                Some(SearchResult {
+                    title: strip_tags(result[title].as_str()?),
+                    url: result["url"].as_str()?.to_string(),
+                    snippet: strip_tags(result[snippet].as_str().unwrap_or_default()),
+                })
+            })
+            .take(count.parse().unwrap_or(usize::MAX))
+            .collect())
+    }
+    /// Register the `web_search` tool in the registry.
+    pub fn register(&self, registry: &mut ToolRegistry) {
+        let search = self.clone();
+        registry.register(
+            "web_search",
+            "Search the web for current information. Give the results with their title, url \
+             and snippet.",
+            json!({
+                "type": "object",
+                "properties": {
+                    "query": { "type": "string", "description": "The search query." },
+                    "count": {
+                        "type": "integer",
+                        "description": "The number of results.",
+                        "minimum": 1
+                    }
+                },
+                "required": ["query"]
+            }),
+            move |arguments| {
+                let search = search.clone();
+                async move {
+                    let query = arguments["query"]
+                        .as_str()
+                        .ok_or_else(|| "query should be a string".to_string())?;
+                    let count = arguments["count"]
+                        .as_u64()
+                        .map_or(search.max_results, |count| count.max(1) as usize);
+                    let results = search
+                        .search(query, count)
+                        .await
+                        .map_err(|e| e.get_message().to_string())?;
+                    Ok(format_results(&results))
+                }
+            },
+        );
+    }
+    /// Get the api key, required by the hosted apis.
+    fn key(&self) -> AINodeResult<&str> {
+        self.api_key.as_ref().map(ApiKey::expose).ok_or_else(|| {
+            search_error(format!(
+                "The {:?} search api needs an api key.",
+                self.backend
+            ))
+        })
+    }
+    /// Get the api of the backend.
+    pub fn get_backend(&self) -> SearchBackend {
+        self.backend
+    }
+    /// Set the url of the api as builder.
+    pub fn url(mut self, url: &str) -> Self {
+        self.url = url.trim_end_matches('/').to_string();
+        self
+    }
+    /// Get the url of the api.
+    pub fn get_url(&self) -> &str {
+        &self.url
+    }
+    /// Set the api key as builder.
+    pub fn api_key<K: Into<ApiKey>>(mut self, api_key: Option<K>) -> Self {
+        self.api_key = api_key.map(Into::into);
+        self
+    }
+    /// Set the api key.
+    pub fn set_api_key<K: Into<ApiKey>>(&mut self, api_key: Option<K>) {
+        self.api_key = api_key.map(Into::into);
+    }
+    /// Get the api key.
+    pub fn get_api_key(&self) -> Option<&str> {
+        self.api_key.as_ref().map(ApiKey::expose)
+    }
+    /// Set the number of results when the call doesn't give one as builder.
+    pub fn max_results(mut self, max_results: usize) -> Self {
+        self.max_results = max_results;
+        self
+    }
+    /// Get the number of results when the call doesn't give one.
+    pub fn get_max_results(&self) -> usize {
+        self.max_results
+    }
+    /// The default number of results.
+    pub fn default_max_results() -> usize {
+        5
+    }
+}
+
+/// Get the text of the results for the AI service.
+pub fn format_results(results: &[SearchResult]) -> String {
+    if results.is_empty() {
+        return "No result.".to_string();
+    }
+    results
+        .iter()
+        .enumerate()
+        .map(|(i, result)| {
+            format!(
+                "{}. {}\n{}\n{}",
+                i + 1,
+                result.title,
+                result.url,
+                result.snippet
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n\n")
+}
+
+/// Remove the html tags, like the `<strong>` of the matched words, and decode the entities.
+fn strip_tags(text: &str) -> String {
+    crate::loader::html::to_text(text)
+        .text
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Create an AINodeError of the search.
+fn search_error(message: String) -> AINodeError {
+    AINodeError::new(AINodeErrorType::SearchError, message)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::worknode::ai_node::tool::ToolCall;
+
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+    use tokio::runtime::Runtime;
+
+    /// Answer the first request with the body, and send back its request line and headers.
+    async fn server(listener: TcpListener, body: Value) -> String {
+        let (mut stream, _) = listener.accept().await.unwrap();
+        let mut request = Vec::new();
+        let mut buffer = [0; 1024];
+        while !request.ends_with(b"\r\n\r\n") {
+            let n = stream.read(&mut buffer).await.unwrap();
+            request.extend_from_slice(&buffer[..n]);
+        }
+        let body = body.to_string();
+        let response = format!(
+            "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            body.len(),
+            body
+        );
+        stream.write_all(response.as_bytes()).await.unwrap();
+        String::from_utf8(request).unwrap()
+    }
+
+    #[test]
+    fn search_backends() {
+        let rt = Runtime::new().unwrap();
+        let searxng = json!({"results": [
+            {"title": "Rust", "url": "https://rust-lang.org", "content": "A language &amp; more"},
+            {"title": "Crates", "url": "https://crates.io", "content": "Packages"}
+        ]});
+        let listener = rt.block_on(TcpListener::bind("127.0.0.1:0")).unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        let request = rt.spawn(server(listener, searxng));
+        let mut registry = ToolRegistry::new();
+        WebSearch::searxng(&url).register(&mut registry);
+        let call = ToolCall {
+            id: "call_0".to_string(),
+            name: "web_search".to_string(),
+            arguments: r#"{"query": "rust lang", "count": 1}"#.to_string(),
+        };
+        assert_eq!(
+            rt.block_on(registry.call(&call)),
+            "1. Rust\nhttps://rust-lang.org\nA language & more"
+        );
+        let request = rt.block_on(request).unwrap();
+        assert!(request.starts_with("GET /search?q=rust+lang&format=json "));
+
+        let bing = json!({"webPages": {"value": [
+            {"name": "Tokio", "url": "https://tokio.rs", "snippet": "An <b>async</b> runtime"}
+        ]}});
+        let listener = rt.block_on(TcpListener::bind("127.0.0.1:0")).unwrap();
+        let url = format!("http://{}/v7.0/search", listener.local_addr().unwrap());
+        let request = rt.spawn(server(listener, bing));
+        let results = rt
+            .block_on(WebSearch::bing("key").url(&url).search("tokio", 3))
+            .unwrap();
+        assert_eq!(results[0].title, "Tokio");
+        assert_eq!(results[0].snippet, "An async runtime");
+        let request = rt.block_on(request).unwrap().to_lowercase();
+        assert!(request.contains("ocp-apim-subscription-key: key"));
+        assert!(request.contains("count=3"));
+
+        let error = rt
+            .block_on(WebSearch::new(SearchBackend::Brave, &url).search("x", 1))
+            .unwrap_err();
+        assert!(error.get_message().contains("needs an api key"));
+    }
+}
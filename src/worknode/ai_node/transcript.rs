@@ -0,0 +1,164 @@
+//! # Transcript
+//!
+//! This module renders a conversation as a Markdown or simple HTML transcript, with the role,
+//! the time and the token usage of every turn, so the result of an AI node can be shared with
+//! people who don't read json.
+
+use super::{Chat, Content, ContentPart};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// The enum of the format of the transcript.
+pub enum TranscriptFormat {
+    /// Markdown.
+    Markdown,
+    /// A standalone HTML page.
+    Html,
+}
+
+/// Render the chats in the given format.
+pub fn render(chats: &[Chat], format: TranscriptFormat) -> String {
+    match format {
+        TranscriptFormat::Markdown => to_markdown(chats),
+        TranscriptFormat::Html => to_html(chats),
+    }
+}
+
+/// Render the chats as Markdown.
+pub fn to_markdown(chats: &[Chat]) -> String {
+    let mut text = String::from("# Transcript\n");
+    for chat in chats {
+        text.push_str(&format!("\n## {}\n\n", heading(chat)));
+        if let Some(usage) = usage(chat) {
+            text.push_str(&format!("*{}*\n\n", usage));
+        }
+        match chat.get_content() {
+            Content::Text(content) => text.push_str(content),
+            Content::Parts(parts) => {
+                for part in parts {
+                    match part {
+                        ContentPart::Text { text: content } => text.push_str(content),
+                        _ => text.push_str(&format!("![image]({})", image_src(part))),
+                    }
+                    text.push_str("\n\n");
+                }
+            }
+        }
+        text.push('\n');
+    }
+    text
+}
+
+/// Render the chats as a standalone HTML page.
+pub fn to_html(chats: &[Chat]) -> String {
+    let mut html = String::from(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>Transcript</title>\n\
+         </head>\n<body>\n<h1>Transcript</h1>\n",
+    );
+    for chat in chats {
+        html.push_str(&format!(
+            "<div class=\"chat {}\">\n<h2>{}</h2>\n",
+            chat.get_role(),
+            escape_html(&heading(chat))
+        ));
+        if let Some(usage) = usage(chat) {
+            html.push_str(&format!("<p><em>{}</em></p>\n", escape_html(&usage)));
+        }
+        match chat.get_content() {
+            Content::Text(content) => {
+                html.push_str(&format!("<pre>{}</pre>\n", escape_html(content)))
+            }
+            Content::Parts(parts) => {
+                for part in parts {
+                    match part {
+                        ContentPart::Text { text } => {
+                            html.push_str(&format!("<pre>{}</pre>\n", escape_html(text)))
+                        }
+                        _ => html.push_str(&format!(
+                            "<img src=\"{}\" alt=\"image\">\n",
+                            escape_html(&image_src(part))
+                        )),
+                    }
+                }
+            }
+        }
+        html.push_str("</div>\n");
+    }
+    html.push_str("</body>\n</html>\n");
+    html
+}
+
+/// The heading of a chat, made of the role, the name and the time.
+fn heading(chat: &Chat) -> String {
+    let metadata = chat.get_metadata();
+    let mut heading = chat.get_role().to_string();
+    if let Some(name) = &metadata.name {
+        heading.push_str(&format!(" ({})", name));
+    }
+    if let Some(created_at) = metadata.created_at {
+        heading.push_str(&format!(
+            " - {}",
+            created_at.format("%Y-%m-%d %H:%M:%S UTC")
+        ));
+    }
+    heading
+}
+
+/// The token usage of a chat, if it is recorded.
+fn usage(chat: &Chat) -> Option<String> {
+    let metadata = chat.get_metadata();
+    match (metadata.prompt_tokens, metadata.completion_tokens) {
+        (None, None) => None,
+        (prompt, completion) => Some(format!(
+            "prompt tokens: {}, completion tokens: {}",
+            prompt.map_or("-".to_string(), |t| t.to_string()),
+            completion.map_or("-".to_string(), |t| t.to_string())
+        )),
+    }
+}
+
+/// The source of an image part.
+fn image_src(part: &ContentPart) -> String {
+    match part {
+        ContentPart::ImageUrl { url, .. } => url.clone(),
+        ContentPart::ImageBase64 { media_type, data } => {
+            format!("data:{};base64,{}", media_type, data)
+        }
+        ContentPart::Text { .. } => String::new(),
+    }
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::Role;
+    use super::*;
+
+    fn chats() -> Vec<Chat> {
+        vec![
+            Chat::new(Role::User, "Is 1 < 2?".to_string()).name(Some("alice".to_string())),
+            Chat::new(Role::Assistant, "Yes".to_string()).tokens(Some(12), Some(1)),
+        ]
+    }
+
+    #[test]
+    fn markdown_transcript() {
+        let text = to_markdown(&chats());
+        assert!(text.contains("## user (alice) - "));
+        assert!(text.contains("Is 1 < 2?"));
+        assert!(text.contains("*prompt tokens: 12, completion tokens: 1*"));
+    }
+
+    #[test]
+    fn html_transcript_is_escaped() {
+        let html = render(&chats(), TranscriptFormat::Html);
+        assert!(html.contains("<pre>Is 1 &lt; 2?</pre>"));
+        assert!(html.contains("<div class=\"chat assistant\">"));
+        assert!(html.ends_with("</html>\n"));
+    }
+}
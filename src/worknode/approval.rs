@@ -0,0 +1,256 @@
+//! # Approval
+//!
+//! This node pauses a run until a human approves or rejects it, for the workflows that run
+//! shell commands, send emails or do anything else that should be checked first.
+//!
+//! When the node starts, a pending `ApprovalRequest` with the rendered message and the input
+//! of the node is put in the `Approvals` of the run context, and the workflow emits it as a
+//! `RunEvent::ApprovalRequested`. The run goes on with the other branches while the node
+//! waits. `Approvals::approve` lets the input pass to the normal edges of the node, and
+//! `Approvals::reject` sends the reason of the rejection to the rejected edges instead.
+//!
+//! The message is a template (see [`crate::template`]) rendered with the variables of the
+//! run context and the variable `input`.
+
+use crate::error::graph_error::{GraphError, GraphErrorType};
+use crate::error::{PilotError, PilotErrorType, PilotResult};
+use crate::template;
+use crate::workflow::context::RunContext;
+
+use serde::{Deserialize, Serialize};
+use tokio::sync::Notify;
+use uuid::Uuid;
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, PoisonError};
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+/// The enum of the decision of a human.
+pub enum Decision {
+    /// Go on with the normal edges.
+    Approve,
+    /// Go on with the rejected edges, with the reason.
+    Reject(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// The struct of a decision that a node waits for.
+pub struct ApprovalRequest {
+    /// The uid of the node.
+    pub node: Uuid,
+    /// The message shown to the human.
+    pub message: String,
+    /// The input of the node, which is what gets approved.
+    pub input: String,
+}
+
+#[derive(Debug, Default)]
+/// The struct of the requests and the decisions of a run.
+struct ApprovalState {
+    /// The requests that wait for a decision, by node.
+    pending: HashMap<Uuid, ApprovalRequest>,
+    /// The decisions that are not taken by their node yet, by node.
+    decisions: HashMap<Uuid, Decision>,
+}
+
+#[derive(Debug, Clone, Default)]
+/// The struct of the approvals of a run. The clones share the same approvals.
+pub struct Approvals {
+    state: Arc<Mutex<ApprovalState>>,
+    /// Wakes up the waiting nodes when a decision is made.
+    decided: Arc<Notify>,
+}
+
+impl Approvals {
+    /// Create a new Approvals without any request.
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Add a request. A request of the same node that is still pending is kept, and a request
+    /// that is already decided is not added again.
+    pub fn request(&self, request: ApprovalRequest) {
+        let mut state = self.lock();
+        if !state.decisions.contains_key(&request.node) {
+            state.pending.entry(request.node).or_insert(request);
+        }
+    }
+    /// Get the requests that wait for a decision.
+    pub fn pending(&self) -> Vec<ApprovalRequest> {
+        self.lock().pending.values().cloned().collect()
+    }
+    /// Get the request of the node, if it waits for a decision.
+    pub fn get_pending(&self, node: Uuid) -> Option<ApprovalRequest> {
+        self.lock().pending.get(&node).cloned()
+    }
+    /// Approve the request of the node.
+    pub fn approve(&self, node: Uuid) -> PilotResult<()> {
+        self.decide(node, Decision::Approve)
+    }
+    /// Reject the request of the node with the reason.
+    pub fn reject(&self, node: Uuid, reason: &str) -> PilotResult<()> {
+        self.decide(node, Decision::Reject(reason.to_string()))
+    }
+    /// Make the decision of the request of the node. The node must wait for a decision.
+    pub fn decide(&self, node: Uuid, decision: Decision) -> PilotResult<()> {
+        let mut state = self.lock();
+        if state.pending.remove(&node).is_none() {
+            return Err(approval_error(format!(
+                "The node {} doesn't wait for a decision.",
+                node
+            )));
+        }
+        state.decisions.insert(node, decision);
+        drop(state);
+        self.decided.notify_waiters();
+        Ok(())
+    }
+    /// Wait for the decision of the node.
+    pub(crate) async fn wait(&self, node: Uuid) -> Decision {
+        loop {
+            // listen before checking, so a decision made in between is not missed
+            let decided = self.decided.notified();
+            if let Some(decision) = self.lock().decisions.remove(&node) {
+                return decision;
+            }
+            decided.await;
+        }
+    }
+    /// Lock the state. A panic in another node doesn't make the state invalid.
+    fn lock(&self) -> std::sync::MutexGuard<'_, ApprovalState> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+/// The struct of the approval node.
+pub struct ApprovalNode {
+    /// The template of the message shown to the human.
+    message: String,
+    /// The decision of the last execution.
+    decision: Option<Decision>,
+    /// The uid of the worknode that holds this node.
+    node_uid: Option<Uuid>,
+}
+
+impl ApprovalNode {
+    /// Create a new ApprovalNode with the template of the message.
+    pub fn new(message: &str) -> Self {
+        ApprovalNode {
+            message: message.to_string(),
+            ..Self::default()
+        }
+    }
+    /// Build the request of the input.
+    pub fn request_for(&self, input: &str, context: &RunContext) -> PilotResult<ApprovalRequest> {
+        let node = self
+            .node_uid
+            .ok_or_else(|| approval_error("The approval node is not in a worknode.".to_string()))?;
+        let mut variables = context.to_variables();
+        variables.insert("input".to_string(), input.to_string());
+        let message = template::render(&self.message, &variables).map_err(|e| {
+            approval_error(format!(
+                "Failed to render the message of the approval. {}",
+                e
+            ))
+        })?;
+        Ok(ApprovalRequest {
+            node,
+            message,
+            input: input.to_string(),
+        })
+    }
+    /// Wait for the decision. The input is the output when it is approved, and the reason is
+    /// the output when it is rejected.
+    pub async fn execute(&mut self, input: String, context: &RunContext) -> PilotResult<String> {
+        let request = self.request_for(&input, context)?;
+        let node = request.node;
+        self.decision = None;
+        context.approvals().request(request);
+        let decision = context.approvals().wait(node).await;
+        let output = match &decision {
+            Decision::Approve => input,
+            Decision::Reject(reason) => reason.clone(),
+        };
+        self.decision = Some(decision);
+        Ok(output)
+    }
+    /// Whether the last execution was rejected.
+    pub fn is_rejected(&self) -> bool {
+        matches!(self.decision, Some(Decision::Reject(_)))
+    }
+    /// Get the decision of the last execution.
+    pub fn get_decision(&self) -> Option<&Decision> {
+        self.decision.as_ref()
+    }
+    /// Set the template of the message as builder.
+    pub fn message(mut self, message: &str) -> Self {
+        self.message = message.to_string();
+        self
+    }
+    /// Set the template of the message.
+    pub fn set_message(&mut self, message: &str) {
+        self.message = message.to_string();
+    }
+    /// Get the template of the message.
+    pub fn get_message(&self) -> &str {
+        &self.message
+    }
+    /// Set the uid of the worknode that holds this node.
+    pub fn set_node_uid(&mut self, node_uid: Option<Uuid>) {
+        self.node_uid = node_uid;
+    }
+}
+
+/// Create a PilotError of an approval.
+fn approval_error(message: String) -> PilotError {
+    PilotError::new(
+        PilotErrorType::GraphErr(GraphError::new(GraphErrorType::ApprovalError, message)),
+        "The approval failed".to_string(),
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use tokio::runtime::Runtime;
+
+    #[test]
+    fn approve_and_reject() {
+        let node = Uuid::new_v4();
+        let mut approval = ApprovalNode::new("Run {{input}} for {{context.user}}?");
+        approval.set_node_uid(Some(node));
+        let context = RunContext::new();
+        context.set("user", "Tom").unwrap();
+        let rt = Runtime::new().unwrap();
+
+        let approvals = context.approvals().clone();
+        assert!(approvals.approve(node).is_err());
+        let decider = std::thread::spawn(move || loop {
+            if let Some(request) = approvals.get_pending(node) {
+                assert_eq!(request.message, "Run rm -rf for Tom?");
+                approvals.reject(node, "too dangerous").unwrap();
+                break;
+            }
+            std::thread::yield_now();
+        });
+        let output = rt
+            .block_on(approval.execute("rm -rf".to_string(), &context))
+            .unwrap();
+        decider.join().unwrap();
+        assert_eq!(output, "too dangerous");
+        assert!(approval.is_rejected());
+
+        context
+            .approvals()
+            .request(approval.request_for("ls", &context).unwrap());
+        context.approvals().approve(node).unwrap();
+        let output = rt
+            .block_on(approval.execute("ls".to_string(), &context))
+            .unwrap();
+        assert_eq!(output, "ls");
+        assert_eq!(approval.get_decision(), Some(&Decision::Approve));
+        assert!(context.approvals().pending().is_empty());
+    }
+}
@@ -0,0 +1,287 @@
+//! # Assert
+//!
+//! This node checks its input against rules and passes it on unchanged when it keeps all of
+//! them. It guards a side-effecting node, like a file write or a local command, from an AI
+//! output that is malformed.
+//!
+//! The rules are:
+//! - non_empty: the value is not empty. A text of only spaces, `null`, `[]` and `{}` are
+//!   empty.
+//! - regex: the value matches the regular expression somewhere. Use `^` and `$` to match
+//!   the whole value.
+//! - range: the value is a number between `min` and `max`, both included.
+//! - schema: the input is json that matches the json schema.
+//!
+//! A rule other than schema checks the whole input as text, or with a json pointer, like
+//! `/score`, the value at the pointer of the json input, where a string is checked without
+//! its quotes.
+//!
+//! All the rules are checked, and when the input breaks any of them, the node fails with the
+//! list of the violations, which an on_error edge can carry to a node that handles them.
+
+use crate::error::assert_node_error::{
+    AssertNodeError, AssertNodeErrorType, AssertNodeResult, Violation,
+};
+
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "rule", rename_all = "snake_case")]
+/// The enum of a rule that the input must keep.
+pub enum Rule {
+    /// The value is not empty.
+    NonEmpty {
+        /// The json pointer to the value, or the whole input.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pointer: Option<String>,
+    },
+    /// The value matches the regular expression.
+    Regex {
+        /// The regular expression.
+        pattern: String,
+        /// The json pointer to the value, or the whole input.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pointer: Option<String>,
+    },
+    /// The value is a number between the bounds.
+    Range {
+        /// The smallest number allowed.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        min: Option<f64>,
+        /// The largest number allowed.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        max: Option<f64>,
+        /// The json pointer to the value, or the whole input.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pointer: Option<String>,
+    },
+    /// The input matches the json schema.
+    Schema {
+        /// The json schema.
+        schema: Value,
+    },
+}
+
+impl Rule {
+    /// Get the name of the rule.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Rule::NonEmpty { .. } => "non_empty",
+            Rule::Regex { .. } => "regex",
+            Rule::Range { .. } => "range",
+            Rule::Schema { .. } => "schema",
+        }
+    }
+    /// Check the input, and get what is wrong with it.
+    pub fn check(&self, input: &str) -> AssertNodeResult<Vec<Violation>> {
+        let pointer = match self {
+            Rule::NonEmpty { pointer }
+            | Rule::Regex { pointer, .. }
+            | Rule::Range { pointer, .. } => pointer.as_deref(),
+            Rule::Schema { schema } => return self.check_schema(schema, input),
+        };
+        let violation = |message: String| Violation {
+            rule: self.name().to_string(),
+            path: pointer.unwrap_or_default().to_string(),
+            message,
+        };
+        let value = match pointer {
+            None => Value::String(input.to_string()),
+            Some(pointer) => match serde_json::from_str::<Value>(input) {
+                Ok(json) => match json.pointer(pointer) {
+                    Some(value) => value.clone(),
+                    None => return Ok(vec![violation("There is no value.".to_string())]),
+                },
+                Err(e) => {
+                    return Ok(vec![violation(format!(
+                        "The input is not valid json. {}",
+                        e
+                    ))])
+                }
+            },
+        };
+        let text = match &value {
+            Value::String(text) => text.clone(),
+            value => value.to_string(),
+        };
+        let message = match self {
+            Rule::NonEmpty { .. } => is_empty(&value).then(|| "The value is empty.".to_string()),
+            Rule::Regex { pattern, .. } => {
+                let regex = Regex::new(pattern).map_err(|e| {
+                    AssertNodeError::new(
+                        AssertNodeErrorType::InvalidRule,
+                        format!("The regex `{}` is not valid. {}", pattern, e),
+                    )
+                })?;
+                (!regex.is_match(&text)).then(|| format!("`{}` doesn't match `{}`.", text, pattern))
+            }
+            Rule::Range { min, max, .. } => match text.trim().parse::<f64>() {
+                Ok(number) if min.is_some_and(|min| number < min) => {
+                    Some(format!("{} is less than {}.", number, min.unwrap()))
+                }
+                Ok(number) if max.is_some_and(|max| number > max) => {
+                    Some(format!("{} is more than {}.", number, max.unwrap()))
+                }
+                Ok(number) if number.is_nan() => Some("The value is not a number.".to_string()),
+                Ok(_) => None,
+                Err(_) => Some(format!("`{}` is not a number.", text)),
+            },
+            Rule::Schema { .. } => None,
+        };
+        Ok(message.map(violation).into_iter().collect())
+    }
+    /// Check the input against the json schema.
+    fn check_schema(&self, schema: &Value, input: &str) -> AssertNodeResult<Vec<Violation>> {
+        let violation = |path: String, message: String| Violation {
+            rule: self.name().to_string(),
+            path,
+            message,
+        };
+        let validator = jsonschema::validator_for(schema).map_err(|e| {
+            AssertNodeError::new(
+                AssertNodeErrorType::InvalidRule,
+                format!("The schema is not valid. {}", e),
+            )
+        })?;
+        Ok(match serde_json::from_str(input) {
+            Ok(value) => validator
+                .iter_errors(&value)
+                .map(|e| violation(e.instance_path().to_string(), e.to_string()))
+                .collect(),
+            Err(e) => vec![violation(
+                String::new(),
+                format!("The input is not valid json. {}", e),
+            )],
+        })
+    }
+}
+
+/// Whether the value is empty.
+fn is_empty(value: &Value) -> bool {
+    match value {
+        Value::Null => true,
+        Value::String(text) => text.trim().is_empty(),
+        Value::Array(items) => items.is_empty(),
+        Value::Object(fields) => fields.is_empty(),
+        _ => false,
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+/// The struct of the assert node.
+pub struct AssertNode {
+    /// The rules that the input must keep.
+    rules: Vec<Rule>,
+}
+
+impl AssertNode {
+    /// Create a new AssertNode with the rules.
+    pub fn new(rules: Vec<Rule>) -> Self {
+        AssertNode { rules }
+    }
+    /// Check the input against all the rules, and pass it on if it keeps them.
+    pub fn execute(&self, input: String) -> AssertNodeResult<String> {
+        let mut violations = Vec::new();
+        for rule in &self.rules {
+            violations.extend(rule.check(&input)?);
+        }
+        if violations.is_empty() {
+            Ok(input)
+        } else {
+            Err(AssertNodeError::new(
+                AssertNodeErrorType::Violated(violations),
+                "The input breaks the rules.".to_string(),
+            ))
+        }
+    }
+    /// Add a rule as builder.
+    pub fn rule(mut self, rule: Rule) -> Self {
+        self.rules.push(rule);
+        self
+    }
+    /// Add a rule.
+    pub fn add_rule(&mut self, rule: Rule) {
+        self.rules.push(rule);
+    }
+    /// Set the rules.
+    pub fn set_rules(&mut self, rules: Vec<Rule>) {
+        self.rules = rules;
+    }
+    /// Get the rules.
+    pub fn get_rules(&self) -> &Vec<Rule> {
+        &self.rules
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use serde_json::json;
+
+    fn violations(node: &AssertNode, input: &str) -> Vec<Violation> {
+        match node.execute(input.to_string()) {
+            Ok(_) => Vec::new(),
+            Err(e) => match e.get_error_type() {
+                AssertNodeErrorType::Violated(violations) => violations.clone(),
+                AssertNodeErrorType::InvalidRule => panic!("{}", e),
+            },
+        }
+    }
+
+    #[test]
+    fn check_rules() {
+        let node = AssertNode::default()
+            .rule(Rule::NonEmpty {
+                pointer: Some("/summary".to_string()),
+            })
+            .rule(Rule::Regex {
+                pattern: "^[a-z]+$".to_string(),
+                pointer: Some("/label".to_string()),
+            })
+            .rule(Rule::Range {
+                min: Some(0.0),
+                max: Some(1.0),
+                pointer: Some("/score".to_string()),
+            })
+            .rule(Rule::Schema {
+                schema: json!({"type": "object", "required": ["summary"]}),
+            });
+        let input = r#"{"summary": "ok", "label": "spam", "score": 0.5}"#;
+        assert_eq!(node.execute(input.to_string()).unwrap(), input);
+
+        let broken = violations(&node, r#"{"summary": " ", "label": "Spam", "score": 2}"#);
+        let rules: Vec<&str> = broken.iter().map(|v| v.rule.as_str()).collect();
+        assert_eq!(rules, ["non_empty", "regex", "range"]);
+        assert_eq!(broken[2].path, "/score");
+        let broken = violations(&node, r#"{"label": "spam", "score": "x"}"#);
+        let rules: Vec<&str> = broken.iter().map(|v| v.rule.as_str()).collect();
+        assert_eq!(rules, ["non_empty", "range", "schema"]);
+
+        // without a pointer, the whole input is checked as text
+        let node = AssertNode::new(vec![
+            Rule::NonEmpty { pointer: None },
+            Rule::Range {
+                min: Some(1.0),
+                max: None,
+                pointer: None,
+            },
+        ]);
+        assert!(node.execute(" 3 ".to_string()).is_ok());
+        assert_eq!(violations(&node, "").len(), 2);
+
+        let invalid = AssertNode::default().rule(Rule::Regex {
+            pattern: "(".to_string(),
+            pointer: None,
+        });
+        assert!(matches!(
+            invalid
+                .execute("a".to_string())
+                .unwrap_err()
+                .get_error_type(),
+            AssertNodeErrorType::InvalidRule
+        ));
+    }
+}
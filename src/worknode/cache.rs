@@ -0,0 +1,302 @@
+//! # Cache
+//!
+//! This node wraps a child node and remembers its outputs, so an expensive step, like an AI
+//! node that gets the same input again and again, is not run (and billed) again for an input
+//! it has already seen.
+//!
+//! The key of an entry is the SHA-256 hash of the namespace of the node and the input. On a
+//! hit, the output is taken from the store and the child is not executed; on a miss, the
+//! child is executed and its output is stored. Only the input is in the key: a child whose
+//! output depends on the run context, or a child that is changed, should get a new namespace.
+//! With a time to live, an entry older than it is a miss.
+//!
+//! The store is in memory, shared by the clones of the node, or on the disk, as one json file
+//! per entry in a directory, so the entries outlive the process. A store that can't be read
+//! or written is logged and taken as a miss, so the cache never fails the node by itself.
+
+use super::Worknodecore;
+use crate::error::PilotResult;
+use crate::workflow::context::RunContext;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use std::collections::HashMap;
+use std::future::Future;
+use std::path::PathBuf;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::Duration;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+/// The struct of an output remembered by the cache.
+pub struct CacheEntry {
+    /// The input of the child, compared on a hit so a collision of the hash is a miss.
+    pub input: String,
+    /// The output of the child.
+    pub output: String,
+    /// When the output was stored.
+    pub created: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone)]
+/// The enum of where the entries are stored.
+pub enum CacheStore {
+    /// In memory, shared by the clones of the store.
+    Memory(Arc<Mutex<HashMap<String, CacheEntry>>>),
+    /// In a directory, as one json file per entry.
+    Disk(PathBuf),
+}
+
+impl Default for CacheStore {
+    fn default() -> Self {
+        Self::memory()
+    }
+}
+
+impl CacheStore {
+    /// Create a new empty store in memory.
+    pub fn memory() -> Self {
+        CacheStore::Memory(Arc::default())
+    }
+    /// Create a store in the directory. The directory is created when the first entry is
+    /// written.
+    pub fn disk<P: Into<PathBuf>>(dir: P) -> Self {
+        CacheStore::Disk(dir.into())
+    }
+    /// Get the entry of the key.
+    pub async fn get(&self, key: &str) -> Option<CacheEntry> {
+        match self {
+            // the lock is only poisoned by a panic, which has failed the run already
+            CacheStore::Memory(entries) => entries
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .get(key)
+                .cloned(),
+            CacheStore::Disk(dir) => {
+                let path = dir.join(format!("{}.json", key));
+                let text = tokio::fs::read_to_string(&path).await.ok()?;
+                serde_json::from_str(&text)
+                    .inspect_err(|e| {
+                        log::warn!("Failed to read the cache entry {}. {}", path.display(), e)
+                    })
+                    .ok()
+            }
+        }
+    }
+    /// Store the entry under the key.
+    pub async fn set(&self, key: &str, entry: CacheEntry) {
+        match self {
+            CacheStore::Memory(entries) => {
+                entries
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .insert(key.to_string(), entry);
+            }
+            CacheStore::Disk(dir) => {
+                let path = dir.join(format!("{}.json", key));
+                // the entry is always valid json
+                let text = serde_json::to_string(&entry).unwrap();
+                let result = match tokio::fs::create_dir_all(dir).await {
+                    Ok(()) => tokio::fs::write(&path, text).await,
+                    Err(e) => Err(e),
+                };
+                if let Err(e) = result {
+                    log::warn!("Failed to write the cache entry {}. {}", path.display(), e);
+                }
+            }
+        }
+    }
+    /// Remove all entries.
+    pub async fn clear(&self) {
+        match self {
+            CacheStore::Memory(entries) => entries
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .clear(),
+            CacheStore::Disk(dir) => {
+                if let Err(e) = tokio::fs::remove_dir_all(dir).await {
+                    log::warn!("Failed to clear the cache {}. {}", dir.display(), e);
+                }
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+/// The struct of the cache node.
+pub struct CacheNode {
+    /// The child node whose outputs are remembered.
+    node: Box<Worknodecore>,
+    /// Where the entries are stored.
+    store: CacheStore,
+    /// How long an entry is valid, or forever.
+    ttl: Option<Duration>,
+    /// The namespace put in the key of the entries.
+    namespace: String,
+    /// Whether the last execution was answered from the store.
+    hit: bool,
+}
+
+impl CacheNode {
+    /// Create a new CacheNode that remembers the outputs of the child in memory.
+    pub fn new(node: Worknodecore) -> Self {
+        CacheNode {
+            node: Box::new(node),
+            store: CacheStore::default(),
+            ttl: None,
+            namespace: String::new(),
+            hit: false,
+        }
+    }
+    /// Get the output of the input from the store, or execute the child and store its output.
+    pub async fn execute(&mut self, input: String, context: &RunContext) -> PilotResult<String> {
+        let key = self.key(&input);
+        self.hit = false;
+        if let Some(entry) = self.store.get(&key).await {
+            let fresh = self.ttl.is_none_or(|ttl| {
+                (Utc::now() - entry.created)
+                    .to_std()
+                    .is_ok_and(|age| age < ttl)
+            });
+            if entry.input == input && fresh {
+                self.hit = true;
+                return Ok(entry.output);
+            }
+        }
+        let output = execute_child(&mut self.node, input.clone(), context).await?;
+        let entry = CacheEntry {
+            input,
+            output: output.clone(),
+            created: Utc::now(),
+        };
+        self.store.set(&key, entry).await;
+        Ok(output)
+    }
+    /// Get the key of the entry of the input.
+    pub fn key(&self, input: &str) -> String {
+        let mut hasher = Sha256::new();
+        hasher.update(self.namespace.as_bytes());
+        hasher.update([0]);
+        hasher.update(input.as_bytes());
+        format!("{:x}", hasher.finalize())
+    }
+    /// Whether the last execution was answered from the store.
+    pub fn is_hit(&self) -> bool {
+        self.hit
+    }
+    /// Get the child node.
+    pub fn get_node(&self) -> &Worknodecore {
+        &self.node
+    }
+    /// Get the mutable child node.
+    pub fn get_node_mut(&mut self) -> &mut Worknodecore {
+        &mut self.node
+    }
+    /// Set the store as builder.
+    pub fn store(mut self, store: CacheStore) -> Self {
+        self.store = store;
+        self
+    }
+    /// Set the store.
+    pub fn set_store(&mut self, store: CacheStore) {
+        self.store = store;
+    }
+    /// Get the store.
+    pub fn get_store(&self) -> &CacheStore {
+        &self.store
+    }
+    /// Set the time to live of the entries as builder.
+    pub fn ttl(mut self, ttl: Option<Duration>) -> Self {
+        self.ttl = ttl;
+        self
+    }
+    /// Set the time to live of the entries.
+    pub fn set_ttl(&mut self, ttl: Option<Duration>) {
+        self.ttl = ttl;
+    }
+    /// Get the time to live of the entries.
+    pub fn get_ttl(&self) -> Option<Duration> {
+        self.ttl
+    }
+    /// Set the namespace as builder.
+    pub fn namespace(mut self, namespace: &str) -> Self {
+        self.namespace = namespace.to_string();
+        self
+    }
+    /// Set the namespace.
+    pub fn set_namespace(&mut self, namespace: &str) {
+        self.namespace = namespace.to_string();
+    }
+    /// Get the namespace.
+    pub fn get_namespace(&self) -> &str {
+        &self.namespace
+    }
+}
+
+/// Execute the child once. The future is boxed, since the child may be a cache node itself.
+fn execute_child<'a>(
+    node: &'a mut Worknodecore,
+    input: String,
+    context: &'a RunContext,
+) -> Pin<Box<dyn Future<Output = PilotResult<String>> + Send + 'a>> {
+    Box::pin(node.excute_once(input, context))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::worknode::transform::JsonTransformNode;
+
+    use tokio::runtime::Runtime;
+    use uuid::Uuid;
+
+    #[test]
+    fn cache_outputs() {
+        let rt = Runtime::new().unwrap();
+        let context = RunContext::new();
+        let child = Worknodecore::JsonTransform(JsonTransformNode::filter(".n"));
+        let mut cache = CacheNode::new(child.clone());
+        let output = rt
+            .block_on(cache.execute(r#"{"n": 1}"#.to_string(), &context))
+            .unwrap();
+        assert_eq!(output, "1");
+        assert!(!cache.is_hit());
+        // the clones share the memory store
+        let mut clone = cache.clone();
+        rt.block_on(clone.execute(r#"{"n": 1}"#.to_string(), &context))
+            .unwrap();
+        assert!(clone.is_hit());
+        // a failure of the child is not stored
+        assert!(rt
+            .block_on(cache.execute("x".to_string(), &context))
+            .is_err());
+        assert!(rt
+            .block_on(cache.get_store().get(&cache.key("x")))
+            .is_none());
+
+        let dir = std::env::temp_dir().join(format!("aipilot-{}", Uuid::new_v4()));
+        let mut cache = CacheNode::new(child)
+            .store(CacheStore::disk(&dir))
+            .ttl(Some(Duration::from_secs(60)));
+        let input = r#"{"n": 2}"#.to_string();
+        rt.block_on(cache.execute(input.clone(), &context)).unwrap();
+        let key = cache.key(&input);
+        let mut entry = rt.block_on(cache.get_store().get(&key)).unwrap();
+        assert_eq!(entry.output, "2");
+        // the output in the store is returned on a hit
+        entry.output = "cached".to_string();
+        rt.block_on(cache.get_store().set(&key, entry.clone()));
+        let output = rt.block_on(cache.execute(input.clone(), &context)).unwrap();
+        assert_eq!(output, "cached");
+        // an expired entry is a miss
+        entry.created = Utc::now() - Duration::from_secs(120);
+        rt.block_on(cache.get_store().set(&key, entry));
+        let output = rt.block_on(cache.execute(input, &context)).unwrap();
+        assert_eq!(output, "2");
+        assert!(!cache.is_hit());
+        rt.block_on(cache.get_store().clear());
+        assert!(!dir.exists());
+    }
+}
@@ -0,0 +1,588 @@
+//! # Chunker
+//!
+//! This node splits long documents into pieces of a size for retrieval, to be embedded and
+//! stored one by one.
+//!
+//! The text is cut into units at the boundaries of the strategy, and the units are packed
+//! into chunks of at most `size` tokens (estimated as in the history of the AI node), where
+//! the next chunk starts again with the last `overlap` tokens of units of the previous one.
+//! A unit larger than a chunk is cut at the words. The strategies are:
+//! - tokens: the units are the words, so the chunks are of a fixed size.
+//! - sentences: the units are the sentences, so no sentence is cut.
+//! - markdown: the units are the paragraphs, and a chunk never spans two sections. A code
+//!   block is a paragraph, even with blank lines in it. The chunks have the headings of
+//!   their section.
+//! - code: the units are the top level blocks of the source, which start without indent after
+//!   a blank line, so a function is kept with its comments.
+//!
+//! The input is a text, or a json object with the `text` and the `metadata` of a document,
+//! like the output of a load node, or a json array of them. The output is the json array of
+//! the chunks, each with its `text`, its `index` in the document, its `start` and `end` byte
+//! offsets in the text, its estimated `tokens`, its `heading` for markdown, and the
+//! `metadata` of the document.
+
+use super::ai_node::history::estimate_tokens;
+use crate::error::chunker_node_error::{ChunkerNodeError, ChunkerNodeErrorType, ChunkerNodeResult};
+
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+/// The enum of where the text is cut.
+pub enum ChunkStrategy {
+    /// At the words.
+    #[default]
+    Tokens,
+    /// At the sentences.
+    Sentences,
+    /// At the paragraphs, and always at the headings.
+    Markdown,
+    /// At the top level blocks of the source.
+    Code,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+/// The struct of a chunk of a text.
+pub struct Chunk {
+    /// The index of the chunk in the text.
+    pub index: usize,
+    /// The text of the chunk.
+    pub text: String,
+    /// The byte offset of the start of the chunk in the text.
+    pub start: usize,
+    /// The byte offset of the end of the chunk in the text.
+    pub end: usize,
+    /// The estimated number of tokens.
+    pub tokens: usize,
+    /// The headings of the section of the chunk, joined by ` > `, for markdown.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub heading: Option<String>,
+}
+
+/// The struct of a span of the text that is not cut.
+#[derive(Debug, Clone, Copy)]
+struct Unit {
+    start: usize,
+    end: usize,
+    /// The section of the unit. Units of different sections are never in the same chunk.
+    section: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+/// The struct of the chunker node.
+pub struct ChunkerNode {
+    /// Where the text is cut.
+    #[serde(default)]
+    strategy: ChunkStrategy,
+    /// The max number of tokens of a chunk.
+    #[serde(default = "ChunkerNode::default_size")]
+    size: usize,
+    /// The number of tokens repeated from the end of a chunk at the start of the next one.
+    #[serde(default = "ChunkerNode::default_overlap")]
+    overlap: usize,
+}
+
+impl Default for ChunkerNode {
+    fn default() -> Self {
+        Self::new(ChunkStrategy::default())
+    }
+}
+
+impl ChunkerNode {
+    /// Create a new ChunkerNode with the default size and overlap.
+    pub fn new(strategy: ChunkStrategy) -> Self {
+        ChunkerNode {
+            strategy,
+            size: Self::default_size(),
+            overlap: Self::default_overlap(),
+        }
+    }
+    /// Split the text or the documents of the input into chunks.
+    pub fn execute(&self, input: String) -> ChunkerNodeResult<String> {
+        let documents = match serde_json::from_str::<Value>(&input) {
+            Ok(Value::Array(items)) if items.iter().all(is_document) => items,
+            Ok(item) if is_document(&item) => vec![item],
+            _ => vec![json!({ "text": input })],
+        };
+        let mut output = Vec::new();
+        for document in documents {
+            let text = document["text"].as_str().unwrap_or_default();
+            for chunk in self.split(text)? {
+                // a chunk is always a json object
+                let mut value = serde_json::to_value(chunk).unwrap();
+                if let Some(metadata) = document.get("metadata") {
+                    value["metadata"] = metadata.clone();
+                }
+                output.push(value);
+            }
+        }
+        Ok(Value::Array(output).to_string())
+    }
+    /// Split the text into chunks.
+    pub fn split(&self, text: &str) -> ChunkerNodeResult<Vec<Chunk>> {
+        if self.size == 0 || self.overlap >= self.size {
+            return Err(ChunkerNodeError::new(
+                ChunkerNodeErrorType::InvalidConfig,
+                format!(
+                    "The overlap {} must be less than the size {} of the chunks.",
+                    self.overlap, self.size
+                ),
+            ));
+        }
+        let (units, headings) = match self.strategy {
+            ChunkStrategy::Tokens => (words(text, 0, text.len(), 0), Vec::new()),
+            ChunkStrategy::Sentences => (sentences(text), Vec::new()),
+            ChunkStrategy::Markdown => markdown(text),
+            ChunkStrategy::Code => (code(text), Vec::new()),
+        };
+        let units = self.fit(text, units);
+        let mut chunks = Vec::new();
+        for (start, end, section) in self.pack(text, &units) {
+            let span = &text[start..end];
+            let trimmed = span.trim_start();
+            let start = start + span.len() - trimmed.len();
+            let trimmed = trimmed.trim_end();
+            if trimmed.is_empty() {
+                continue;
+            }
+            chunks.push(Chunk {
+                index: chunks.len(),
+                text: trimmed.to_string(),
+                start,
+                end: start + trimmed.len(),
+                tokens: estimate_tokens(trimmed),
+                heading: headings.get(section).filter(|h| !h.is_empty()).cloned(),
+            });
+        }
+        Ok(chunks)
+    }
+    /// Cut the units larger than a chunk at the words, and the words larger than a chunk at
+    /// the characters.
+    fn fit(&self, text: &str, units: Vec<Unit>) -> Vec<Unit> {
+        let mut fitted = Vec::with_capacity(units.len());
+        for unit in units {
+            if estimate_tokens(&text[unit.start..unit.end]) <= self.size {
+                fitted.push(unit);
+                continue;
+            }
+            for word in words(text, unit.start, unit.end, unit.section) {
+                if estimate_tokens(&text[word.start..word.end]) <= self.size {
+                    fitted.push(word);
+                    continue;
+                }
+                let mut start = word.start;
+                let mut weight = Weight::default();
+                for (offset, c) in text[word.start..word.end].char_indices() {
+                    let next = weight.add(c);
+                    if next.tokens() > self.size {
+                        fitted.push(Unit {
+                            start,
+                            end: word.start + offset,
+                            section: word.section,
+                        });
+                        start = word.start + offset;
+                        weight = Weight::default().add(c);
+                    } else {
+                        weight = next;
+                    }
+                }
+                fitted.push(Unit { start, ..word });
+            }
+        }
+        fitted
+    }
+    /// Pack the units into the spans of the chunks, with their sections.
+    fn pack(&self, text: &str, units: &[Unit]) -> Vec<(usize, usize, usize)> {
+        let weights: Vec<Weight> = units
+            .iter()
+            .map(|unit| Weight::of(&text[unit.start..unit.end]))
+            .collect();
+        let mut spans = Vec::new();
+        let mut first = 0;
+        while first < units.len() {
+            let section = units[first].section;
+            let mut weight = weights[first];
+            let mut last = first + 1;
+            while last < units.len() && units[last].section == section {
+                let next = weight.sum(weights[last]);
+                if next.tokens() > self.size {
+                    break;
+                }
+                weight = next;
+                last += 1;
+            }
+            spans.push((units[first].start, units[last - 1].end, section));
+            if last == units.len() || units[last].section != section {
+                first = last;
+                continue;
+            }
+            // start the next chunk with the units of the overlap, but always move forward
+            let mut next = last;
+            let mut overlap = Weight::default();
+            while next > first + 1 {
+                let more = overlap.sum(weights[next - 1]);
+                if more.tokens() > self.overlap {
+                    break;
+                }
+                overlap = more;
+                next -= 1;
+            }
+            first = next;
+        }
+        spans
+    }
+    /// Set the strategy as builder.
+    pub fn strategy(mut self, strategy: ChunkStrategy) -> Self {
+        self.strategy = strategy;
+        self
+    }
+    /// Set the strategy.
+    pub fn set_strategy(&mut self, strategy: ChunkStrategy) {
+        self.strategy = strategy;
+    }
+    /// Get the strategy.
+    pub fn get_strategy(&self) -> ChunkStrategy {
+        self.strategy
+    }
+    /// Set the max number of tokens of a chunk as builder.
+    pub fn size(mut self, size: usize) -> Self {
+        self.size = size;
+        self
+    }
+    /// Set the max number of tokens of a chunk.
+    pub fn set_size(&mut self, size: usize) {
+        self.size = size;
+    }
+    /// Get the max number of tokens of a chunk.
+    pub fn get_size(&self) -> usize {
+        self.size
+    }
+    /// Set the number of tokens of the overlap as builder.
+    pub fn overlap(mut self, overlap: usize) -> Self {
+        self.overlap = overlap;
+        self
+    }
+    /// Set the number of tokens of the overlap.
+    pub fn set_overlap(&mut self, overlap: usize) {
+        self.overlap = overlap;
+    }
+    /// Get the number of tokens of the overlap.
+    pub fn get_overlap(&self) -> usize {
+        self.overlap
+    }
+    /// The default max number of tokens of a chunk.
+    pub fn default_size() -> usize {
+        512
+    }
+    /// The default number of tokens of the overlap.
+    pub fn default_overlap() -> usize {
+        64
+    }
+}
+
+/// The struct of the characters of a text, to estimate the tokens of a sum of texts as
+/// `estimate_tokens` does for the whole text.
+#[derive(Debug, Clone, Copy, Default)]
+struct Weight {
+    ascii: usize,
+    others: usize,
+}
+
+impl Weight {
+    fn of(text: &str) -> Self {
+        text.chars().fold(Self::default(), Self::add)
+    }
+    fn add(self, c: char) -> Self {
+        if c.is_ascii() {
+            Weight {
+                ascii: self.ascii + 1,
+                ..self
+            }
+        } else {
+            Weight {
+                others: self.others + 1,
+                ..self
+            }
+        }
+    }
+    fn sum(self, other: Self) -> Self {
+        Weight {
+            ascii: self.ascii + other.ascii,
+            others: self.others + other.others,
+        }
+    }
+    fn tokens(self) -> usize {
+        self.ascii.div_ceil(4) + self.others
+    }
+}
+
+/// Whether the value is a document with a text.
+fn is_document(value: &Value) -> bool {
+    value.get("text").is_some_and(Value::is_string)
+}
+
+/// Get the words of the span, each with the spaces after it.
+fn words(text: &str, start: usize, end: usize, section: usize) -> Vec<Unit> {
+    let mut units = Vec::new();
+    let mut unit_start = start;
+    let mut in_space = false;
+    for (offset, c) in text[start..end].char_indices() {
+        let offset = start + offset;
+        if c.is_whitespace() {
+            in_space = true;
+        } else if in_space {
+            units.push(Unit {
+                start: unit_start,
+                end: offset,
+                section,
+            });
+            unit_start = offset;
+            in_space = false;
+        }
+    }
+    if unit_start < end {
+        units.push(Unit {
+            start: unit_start,
+            end,
+            section,
+        });
+    }
+    units
+}
+
+/// Get the sentences of the text, each with the spaces after it. A sentence ends with a
+/// full stop, a question mark or an exclamation mark followed by a space, or with a blank
+/// line.
+fn sentences(text: &str) -> Vec<Unit> {
+    let mut units = Vec::new();
+    let mut start = 0;
+    let mut chars = text.char_indices().peekable();
+    while let Some((offset, c)) = chars.next() {
+        let next = chars.peek().map(|(_, c)| *c);
+        let ends = match c {
+            '。' | '！' | '？' => true,
+            '.' | '!' | '?' => next.is_none_or(char::is_whitespace),
+            '\n' => next == Some('\n'),
+            _ => false,
+        };
+        if ends {
+            // the spaces after the end belong to the sentence
+            let mut end = offset + c.len_utf8();
+            while let Some((offset, c)) = chars.peek().copied() {
+                if !c.is_whitespace() {
+                    break;
+                }
+                end = offset + c.len_utf8();
+                chars.next();
+            }
+            units.push(Unit {
+                start,
+                end,
+                section: 0,
+            });
+            start = end;
+        }
+    }
+    if start < text.len() {
+        units.push(Unit {
+            start,
+            end: text.len(),
+            section: 0,
+        });
+    }
+    units
+}
+
+/// Get the lines of the text with their byte offsets, each with its line break.
+fn lines(text: &str) -> impl Iterator<Item = (usize, &str)> {
+    text.split_inclusive('\n').scan(0, |offset, line| {
+        let start = *offset;
+        *offset += line.len();
+        Some((start, line))
+    })
+}
+
+/// Get the paragraphs of a markdown text, with the headings of every section. A section
+/// starts at every heading.
+fn markdown(text: &str) -> (Vec<Unit>, Vec<String>) {
+    let mut units = Vec::new();
+    let mut headings = vec![String::new()];
+    let mut path: Vec<(usize, String)> = Vec::new();
+    let mut fence: Option<&str> = None;
+    let mut paragraph: Option<usize> = None;
+    for (offset, line) in lines(text) {
+        let trimmed = line.trim();
+        let section = headings.len() - 1;
+        let close = |paragraph: &mut Option<usize>, end: usize, units: &mut Vec<Unit>| {
+            if let Some(start) = paragraph.take() {
+                units.push(Unit {
+                    start,
+                    end,
+                    section,
+                });
+            }
+        };
+        if let Some(marker) = fence {
+            if trimmed.starts_with(marker) {
+                fence = None;
+            }
+            continue;
+        }
+        if trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+            fence = Some(&trimmed[..3]);
+            paragraph.get_or_insert(offset);
+            continue;
+        }
+        let level = trimmed.chars().take_while(|c| *c == '#').count();
+        if (1..=6).contains(&level) && trimmed[level..].starts_with(' ') {
+            close(&mut paragraph, offset, &mut units);
+            path.retain(|(l, _)| *l < level);
+            path.push((level, trimmed[level..].trim().to_string()));
+            headings.push(
+                path.iter()
+                    .map(|(_, title)| title.as_str())
+                    .collect::<Vec<&str>>()
+                    .join(" > "),
+            );
+            // the heading is the start of the first paragraph of its section
+            paragraph = Some(offset);
+        } else if trimmed.is_empty() {
+            close(&mut paragraph, offset + line.len(), &mut units);
+        } else {
+            paragraph.get_or_insert(offset);
+        }
+    }
+    if let Some(start) = paragraph {
+        units.push(Unit {
+            start,
+            end: text.len(),
+            section: headings.len() - 1,
+        });
+    }
+    (units, headings)
+}
+
+/// Get the top level blocks of a source. A block starts at a line without indent after a
+/// blank line, unless the line closes a block, like `}`.
+fn code(text: &str) -> Vec<Unit> {
+    let mut units = Vec::new();
+    let mut start = 0;
+    let mut blank = false;
+    for (offset, line) in lines(text) {
+        if line.trim().is_empty() {
+            blank = true;
+            continue;
+        }
+        let top_level = !line.starts_with(char::is_whitespace)
+            && !line.starts_with(['}', ')', ']'])
+            && !line.starts_with("end");
+        if blank && top_level && offset > start {
+            units.push(Unit {
+                start,
+                end: offset,
+                section: 0,
+            });
+            start = offset;
+        }
+        blank = false;
+    }
+    if start < text.len() {
+        units.push(Unit {
+            start,
+            end: text.len(),
+            section: 0,
+        });
+    }
+    units
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn split_texts() {
+        // every word is one token, so a chunk has 4 words and repeats the last one
+        let text = "aaa bbb ccc ddd eee fff ggg hhh iii";
+        let chunks = ChunkerNode::default()
+            .size(4)
+            .overlap(1)
+            .split(text)
+            .unwrap();
+        let texts: Vec<&str> = chunks.iter().map(|c| c.text.as_str()).collect();
+        assert_eq!(texts, ["aaa bbb ccc ddd", "ddd eee fff ggg", "ggg hhh iii"]);
+        assert_eq!(&text[chunks[1].start..chunks[1].end], chunks[1].text);
+
+        let text = "One two three. Four five six! Seven eight? Nine.";
+        let chunks = ChunkerNode::new(ChunkStrategy::Sentences)
+            .size(8)
+            .overlap(0)
+            .split(text)
+            .unwrap();
+        let texts: Vec<&str> = chunks.iter().map(|c| c.text.as_str()).collect();
+        assert_eq!(
+            texts,
+            ["One two three. Four five six!", "Seven eight? Nine."]
+        );
+
+        let text = "# Guide\nIntro.\n\n## Install\n```sh\ncargo build\n\ncargo test\n```\n\n## Use\nRun it.\n";
+        let chunks = ChunkerNode::new(ChunkStrategy::Markdown)
+            .size(100)
+            .overlap(0)
+            .split(text)
+            .unwrap();
+        let headings: Vec<&str> = chunks.iter().filter_map(|c| c.heading.as_deref()).collect();
+        assert_eq!(headings, ["Guide", "Guide > Install", "Guide > Use"]);
+        assert!(chunks[1].text.ends_with("cargo test\n```"));
+
+        let text = "use std::io;\n\n// Add.\nfn add() {\n    1\n\n    + 2\n}\n\nfn sub() {}\n";
+        let chunks = ChunkerNode::new(ChunkStrategy::Code)
+            .size(12)
+            .overlap(0)
+            .split(text)
+            .unwrap();
+        let texts: Vec<&str> = chunks.iter().map(|c| c.text.as_str()).collect();
+        assert_eq!(
+            texts,
+            [
+                "use std::io;",
+                "// Add.\nfn add() {\n    1\n\n    + 2\n}",
+                "fn sub() {}"
+            ]
+        );
+
+        // a word larger than a chunk is cut
+        let chunks = ChunkerNode::default()
+            .size(2)
+            .overlap(0)
+            .split("abcdefghijkl")
+            .unwrap();
+        assert_eq!(chunks.len(), 2);
+        assert!(ChunkerNode::default()
+            .size(4)
+            .overlap(4)
+            .split("a")
+            .is_err());
+    }
+
+    #[test]
+    fn chunk_documents() {
+        let node = ChunkerNode::default().size(2).overlap(0);
+        let input = json!([
+            {"text": "aaaa bbbb cccc", "metadata": {"source": "a.md"}},
+            {"text": "dddd", "metadata": {"source": "b.md"}},
+        ]);
+        let output: Value =
+            serde_json::from_str(&node.execute(input.to_string()).unwrap()).unwrap();
+        assert_eq!(output.as_array().unwrap().len(), 4);
+        assert_eq!(output[2]["text"], "cccc");
+        assert_eq!(output[2]["start"], 10);
+        assert_eq!(output[3]["index"], 0);
+        assert_eq!(output[3]["metadata"]["source"], "b.md");
+        // a text that is not a document is chunked as it is
+        let output: Value =
+            serde_json::from_str(&node.execute("[1, 2]".to_string()).unwrap()).unwrap();
+        assert_eq!(output[0]["text"], "[1, 2]");
+    }
+}
@@ -0,0 +1,146 @@
+//! # Delay
+//!
+//! This node waits before it passes its input on: for a while, until a moment, or until the
+//! next moment of a cron schedule (see [`cron`]). It paces a polling loop under a rate limit,
+//! or sends the follow-up of a sequence on the next morning.
+//!
+//! The node wakes at a moment, which it computes when it starts. In a workflow with a
+//! checkpoint path, the moment is saved in the checkpoint as soon as the node starts, so a
+//! resumed run wakes at the same moment instead of waiting all over again, and doesn't wait
+//! at all if the moment has passed.
+
+pub mod cron;
+
+use crate::error::delay_node_error::{DelayNodeError, DelayNodeErrorType, DelayNodeResult};
+use cron::CronSchedule;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use std::time::Duration;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+/// The enum of what the node waits for.
+pub enum Wait {
+    /// Wait for the duration.
+    Duration(Duration),
+    /// Wait until the moment. A moment that has passed isn't waited for.
+    Until(DateTime<Utc>),
+    /// Wait until the next moment of the cron schedule.
+    Cron(CronSchedule),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+/// The struct of the delay node.
+pub struct DelayNode {
+    /// What the node waits for.
+    wait: Wait,
+    /// The moment the node wakes at, set when it starts.
+    #[serde(skip)]
+    wake: Option<DateTime<Utc>>,
+}
+
+impl DelayNode {
+    /// Create a new DelayNode.
+    pub fn new(wait: Wait) -> Self {
+        DelayNode { wait, wake: None }
+    }
+    /// Create a new DelayNode that waits for the duration.
+    pub fn duration(duration: Duration) -> Self {
+        Self::new(Wait::Duration(duration))
+    }
+    /// Create a new DelayNode that waits until the moment.
+    pub fn until(moment: DateTime<Utc>) -> Self {
+        Self::new(Wait::Until(moment))
+    }
+    /// Create a new DelayNode that waits until the next moment of the cron schedule.
+    pub fn cron(expression: &str) -> DelayNodeResult<Self> {
+        Ok(Self::new(Wait::Cron(CronSchedule::parse(expression)?)))
+    }
+    /// Wait until the moment the node wakes at, and pass the input on. Without a moment set,
+    /// the node wakes at the moment computed from now.
+    pub async fn execute(&mut self, input: String) -> DelayNodeResult<String> {
+        let wake = match self.wake.take() {
+            Some(wake) => wake,
+            None => self.wake_at(Utc::now())?,
+        };
+        if let Ok(delay) = (wake - Utc::now()).to_std() {
+            tokio::time::sleep(delay).await;
+        }
+        Ok(input)
+    }
+    /// Get the moment the node wakes at when it starts at the time.
+    pub fn wake_at(&self, time: DateTime<Utc>) -> DelayNodeResult<DateTime<Utc>> {
+        match &self.wait {
+            Wait::Duration(duration) => Ok(time + *duration),
+            Wait::Until(moment) => Ok(*moment),
+            Wait::Cron(schedule) => schedule.next_after(time).ok_or_else(|| {
+                DelayNodeError::new(
+                    DelayNodeErrorType::NoMoment,
+                    format!(
+                        "The cron schedule `{}` has no moment to wait for.",
+                        schedule.get_expression()
+                    ),
+                )
+            }),
+        }
+    }
+    /// Set the moment the node wakes at in the next execution, like the one saved in a
+    /// checkpoint.
+    pub fn set_wake(&mut self, wake: Option<DateTime<Utc>>) {
+        self.wake = wake;
+    }
+    /// Get the moment the node wakes at in the next execution.
+    pub fn get_wake(&self) -> Option<DateTime<Utc>> {
+        self.wake
+    }
+    /// Get what the node waits for.
+    pub fn get_wait(&self) -> &Wait {
+        &self.wait
+    }
+    /// Set what the node waits for.
+    pub fn set_wait(&mut self, wait: Wait) {
+        self.wait = wait;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use chrono::TimeDelta;
+    use tokio::runtime::Runtime;
+
+    use std::time::Instant;
+
+    #[test]
+    fn delay_input() {
+        let rt = Runtime::new().unwrap();
+        let mut delay = DelayNode::duration(Duration::from_millis(50));
+        let started = Instant::now();
+        let output = rt.block_on(delay.execute("a".to_string())).unwrap();
+        assert_eq!(output, "a");
+        assert!(started.elapsed() >= Duration::from_millis(50));
+
+        // a moment that has passed isn't waited for
+        let mut delay = DelayNode::duration(Duration::from_secs(3600));
+        delay.set_wake(Some(Utc::now() - TimeDelta::seconds(1)));
+        let started = Instant::now();
+        rt.block_on(delay.execute("b".to_string())).unwrap();
+        assert!(started.elapsed() < Duration::from_secs(1));
+        assert_eq!(delay.get_wake(), None);
+
+        let error = rt
+            .block_on(
+                DelayNode::cron("0 0 31 4 *")
+                    .unwrap()
+                    .execute("c".to_string()),
+            )
+            .unwrap_err();
+        assert!(matches!(
+            error.get_error_type(),
+            DelayNodeErrorType::NoMoment
+        ));
+    }
+}
@@ -0,0 +1,294 @@
+//! # Cron
+//!
+//! This module parses the cron schedules of the delay node and finds their next moment.
+//!
+//! A schedule has five fields: the minute (0-59), the hour (0-23), the day of the month
+//! (1-31), the month (1-12 or `jan`-`dec`) and the day of the week (0-7 or `sun`-`sat`, where
+//! both 0 and 7 are Sunday). A field is `*`, a value, a range `a-b`, or a list of them
+//! separated by commas, and each of them can have a step, like `*/15` or `9-17/2`. As in the
+//! usual cron, when both days are restricted, a day matches if either of them matches. The
+//! shortcuts `@yearly`, `@monthly`, `@weekly`, `@daily` and `@hourly` are accepted too.
+//!
+//! The moments of a schedule are in UTC.
+
+use crate::error::delay_node_error::{DelayNodeError, DelayNodeErrorType, DelayNodeResult};
+
+use chrono::{DateTime, Datelike, NaiveDate, NaiveDateTime, TimeDelta, Timelike, Utc};
+use serde::{Deserialize, Serialize};
+
+/// The number of years searched for the next moment. A schedule of the 29th of February
+/// has a moment at least every eight years.
+const SEARCH_YEARS: i32 = 8;
+
+/// The struct of the range and the names of the values of a field.
+struct Field {
+    /// The name of the field, for the errors.
+    name: &'static str,
+    /// The smallest value.
+    min: u32,
+    /// The largest value.
+    max: u32,
+    /// The names of the values from the smallest one.
+    names: &'static [&'static str],
+}
+
+const MINUTE: Field = Field {
+    name: "minute",
+    min: 0,
+    max: 59,
+    names: &[],
+};
+const HOUR: Field = Field {
+    name: "hour",
+    min: 0,
+    max: 23,
+    names: &[],
+};
+const DAY: Field = Field {
+    name: "day of the month",
+    min: 1,
+    max: 31,
+    names: &[],
+};
+const MONTH: Field = Field {
+    name: "month",
+    min: 1,
+    max: 12,
+    names: &[
+        "jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec",
+    ],
+};
+const WEEKDAY: Field = Field {
+    name: "day of the week",
+    min: 0,
+    max: 7,
+    names: &["sun", "mon", "tue", "wed", "thu", "fri", "sat"],
+};
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+/// The struct of a cron schedule. Every field is a bit set of the values that match.
+pub struct CronSchedule {
+    /// The expression of the schedule.
+    expression: String,
+    minutes: u64,
+    hours: u64,
+    days: u64,
+    months: u64,
+    weekdays: u64,
+    /// Whether the day of the month is not restricted.
+    any_day: bool,
+    /// Whether the day of the week is not restricted.
+    any_weekday: bool,
+}
+
+impl CronSchedule {
+    /// Parse a cron expression.
+    pub fn parse(expression: &str) -> DelayNodeResult<Self> {
+        let expanded = match expression.trim() {
+            "@yearly" | "@annually" => "0 0 1 1 *",
+            "@monthly" => "0 0 1 * *",
+            "@weekly" => "0 0 * * 0",
+            "@daily" | "@midnight" => "0 0 * * *",
+            "@hourly" => "0 * * * *",
+            expression => expression,
+        };
+        let fields: Vec<&str> = expanded.split_whitespace().collect();
+        let [minutes, hours, days, months, weekdays] = fields.as_slice() else {
+            return Err(parse_error(format!(
+                "The cron expression `{}` doesn't have five fields.",
+                expression
+            )));
+        };
+        let mut weekday_bits = parse_field(weekdays, &WEEKDAY)?;
+        // 7 is Sunday as well as 0
+        if weekday_bits & (1 << 7) != 0 {
+            weekday_bits = (weekday_bits & !(1 << 7)) | 1;
+        }
+        Ok(CronSchedule {
+            expression: expression.trim().to_string(),
+            minutes: parse_field(minutes, &MINUTE)?,
+            hours: parse_field(hours, &HOUR)?,
+            days: parse_field(days, &DAY)?,
+            months: parse_field(months, &MONTH)?,
+            weekdays: weekday_bits,
+            any_day: days.starts_with('*'),
+            any_weekday: weekdays.starts_with('*'),
+        })
+    }
+    /// Get the expression of the schedule.
+    pub fn get_expression(&self) -> &str {
+        &self.expression
+    }
+    /// Get the first moment of the schedule after the time, or `None` if it has none.
+    pub fn next_after(&self, time: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        let time = time.naive_utc();
+        let mut moment =
+            time.date().and_hms_opt(time.hour(), time.minute(), 0)? + TimeDelta::minutes(1);
+        let last_year = moment.year() + SEARCH_YEARS;
+        while moment.year() <= last_year {
+            if !has(self.months, moment.month()) {
+                moment = first_of_next_month(moment)?;
+            } else if !self.matches_day(moment.date()) {
+                moment = (moment.date() + TimeDelta::days(1)).and_hms_opt(0, 0, 0)?;
+            } else if !has(self.hours, moment.hour()) {
+                moment = moment.date().and_hms_opt(moment.hour(), 0, 0)? + TimeDelta::hours(1);
+            } else if !has(self.minutes, moment.minute()) {
+                moment += TimeDelta::minutes(1);
+            } else {
+                return Some(moment.and_utc());
+            }
+        }
+        None
+    }
+    /// Whether the date matches the days of the schedule.
+    fn matches_day(&self, date: NaiveDate) -> bool {
+        let day = has(self.days, date.day());
+        let weekday = has(self.weekdays, date.weekday().num_days_from_sunday());
+        if self.any_day || self.any_weekday {
+            day && weekday
+        } else {
+            day || weekday
+        }
+    }
+}
+
+impl TryFrom<String> for CronSchedule {
+    type Error = DelayNodeError;
+
+    fn try_from(expression: String) -> DelayNodeResult<Self> {
+        Self::parse(&expression)
+    }
+}
+
+impl From<CronSchedule> for String {
+    fn from(schedule: CronSchedule) -> Self {
+        schedule.expression
+    }
+}
+
+/// Whether the value is in the bit set.
+fn has(bits: u64, value: u32) -> bool {
+    bits & (1 << value) != 0
+}
+
+/// Get the start of the first day of the month after the moment.
+fn first_of_next_month(moment: NaiveDateTime) -> Option<NaiveDateTime> {
+    let (year, month) = match moment.month() {
+        12 => (moment.year() + 1, 1),
+        month => (moment.year(), month + 1),
+    };
+    NaiveDate::from_ymd_opt(year, month, 1)?.and_hms_opt(0, 0, 0)
+}
+
+/// Parse a field into the bit set of its values.
+fn parse_field(text: &str, field: &Field) -> DelayNodeResult<u64> {
+    let mut bits = 0;
+    for part in text.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => match step.parse::<usize>() {
+                Ok(step) if step > 0 => (range, Some(step)),
+                _ => {
+                    return Err(parse_error(format!(
+                        "The step `{}` of the {} is not a positive number.",
+                        step, field.name
+                    )))
+                }
+            },
+            None => (part, None),
+        };
+        let (low, high) = match range.split_once('-') {
+            _ if range == "*" => (field.min, field.max),
+            Some((low, high)) => (parse_value(low, field)?, parse_value(high, field)?),
+            // a value with a step runs to the end of the field, like `5/15`
+            None if step.is_some() => (parse_value(range, field)?, field.max),
+            None => {
+                let value = parse_value(range, field)?;
+                (value, value)
+            }
+        };
+        if low > high {
+            return Err(parse_error(format!(
+                "The range `{}` of the {} is empty.",
+                range, field.name
+            )));
+        }
+        for value in (low..=high).step_by(step.unwrap_or(1)) {
+            bits |= 1 << value;
+        }
+    }
+    Ok(bits)
+}
+
+/// Parse a value of a field, a number or a name.
+fn parse_value(text: &str, field: &Field) -> DelayNodeResult<u32> {
+    let lowercase = text.to_lowercase();
+    let value = match field.names.iter().position(|name| *name == lowercase) {
+        Some(index) => field.min + index as u32,
+        None => text.parse().map_err(|_| {
+            parse_error(format!(
+                "The value `{}` of the {} is not valid.",
+                text, field.name
+            ))
+        })?,
+    };
+    if value < field.min || value > field.max {
+        return Err(parse_error(format!(
+            "The value {} of the {} is not in {}-{}.",
+            value, field.name, field.min, field.max
+        )));
+    }
+    Ok(value)
+}
+
+/// Create a DelayNodeError of a cron expression.
+fn parse_error(message: String) -> DelayNodeError {
+    DelayNodeError::new(DelayNodeErrorType::ParseError, message)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn time(text: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(text).unwrap().to_utc()
+    }
+
+    #[test]
+    fn next_moments() {
+        let start = time("2026-10-16T10:07:30Z");
+        let next = |expression: &str| {
+            CronSchedule::parse(expression)
+                .unwrap()
+                .next_after(start)
+                .map(|moment| moment.to_rfc3339())
+        };
+        assert_eq!(next("* * * * *").unwrap(), "2026-10-16T10:08:00+00:00");
+        assert_eq!(next("*/15 * * * *").unwrap(), "2026-10-16T10:15:00+00:00");
+        assert_eq!(next("0 9 * * mon").unwrap(), "2026-10-19T09:00:00+00:00");
+        assert_eq!(
+            next("30 8-17/3 * * *").unwrap(),
+            "2026-10-16T11:30:00+00:00"
+        );
+        assert_eq!(next("@monthly").unwrap(), "2026-11-01T00:00:00+00:00");
+        assert_eq!(next("0 0 29 feb *").unwrap(), "2028-02-29T00:00:00+00:00");
+        // either day matches when both are restricted
+        assert_eq!(next("0 0 1 * 7").unwrap(), "2026-10-18T00:00:00+00:00");
+        assert_eq!(next("0 0 30 2 *"), None);
+
+        for expression in [
+            "* * * *",
+            "60 * * * *",
+            "* * * * foo",
+            "5-1 * * * *",
+            "*/0 * * * *",
+        ] {
+            assert!(matches!(
+                CronSchedule::parse(expression)
+                    .unwrap_err()
+                    .get_error_type(),
+                DelayNodeErrorType::ParseError
+            ));
+        }
+    }
+}
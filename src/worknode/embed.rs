@@ -0,0 +1,101 @@
+//! # Embed
+//!
+//! This node turns its input into embedding vectors with an embeddings api (see
+//! [`crate::worknode::ai_node::embedding`]), for the retrieval features.
+//!
+//! The input is a text, or a json array of texts. The output is the json array of the vector
+//! of the text, or the json array of the vectors of the texts in the same order.
+
+use super::ai_node::embedding::EmbeddingClient;
+use crate::error::ai_node_error::{AINodeError, AINodeErrorType, AINodeResult};
+
+use serde_json::Value;
+
+#[derive(Debug, Clone)]
+/// The struct of the embed node.
+pub struct EmbedNode {
+    /// The client of the embeddings api.
+    client: EmbeddingClient,
+    /// The name of the client in the registry, used to save the node in a workflow file.
+    provider: Option<String>,
+}
+
+impl EmbedNode {
+    /// Create a new EmbedNode.
+    pub fn new(client: EmbeddingClient) -> Self {
+        EmbedNode {
+            client,
+            provider: None,
+        }
+    }
+    /// Embed the text or the texts of the input.
+    pub async fn execute(&mut self, input: String) -> AINodeResult<String> {
+        let texts = match serde_json::from_str::<Value>(&input) {
+            Ok(Value::Array(items)) => Some(
+                items
+                    .into_iter()
+                    .map(|item| match item {
+                        Value::String(text) => Ok(text),
+                        item => Err(AINodeError::new(
+                            AINodeErrorType::InvalidInput,
+                            format!("The item {} to embed is not a text.", item),
+                        )),
+                    })
+                    .collect::<AINodeResult<Vec<String>>>()?,
+            ),
+            _ => None,
+        };
+        // the vectors are numbers, so they are always valid json
+        Ok(match texts {
+            Some(texts) => serde_json::to_string(&self.client.embed(&texts).await?).unwrap(),
+            None => {
+                let mut vectors = self.client.embed(&[input]).await?;
+                serde_json::to_string(&vectors.pop()).unwrap()
+            }
+        })
+    }
+    /// Get the client of the embeddings api.
+    pub fn get_client(&self) -> &EmbeddingClient {
+        &self.client
+    }
+    /// Set the client of the embeddings api.
+    pub fn set_client(&mut self, client: EmbeddingClient) {
+        self.client = client;
+    }
+    /// Set the name of the client in the registry as builder.
+    pub fn provider(mut self, provider: Option<String>) -> Self {
+        self.provider = provider;
+        self
+    }
+    /// Set the name of the client in the registry.
+    pub fn set_provider(&mut self, provider: Option<String>) {
+        self.provider = provider;
+    }
+    /// Get the name of the client in the registry.
+    pub fn get_provider(&self) -> Option<&str> {
+        self.provider.as_deref()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use tokio::runtime::Runtime;
+
+    #[test]
+    fn embed_input() {
+        let rt = Runtime::new().unwrap();
+        // the client is never asked, since there is nothing to embed or the input is invalid
+        let mut node = EmbedNode::new(EmbeddingClient::ollama("nomic-embed-text"));
+        let output = rt.block_on(node.execute("[]".to_string())).unwrap();
+        assert_eq!(output, "[]");
+        let error = rt
+            .block_on(node.execute(r#"["a", 1]"#.to_string()))
+            .unwrap_err();
+        assert!(matches!(
+            error.get_error_type(),
+            AINodeErrorType::InvalidInput
+        ));
+    }
+}
@@ -0,0 +1,486 @@
+//! # File
+//!
+//! This module defines the nodes that read and write files, so a workflow can put a source file
+//! in a prompt, or keep the answer of an AI node on the disk, without a local command.
+//!
+//! The path of both nodes is a template (see [`crate::template`]) rendered with the variable
+//! `input` and the variables of the run context, like `reports/{{context.customer}}.md`.
+//! - The file read node outputs the content of the file. Its input is only used in the path.
+//! - The file write node writes its input to the file, by overwriting or appending to it, and
+//!   outputs its input again, so the next node gets the same text.
+//!
+//! The content is text in UTF-8 by default. It can also be decoded lossily, or be base64, so
+//! a binary file is read as base64 and a base64 input is written as its bytes.
+//!
+//! A path rendered from the answer of an AI node can point anywhere, so a node can be limited
+//! to the files under some allowed paths. The path is resolved before the check, so `..` and
+//! symbolic links can't escape the allowed paths. Without any allowed path, every path is
+//! allowed. The size of the files read and of the content written is limited too.
+
+use crate::error::file_node_error::{FileNodeError, FileNodeErrorType, FileNodeResult};
+use crate::template;
+use crate::workflow::context::RunContext;
+
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use tokio::io::AsyncWriteExt;
+
+use std::path::{Component, Path, PathBuf};
+
+/// The size limit of the files by default, 10 MiB.
+pub const DEFAULT_MAX_SIZE: u64 = 10 * 1024 * 1024;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+/// The enum of how the content of a file is turned into text and back.
+pub enum FileEncoding {
+    /// UTF-8 text. Invalid UTF-8 fails the node.
+    #[default]
+    Utf8,
+    /// UTF-8 text, where the invalid bytes are replaced when the file is read.
+    Utf8Lossy,
+    /// Any bytes, as base64 text.
+    Base64,
+}
+
+impl FileEncoding {
+    /// Turn the bytes of a file into text.
+    fn decode(&self, bytes: Vec<u8>, path: &Path) -> FileNodeResult<String> {
+        match self {
+            Self::Utf8 => String::from_utf8(bytes).map_err(|_| {
+                file_error(
+                    FileNodeErrorType::EncodingError,
+                    format!("{} is not valid UTF-8.", path.display()),
+                )
+            }),
+            Self::Utf8Lossy => Ok(String::from_utf8_lossy(&bytes).to_string()),
+            Self::Base64 => Ok(base64::engine::general_purpose::STANDARD.encode(bytes)),
+        }
+    }
+    /// Turn text into the bytes of a file.
+    fn encode(&self, text: String) -> FileNodeResult<Vec<u8>> {
+        match self {
+            Self::Utf8 | Self::Utf8Lossy => Ok(text.into_bytes()),
+            Self::Base64 => base64::engine::general_purpose::STANDARD
+                .decode(text.trim())
+                .map_err(|e| {
+                    file_error(
+                        FileNodeErrorType::EncodingError,
+                        format!("The input is not valid base64. {}", e),
+                    )
+                }),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+/// The enum of how the file write node writes to an existing file.
+pub enum WriteMode {
+    /// Replace the content of the file.
+    #[default]
+    Overwrite,
+    /// Add to the end of the file.
+    Append,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+/// The struct of the file read node.
+pub struct FileReadNode {
+    /// The path template of the file.
+    path: String,
+    /// The encoding of the content.
+    encoding: FileEncoding,
+    /// The size in bytes of the largest file that can be read, or `None` for no limit.
+    max_size: Option<u64>,
+    /// The paths that the file must be under. Empty means every path is allowed.
+    allowed_paths: Vec<PathBuf>,
+}
+
+impl Default for FileReadNode {
+    fn default() -> Self {
+        FileReadNode {
+            path: String::new(),
+            encoding: FileEncoding::default(),
+            max_size: Some(DEFAULT_MAX_SIZE),
+            allowed_paths: Vec::new(),
+        }
+    }
+}
+
+impl FileReadNode {
+    /// Create a new FileReadNode that reads the file of the path template.
+    pub fn new(path: &str) -> Self {
+        FileReadNode {
+            path: path.to_string(),
+            ..Self::default()
+        }
+    }
+    /// Read the file, and get its content.
+    pub async fn execute(&self, input: String, context: &RunContext) -> FileNodeResult<String> {
+        let path = render_path(&self.path, input, context)?;
+        let path = check_allowed(&path, &self.allowed_paths)?;
+        let metadata = tokio::fs::metadata(&path)
+            .await
+            .map_err(|e| io_error("read", &path, e))?;
+        if let Some(max_size) = self.max_size {
+            if metadata.len() > max_size {
+                return Err(too_large(&path, metadata.len(), max_size));
+            }
+        }
+        let bytes = tokio::fs::read(&path)
+            .await
+            .map_err(|e| io_error("read", &path, e))?;
+        self.encoding.decode(bytes, &path)
+    }
+    /// Set the path template as builder.
+    pub fn path(mut self, path: &str) -> Self {
+        self.path = path.to_string();
+        self
+    }
+    /// Set the path template.
+    pub fn set_path(&mut self, path: &str) {
+        self.path = path.to_string();
+    }
+    /// Get the path template.
+    pub fn get_path(&self) -> &str {
+        &self.path
+    }
+    /// Set the encoding as builder.
+    pub fn encoding(mut self, encoding: FileEncoding) -> Self {
+        self.encoding = encoding;
+        self
+    }
+    /// Set the encoding.
+    pub fn set_encoding(&mut self, encoding: FileEncoding) {
+        self.encoding = encoding;
+    }
+    /// Get the encoding.
+    pub fn get_encoding(&self) -> FileEncoding {
+        self.encoding
+    }
+    /// Set the size limit as builder.
+    pub fn max_size(mut self, max_size: Option<u64>) -> Self {
+        self.max_size = max_size;
+        self
+    }
+    /// Set the size limit.
+    pub fn set_max_size(&mut self, max_size: Option<u64>) {
+        self.max_size = max_size;
+    }
+    /// Get the size limit.
+    pub fn get_max_size(&self) -> Option<u64> {
+        self.max_size
+    }
+    /// Allow the files under the path as builder.
+    pub fn allow_path<P: AsRef<Path>>(mut self, path: P) -> Self {
+        self.allowed_paths.push(path.as_ref().to_path_buf());
+        self
+    }
+    /// Set the allowed paths.
+    pub fn set_allowed_paths(&mut self, allowed_paths: Vec<PathBuf>) {
+        self.allowed_paths = allowed_paths;
+    }
+    /// Get the allowed paths.
+    pub fn get_allowed_paths(&self) -> &Vec<PathBuf> {
+        &self.allowed_paths
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+/// The struct of the file write node.
+pub struct FileWriteNode {
+    /// The path template of the file.
+    path: String,
+    /// The encoding of the content.
+    encoding: FileEncoding,
+    /// Whether the content replaces the file or is added to its end.
+    mode: WriteMode,
+    /// The size in bytes of the largest content that can be written, or `None` for no limit.
+    max_size: Option<u64>,
+    /// Whether the missing parent directories are created.
+    create_dirs: bool,
+    /// The paths that the file must be under. Empty means every path is allowed.
+    allowed_paths: Vec<PathBuf>,
+}
+
+impl Default for FileWriteNode {
+    fn default() -> Self {
+        FileWriteNode {
+            path: String::new(),
+            encoding: FileEncoding::default(),
+            mode: WriteMode::default(),
+            max_size: Some(DEFAULT_MAX_SIZE),
+            create_dirs: false,
+            allowed_paths: Vec::new(),
+        }
+    }
+}
+
+impl FileWriteNode {
+    /// Create a new FileWriteNode that writes to the file of the path template.
+    pub fn new(path: &str) -> Self {
+        FileWriteNode {
+            path: path.to_string(),
+            ..Self::default()
+        }
+    }
+    /// Write the input to the file, and get the input again.
+    pub async fn execute(&self, input: String, context: &RunContext) -> FileNodeResult<String> {
+        let path = render_path(&self.path, input.clone(), context)?;
+        let path = check_allowed(&path, &self.allowed_paths)?;
+        let bytes = self.encoding.encode(input.clone())?;
+        if let Some(max_size) = self.max_size {
+            if bytes.len() as u64 > max_size {
+                return Err(too_large(&path, bytes.len() as u64, max_size));
+            }
+        }
+        if self.create_dirs {
+            if let Some(parent) = path.parent() {
+                tokio::fs::create_dir_all(parent)
+                    .await
+                    .map_err(|e| io_error("create the directory of", &path, e))?;
+            }
+        }
+        let mut file = tokio::fs::OpenOptions::new()
+            .create(true)
+            .write(true)
+            .append(self.mode == WriteMode::Append)
+            .truncate(self.mode == WriteMode::Overwrite)
+            .open(&path)
+            .await
+            .map_err(|e| io_error("open", &path, e))?;
+        file.write_all(&bytes)
+            .await
+            .map_err(|e| io_error("write", &path, e))?;
+        file.flush()
+            .await
+            .map_err(|e| io_error("write", &path, e))?;
+        Ok(input)
+    }
+    /// Set the path template as builder.
+    pub fn path(mut self, path: &str) -> Self {
+        self.path = path.to_string();
+        self
+    }
+    /// Set the path template.
+    pub fn set_path(&mut self, path: &str) {
+        self.path = path.to_string();
+    }
+    /// Get the path template.
+    pub fn get_path(&self) -> &str {
+        &self.path
+    }
+    /// Set the encoding as builder.
+    pub fn encoding(mut self, encoding: FileEncoding) -> Self {
+        self.encoding = encoding;
+        self
+    }
+    /// Set the encoding.
+    pub fn set_encoding(&mut self, encoding: FileEncoding) {
+        self.encoding = encoding;
+    }
+    /// Get the encoding.
+    pub fn get_encoding(&self) -> FileEncoding {
+        self.encoding
+    }
+    /// Set the write mode as builder.
+    pub fn mode(mut self, mode: WriteMode) -> Self {
+        self.mode = mode;
+        self
+    }
+    /// Set the write mode.
+    pub fn set_mode(&mut self, mode: WriteMode) {
+        self.mode = mode;
+    }
+    /// Get the write mode.
+    pub fn get_mode(&self) -> WriteMode {
+        self.mode
+    }
+    /// Set the size limit as builder.
+    pub fn max_size(mut self, max_size: Option<u64>) -> Self {
+        self.max_size = max_size;
+        self
+    }
+    /// Set the size limit.
+    pub fn set_max_size(&mut self, max_size: Option<u64>) {
+        self.max_size = max_size;
+    }
+    /// Get the size limit.
+    pub fn get_max_size(&self) -> Option<u64> {
+        self.max_size
+    }
+    /// Set whether the missing parent directories are created as builder.
+    pub fn create_dirs(mut self, create_dirs: bool) -> Self {
+        self.create_dirs = create_dirs;
+        self
+    }
+    /// Set whether the missing parent directories are created.
+    pub fn set_create_dirs(&mut self, create_dirs: bool) {
+        self.create_dirs = create_dirs;
+    }
+    /// Get whether the missing parent directories are created.
+    pub fn get_create_dirs(&self) -> bool {
+        self.create_dirs
+    }
+    /// Allow the files under the path as builder.
+    pub fn allow_path<P: AsRef<Path>>(mut self, path: P) -> Self {
+        self.allowed_paths.push(path.as_ref().to_path_buf());
+        self
+    }
+    /// Set the allowed paths.
+    pub fn set_allowed_paths(&mut self, allowed_paths: Vec<PathBuf>) {
+        self.allowed_paths = allowed_paths;
+    }
+    /// Get the allowed paths.
+    pub fn get_allowed_paths(&self) -> &Vec<PathBuf> {
+        &self.allowed_paths
+    }
+}
+
+/// Render the path template with the input and the variables of the context.
+pub(crate) fn render_path(
+    path: &str,
+    input: String,
+    context: &RunContext,
+) -> FileNodeResult<PathBuf> {
+    let mut variables = context.to_variables();
+    variables.insert("input".to_string(), input);
+    template::render(path, &variables)
+        .map(PathBuf::from)
+        .map_err(|e| {
+            file_error(
+                FileNodeErrorType::TemplateError(e),
+                "Failed to render the path of the file.".to_string(),
+            )
+        })
+}
+
+/// Check that the path is under one of the allowed paths, and get the resolved path. Every
+/// path is allowed if there is no allowed path.
+pub(crate) fn check_allowed(path: &Path, allowed: &[PathBuf]) -> FileNodeResult<PathBuf> {
+    if allowed.is_empty() {
+        return Ok(path.to_path_buf());
+    }
+    let resolved = resolve(path).ok_or_else(|| not_allowed(path))?;
+    let allowed = allowed
+        .iter()
+        .filter_map(|allowed| allowed.canonicalize().ok())
+        .any(|allowed| resolved.starts_with(allowed));
+    match allowed {
+        true => Ok(resolved),
+        false => Err(not_allowed(path)),
+    }
+}
+
+/// Resolve the path like `canonicalize`, but also for a file that doesn't exist yet: the
+/// deepest existing ancestor is resolved, and the rest is joined to it. The rest can't go up.
+fn resolve(path: &Path) -> Option<PathBuf> {
+    let mut existing = path;
+    let mut rest = Vec::new();
+    loop {
+        if let Ok(resolved) = existing.canonicalize() {
+            return Some(
+                rest.into_iter()
+                    .rev()
+                    .fold(resolved, |path, part| path.join(part)),
+            );
+        }
+        let mut components = existing.components();
+        match components.next_back()? {
+            Component::Normal(part) => rest.push(part),
+            Component::CurDir => {}
+            _ => return None,
+        }
+        existing = components.as_path();
+        if existing.as_os_str().is_empty() {
+            existing = Path::new(".");
+        }
+    }
+}
+
+/// Create a FileNodeError.
+fn file_error(error_type: FileNodeErrorType, message: String) -> FileNodeError {
+    FileNodeError::new(error_type, message)
+}
+
+/// Create a FileNodeError of a path that is not allowed.
+fn not_allowed(path: &Path) -> FileNodeError {
+    file_error(
+        FileNodeErrorType::PathNotAllowed,
+        format!("{} is not under the allowed paths.", path.display()),
+    )
+}
+
+/// Create a FileNodeError of a file that is too large.
+pub(crate) fn too_large(path: &Path, size: u64, max_size: u64) -> FileNodeError {
+    file_error(
+        FileNodeErrorType::TooLarge,
+        format!(
+            "The content of {} has {} bytes, more than the limit of {} bytes.",
+            path.display(),
+            size,
+            max_size
+        ),
+    )
+}
+
+/// Create a FileNodeError of a failed file operation.
+pub(crate) fn io_error(action: &str, path: &Path, e: std::io::Error) -> FileNodeError {
+    file_error(
+        FileNodeErrorType::IoError,
+        format!("Failed to {} {}. {}", action, path.display(), e),
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use tokio::runtime::Runtime;
+
+    #[test]
+    fn read_and_write() {
+        let rt = Runtime::new().unwrap();
+        let dir = std::env::temp_dir().join(format!("file-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir(&dir).unwrap();
+        let context = RunContext::new();
+        context.set("name", "report").unwrap();
+        let template = format!("{}/out/{{{{context.name}}}}.md", dir.display());
+
+        let write = FileWriteNode::new(&template)
+            .create_dirs(true)
+            .allow_path(&dir);
+        let output = rt.block_on(write.execute("# Title\n".to_string(), &context));
+        assert_eq!(output.unwrap(), "# Title\n");
+        let append = write.clone().mode(WriteMode::Append);
+        rt.block_on(append.execute("body\n".to_string(), &context))
+            .unwrap();
+        let read = FileReadNode::new(&template).allow_path(&dir);
+        let content = rt.block_on(read.execute(String::new(), &context));
+        assert_eq!(content.unwrap(), "# Title\nbody\n");
+
+        let base64 = read.clone().encoding(FileEncoding::Base64);
+        let content = rt.block_on(base64.execute(String::new(), &context));
+        assert_eq!(content.unwrap(), "IyBUaXRsZQpib2R5Cg==");
+        let small = read.clone().max_size(Some(4));
+        let error = rt.block_on(small.execute(String::new(), &context));
+        assert!(matches!(
+            error.unwrap_err().get_error_type(),
+            FileNodeErrorType::TooLarge
+        ));
+
+        // the input picks the file, but it can't leave the allowed directory
+        let escape =
+            FileWriteNode::new(&format!("{}/{{{{input}}}}", dir.display())).allow_path(&dir);
+        let error = rt.block_on(escape.execute("../escaped".to_string(), &context));
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert!(matches!(
+            error.unwrap_err().get_error_type(),
+            FileNodeErrorType::PathNotAllowed
+        ));
+        assert!(!dir.with_file_name("escaped").exists());
+    }
+}
@@ -0,0 +1,116 @@
+//! # Ingest
+//!
+//! This node fills a vector store with the documents of its sources, with the incremental
+//! pipeline of [`crate::ingest`], so a workflow can refresh a knowledge base before it is
+//! queried, or on its own as the prebuilt ingestion workflow (see
+//! [`IngestNode::into_workflow`]).
+//!
+//! The input is a path or a url, or a json array of them. An empty input ingests the sources
+//! of the options. The output is the json of the report of the ingestion, with the numbers of
+//! documents `added`, `updated`, `unchanged` and `removed`, and of `chunks` embedded.
+
+use super::ai_node::embedding::EmbeddingClient;
+use super::{Worknode, Worknodecore};
+use crate::error::ingest_node_error::IngestNodeResult;
+use crate::ingest::{Ingest, IngestOptions};
+use crate::vector_store::VectorStore;
+use crate::workflow::Workflow;
+
+use serde_json::Value;
+
+use std::sync::Arc;
+
+#[derive(Debug, Clone)]
+/// The struct of the ingest node.
+pub struct IngestNode {
+    /// The pipeline of the ingestion.
+    ingest: Ingest,
+    /// The name of the client in the registry, used to save the node in a workflow file.
+    provider: Option<String>,
+    /// The name of the store in the registry, used to save the node in a workflow file.
+    store_name: Option<String>,
+}
+
+impl IngestNode {
+    /// Create a new IngestNode with the default options.
+    pub fn new(client: EmbeddingClient, store: Arc<dyn VectorStore>) -> Self {
+        IngestNode {
+            ingest: Ingest::new(client, store),
+            provider: None,
+            store_name: None,
+        }
+    }
+    /// Ingest the sources of the input, or of the options, and get the report as json.
+    pub async fn execute(&mut self, input: String) -> IngestNodeResult<String> {
+        let input = input.trim();
+        let report = if input.is_empty() {
+            self.ingest.run().await?
+        } else {
+            let sources = match serde_json::from_str::<Value>(input) {
+                Ok(Value::Array(items)) if items.iter().all(Value::is_string) => items
+                    .into_iter()
+                    .filter_map(|item| item.as_str().map(str::to_string))
+                    .collect(),
+                _ => vec![input.to_string()],
+            };
+            self.ingest.ingest(&sources).await?
+        };
+        // the report is plain numbers, so it always serializes
+        Ok(serde_json::to_string(&report).unwrap())
+    }
+    /// Get the prebuilt workflow that only ingests: start, this node and end. Its input is
+    /// the input of the node, and its output the report.
+    pub fn into_workflow(self) -> Workflow {
+        let mut workflow = Workflow::new();
+        let start = workflow.add_node(Worknode::new(Worknodecore::Start));
+        let ingest = workflow.add_node(Worknode::new(Worknodecore::Ingest(self)));
+        let end = workflow.add_node(Worknode::new(Worknodecore::End));
+        // the nodes were just added, so the edges are valid
+        workflow.add_edge(start, ingest).unwrap();
+        workflow.add_edge(ingest, end).unwrap();
+        workflow
+    }
+    /// Set the options as builder.
+    pub fn options(mut self, options: IngestOptions) -> Self {
+        self.ingest.set_options(options);
+        self
+    }
+    /// Set the options.
+    pub fn set_options(&mut self, options: IngestOptions) {
+        self.ingest.set_options(options);
+    }
+    /// Get the options.
+    pub fn get_options(&self) -> &IngestOptions {
+        self.ingest.get_options()
+    }
+    /// Get the pipeline of the ingestion.
+    pub fn get_ingest(&self) -> &Ingest {
+        &self.ingest
+    }
+    /// Set the name of the client in the registry as builder.
+    pub fn provider(mut self, provider: Option<String>) -> Self {
+        self.provider = provider;
+        self
+    }
+    /// Set the name of the client in the registry.
+    pub fn set_provider(&mut self, provider: Option<String>) {
+        self.provider = provider;
+    }
+    /// Get the name of the client in the registry.
+    pub fn get_provider(&self) -> Option<&str> {
+        self.provider.as_deref()
+    }
+    /// Set the name of the store in the registry as builder.
+    pub fn store_name(mut self, store_name: Option<String>) -> Self {
+        self.store_name = store_name;
+        self
+    }
+    /// Set the name of the store in the registry.
+    pub fn set_store_name(&mut self, store_name: Option<String>) {
+        self.store_name = store_name;
+    }
+    /// Get the name of the store in the registry.
+    pub fn get_store_name(&self) -> Option<&str> {
+        self.store_name.as_deref()
+    }
+}
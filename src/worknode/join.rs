@@ -0,0 +1,88 @@
+//! # Join
+//!
+//! This node waits for all of its incoming branches and merges their outputs into one.
+//!
+//! The workflow gives the join node the outputs of its predecessors as a json array of
+//! strings, in the order of the edges. A plain text input is taken as a single output.
+
+use json::JsonValue;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+/// The enum of the way to merge the outputs of the branches.
+pub enum JoinStrategy {
+    /// Join the outputs with the separator.
+    Concat(String),
+    /// Put the outputs in a json array. An output that is valid json is kept as json,
+    /// otherwise it is kept as a string.
+    JsonArray,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+/// The struct of the join node.
+pub struct JoinNode {
+    /// The way to merge the outputs.
+    strategy: JoinStrategy,
+}
+
+impl Default for JoinNode {
+    fn default() -> Self {
+        JoinNode {
+            strategy: JoinStrategy::Concat("\n".to_string()),
+        }
+    }
+}
+
+impl JoinNode {
+    /// Create a new JoinNode.
+    pub fn new(strategy: JoinStrategy) -> Self {
+        JoinNode { strategy }
+    }
+    /// Merge the outputs of the branches.
+    pub fn execute(&self, input: String) -> String {
+        let outputs: Vec<String> = match json::parse(&input) {
+            Ok(JsonValue::Array(outputs)) => outputs
+                .iter()
+                .map(|output| match output.as_str() {
+                    Some(output) => output.to_string(),
+                    None => output.dump(),
+                })
+                .collect(),
+            _ => vec![input],
+        };
+        match &self.strategy {
+            JoinStrategy::Concat(separator) => outputs.join(separator),
+            JoinStrategy::JsonArray => JsonValue::Array(
+                outputs
+                    .into_iter()
+                    .map(|output| json::parse(&output).unwrap_or(JsonValue::String(output)))
+                    .collect(),
+            )
+            .dump(),
+        }
+    }
+    /// Get the strategy.
+    pub fn get_strategy(&self) -> &JoinStrategy {
+        &self.strategy
+    }
+    /// Set the strategy.
+    pub fn set_strategy(&mut self, strategy: JoinStrategy) {
+        self.strategy = strategy;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn merge_outputs() {
+        let input = r#"["a", "{\"b\": 1}"]"#.to_string();
+        let concat = JoinNode::new(JoinStrategy::Concat(", ".to_string()));
+        assert_eq!(concat.execute(input.clone()), r#"a, {"b": 1}"#);
+        let array = JoinNode::new(JoinStrategy::JsonArray);
+        assert_eq!(array.execute(input), r#"["a",{"b":1}]"#);
+        assert_eq!(JoinNode::default().execute("plain".to_string()), "plain");
+    }
+}
@@ -0,0 +1,230 @@
+//! # Load
+//!
+//! This node turns a file, or all the files under a directory, into plain text with metadata
+//! with the loaders of [`crate::loader`], to feed the chunker and embed nodes.
+//!
+//! The path is a template rendered with the variable `input` and the variables of the run
+//! context, like the path of the file read node, and is `{{input}}` by default, so the input
+//! is the path. The format is detected from the extension of each file, unless it is given.
+//!
+//! The output of a file is the json object of its document, `{"text": ..., "metadata": ...}`.
+//! The output of a directory is the json array of the documents of its files of a known
+//! format, in the order of their paths.
+//!
+//! Like the file read node, the node can be limited to the files under some allowed paths,
+//! and the size of each file is limited.
+
+use super::file::{check_allowed, io_error, render_path, too_large, DEFAULT_MAX_SIZE};
+use crate::error::load_node_error::{LoadNodeError, LoadNodeErrorType, LoadNodeResult};
+use crate::loader::{self, Format, LoadedDocument};
+use crate::workflow::context::RunContext;
+
+use serde::{Deserialize, Serialize};
+
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+/// The struct of the load node.
+pub struct LoadNode {
+    /// The path template of the file or the directory.
+    path: String,
+    /// The format of the files, or `None` to detect it from their extensions.
+    format: Option<Format>,
+    /// The size in bytes of the largest file that can be loaded, or `None` for no limit.
+    max_size: Option<u64>,
+    /// The paths that the files must be under. Empty means every path is allowed.
+    allowed_paths: Vec<PathBuf>,
+}
+
+impl Default for LoadNode {
+    fn default() -> Self {
+        LoadNode {
+            path: "{{input}}".to_string(),
+            format: None,
+            max_size: Some(DEFAULT_MAX_SIZE),
+            allowed_paths: Vec::new(),
+        }
+    }
+}
+
+impl LoadNode {
+    /// Create a new LoadNode that loads the file or the directory of its input.
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Load the file or the directory, and get its documents as json.
+    pub async fn execute(&self, input: String, context: &RunContext) -> LoadNodeResult<String> {
+        let path = render_path(&self.path, input.trim().to_string(), context)
+            .map_err(|e| file_error(e, "Failed to render the path."))?;
+        let path = check_allowed(&path, &self.allowed_paths)
+            .map_err(|e| file_error(e, "The path is not allowed."))?;
+        let node = self.clone();
+        let output = tokio::task::spawn_blocking(move || -> LoadNodeResult<String> {
+            let to_json = |value: serde_json::Result<String>| {
+                value.map_err(|e| {
+                    LoadNodeError::new(
+                        LoadNodeErrorType::ParseError,
+                        format!("Failed to serialize the documents. {}", e),
+                    )
+                })
+            };
+            if path.is_dir() {
+                let documents = loader::list_files(&path)?
+                    .iter()
+                    .map(|file| node.load_file(file))
+                    .collect::<LoadNodeResult<Vec<_>>>()?;
+                to_json(serde_json::to_string(&documents))
+            } else {
+                to_json(serde_json::to_string(&node.load_file(&path)?))
+            }
+        })
+        .await
+        .map_err(|e| {
+            LoadNodeError::new(
+                LoadNodeErrorType::ParseError,
+                format!("The loader panicked. {}", e),
+            )
+        })?;
+        output
+    }
+    /// Load a file, within the size limit.
+    fn load_file(&self, path: &Path) -> LoadNodeResult<LoadedDocument> {
+        let metadata = std::fs::metadata(path)
+            .map_err(|e| file_error(io_error("read", path, e), "Failed to load the file."))?;
+        if let Some(max_size) = self.max_size {
+            if metadata.len() > max_size {
+                return Err(file_error(
+                    too_large(path, metadata.len(), max_size),
+                    "The file is too large.",
+                ));
+            }
+        }
+        match self.format {
+            Some(format) => loader::load_as(path, format),
+            None => loader::load(path),
+        }
+    }
+    /// Set the path template as builder.
+    pub fn path(mut self, path: &str) -> Self {
+        self.path = path.to_string();
+        self
+    }
+    /// Set the path template.
+    pub fn set_path(&mut self, path: &str) {
+        self.path = path.to_string();
+    }
+    /// Get the path template.
+    pub fn get_path(&self) -> &str {
+        &self.path
+    }
+    /// Set the format as builder.
+    pub fn format(mut self, format: Option<Format>) -> Self {
+        self.format = format;
+        self
+    }
+    /// Set the format.
+    pub fn set_format(&mut self, format: Option<Format>) {
+        self.format = format;
+    }
+    /// Get the format.
+    pub fn get_format(&self) -> Option<Format> {
+        self.format
+    }
+    /// Set the size limit as builder.
+    pub fn max_size(mut self, max_size: Option<u64>) -> Self {
+        self.max_size = max_size;
+        self
+    }
+    /// Set the size limit.
+    pub fn set_max_size(&mut self, max_size: Option<u64>) {
+        self.max_size = max_size;
+    }
+    /// Get the size limit.
+    pub fn get_max_size(&self) -> Option<u64> {
+        self.max_size
+    }
+    /// Add an allowed path as builder.
+    pub fn allow_path<P: AsRef<Path>>(mut self, path: P) -> Self {
+        self.allowed_paths.push(path.as_ref().to_path_buf());
+        self
+    }
+    /// Set the allowed paths.
+    pub fn set_allowed_paths(&mut self, allowed_paths: Vec<PathBuf>) {
+        self.allowed_paths = allowed_paths;
+    }
+    /// Get the allowed paths.
+    pub fn get_allowed_paths(&self) -> &Vec<PathBuf> {
+        &self.allowed_paths
+    }
+}
+
+/// Create a LoadNodeError of a FileNodeError.
+fn file_error(e: crate::error::file_node_error::FileNodeError, message: &str) -> LoadNodeError {
+    LoadNodeError::new(
+        LoadNodeErrorType::FileError(Box::new(e)),
+        message.to_string(),
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::error::file_node_error::FileNodeErrorType;
+
+    use serde_json::Value;
+    use tokio::runtime::Runtime;
+
+    #[test]
+    fn load_paths() {
+        let rt = Runtime::new().unwrap();
+        let dir = std::env::temp_dir().join(format!("load-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(dir.join("docs")).unwrap();
+        std::fs::write(
+            dir.join("docs/index.html"),
+            "<title>Home</title><nav>Menu</nav><p>Welcome</p>",
+        )
+        .unwrap();
+        std::fs::write(dir.join("docs/notes.txt"), "Some notes").unwrap();
+        std::fs::write(dir.join("docs/data.bin"), [0u8, 1]).unwrap();
+        let context = RunContext::new();
+        let node = LoadNode::new().allow_path(&dir);
+
+        let input = dir.join("docs/index.html").display().to_string();
+        let output = rt.block_on(node.execute(input, &context)).unwrap();
+        let document: Value = serde_json::from_str(&output).unwrap();
+        assert_eq!(document["text"], "Welcome");
+        assert_eq!(document["metadata"]["title"], "Home");
+        assert_eq!(document["metadata"]["format"], "html");
+
+        let input = dir.join("docs").display().to_string();
+        let output = rt.block_on(node.execute(input, &context)).unwrap();
+        let documents: Vec<LoadedDocument> = serde_json::from_str(&output).unwrap();
+        let texts: Vec<&str> = documents.iter().map(|d| d.text.as_str()).collect();
+        assert_eq!(texts, ["Welcome", "Some notes"]);
+
+        // a given format loads a file of any extension
+        let input = dir.join("docs/data.bin").display().to_string();
+        let error = rt.block_on(node.execute(input.clone(), &context));
+        assert!(matches!(
+            error.unwrap_err().get_error_type(),
+            LoadNodeErrorType::UnsupportedFormat
+        ));
+        let text = node.clone().format(Some(Format::Text));
+        assert!(rt.block_on(text.execute(input.clone(), &context)).is_ok());
+        let small = text.max_size(Some(1));
+        let error = rt.block_on(small.execute(input, &context));
+        assert!(matches!(
+            error.unwrap_err().get_error_type(),
+            LoadNodeErrorType::FileError(e) if matches!(e.get_error_type(), FileNodeErrorType::TooLarge)
+        ));
+
+        let error = rt.block_on(node.execute(dir.join("../x.txt").display().to_string(), &context));
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert!(matches!(
+            error.unwrap_err().get_error_type(),
+            LoadNodeErrorType::FileError(e) if matches!(e.get_error_type(), FileNodeErrorType::PathNotAllowed)
+        ));
+    }
+}
@@ -0,0 +1,321 @@
+//! # Local
+//!
+//! This node runs a command on the local machine, like a script that formats the answer of an
+//! AI node, runs the tests of generated code or calls another program.
+//!
+//! The input of the node is written to the stdin of the command. The arguments are templates
+//! (see [`crate::template`]) rendered with the variable `input` and the variables of the run
+//! context, so the input can also be given as an argument. Prefer the stdin for an input made
+//! by an AI node: an argument of `sh -c` is run as shell code.
+//!
+//! The output of the node is the stdout of the command. A command that exits with a status
+//! other than 0 fails the node with its stderr. With a timeout, a command that runs too long
+//! is killed. The stdout, the stderr and the exit code of the last execution are kept in the
+//! node.
+//!
+//! A command runs in a sandbox (see [`sandbox`]). By default it only gets a few environment
+//! variables of the process and can't gain privileges; the sandbox can also limit its
+//! resources, deny the network and restrict the filesystem. Use [`Sandbox::none`] to run a
+//! trusted command with all the privileges of the user.
+
+pub mod sandbox;
+
+use crate::error::local_node_error::{LocalNodeError, LocalNodeErrorType, LocalNodeResult};
+use crate::template;
+use crate::workflow::context::RunContext;
+use sandbox::Sandbox;
+
+use serde::{Deserialize, Serialize};
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::process::Stdio;
+use std::time::Duration;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+/// The struct of what a command printed and how it exited.
+pub struct LocalOutput {
+    /// The stdout of the command.
+    pub stdout: String,
+    /// The stderr of the command.
+    pub stderr: String,
+    /// The exit code, or `None` if the command was killed by a signal.
+    pub exit_code: Option<i32>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+/// The struct of the local node.
+pub struct LocalNode {
+    /// The program to run, like `python3` or `./format.sh`.
+    program: String,
+    /// The argument templates of the program.
+    args: Vec<String>,
+    /// Whether the input is written to the stdin of the command.
+    stdin: bool,
+    /// The working directory of the command, or the one of the process.
+    working_dir: Option<PathBuf>,
+    /// The environment variables set for the command, besides the ones of the process.
+    env: BTreeMap<String, String>,
+    /// The time the command may take before it is killed.
+    timeout: Option<Duration>,
+    /// The limits and the isolation of the command.
+    sandbox: Sandbox,
+    /// The output of the last execution.
+    #[serde(skip)]
+    last_output: Option<LocalOutput>,
+}
+
+impl Default for LocalNode {
+    fn default() -> Self {
+        LocalNode {
+            program: String::new(),
+            args: Vec::new(),
+            stdin: true,
+            working_dir: None,
+            env: BTreeMap::new(),
+            timeout: None,
+            sandbox: Sandbox::default(),
+            last_output: None,
+        }
+    }
+}
+
+impl LocalNode {
+    /// Create a new LocalNode that runs the program.
+    pub fn new(program: &str) -> Self {
+        LocalNode {
+            program: program.to_string(),
+            ..Self::default()
+        }
+    }
+    /// Create a new LocalNode that runs the command with `sh -c`.
+    pub fn shell(command: &str) -> Self {
+        Self::new("sh").args(vec!["-c".to_string(), command.to_string()])
+    }
+    /// Run the command with the input, and get its stdout.
+    pub async fn execute(
+        &mut self,
+        input: String,
+        context: &RunContext,
+    ) -> LocalNodeResult<String> {
+        self.last_output = None;
+        let mut variables = context.to_variables();
+        variables.insert("input".to_string(), input.clone());
+        let args = self
+            .args
+            .iter()
+            .map(|arg| template::render(arg, &variables))
+            .collect::<Result<Vec<String>, _>>()
+            .map_err(|e| {
+                LocalNodeError::new(
+                    LocalNodeErrorType::TemplateError(e),
+                    "Failed to render the arguments of the command.".to_string(),
+                )
+            })?;
+        let mut command = Command::new(&self.program);
+        // the sandbox clears the environment, so it is applied before the variables of the node
+        let guard = self.sandbox.apply(&mut command)?;
+        command
+            .args(&args)
+            .envs(&self.env)
+            .stdin(if self.stdin {
+                Stdio::piped()
+            } else {
+                Stdio::null()
+            })
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            // the command is killed when the node is stopped, like on a timeout
+            .kill_on_drop(true);
+        if let Some(working_dir) = &self.working_dir {
+            command.current_dir(working_dir);
+        }
+        let mut child = command.spawn().map_err(|e| {
+            LocalNodeError::new(
+                LocalNodeErrorType::SpawnError,
+                format!("Failed to start {}. {}", self.program, e),
+            )
+        })?;
+        drop(guard);
+        if let Some(mut stdin) = child.stdin.take() {
+            // write in the background, so a large input doesn't block the reading of the
+            // output, and a command that doesn't read its stdin doesn't fail the node
+            tokio::spawn(async move {
+                let _ = stdin.write_all(input.as_bytes()).await;
+            });
+        }
+        let output = child.wait_with_output();
+        let output = match self.timeout {
+            Some(timeout) => tokio::time::timeout(timeout, output).await.map_err(|_| {
+                LocalNodeError::new(
+                    LocalNodeErrorType::Timeout,
+                    format!("{} didn't finish in {:?}.", self.program, timeout),
+                )
+            })?,
+            None => output.await,
+        }
+        .map_err(|e| {
+            LocalNodeError::new(
+                LocalNodeErrorType::IoError,
+                format!("Failed to read the output of {}. {}", self.program, e),
+            )
+        })?;
+        let output = LocalOutput {
+            stdout: String::from_utf8_lossy(&output.stdout).to_string(),
+            stderr: String::from_utf8_lossy(&output.stderr).to_string(),
+            exit_code: output.status.code(),
+        };
+        self.last_output = Some(output.clone());
+        if output.exit_code != Some(0) {
+            return Err(LocalNodeError::new(
+                LocalNodeErrorType::ExitError {
+                    code: output.exit_code,
+                    stderr: output.stderr,
+                },
+                format!("{} failed.", self.program),
+            ));
+        }
+        Ok(output.stdout)
+    }
+    /// Get the output of the last execution.
+    pub fn get_last_output(&self) -> Option<&LocalOutput> {
+        self.last_output.as_ref()
+    }
+    /// Set the program as builder.
+    pub fn program(mut self, program: &str) -> Self {
+        self.program = program.to_string();
+        self
+    }
+    /// Set the program.
+    pub fn set_program(&mut self, program: &str) {
+        self.program = program.to_string();
+    }
+    /// Get the program.
+    pub fn get_program(&self) -> &str {
+        &self.program
+    }
+    /// Set the argument templates as builder.
+    pub fn args(mut self, args: Vec<String>) -> Self {
+        self.args = args;
+        self
+    }
+    /// Set the argument templates.
+    pub fn set_args(&mut self, args: Vec<String>) {
+        self.args = args;
+    }
+    /// Get the argument templates.
+    pub fn get_args(&self) -> &Vec<String> {
+        &self.args
+    }
+    /// Set whether the input is written to the stdin as builder.
+    pub fn stdin(mut self, stdin: bool) -> Self {
+        self.stdin = stdin;
+        self
+    }
+    /// Set whether the input is written to the stdin.
+    pub fn set_stdin(&mut self, stdin: bool) {
+        self.stdin = stdin;
+    }
+    /// Get whether the input is written to the stdin.
+    pub fn get_stdin(&self) -> bool {
+        self.stdin
+    }
+    /// Set the working directory as builder.
+    pub fn working_dir(mut self, working_dir: Option<PathBuf>) -> Self {
+        self.working_dir = working_dir;
+        self
+    }
+    /// Set the working directory.
+    pub fn set_working_dir(&mut self, working_dir: Option<PathBuf>) {
+        self.working_dir = working_dir;
+    }
+    /// Get the working directory.
+    pub fn get_working_dir(&self) -> Option<&PathBuf> {
+        self.working_dir.as_ref()
+    }
+    /// Set an environment variable as builder.
+    pub fn env(mut self, name: &str, value: &str) -> Self {
+        self.set_env(name, value);
+        self
+    }
+    /// Set an environment variable.
+    pub fn set_env(&mut self, name: &str, value: &str) {
+        self.env.insert(name.to_string(), value.to_string());
+    }
+    /// Get the environment variables.
+    pub fn get_env(&self) -> &BTreeMap<String, String> {
+        &self.env
+    }
+    /// Set the timeout as builder.
+    pub fn timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.timeout = timeout;
+        self
+    }
+    /// Set the timeout.
+    pub fn set_timeout(&mut self, timeout: Option<Duration>) {
+        self.timeout = timeout;
+    }
+    /// Get the timeout.
+    pub fn get_timeout(&self) -> Option<Duration> {
+        self.timeout
+    }
+    /// Set the sandbox as builder.
+    pub fn sandbox(mut self, sandbox: Sandbox) -> Self {
+        self.sandbox = sandbox;
+        self
+    }
+    /// Set the sandbox.
+    pub fn set_sandbox(&mut self, sandbox: Sandbox) {
+        self.sandbox = sandbox;
+    }
+    /// Get the sandbox.
+    pub fn get_sandbox(&self) -> &Sandbox {
+        &self.sandbox
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use tokio::runtime::Runtime;
+
+    #[test]
+    fn run_commands() {
+        let rt = Runtime::new().unwrap();
+        let context = RunContext::new();
+        context.set("name", "Tom").unwrap();
+
+        let mut upper = LocalNode::new("tr").args(vec!["a-z".to_string(), "A-Z".to_string()]);
+        let output = rt.block_on(upper.execute("hello".to_string(), &context));
+        assert_eq!(output.unwrap(), "HELLO");
+
+        let mut echo = LocalNode::shell("echo \"$GREETING {{context.name}}\"; pwd")
+            .stdin(false)
+            .env("GREETING", "Hi")
+            .working_dir(Some(std::env::temp_dir()));
+        let output = rt.block_on(echo.execute(String::new(), &context)).unwrap();
+        let temp_dir = std::env::temp_dir().canonicalize().unwrap();
+        assert_eq!(output, format!("Hi Tom\n{}\n", temp_dir.display()));
+
+        let mut failing = LocalNode::shell("echo oops >&2; exit 3");
+        let error = rt.block_on(failing.execute(String::new(), &context));
+        assert!(matches!(
+            error.unwrap_err().get_error_type(),
+            LocalNodeErrorType::ExitError { code: Some(3), stderr } if stderr == "oops\n"
+        ));
+        assert_eq!(failing.get_last_output().unwrap().exit_code, Some(3));
+
+        let mut slow = LocalNode::new("sleep")
+            .args(vec!["5".to_string()])
+            .timeout(Some(Duration::from_millis(100)));
+        let error = rt.block_on(slow.execute(String::new(), &context));
+        assert!(matches!(
+            error.unwrap_err().get_error_type(),
+            LocalNodeErrorType::Timeout
+        ));
+    }
+}
@@ -0,0 +1,598 @@
+//! # Sandbox
+//!
+//! This module limits what the command of a local node can do, since a command may be written
+//! by an AI service and should not run with all the privileges of the user.
+//!
+//! By default a command gets only a few environment variables of the process (like `PATH`
+//! and `HOME`, so no api key leaks into it), and can't gain privileges through setuid
+//! programs. A sandbox can also:
+//! - limit the CPU time, the memory and the size of the files written by the command;
+//! - deny the network, by running the command in a new network namespace;
+//! - restrict the filesystem to read-only and read-write paths, with Landlock.
+//!
+//! The limits are set in the child process right before the command starts. They need Linux;
+//! the network denial needs user namespaces, and the filesystem restriction a kernel with
+//! Landlock (5.13 or newer). A sandbox that can't be set up fails the node instead of
+//! running the command without it.
+
+use crate::error::local_node_error::{LocalNodeError, LocalNodeErrorType, LocalNodeResult};
+
+use serde::{Deserialize, Serialize};
+use tokio::process::Command;
+
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// The environment variables of the process that a command gets by default.
+pub const DEFAULT_ALLOWED_ENV: [&str; 7] =
+    ["PATH", "HOME", "USER", "LANG", "LC_ALL", "TERM", "TMPDIR"];
+
+/// The system paths that a strict sandbox can read, so the programs and their libraries load.
+pub const SYSTEM_PATHS: [&str; 5] = ["/usr", "/bin", "/lib", "/lib64", "/etc"];
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+/// The struct of the limits and the isolation of a command.
+pub struct Sandbox {
+    /// Whether the command gets only the allowed environment variables of the process. The
+    /// variables of the node are always set.
+    clear_env: bool,
+    /// The environment variables of the process that the command gets.
+    allowed_env: Vec<String>,
+    /// Whether the command and its children can't gain privileges, like through setuid.
+    no_new_privileges: bool,
+    /// The CPU time the command may use before it is killed.
+    cpu_time: Option<Duration>,
+    /// The bytes of memory the command may map.
+    memory: Option<u64>,
+    /// The bytes of the largest file the command may write.
+    file_size: Option<u64>,
+    /// Whether the command runs without network.
+    deny_network: bool,
+    /// Whether the command can only access the read-only and the read-write paths.
+    restrict_filesystem: bool,
+    /// The paths that the command can read and execute, with everything below them.
+    read_only_paths: Vec<PathBuf>,
+    /// The paths that the command can read and write, with everything below them.
+    read_write_paths: Vec<PathBuf>,
+}
+
+impl Default for Sandbox {
+    fn default() -> Self {
+        Sandbox {
+            clear_env: true,
+            allowed_env: DEFAULT_ALLOWED_ENV
+                .iter()
+                .map(|name| name.to_string())
+                .collect(),
+            no_new_privileges: true,
+            cpu_time: None,
+            memory: None,
+            file_size: None,
+            deny_network: false,
+            restrict_filesystem: false,
+            read_only_paths: Vec::new(),
+            read_write_paths: Vec::new(),
+        }
+    }
+}
+
+impl Sandbox {
+    /// Create a new Sandbox with the default restrictions: a restricted environment and no
+    /// new privileges.
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Create a Sandbox that doesn't restrict anything. The command runs with the
+    /// environment and the privileges of the process.
+    pub fn none() -> Self {
+        Sandbox {
+            clear_env: false,
+            no_new_privileges: false,
+            ..Self::default()
+        }
+    }
+    /// Create a strict Sandbox: no network, the system paths read-only, and only the paths
+    /// given writable.
+    pub fn strict(read_write_paths: Vec<PathBuf>) -> Self {
+        Sandbox {
+            deny_network: true,
+            restrict_filesystem: true,
+            read_only_paths: SYSTEM_PATHS.iter().map(PathBuf::from).collect(),
+            read_write_paths,
+            ..Self::default()
+        }
+    }
+    /// Set up the sandbox of the command before it is spawned. The returned guard must live
+    /// until the command is spawned.
+    pub(super) fn apply(&self, command: &mut Command) -> LocalNodeResult<SandboxGuard> {
+        if self.clear_env {
+            let kept: Vec<(String, String)> = self
+                .allowed_env
+                .iter()
+                .filter_map(|name| std::env::var(name).ok().map(|value| (name.clone(), value)))
+                .collect();
+            command.env_clear().envs(kept);
+        }
+        platform::apply(self, command)
+    }
+    /// Set whether the command gets only the allowed environment variables as builder.
+    pub fn clear_env(mut self, clear_env: bool) -> Self {
+        self.clear_env = clear_env;
+        self
+    }
+    /// Set whether the command gets only the allowed environment variables.
+    pub fn set_clear_env(&mut self, clear_env: bool) {
+        self.clear_env = clear_env;
+    }
+    /// Get whether the command gets only the allowed environment variables.
+    pub fn get_clear_env(&self) -> bool {
+        self.clear_env
+    }
+    /// Allow the command to get an environment variable of the process as builder.
+    pub fn allow_env(mut self, name: &str) -> Self {
+        self.allowed_env.push(name.to_string());
+        self
+    }
+    /// Set the environment variables of the process that the command gets.
+    pub fn set_allowed_env(&mut self, allowed_env: Vec<String>) {
+        self.allowed_env = allowed_env;
+    }
+    /// Get the environment variables of the process that the command gets.
+    pub fn get_allowed_env(&self) -> &Vec<String> {
+        &self.allowed_env
+    }
+    /// Set whether the command can't gain privileges as builder.
+    pub fn no_new_privileges(mut self, no_new_privileges: bool) -> Self {
+        self.no_new_privileges = no_new_privileges;
+        self
+    }
+    /// Set whether the command can't gain privileges.
+    pub fn set_no_new_privileges(&mut self, no_new_privileges: bool) {
+        self.no_new_privileges = no_new_privileges;
+    }
+    /// Get whether the command can't gain privileges.
+    pub fn get_no_new_privileges(&self) -> bool {
+        self.no_new_privileges
+    }
+    /// Set the CPU time the command may use as builder. It is counted in whole seconds.
+    pub fn cpu_time(mut self, cpu_time: Option<Duration>) -> Self {
+        self.cpu_time = cpu_time;
+        self
+    }
+    /// Set the CPU time the command may use.
+    pub fn set_cpu_time(&mut self, cpu_time: Option<Duration>) {
+        self.cpu_time = cpu_time;
+    }
+    /// Get the CPU time the command may use.
+    pub fn get_cpu_time(&self) -> Option<Duration> {
+        self.cpu_time
+    }
+    /// Set the bytes of memory the command may map as builder.
+    pub fn memory(mut self, memory: Option<u64>) -> Self {
+        self.memory = memory;
+        self
+    }
+    /// Set the bytes of memory the command may map.
+    pub fn set_memory(&mut self, memory: Option<u64>) {
+        self.memory = memory;
+    }
+    /// Get the bytes of memory the command may map.
+    pub fn get_memory(&self) -> Option<u64> {
+        self.memory
+    }
+    /// Set the bytes of the largest file the command may write as builder.
+    pub fn file_size(mut self, file_size: Option<u64>) -> Self {
+        self.file_size = file_size;
+        self
+    }
+    /// Set the bytes of the largest file the command may write.
+    pub fn set_file_size(&mut self, file_size: Option<u64>) {
+        self.file_size = file_size;
+    }
+    /// Get the bytes of the largest file the command may write.
+    pub fn get_file_size(&self) -> Option<u64> {
+        self.file_size
+    }
+    /// Set whether the command runs without network as builder.
+    pub fn deny_network(mut self, deny_network: bool) -> Self {
+        self.deny_network = deny_network;
+        self
+    }
+    /// Set whether the command runs without network.
+    pub fn set_deny_network(&mut self, deny_network: bool) {
+        self.deny_network = deny_network;
+    }
+    /// Get whether the command runs without network.
+    pub fn get_deny_network(&self) -> bool {
+        self.deny_network
+    }
+    /// Restrict the filesystem to the read-only and the read-write paths as builder.
+    pub fn restrict_filesystem(mut self, restrict_filesystem: bool) -> Self {
+        self.restrict_filesystem = restrict_filesystem;
+        self
+    }
+    /// Set whether the filesystem is restricted to the read-only and the read-write paths.
+    pub fn set_restrict_filesystem(&mut self, restrict_filesystem: bool) {
+        self.restrict_filesystem = restrict_filesystem;
+    }
+    /// Get whether the filesystem is restricted to the read-only and the read-write paths.
+    pub fn get_restrict_filesystem(&self) -> bool {
+        self.restrict_filesystem
+    }
+    /// Add a path that the command can read as builder.
+    pub fn read_only_path<P: AsRef<Path>>(mut self, path: P) -> Self {
+        self.read_only_paths.push(path.as_ref().to_path_buf());
+        self
+    }
+    /// Get the paths that the command can read.
+    pub fn get_read_only_paths(&self) -> &Vec<PathBuf> {
+        &self.read_only_paths
+    }
+    /// Add a path that the command can read and write as builder.
+    pub fn read_write_path<P: AsRef<Path>>(mut self, path: P) -> Self {
+        self.read_write_paths.push(path.as_ref().to_path_buf());
+        self
+    }
+    /// Get the paths that the command can read and write.
+    pub fn get_read_write_paths(&self) -> &Vec<PathBuf> {
+        &self.read_write_paths
+    }
+    /// Whether the filesystem can be restricted on this system, which needs Landlock.
+    pub fn can_restrict_filesystem() -> bool {
+        platform::landlock_abi().is_some()
+    }
+    #[cfg(not(target_os = "linux"))]
+    /// Whether the sandbox needs more than the environment, which only Linux supports.
+    fn isolates(&self) -> bool {
+        self.no_new_privileges
+            || self.cpu_time.is_some()
+            || self.memory.is_some()
+            || self.file_size.is_some()
+            || self.deny_network
+            || self.restrict_filesystem
+    }
+}
+
+/// Create a LocalNodeError of the sandbox.
+fn sandbox_error(message: String) -> LocalNodeError {
+    LocalNodeError::new(LocalNodeErrorType::SandboxError, message)
+}
+
+#[cfg(target_os = "linux")]
+/// The guard of the resources of a sandbox that the command needs until it is spawned.
+pub(super) struct SandboxGuard {
+    /// The Landlock ruleset that the command enters.
+    _ruleset: Option<std::os::fd::OwnedFd>,
+}
+
+#[cfg(not(target_os = "linux"))]
+/// The guard of the resources of a sandbox that the command needs until it is spawned.
+pub(super) struct SandboxGuard;
+
+#[cfg(target_os = "linux")]
+mod platform {
+    use super::{sandbox_error, Sandbox, SandboxGuard};
+    use crate::error::local_node_error::LocalNodeResult;
+
+    use tokio::process::Command;
+
+    use std::os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+    use std::os::unix::fs::OpenOptionsExt;
+    use std::path::PathBuf;
+
+    /// The Landlock rights to execute, read and write files, and to read, create and remove
+    /// the entries of directories, which are the rights of the first Landlock version.
+    const ACCESS_FS_V1: u64 = (1 << 13) - 1;
+    /// The Landlock right to link or rename a file into another directory, since the second
+    /// version.
+    const ACCESS_FS_REFER: u64 = 1 << 13;
+    /// The Landlock right to truncate a file, since the third version.
+    const ACCESS_FS_TRUNCATE: u64 = 1 << 14;
+    /// The Landlock rights to execute and read files, and to read directories.
+    const ACCESS_FS_READ: u64 = 1 | (1 << 2) | (1 << 3);
+    /// The Landlock rights that apply to a file that is not a directory.
+    const ACCESS_FS_FILE: u64 = 1 | (1 << 1) | (1 << 2) | ACCESS_FS_TRUNCATE;
+    /// The type of a Landlock rule on the files below a path.
+    const LANDLOCK_RULE_PATH_BENEATH: libc::c_int = 1;
+    /// The flag of `landlock_create_ruleset` that asks for the version of Landlock.
+    const LANDLOCK_CREATE_RULESET_VERSION: u32 = 1;
+
+    #[repr(C)]
+    /// The attributes of a Landlock ruleset.
+    struct RulesetAttr {
+        handled_access_fs: u64,
+    }
+
+    #[repr(C, packed)]
+    /// The attributes of a Landlock rule on the files below a path.
+    struct PathBeneathAttr {
+        allowed_access: u64,
+        parent_fd: i32,
+    }
+
+    #[derive(Clone, Copy)]
+    /// The limits that the child process sets on itself.
+    struct Limits {
+        no_new_privileges: bool,
+        cpu_time: Option<u64>,
+        memory: Option<u64>,
+        file_size: Option<u64>,
+        deny_network: bool,
+        ruleset: Option<RawFd>,
+    }
+
+    impl Limits {
+        /// Enter the sandbox in the child process, right before the command starts. Only
+        /// system calls are made here, since the child is a fork of a threaded process.
+        fn enter(&self) -> std::io::Result<()> {
+            set_limit(libc::RLIMIT_CPU, self.cpu_time)?;
+            set_limit(libc::RLIMIT_AS, self.memory)?;
+            set_limit(libc::RLIMIT_FSIZE, self.file_size)?;
+            if self.deny_network {
+                // root can make a network namespace, other users need a user namespace first
+                let flags = match unsafe { libc::geteuid() } {
+                    0 => libc::CLONE_NEWNET,
+                    _ => libc::CLONE_NEWUSER | libc::CLONE_NEWNET,
+                };
+                check(unsafe { libc::unshare(flags) } as i64)?;
+            }
+            if self.no_new_privileges || self.ruleset.is_some() {
+                check(unsafe { libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) } as i64)?;
+            }
+            if let Some(ruleset) = self.ruleset {
+                check(unsafe { libc::syscall(libc::SYS_landlock_restrict_self, ruleset, 0) })?;
+            }
+            Ok(())
+        }
+    }
+
+    /// Set up the limits of the sandbox in the command.
+    pub(super) fn apply(sandbox: &Sandbox, command: &mut Command) -> LocalNodeResult<SandboxGuard> {
+        let ruleset = match sandbox.restrict_filesystem {
+            true => Some(ruleset(
+                &sandbox.read_only_paths,
+                &sandbox.read_write_paths,
+            )?),
+            false => None,
+        };
+        let limits = Limits {
+            no_new_privileges: sandbox.no_new_privileges,
+            // the command gets SIGXCPU at the limit, and is killed a second later
+            cpu_time: sandbox.cpu_time.map(|time| time.as_secs().max(1)),
+            memory: sandbox.memory,
+            file_size: sandbox.file_size,
+            deny_network: sandbox.deny_network,
+            ruleset: ruleset.as_ref().map(|ruleset| ruleset.as_raw_fd()),
+        };
+        // SAFETY: `enter` only makes system calls, which is safe between fork and exec
+        unsafe {
+            command.pre_exec(move || limits.enter());
+        }
+        Ok(SandboxGuard { _ruleset: ruleset })
+    }
+
+    /// Get the version of Landlock of the kernel, or `None` if it is not available.
+    pub(super) fn landlock_abi() -> Option<i64> {
+        let abi = unsafe {
+            libc::syscall(
+                libc::SYS_landlock_create_ruleset,
+                std::ptr::null::<RulesetAttr>(),
+                0,
+                LANDLOCK_CREATE_RULESET_VERSION,
+            )
+        };
+        (abi > 0).then_some(abi)
+    }
+
+    /// Create the Landlock ruleset that allows the paths only. Every right that the kernel
+    /// knows is handled, so a right of a later version, like truncating, is denied too.
+    fn ruleset(read_only: &[PathBuf], read_write: &[PathBuf]) -> LocalNodeResult<OwnedFd> {
+        let abi = landlock_abi().ok_or_else(|| {
+            sandbox_error(format!(
+                "Can't restrict the filesystem, Landlock is not available. {}",
+                std::io::Error::last_os_error()
+            ))
+        })?;
+        let mut handled = ACCESS_FS_V1;
+        if abi >= 2 {
+            handled |= ACCESS_FS_REFER;
+        }
+        if abi >= 3 {
+            handled |= ACCESS_FS_TRUNCATE;
+        }
+        let attr = RulesetAttr {
+            handled_access_fs: handled,
+        };
+        let fd = unsafe {
+            libc::syscall(
+                libc::SYS_landlock_create_ruleset,
+                &attr as *const RulesetAttr,
+                std::mem::size_of::<RulesetAttr>(),
+                0,
+            )
+        };
+        if fd < 0 {
+            return Err(sandbox_error(format!(
+                "Can't restrict the filesystem, Landlock is not available. {}",
+                std::io::Error::last_os_error()
+            )));
+        }
+        // SAFETY: the ruleset is a new file descriptor that nothing else owns
+        let ruleset = unsafe { OwnedFd::from_raw_fd(fd as RawFd) };
+        let rules = read_only
+            .iter()
+            .map(|path| (path, ACCESS_FS_READ))
+            .chain(read_write.iter().map(|path| (path, handled)));
+        for (path, access) in rules {
+            // a path that doesn't exist can't be accessed anyway
+            let Ok(file) = std::fs::File::options()
+                .read(true)
+                .custom_flags(libc::O_PATH | libc::O_CLOEXEC)
+                .open(path)
+            else {
+                continue;
+            };
+            let is_dir = file.metadata().map(|m| m.is_dir()).unwrap_or(false);
+            let rule = PathBeneathAttr {
+                allowed_access: if is_dir {
+                    access
+                } else {
+                    access & ACCESS_FS_FILE & handled
+                },
+                parent_fd: file.as_raw_fd(),
+            };
+            let result = unsafe {
+                libc::syscall(
+                    libc::SYS_landlock_add_rule,
+                    ruleset.as_raw_fd(),
+                    LANDLOCK_RULE_PATH_BENEATH,
+                    &rule as *const PathBeneathAttr,
+                    0,
+                )
+            };
+            if result < 0 {
+                return Err(sandbox_error(format!(
+                    "Can't allow the path {}. {}",
+                    path.display(),
+                    std::io::Error::last_os_error()
+                )));
+            }
+        }
+        Ok(ruleset)
+    }
+
+    /// Set the soft limit of the resource, and the hard limit a bit higher so the command is
+    /// warned first where the resource has a warning.
+    fn set_limit(resource: libc::__rlimit_resource_t, limit: Option<u64>) -> std::io::Result<()> {
+        let Some(limit) = limit else {
+            return Ok(());
+        };
+        let hard = match resource {
+            libc::RLIMIT_CPU => limit + 1,
+            _ => limit,
+        };
+        let limit = libc::rlimit {
+            rlim_cur: limit,
+            rlim_max: hard,
+        };
+        check(unsafe { libc::setrlimit(resource, &limit) } as i64)
+    }
+
+    /// Turn the result of a system call into an io result.
+    fn check(result: i64) -> std::io::Result<()> {
+        match result {
+            result if result < 0 => Err(std::io::Error::last_os_error()),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+mod platform {
+    use super::{sandbox_error, Sandbox, SandboxGuard};
+    use crate::error::local_node_error::LocalNodeResult;
+
+    use tokio::process::Command;
+
+    /// Refuse a sandbox that needs Linux.
+    pub(super) fn apply(sandbox: &Sandbox, _: &mut Command) -> LocalNodeResult<SandboxGuard> {
+        match sandbox.isolates() {
+            true => Err(sandbox_error(
+                "The sandbox can only limit the environment on this system.".to_string(),
+            )),
+            false => Ok(SandboxGuard),
+        }
+    }
+
+    /// Landlock is a feature of Linux only.
+    pub(super) fn landlock_abi() -> Option<i64> {
+        None
+    }
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod test {
+    use super::super::LocalNode;
+    use super::*;
+    use crate::workflow::context::RunContext;
+
+    use tokio::runtime::Runtime;
+
+    fn run(node: &mut LocalNode) -> LocalNodeResult<String> {
+        Runtime::new()
+            .unwrap()
+            .block_on(node.execute(String::new(), &RunContext::new()))
+    }
+
+    #[test]
+    fn restricted_env_and_limits() {
+        // cargo sets the package name for the tests, but it is not an allowed variable
+        let command = "echo ${CARGO_PKG_NAME:-none}";
+        let mut node = LocalNode::shell(command);
+        assert_eq!(run(&mut node).unwrap(), "none\n");
+        let mut node = LocalNode::shell(command).sandbox(Sandbox::none());
+        assert_eq!(run(&mut node).unwrap(), "aipilot\n");
+
+        let dir = std::env::temp_dir().join(format!("sandbox-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir(&dir).unwrap();
+        let mut node = LocalNode::shell("head -c 4096 /dev/zero > big")
+            .working_dir(Some(dir.clone()))
+            .sandbox(Sandbox::new().file_size(Some(1024)));
+        let error = run(&mut node);
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert!(matches!(
+            error.unwrap_err().get_error_type(),
+            LocalNodeErrorType::ExitError { .. }
+        ));
+    }
+
+    #[test]
+    fn strict_isolation() {
+        // a file that can be read can't be truncated, where Landlock knows the right
+        if platform::landlock_abi().is_some_and(|abi| abi >= 3) {
+            let kept = std::env::temp_dir().join(format!("sandbox-{}", uuid::Uuid::new_v4()));
+            std::fs::write(&kept, "keep").unwrap();
+            // truncate(2) needs no file opened for writing
+            let command = format!("perl -e 'truncate(shift, 0) or die $!' {}", kept.display());
+            let mut node = LocalNode::shell(&command).sandbox(
+                Sandbox::strict(vec![])
+                    .deny_network(false)
+                    .read_only_path("/dev/null")
+                    .read_only_path(&kept),
+            );
+            let result = run(&mut node);
+            let content = std::fs::read_to_string(&kept).unwrap();
+            std::fs::remove_file(&kept).unwrap();
+            assert!(result.is_err());
+            assert_eq!(content, "keep");
+        }
+
+        let dir = std::env::temp_dir().join(format!("sandbox-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir(&dir).unwrap();
+        let outside = std::env::temp_dir().join(format!("sandbox-{}", uuid::Uuid::new_v4()));
+        let command = format!(
+            "echo ok > inside && cat inside && grep -c : /proc/net/dev && touch {}",
+            outside.display()
+        );
+        let mut node = LocalNode::shell(&command)
+            .working_dir(Some(dir.clone()))
+            .sandbox(Sandbox::strict(vec![dir.clone()]).read_only_path("/proc"));
+        let result = run(&mut node);
+        std::fs::remove_dir_all(&dir).unwrap();
+        if let Err(e) = &result {
+            if matches!(
+                e.get_error_type(),
+                LocalNodeErrorType::SpawnError | LocalNodeErrorType::SandboxError
+            ) {
+                // the system has no user namespaces or no Landlock
+                return;
+            }
+        }
+        // only the loopback interface is in the new network namespace
+        let output = node.get_last_output().unwrap();
+        assert_eq!(output.stdout, "ok\n1\n");
+        assert!(!outside.exists());
+        assert!(result.is_err());
+    }
+}
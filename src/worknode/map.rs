@@ -0,0 +1,183 @@
+//! # Map
+//!
+//! This node runs a subworkflow on every item of its json array input, so a batch task like
+//! summarizing 500 files is a single node of the graph.
+//!
+//! Every item is the input of its own run of the subworkflow: a string item as it is, and any
+//! other item as json. The runs go on concurrently, at most `max_parallelism` at a time, and
+//! the output is the json array of their outputs in the order of the items. An output that is
+//! valid json is kept as json, otherwise it is kept as a string.
+//!
+//! The runs share the run context of the node. When the subworkflow fails on an item, the node
+//! fails and the other runs are stopped.
+
+use crate::error::map_node_error::{MapNodeError, MapNodeErrorType, MapNodeResult};
+use crate::error::PilotResult;
+use crate::workflow::context::RunContext;
+use crate::workflow::Workflow;
+
+use serde_json::Value;
+use tokio::sync::Semaphore;
+use tokio::task::JoinSet;
+
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+
+#[derive(Debug, Clone)]
+/// The struct of the map node.
+pub struct MapNode {
+    /// The subworkflow run on every item.
+    workflow: Workflow,
+    /// The max number of items running at the same time.
+    max_parallelism: usize,
+}
+
+impl MapNode {
+    /// Create a new MapNode that runs the subworkflow on every item.
+    pub fn new(workflow: Workflow) -> Self {
+        MapNode {
+            workflow,
+            max_parallelism: Self::default_max_parallelism(),
+        }
+    }
+    /// Run the subworkflow on every item of the input, and get the json array of the outputs.
+    pub async fn execute(&self, input: String, context: &RunContext) -> MapNodeResult<String> {
+        let items = match serde_json::from_str(&input) {
+            Ok(Value::Array(items)) => items,
+            _ => {
+                return Err(MapNodeError::new(
+                    MapNodeErrorType::InvalidInput,
+                    "The input of the map node is not a json array.".to_string(),
+                ))
+            }
+        };
+        let mut outputs = vec![Value::Null; items.len()];
+        let permits = Arc::new(Semaphore::new(self.max_parallelism.max(1)));
+        // dropping the tasks, when an item fails or the node is stopped, aborts the other runs
+        let mut tasks = JoinSet::new();
+        for (index, item) in items.into_iter().enumerate() {
+            let workflow = self.workflow.clone();
+            let context = context.clone();
+            let permits = permits.clone();
+            tasks.spawn(async move {
+                // the semaphore is never closed
+                let _permit = permits.acquire_owned().await;
+                let input = match item {
+                    Value::String(text) => text,
+                    item => item.to_string(),
+                };
+                (index, run_item(workflow, input, context).await)
+            });
+        }
+        while let Some(finished) = tasks.join_next().await {
+            let (index, result) = match finished {
+                Ok(finished) => finished,
+                Err(e) => std::panic::resume_unwind(e.into_panic()),
+            };
+            let output = result.map_err(|e| {
+                MapNodeError::new(
+                    MapNodeErrorType::ItemFailed(Box::new(e)),
+                    format!("The subworkflow failed on the item {}.", index),
+                )
+            })?;
+            outputs[index] = serde_json::from_str(&output).unwrap_or(Value::String(output));
+        }
+        Ok(Value::Array(outputs).to_string())
+    }
+    /// Set the subworkflow as builder.
+    pub fn workflow(mut self, workflow: Workflow) -> Self {
+        self.workflow = workflow;
+        self
+    }
+    /// Set the subworkflow.
+    pub fn set_workflow(&mut self, workflow: Workflow) {
+        self.workflow = workflow;
+    }
+    /// Get the subworkflow.
+    pub fn get_workflow(&self) -> &Workflow {
+        &self.workflow
+    }
+    /// Get the mutable subworkflow.
+    pub fn get_workflow_mut(&mut self) -> &mut Workflow {
+        &mut self.workflow
+    }
+    /// Set the max number of items running at the same time as builder.
+    pub fn max_parallelism(mut self, max_parallelism: usize) -> Self {
+        self.max_parallelism = max_parallelism;
+        self
+    }
+    /// Set the max number of items running at the same time.
+    pub fn set_max_parallelism(&mut self, max_parallelism: usize) {
+        self.max_parallelism = max_parallelism;
+    }
+    /// Get the max number of items running at the same time.
+    pub fn get_max_parallelism(&self) -> usize {
+        self.max_parallelism
+    }
+    /// The default max number of items running at the same time.
+    pub fn default_max_parallelism() -> usize {
+        4
+    }
+}
+
+/// Run the subworkflow on an item. The future is boxed, since the run of a workflow may hold
+/// a map node itself.
+fn run_item(
+    mut workflow: Workflow,
+    input: String,
+    context: RunContext,
+) -> Pin<Box<dyn Future<Output = PilotResult<String>> + Send>> {
+    Box::pin(async move { workflow.run_with_context(input, &context).await })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::worknode::transform::JsonTransformNode;
+    use crate::worknode::{Worknode, Worknodecore};
+
+    use tokio::runtime::Runtime;
+
+    #[test]
+    fn map_items() {
+        let mut workflow = Workflow::new();
+        let start = workflow.add_node(Worknode::new(Worknodecore::Start));
+        let double = workflow.add_node(Worknode::new(Worknodecore::JsonTransform(
+            JsonTransformNode::filter("{n: (.n * 2)}"),
+        )));
+        let end = workflow.add_node(Worknode::new(Worknodecore::End));
+        workflow.add_edge(start, double).unwrap();
+        workflow.add_edge(double, end).unwrap();
+        let map = MapNode::new(workflow).max_parallelism(2);
+        let context = RunContext::new();
+        let rt = Runtime::new().unwrap();
+
+        let input = r#"[{"n": 1}, {"n": 2}, {"n": 3}, {"n": 4}, {"n": 5}]"#;
+        let output = rt
+            .block_on(map.execute(input.to_string(), &context))
+            .unwrap();
+        assert_eq!(output, r#"[{"n":2},{"n":4},{"n":6},{"n":8},{"n":10}]"#);
+        assert_eq!(
+            rt.block_on(map.execute("[]".to_string(), &context))
+                .unwrap(),
+            "[]"
+        );
+
+        let error = rt
+            .block_on(map.execute(r#"[{"n": 1}, {"n": "x"}]"#.to_string(), &context))
+            .unwrap_err();
+        assert!(matches!(
+            error.get_error_type(),
+            MapNodeErrorType::ItemFailed(_)
+        ));
+        assert!(error.get_message().contains("item 1"));
+        let error = rt
+            .block_on(map.execute("{}".to_string(), &context))
+            .unwrap_err();
+        assert!(matches!(
+            error.get_error_type(),
+            MapNodeErrorType::InvalidInput
+        ));
+    }
+}
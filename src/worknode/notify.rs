@@ -0,0 +1,369 @@
+//! # Notify
+//!
+//! This node sends a message to the channels it is configured with, and passes its input on
+//! unchanged. It tells a human or another system that a run has reached a point, like the end
+//! of a long job or a failure caught by an on_error edge.
+//!
+//! The channels are:
+//! - webhook: a POST of the json `{"message", "input", "context"}` to the url, with the
+//!   headers, where the context is the snapshot of the run context.
+//! - slack: a POST of `{"text"}` to the incoming webhook of a Slack channel.
+//! - discord: a POST of `{"content"}` to the webhook of a Discord channel, cut to the 2000
+//!   characters that Discord accepts.
+//! - email: an email sent through an SMTP server (see [`smtp`]).
+//!
+//! The message, and the subject of an email, are templates (see [`crate::template`])
+//! rendered with the variables of the run context and the variable `input`.
+//!
+//! The message is sent to every channel, even when some of them fail, and the node fails
+//! with the list of the channels that failed, so the others are not missed because of one.
+
+pub mod smtp;
+
+use crate::error::notify_node_error::{NotifyNodeError, NotifyNodeErrorType, NotifyNodeResult};
+use crate::template;
+use crate::workflow::context::RunContext;
+use smtp::EmailChannel;
+
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+/// The time to wait for a webhook to answer.
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// The most characters Discord accepts in a message.
+const DISCORD_LIMIT: usize = 2000;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "channel", rename_all = "snake_case")]
+/// The enum of a channel the message is sent to.
+pub enum Channel {
+    /// A POST of the message, the input and the run context as json.
+    Webhook {
+        /// The url of the webhook.
+        url: String,
+        /// The headers of the request, like the authorization.
+        #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+        headers: BTreeMap<String, String>,
+    },
+    /// The incoming webhook of a Slack channel.
+    Slack {
+        /// The url of the webhook.
+        url: String,
+    },
+    /// The webhook of a Discord channel.
+    Discord {
+        /// The url of the webhook.
+        url: String,
+    },
+    /// An email sent through an SMTP server.
+    Email(EmailChannel),
+}
+
+impl Channel {
+    /// Get the name of the channel.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Channel::Webhook { .. } => "webhook",
+            Channel::Slack { .. } => "slack",
+            Channel::Discord { .. } => "discord",
+            Channel::Email(_) => "email",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+/// The struct of the notify node.
+pub struct NotifyNode {
+    /// The channels the message is sent to.
+    channels: Vec<Channel>,
+    /// The template of the message.
+    #[serde(default = "NotifyNode::default_message")]
+    message: String,
+}
+
+impl Default for NotifyNode {
+    fn default() -> Self {
+        NotifyNode {
+            channels: Vec::new(),
+            message: Self::default_message(),
+        }
+    }
+}
+
+impl NotifyNode {
+    /// Create a new NotifyNode that sends the input to the channels.
+    pub fn new(channels: Vec<Channel>) -> Self {
+        NotifyNode {
+            channels,
+            ..Self::default()
+        }
+    }
+    /// The default template of the message, the input of the node.
+    pub fn default_message() -> String {
+        "{{input}}".to_string()
+    }
+    /// Send the message to all the channels, and pass the input on.
+    pub async fn execute(&self, input: String, context: &RunContext) -> NotifyNodeResult<String> {
+        let mut variables = context.to_variables();
+        variables.insert("input".to_string(), input.clone());
+        let render = |template: &str| {
+            template::render(template, &variables).map_err(|e| {
+                NotifyNodeError::new(
+                    NotifyNodeErrorType::TemplateError(e),
+                    "Failed to render the template of the notification.".to_string(),
+                )
+            })
+        };
+        let message = render(&self.message)?;
+        // the client only fails to build without a tls backend
+        let client = reqwest::Client::builder()
+            .timeout(WEBHOOK_TIMEOUT)
+            .build()
+            .unwrap();
+        let mut failures = Vec::new();
+        for channel in &self.channels {
+            let result = match channel {
+                Channel::Webhook { url, headers } => {
+                    let body = json!({
+                        "message": message,
+                        "input": input,
+                        "context": context.snapshot(),
+                    });
+                    post(&client, url, headers, body).await
+                }
+                Channel::Slack { url } => {
+                    post(&client, url, &BTreeMap::new(), json!({ "text": message })).await
+                }
+                Channel::Discord { url } => {
+                    let content: String = message.chars().take(DISCORD_LIMIT).collect();
+                    post(
+                        &client,
+                        url,
+                        &BTreeMap::new(),
+                        json!({ "content": content }),
+                    )
+                    .await
+                }
+                Channel::Email(email) => email.send(&render(&email.subject)?, &message).await,
+            };
+            if let Err(e) = result {
+                log::warn!("Failed to notify the {} channel. {}", channel.name(), e);
+                failures.push(format!("{}: {}", channel.name(), e));
+            }
+        }
+        if failures.is_empty() {
+            Ok(input)
+        } else {
+            Err(NotifyNodeError::new(
+                NotifyNodeErrorType::SendError,
+                format!(
+                    "Failed to notify {} of {} channels.\n{}",
+                    failures.len(),
+                    self.channels.len(),
+                    failures.join("\n")
+                ),
+            ))
+        }
+    }
+    /// Add a channel as builder.
+    pub fn channel(mut self, channel: Channel) -> Self {
+        self.channels.push(channel);
+        self
+    }
+    /// Add a channel.
+    pub fn add_channel(&mut self, channel: Channel) {
+        self.channels.push(channel);
+    }
+    /// Set the channels.
+    pub fn set_channels(&mut self, channels: Vec<Channel>) {
+        self.channels = channels;
+    }
+    /// Get the channels.
+    pub fn get_channels(&self) -> &Vec<Channel> {
+        &self.channels
+    }
+    /// Set the template of the message as builder.
+    pub fn message(mut self, message: &str) -> Self {
+        self.message = message.to_string();
+        self
+    }
+    /// Set the template of the message.
+    pub fn set_message(&mut self, message: &str) {
+        self.message = message.to_string();
+    }
+    /// Get the template of the message.
+    pub fn get_message(&self) -> &str {
+        &self.message
+    }
+}
+
+/// Post the json to the url. The error is a message of what failed.
+pub(crate) async fn post(
+    client: &reqwest::Client,
+    url: &str,
+    headers: &BTreeMap<String, String>,
+    body: serde_json::Value,
+) -> Result<(), String> {
+    let mut request = client
+        .post(url)
+        .header("Content-Type", "application/json")
+        .body(body.to_string());
+    for (name, value) in headers {
+        request = request.header(name, value);
+    }
+    let response = request
+        .send()
+        .await
+        .map_err(|e| format!("Failed to send the request to {}. {}", url, e))?;
+    let status = response.status();
+    if status.is_success() {
+        Ok(())
+    } else {
+        let text = response.text().await.unwrap_or_default();
+        Err(format!("{} answers {}. {}", url, status, text))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use smtp::SmtpSecurity;
+
+    use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+    use tokio::net::TcpListener;
+    use tokio::runtime::Runtime;
+
+    /// Answer the http requests with 200, and get their bodies.
+    async fn http_server(listener: TcpListener, requests: usize) -> Vec<serde_json::Value> {
+        let mut bodies = Vec::new();
+        for _ in 0..requests {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut stream = BufReader::new(stream);
+            let mut length = 0;
+            loop {
+                let mut line = String::new();
+                stream.read_line(&mut line).await.unwrap();
+                if line == "\r\n" {
+                    break;
+                }
+                if let Some(value) = line.to_lowercase().strip_prefix("content-length:") {
+                    length = value.trim().parse().unwrap();
+                }
+            }
+            let mut body = vec![0; length];
+            stream.read_exact(&mut body).await.unwrap();
+            bodies.push(serde_json::from_slice(&body).unwrap());
+            stream
+                .get_mut()
+                .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\nConnection: close\r\n\r\n")
+                .await
+                .unwrap();
+        }
+        bodies
+    }
+
+    /// Answer an SMTP session, and get the commands and the data.
+    async fn smtp_server(listener: TcpListener) -> Vec<String> {
+        let (stream, _) = listener.accept().await.unwrap();
+        let mut stream = BufReader::new(stream);
+        let mut lines = Vec::new();
+        stream.get_mut().write_all(b"220 ready\r\n").await.unwrap();
+        let mut data = false;
+        loop {
+            let mut line = String::new();
+            if stream.read_line(&mut line).await.unwrap() == 0 {
+                break;
+            }
+            let line = line.trim_end().to_string();
+            let reply: &[u8] = if data {
+                data = line != ".";
+                if data {
+                    lines.push(line);
+                    continue;
+                }
+                b"250 queued\r\n"
+            } else if line.starts_with("EHLO") {
+                b"250-localhost\r\n250 AUTH PLAIN\r\n"
+            } else if line == "DATA" {
+                data = true;
+                b"354 go on\r\n"
+            } else if line == "QUIT" {
+                b"221 bye\r\n"
+            } else if line.starts_with("AUTH") {
+                b"235 ok\r\n"
+            } else {
+                b"250 ok\r\n"
+            };
+            lines.push(line);
+            stream.get_mut().write_all(reply).await.unwrap();
+        }
+        lines
+    }
+
+    #[test]
+    fn notify_channels() {
+        let rt = Runtime::new().unwrap();
+        let context = RunContext::new();
+        context.set("job", "report").unwrap();
+        let listener = rt.block_on(TcpListener::bind("127.0.0.1:0")).unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let server = rt.spawn(http_server(listener, 2));
+        let node = NotifyNode::default()
+            .message("{{context.job}} done: {{input}}")
+            .channel(Channel::Webhook {
+                url: url.clone(),
+                headers: BTreeMap::from([("X-Token".to_string(), "t".to_string())]),
+            })
+            .channel(Channel::Slack { url });
+        let output = rt
+            .block_on(node.execute("ok".to_string(), &context))
+            .unwrap();
+        assert_eq!(output, "ok");
+        let bodies = rt.block_on(server).unwrap();
+        assert_eq!(bodies[0]["message"], "report done: ok");
+        assert_eq!(bodies[0]["context"]["job"], "report");
+        assert_eq!(bodies[1], json!({"text": "report done: ok"}));
+
+        // a channel that fails doesn't stop the others
+        let listener = rt.block_on(TcpListener::bind("127.0.0.1:0")).unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = rt.spawn(smtp_server(listener));
+        std::env::set_var("AIPILOT_TEST_SMTP_PASSWORD", "secret");
+        let mut email = EmailChannel::new(
+            "127.0.0.1",
+            "pilot@example.com",
+            vec!["a@example.com".to_string(), "b@example.com".to_string()],
+        );
+        email.port = port;
+        email.security = SmtpSecurity::None;
+        email.username = Some("pilot".to_string());
+        email.password_env = Some("AIPILOT_TEST_SMTP_PASSWORD".to_string());
+        email.subject = "Report {{context.job}}".to_string();
+        let node = NotifyNode::new(vec![
+            Channel::Discord {
+                url: "http://127.0.0.1:1".to_string(),
+            },
+            Channel::Email(email),
+        ]);
+        let error = rt
+            .block_on(node.execute("ok".to_string(), &context))
+            .unwrap_err();
+        assert!(matches!(
+            error.get_error_type(),
+            NotifyNodeErrorType::SendError
+        ));
+        assert!(error.get_message().contains("1 of 2"));
+        assert!(error.get_message().contains("discord"));
+        let lines = rt.block_on(server).unwrap();
+        assert_eq!(lines[1], "AUTH PLAIN AHBpbG90AHNlY3JldA==");
+        assert_eq!(lines[2], "MAIL FROM:<pilot@example.com>");
+        assert_eq!(lines[4], "RCPT TO:<b@example.com>");
+        assert!(lines.contains(&"Subject: Report report".to_string()));
+        assert!(lines.contains(&"b2s=".to_string()));
+        assert_eq!(lines.last().unwrap(), "QUIT");
+    }
+}
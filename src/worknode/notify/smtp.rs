@@ -0,0 +1,227 @@
+//! # SMTP
+//!
+//! This module sends the emails of the notify node with a small SMTP client.
+//!
+//! The connection is upgraded with STARTTLS (usually port 587), encrypted from the start
+//! (usually port 465), or left plain for a relay on a trusted network. With a username, the
+//! client logs in with AUTH PLAIN, taking the password from an environment variable, so it is
+//! never written to a workflow file. The body is sent as UTF-8 text in base64.
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+
+use std::time::Duration;
+
+/// The time to wait for the server at every step.
+const SMTP_TIMEOUT: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+/// The enum of how the connection to the SMTP server is encrypted.
+pub enum SmtpSecurity {
+    /// Upgrade the plain connection with STARTTLS.
+    #[default]
+    StartTls,
+    /// Encrypt the connection from the start.
+    Tls,
+    /// Don't encrypt the connection.
+    None,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+/// The struct of the email channel of a notify node.
+pub struct EmailChannel {
+    /// The host of the SMTP server.
+    pub host: String,
+    /// The port of the SMTP server.
+    #[serde(default = "EmailChannel::default_port")]
+    pub port: u16,
+    /// How the connection is encrypted.
+    #[serde(default)]
+    pub security: SmtpSecurity,
+    /// The username to log in with, or no login.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub username: Option<String>,
+    /// The environment variable that holds the password.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub password_env: Option<String>,
+    /// The address the email is sent from.
+    pub from: String,
+    /// The addresses the email is sent to.
+    pub to: Vec<String>,
+    /// The template of the subject.
+    #[serde(default = "EmailChannel::default_subject")]
+    pub subject: String,
+}
+
+/// A connection to the SMTP server, plain or encrypted.
+trait Connection: AsyncRead + AsyncWrite + Unpin + Send {}
+
+impl<T: AsyncRead + AsyncWrite + Unpin + Send> Connection for T {}
+
+impl EmailChannel {
+    /// Create a new EmailChannel that sends from the address to the addresses through the
+    /// SMTP server, with STARTTLS on the default port.
+    pub fn new(host: &str, from: &str, to: Vec<String>) -> Self {
+        EmailChannel {
+            host: host.to_string(),
+            port: Self::default_port(),
+            security: SmtpSecurity::default(),
+            username: None,
+            password_env: None,
+            from: from.to_string(),
+            to,
+            subject: Self::default_subject(),
+        }
+    }
+    /// The default port, the submission port of STARTTLS.
+    pub fn default_port() -> u16 {
+        587
+    }
+    /// The default template of the subject.
+    pub fn default_subject() -> String {
+        "AIPilot notification".to_string()
+    }
+    /// Send the email with the subject and the body. The error is a message of what failed.
+    pub async fn send(&self, subject: &str, body: &str) -> Result<(), String> {
+        tokio::time::timeout(SMTP_TIMEOUT * 4, self.session(subject, body))
+            .await
+            .unwrap_or_else(|_| Err("The SMTP server doesn't answer in time.".to_string()))
+    }
+    /// Run the SMTP session that sends the email.
+    async fn session(&self, subject: &str, body: &str) -> Result<(), String> {
+        let tcp = TcpStream::connect((self.host.as_str(), self.port))
+            .await
+            .map_err(|e| format!("Can't connect to {}:{}. {}", self.host, self.port, e))?;
+        let mut stream: BufReader<Box<dyn Connection>> = match self.security {
+            SmtpSecurity::Tls => BufReader::new(Box::new(self.encrypt(tcp).await?)),
+            _ => BufReader::new(Box::new(tcp)),
+        };
+        expect(&mut stream, 220).await?;
+        command(&mut stream, "EHLO aipilot", 250).await?;
+        if self.security == SmtpSecurity::StartTls {
+            command(&mut stream, "STARTTLS", 220).await?;
+            // the server says nothing more before the handshake, so the buffer is empty
+            let tcp = stream.into_inner();
+            stream = BufReader::new(Box::new(self.encrypt(tcp).await?));
+            command(&mut stream, "EHLO aipilot", 250).await?;
+        }
+        if let Some(username) = &self.username {
+            let password = match &self.password_env {
+                Some(name) => std::env::var(name).map_err(|_| {
+                    format!(
+                        "The environment variable {} of the password is not set.",
+                        name
+                    )
+                })?,
+                None => String::new(),
+            };
+            let credentials = STANDARD.encode(format!("\0{}\0{}", username, password));
+            command(&mut stream, &format!("AUTH PLAIN {}", credentials), 235).await?;
+        }
+        command(&mut stream, &format!("MAIL FROM:<{}>", self.from), 250).await?;
+        for to in &self.to {
+            command(&mut stream, &format!("RCPT TO:<{}>", to), 250).await?;
+        }
+        command(&mut stream, "DATA", 354).await?;
+        command(&mut stream, &self.message(subject, body), 250).await?;
+        // the email is sent, so a failure to quit doesn't matter
+        let _ = command(&mut stream, "QUIT", 221).await;
+        Ok(())
+    }
+    /// Encrypt the connection with TLS.
+    async fn encrypt<S>(&self, stream: S) -> Result<tokio_native_tls::TlsStream<S>, String>
+    where
+        S: AsyncRead + AsyncWrite + Unpin,
+    {
+        let connector =
+            native_tls::TlsConnector::new().map_err(|e| format!("Can't set up TLS. {}", e))?;
+        tokio_native_tls::TlsConnector::from(connector)
+            .connect(&self.host, stream)
+            .await
+            .map_err(|e| format!("Can't encrypt the connection to {}. {}", self.host, e))
+    }
+    /// Build the message of the email, ending with the line of a single dot.
+    fn message(&self, subject: &str, body: &str) -> String {
+        let body = STANDARD.encode(body);
+        let lines: Vec<&str> = body
+            .as_bytes()
+            .chunks(76)
+            // base64 is ascii, so every chunk is valid utf-8
+            .map(|chunk| std::str::from_utf8(chunk).unwrap())
+            .collect();
+        format!(
+            "From: <{}>\r\nTo: {}\r\nSubject: {}\r\nDate: {}\r\nMIME-Version: 1.0\r\n\
+             Content-Type: text/plain; charset=utf-8\r\nContent-Transfer-Encoding: base64\r\n\
+             \r\n{}\r\n.",
+            self.from,
+            self.to
+                .iter()
+                .map(|to| format!("<{}>", to))
+                .collect::<Vec<String>>()
+                .join(", "),
+            encode_header(subject),
+            chrono::Utc::now().to_rfc2822(),
+            lines.join("\r\n"),
+        )
+    }
+}
+
+/// Encode a header with non-ascii characters as an encoded word.
+fn encode_header(text: &str) -> String {
+    // a line break would start a new header
+    let text = text.replace(['\r', '\n'], " ");
+    if text.is_ascii() {
+        text
+    } else {
+        format!("=?UTF-8?B?{}?=", STANDARD.encode(text))
+    }
+}
+
+/// Send a command and check the code of the reply.
+async fn command(
+    stream: &mut BufReader<Box<dyn Connection>>,
+    line: &str,
+    code: u16,
+) -> Result<(), String> {
+    let sent = tokio::time::timeout(SMTP_TIMEOUT, async {
+        stream.get_mut().write_all(line.as_bytes()).await?;
+        stream.get_mut().write_all(b"\r\n").await?;
+        stream.get_mut().flush().await
+    })
+    .await;
+    match sent {
+        Ok(Ok(())) => expect(stream, code).await,
+        Ok(Err(e)) => Err(format!("Can't write to the SMTP server. {}", e)),
+        Err(_) => Err("The SMTP server doesn't answer in time.".to_string()),
+    }
+}
+
+/// Read a reply, which may have several lines, and check its code.
+async fn expect(stream: &mut BufReader<Box<dyn Connection>>, code: u16) -> Result<(), String> {
+    let mut reply = String::new();
+    loop {
+        let mut line = String::new();
+        match tokio::time::timeout(SMTP_TIMEOUT, stream.read_line(&mut line)).await {
+            Ok(Ok(0)) => return Err("The SMTP server closed the connection.".to_string()),
+            Ok(Ok(_)) => {}
+            Ok(Err(e)) => return Err(format!("Can't read from the SMTP server. {}", e)),
+            Err(_) => return Err("The SMTP server doesn't answer in time.".to_string()),
+        }
+        reply.push_str(&line);
+        // the last line of a reply has a space after the code, the others a dash
+        if line.as_bytes().get(3) != Some(&b'-') {
+            break;
+        }
+    }
+    match reply.get(..3).and_then(|code| code.parse::<u16>().ok()) {
+        Some(received) if received == code => Ok(()),
+        _ => Err(format!(
+            "The SMTP server answers `{}`, not {}.",
+            reply.trim(),
+            code
+        )),
+    }
+}
@@ -0,0 +1,469 @@
+//! # Planner
+//!
+//! This node asks an AI service to break a goal down into an ordered list of steps, then runs
+//! the steps as a workflow that is built for the plan.
+//!
+//! The plan is asked as json that must match a schema (see [`PlannerNode::plan_schema`]).
+//! Every step has an id and a kind:
+//! - `ai`: the prompt of the step is given to a fork of the AI node of the planner, without
+//!   its history and its tools.
+//! - `local`: the shell command of the step runs as a local node in the sandbox of the
+//!   planner. The local steps are only planned when the planner has a sandbox.
+//! - `tool`: a tool of the planner is called with the arguments of the step, as a tool node.
+//!
+//! The plan is checked before it runs: the ids are unique, the tools exist and don't need the
+//! approval of a human, and there are no more steps than allowed. The workflow of the plan chains the steps in order: the input of a
+//! step is the output of the previous one, the goal for the first one, and the output of the
+//! last step is the output of the node. The output of every step is also put in the run
+//! context under `plan.<id>`, and the goal under `plan.goal`, so the prompts, the commands and
+//! the arguments of the steps can refer to any earlier result, like `{{context.plan.s1}}`.
+
+use super::ai_node::{AINode, ToolPolicy, ToolRegistry};
+use super::local::sandbox::Sandbox;
+use super::local::LocalNode;
+use super::tool::ToolNode;
+use super::{Worknode, Worknodecore};
+use crate::error::planner_node_error::{PlannerNodeError, PlannerNodeErrorType, PlannerNodeResult};
+use crate::error::PilotResult;
+use crate::workflow::context::RunContext;
+use crate::workflow::Workflow;
+
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use std::collections::HashSet;
+use std::future::Future;
+use std::pin::Pin;
+use std::time::Duration;
+
+/// The instruction that asks the AI service for a plan.
+const PLAN_PROMPT: &str = "Break the goal below down into an ordered list of steps that reach \
+it when they run one after the other. The input of a step is the result of the previous step, \
+or the goal for the first step, and the result of the last step is the final answer. A step \
+can also use the result of any earlier step by writing {{context.plan.<id>}}, where <id> is \
+the id of that step.";
+
+/// The context key that the goal and the outputs of the steps are put under.
+const CONTEXT_PREFIX: &str = "plan.";
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+/// The struct of the plan of a goal.
+pub struct Plan {
+    /// The steps, in the order they run.
+    pub steps: Vec<PlanStep>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+/// The struct of a step of a plan.
+pub struct PlanStep {
+    /// The id of the step, unique in the plan.
+    pub id: String,
+    /// What the step does.
+    #[serde(flatten)]
+    pub action: StepAction,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+/// The enum of what a step of a plan does.
+pub enum StepAction {
+    /// Ask the AI service.
+    Ai {
+        /// The instruction of the step.
+        prompt: String,
+    },
+    /// Run a shell command.
+    Local {
+        /// The shell command.
+        command: String,
+    },
+    /// Call a tool.
+    Tool {
+        /// The name of the tool.
+        tool: String,
+        /// The arguments of the call.
+        #[serde(default)]
+        arguments: Value,
+    },
+}
+
+#[derive(Debug, Clone)]
+/// The struct of the planner node.
+pub struct PlannerNode {
+    /// The AI node that plans and runs the ai steps, with the tools of the tool steps.
+    node: AINode,
+    /// The sandbox of the local steps, or `None` to not plan local steps.
+    sandbox: Option<Sandbox>,
+    /// The time a local step may take before it is killed.
+    local_timeout: Duration,
+    /// The max number of steps of a plan.
+    max_steps: usize,
+    /// The plan of the last execution.
+    last_plan: Option<Plan>,
+}
+
+impl PlannerNode {
+    /// Create a new PlannerNode with the AI node and the tools the steps can call.
+    pub fn new(node: AINode, tools: ToolRegistry) -> Self {
+        PlannerNode {
+            node: node.tools(tools),
+            sandbox: None,
+            local_timeout: Duration::from_secs(60),
+            max_steps: Self::default_max_steps(),
+            last_plan: None,
+        }
+    }
+    /// Plan the goal of the input, run the plan and get the output of its last step.
+    pub async fn execute(
+        &mut self,
+        input: String,
+        context: &RunContext,
+    ) -> PlannerNodeResult<String> {
+        self.last_plan = None;
+        let plan = self.plan(&input, context).await?;
+        self.last_plan = Some(plan.clone());
+        self.run_plan(&plan, input, context).await
+    }
+    /// Ask the AI service for the plan of the goal, and check it.
+    pub async fn plan(&self, goal: &str, context: &RunContext) -> PlannerNodeResult<Plan> {
+        let mut planner = self.fork()?;
+        planner.set_context_variables(context);
+        planner.set_output_schema(Some(self.plan_schema()));
+        let plan: Plan = planner
+            .execute_typed(self.plan_prompt(goal))
+            .await
+            .map_err(|e| {
+                PlannerNodeError::new(
+                    PlannerNodeErrorType::PlanError(Box::new(e)),
+                    "Failed to plan the goal.".to_string(),
+                )
+            })?;
+        self.check(&plan)?;
+        Ok(plan)
+    }
+    /// Check the plan, build its workflow and run it with the goal.
+    pub async fn run_plan(
+        &self,
+        plan: &Plan,
+        goal: String,
+        context: &RunContext,
+    ) -> PlannerNodeResult<String> {
+        let workflow = self.materialize(plan)?;
+        context.set_value(
+            &format!("{}goal", CONTEXT_PREFIX),
+            Value::String(goal.clone()),
+        );
+        run_workflow(workflow, goal, context.clone())
+            .await
+            .map_err(|e| {
+                PlannerNodeError::new(
+                    PlannerNodeErrorType::StepFailed(Box::new(e)),
+                    "A step of the plan failed.".to_string(),
+                )
+            })
+    }
+    /// Check the plan and build its workflow: the start node, the steps in order and the end
+    /// node.
+    pub fn materialize(&self, plan: &Plan) -> PlannerNodeResult<Workflow> {
+        self.check(plan)?;
+        let mut workflow = Workflow::new();
+        let mut previous = workflow.add_node(Worknode::new(Worknodecore::Start));
+        for step in &plan.steps {
+            let core = match &step.action {
+                StepAction::Ai { prompt } => {
+                    let mut node = self.fork()?;
+                    node.set_prompt_prefix(format!(
+                        "You are doing one step of a plan for the goal: {{{{context.plan.goal}}}}\n\nStep: {}\n\nInput of the step:",
+                        prompt
+                    ));
+                    node.set_prompt_suffix(String::new());
+                    Worknodecore::AINode(node)
+                }
+                StepAction::Local { command } => Worknodecore::Local(
+                    LocalNode::shell(command)
+                        .timeout(Some(self.local_timeout))
+                        // the check makes sure that there is a sandbox
+                        .sandbox(self.sandbox.clone().unwrap_or_default()),
+                ),
+                StepAction::Tool { tool, arguments } => Worknodecore::Tool(
+                    ToolNode::new(self.node.get_tools().clone(), tool)
+                        .arguments(Some(arguments.clone())),
+                ),
+            };
+            let node = workflow.add_node(
+                Worknode::new(core).context_key(Some(format!("{}{}", CONTEXT_PREFIX, step.id))),
+            );
+            // the nodes were just added, so the edges are valid
+            workflow.add_edge(previous, node).unwrap();
+            previous = node;
+        }
+        let end = workflow.add_node(Worknode::new(Worknodecore::End));
+        workflow.add_edge(previous, end).unwrap();
+        Ok(workflow)
+    }
+    /// Check that the plan can run.
+    fn check(&self, plan: &Plan) -> PlannerNodeResult<()> {
+        let invalid = |message: String| {
+            Err(PlannerNodeError::new(
+                PlannerNodeErrorType::InvalidPlan,
+                message,
+            ))
+        };
+        if plan.steps.is_empty() {
+            return invalid("The plan has no step.".to_string());
+        }
+        if plan.steps.len() > self.max_steps {
+            return invalid(format!(
+                "The plan has {} steps, more than the {} allowed.",
+                plan.steps.len(),
+                self.max_steps
+            ));
+        }
+        let mut ids = HashSet::new();
+        for step in &plan.steps {
+            if step.id == "goal" || !ids.insert(step.id.as_str()) {
+                return invalid(format!("The id {} of a step is not unique.", step.id));
+            }
+            match &step.action {
+                StepAction::Local { .. } if self.sandbox.is_none() => {
+                    return invalid(format!(
+                        "The step {} runs a command, which is not allowed.",
+                        step.id
+                    ));
+                }
+                StepAction::Tool { tool, .. }
+                    if self.node.get_tools().get_policy(tool) == Some(ToolPolicy::Confirm) =>
+                {
+                    return invalid(format!(
+                        "The step {} calls the tool {}, which needs the approval of a human.",
+                        step.id, tool
+                    ));
+                }
+                StepAction::Tool { tool, .. } if self.node.get_tools().get(tool).is_none() => {
+                    return invalid(format!(
+                        "The step {} calls the unknown tool {}.",
+                        step.id, tool
+                    ));
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+    /// Get the json schema of the plans that the planner can run.
+    pub fn plan_schema(&self) -> Value {
+        let mut kinds = vec![json!({
+            "type": "object",
+            "properties": {
+                "id": { "type": "string" },
+                "kind": { "const": "ai" },
+                "prompt": { "type": "string" }
+            },
+            "required": ["id", "kind", "prompt"]
+        })];
+        if self.sandbox.is_some() {
+            kinds.push(json!({
+                "type": "object",
+                "properties": {
+                    "id": { "type": "string" },
+                    "kind": { "const": "local" },
+                    "command": { "type": "string" }
+                },
+                "required": ["id", "kind", "command"]
+            }));
+        }
+        let tools = self.node.get_tools().names();
+        if !tools.is_empty() {
+            kinds.push(json!({
+                "type": "object",
+                "properties": {
+                    "id": { "type": "string" },
+                    "kind": { "const": "tool" },
+                    "tool": { "enum": tools },
+                    "arguments": { "type": "object" }
+                },
+                "required": ["id", "kind", "tool", "arguments"]
+            }));
+        }
+        json!({
+            "type": "object",
+            "properties": {
+                "steps": {
+                    "type": "array",
+                    "minItems": 1,
+                    "maxItems": self.max_steps,
+                    "items": { "oneOf": kinds }
+                }
+            },
+            "required": ["steps"]
+        })
+    }
+    /// Get the prompt that asks for the plan of the goal.
+    fn plan_prompt(&self, goal: &str) -> String {
+        let mut kinds = vec![
+            "- ai: {\"id\", \"kind\": \"ai\", \"prompt\"}, the prompt is given to an AI assistant with the input of the step.".to_string(),
+        ];
+        if self.sandbox.is_some() {
+            kinds.push("- local: {\"id\", \"kind\": \"local\", \"command\"}, the command is run by sh with the input of the step on its stdin, and its stdout is the result.".to_string());
+        }
+        let tools = self.node.get_tools();
+        if !tools.is_empty() {
+            kinds.push("- tool: {\"id\", \"kind\": \"tool\", \"tool\", \"arguments\"}, the tool is called with the json arguments, and {{input}} in a string argument is the input of the step. The tools are:".to_string());
+            for name in tools.names() {
+                // the names come from the registry
+                let tool = tools.get(&name).unwrap();
+                kinds.push(format!(
+                    "  - {}: {} The parameters: {}",
+                    name,
+                    tool.get_description(),
+                    tool.get_parameters()
+                ));
+            }
+        }
+        format!(
+            "{}\n\nThe kinds of step are:\n{}\n\nUse at most {} steps, and answer with {{\"steps\": [...]}}.\n\nGoal: {}",
+            PLAN_PROMPT,
+            kinds.join("\n"),
+            self.max_steps,
+            goal
+        )
+    }
+    /// Fork the AI node without its history and its tools, keeping its role.
+    fn fork(&self) -> PlannerNodeResult<AINode> {
+        let role = usize::from(self.node.get_role().is_some());
+        let mut node = self.node.fork_at(role).map_err(|e| {
+            PlannerNodeError::new(
+                PlannerNodeErrorType::PlanError(Box::new(e)),
+                "Failed to fork the AI node.".to_string(),
+            )
+        })?;
+        node.set_tools(ToolRegistry::new());
+        Ok(node)
+    }
+    /// Get the plan of the last execution.
+    pub fn get_last_plan(&self) -> Option<&Plan> {
+        self.last_plan.as_ref()
+    }
+    /// Get the AI node of the planner.
+    pub fn get_node(&self) -> &AINode {
+        &self.node
+    }
+    /// Get the mutable AI node of the planner.
+    pub fn get_node_mut(&mut self) -> &mut AINode {
+        &mut self.node
+    }
+    /// Set the sandbox of the local steps as builder.
+    pub fn sandbox(mut self, sandbox: Option<Sandbox>) -> Self {
+        self.sandbox = sandbox;
+        self
+    }
+    /// Set the sandbox of the local steps.
+    pub fn set_sandbox(&mut self, sandbox: Option<Sandbox>) {
+        self.sandbox = sandbox;
+    }
+    /// Get the sandbox of the local steps.
+    pub fn get_sandbox(&self) -> Option<&Sandbox> {
+        self.sandbox.as_ref()
+    }
+    /// Set the time a local step may take as builder.
+    pub fn local_timeout(mut self, local_timeout: Duration) -> Self {
+        self.local_timeout = local_timeout;
+        self
+    }
+    /// Set the time a local step may take.
+    pub fn set_local_timeout(&mut self, local_timeout: Duration) {
+        self.local_timeout = local_timeout;
+    }
+    /// Get the time a local step may take.
+    pub fn get_local_timeout(&self) -> Duration {
+        self.local_timeout
+    }
+    /// Set the max number of steps of a plan as builder.
+    pub fn max_steps(mut self, max_steps: usize) -> Self {
+        self.max_steps = max_steps;
+        self
+    }
+    /// Set the max number of steps of a plan.
+    pub fn set_max_steps(&mut self, max_steps: usize) {
+        self.max_steps = max_steps;
+    }
+    /// Get the max number of steps of a plan.
+    pub fn get_max_steps(&self) -> usize {
+        self.max_steps
+    }
+    /// The default max number of steps of a plan.
+    pub fn default_max_steps() -> usize {
+        8
+    }
+}
+
+/// Run the workflow of a plan. The future is boxed, since the run of a workflow may hold a
+/// planner node itself.
+fn run_workflow(
+    mut workflow: Workflow,
+    input: String,
+    context: RunContext,
+) -> Pin<Box<dyn Future<Output = PilotResult<String>> + Send>> {
+    Box::pin(async move { workflow.run_with_context(input, &context).await })
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::ai_node::deepseek::{DeepSeekClient, DeepSeekModel, DEEPSEEK_API_URL};
+    use super::super::ai_node::tool::calculate;
+    use super::super::ai_node::AIService;
+    use super::*;
+
+    use tokio::runtime::Runtime;
+
+    #[test]
+    fn run_plan() {
+        let client = DeepSeekClient::new(DEEPSEEK_API_URL, DeepSeekModel::DeepseekChat);
+        let mut tools = ToolRegistry::new();
+        calculate::register(&mut tools);
+        let planner = PlannerNode::new(AINode::new(AIService::new_deepseek(client)), tools)
+            .max_steps(3)
+            .sandbox(Some(Sandbox::new()));
+        let plan: Plan = serde_json::from_value(json!({"steps": [
+            {"id": "price", "kind": "tool", "tool": "calculate", "arguments": {"expression": "{{input}} * 3"}},
+            {"id": "total", "kind": "local", "command": "echo \"{{context.plan.goal}} x3 = $(cat)\""}
+        ]}))
+        .unwrap();
+        let rt = Runtime::new().unwrap();
+        let context = RunContext::new();
+        let output = rt.block_on(planner.run_plan(&plan, "14".to_string(), &context));
+        assert_eq!(output.unwrap(), "14 x3 = 42\n");
+        assert_eq!(context.get_value("plan.price"), Some(json!(42)));
+
+        let schema = planner.plan_schema();
+        assert!(jsonschema::is_valid(
+            &schema,
+            &serde_json::to_value(&plan).unwrap()
+        ));
+        let invalid = [
+            json!({"steps": []}),
+            json!({"steps": [{"id": "a", "kind": "tool", "tool": "search", "arguments": {}}]}),
+            json!({"steps": [
+                {"id": "a", "kind": "ai", "prompt": "x"},
+                {"id": "a", "kind": "ai", "prompt": "y"}
+            ]}),
+        ];
+        for plan in invalid {
+            let plan: Plan = serde_json::from_value(plan).unwrap();
+            let error = planner.materialize(&plan).unwrap_err();
+            assert!(matches!(
+                error.get_error_type(),
+                PlannerNodeErrorType::InvalidPlan
+            ));
+        }
+        let local = planner.sandbox(None);
+        assert!(local.materialize(&plan).is_err());
+        assert_eq!(
+            local.plan_schema()["properties"]["steps"]["items"]["oneOf"]
+                .as_array()
+                .unwrap()
+                .len(),
+            2
+        );
+    }
+}
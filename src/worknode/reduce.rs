@@ -0,0 +1,334 @@
+//! # Reduce
+//!
+//! This node merges the outputs of parallel branches, or the items of a map node, into one
+//! output. It is the fan-in side of a parallel workflow, like the join node, with more ways
+//! to merge.
+//!
+//! Like the join node, the workflow gives the reduce node the outputs of its predecessors as a
+//! json array of strings. The outputs are the items to merge, and a single output that is a
+//! json array, like the output of a map node, is merged item by item. A plain text input is
+//! taken as a single item.
+//!
+//! The items are merged with a strategy:
+//! - concat: join the items with a separator.
+//! - json merge: concatenate the items when they are all json arrays, or merge them when they
+//!   are all json objects. The objects are merged deeply, and a later item wins on the other
+//!   keys they share.
+//! - custom: call a named closure (see [`Reducer`]), registered in the `Registry` when the
+//!   workflow is loaded from a file.
+//! - summarize: ask an AI service to summarize the items into one text.
+
+use super::ai_node::{AINode, Chat, RequestOverrides, Role};
+use crate::error::reduce_node_error::{ReduceNodeError, ReduceNodeErrorType, ReduceNodeResult};
+
+use serde_json::{Map, Value};
+use uuid::Uuid;
+
+use std::sync::Arc;
+
+/// The instruction that makes the AI service summarize the items.
+const SUMMARY_PROMPT: &str = "The message of the user holds several parts, produced \
+separately for the same task. Merge them into one complete and coherent answer, without \
+repeating what they share.";
+
+/// The function of a reducer, which takes the items and merges them.
+pub type ReduceFn = Arc<dyn Fn(Vec<String>) -> Result<String, String> + Send + Sync>;
+
+#[derive(Clone)]
+/// The struct of a named closure that merges the items.
+pub struct Reducer {
+    /// The name of the reducer.
+    name: String,
+    /// The function of the reducer.
+    function: ReduceFn,
+}
+
+impl Reducer {
+    /// Create a new Reducer. The function returns `Err` with a message when it fails.
+    pub fn new<F>(name: &str, function: F) -> Self
+    where
+        F: Fn(Vec<String>) -> Result<String, String> + Send + Sync + 'static,
+    {
+        Reducer {
+            name: name.to_string(),
+            function: Arc::new(function),
+        }
+    }
+    /// Get the name of the reducer.
+    pub fn get_name(&self) -> &str {
+        &self.name
+    }
+    /// Merge the items.
+    pub fn call(&self, items: Vec<String>) -> Result<String, String> {
+        (self.function)(items)
+    }
+}
+
+impl std::fmt::Debug for Reducer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Reducer").field("name", &self.name).finish()
+    }
+}
+
+#[derive(Debug, Clone)]
+#[allow(clippy::large_enum_variant)]
+/// The enum of the way to merge the items.
+pub enum ReduceStrategy {
+    /// Join the items with the separator.
+    Concat(String),
+    /// Concatenate the json arrays or merge the json objects.
+    JsonMerge,
+    /// Merge the items with a closure.
+    Custom(Reducer),
+    /// Ask the AI service of the node to summarize the items. Its role, if any, is added to
+    /// the instruction.
+    Summarize(AINode),
+}
+
+#[derive(Debug, Clone)]
+/// The struct of the reduce node.
+pub struct ReduceNode {
+    /// The way to merge the items.
+    strategy: ReduceStrategy,
+}
+
+impl ReduceNode {
+    /// Create a new ReduceNode.
+    pub fn new(strategy: ReduceStrategy) -> Self {
+        ReduceNode { strategy }
+    }
+    /// Merge the items of the input.
+    pub async fn execute(&mut self, input: String) -> ReduceNodeResult<String> {
+        let items = items(&input);
+        match &mut self.strategy {
+            ReduceStrategy::Concat(separator) => Ok(items.join(separator)),
+            ReduceStrategy::JsonMerge => merge(items).map(|merged| merged.to_string()),
+            ReduceStrategy::Custom(reducer) => reducer.call(items).map_err(|e| {
+                ReduceNodeError::new(
+                    ReduceNodeErrorType::ReducerError,
+                    format!("The reducer {} failed. {}", reducer.get_name(), e),
+                )
+            }),
+            ReduceStrategy::Summarize(node) => {
+                let chats = summary_chats(node, &items);
+                node.complete(&chats, &RequestOverrides::new())
+                    .await
+                    .map_err(|e| {
+                        ReduceNodeError::new(
+                            ReduceNodeErrorType::SummaryError(Box::new(e)),
+                            "The AI service failed to summarize the items.".to_string(),
+                        )
+                    })
+            }
+        }
+    }
+    /// Get the strategy.
+    pub fn get_strategy(&self) -> &ReduceStrategy {
+        &self.strategy
+    }
+    /// Get the mutable strategy.
+    pub fn get_strategy_mut(&mut self) -> &mut ReduceStrategy {
+        &mut self.strategy
+    }
+    /// Set the strategy.
+    pub fn set_strategy(&mut self, strategy: ReduceStrategy) {
+        self.strategy = strategy;
+    }
+    /// Get the AI node that summarizes the items, for the summarize strategy.
+    pub fn get_node(&self) -> Option<&AINode> {
+        match &self.strategy {
+            ReduceStrategy::Summarize(node) => Some(node),
+            _ => None,
+        }
+    }
+    /// Get the mutable AI node that summarizes the items, for the summarize strategy.
+    pub fn get_node_mut(&mut self) -> Option<&mut AINode> {
+        match &mut self.strategy {
+            ReduceStrategy::Summarize(node) => Some(node),
+            _ => None,
+        }
+    }
+    /// Set the uid of the worknode that holds this node.
+    pub fn set_node_uid(&mut self, node_uid: Option<Uuid>) {
+        if let Some(node) = self.get_node_mut() {
+            node.set_node_uid(node_uid);
+        }
+    }
+}
+
+/// Get the items of the input.
+fn items(input: &str) -> Vec<String> {
+    let items: Vec<String> = match serde_json::from_str(input) {
+        Ok(Value::Array(items)) => items.into_iter().map(item_text).collect(),
+        _ => return vec![input.to_string()],
+    };
+    // a single output that is an array, like the output of a map node, is merged by item
+    match items.as_slice() {
+        [item] => match serde_json::from_str(item) {
+            Ok(Value::Array(inner)) => inner.into_iter().map(item_text).collect(),
+            _ => items,
+        },
+        _ => items,
+    }
+}
+
+/// Get the text of an item: a string as it is, and any other value as json.
+fn item_text(item: Value) -> String {
+    match item {
+        Value::String(text) => text,
+        item => item.to_string(),
+    }
+}
+
+/// Merge the items as json.
+fn merge(items: Vec<String>) -> ReduceNodeResult<Value> {
+    let values = items
+        .iter()
+        .enumerate()
+        .map(|(index, item)| {
+            serde_json::from_str(item).map_err(|e| {
+                ReduceNodeError::new(
+                    ReduceNodeErrorType::MergeError,
+                    format!("The item {} is not valid json. {}", index, e),
+                )
+            })
+        })
+        .collect::<ReduceNodeResult<Vec<Value>>>()?;
+    if values.iter().all(Value::is_array) {
+        Ok(Value::Array(
+            values
+                .into_iter()
+                .flat_map(|value| match value {
+                    Value::Array(items) => items,
+                    _ => Vec::new(),
+                })
+                .collect(),
+        ))
+    } else if values.iter().all(Value::is_object) {
+        Ok(values
+            .into_iter()
+            .fold(Value::Object(Map::new()), |merged, value| {
+                merge_value(merged, value)
+            }))
+    } else {
+        Err(ReduceNodeError::new(
+            ReduceNodeErrorType::MergeError,
+            "The items are neither all json arrays nor all json objects.".to_string(),
+        ))
+    }
+}
+
+/// Merge the value into the merged one: the objects deeply, and any other value replaces.
+fn merge_value(merged: Value, value: Value) -> Value {
+    match (merged, value) {
+        (Value::Object(mut merged), Value::Object(value)) => {
+            for (key, value) in value {
+                let entry = merged.remove(&key).unwrap_or(Value::Null);
+                merged.insert(key, merge_value(entry, value));
+            }
+            Value::Object(merged)
+        }
+        (_, value) => value,
+    }
+}
+
+/// Build the chats sent to the AI service to summarize the items.
+fn summary_chats(node: &AINode, items: &[String]) -> Vec<Chat> {
+    let mut instruction = String::new();
+    if let Some(role) = node.get_role() {
+        instruction.push_str(role);
+        instruction.push_str("\n\n");
+    }
+    instruction.push_str(SUMMARY_PROMPT);
+    let parts: Vec<String> = items
+        .iter()
+        .enumerate()
+        .map(|(index, item)| format!("## Part {}\n{}", index + 1, item))
+        .collect();
+    vec![
+        Chat::new(Role::System, instruction),
+        Chat::new(Role::User, parts.join("\n\n")),
+    ]
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::worknode::ai_node::deepseek::{DeepSeekClient, DeepSeekModel, DEEPSEEK_API_URL};
+    use crate::worknode::ai_node::recording::Recording;
+    use crate::worknode::ai_node::AIService;
+
+    use tokio::runtime::Runtime;
+
+    #[test]
+    fn reduce_items() {
+        let rt = Runtime::new().unwrap();
+        let reduce = |strategy: ReduceStrategy, input: &str| {
+            rt.block_on(ReduceNode::new(strategy).execute(input.to_string()))
+        };
+        let branches = r#"["a", "b"]"#;
+        assert_eq!(
+            reduce(ReduceStrategy::Concat("+".to_string()), branches).unwrap(),
+            "a+b"
+        );
+        // the output of a map node is merged by item
+        assert_eq!(
+            reduce(ReduceStrategy::Concat("+".to_string()), r#"["[1, \"c\"]"]"#).unwrap(),
+            "1+c"
+        );
+        assert_eq!(
+            reduce(ReduceStrategy::Concat("+".to_string()), "plain").unwrap(),
+            "plain"
+        );
+
+        let objects = r#"["{\"a\": {\"x\": 1}, \"b\": 1}", "{\"a\": {\"y\": 2}, \"b\": 2}"]"#;
+        assert_eq!(
+            reduce(ReduceStrategy::JsonMerge, objects).unwrap(),
+            r#"{"a":{"x":1,"y":2},"b":2}"#
+        );
+        assert_eq!(
+            reduce(ReduceStrategy::JsonMerge, r#"["[1]", "[2, 3]"]"#).unwrap(),
+            "[1,2,3]"
+        );
+        let error = reduce(ReduceStrategy::JsonMerge, r#"["[1]", "{}"]"#).unwrap_err();
+        assert!(matches!(
+            error.get_error_type(),
+            ReduceNodeErrorType::MergeError
+        ));
+
+        let longest = Reducer::new("longest", |items| {
+            items
+                .into_iter()
+                .max_by_key(String::len)
+                .ok_or_else(|| "no item".to_string())
+        });
+        assert_eq!(
+            reduce(ReduceStrategy::Custom(longest.clone()), r#"["ab", "abc"]"#).unwrap(),
+            "abc"
+        );
+        let error = reduce(ReduceStrategy::Custom(longest), "[]").unwrap_err();
+        assert!(error.get_message().contains("longest"));
+    }
+
+    #[test]
+    fn summarize_items() {
+        let client = DeepSeekClient::new(DEEPSEEK_API_URL, DeepSeekModel::DeepseekChat);
+        let items = vec!["a".to_string(), "b".to_string()];
+        let node = AINode::new(AIService::DeepSeek {
+            client: client.clone(),
+        });
+        let chats = summary_chats(&node, &items);
+        let recording = Recording::replay(vec![client.exchange(
+            &chats,
+            &RequestOverrides::new(),
+            "a and b",
+        )]);
+        let node = AINode::new(AIService::DeepSeek {
+            client: client.recording(Some(recording)),
+        });
+        let mut reduce = ReduceNode::new(ReduceStrategy::Summarize(node));
+        let rt = Runtime::new().unwrap();
+        let output = rt.block_on(reduce.execute(r#"["a", "b"]"#.to_string()));
+        assert_eq!(output.unwrap(), "a and b");
+    }
+}
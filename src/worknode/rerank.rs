@@ -0,0 +1,408 @@
+//! # Rerank
+//!
+//! This node re-orders the documents found by a retrieve node by their relevance to the query,
+//! so a retrieve node can fetch many candidates and the AI node after them only reads the best
+//! ones. The documents are scored either:
+//! 1. By a rerank api (see [`crate::worknode::ai_node::rerank`]), whose cross-encoder models
+//!    read the query with each document.
+//! 2. By an AI node, asked to rate every document from 0 to 10 in one request, at
+//!    temperature 0. The ratings are divided by 10, so the scores are from 0 to 1.
+//!
+//! The node works on the run context after a retrieve node: the candidates are the citations
+//! under the citations key, and the query is a template rendered with the variable `input`
+//! and the variables of the run context (`{{input}}` by default, the question that the
+//! retrieve node passed on). The documents are sorted by their new score, those below
+//! `min_score` are dropped and the `top_n` first are kept. They replace the citations,
+//! numbered again from 1, with their new `score` and their `retrieval_score`, and their
+//! snippets replace the snippets under the context key, rendered like in the retrieve node.
+//!
+//! The output is the input, like the retrieve node.
+
+use super::ai_node::rerank::RerankClient;
+use super::ai_node::{AINode, Chat, RequestOverrides, Role};
+use super::retrieve::render_snippets;
+use crate::error::ai_node_error::{AINodeError, AINodeErrorType, AINodeResult};
+use crate::template;
+use crate::workflow::context::RunContext;
+
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use uuid::Uuid;
+
+/// The instruction that makes the AI service rate the documents.
+const RERANK_PROMPT: &str = "Rate how relevant each numbered passage is to the query, from 0 \
+(unrelated) to 10 (answers it). Answer with a json array of the ratings only, one number per \
+passage in their order, without any other word.";
+
+#[derive(Debug, Clone)]
+#[allow(clippy::large_enum_variant)]
+/// The enum of what scores the documents.
+pub enum Reranker {
+    /// A rerank api.
+    Api(RerankClient),
+    /// An AI node that rates the documents.
+    Llm(AINode),
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+/// The struct of the options of the rerank node.
+pub struct RerankOptions {
+    /// The template of the query.
+    pub query: String,
+    /// The key of the run context that the citations are read from and put under.
+    pub citations_key: String,
+    /// The max number of documents kept, or `None` to keep them all.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub top_n: Option<usize>,
+    /// The lowest new score of a document kept, or `None` for no threshold.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub min_score: Option<f32>,
+    /// The template of a snippet.
+    pub snippet: String,
+    /// The text between the snippets.
+    pub separator: String,
+    /// The key of the run context that the snippets are put under.
+    pub context_key: String,
+}
+
+impl Default for RerankOptions {
+    fn default() -> Self {
+        RerankOptions {
+            query: "{{input}}".to_string(),
+            citations_key: "citations".to_string(),
+            top_n: None,
+            min_score: None,
+            snippet: "[{{citation}}] {{source}}\n{{text}}".to_string(),
+            separator: "\n\n".to_string(),
+            context_key: "retrieved".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+/// The struct of the rerank node.
+pub struct RerankNode {
+    /// What scores the documents.
+    reranker: Reranker,
+    /// The options.
+    options: RerankOptions,
+    /// The name of the rerank client in the registry, used to save the node in a workflow
+    /// file. The AI node of a reranker has its own provider name.
+    provider: Option<String>,
+}
+
+impl RerankNode {
+    /// Create a new RerankNode with the default options.
+    pub fn new(reranker: Reranker) -> Self {
+        RerankNode {
+            reranker,
+            options: RerankOptions::default(),
+            provider: None,
+        }
+    }
+    /// Re-order the citations in the context by their relevance to the query, put them and
+    /// their snippets in the context again, and get the input again.
+    pub async fn execute(&mut self, input: String, context: &RunContext) -> AINodeResult<String> {
+        let mut variables = context.to_variables();
+        variables.insert("input".to_string(), input.clone());
+        let query = template::render(&self.options.query, &variables).map_err(|e| {
+            AINodeError::new(
+                AINodeErrorType::TemplateError(e),
+                "Failed to render the query.".to_string(),
+            )
+        })?;
+        let key = &self.options.citations_key;
+        let candidates: Vec<Value> = match context.get_value(key) {
+            Some(Value::Array(candidates)) => candidates
+                .into_iter()
+                .map(|candidate| match candidate {
+                    Value::String(text) => json!({ "text": text }),
+                    candidate => candidate,
+                })
+                .collect(),
+            _ => {
+                return Err(AINodeError::new(
+                    AINodeErrorType::InvalidInput,
+                    format!("The context has no json array of citations under {}.", key),
+                ))
+            }
+        };
+        let texts = candidates
+            .iter()
+            .map(|candidate| {
+                candidate["text"]
+                    .as_str()
+                    .map(str::to_string)
+                    .ok_or_else(|| {
+                        AINodeError::new(
+                            AINodeErrorType::InvalidInput,
+                            format!("The citation {} has no text.", candidate),
+                        )
+                    })
+            })
+            .collect::<AINodeResult<Vec<String>>>()?;
+
+        let scores = match &mut self.reranker {
+            Reranker::Api(client) => client.rerank(&query, &texts).await?,
+            Reranker::Llm(node) => {
+                let answer = node
+                    .complete(&Self::chats(&query, &texts), &Self::overrides())
+                    .await?;
+                Self::parse_ratings(&answer, texts.len())?
+            }
+        };
+        let citations: Vec<Value> = scores
+            .into_iter()
+            .filter(|(_, score)| self.options.min_score.is_none_or(|min| *score >= min))
+            .take(self.options.top_n.unwrap_or(usize::MAX))
+            .enumerate()
+            .map(|(rank, (index, score))| {
+                let mut citation = candidates[index].clone();
+                if let Some(retrieval_score) = citation.get("score").cloned() {
+                    citation["retrieval_score"] = retrieval_score;
+                }
+                citation["score"] = json!(score);
+                citation["citation"] = json!(rank + 1);
+                citation
+            })
+            .collect();
+        let snippets = render_snippets(&citations, &self.options.snippet, &self.options.separator)
+            .map_err(|e| {
+                AINodeError::new(
+                    AINodeErrorType::TemplateError(e),
+                    "Failed to render a snippet.".to_string(),
+                )
+            })?;
+        context.set_value(&self.options.context_key, Value::String(snippets));
+        context.set_value(key, Value::Array(citations));
+        Ok(input)
+    }
+    /// Build the chats that ask the AI service to rate the documents.
+    pub(crate) fn chats(query: &str, texts: &[String]) -> Vec<Chat> {
+        let mut message = format!("Query: {}", query);
+        for (i, text) in texts.iter().enumerate() {
+            message.push_str(&format!("\n\n[{}] {}", i + 1, text));
+        }
+        vec![
+            Chat::new(Role::System, RERANK_PROMPT.to_string()),
+            Chat::new(Role::User, message),
+        ]
+    }
+    /// The parameters of the rating request.
+    pub(crate) fn overrides() -> RequestOverrides {
+        RequestOverrides::new().temperature(0.0)
+    }
+    /// Get the indices of the documents with their scores from the ratings in the answer,
+    /// the most relevant first.
+    fn parse_ratings(answer: &str, count: usize) -> AINodeResult<Vec<(usize, f32)>> {
+        let invalid = || {
+            AINodeError::new(
+                AINodeErrorType::InvalidJsonOutput,
+                format!(
+                    "The answer `{}` is not a json array of {} ratings.",
+                    answer.trim(),
+                    count
+                ),
+            )
+        };
+        let start = answer.find('[').ok_or_else(invalid)?;
+        let end = answer
+            .rfind(']')
+            .filter(|&end| end > start)
+            .ok_or_else(invalid)?;
+        let ratings: Vec<f64> =
+            serde_json::from_str(&answer[start..=end]).map_err(|_| invalid())?;
+        if ratings.len() != count {
+            return Err(invalid());
+        }
+        let mut scores: Vec<(usize, f32)> = ratings
+            .into_iter()
+            .map(|rating| (rating / 10.0) as f32)
+            .enumerate()
+            .collect();
+        // the sort is stable, so the documents rated the same keep the order of the retrieval
+        scores.sort_by(|a, b| b.1.total_cmp(&a.1));
+        Ok(scores)
+    }
+    /// Set the options as builder.
+    pub fn options(mut self, options: RerankOptions) -> Self {
+        self.options = options;
+        self
+    }
+    /// Set the options.
+    pub fn set_options(&mut self, options: RerankOptions) {
+        self.options = options;
+    }
+    /// Get the options.
+    pub fn get_options(&self) -> &RerankOptions {
+        &self.options
+    }
+    /// Get what scores the documents.
+    pub fn get_reranker(&self) -> &Reranker {
+        &self.reranker
+    }
+    /// Set what scores the documents.
+    pub fn set_reranker(&mut self, reranker: Reranker) {
+        self.reranker = reranker;
+    }
+    /// Set the name of the rerank client in the registry as builder.
+    pub fn provider(mut self, provider: Option<String>) -> Self {
+        self.provider = provider;
+        self
+    }
+    /// Set the name of the rerank client in the registry.
+    pub fn set_provider(&mut self, provider: Option<String>) {
+        self.provider = provider;
+    }
+    /// Get the name of the provider: the rerank client in the registry, or the AI service of
+    /// the AI node.
+    pub fn get_provider(&self) -> Option<&str> {
+        match &self.reranker {
+            Reranker::Api(_) => self.provider.as_deref(),
+            Reranker::Llm(node) => node.get_provider(),
+        }
+    }
+    /// Tell the AI node of the reranker the uid of the worknode that holds it.
+    pub fn set_node_uid(&mut self, node_uid: Option<Uuid>) {
+        if let Reranker::Llm(node) = &mut self.reranker {
+            node.set_node_uid(node_uid);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::worknode::ai_node::deepseek::{DeepSeekClient, DeepSeekModel, DEEPSEEK_API_URL};
+    use crate::worknode::ai_node::recording::Recording;
+    use crate::worknode::ai_node::rerank::RerankProvider;
+    use crate::worknode::ai_node::AIService;
+
+    use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+    use tokio::net::TcpListener;
+    use tokio::runtime::Runtime;
+
+    /// Answer one request with the body, and get the body of the request.
+    async fn server(listener: TcpListener, answer: Value) -> Value {
+        let (stream, _) = listener.accept().await.unwrap();
+        let mut stream = BufReader::new(stream);
+        let mut length = 0;
+        loop {
+            let mut line = String::new();
+            stream.read_line(&mut line).await.unwrap();
+            if line == "\r\n" {
+                break;
+            }
+            if let Some(value) = line.to_lowercase().strip_prefix("content-length:") {
+                length = value.trim().parse().unwrap();
+            }
+        }
+        let mut body = vec![0; length];
+        stream.read_exact(&mut body).await.unwrap();
+        let answer = answer.to_string();
+        let response = format!(
+            "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            answer.len(),
+            answer
+        );
+        stream
+            .get_mut()
+            .write_all(response.as_bytes())
+            .await
+            .unwrap();
+        serde_json::from_slice(&body).unwrap()
+    }
+
+    /// Get a context with the citations of a retrieve node.
+    fn retrieved() -> RunContext {
+        let context = RunContext::new();
+        context.set_value(
+            "citations",
+            json!([
+                {"citation": 1, "id": "a", "source": "a.md", "score": 0.9, "text": "Cats sleep."},
+                {"citation": 2, "id": "b", "source": "b.md", "score": 0.8, "text": "Cats purr."},
+                {"citation": 3, "id": "c", "source": "c.md", "score": 0.7, "text": "Dogs bark."},
+            ]),
+        );
+        context
+    }
+
+    #[test]
+    fn rerank_citations() {
+        let rt = Runtime::new().unwrap();
+        let listener = rt.block_on(TcpListener::bind("127.0.0.1:0")).unwrap();
+        let url = format!("http://{}/v1/rerank", listener.local_addr().unwrap());
+        let answer = json!({"results": [
+            {"index": 1, "relevance_score": 0.95},
+            {"index": 0, "relevance_score": 0.4},
+            {"index": 2, "relevance_score": 0.01},
+        ]});
+        let server_task = rt.spawn(server(listener, answer));
+        let client = RerankClient::new(RerankProvider::Jina, &url, "reranker");
+        let mut node = RerankNode::new(Reranker::Api(client)).options(RerankOptions {
+            min_score: Some(0.1),
+            ..RerankOptions::default()
+        });
+        let context = retrieved();
+        let output = rt
+            .block_on(node.execute("Do cats purr?".to_string(), &context))
+            .unwrap();
+        assert_eq!(output, "Do cats purr?");
+        let request = rt.block_on(server_task).unwrap();
+        assert_eq!(request["query"], "Do cats purr?");
+        assert_eq!(request["documents"][2], "Dogs bark.");
+        assert_eq!(
+            context.get::<String>("retrieved").unwrap().unwrap(),
+            "[1] b.md\nCats purr.\n\n[2] a.md\nCats sleep."
+        );
+        let citations: Vec<Value> = context.get("citations").unwrap().unwrap();
+        assert_eq!(citations.len(), 2);
+        assert_eq!(citations[0]["citation"], 1);
+        assert_eq!(citations[0]["retrieval_score"], 0.8);
+
+        // an AI node rates the documents
+        let client = DeepSeekClient::new(DEEPSEEK_API_URL, DeepSeekModel::DeepseekChat);
+        let texts: Vec<String> = ["Cats sleep.", "Cats purr.", "Dogs bark."]
+            .iter()
+            .map(|text| text.to_string())
+            .collect();
+        let chats = RerankNode::chats("Do dogs bark?", &texts);
+        let overrides = RerankNode::overrides();
+        let recording = Recording::replay(vec![
+            client.exchange(&chats, &overrides, "Ratings: [1, 0, 9]"),
+            client.exchange(&chats, &overrides, "[1, 2]"),
+        ]);
+        let service = AIService::DeepSeek {
+            client: client.recording(Some(recording)),
+        };
+        let mut node =
+            RerankNode::new(Reranker::Llm(AINode::new(service))).options(RerankOptions {
+                top_n: Some(1),
+                snippet: "{{text}} {{score}}".to_string(),
+                ..RerankOptions::default()
+            });
+        let context = retrieved();
+        rt.block_on(node.execute("Do dogs bark?".to_string(), &context))
+            .unwrap();
+        assert_eq!(
+            context.get::<String>("retrieved").unwrap().unwrap(),
+            "Dogs bark. 0.900"
+        );
+        let error = rt
+            .block_on(node.execute("Do dogs bark?".to_string(), &retrieved()))
+            .unwrap_err();
+        assert!(matches!(
+            error.get_error_type(),
+            AINodeErrorType::InvalidJsonOutput
+        ));
+
+        let error = rt
+            .block_on(node.execute("Do dogs bark?".to_string(), &RunContext::new()))
+            .unwrap_err();
+        assert!(matches!(
+            error.get_error_type(),
+            AINodeErrorType::InvalidInput
+        ));
+    }
+}
@@ -0,0 +1,371 @@
+//! # Retrieve
+//!
+//! This node finds the documents relevant to a query in a vector store, and gives them to the
+//! AI nodes after it as the context of their prompts, for retrieval augmented generation.
+//!
+//! The query, a template rendered with the variable `input` and the variables of the run
+//! context (`{{input}}` by default), is embedded with the client of an embeddings api (see
+//! [`crate::worknode::ai_node::embedding`]). The `top_k` documents most similar to it that
+//! match the filter on their metadata, and whose score is at least `min_score`, are kept.
+//!
+//! Each document becomes a snippet with the snippet template, numbered from 1 so an answer
+//! can cite it, and the snippets are put in the run context under the context key, by
+//! default as `{{context.retrieved}}`. The variables of the snippet template are `citation`,
+//! `id`, `text`, `score`, `source` (the `source` of the metadata, or the id), and the values
+//! of the metadata as `metadata.<key>`. The json array of the citations, with the `citation`,
+//! `id`, `source`, `score`, `text` and `metadata` of each document, is put in the context
+//! under the citations key, if any.
+//!
+//! The output is the input, so the question goes on to the AI node that answers it.
+
+use super::ai_node::embedding::EmbeddingClient;
+use crate::error::retrieve_node_error::{
+    RetrieveNodeError, RetrieveNodeErrorType, RetrieveNodeResult,
+};
+use crate::error::template_error::TemplateResult;
+use crate::error::vector_store_error::{VectorStoreError, VectorStoreErrorType};
+use crate::template::{self, Variables};
+use crate::vector_store::{Filter, Match, VectorStore};
+use crate::workflow::context::RunContext;
+
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use std::sync::Arc;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+/// The struct of the options of the retrieve node.
+pub struct RetrieveOptions {
+    /// The template of the query.
+    pub query: String,
+    /// The max number of documents.
+    pub top_k: usize,
+    /// The lowest score of a document, from -1 to 1, or `None` for no threshold.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub min_score: Option<f32>,
+    /// The filter on the metadata of the documents.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub filter: Option<Filter>,
+    /// The template of a snippet.
+    pub snippet: String,
+    /// The text between the snippets.
+    pub separator: String,
+    /// The key of the run context that the snippets are put under.
+    pub context_key: String,
+    /// The key of the run context that the citations are put under, or `None` to not put them.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub citations_key: Option<String>,
+}
+
+impl Default for RetrieveOptions {
+    fn default() -> Self {
+        RetrieveOptions {
+            query: "{{input}}".to_string(),
+            top_k: 4,
+            min_score: None,
+            filter: None,
+            snippet: "[{{citation}}] {{source}}\n{{text}}".to_string(),
+            separator: "\n\n".to_string(),
+            context_key: "retrieved".to_string(),
+            citations_key: Some("citations".to_string()),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+/// The struct of the retrieve node.
+pub struct RetrieveNode {
+    /// The client of the embeddings api.
+    client: EmbeddingClient,
+    /// The store of the documents.
+    store: Arc<dyn VectorStore>,
+    /// The options.
+    options: RetrieveOptions,
+    /// The name of the client in the registry, used to save the node in a workflow file.
+    provider: Option<String>,
+    /// The name of the store in the registry, used to save the node in a workflow file.
+    store_name: Option<String>,
+}
+
+impl RetrieveNode {
+    /// Create a new RetrieveNode with the default options.
+    pub fn new(client: EmbeddingClient, store: Arc<dyn VectorStore>) -> Self {
+        RetrieveNode {
+            client,
+            store,
+            options: RetrieveOptions::default(),
+            provider: None,
+            store_name: None,
+        }
+    }
+    /// Find the documents of the query, put their snippets and citations in the context, and
+    /// get the input again.
+    pub async fn execute(
+        &mut self,
+        input: String,
+        context: &RunContext,
+    ) -> RetrieveNodeResult<String> {
+        let mut variables = context.to_variables();
+        variables.insert("input".to_string(), input.clone());
+        let query = template::render(&self.options.query, &variables).map_err(|e| {
+            RetrieveNodeError::new(
+                RetrieveNodeErrorType::TemplateError(e),
+                "Failed to render the query.".to_string(),
+            )
+        })?;
+        let vector = self
+            .client
+            .embed(&[query])
+            .await
+            .map_err(|e| {
+                RetrieveNodeError::new(
+                    RetrieveNodeErrorType::EmbeddingError(Box::new(e)),
+                    "Failed to embed the query.".to_string(),
+                )
+            })?
+            .pop()
+            .unwrap_or_default();
+        let store = self.store.clone();
+        let (top_k, filter) = (self.options.top_k, self.options.filter.clone());
+        // the store may read a file, so it is queried out of the async runtime
+        let matches =
+            tokio::task::spawn_blocking(move || store.query(&vector, top_k, filter.as_ref()))
+                .await
+                .map_err(|e| {
+                    store_error(VectorStoreError::new(
+                        VectorStoreErrorType::StorageError,
+                        format!("The query panicked. {}", e),
+                    ))
+                })?
+                .map_err(store_error)?;
+        let matches: Vec<Match> = matches
+            .into_iter()
+            .filter(|m| self.options.min_score.is_none_or(|min| m.score >= min))
+            .collect();
+
+        let citations: Vec<Value> = matches
+            .into_iter()
+            .enumerate()
+            .map(|(i, m)| {
+                let document = m.document;
+                let source = match document.metadata.get("source") {
+                    Some(Value::String(source)) => source.clone(),
+                    Some(source) => source.to_string(),
+                    None => document.id.clone(),
+                };
+                json!({
+                    "citation": i + 1,
+                    "id": document.id,
+                    "source": source,
+                    "score": m.score,
+                    "text": document.text,
+                    "metadata": document.metadata,
+                })
+            })
+            .collect();
+        let snippets = render_snippets(&citations, &self.options.snippet, &self.options.separator)
+            .map_err(|e| {
+                RetrieveNodeError::new(
+                    RetrieveNodeErrorType::TemplateError(e),
+                    "Failed to render a snippet.".to_string(),
+                )
+            })?;
+        context.set_value(&self.options.context_key, Value::String(snippets));
+        if let Some(key) = &self.options.citations_key {
+            context.set_value(key, Value::Array(citations));
+        }
+        Ok(input)
+    }
+    /// Set the options as builder.
+    pub fn options(mut self, options: RetrieveOptions) -> Self {
+        self.options = options;
+        self
+    }
+    /// Set the options.
+    pub fn set_options(&mut self, options: RetrieveOptions) {
+        self.options = options;
+    }
+    /// Get the options.
+    pub fn get_options(&self) -> &RetrieveOptions {
+        &self.options
+    }
+    /// Get the client of the embeddings api.
+    pub fn get_client(&self) -> &EmbeddingClient {
+        &self.client
+    }
+    /// Set the client of the embeddings api.
+    pub fn set_client(&mut self, client: EmbeddingClient) {
+        self.client = client;
+    }
+    /// Get the store of the documents.
+    pub fn get_store(&self) -> &Arc<dyn VectorStore> {
+        &self.store
+    }
+    /// Set the store of the documents.
+    pub fn set_store(&mut self, store: Arc<dyn VectorStore>) {
+        self.store = store;
+    }
+    /// Set the name of the client in the registry as builder.
+    pub fn provider(mut self, provider: Option<String>) -> Self {
+        self.provider = provider;
+        self
+    }
+    /// Set the name of the client in the registry.
+    pub fn set_provider(&mut self, provider: Option<String>) {
+        self.provider = provider;
+    }
+    /// Get the name of the client in the registry.
+    pub fn get_provider(&self) -> Option<&str> {
+        self.provider.as_deref()
+    }
+    /// Set the name of the store in the registry as builder.
+    pub fn store_name(mut self, store_name: Option<String>) -> Self {
+        self.store_name = store_name;
+        self
+    }
+    /// Set the name of the store in the registry.
+    pub fn set_store_name(&mut self, store_name: Option<String>) {
+        self.store_name = store_name;
+    }
+    /// Get the name of the store in the registry.
+    pub fn get_store_name(&self) -> Option<&str> {
+        self.store_name.as_deref()
+    }
+}
+
+/// Render the snippets of the citations, with the `citation`, `id`, `text`, `score`,
+/// `source` and `metadata.<key>` variables, and join them with the separator.
+pub(crate) fn render_snippets(
+    citations: &[Value],
+    snippet: &str,
+    separator: &str,
+) -> TemplateResult<String> {
+    let text = |value: &Value| match value {
+        Value::String(text) => text.clone(),
+        value => value.to_string(),
+    };
+    let mut snippets = Vec::with_capacity(citations.len());
+    for citation in citations {
+        let mut variables: Variables = citation["metadata"]
+            .as_object()
+            .into_iter()
+            .flatten()
+            .map(|(key, value)| (format!("metadata.{}", key), text(value)))
+            .collect();
+        for key in ["citation", "id", "text", "source"] {
+            variables.insert(key.to_string(), text(&citation[key]));
+        }
+        let score = citation["score"].as_f64().unwrap_or_default();
+        variables.insert("score".to_string(), format!("{:.3}", score));
+        snippets.push(template::render(snippet, &variables)?);
+    }
+    Ok(snippets.join(separator))
+}
+
+/// Create a RetrieveNodeError of the vector store.
+fn store_error(e: VectorStoreError) -> RetrieveNodeError {
+    RetrieveNodeError::new(
+        RetrieveNodeErrorType::StoreError(e),
+        "Failed to query the vector store.".to_string(),
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::vector_store::memory::MemoryVectorStore;
+    use crate::vector_store::Document;
+    use crate::worknode::ai_node::embedding::EmbeddingProvider;
+
+    use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+    use tokio::net::TcpListener;
+    use tokio::runtime::Runtime;
+
+    /// Answer every request with the embedding.
+    async fn server(listener: TcpListener, embedding: Vec<f32>) {
+        loop {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut stream = BufReader::new(stream);
+            let mut length = 0;
+            loop {
+                let mut line = String::new();
+                stream.read_line(&mut line).await.unwrap();
+                if line == "\r\n" {
+                    break;
+                }
+                if let Some(value) = line.to_lowercase().strip_prefix("content-length:") {
+                    length = value.trim().parse().unwrap();
+                }
+            }
+            let mut body = vec![0; length];
+            stream.read_exact(&mut body).await.unwrap();
+            let answer = json!({"embeddings": [embedding]}).to_string();
+            let response = format!(
+                "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                answer.len(),
+                answer
+            );
+            stream
+                .get_mut()
+                .write_all(response.as_bytes())
+                .await
+                .unwrap();
+        }
+    }
+
+    #[test]
+    fn retrieve_snippets() {
+        let rt = Runtime::new().unwrap();
+        let listener = rt.block_on(TcpListener::bind("127.0.0.1:0")).unwrap();
+        let url = format!("http://{}/api/embed", listener.local_addr().unwrap());
+        rt.spawn(server(listener, vec![1.0, 0.0]));
+        let client = EmbeddingClient::new(EmbeddingProvider::Ollama, &url, "nomic-embed-text");
+
+        let store = MemoryVectorStore::new();
+        store
+            .upsert(vec![
+                Document::new("a", "Cats purr.", vec![1.0, 0.0])
+                    .meta("source", json!("cats.md"))
+                    .meta("lang", json!("en")),
+                Document::new("b", "Chats ronronnent.", vec![0.9, 0.1]).meta("lang", json!("fr")),
+                Document::new("c", "Dogs bark.", vec![0.0, 1.0]).meta("lang", json!("en")),
+            ])
+            .unwrap();
+        let mut node = RetrieveNode::new(client, Arc::new(store)).options(RetrieveOptions {
+            min_score: Some(0.5),
+            ..RetrieveOptions::default()
+        });
+        let context = RunContext::new();
+        let output = rt
+            .block_on(node.execute("Do cats purr?".to_string(), &context))
+            .unwrap();
+        assert_eq!(output, "Do cats purr?");
+        assert_eq!(
+            context.get::<String>("retrieved").unwrap().unwrap(),
+            "[1] cats.md\nCats purr.\n\n[2] b\nChats ronronnent."
+        );
+        let citations: Vec<Value> = context.get("citations").unwrap().unwrap();
+        assert_eq!(citations.len(), 2);
+        assert_eq!(citations[1]["id"], "b");
+        assert_eq!(citations[1]["metadata"]["lang"], "fr");
+
+        node.set_options(RetrieveOptions {
+            top_k: 1,
+            filter: Some(Filter::eq("lang", json!("fr"))),
+            snippet: "{{text}} ({{metadata.lang}}, {{score}})".to_string(),
+            context_key: "facts".to_string(),
+            citations_key: None,
+            ..RetrieveOptions::default()
+        });
+        let context = RunContext::new();
+        rt.block_on(node.execute("chats".to_string(), &context))
+            .unwrap();
+        assert_eq!(
+            context.get::<String>("facts").unwrap().unwrap(),
+            "Chats ronronnent. (fr, 0.994)"
+        );
+        assert!(!context.contains("citations"));
+    }
+}
@@ -0,0 +1,453 @@
+//! # Retry
+//!
+//! This module defines the retry policy of a worknode.
+//!
+//! The policy is applied around the whole execution of the node, independent of the retries
+//! inside the AI service (like the json repair), so a transient failure of the AI service
+//! doesn't kill a long workflow run. Only the errors whose class is listed in the policy are
+//! retried, and the node waits for the backoff between two attempts.
+//!
+//! The class of an error of an AI service comes from the HTTP status of the answer, and from
+//! the `error.type` or `error.code` of its json body when it tells more, like DeepSeek and
+//! OpenAI do. `ErrorClass::is_retryable` tells the transient classes apart from the ones that
+//! fail again the same way.
+//!
+//! The policy may also limit the time of every attempt. An attempt that takes longer is
+//! stopped and fails with a timeout error, whose class is `Timeout`, like the requests that
+//! time out in the AI service and the commands and plugins that run too long.
+
+use crate::error::ai_node_error::deepseek_error::{ApiErrorKind, DeepSeekErrorType};
+use crate::error::ai_node_error::embedding_error::EmbeddingErrorType;
+use crate::error::ai_node_error::rerank_error::RerankErrorType;
+use crate::error::ai_node_error::{AINodeError, AINodeErrorType};
+use crate::error::ingest_node_error::IngestNodeErrorType;
+use crate::error::local_node_error::LocalNodeErrorType;
+use crate::error::map_node_error::MapNodeErrorType;
+use crate::error::planner_node_error::PlannerNodeErrorType;
+use crate::error::reduce_node_error::ReduceNodeErrorType;
+use crate::error::retrieve_node_error::RetrieveNodeErrorType;
+use crate::error::wasm_node_error::WasmNodeErrorType;
+use crate::error::{PilotError, PilotErrorType};
+
+use serde::{Deserialize, Serialize};
+
+use std::time::Duration;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+/// The enum of the class of an error, used to decide whether it is retried.
+pub enum ErrorClass {
+    /// The request can't reach the AI service.
+    Network,
+    /// The work takes longer than its limit, like a request or an attempt of a node.
+    Timeout,
+    /// The AI service rejects the request for too many requests (HTTP 429).
+    RateLimit,
+    /// The AI service fails on its side (HTTP 5xx).
+    Server,
+    /// The AI service rejects the api key, or doesn't allow it (HTTP 401 and 403).
+    Auth,
+    /// The AI service rejects the request as it is, like a wrong parameter or a too long
+    /// prompt (the other HTTP 4xx).
+    InvalidRequest,
+    /// The output of the AI service is not valid json, doesn't match the schema or names no
+    /// route of a router node.
+    InvalidOutput,
+    /// Any other error, which won't be fixed by retrying.
+    #[default]
+    Other,
+}
+
+impl ErrorClass {
+    /// Get the class of the error.
+    pub fn of(error: &PilotError) -> Self {
+        match error.get_error_type() {
+            PilotErrorType::AINodeErr(e) => ErrorClass::of_ai(e),
+            PilotErrorType::TimeoutErr(_) => ErrorClass::Timeout,
+            PilotErrorType::LocalNodeErr(e)
+                if matches!(e.get_error_type(), LocalNodeErrorType::Timeout) =>
+            {
+                ErrorClass::Timeout
+            }
+            PilotErrorType::WasmNodeErr(e)
+                if matches!(e.get_error_type(), WasmNodeErrorType::Timeout) =>
+            {
+                ErrorClass::Timeout
+            }
+            PilotErrorType::GraphErr(_)
+            | PilotErrorType::LocalNodeErr(_)
+            | PilotErrorType::WasmNodeErr(_)
+            | PilotErrorType::ScriptNodeErr(_)
+            | PilotErrorType::FileNodeErr(_)
+            | PilotErrorType::UserNodeErr(_)
+            | PilotErrorType::TransformNodeErr(_)
+            | PilotErrorType::DelayNodeErr(_)
+            | PilotErrorType::AssertNodeErr(_)
+            | PilotErrorType::NotifyNodeErr(_)
+            | PilotErrorType::LoadNodeErr(_)
+            | PilotErrorType::ChunkerNodeErr(_)
+            | PilotErrorType::ConfigErr(_)
+            | PilotErrorType::StorageErr(_) => ErrorClass::Other,
+            // the map node fails as its item failed
+            PilotErrorType::MapNodeErr(e) => match e.get_error_type() {
+                MapNodeErrorType::ItemFailed(e) => ErrorClass::of(e),
+                MapNodeErrorType::InvalidInput => ErrorClass::Other,
+            },
+            PilotErrorType::ReduceNodeErr(e) => match e.get_error_type() {
+                ReduceNodeErrorType::SummaryError(e) => ErrorClass::of_ai(e),
+                _ => ErrorClass::Other,
+            },
+            PilotErrorType::RetrieveNodeErr(e) => match e.get_error_type() {
+                RetrieveNodeErrorType::EmbeddingError(e) => ErrorClass::of_ai(e),
+                _ => ErrorClass::Other,
+            },
+            PilotErrorType::IngestNodeErr(e) => match e.get_error_type() {
+                IngestNodeErrorType::EmbeddingError(e) => ErrorClass::of_ai(e),
+                _ => ErrorClass::Other,
+            },
+            // the planner node fails as its planning or its step failed
+            PilotErrorType::PlannerNodeErr(e) => match e.get_error_type() {
+                PlannerNodeErrorType::PlanError(e) => ErrorClass::of_ai(e),
+                PlannerNodeErrorType::StepFailed(e) => ErrorClass::of(e),
+                PlannerNodeErrorType::InvalidPlan => ErrorClass::Other,
+            },
+        }
+    }
+    /// Get the class of an error of the AI service.
+    fn of_ai(error: &AINodeError) -> Self {
+        match error.get_error_type() {
+            AINodeErrorType::DeepSeekError(e) => match e.get_error_type() {
+                DeepSeekErrorType::ApiKeyError => ErrorClass::Auth,
+                DeepSeekErrorType::TimeoutError(_) => ErrorClass::Timeout,
+                DeepSeekErrorType::RequestParamError(_) => ErrorClass::InvalidRequest,
+                DeepSeekErrorType::RequestError => match e.get_api_error() {
+                    Some(ApiErrorKind::Authentication) => ErrorClass::Auth,
+                    Some(ApiErrorKind::RateLimit) => ErrorClass::RateLimit,
+                    Some(ApiErrorKind::ServerError | ApiErrorKind::ServerOverloaded) => {
+                        ErrorClass::Server
+                    }
+                    Some(
+                        ApiErrorKind::InvalidRequest
+                        | ApiErrorKind::InvalidParameters
+                        | ApiErrorKind::ContextLengthExceeded,
+                    ) => ErrorClass::InvalidRequest,
+                    // the account has to be topped up before it is retried
+                    Some(ApiErrorKind::InsufficientBalance) => ErrorClass::Other,
+                    Some(ApiErrorKind::Unknown) | None => {
+                        ErrorClass::of_http(e.get_status(), e.get_body())
+                            .unwrap_or(ErrorClass::Network)
+                    }
+                },
+                _ => ErrorClass::of_http(e.get_status(), e.get_body()).unwrap_or_default(),
+            },
+            AINodeErrorType::EmbeddingError(e) => match e.get_error_type() {
+                EmbeddingErrorType::ApiKeyError => ErrorClass::Auth,
+                EmbeddingErrorType::RequestError => {
+                    ErrorClass::of_http(e.get_status(), None).unwrap_or(ErrorClass::Network)
+                }
+                _ => ErrorClass::of_http(e.get_status(), None).unwrap_or_default(),
+            },
+            AINodeErrorType::RerankError(e) => match e.get_error_type() {
+                RerankErrorType::ApiKeyError => ErrorClass::Auth,
+                RerankErrorType::RequestError => {
+                    ErrorClass::of_http(e.get_status(), None).unwrap_or(ErrorClass::Network)
+                }
+                _ => ErrorClass::of_http(e.get_status(), None).unwrap_or_default(),
+            },
+            AINodeErrorType::InvalidJsonOutput
+            | AINodeErrorType::SchemaViolation(_)
+            | AINodeErrorType::RouteError => ErrorClass::InvalidOutput,
+            _ => ErrorClass::Other,
+        }
+    }
+    /// Get the class of a failed answer of an AI service from its HTTP status and its body,
+    /// or `None` without an answer.
+    pub fn of_http(status: Option<u16>, body: Option<&str>) -> Option<Self> {
+        // the type of the error in the body tells more than the status, like a 400 that is
+        // a rate limit
+        let error = body
+            .and_then(|body| serde_json::from_str::<serde_json::Value>(body).ok())
+            .map(|body| {
+                let error = &body["error"];
+                format!(
+                    "{} {}",
+                    error["type"].as_str().unwrap_or_default(),
+                    error["code"].as_str().unwrap_or_default()
+                )
+                .to_lowercase()
+            })
+            .unwrap_or_default();
+        if error.contains("rate_limit") {
+            return Some(ErrorClass::RateLimit);
+        } else if error.contains("auth") || error.contains("permission") {
+            return Some(ErrorClass::Auth);
+        } else if error.contains("invalid_request") {
+            return Some(ErrorClass::InvalidRequest);
+        }
+        Some(match status? {
+            408 => ErrorClass::Network,
+            429 => ErrorClass::RateLimit,
+            401 | 403 => ErrorClass::Auth,
+            400..500 => ErrorClass::InvalidRequest,
+            500..600 => ErrorClass::Server,
+            _ => ErrorClass::Other,
+        })
+    }
+    /// Whether the errors of the class are transient, so a retry may succeed.
+    pub fn is_retryable(&self) -> bool {
+        matches!(
+            self,
+            ErrorClass::Network | ErrorClass::Timeout | ErrorClass::RateLimit | ErrorClass::Server
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+/// The enum of the wait between two attempts.
+pub enum Backoff {
+    /// Retry at once.
+    None,
+    /// Wait the same time before every retry.
+    Fixed(Duration),
+    /// Wait `initial`, then double the wait before every retry, up to `max`.
+    Exponential { initial: Duration, max: Duration },
+}
+
+impl Backoff {
+    /// Get the wait before the given retry, counted from 1.
+    pub fn delay(&self, retry: usize) -> Duration {
+        match *self {
+            Backoff::None => Duration::ZERO,
+            Backoff::Fixed(delay) => delay,
+            Backoff::Exponential { initial, max } => {
+                let factor = 2u32.saturating_pow(retry.saturating_sub(1) as u32);
+                initial.saturating_mul(factor).min(max)
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+/// The struct of the retry policy of a worknode.
+pub struct RetryPolicy {
+    /// The max number of attempts, including the first one. 1 means no retry.
+    max_attempts: usize,
+    /// The wait between two attempts.
+    backoff: Backoff,
+    /// The classes of the errors to retry.
+    retry_on: Vec<ErrorClass>,
+    /// The max time of an attempt, or `None` to let it run as long as it takes.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    timeout: Option<Duration>,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_attempts: 1,
+            backoff: Self::default_backoff(),
+            retry_on: Self::default_retry_on(),
+            timeout: None,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Create a new RetryPolicy with the default backoff and error classes.
+    pub fn new(max_attempts: usize) -> Self {
+        RetryPolicy {
+            max_attempts,
+            ..Default::default()
+        }
+    }
+    /// Set the backoff as builder.
+    pub fn backoff(mut self, backoff: Backoff) -> Self {
+        self.backoff = backoff;
+        self
+    }
+    /// Set the classes of the errors to retry as builder.
+    pub fn retry_on(mut self, retry_on: Vec<ErrorClass>) -> Self {
+        self.retry_on = retry_on;
+        self
+    }
+    /// Set the max time of an attempt as builder.
+    pub fn timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.timeout = timeout;
+        self
+    }
+    /// Get the max number of attempts.
+    pub fn get_max_attempts(&self) -> usize {
+        self.max_attempts
+    }
+    /// Get the backoff.
+    pub fn get_backoff(&self) -> Backoff {
+        self.backoff
+    }
+    /// Get the classes of the errors to retry.
+    pub fn get_retry_on(&self) -> &[ErrorClass] {
+        &self.retry_on
+    }
+    /// Get the max time of an attempt.
+    pub fn get_timeout(&self) -> Option<Duration> {
+        self.timeout
+    }
+    /// Whether the error should be retried after the given number of attempts.
+    pub fn should_retry(&self, error: &PilotError, attempts: usize) -> bool {
+        attempts < self.max_attempts && self.retry_on.contains(&ErrorClass::of(error))
+    }
+    /// The default backoff, from 1 second up to 1 minute.
+    pub fn default_backoff() -> Backoff {
+        Backoff::Exponential {
+            initial: Duration::from_secs(1),
+            max: Duration::from_secs(60),
+        }
+    }
+    /// The default classes of the errors to retry, which are the transient ones.
+    pub fn default_retry_on() -> Vec<ErrorClass> {
+        vec![
+            ErrorClass::Network,
+            ErrorClass::Timeout,
+            ErrorClass::RateLimit,
+            ErrorClass::Server,
+        ]
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::error::ai_node_error::deepseek_error::DeepSeekError;
+    use crate::error::ai_node_error::AINodeError;
+
+    fn deepseek_error(error_type: DeepSeekErrorType, status: Option<u16>) -> PilotError {
+        let e = DeepSeekError::new(error_type, "failed".to_string()).status(status);
+        PilotError::new(
+            PilotErrorType::AINodeErr(AINodeError::new(
+                AINodeErrorType::DeepSeekError(Box::new(e)),
+                "failed".to_string(),
+            )),
+            "failed".to_string(),
+        )
+    }
+
+    #[test]
+    fn classify_errors() {
+        let rate_limit = deepseek_error(DeepSeekErrorType::ResponseError, Some(429));
+        let server = deepseek_error(DeepSeekErrorType::ResponseError, Some(503));
+        let network = deepseek_error(DeepSeekErrorType::RequestError, None);
+        let api_key = deepseek_error(DeepSeekErrorType::ResponseError, Some(401));
+        assert_eq!(ErrorClass::of(&rate_limit), ErrorClass::RateLimit);
+        assert_eq!(ErrorClass::of(&server), ErrorClass::Server);
+        assert_eq!(ErrorClass::of(&network), ErrorClass::Network);
+        assert_eq!(ErrorClass::of(&api_key), ErrorClass::Auth);
+        assert!(server.is_retryable());
+        assert!(!api_key.is_retryable());
+        assert_eq!(
+            ErrorClass::of(&deepseek_error(
+                DeepSeekErrorType::RequestParamError(Box::default()),
+                None
+            )),
+            ErrorClass::InvalidRequest
+        );
+        assert_eq!(
+            ErrorClass::of_http(
+                Some(400),
+                Some(r#"{"error": {"message": "slow down", "type": "rate_limit_error"}}"#)
+            ),
+            Some(ErrorClass::RateLimit)
+        );
+        assert_eq!(
+            ErrorClass::of_http(Some(422), Some("not json")),
+            Some(ErrorClass::InvalidRequest)
+        );
+        assert_eq!(ErrorClass::of_http(None, None), None);
+
+        let policy = RetryPolicy::new(3);
+        assert!(policy.should_retry(&rate_limit, 1));
+        assert!(policy.should_retry(&server, 2));
+        assert!(!policy.should_retry(&server, 3));
+        assert!(!policy.should_retry(&api_key, 1));
+    }
+
+    #[test]
+    fn classify_http_answers() {
+        // (status, body, class, retryable)
+        let answers = [
+            (Some(500), None, ErrorClass::Server, true),
+            (
+                Some(503),
+                Some("Service Unavailable"),
+                ErrorClass::Server,
+                true,
+            ),
+            (Some(408), None, ErrorClass::Network, true),
+            (Some(429), None, ErrorClass::RateLimit, true),
+            (
+                Some(400),
+                Some(r#"{"error": {"message": "slow down", "code": "rate_limit_exceeded"}}"#),
+                ErrorClass::RateLimit,
+                true,
+            ),
+            (Some(401), None, ErrorClass::Auth, false),
+            (Some(403), Some("Forbidden"), ErrorClass::Auth, false),
+            (
+                Some(400),
+                Some(r#"{"error": {"message": "bad key", "type": "authentication_error"}}"#),
+                ErrorClass::Auth,
+                false,
+            ),
+            (Some(404), None, ErrorClass::InvalidRequest, false),
+            (
+                Some(500),
+                Some(r#"{"error": {"message": "too long", "type": "invalid_request_error"}}"#),
+                ErrorClass::InvalidRequest,
+                false,
+            ),
+            (Some(302), None, ErrorClass::Other, false),
+        ];
+        for (status, body, class, retryable) in answers {
+            let e = DeepSeekError::new(DeepSeekErrorType::ResponseError, "failed".to_string())
+                .status(status)
+                .body(body.map(str::to_string));
+            let error: PilotError = AINodeError::from(e).into();
+            assert_eq!(error.class(), class, "{:?} {:?}", status, body);
+            assert_eq!(error.is_retryable(), retryable, "{:?} {:?}", status, body);
+        }
+    }
+
+    #[test]
+    fn attempt_timeout() {
+        use crate::worknode::delay::DelayNode;
+        use crate::worknode::{Worknode, Worknodecore};
+
+        let policy = RetryPolicy::new(2)
+            .backoff(Backoff::None)
+            .timeout(Some(Duration::from_millis(50)));
+        let mut node = Worknode::new(Worknodecore::Delay(DelayNode::duration(
+            Duration::from_secs(10),
+        )))
+        .retry_policy(policy);
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        let error = rt.block_on(node.excute("input".to_string())).unwrap_err();
+        assert_eq!(error.code(), "timeout.total");
+        assert_eq!(error.class(), ErrorClass::Timeout);
+        // a timeout is transient, so the attempt is retried
+        assert_eq!(node.get_attempts(), 2);
+        let json = serde_json::to_value(node.get_retry_policy()).unwrap();
+        assert_eq!(json["timeout"]["nanos"], 50_000_000);
+    }
+
+    #[test]
+    fn exponential_backoff() {
+        let backoff = Backoff::Exponential {
+            initial: Duration::from_secs(1),
+            max: Duration::from_secs(5),
+        };
+        assert_eq!(backoff.delay(1), Duration::from_secs(1));
+        assert_eq!(backoff.delay(3), Duration::from_secs(4));
+        assert_eq!(backoff.delay(4), Duration::from_secs(5));
+        assert_eq!(backoff.delay(100), Duration::from_secs(5));
+    }
+}
@@ -0,0 +1,272 @@
+//! # Router
+//!
+//! This node asks an AI service, usually a cheap model, to classify its input into one of the
+//! declared labels, and the run goes on only down the route edges of the chosen label (see
+//! `Workflow::add_route_edge`). This is the intent router: a request is read once and sent to
+//! the branch that handles it.
+//!
+//! The AI service is told the labels with their descriptions and asked to answer with one
+//! label only, at temperature 0. The answer is matched to the labels ignoring the case and the
+//! quotes or punctuation around it, or else by the only label it mentions. When nothing
+//! matches, the fallback label is chosen, and without a fallback the node fails.
+//!
+//! The output of the node is its input, so the chosen branch gets what was routed. The edges
+//! of the node without a label are followed whatever the label is.
+
+use super::ai_node::{AINode, Chat, RequestOverrides, Role};
+use crate::error::ai_node_error::{AINodeError, AINodeErrorType, AINodeResult};
+
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+/// The instruction that makes the AI service answer with a label.
+const ROUTER_PROMPT: &str = "Classify the message of the user into exactly one of the \
+following labels. Answer with the label only, without any other word.";
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+/// The struct of a label that the input can be classified into.
+pub struct Route {
+    /// The label, which names the route edges.
+    pub label: String,
+    /// What belongs to the label, told to the AI service.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+/// The struct of the router node.
+pub struct RouterNode {
+    /// The AI node that classifies the input. Its role, if any, is added to the instruction.
+    node: AINode,
+    /// The labels that the input can be classified into.
+    routes: Vec<Route>,
+    /// The label chosen when the answer matches no label.
+    fallback: Option<String>,
+    /// The label chosen in the last execution.
+    label: Option<String>,
+}
+
+impl RouterNode {
+    /// Create a new RouterNode with the AI node that classifies the input.
+    pub fn new(node: AINode) -> Self {
+        RouterNode {
+            node,
+            routes: Vec::new(),
+            fallback: None,
+            label: None,
+        }
+    }
+    /// Classify the input and choose its label. The input is the output.
+    pub async fn execute(&mut self, input: String) -> AINodeResult<String> {
+        self.label = None;
+        let answer = self
+            .node
+            .complete(&self.chats(&input), &Self::overrides())
+            .await?;
+        let label = self
+            .match_label(&answer)
+            .or_else(|| self.fallback.clone())
+            .ok_or_else(|| {
+                AINodeError::new(
+                    AINodeErrorType::RouteError,
+                    format!(
+                        "The answer `{}` matches none of the labels [{}].",
+                        answer.trim(),
+                        self.labels().join(", ")
+                    ),
+                )
+            })?;
+        self.label = Some(label);
+        Ok(input)
+    }
+    /// Build the chats sent to the AI service for the input.
+    pub(crate) fn chats(&self, input: &str) -> Vec<Chat> {
+        let mut instruction = String::new();
+        if let Some(role) = self.node.get_role() {
+            instruction.push_str(role);
+            instruction.push_str("\n\n");
+        }
+        instruction.push_str(ROUTER_PROMPT);
+        for route in &self.routes {
+            match &route.description {
+                Some(description) => {
+                    instruction.push_str(&format!("\n- {}: {}", route.label, description))
+                }
+                None => instruction.push_str(&format!("\n- {}", route.label)),
+            }
+        }
+        vec![
+            Chat::new(Role::System, instruction),
+            Chat::new(Role::User, input.to_string()),
+        ]
+    }
+    /// The parameters of the classification request.
+    pub(crate) fn overrides() -> RequestOverrides {
+        RequestOverrides::new().temperature(0.0)
+    }
+    /// Get the label that the answer names: the label equal to the answer, or the only label
+    /// mentioned in the answer.
+    pub fn match_label(&self, answer: &str) -> Option<String> {
+        let answer = normalize(answer);
+        if let Some(route) = self
+            .routes
+            .iter()
+            .find(|route| normalize(&route.label) == answer)
+        {
+            return Some(route.label.clone());
+        }
+        let mentioned: Vec<&Route> = self
+            .routes
+            .iter()
+            .filter(|route| mentions(&answer, &normalize(&route.label)))
+            .collect();
+        match mentioned.as_slice() {
+            [route] => Some(route.label.clone()),
+            _ => None,
+        }
+    }
+    /// Get the labels of the routes.
+    pub fn labels(&self) -> Vec<&str> {
+        self.routes
+            .iter()
+            .map(|route| route.label.as_str())
+            .collect()
+    }
+    /// Whether the label is one that the node can choose, a label of a route or the fallback.
+    pub fn has_label(&self, label: &str) -> bool {
+        self.labels().contains(&label) || self.fallback.as_deref() == Some(label)
+    }
+    /// Add a label with the description of what belongs to it as builder.
+    pub fn route(mut self, label: &str, description: Option<&str>) -> Self {
+        self.add_route(label, description);
+        self
+    }
+    /// Add a label with the description of what belongs to it. A label that is already there
+    /// gets the new description.
+    pub fn add_route(&mut self, label: &str, description: Option<&str>) {
+        let description = description.map(str::to_string);
+        match self.routes.iter_mut().find(|route| route.label == label) {
+            Some(route) => route.description = description,
+            None => self.routes.push(Route {
+                label: label.to_string(),
+                description,
+            }),
+        }
+    }
+    /// Set the labels as builder.
+    pub fn routes(mut self, routes: Vec<Route>) -> Self {
+        self.routes = routes;
+        self
+    }
+    /// Set the labels.
+    pub fn set_routes(&mut self, routes: Vec<Route>) {
+        self.routes = routes;
+    }
+    /// Get the labels with their descriptions.
+    pub fn get_routes(&self) -> &Vec<Route> {
+        &self.routes
+    }
+    /// Set the fallback label as builder.
+    pub fn fallback(mut self, fallback: Option<String>) -> Self {
+        self.fallback = fallback;
+        self
+    }
+    /// Set the fallback label.
+    pub fn set_fallback(&mut self, fallback: Option<String>) {
+        self.fallback = fallback;
+    }
+    /// Get the fallback label.
+    pub fn get_fallback(&self) -> Option<&str> {
+        self.fallback.as_deref()
+    }
+    /// Get the label chosen in the last execution.
+    pub fn get_label(&self) -> Option<&str> {
+        self.label.as_deref()
+    }
+    /// Get the AI node that classifies the input.
+    pub fn get_node(&self) -> &AINode {
+        &self.node
+    }
+    /// Get the mutable AI node that classifies the input.
+    pub fn get_node_mut(&mut self) -> &mut AINode {
+        &mut self.node
+    }
+    /// Set the uid of the worknode that holds this node.
+    pub fn set_node_uid(&mut self, node_uid: Option<Uuid>) {
+        self.node.set_node_uid(node_uid);
+    }
+}
+
+/// Lowercase the text and trim the spaces, quotes and punctuation around it.
+fn normalize(text: &str) -> String {
+    text.trim_matches(|c: char| !c.is_alphanumeric())
+        .to_lowercase()
+}
+
+/// Whether the text mentions the label as a whole word.
+fn mentions(text: &str, label: &str) -> bool {
+    let is_word = |c: char| c.is_alphanumeric() || c == '_' || c == '-';
+    !label.is_empty()
+        && text.match_indices(label).any(|(start, _)| {
+            let before = text[..start].chars().next_back();
+            let after = text[start + label.len()..].chars().next();
+            !before.is_some_and(is_word) && !after.is_some_and(is_word)
+        })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::worknode::ai_node::deepseek::{DeepSeekClient, DeepSeekModel, DEEPSEEK_API_URL};
+    use crate::worknode::ai_node::recording::Recording;
+    use crate::worknode::ai_node::AIService;
+
+    use tokio::runtime::Runtime;
+
+    fn router(client: DeepSeekClient) -> RouterNode {
+        RouterNode::new(AINode::new(AIService::DeepSeek { client }))
+            .route("billing", Some("invoices, payments and refunds"))
+            .route("tech_support", None)
+    }
+
+    #[test]
+    fn route_answers() {
+        let client = DeepSeekClient::new(DEEPSEEK_API_URL, DeepSeekModel::DeepseekChat);
+        let plain = router(client.clone());
+        assert_eq!(
+            plain.match_label(" \"Billing\".\n"),
+            Some("billing".to_string())
+        );
+        assert_eq!(
+            plain.match_label("The label is tech_support."),
+            Some("tech_support".to_string())
+        );
+        assert_eq!(plain.match_label("billing or tech_support"), None);
+        assert_eq!(plain.match_label("tech_supports"), None);
+
+        let input = "I was charged twice";
+        let chats = plain.chats(input);
+        let overrides = RouterNode::overrides();
+        let recording = Recording::replay(vec![
+            client.exchange(&chats, &overrides, "billing"),
+            client.exchange(&chats, &overrides, "sales"),
+            client.exchange(&chats, &overrides, "sales"),
+        ]);
+        let mut node = router(client.recording(Some(recording)));
+        let rt = Runtime::new().unwrap();
+        let output = rt.block_on(node.execute(input.to_string())).unwrap();
+        assert_eq!(output, input);
+        assert_eq!(node.get_label(), Some("billing"));
+
+        let error = rt.block_on(node.execute(input.to_string())).unwrap_err();
+        assert!(matches!(
+            error.get_error_type(),
+            AINodeErrorType::RouteError
+        ));
+        assert_eq!(node.get_label(), None);
+        node.set_fallback(Some("human".to_string()));
+        rt.block_on(node.execute(input.to_string())).unwrap();
+        assert_eq!(node.get_label(), Some("human"));
+        assert!(node.has_label("human"));
+    }
+}
@@ -0,0 +1,205 @@
+//! # Script
+//!
+//! This node runs a [Rhai](https://rhai.rs) script, for the light transformations and the glue
+//! logic between nodes that don't need a local command or a call to the AI service, like
+//! picking a field out of a json answer or joining some values of the context.
+//!
+//! The script gets two variables:
+//! - `input`: the input of the node, as a string. `parse_json(input)` reads a json input.
+//! - `context`: the values of the run context, as a map of their json values. The keys that
+//!   the script sets, changes or removes in the map are written back to the run context.
+//!
+//! The value of the last statement (or of `return`) is the output of the node: a string is
+//! output as it is, `()` as an empty string, and any other value as json.
+//!
+//! A script can't access files, the network or the environment. It is limited in the
+//! operations it runs, so a script that loops forever fails the node instead of blocking the
+//! run. What the script prints is logged.
+
+use crate::error::script_node_error::{ScriptNodeError, ScriptNodeErrorType, ScriptNodeResult};
+use crate::workflow::context::RunContext;
+
+use rhai::{Dynamic, Engine, Map, Scope};
+use serde::{Deserialize, Serialize};
+
+/// The operations a script may run by default.
+pub const DEFAULT_MAX_OPERATIONS: u64 = 1_000_000;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+/// The struct of the script node.
+pub struct ScriptNode {
+    /// The source of the script.
+    script: String,
+    /// The operations the script may run, or `None` for no limit.
+    max_operations: Option<u64>,
+}
+
+impl Default for ScriptNode {
+    fn default() -> Self {
+        ScriptNode {
+            script: String::new(),
+            max_operations: Some(DEFAULT_MAX_OPERATIONS),
+        }
+    }
+}
+
+impl ScriptNode {
+    /// Create a new ScriptNode that runs the script.
+    pub fn new(script: &str) -> Self {
+        ScriptNode {
+            script: script.to_string(),
+            ..Self::default()
+        }
+    }
+    /// Run the script with the input and the run context, and get its output.
+    pub fn execute(&self, input: String, context: &RunContext) -> ScriptNodeResult<String> {
+        let mut engine = Engine::new();
+        engine.set_max_operations(self.max_operations.unwrap_or(0));
+        engine.on_print(|text| log::info!("Script: {}", text));
+        engine.on_debug(|text, _, position| log::debug!("Script at {}: {}", position, text));
+        let ast = engine.compile(&self.script).map_err(|e| {
+            script_error(
+                ScriptNodeErrorType::CompileError,
+                format!("The script can't be compiled. {}", e),
+            )
+        })?;
+        let snapshot = context.snapshot();
+        let mut values = Map::new();
+        for (key, value) in &snapshot {
+            values.insert(key.into(), to_dynamic(value)?);
+        }
+        let mut scope = Scope::new();
+        scope.push("input", input);
+        scope.push("context", values);
+        let output: Dynamic = engine.eval_ast_with_scope(&mut scope, &ast).map_err(|e| {
+            script_error(
+                ScriptNodeErrorType::RuntimeError,
+                format!("The script failed. {}", e),
+            )
+        })?;
+        // write back what the script changed, so the keys set by other nodes meanwhile stay
+        let values = scope.get_value::<Map>("context").ok_or_else(|| {
+            script_error(
+                ScriptNodeErrorType::OutputError,
+                "The script replaced `context` with a value that is not a map.".to_string(),
+            )
+        })?;
+        for (key, value) in &values {
+            let value = to_json(value)?;
+            if snapshot.get(key.as_str()) != Some(&value) {
+                context.set_value(key, value);
+            }
+        }
+        for key in snapshot.keys() {
+            if !values.contains_key(key.as_str()) {
+                context.remove(key);
+            }
+        }
+        if output.is_unit() {
+            return Ok(String::new());
+        }
+        if output.is_string() {
+            return Ok(output.into_string().unwrap_or_default());
+        }
+        Ok(to_json(&output)?.to_string())
+    }
+    /// Set the script as builder.
+    pub fn script(mut self, script: &str) -> Self {
+        self.script = script.to_string();
+        self
+    }
+    /// Set the script.
+    pub fn set_script(&mut self, script: &str) {
+        self.script = script.to_string();
+    }
+    /// Get the script.
+    pub fn get_script(&self) -> &str {
+        &self.script
+    }
+    /// Set the operations the script may run as builder.
+    pub fn max_operations(mut self, max_operations: Option<u64>) -> Self {
+        self.max_operations = max_operations;
+        self
+    }
+    /// Set the operations the script may run.
+    pub fn set_max_operations(&mut self, max_operations: Option<u64>) {
+        self.max_operations = max_operations;
+    }
+    /// Get the operations the script may run.
+    pub fn get_max_operations(&self) -> Option<u64> {
+        self.max_operations
+    }
+}
+
+/// Create a ScriptNodeError.
+fn script_error(error_type: ScriptNodeErrorType, message: String) -> ScriptNodeError {
+    ScriptNodeError::new(error_type, message)
+}
+
+/// Convert a json value of the context to a value of the script.
+fn to_dynamic(value: &serde_json::Value) -> ScriptNodeResult<Dynamic> {
+    rhai::serde::to_dynamic(value).map_err(|e| {
+        script_error(
+            ScriptNodeErrorType::OutputError,
+            format!("A value of the context can't be given to the script. {}", e),
+        )
+    })
+}
+
+/// Convert a value of the script to json.
+fn to_json(value: &Dynamic) -> ScriptNodeResult<serde_json::Value> {
+    rhai::serde::from_dynamic(value).map_err(|e| {
+        script_error(
+            ScriptNodeErrorType::OutputError,
+            format!("The value {} can't be converted to json. {}", value, e),
+        )
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn run_scripts() {
+        let context = RunContext::new();
+        context.set("name", "Tom").unwrap();
+        context.set("scores", vec![1, 2, 3]).unwrap();
+        context.set("draft", true).unwrap();
+
+        let script = ScriptNode::new(
+            r#"
+                let answer = parse_json(input);
+                context.total = context.scores.reduce(|sum, x| sum + x, 0);
+                context.remove("draft");
+                `${context.name}: ${answer.grade}`
+            "#,
+        );
+        let output = script.execute(r#"{"grade": "A"}"#.to_string(), &context);
+        assert_eq!(output.unwrap(), "Tom: A");
+        assert_eq!(context.get::<i64>("total").unwrap(), Some(6));
+        assert!(!context.contains("draft"));
+        assert_eq!(context.get::<String>("name").unwrap().unwrap(), "Tom");
+
+        let json = ScriptNode::new("#{ upper: input.to_upper(), size: input.len() }");
+        let output = json.execute("hi".to_string(), &context).unwrap();
+        assert_eq!(
+            serde_json::from_str::<serde_json::Value>(&output).unwrap(),
+            serde_json::json!({ "upper": "HI", "size": 2 })
+        );
+
+        let endless = ScriptNode::new("loop {}").max_operations(Some(1000));
+        let error = endless.execute(String::new(), &context);
+        assert!(matches!(
+            error.unwrap_err().get_error_type(),
+            ScriptNodeErrorType::RuntimeError
+        ));
+        let invalid = ScriptNode::new("let = ;");
+        let error = invalid.execute(String::new(), &context);
+        assert!(matches!(
+            error.unwrap_err().get_error_type(),
+            ScriptNodeErrorType::CompileError
+        ));
+    }
+}
@@ -0,0 +1,155 @@
+//! # Tool
+//!
+//! This node calls one tool of a tool registry (see [`super::ai_node::tool`]) directly,
+//! without asking an AI service, like a step of a plan that searches the web or calculates.
+//!
+//! The arguments of the call are a json template: every string in them is rendered (see
+//! [`crate::template`]) with the variable `input` and the variables of the run context. A node
+//! without arguments calls the tool with its input, which must be a json object. The output
+//! is the result of the tool, and a tool that fails fails the node.
+
+use super::ai_node::ToolRegistry;
+use crate::error::ai_node_error::{AINodeError, AINodeErrorType, AINodeResult};
+use crate::template::{self, Variables};
+use crate::workflow::context::RunContext;
+
+use serde_json::Value;
+
+#[derive(Debug, Clone)]
+/// The struct of the tool node.
+pub struct ToolNode {
+    /// The tools that the tool is found in.
+    tools: ToolRegistry,
+    /// The name of the tool.
+    tool: String,
+    /// The json template of the arguments, or `None` to call the tool with the input.
+    arguments: Option<Value>,
+}
+
+impl ToolNode {
+    /// Create a new ToolNode that calls the tool of the registry with its input.
+    pub fn new(tools: ToolRegistry, tool: &str) -> Self {
+        ToolNode {
+            tools,
+            tool: tool.to_string(),
+            arguments: None,
+        }
+    }
+    /// Call the tool and get its result.
+    pub async fn execute(&self, input: String, context: &RunContext) -> AINodeResult<String> {
+        let arguments = match &self.arguments {
+            Some(arguments) => {
+                let mut variables = context.to_variables();
+                variables.insert("input".to_string(), input);
+                render(arguments, &variables)?
+            }
+            None => serde_json::from_str(&input).map_err(|e| {
+                tool_error(format!(
+                    "The input is not the json arguments of {}. {}",
+                    self.tool, e
+                ))
+            })?,
+        };
+        self.tools
+            .invoke(&self.tool, arguments)
+            .await
+            .map_err(|e| tool_error(format!("The tool {} failed: {}", self.tool, e)))
+    }
+    /// Set the tools as builder.
+    pub fn tools(mut self, tools: ToolRegistry) -> Self {
+        self.tools = tools;
+        self
+    }
+    /// Set the tools.
+    pub fn set_tools(&mut self, tools: ToolRegistry) {
+        self.tools = tools;
+    }
+    /// Get the tools.
+    pub fn get_tools(&self) -> &ToolRegistry {
+        &self.tools
+    }
+    /// Get the name of the tool.
+    pub fn get_tool(&self) -> &str {
+        &self.tool
+    }
+    /// Set the json template of the arguments as builder.
+    pub fn arguments(mut self, arguments: Option<Value>) -> Self {
+        self.arguments = arguments;
+        self
+    }
+    /// Set the json template of the arguments.
+    pub fn set_arguments(&mut self, arguments: Option<Value>) {
+        self.arguments = arguments;
+    }
+    /// Get the json template of the arguments.
+    pub fn get_arguments(&self) -> Option<&Value> {
+        self.arguments.as_ref()
+    }
+}
+
+/// Render the strings of the json template.
+fn render(arguments: &Value, variables: &Variables) -> AINodeResult<Value> {
+    Ok(match arguments {
+        Value::String(text) => Value::String(template::render(text, variables).map_err(|e| {
+            AINodeError::new(
+                AINodeErrorType::TemplateError(e),
+                "Failed to render the arguments of the tool.".to_string(),
+            )
+        })?),
+        Value::Array(items) => Value::Array(
+            items
+                .iter()
+                .map(|item| render(item, variables))
+                .collect::<AINodeResult<_>>()?,
+        ),
+        Value::Object(members) => Value::Object(
+            members
+                .iter()
+                .map(|(key, value)| Ok((key.clone(), render(value, variables)?)))
+                .collect::<AINodeResult<_>>()?,
+        ),
+        value => value.clone(),
+    })
+}
+
+/// Create an AINodeError of the tool.
+fn tool_error(message: String) -> AINodeError {
+    AINodeError::new(AINodeErrorType::ToolError, message)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::worknode::ai_node::tool::calculate;
+
+    use serde_json::json;
+    use tokio::runtime::Runtime;
+
+    #[test]
+    fn call_tool() {
+        let rt = Runtime::new().unwrap();
+        let mut tools = ToolRegistry::new();
+        calculate::register(&mut tools);
+        let context = RunContext::new();
+        context.set("price", 12).unwrap();
+
+        let node = ToolNode::new(tools.clone(), "calculate").arguments(Some(json!({
+            "expression": "{{context.price}} * {{input}}"
+        })));
+        let output = rt.block_on(node.execute("3".to_string(), &context));
+        assert_eq!(output.unwrap(), "36");
+
+        let node = ToolNode::new(tools.clone(), "calculate");
+        let output = rt.block_on(node.execute(r#"{"expression": "2 ^ 10"}"#.to_string(), &context));
+        assert_eq!(output.unwrap(), "1024");
+        let error = rt
+            .block_on(node.execute(r#"{"expression": "1 / 0"}"#.to_string(), &context))
+            .unwrap_err();
+        assert!(matches!(error.get_error_type(), AINodeErrorType::ToolError));
+        assert!(error.get_message().contains("Division by zero"));
+
+        let node = ToolNode::new(tools, "missing");
+        let error = rt.block_on(node.execute("{}".to_string(), &context));
+        assert!(error.unwrap_err().get_message().contains("no tool named"));
+    }
+}
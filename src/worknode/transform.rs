@@ -0,0 +1,183 @@
+//! # Transform
+//!
+//! This node reshapes its json input, like the structured answer of an AI node, before the
+//! next node gets it. The input is selected from with a JSONPath (see [`path`]), like
+//! `$.items[*].title`, or transformed with a jq-style filter (see [`filter`]), like
+//! `.items | map({title, score: .votes * 2})`.
+//!
+//! Both give a list of results. The node outputs the first one, or all of them as a json
+//! array. A result that is a string is output as it is, without the quotes of json, unless
+//! the raw output is turned off.
+
+pub mod filter;
+pub mod path;
+
+use crate::error::transform_node_error::{
+    TransformNodeError, TransformNodeErrorType, TransformNodeResult,
+};
+use filter::Filter;
+use path::JsonPath;
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+/// The enum of the expression that transforms the input.
+pub enum Transform {
+    /// Select the values of a JSONPath.
+    Path(String),
+    /// Run a jq-style filter.
+    Filter(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+/// The struct of the json transform node.
+pub struct JsonTransformNode {
+    /// The expression that transforms the input.
+    transform: Transform,
+    /// Whether all results are output as a json array, instead of the first one.
+    all: bool,
+    /// Whether a string result is output without quotes.
+    raw: bool,
+}
+
+impl Default for JsonTransformNode {
+    fn default() -> Self {
+        JsonTransformNode {
+            transform: Transform::Filter(".".to_string()),
+            all: false,
+            raw: true,
+        }
+    }
+}
+
+impl JsonTransformNode {
+    /// Create a new JsonTransformNode that selects the values of the JSONPath.
+    pub fn path(path: &str) -> Self {
+        JsonTransformNode {
+            transform: Transform::Path(path.to_string()),
+            ..Self::default()
+        }
+    }
+    /// Create a new JsonTransformNode that runs the filter.
+    pub fn filter(filter: &str) -> Self {
+        JsonTransformNode {
+            transform: Transform::Filter(filter.to_string()),
+            ..Self::default()
+        }
+    }
+    /// Check that the expression is valid, without an input.
+    pub fn check(&self) -> TransformNodeResult<()> {
+        match &self.transform {
+            Transform::Path(path) => JsonPath::parse(path).map(|_| ()),
+            Transform::Filter(filter) => Filter::parse(filter).map(|_| ()),
+        }
+    }
+    /// Transform the input, and get the output.
+    pub fn execute(&self, input: String) -> TransformNodeResult<String> {
+        let input: serde_json::Value = serde_json::from_str(&input).map_err(|e| {
+            TransformNodeError::new(
+                TransformNodeErrorType::InvalidInput,
+                format!("The input is not valid json. {}", e),
+            )
+        })?;
+        let mut results = match &self.transform {
+            Transform::Path(path) => JsonPath::parse(path)?.select(&input),
+            Transform::Filter(filter) => Filter::parse(filter)?.run(&input)?,
+        };
+        let output = match self.all {
+            true => serde_json::Value::Array(results),
+            false if results.is_empty() => {
+                return Err(TransformNodeError::new(
+                    TransformNodeErrorType::NoResult,
+                    "The expression gave no result.".to_string(),
+                ))
+            }
+            false => results.swap_remove(0),
+        };
+        Ok(match output {
+            serde_json::Value::String(text) if self.raw => text,
+            output => output.to_string(),
+        })
+    }
+    /// Set the expression as builder.
+    pub fn transform(mut self, transform: Transform) -> Self {
+        self.transform = transform;
+        self
+    }
+    /// Set the expression.
+    pub fn set_transform(&mut self, transform: Transform) {
+        self.transform = transform;
+    }
+    /// Get the expression.
+    pub fn get_transform(&self) -> &Transform {
+        &self.transform
+    }
+    /// Set whether all results are output as builder.
+    pub fn all(mut self, all: bool) -> Self {
+        self.all = all;
+        self
+    }
+    /// Set whether all results are output.
+    pub fn set_all(&mut self, all: bool) {
+        self.all = all;
+    }
+    /// Get whether all results are output.
+    pub fn get_all(&self) -> bool {
+        self.all
+    }
+    /// Set whether a string result is output without quotes as builder.
+    pub fn raw(mut self, raw: bool) -> Self {
+        self.raw = raw;
+        self
+    }
+    /// Set whether a string result is output without quotes.
+    pub fn set_raw(&mut self, raw: bool) {
+        self.raw = raw;
+    }
+    /// Get whether a string result is output without quotes.
+    pub fn get_raw(&self) -> bool {
+        self.raw
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn transform_answers() {
+        let answer = r#"{"items": [{"title": "A", "votes": 3}, {"title": "B", "votes": 5}]}"#;
+
+        let first = JsonTransformNode::path("$.items[*].title");
+        assert_eq!(first.execute(answer.to_string()).unwrap(), "A");
+        let all = first.clone().all(true);
+        assert_eq!(all.execute(answer.to_string()).unwrap(), r#"["A","B"]"#);
+        let quoted = first.raw(false);
+        assert_eq!(quoted.execute(answer.to_string()).unwrap(), r#""A""#);
+
+        let filter = JsonTransformNode::filter(
+            ".items | map(select(.votes > 4) | {title, score: .votes * 2})",
+        );
+        assert_eq!(
+            filter.execute(answer.to_string()).unwrap(),
+            r#"[{"score":10,"title":"B"}]"#
+        );
+
+        let none = JsonTransformNode::path("$.missing");
+        assert!(matches!(
+            none.execute(answer.to_string())
+                .unwrap_err()
+                .get_error_type(),
+            TransformNodeErrorType::NoResult
+        ));
+        assert!(matches!(
+            none.execute("not json".to_string())
+                .unwrap_err()
+                .get_error_type(),
+            TransformNodeErrorType::InvalidInput
+        ));
+        assert!(JsonTransformNode::filter(".items |").check().is_err());
+    }
+}
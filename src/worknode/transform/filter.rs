@@ -0,0 +1,1471 @@
+//! # Filter
+//!
+//! This module runs jq-style filters on json. A filter takes a value and outputs zero, one or
+//! several values; the common subset of the jq language is supported:
+//! - `.` is the input, `.name`, `."name"`, `.[0]`, `.[-1]` and `.[2:4]` index it, `.[]`
+//!   outputs all elements or members, and `..` the input and all values below it.
+//! - `a | b` runs `b` on every output of `a`, and `a, b` outputs the outputs of both.
+//! - Literals, `[ ... ]` collects the outputs in an array, and `{name, key: .x, (.k): .v}`
+//!   builds an object. Strings can interpolate filters, like `"\(.name) is \(.age)"`.
+//! - `+ - * / %`, `== != < <= > >=`, `and`, `or`, `a // b` (the outputs of `a` that are not
+//!   `false` or `null`, or else `b`), `if ... then ... elif ... else ... end`, and `a?` that
+//!   ignores the errors of `a`.
+//! - The functions `length`, `keys`, `has(k)`, `map(f)`, `map_values(f)`, `select(f)`,
+//!   `empty`, `error(msg)`, `not`, `type`, `add`, `any`, `all`, `any(f)`, `all(f)`, `range(n)`,
+//!   `first`, `last`, `first(f)`, `last(f)`, `limit(n; f)`, `reverse`, `sort`, `sort_by(f)`,
+//!   `group_by(f)`, `unique`, `unique_by(f)`, `min`, `max`, `min_by(f)`, `max_by(f)`,
+//!   `flatten`, `flatten(depth)`, `to_entries`, `from_entries`, `with_entries(f)`, `join(s)`,
+//!   `split(s)`, `contains(x)`, `startswith(s)`, `endswith(s)`, `ltrimstr(s)`, `rtrimstr(s)`,
+//!   `ascii_downcase`, `ascii_upcase`, `tostring`, `tonumber`, `tojson`, `fromjson`, `floor`,
+//!   `ceil`, `round`, `abs`, and the type selectors `numbers`, `strings`, `booleans`,
+//!   `nulls`, `arrays`, `objects`, `iterables` and `scalars`.
+//!
+//! Variables, `reduce`, assignments, regular expressions and user-defined functions are not
+//! supported.
+
+use crate::error::transform_node_error::{
+    TransformNodeError, TransformNodeErrorType, TransformNodeResult,
+};
+
+use serde_json::{Map, Value};
+
+use std::cmp::Ordering;
+
+/// The most numbers output by `range(n)`.
+const MAX_RANGE: usize = 1_000_000;
+
+/// The punctuation of the filters, the longer ones first.
+const PUNCTUATION: [&str; 25] = [
+    "//", "==", "!=", "<=", ">=", "..", "|", ",", "(", ")", "[", "]", "{", "}", ":", ";", "?", "<",
+    ">", "+", "-", "*", "/", "%", ".",
+];
+
+#[derive(Debug, Clone, PartialEq)]
+/// The enum of a token of a filter.
+enum Token {
+    /// A punctuation or an operator.
+    Punct(&'static str),
+    /// `.name`
+    Field(String),
+    /// A name, like a keyword or a function.
+    Ident(String),
+    Number(Value),
+    Str(Vec<StrPart>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+/// The enum of a part of a string literal.
+enum StrPart {
+    Text(String),
+    /// `\(filter)`
+    Interpolation(Expr),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+/// The enum of the key of an object construction.
+enum ObjectKey {
+    Name(String),
+    Expr(Expr),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+/// The enum of the binary operators.
+enum BinaryOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+/// The enum of the expressions of a filter.
+enum Expr {
+    Identity,
+    Recurse,
+    Literal(Value),
+    Str(Vec<StrPart>),
+    Array(Option<Box<Expr>>),
+    /// The entries, where a missing value is the member of the input with the name.
+    Object(Vec<(ObjectKey, Option<Expr>)>),
+    Index(Box<Expr>, Box<Expr>),
+    Slice(Box<Expr>, Option<Box<Expr>>, Option<Box<Expr>>),
+    Iterate(Box<Expr>),
+    Try(Box<Expr>),
+    Pipe(Box<Expr>, Box<Expr>),
+    Comma(Box<Expr>, Box<Expr>),
+    Alternative(Box<Expr>, Box<Expr>),
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Binary(BinaryOp, Box<Expr>, Box<Expr>),
+    Neg(Box<Expr>),
+    /// The conditions with their branches, and the else branch.
+    If(Vec<(Expr, Expr)>, Option<Box<Expr>>),
+    Call(String, Vec<Expr>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+/// The struct of a parsed filter.
+pub struct Filter {
+    expr: Expr,
+}
+
+impl Filter {
+    /// Parse the filter.
+    pub fn parse(filter: &str) -> TransformNodeResult<Self> {
+        let expr = parse_expr(filter).map_err(|e| {
+            TransformNodeError::new(
+                TransformNodeErrorType::ParseError,
+                format!("The filter `{}` is not valid. {}", filter, e),
+            )
+        })?;
+        Ok(Filter { expr })
+    }
+    /// Run the filter on the input, and get its outputs.
+    pub fn run(&self, input: &Value) -> TransformNodeResult<Vec<Value>> {
+        eval(&self.expr, input).map_err(|e| {
+            TransformNodeError::new(
+                TransformNodeErrorType::EvalError,
+                format!("The filter failed. {}", e),
+            )
+        })
+    }
+}
+
+/// Parse the source of a filter to its expression.
+fn parse_expr(source: &str) -> Result<Expr, String> {
+    let mut parser = Parser {
+        tokens: lex(source)?,
+        pos: 0,
+    };
+    let expr = parser.pipe(true)?;
+    match parser.peek() {
+        None => Ok(expr),
+        Some(token) => Err(format!("Unexpected {:?}.", token)),
+    }
+}
+
+/// Split the source of a filter into tokens.
+fn lex(source: &str) -> Result<Vec<Token>, String> {
+    let chars: Vec<char> = source.chars().collect();
+    let mut tokens = Vec::new();
+    let mut pos = 0;
+    let is_name_start = |c: char| c.is_alphabetic() || c == '_';
+    let is_name = |c: char| c.is_alphanumeric() || c == '_';
+    while pos < chars.len() {
+        let c = chars[pos];
+        if c.is_whitespace() {
+            pos += 1;
+        } else if c == '#' {
+            while pos < chars.len() && chars[pos] != '\n' {
+                pos += 1;
+            }
+        } else if c == '"' {
+            let (parts, end) = lex_string(&chars, pos + 1)?;
+            tokens.push(Token::Str(parts));
+            pos = end;
+        } else if c.is_ascii_digit() {
+            let start = pos;
+            while pos < chars.len()
+                && (chars[pos].is_ascii_digit()
+                    || chars[pos] == '.'
+                    || matches!(chars[pos], 'e' | 'E')
+                    || (matches!(chars[pos], '+' | '-') && matches!(chars[pos - 1], 'e' | 'E')))
+            {
+                pos += 1;
+            }
+            let text: String = chars[start..pos].iter().collect();
+            let number: f64 = text
+                .parse()
+                .map_err(|_| format!("Invalid number {}.", text))?;
+            tokens.push(Token::Number(number_value(number)));
+        } else if c == '.' && chars.get(pos + 1).is_some_and(|&c| is_name_start(c)) {
+            let start = pos + 1;
+            pos = start;
+            while pos < chars.len() && is_name(chars[pos]) {
+                pos += 1;
+            }
+            tokens.push(Token::Field(chars[start..pos].iter().collect()));
+        } else if is_name_start(c) {
+            let start = pos;
+            while pos < chars.len() && is_name(chars[pos]) {
+                pos += 1;
+            }
+            tokens.push(Token::Ident(chars[start..pos].iter().collect()));
+        } else {
+            let punct = PUNCTUATION
+                .iter()
+                .find(|punct| {
+                    punct
+                        .chars()
+                        .enumerate()
+                        .all(|(i, c)| chars.get(pos + i) == Some(&c))
+                })
+                .ok_or_else(|| format!("Unexpected character `{}`.", c))?;
+            tokens.push(Token::Punct(punct));
+            pos += punct.len();
+        }
+    }
+    Ok(tokens)
+}
+
+/// Read a string literal that starts after its quote, and get its parts and the position
+/// after its closing quote.
+fn lex_string(chars: &[char], mut pos: usize) -> Result<(Vec<StrPart>, usize), String> {
+    let mut parts = Vec::new();
+    let mut text = String::new();
+    loop {
+        let c = *chars.get(pos).ok_or("Unterminated string.")?;
+        pos += 1;
+        match c {
+            '"' => break,
+            '\\' => {
+                let escaped = *chars.get(pos).ok_or("Unterminated string.")?;
+                pos += 1;
+                match escaped {
+                    'n' => text.push('\n'),
+                    't' => text.push('\t'),
+                    'r' => text.push('\r'),
+                    'b' => text.push('\u{8}'),
+                    'f' => text.push('\u{c}'),
+                    '"' | '\\' | '/' => text.push(escaped),
+                    'u' => {
+                        let hex: String = chars
+                            .get(pos..pos + 4)
+                            .ok_or("Invalid escape.")?
+                            .iter()
+                            .collect();
+                        let code = u32::from_str_radix(&hex, 16).map_err(|_| "Invalid escape.")?;
+                        text.push(char::from_u32(code).ok_or("Invalid escape.")?);
+                        pos += 4;
+                    }
+                    '(' => {
+                        let end = interpolation_end(chars, pos)?;
+                        let source: String = chars[pos..end].iter().collect();
+                        if !text.is_empty() {
+                            parts.push(StrPart::Text(std::mem::take(&mut text)));
+                        }
+                        parts.push(StrPart::Interpolation(parse_expr(&source)?));
+                        pos = end + 1;
+                    }
+                    _ => return Err(format!("Invalid escape `\\{}`.", escaped)),
+                }
+            }
+            c => text.push(c),
+        }
+    }
+    if !text.is_empty() || parts.is_empty() {
+        parts.push(StrPart::Text(text));
+    }
+    Ok((parts, pos))
+}
+
+/// Find the closing parenthesis of an interpolation that starts at the position.
+fn interpolation_end(chars: &[char], mut pos: usize) -> Result<usize, String> {
+    let mut depth = 0;
+    let mut in_string = false;
+    while let Some(&c) = chars.get(pos) {
+        match (in_string, c) {
+            (true, '\\') => pos += 1,
+            (true, '"') | (false, '"') => in_string = !in_string,
+            (false, '(') => depth += 1,
+            (false, ')') if depth == 0 => return Ok(pos),
+            (false, ')') => depth -= 1,
+            _ => {}
+        }
+        pos += 1;
+    }
+    Err("Unterminated interpolation.".to_string())
+}
+
+/// The struct of the parser of the tokens of a filter.
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+    fn eat(&mut self, punct: &str) -> bool {
+        let found = matches!(self.peek(), Some(Token::Punct(p)) if *p == punct);
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+    fn eat_keyword(&mut self, keyword: &str) -> bool {
+        let found = matches!(self.peek(), Some(Token::Ident(name)) if name == keyword);
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+    fn expect(&mut self, punct: &str) -> Result<(), String> {
+        match self.eat(punct) {
+            true => Ok(()),
+            false => Err(format!("Expected `{}`, found {:?}.", punct, self.peek())),
+        }
+    }
+    fn expect_keyword(&mut self, keyword: &str) -> Result<(), String> {
+        match self.eat_keyword(keyword) {
+            true => Ok(()),
+            false => Err(format!("Expected `{}`, found {:?}.", keyword, self.peek())),
+        }
+    }
+    /// Parse the filters joined with `|`, and with `,` if allowed.
+    fn pipe(&mut self, comma: bool) -> Result<Expr, String> {
+        let left = match comma {
+            true => self.comma()?,
+            false => self.alternative()?,
+        };
+        match self.eat("|") {
+            true => Ok(Expr::Pipe(Box::new(left), Box::new(self.pipe(comma)?))),
+            false => Ok(left),
+        }
+    }
+    fn comma(&mut self) -> Result<Expr, String> {
+        let mut left = self.alternative()?;
+        while self.eat(",") {
+            left = Expr::Comma(Box::new(left), Box::new(self.alternative()?));
+        }
+        Ok(left)
+    }
+    fn alternative(&mut self) -> Result<Expr, String> {
+        let left = self.or()?;
+        match self.eat("//") {
+            true => Ok(Expr::Alternative(
+                Box::new(left),
+                Box::new(self.alternative()?),
+            )),
+            false => Ok(left),
+        }
+    }
+    fn or(&mut self) -> Result<Expr, String> {
+        let mut left = self.and()?;
+        while self.eat_keyword("or") {
+            left = Expr::Or(Box::new(left), Box::new(self.and()?));
+        }
+        Ok(left)
+    }
+    fn and(&mut self) -> Result<Expr, String> {
+        let mut left = self.comparison()?;
+        while self.eat_keyword("and") {
+            left = Expr::And(Box::new(left), Box::new(self.comparison()?));
+        }
+        Ok(left)
+    }
+    fn comparison(&mut self) -> Result<Expr, String> {
+        let left = self.additive()?;
+        let op = match self.peek() {
+            Some(Token::Punct("==")) => BinaryOp::Eq,
+            Some(Token::Punct("!=")) => BinaryOp::Ne,
+            Some(Token::Punct("<")) => BinaryOp::Lt,
+            Some(Token::Punct("<=")) => BinaryOp::Le,
+            Some(Token::Punct(">")) => BinaryOp::Gt,
+            Some(Token::Punct(">=")) => BinaryOp::Ge,
+            _ => return Ok(left),
+        };
+        self.pos += 1;
+        Ok(Expr::Binary(op, Box::new(left), Box::new(self.additive()?)))
+    }
+    fn additive(&mut self) -> Result<Expr, String> {
+        let mut left = self.multiplicative()?;
+        loop {
+            let op = match self.peek() {
+                Some(Token::Punct("+")) => BinaryOp::Add,
+                Some(Token::Punct("-")) => BinaryOp::Sub,
+                _ => return Ok(left),
+            };
+            self.pos += 1;
+            left = Expr::Binary(op, Box::new(left), Box::new(self.multiplicative()?));
+        }
+    }
+    fn multiplicative(&mut self) -> Result<Expr, String> {
+        let mut left = self.unary()?;
+        loop {
+            let op = match self.peek() {
+                Some(Token::Punct("*")) => BinaryOp::Mul,
+                Some(Token::Punct("/")) => BinaryOp::Div,
+                Some(Token::Punct("%")) => BinaryOp::Rem,
+                _ => return Ok(left),
+            };
+            self.pos += 1;
+            left = Expr::Binary(op, Box::new(left), Box::new(self.unary()?));
+        }
+    }
+    fn unary(&mut self) -> Result<Expr, String> {
+        match self.eat("-") {
+            true => Ok(Expr::Neg(Box::new(self.postfix()?))),
+            false => self.postfix(),
+        }
+    }
+    /// Parse a term and the indexes, iterations and `?` after it.
+    fn postfix(&mut self) -> Result<Expr, String> {
+        let mut expr = self.term()?;
+        loop {
+            match self.peek() {
+                Some(Token::Field(name)) => {
+                    let name = Value::String(name.clone());
+                    self.pos += 1;
+                    expr = Expr::Index(Box::new(expr), Box::new(Expr::Literal(name)));
+                }
+                Some(Token::Punct(".")) => {
+                    self.pos += 1;
+                    match self.peek() {
+                        Some(Token::Str(_)) => {
+                            let Some(Token::Str(parts)) = self.next() else {
+                                unreachable!()
+                            };
+                            expr = Expr::Index(Box::new(expr), Box::new(Expr::Str(parts)));
+                        }
+                        Some(Token::Punct("[")) => {}
+                        _ => return Err("Expected a name or `[` after `.`.".to_string()),
+                    }
+                }
+                Some(Token::Punct("[")) => {
+                    self.pos += 1;
+                    expr = self.bracket(expr)?;
+                }
+                Some(Token::Punct("?")) => {
+                    self.pos += 1;
+                    expr = Expr::Try(Box::new(expr));
+                }
+                _ => return Ok(expr),
+            }
+        }
+    }
+    /// Parse what is in the brackets after the target, from after `[`.
+    fn bracket(&mut self, target: Expr) -> Result<Expr, String> {
+        let target = Box::new(target);
+        if self.eat("]") {
+            return Ok(Expr::Iterate(target));
+        }
+        if self.eat(":") {
+            let end = self.pipe(true)?;
+            self.expect("]")?;
+            return Ok(Expr::Slice(target, None, Some(Box::new(end))));
+        }
+        let index = self.pipe(true)?;
+        if self.eat(":") {
+            let end = match self.eat("]") {
+                true => return Ok(Expr::Slice(target, Some(Box::new(index)), None)),
+                false => self.pipe(true)?,
+            };
+            self.expect("]")?;
+            return Ok(Expr::Slice(
+                target,
+                Some(Box::new(index)),
+                Some(Box::new(end)),
+            ));
+        }
+        self.expect("]")?;
+        Ok(Expr::Index(target, Box::new(index)))
+    }
+    /// Parse a term, the start of a postfix expression.
+    fn term(&mut self) -> Result<Expr, String> {
+        match self.next() {
+            Some(Token::Punct(".")) => match self.peek() {
+                Some(Token::Str(_)) => {
+                    let Some(Token::Str(parts)) = self.next() else {
+                        unreachable!()
+                    };
+                    Ok(Expr::Index(
+                        Box::new(Expr::Identity),
+                        Box::new(Expr::Str(parts)),
+                    ))
+                }
+                _ => Ok(Expr::Identity),
+            },
+            Some(Token::Punct("..")) => Ok(Expr::Recurse),
+            Some(Token::Field(name)) => Ok(Expr::Index(
+                Box::new(Expr::Identity),
+                Box::new(Expr::Literal(Value::String(name))),
+            )),
+            Some(Token::Number(number)) => Ok(Expr::Literal(number)),
+            Some(Token::Str(parts)) => Ok(Expr::Str(parts)),
+            Some(Token::Punct("(")) => {
+                let expr = self.pipe(true)?;
+                self.expect(")")?;
+                Ok(expr)
+            }
+            Some(Token::Punct("[")) => {
+                if self.eat("]") {
+                    return Ok(Expr::Array(None));
+                }
+                let expr = self.pipe(true)?;
+                self.expect("]")?;
+                Ok(Expr::Array(Some(Box::new(expr))))
+            }
+            Some(Token::Punct("{")) => self.object(),
+            Some(Token::Ident(name)) => match name.as_str() {
+                "true" => Ok(Expr::Literal(Value::Bool(true))),
+                "false" => Ok(Expr::Literal(Value::Bool(false))),
+                "null" => Ok(Expr::Literal(Value::Null)),
+                "if" => self.conditional(),
+                _ => {
+                    let mut args = Vec::new();
+                    if self.eat("(") {
+                        loop {
+                            args.push(self.pipe(true)?);
+                            if self.eat(")") {
+                                break;
+                            }
+                            self.expect(";")?;
+                        }
+                    }
+                    Ok(Expr::Call(name, args))
+                }
+            },
+            token => Err(format!("Unexpected {:?}.", token)),
+        }
+    }
+    /// Parse an object construction, from after `{`.
+    fn object(&mut self) -> Result<Expr, String> {
+        let mut entries = Vec::new();
+        if self.eat("}") {
+            return Ok(Expr::Object(entries));
+        }
+        loop {
+            let key = match self.next() {
+                Some(Token::Ident(name)) => ObjectKey::Name(name),
+                Some(Token::Str(parts)) => match parts.as_slice() {
+                    [StrPart::Text(text)] => ObjectKey::Name(text.clone()),
+                    _ => ObjectKey::Expr(Expr::Str(parts)),
+                },
+                Some(Token::Punct("(")) => {
+                    let expr = self.pipe(true)?;
+                    self.expect(")")?;
+                    ObjectKey::Expr(expr)
+                }
+                token => return Err(format!("Expected a key of the object, found {:?}.", token)),
+            };
+            let value = match self.eat(":") {
+                true => Some(self.pipe(false)?),
+                false => None,
+            };
+            if value.is_none() && !matches!(key, ObjectKey::Name(_)) {
+                return Err("Expected `:` after the key of the object.".to_string());
+            }
+            entries.push((key, value));
+            if self.eat("}") {
+                return Ok(Expr::Object(entries));
+            }
+            self.expect(",")?;
+        }
+    }
+    /// Parse a conditional, from after `if`.
+    fn conditional(&mut self) -> Result<Expr, String> {
+        let mut branches = Vec::new();
+        loop {
+            let condition = self.pipe(true)?;
+            self.expect_keyword("then")?;
+            branches.push((condition, self.pipe(true)?));
+            if !self.eat_keyword("elif") {
+                break;
+            }
+        }
+        let otherwise = match self.eat_keyword("else") {
+            true => Some(Box::new(self.pipe(true)?)),
+            false => None,
+        };
+        self.expect_keyword("end")?;
+        Ok(Expr::If(branches, otherwise))
+    }
+}
+
+/// The outputs of an expression, or the message of its error.
+type Outputs = Result<Vec<Value>, String>;
+
+/// Run the expression on the input.
+fn eval(expr: &Expr, input: &Value) -> Outputs {
+    match expr {
+        Expr::Identity => Ok(vec![input.clone()]),
+        Expr::Recurse => {
+            let mut all = Vec::new();
+            recurse(input, &mut all);
+            Ok(all)
+        }
+        Expr::Literal(value) => Ok(vec![value.clone()]),
+        Expr::Str(parts) => {
+            let mut texts = vec![String::new()];
+            for part in parts {
+                texts = match part {
+                    StrPart::Text(text) => texts.into_iter().map(|t| t + text.as_str()).collect(),
+                    StrPart::Interpolation(expr) => {
+                        let values = eval(expr, input)?;
+                        let mut next = Vec::new();
+                        for value in &values {
+                            for t in &texts {
+                                next.push(format!("{}{}", t, to_text(value)));
+                            }
+                        }
+                        next
+                    }
+                };
+            }
+            Ok(texts.into_iter().map(Value::String).collect())
+        }
+        Expr::Array(None) => Ok(vec![Value::Array(Vec::new())]),
+        Expr::Array(Some(expr)) => Ok(vec![Value::Array(eval(expr, input)?)]),
+        Expr::Object(entries) => {
+            let mut objects = vec![Map::new()];
+            for (key, value) in entries {
+                let keys = match key {
+                    ObjectKey::Name(name) => vec![name.clone()],
+                    ObjectKey::Expr(expr) => eval(expr, input)?
+                        .into_iter()
+                        .map(|key| match key {
+                            Value::String(key) => Ok(key),
+                            key => Err(format!("Object keys must be strings, not {}.", key)),
+                        })
+                        .collect::<Result<_, _>>()?,
+                };
+                let values = match value {
+                    Some(value) => eval(value, input)?,
+                    None => vec![index(input, &Value::String(keys[0].clone()))?],
+                };
+                let mut next = Vec::new();
+                for object in &objects {
+                    for key in &keys {
+                        for value in &values {
+                            let mut object = object.clone();
+                            object.insert(key.clone(), value.clone());
+                            next.push(object);
+                        }
+                    }
+                }
+                objects = next;
+            }
+            Ok(objects.into_iter().map(Value::Object).collect())
+        }
+        Expr::Index(target, key) => {
+            let keys = eval(key, input)?;
+            let mut out = Vec::new();
+            for target in eval(target, input)? {
+                for key in &keys {
+                    out.push(index(&target, key)?);
+                }
+            }
+            Ok(out)
+        }
+        Expr::Slice(target, start, end) => {
+            let bound = |expr: &Option<Box<Expr>>| -> Result<Vec<Value>, String> {
+                match expr {
+                    Some(expr) => eval(expr, input),
+                    None => Ok(vec![Value::Null]),
+                }
+            };
+            let (starts, ends) = (bound(start)?, bound(end)?);
+            let mut out = Vec::new();
+            for target in eval(target, input)? {
+                for start in &starts {
+                    for end in &ends {
+                        out.push(slice(&target, start, end)?);
+                    }
+                }
+            }
+            Ok(out)
+        }
+        Expr::Iterate(target) => {
+            let mut out = Vec::new();
+            for target in eval(target, input)? {
+                out.extend(iterate(&target)?);
+            }
+            Ok(out)
+        }
+        // the errors are ignored for each target, so the outputs of the targets before stay
+        Expr::Try(expr) => match expr.as_ref() {
+            Expr::Index(target, key) => {
+                let keys = eval(key, input)?;
+                let mut out = Vec::new();
+                for target in eval(target, input)? {
+                    out.extend(keys.iter().filter_map(|key| index(&target, key).ok()));
+                }
+                Ok(out)
+            }
+            Expr::Iterate(target) => {
+                let mut out = Vec::new();
+                for target in eval(target, input)? {
+                    out.extend(iterate(&target).unwrap_or_default());
+                }
+                Ok(out)
+            }
+            expr => Ok(eval(expr, input).unwrap_or_default()),
+        },
+        Expr::Pipe(left, right) => {
+            let mut out = Vec::new();
+            for value in eval(left, input)? {
+                out.extend(eval(right, &value)?);
+            }
+            Ok(out)
+        }
+        Expr::Comma(left, right) => {
+            let mut out = eval(left, input)?;
+            out.extend(eval(right, input)?);
+            Ok(out)
+        }
+        Expr::Alternative(left, right) => {
+            let found: Vec<Value> = eval(left, input)
+                .unwrap_or_default()
+                .into_iter()
+                .filter(truthy)
+                .collect();
+            match found.is_empty() {
+                true => eval(right, input),
+                false => Ok(found),
+            }
+        }
+        Expr::And(left, right) | Expr::Or(left, right) => {
+            let is_and = matches!(expr, Expr::And(..));
+            let mut out = Vec::new();
+            for value in eval(left, input)? {
+                if truthy(&value) != is_and {
+                    out.push(Value::Bool(!is_and));
+                    continue;
+                }
+                for value in eval(right, input)? {
+                    out.push(Value::Bool(truthy(&value)));
+                }
+            }
+            Ok(out)
+        }
+        Expr::Binary(op, left, right) => {
+            let lefts = eval(left, input)?;
+            let mut out = Vec::new();
+            for right in eval(right, input)? {
+                for left in &lefts {
+                    out.push(binary(*op, left, &right)?);
+                }
+            }
+            Ok(out)
+        }
+        Expr::Neg(expr) => eval(expr, input)?
+            .into_iter()
+            .map(|value| match value.as_f64() {
+                Some(number) => Ok(number_value(-number)),
+                None => Err(format!("{} can't be negated.", value)),
+            })
+            .collect(),
+        Expr::If(branches, otherwise) => conditional(branches, otherwise, input),
+        Expr::Call(name, args) => call(name, args, input),
+    }
+}
+
+/// Run the first branch whose condition is true, or the else branch.
+fn conditional(branches: &[(Expr, Expr)], otherwise: &Option<Box<Expr>>, input: &Value) -> Outputs {
+    let Some(((condition, then), rest)) = branches.split_first() else {
+        return match otherwise {
+            Some(otherwise) => eval(otherwise, input),
+            None => Ok(vec![input.clone()]),
+        };
+    };
+    let mut out = Vec::new();
+    for value in eval(condition, input)? {
+        match truthy(&value) {
+            true => out.extend(eval(then, input)?),
+            false => out.extend(conditional(rest, otherwise, input)?),
+        }
+    }
+    Ok(out)
+}
+
+/// Call a function.
+fn call(name: &str, args: &[Expr], input: &Value) -> Outputs {
+    let one = |value: Value| Ok(vec![value]);
+    match (name, args) {
+        ("empty", []) => Ok(Vec::new()),
+        ("error", []) => Err(to_text(input)),
+        ("error", [message]) => Err(to_text(&first(message, input)?)),
+        ("not", []) => one(Value::Bool(!truthy(input))),
+        ("length", []) => one(match input {
+            Value::Null => Value::from(0),
+            Value::Number(_) => number_value(input.as_f64().unwrap_or(0.0).abs()),
+            Value::String(text) => Value::from(text.chars().count()),
+            Value::Array(items) => Value::from(items.len()),
+            Value::Object(members) => Value::from(members.len()),
+            Value::Bool(_) => return Err("A boolean has no length.".to_string()),
+        }),
+        ("type", []) => one(Value::from(type_name(input))),
+        ("keys", []) => one(match input {
+            Value::Object(members) => {
+                let mut keys: Vec<&String> = members.keys().collect();
+                keys.sort();
+                keys.into_iter()
+                    .map(|key| Value::from(key.as_str()))
+                    .collect()
+            }
+            Value::Array(items) => (0..items.len()).map(Value::from).collect(),
+            _ => return Err(format!("{} has no keys.", type_name(input))),
+        }),
+        ("has", [key]) => map_arg(key, input, |key| match (input, &key) {
+            (Value::Object(members), Value::String(key)) => {
+                Ok(Value::Bool(members.contains_key(key.as_str())))
+            }
+            (Value::Array(items), Value::Number(_)) => {
+                let index = key.as_f64().unwrap_or(-1.0);
+                Ok(Value::Bool(index >= 0.0 && (index as usize) < items.len()))
+            }
+            _ => Err(format!("Can't check if {} has {}.", type_name(input), key)),
+        }),
+        ("map", [f]) => {
+            let mut out = Vec::new();
+            for item in iterate(input)? {
+                out.extend(eval(f, &item)?);
+            }
+            one(Value::Array(out))
+        }
+        ("map_values", [f]) => match input {
+            Value::Object(members) => {
+                let mut out = Map::new();
+                for (key, value) in members {
+                    if let Some(value) = eval(f, value)?.into_iter().next() {
+                        out.insert(key.clone(), value);
+                    }
+                }
+                one(Value::Object(out))
+            }
+            Value::Array(items) => {
+                let mut out = Vec::new();
+                for item in items {
+                    out.extend(eval(f, item)?.into_iter().next());
+                }
+                one(Value::Array(out))
+            }
+            _ => Err(format!("Can't iterate over {}.", type_name(input))),
+        },
+        ("select", [f]) => Ok(eval(f, input)?
+            .iter()
+            .filter(|value| truthy(value))
+            .map(|_| input.clone())
+            .collect()),
+        ("add", []) => {
+            let mut sum = Value::Null;
+            for item in iterate(input)? {
+                sum = binary(BinaryOp::Add, &sum, &item)?;
+            }
+            one(sum)
+        }
+        ("any", []) => one(Value::Bool(iterate(input)?.iter().any(truthy))),
+        ("all", []) => one(Value::Bool(iterate(input)?.iter().all(truthy))),
+        ("any", [f]) | ("all", [f]) => {
+            let mut results = Vec::new();
+            for item in iterate(input)? {
+                results.push(eval(f, &item)?.iter().any(truthy));
+            }
+            one(Value::Bool(match name {
+                "any" => results.into_iter().any(|result| result),
+                _ => results.into_iter().all(|result| result),
+            }))
+        }
+        ("range", [n]) => {
+            let mut out = Vec::new();
+            for n in eval(n, input)? {
+                let n = n.as_f64().ok_or("The range needs a number.")?;
+                if n > MAX_RANGE as f64 {
+                    return Err(format!("The range can't be longer than {}.", MAX_RANGE));
+                }
+                out.extend((0..n.ceil().max(0.0) as i64).map(Value::from));
+            }
+            Ok(out)
+        }
+        ("first", []) => one(index(input, &Value::from(0))?),
+        ("last", []) => one(index(input, &Value::from(-1))?),
+        ("first", [f]) => Ok(eval(f, input)?.into_iter().take(1).collect()),
+        ("last", [f]) => Ok(eval(f, input)?.into_iter().last().into_iter().collect()),
+        ("limit", [n, f]) => {
+            let n = first(n, input)?
+                .as_f64()
+                .ok_or("The limit needs a number.")?;
+            Ok(eval(f, input)?
+                .into_iter()
+                .take(n.max(0.0) as usize)
+                .collect())
+        }
+        ("reverse", []) => match input {
+            Value::String(text) => one(Value::String(text.chars().rev().collect())),
+            Value::Null => one(Value::Array(Vec::new())),
+            _ => one(Value::Array(array(input)?.iter().rev().cloned().collect())),
+        },
+        ("sort", []) => {
+            let mut items = array(input)?.clone();
+            items.sort_by(order);
+            one(Value::Array(items))
+        }
+        ("unique", []) => {
+            let mut items = array(input)?.clone();
+            items.sort_by(order);
+            items.dedup();
+            one(Value::Array(items))
+        }
+        ("min", []) => one(array(input)?
+            .iter()
+            .min_by(|a, b| order(a, b))
+            .cloned()
+            .unwrap_or(Value::Null)),
+        ("max", []) => one(array(input)?
+            .iter()
+            .max_by(|a, b| order(a, b))
+            .cloned()
+            .unwrap_or(Value::Null)),
+        ("sort_by", [f])
+        | ("group_by", [f])
+        | ("unique_by", [f])
+        | ("min_by", [f])
+        | ("max_by", [f]) => {
+            let mut keyed = Vec::new();
+            for item in array(input)? {
+                keyed.push((Value::Array(eval(f, item)?), item.clone()));
+            }
+            // a stable sort keeps the items with the same key in their order
+            keyed.sort_by(|(a, _), (b, _)| order(a, b));
+            one(match name {
+                "sort_by" => Value::Array(keyed.into_iter().map(|(_, item)| item).collect()),
+                "min_by" => keyed
+                    .into_iter()
+                    .next()
+                    .map(|(_, item)| item)
+                    .unwrap_or(Value::Null),
+                "max_by" => keyed
+                    .into_iter()
+                    .last()
+                    .map(|(_, item)| item)
+                    .unwrap_or(Value::Null),
+                _ => {
+                    let mut groups: Vec<(Value, Vec<Value>)> = Vec::new();
+                    for (key, item) in keyed {
+                        match groups.last_mut() {
+                            Some((last, group)) if *last == key => group.push(item),
+                            _ => groups.push((key, vec![item])),
+                        }
+                    }
+                    Value::Array(match name {
+                        "group_by" => groups
+                            .into_iter()
+                            .map(|(_, group)| Value::Array(group))
+                            .collect(),
+                        _ => groups
+                            .into_iter()
+                            .map(|(_, mut group)| group.swap_remove(0))
+                            .collect(),
+                    })
+                }
+            })
+        }
+        ("flatten", []) => one(Value::Array(flatten(array(input)?, usize::MAX))),
+        ("flatten", [depth]) => map_arg(depth, input, |depth| {
+            let depth = depth
+                .as_f64()
+                .filter(|depth| *depth >= 0.0)
+                .ok_or("The depth must be a positive number.")?;
+            Ok(Value::Array(flatten(array(input)?, depth as usize)))
+        }),
+        ("to_entries", []) => one(Value::Array(entries(input)?)),
+        ("from_entries", []) => one(from_entries(input)?),
+        ("with_entries", [f]) => {
+            let mut out = Vec::new();
+            for entry in entries(input)? {
+                out.extend(eval(f, &entry)?);
+            }
+            one(from_entries(&Value::Array(out))?)
+        }
+        ("join", [separator]) => map_arg(separator, input, |separator| {
+            let separator = to_text(&separator);
+            let texts: Vec<String> = array(input)?
+                .iter()
+                .map(|item| match item {
+                    Value::Null => String::new(),
+                    item => to_text(item),
+                })
+                .collect();
+            Ok(Value::String(texts.join(&separator)))
+        }),
+        ("split", [separator]) => map_arg(separator, input, |separator| {
+            let (text, separator) = (string(input)?, string(&separator)?);
+            Ok(Value::Array(
+                text.split(separator.as_str()).map(Value::from).collect(),
+            ))
+        }),
+        ("contains", [value]) => map_arg(value, input, |value| {
+            Ok(Value::Bool(contains(input, &value)))
+        }),
+        ("startswith", [prefix]) => map_arg(prefix, input, |prefix| {
+            Ok(Value::Bool(
+                string(input)?.starts_with(string(&prefix)?.as_str()),
+            ))
+        }),
+        ("endswith", [suffix]) => map_arg(suffix, input, |suffix| {
+            Ok(Value::Bool(
+                string(input)?.ends_with(string(&suffix)?.as_str()),
+            ))
+        }),
+        ("ltrimstr", [prefix]) => map_arg(prefix, input, |prefix| {
+            Ok(match (input, prefix) {
+                (Value::String(text), Value::String(prefix)) => {
+                    Value::from(text.strip_prefix(prefix.as_str()).unwrap_or(text))
+                }
+                _ => input.clone(),
+            })
+        }),
+        ("rtrimstr", [suffix]) => map_arg(suffix, input, |suffix| {
+            Ok(match (input, suffix) {
+                (Value::String(text), Value::String(suffix)) => {
+                    Value::from(text.strip_suffix(suffix.as_str()).unwrap_or(text))
+                }
+                _ => input.clone(),
+            })
+        }),
+        ("ascii_downcase", []) => one(Value::String(string(input)?.to_ascii_lowercase())),
+        ("ascii_upcase", []) => one(Value::String(string(input)?.to_ascii_uppercase())),
+        ("tostring", []) => one(Value::String(to_text(input))),
+        ("tonumber", []) => match input {
+            Value::Number(_) => one(input.clone()),
+            Value::String(text) => text
+                .trim()
+                .parse::<f64>()
+                .map(|number| vec![number_value(number)])
+                .map_err(|_| format!("{} can't be parsed as a number.", input)),
+            _ => Err(format!("{} can't be parsed as a number.", type_name(input))),
+        },
+        ("tojson", []) => one(Value::String(input.to_string())),
+        ("fromjson", []) => serde_json::from_str(string(input)?)
+            .map(|value| vec![value])
+            .map_err(|e| format!("{} is not valid json. {}", input, e)),
+        ("floor", []) | ("ceil", []) | ("round", []) | ("abs", []) => {
+            let number = input
+                .as_f64()
+                .ok_or_else(|| format!("{} is not a number.", type_name(input)))?;
+            one(number_value(match name {
+                "floor" => number.floor(),
+                "ceil" => number.ceil(),
+                "round" => number.round(),
+                _ => number.abs(),
+            }))
+        }
+        (
+            "numbers" | "strings" | "booleans" | "nulls" | "arrays" | "objects" | "iterables"
+            | "scalars",
+            [],
+        ) => {
+            let kind = type_name(input);
+            let keep = match name {
+                "numbers" => kind == "number",
+                "strings" => kind == "string",
+                "booleans" => kind == "boolean",
+                "nulls" => kind == "null",
+                "arrays" => kind == "array",
+                "objects" => kind == "object",
+                "iterables" => matches!(kind, "array" | "object"),
+                _ => !matches!(kind, "array" | "object"),
+            };
+            Ok(if keep {
+                vec![input.clone()]
+            } else {
+                Vec::new()
+            })
+        }
+        _ => Err(format!("Unknown function {}/{}.", name, args.len())),
+    }
+}
+
+/// Run the function on every output of the argument.
+fn map_arg<F>(arg: &Expr, input: &Value, f: F) -> Outputs
+where
+    F: Fn(Value) -> Result<Value, String>,
+{
+    eval(arg, input)?.into_iter().map(f).collect()
+}
+
+/// Get the first output of the expression.
+fn first(expr: &Expr, input: &Value) -> Result<Value, String> {
+    eval(expr, input)?
+        .into_iter()
+        .next()
+        .ok_or_else(|| "The argument gave no value.".to_string())
+}
+
+/// Index the value with a key or an index.
+fn index(value: &Value, key: &Value) -> Result<Value, String> {
+    match (value, key) {
+        (Value::Object(members), Value::String(key)) => {
+            Ok(members.get(key).cloned().unwrap_or(Value::Null))
+        }
+        (Value::Array(items), Value::Number(_)) => {
+            let index = key.as_f64().unwrap_or(0.0).floor() as i64;
+            let index = if index < 0 {
+                items.len() as i64 + index
+            } else {
+                index
+            };
+            Ok(match index < 0 {
+                true => Value::Null,
+                false => items.get(index as usize).cloned().unwrap_or(Value::Null),
+            })
+        }
+        (Value::Null, Value::String(_) | Value::Number(_)) => Ok(Value::Null),
+        _ => Err(format!(
+            "Can't index {} with {}.",
+            type_name(value),
+            type_name(key)
+        )),
+    }
+}
+
+/// Slice an array or a string.
+fn slice(value: &Value, start: &Value, end: &Value) -> Result<Value, String> {
+    let len = match value {
+        Value::Null => return Ok(Value::Null),
+        Value::Array(items) => items.len(),
+        Value::String(text) => text.chars().count(),
+        _ => return Err(format!("Can't slice {}.", type_name(value))),
+    } as i64;
+    let bound = |bound: &Value, default: i64| -> Result<usize, String> {
+        let bound = match bound {
+            Value::Null => default,
+            bound => bound.as_f64().ok_or("A slice needs numbers.")?.floor() as i64,
+        };
+        let bound = if bound < 0 { len + bound } else { bound };
+        Ok(bound.clamp(0, len) as usize)
+    };
+    let (start, end) = (bound(start, 0)?, bound(end, len)?);
+    let end = end.max(start);
+    Ok(match value {
+        Value::Array(items) => Value::Array(items[start..end].to_vec()),
+        Value::String(text) => Value::String(text.chars().skip(start).take(end - start).collect()),
+        _ => Value::Null,
+    })
+}
+
+/// Get the elements of an array or the members of an object.
+fn iterate(value: &Value) -> Result<Vec<Value>, String> {
+    match value {
+        Value::Array(items) => Ok(items.clone()),
+        Value::Object(members) => Ok(members.values().cloned().collect()),
+        _ => Err(format!("Can't iterate over {}.", type_name(value))),
+    }
+}
+
+/// Collect the value and all values below it.
+fn recurse(value: &Value, all: &mut Vec<Value>) {
+    all.push(value.clone());
+    match value {
+        Value::Array(items) => items.iter().for_each(|item| recurse(item, all)),
+        Value::Object(members) => members.values().for_each(|item| recurse(item, all)),
+        _ => {}
+    }
+}
+
+/// Apply a binary operator.
+fn binary(op: BinaryOp, left: &Value, right: &Value) -> Result<Value, String> {
+    let comparison = |f: fn(Ordering) -> bool| Ok(Value::Bool(f(order(left, right))));
+    match op {
+        BinaryOp::Eq => return comparison(|o| o == Ordering::Equal),
+        BinaryOp::Ne => return comparison(|o| o != Ordering::Equal),
+        BinaryOp::Lt => return comparison(|o| o == Ordering::Less),
+        BinaryOp::Le => return comparison(|o| o != Ordering::Greater),
+        BinaryOp::Gt => return comparison(|o| o == Ordering::Greater),
+        BinaryOp::Ge => return comparison(|o| o != Ordering::Less),
+        _ => {}
+    }
+    if let (Some(a), Some(b)) = (left.as_f64(), right.as_f64()) {
+        return match op {
+            BinaryOp::Add => Ok(number_value(a + b)),
+            BinaryOp::Sub => Ok(number_value(a - b)),
+            BinaryOp::Mul => Ok(number_value(a * b)),
+            BinaryOp::Div if b == 0.0 => Err(format!("{} can't be divided by 0.", a)),
+            BinaryOp::Div => Ok(number_value(a / b)),
+            BinaryOp::Rem if b as i64 == 0 => Err(format!("{} can't be divided by 0.", a)),
+            BinaryOp::Rem => (a as i64)
+                .checked_rem(b as i64)
+                .map(Value::from)
+                .ok_or_else(|| format!("The remainder of {} by {} overflows.", a, b)),
+            _ => unreachable!(),
+        };
+    }
+    match (op, left, right) {
+        (BinaryOp::Add, Value::Null, value) | (BinaryOp::Add, value, Value::Null) => {
+            Ok(value.clone())
+        }
+        (BinaryOp::Add, Value::String(a), Value::String(b)) => {
+            Ok(Value::String(format!("{}{}", a, b)))
+        }
+        (BinaryOp::Add, Value::Array(a), Value::Array(b)) => {
+            Ok(Value::Array(a.iter().chain(b).cloned().collect()))
+        }
+        (BinaryOp::Add, Value::Object(a), Value::Object(b)) => {
+            let mut merged = a.clone();
+            merged.extend(b.clone());
+            Ok(Value::Object(merged))
+        }
+        (BinaryOp::Sub, Value::Array(a), Value::Array(b)) => Ok(Value::Array(
+            a.iter().filter(|item| !b.contains(item)).cloned().collect(),
+        )),
+        (BinaryOp::Div, Value::String(a), Value::String(b)) => {
+            Ok(Value::Array(a.split(b.as_str()).map(Value::from).collect()))
+        }
+        _ => Err(format!(
+            "{} and {} can't be combined with {:?}.",
+            type_name(left),
+            type_name(right),
+            op
+        )),
+    }
+}
+
+/// Whether the value is true in a condition. Only `false` and `null` are false.
+fn truthy(value: &Value) -> bool {
+    !matches!(value, Value::Null | Value::Bool(false))
+}
+
+/// Get the name of the type of the value.
+fn type_name(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(_) => "number",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
+
+/// Order two values as jq does: null, false, true, numbers, strings, arrays and objects.
+fn order(left: &Value, right: &Value) -> Ordering {
+    let rank = |value: &Value| match value {
+        Value::Null => 0,
+        Value::Bool(false) => 1,
+        Value::Bool(true) => 2,
+        Value::Number(_) => 3,
+        Value::String(_) => 4,
+        Value::Array(_) => 5,
+        Value::Object(_) => 6,
+    };
+    match (left, right) {
+        (Value::Number(_), Value::Number(_)) => left
+            .as_f64()
+            .partial_cmp(&right.as_f64())
+            .unwrap_or(Ordering::Equal),
+        (Value::String(a), Value::String(b)) => a.cmp(b),
+        (Value::Array(a), Value::Array(b)) => a
+            .iter()
+            .zip(b)
+            .map(|(a, b)| order(a, b))
+            .find(|o| o.is_ne())
+            .unwrap_or(a.len().cmp(&b.len())),
+        (Value::Object(a), Value::Object(b)) => {
+            let mut a_keys: Vec<&String> = a.keys().collect();
+            let mut b_keys: Vec<&String> = b.keys().collect();
+            a_keys.sort();
+            b_keys.sort();
+            a_keys.cmp(&b_keys).then_with(|| {
+                a_keys
+                    .iter()
+                    .map(|key| order(&a[key.as_str()], &b[key.as_str()]))
+                    .find(|o| o.is_ne())
+                    .unwrap_or(Ordering::Equal)
+            })
+        }
+        _ => rank(left).cmp(&rank(right)),
+    }
+}
+
+/// Whether the value contains the other one: a substring, the elements or the members.
+fn contains(value: &Value, other: &Value) -> bool {
+    match (value, other) {
+        (Value::String(a), Value::String(b)) => a.contains(b.as_str()),
+        (Value::Array(a), Value::Array(b)) => b.iter().all(|b| a.iter().any(|a| contains(a, b))),
+        (Value::Object(a), Value::Object(b)) => b
+            .iter()
+            .all(|(key, b)| a.get(key).is_some_and(|a| contains(a, b))),
+        _ => value == other,
+    }
+}
+
+/// Flatten the nested arrays up to the depth.
+fn flatten(items: &[Value], depth: usize) -> Vec<Value> {
+    let mut out = Vec::new();
+    for item in items {
+        match item {
+            Value::Array(inner) if depth > 0 => out.extend(flatten(inner, depth - 1)),
+            item => out.push(item.clone()),
+        }
+    }
+    out
+}
+
+/// Get the entries `{key, value}` of an object.
+fn entries(value: &Value) -> Result<Vec<Value>, String> {
+    match value {
+        Value::Object(members) => Ok(members
+            .iter()
+            .map(|(key, value)| serde_json::json!({ "key": key, "value": value }))
+            .collect()),
+        _ => Err(format!("{} has no entries.", type_name(value))),
+    }
+}
+
+/// Build an object from its entries, like `{key, value}` or `{name, value}`.
+fn from_entries(value: &Value) -> Result<Value, String> {
+    let mut object = Map::new();
+    for entry in array(value)? {
+        let key = ["key", "k", "name", "Name", "Key", "K"]
+            .iter()
+            .find_map(|name| entry.get(name).filter(|key| !key.is_null()))
+            .ok_or_else(|| format!("The entry {} has no key.", entry))?;
+        let value = ["value", "v", "Value", "V"]
+            .iter()
+            .find_map(|name| entry.get(name))
+            .cloned()
+            .unwrap_or(Value::Null);
+        object.insert(to_text(key), value);
+    }
+    Ok(Value::Object(object))
+}
+
+/// Get the elements of an array.
+fn array(value: &Value) -> Result<&Vec<Value>, String> {
+    match value {
+        Value::Array(items) => Ok(items),
+        _ => Err(format!("{} is not an array.", type_name(value))),
+    }
+}
+
+/// Get the text of a string.
+fn string(value: &Value) -> Result<&String, String> {
+    match value {
+        Value::String(text) => Ok(text),
+        _ => Err(format!("{} is not a string.", type_name(value))),
+    }
+}
+
+/// Get the text of a value: a string as it is, and the others as json.
+fn to_text(value: &Value) -> String {
+    match value {
+        Value::String(text) => text.clone(),
+        value => value.to_string(),
+    }
+}
+
+/// Get the json value of a number, as an integer if it is one.
+fn number_value(number: f64) -> Value {
+    if number.fract() == 0.0 && number.abs() < 9_007_199_254_740_992.0 {
+        return Value::from(number as i64);
+    }
+    serde_json::Number::from_f64(number)
+        .map(Value::Number)
+        .unwrap_or(Value::Null)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use serde_json::json;
+
+    fn run(filter: &str, input: Value) -> Vec<Value> {
+        Filter::parse(filter).unwrap().run(&input).unwrap()
+    }
+
+    #[test]
+    fn run_filters() {
+        let users = json!({"users": [
+            {"name": "Tom", "age": 30, "tags": ["admin"]},
+            {"name": "Ann", "age": 25, "tags": []},
+            {"name": "Bob", "age": 35}
+        ]});
+        assert_eq!(run(".users[0].name", users.clone()), vec![json!("Tom")]);
+        assert_eq!(
+            run(".users[-1][\"name\"]", users.clone()),
+            vec![json!("Bob")]
+        );
+        assert_eq!(
+            run(".users[] | select(.age > 28) | .name", users.clone()),
+            vec![json!("Tom"), json!("Bob")]
+        );
+        assert_eq!(
+            run("[.users[].age] | add / length", users.clone()),
+            vec![json!(30)]
+        );
+        assert_eq!(
+            run(
+                ".users | sort_by(.age) | map(.name) | join(\", \")",
+                users.clone()
+            ),
+            vec![json!("Ann, Tom, Bob")]
+        );
+        assert_eq!(
+            run(
+                ".users[1] | {name, adult: (.age >= 18), n: (.tags | length)}",
+                users.clone()
+            ),
+            vec![json!({"name": "Ann", "adult": true, "n": 0})]
+        );
+        assert_eq!(
+            run(".users[] | \"\\(.name) is \\(.age)\"", users.clone()).len(),
+            3
+        );
+        assert_eq!(
+            run(".users[2].tags // [] | length", users.clone()),
+            vec![json!(0)]
+        );
+        assert_eq!(
+            run(
+                ".users[] | if .age < 28 then \"young\" elif .age < 32 then \"mid\" else \"old\" end",
+                users.clone()
+            ),
+            vec![json!("mid"), json!("young"), json!("old")]
+        );
+        assert_eq!(
+            run("[.users[].tags[]?]", users.clone()),
+            vec![json!(["admin"])]
+        );
+        assert_eq!(
+            run(
+                "{a: 1, b: 2} | with_entries(select(.value > 1))",
+                json!(null)
+            ),
+            vec![json!({"b": 2})]
+        );
+        assert_eq!(
+            run(
+                "{a: 1, b: 2} | to_entries | map(.key) , (1, 2) * 10",
+                json!(null)
+            ),
+            vec![json!(["a", "b"]), json!(10), json!(20)]
+        );
+        assert_eq!(
+            run("[.[2:], .[:-1]]", json!([1, 2, 3])),
+            vec![json!([[3], [1, 2]])]
+        );
+        assert_eq!(
+            run("[..|numbers]", json!({"a": [1, {"b": 2}]})),
+            vec![json!([1, 2])]
+        );
+        assert!(Filter::parse(".users[").is_err());
+        assert!(Filter::parse(".a % .b")
+            .unwrap()
+            .run(&json!({"a": -1e19, "b": -1}))
+            .is_err());
+        assert!(Filter::parse("[range(1e12)]")
+            .unwrap()
+            .run(&json!(null))
+            .is_err());
+        assert!(Filter::parse("nope(1)").unwrap().run(&json!(1)).is_err());
+        assert!(Filter::parse(".a.b")
+            .unwrap()
+            .run(&json!({"a": 1}))
+            .is_err());
+    }
+}
@@ -0,0 +1,571 @@
+//! # Path
+//!
+//! This module selects values out of json with a JSONPath, as in RFC 9535:
+//! - `$` is the input, and `@` the value checked by a filter.
+//! - `.name` or `['name']` selects a member of an object, and `[0]` or `[-1]` an element of
+//!   an array. `.*` or `[*]` selects all members or elements.
+//! - `[1:3]` and `[::2]` select a slice of an array, and `[0, 'name']` several selectors.
+//! - `..name`, `..*` or `..[0]` select in the value and all values below it.
+//! - `[?@.price < 10]` selects the members or elements for which the comparison is true. A
+//!   comparison is between a path from `@` or `$` and a literal; a path alone is true if it
+//!   selects something. Comparisons are joined with `&&` and `||`, negated with `!`, and
+//!   grouped with parentheses.
+//!
+//! The values are selected in the order of the document, and a selector that doesn't match
+//! selects nothing instead of failing.
+
+use crate::error::transform_node_error::{
+    TransformNodeError, TransformNodeErrorType, TransformNodeResult,
+};
+
+use serde_json::Value;
+
+use std::cmp::Ordering;
+
+#[derive(Debug, Clone, PartialEq)]
+/// The enum of a selector of a segment.
+enum Selector {
+    /// A member of an object.
+    Name(String),
+    /// An element of an array, from the end if negative.
+    Index(i64),
+    /// All members or elements.
+    Wildcard,
+    /// A slice of an array.
+    Slice(Option<i64>, Option<i64>, i64),
+    /// The members or elements for which the filter is true.
+    Filter(FilterExpr),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+/// The struct of a segment of a path.
+struct Segment {
+    /// Whether the segment selects in all values below too.
+    descendant: bool,
+    selectors: Vec<Selector>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+/// The enum of the expression of a filter selector.
+enum FilterExpr {
+    /// Whether the path selects something.
+    Exists(QueryPath),
+    /// A comparison of the value of the path with a literal.
+    Compare(QueryPath, CompareOp, Value),
+    Not(Box<FilterExpr>),
+    And(Box<FilterExpr>, Box<FilterExpr>),
+    Or(Box<FilterExpr>, Box<FilterExpr>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+/// The struct of a path in a filter, from the value checked or from the input.
+struct QueryPath {
+    relative: bool,
+    segments: Vec<Segment>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+/// The enum of the operators of a comparison.
+enum CompareOp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+/// The struct of a parsed JSONPath.
+pub struct JsonPath {
+    segments: Vec<Segment>,
+}
+
+impl JsonPath {
+    /// Parse the JSONPath.
+    pub fn parse(path: &str) -> TransformNodeResult<Self> {
+        let mut parser = Parser {
+            chars: path.chars().collect(),
+            pos: 0,
+        };
+        parser.skip_spaces();
+        if !parser.eat('$') {
+            return Err(parser.error("A JSONPath starts with `$`"));
+        }
+        let segments = parser.segments()?;
+        parser.skip_spaces();
+        if parser.pos < parser.chars.len() {
+            return Err(parser.error("Unexpected character"));
+        }
+        Ok(JsonPath { segments })
+    }
+    /// Select the values of the path in the input.
+    pub fn select(&self, input: &Value) -> Vec<Value> {
+        select(&self.segments, input, input)
+            .into_iter()
+            .cloned()
+            .collect()
+    }
+}
+
+/// Select the values of the segments in the value.
+fn select<'a>(segments: &[Segment], value: &'a Value, root: &'a Value) -> Vec<&'a Value> {
+    let mut nodes = vec![value];
+    for segment in segments {
+        let mut next = Vec::new();
+        for node in nodes {
+            if segment.descendant {
+                let mut all = Vec::new();
+                descendants(node, &mut all);
+                for node in all {
+                    apply(&segment.selectors, node, root, &mut next);
+                }
+            } else {
+                apply(&segment.selectors, node, root, &mut next);
+            }
+        }
+        nodes = next;
+    }
+    nodes
+}
+
+/// Collect the value and all values below it, in the order of the document.
+fn descendants<'a>(value: &'a Value, all: &mut Vec<&'a Value>) {
+    all.push(value);
+    match value {
+        Value::Array(items) => items.iter().for_each(|item| descendants(item, all)),
+        Value::Object(members) => members.values().for_each(|item| descendants(item, all)),
+        _ => {}
+    }
+}
+
+/// Apply the selectors to the value.
+fn apply<'a>(selectors: &[Selector], value: &'a Value, root: &'a Value, out: &mut Vec<&'a Value>) {
+    for selector in selectors {
+        match (selector, value) {
+            (Selector::Name(name), Value::Object(members)) => out.extend(members.get(name)),
+            (Selector::Index(index), Value::Array(items)) => {
+                let index = match *index < 0 {
+                    true => items.len() as i64 + index,
+                    false => *index,
+                };
+                if index >= 0 {
+                    out.extend(items.get(index as usize));
+                }
+            }
+            (Selector::Wildcard, Value::Array(items)) => out.extend(items),
+            (Selector::Wildcard, Value::Object(members)) => out.extend(members.values()),
+            (Selector::Slice(start, end, step), Value::Array(items)) => {
+                slice(items.len() as i64, *start, *end, *step)
+                    .into_iter()
+                    .for_each(|index| out.push(&items[index]));
+            }
+            (Selector::Filter(filter), Value::Array(items)) => {
+                out.extend(items.iter().filter(|item| test(filter, item, root)))
+            }
+            (Selector::Filter(filter), Value::Object(members)) => {
+                out.extend(members.values().filter(|item| test(filter, item, root)))
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Get the indexes of a slice of an array of the length, as in RFC 9535.
+fn slice(len: i64, start: Option<i64>, end: Option<i64>, step: i64) -> Vec<usize> {
+    let normalize = |index: i64| if index < 0 { len + index } else { index };
+    let mut indexes = Vec::new();
+    if step > 0 {
+        let lower = start.map(normalize).unwrap_or(0).clamp(0, len);
+        let upper = end.map(normalize).unwrap_or(len).clamp(0, len);
+        let mut index = lower;
+        while index < upper {
+            indexes.push(index as usize);
+            // a huge step goes past the end instead of overflowing
+            index = match index.checked_add(step) {
+                Some(index) => index,
+                None => break,
+            };
+        }
+    } else if step < 0 {
+        let upper = start.map(normalize).unwrap_or(len - 1).clamp(-1, len - 1);
+        let lower = end.map(normalize).unwrap_or(-len - 1).clamp(-1, len - 1);
+        let mut index = upper;
+        while lower < index {
+            indexes.push(index as usize);
+            index = match index.checked_add(step) {
+                Some(index) => index,
+                None => break,
+            };
+        }
+    }
+    indexes
+}
+
+/// Whether the filter is true for the value.
+fn test(filter: &FilterExpr, value: &Value, root: &Value) -> bool {
+    let query = |path: &QueryPath| match path.relative {
+        true => select(&path.segments, value, root),
+        false => select(&path.segments, root, root),
+    };
+    match filter {
+        FilterExpr::Exists(path) => !query(path).is_empty(),
+        FilterExpr::Compare(path, op, literal) => {
+            // a path that doesn't select exactly one value is only equal to nothing
+            let values = query(path);
+            let [found] = values.as_slice() else {
+                return *op == CompareOp::Ne;
+            };
+            let ordering = compare(found, literal);
+            match op {
+                CompareOp::Eq => ordering == Some(Ordering::Equal),
+                CompareOp::Ne => ordering != Some(Ordering::Equal),
+                CompareOp::Lt => ordering == Some(Ordering::Less),
+                CompareOp::Le => matches!(ordering, Some(Ordering::Less | Ordering::Equal)),
+                CompareOp::Gt => ordering == Some(Ordering::Greater),
+                CompareOp::Ge => matches!(ordering, Some(Ordering::Greater | Ordering::Equal)),
+            }
+        }
+        FilterExpr::Not(filter) => !test(filter, value, root),
+        FilterExpr::And(left, right) => test(left, value, root) && test(right, value, root),
+        FilterExpr::Or(left, right) => test(left, value, root) || test(right, value, root),
+    }
+}
+
+/// Compare two values. Only numbers and strings are ordered, the others can only be equal.
+fn compare(left: &Value, right: &Value) -> Option<Ordering> {
+    match (left, right) {
+        (Value::Number(left), Value::Number(right)) => left.as_f64()?.partial_cmp(&right.as_f64()?),
+        (Value::String(left), Value::String(right)) => Some(left.cmp(right)),
+        (left, right) if left == right => Some(Ordering::Equal),
+        _ => None,
+    }
+}
+
+/// The struct of the parser of a JSONPath.
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+}
+
+impl Parser {
+    /// Parse the segments until something that is not a segment.
+    fn segments(&mut self) -> TransformNodeResult<Vec<Segment>> {
+        let mut segments = Vec::new();
+        loop {
+            self.skip_spaces();
+            if self.starts_with("..") {
+                self.pos += 2;
+                let selectors = match self.peek() {
+                    Some('[') => self.bracket()?,
+                    _ => vec![self.dot_selector()?],
+                };
+                segments.push(Segment {
+                    descendant: true,
+                    selectors,
+                });
+            } else if self.eat('.') {
+                segments.push(Segment {
+                    descendant: false,
+                    selectors: vec![self.dot_selector()?],
+                });
+            } else if self.peek() == Some('[') {
+                segments.push(Segment {
+                    descendant: false,
+                    selectors: self.bracket()?,
+                });
+            } else {
+                return Ok(segments);
+            }
+        }
+    }
+    /// Parse the selector after a dot: a name or `*`.
+    fn dot_selector(&mut self) -> TransformNodeResult<Selector> {
+        if self.eat('*') {
+            return Ok(Selector::Wildcard);
+        }
+        let start = self.pos;
+        while self
+            .peek()
+            .is_some_and(|c| c.is_alphanumeric() || c == '_' || c == '-')
+        {
+            self.pos += 1;
+        }
+        if start == self.pos {
+            return Err(self.error("Expected a name"));
+        }
+        Ok(Selector::Name(self.chars[start..self.pos].iter().collect()))
+    }
+    /// Parse the selectors in brackets.
+    fn bracket(&mut self) -> TransformNodeResult<Vec<Selector>> {
+        self.expect('[')?;
+        let mut selectors = Vec::new();
+        loop {
+            self.skip_spaces();
+            selectors.push(self.selector()?);
+            self.skip_spaces();
+            if self.eat(']') {
+                return Ok(selectors);
+            }
+            self.expect(',')?;
+        }
+    }
+    /// Parse a selector in brackets.
+    fn selector(&mut self) -> TransformNodeResult<Selector> {
+        match self.peek() {
+            Some('\'' | '"') => Ok(Selector::Name(self.string()?)),
+            Some('*') => {
+                self.pos += 1;
+                Ok(Selector::Wildcard)
+            }
+            Some('?') => {
+                self.pos += 1;
+                Ok(Selector::Filter(self.or()?))
+            }
+            _ => {
+                let start = self.optional_int()?;
+                self.skip_spaces();
+                if !self.eat(':') {
+                    return start
+                        .map(Selector::Index)
+                        .ok_or_else(|| self.error("Expected a selector"));
+                }
+                self.skip_spaces();
+                let end = self.optional_int()?;
+                self.skip_spaces();
+                let step = match self.eat(':') {
+                    true => {
+                        self.skip_spaces();
+                        self.optional_int()?.unwrap_or(1)
+                    }
+                    false => 1,
+                };
+                Ok(Selector::Slice(start, end, step))
+            }
+        }
+    }
+    /// Parse the filter expressions joined with `||`.
+    fn or(&mut self) -> TransformNodeResult<FilterExpr> {
+        let mut left = self.and()?;
+        loop {
+            self.skip_spaces();
+            if !self.starts_with("||") {
+                return Ok(left);
+            }
+            self.pos += 2;
+            left = FilterExpr::Or(Box::new(left), Box::new(self.and()?));
+        }
+    }
+    /// Parse the filter expressions joined with `&&`.
+    fn and(&mut self) -> TransformNodeResult<FilterExpr> {
+        let mut left = self.unary()?;
+        loop {
+            self.skip_spaces();
+            if !self.starts_with("&&") {
+                return Ok(left);
+            }
+            self.pos += 2;
+            left = FilterExpr::And(Box::new(left), Box::new(self.unary()?));
+        }
+    }
+    /// Parse a negated, grouped or basic filter expression.
+    fn unary(&mut self) -> TransformNodeResult<FilterExpr> {
+        self.skip_spaces();
+        if self.peek() == Some('!') && !self.starts_with("!=") {
+            self.pos += 1;
+            return Ok(FilterExpr::Not(Box::new(self.unary()?)));
+        }
+        if self.eat('(') {
+            let expr = self.or()?;
+            self.skip_spaces();
+            self.expect(')')?;
+            return Ok(expr);
+        }
+        let relative = match self.peek() {
+            Some('@') => true,
+            Some('$') => false,
+            _ => return Err(self.error("Expected `@` or `$` in the filter")),
+        };
+        self.pos += 1;
+        let path = QueryPath {
+            relative,
+            segments: self.segments()?,
+        };
+        self.skip_spaces();
+        let op = [
+            ("==", CompareOp::Eq),
+            ("!=", CompareOp::Ne),
+            ("<=", CompareOp::Le),
+            (">=", CompareOp::Ge),
+            ("<", CompareOp::Lt),
+            (">", CompareOp::Gt),
+        ]
+        .into_iter()
+        .find(|(text, _)| self.starts_with(text));
+        let Some((text, op)) = op else {
+            return Ok(FilterExpr::Exists(path));
+        };
+        self.pos += text.len();
+        self.skip_spaces();
+        Ok(FilterExpr::Compare(path, op, self.literal()?))
+    }
+    /// Parse a literal of a comparison.
+    fn literal(&mut self) -> TransformNodeResult<Value> {
+        if matches!(self.peek(), Some('\'' | '"')) {
+            return Ok(Value::String(self.string()?));
+        }
+        for (word, value) in [
+            ("true", Value::Bool(true)),
+            ("false", Value::Bool(false)),
+            ("null", Value::Null),
+        ] {
+            if self.starts_with(word) {
+                self.pos += word.len();
+                return Ok(value);
+            }
+        }
+        let start = self.pos;
+        while self
+            .peek()
+            .is_some_and(|c| c.is_ascii_digit() || matches!(c, '-' | '+' | '.' | 'e' | 'E'))
+        {
+            self.pos += 1;
+        }
+        let text: String = self.chars[start..self.pos].iter().collect();
+        serde_json::from_str::<serde_json::Number>(&text)
+            .map(Value::Number)
+            .map_err(|_| self.error("Expected a literal"))
+    }
+    /// Parse a quoted string.
+    fn string(&mut self) -> TransformNodeResult<String> {
+        let quote = self.peek().ok_or_else(|| self.error("Expected a string"))?;
+        self.pos += 1;
+        let mut text = String::new();
+        loop {
+            match self.peek() {
+                None => return Err(self.error("Unterminated string")),
+                Some(c) if c == quote => {
+                    self.pos += 1;
+                    return Ok(text);
+                }
+                Some('\\') => {
+                    self.pos += 1;
+                    let escaped = match self.peek() {
+                        Some('n') => '\n',
+                        Some('t') => '\t',
+                        Some('r') => '\r',
+                        Some(c @ ('\\' | '/' | '\'' | '"')) => c,
+                        _ => return Err(self.error("Invalid escape")),
+                    };
+                    text.push(escaped);
+                    self.pos += 1;
+                }
+                Some(c) => {
+                    text.push(c);
+                    self.pos += 1;
+                }
+            }
+        }
+    }
+    /// Parse an integer, if there is one.
+    fn optional_int(&mut self) -> TransformNodeResult<Option<i64>> {
+        let start = self.pos;
+        self.eat('-');
+        while self.peek().is_some_and(|c| c.is_ascii_digit()) {
+            self.pos += 1;
+        }
+        let text: String = self.chars[start..self.pos].iter().collect();
+        match text.as_str() {
+            "" => Ok(None),
+            _ => text
+                .parse()
+                .map(Some)
+                .map_err(|_| self.error("Invalid integer")),
+        }
+    }
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+    fn starts_with(&self, text: &str) -> bool {
+        text.chars()
+            .enumerate()
+            .all(|(i, c)| self.chars.get(self.pos + i) == Some(&c))
+    }
+    fn eat(&mut self, c: char) -> bool {
+        let found = self.peek() == Some(c);
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+    fn expect(&mut self, c: char) -> TransformNodeResult<()> {
+        match self.eat(c) {
+            true => Ok(()),
+            false => Err(self.error(&format!("Expected `{}`", c))),
+        }
+    }
+    fn skip_spaces(&mut self) {
+        while self.peek().is_some_and(char::is_whitespace) {
+            self.pos += 1;
+        }
+    }
+    /// Create the error of the JSONPath at the current position.
+    fn error(&self, message: &str) -> TransformNodeError {
+        TransformNodeError::new(
+            TransformNodeErrorType::ParseError,
+            format!(
+                "{} at {} of the JSONPath `{}`.",
+                message,
+                self.pos,
+                self.chars.iter().collect::<String>()
+            ),
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use serde_json::json;
+
+    #[test]
+    fn select_paths() {
+        let store = json!({
+            "books": [
+                {"title": "A", "price": 8, "tags": ["old"]},
+                {"title": "B", "price": 12},
+                {"title": "C", "price": 5, "tags": []}
+            ],
+            "owner": {"name": "Tom"}
+        });
+        let select = |path: &str| JsonPath::parse(path).unwrap().select(&store);
+        assert_eq!(select("$.owner.name"), vec![json!("Tom")]);
+        assert_eq!(select("$['owner']['name']"), vec![json!("Tom")]);
+        assert_eq!(select("$.books[-1].title"), vec![json!("C")]);
+        assert_eq!(select("$.books[0:2].price"), vec![json!(8), json!(12)]);
+        assert_eq!(select("$.books[::-2].title"), vec![json!("C"), json!("A")]);
+        assert_eq!(select("$.books[0, 2].title"), vec![json!("A"), json!("C")]);
+        assert_eq!(select("$..name"), vec![json!("Tom")]);
+        assert_eq!(select("$.books[*].tags[*]"), vec![json!("old")]);
+        assert_eq!(
+            select("$.books[?@.price < 10 && @.tags].title"),
+            vec![json!("A"), json!("C")]
+        );
+        assert_eq!(
+            select("$.books[?!(@.price >= 10) || @.title == 'B'].title").len(),
+            3
+        );
+        assert!(select("$.books[7]").is_empty());
+        assert_eq!(
+            select("$.books[1::9223372036854775807].title"),
+            vec![json!("B")]
+        );
+        assert_eq!(
+            select("$.books[1::-9223372036854775808].title"),
+            vec![json!("B")]
+        );
+        assert!(JsonPath::parse("books").is_err());
+        assert!(JsonPath::parse("$.books[").is_err());
+    }
+}
@@ -0,0 +1,426 @@
+//! # User
+//!
+//! This node asks the user of the terminal, like to clarify a task or to give a value that
+//! only a human knows, and outputs the answer.
+//!
+//! The prompt is a template (see [`crate::template`]) rendered with the variable `input` and
+//! the variables of the run context. It is printed to the stdout, and the answer is read from
+//! the stdin: one line, or in the multi-line mode every line until a line with only the end
+//! marker (or the end of the stdin).
+//!
+//! With a timeout, a user who doesn't answer in time gets the default answer, or fails the
+//! node without it. An empty answer, or the end of the stdin, also gives the default answer.
+//! The user nodes of a run share the stdin, so only one of them asks at a time.
+//!
+//! ## Input source
+//!
+//! A program that embeds AIPilot, like a GUI or a web frontend, asks its user itself: it
+//! registers a [`UserInput`] on the run context (see
+//! [`crate::workflow::context::RunContext::set_user_input`]), and the user nodes of the run
+//! send it a [`UserRequest`] instead of using the terminal. The node waits, with its timeout,
+//! until the answer is given. A `UserInput` is either a callback that returns the answer, or
+//! a channel whose [`UserQuestion`]s are answered one by one. No answer (`None`) is handled
+//! like the end of the stdin.
+
+use crate::error::user_node_error::{UserNodeError, UserNodeErrorType, UserNodeResult};
+use crate::template;
+use crate::workflow::context::RunContext;
+
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncWrite, AsyncWriteExt, BufReader, Stdin};
+use tokio::sync::{mpsc, oneshot, Mutex};
+use uuid::Uuid;
+
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
+
+/// The prompt template by default, which shows the input.
+pub const DEFAULT_PROMPT: &str = "{{input}}\n> ";
+
+/// The end marker of a multi-line answer by default.
+pub const DEFAULT_END_MARKER: &str = "EOF";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// The struct of what a user node asks.
+pub struct UserRequest {
+    /// The uid of the node, or `None` if the node is not in a worknode.
+    pub node: Option<Uuid>,
+    /// The rendered prompt.
+    pub prompt: String,
+    /// The input of the node.
+    pub input: String,
+    /// Whether the answer can have several lines.
+    pub multiline: bool,
+}
+
+/// The future of the answer of a user input source.
+pub type UserInputFuture = Pin<Box<dyn Future<Output = Option<String>> + Send>>;
+
+/// The function that answers the requests of the user nodes.
+pub type UserInputHandler = Arc<dyn Fn(UserRequest) -> UserInputFuture + Send + Sync>;
+
+#[derive(Clone)]
+/// The struct of a source of the answers of the user nodes, registered on a run instead of the
+/// terminal. The clones share the same source.
+pub struct UserInput {
+    handler: UserInputHandler,
+}
+
+impl std::fmt::Debug for UserInput {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "UserInput")
+    }
+}
+
+impl UserInput {
+    /// Create a UserInput that answers with the callback.
+    pub fn new<F, Fut>(handler: F) -> Self
+    where
+        F: Fn(UserRequest) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Option<String>> + Send + 'static,
+    {
+        UserInput {
+            handler: Arc::new(move |request| Box::pin(handler(request))),
+        }
+    }
+    /// Create a UserInput that sends the questions to the channel, and the receiver of the
+    /// channel. A question that is dropped without an answer, or that can't be sent because
+    /// the receiver is dropped, gets no answer.
+    pub fn channel(buffer: usize) -> (Self, mpsc::Receiver<UserQuestion>) {
+        let (sender, receiver) = mpsc::channel(buffer);
+        let input = Self::new(move |request| {
+            let sender = sender.clone();
+            async move {
+                let (answer, answered) = oneshot::channel();
+                sender.send(UserQuestion { request, answer }).await.ok()?;
+                answered.await.ok().flatten()
+            }
+        });
+        (input, receiver)
+    }
+    /// Ask the request, and wait for the answer.
+    pub async fn ask(&self, request: UserRequest) -> Option<String> {
+        (self.handler)(request).await
+    }
+}
+
+#[derive(Debug)]
+/// The struct of a request sent through the channel of a [`UserInput`], to be answered.
+pub struct UserQuestion {
+    /// What the node asks.
+    pub request: UserRequest,
+    answer: oneshot::Sender<Option<String>>,
+}
+
+impl UserQuestion {
+    /// Answer the question.
+    pub fn answer(self, answer: String) {
+        let _ = self.answer.send(Some(answer));
+    }
+    /// Give no answer, so the node takes its default answer or fails.
+    pub fn skip(self) {
+        let _ = self.answer.send(None);
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+/// The struct of the user node.
+pub struct UserNode {
+    /// The prompt template.
+    prompt: String,
+    /// Whether the answer has several lines, until the end marker.
+    multiline: bool,
+    /// The line that ends a multi-line answer.
+    end_marker: String,
+    /// The time the user may take to answer.
+    timeout: Option<Duration>,
+    /// The answer when the user gives none.
+    default: Option<String>,
+    /// The uid of the worknode that holds this node.
+    #[serde(skip)]
+    node_uid: Option<Uuid>,
+}
+
+impl Default for UserNode {
+    fn default() -> Self {
+        UserNode {
+            prompt: DEFAULT_PROMPT.to_string(),
+            multiline: false,
+            end_marker: DEFAULT_END_MARKER.to_string(),
+            timeout: None,
+            default: None,
+            node_uid: None,
+        }
+    }
+}
+
+impl UserNode {
+    /// Create a new UserNode with the prompt template.
+    pub fn new(prompt: &str) -> Self {
+        UserNode {
+            prompt: prompt.to_string(),
+            ..Self::default()
+        }
+    }
+    /// Ask the user through the input source of the run, or in the terminal without it, and
+    /// get the answer.
+    pub async fn execute(&self, input: String, context: &RunContext) -> UserNodeResult<String> {
+        let prompt = self.render_prompt(input.clone(), context)?;
+        if let Some(source) = context.get_user_input() {
+            let request = UserRequest {
+                node: self.node_uid,
+                prompt,
+                input,
+                multiline: self.multiline,
+            };
+            return self.wait(async { Ok(source.ask(request).await) }).await;
+        }
+        let mut stdin = stdin().lock().await;
+        self.ask(&prompt, &mut *stdin, &mut tokio::io::stdout())
+            .await
+    }
+    /// Render the prompt template with the input and the variables of the context.
+    fn render_prompt(&self, input: String, context: &RunContext) -> UserNodeResult<String> {
+        let mut variables = context.to_variables();
+        variables.insert("input".to_string(), input);
+        template::render(&self.prompt, &variables).map_err(|e| {
+            UserNodeError::new(
+                UserNodeErrorType::TemplateError(e),
+                "Failed to render the prompt.".to_string(),
+            )
+        })
+    }
+    /// Write the prompt, and read the answer.
+    async fn ask<R, W>(
+        &self,
+        prompt: &str,
+        reader: &mut R,
+        writer: &mut W,
+    ) -> UserNodeResult<String>
+    where
+        R: AsyncBufRead + Unpin,
+        W: AsyncWrite + Unpin,
+    {
+        writer
+            .write_all(prompt.as_bytes())
+            .await
+            .map_err(io_error)?;
+        writer.flush().await.map_err(io_error)?;
+        self.wait(self.read(reader)).await
+    }
+    /// Wait for the answer with the timeout, and take the default answer when there is none.
+    async fn wait<F>(&self, answer: F) -> UserNodeResult<String>
+    where
+        F: Future<Output = UserNodeResult<Option<String>>>,
+    {
+        let answer = match self.timeout {
+            Some(timeout) => match tokio::time::timeout(timeout, answer).await {
+                Ok(answer) => answer?,
+                Err(_) => {
+                    return self.default.clone().ok_or_else(|| {
+                        UserNodeError::new(
+                            UserNodeErrorType::Timeout,
+                            format!("The user didn't answer in {:?}.", timeout),
+                        )
+                    })
+                }
+            },
+            None => answer.await?,
+        };
+        match (answer, &self.default) {
+            (Some(answer), Some(default)) if answer.is_empty() => Ok(default.clone()),
+            (Some(answer), _) => Ok(answer),
+            (None, Some(default)) => Ok(default.clone()),
+            (None, None) => Err(UserNodeError::new(
+                UserNodeErrorType::NoInput,
+                "The input ended before an answer.".to_string(),
+            )),
+        }
+    }
+    /// Read the answer, or `None` if the input ended before it.
+    async fn read<R: AsyncBufRead + Unpin>(
+        &self,
+        reader: &mut R,
+    ) -> UserNodeResult<Option<String>> {
+        let mut lines = Vec::new();
+        loop {
+            let mut line = String::new();
+            if reader.read_line(&mut line).await.map_err(io_error)? == 0 {
+                break;
+            }
+            let line = line.trim_end_matches(['\n', '\r']).to_string();
+            if !self.multiline {
+                return Ok(Some(line));
+            }
+            if line == self.end_marker {
+                return Ok(Some(lines.join("\n")));
+            }
+            lines.push(line);
+        }
+        match lines.is_empty() {
+            true => Ok(None),
+            false => Ok(Some(lines.join("\n"))),
+        }
+    }
+    /// Set the prompt template as builder.
+    pub fn prompt(mut self, prompt: &str) -> Self {
+        self.prompt = prompt.to_string();
+        self
+    }
+    /// Set the prompt template.
+    pub fn set_prompt(&mut self, prompt: &str) {
+        self.prompt = prompt.to_string();
+    }
+    /// Get the prompt template.
+    pub fn get_prompt(&self) -> &str {
+        &self.prompt
+    }
+    /// Set whether the answer has several lines as builder.
+    pub fn multiline(mut self, multiline: bool) -> Self {
+        self.multiline = multiline;
+        self
+    }
+    /// Set whether the answer has several lines.
+    pub fn set_multiline(&mut self, multiline: bool) {
+        self.multiline = multiline;
+    }
+    /// Get whether the answer has several lines.
+    pub fn get_multiline(&self) -> bool {
+        self.multiline
+    }
+    /// Set the end marker of a multi-line answer as builder.
+    pub fn end_marker(mut self, end_marker: &str) -> Self {
+        self.end_marker = end_marker.to_string();
+        self
+    }
+    /// Set the end marker of a multi-line answer.
+    pub fn set_end_marker(&mut self, end_marker: &str) {
+        self.end_marker = end_marker.to_string();
+    }
+    /// Get the end marker of a multi-line answer.
+    pub fn get_end_marker(&self) -> &str {
+        &self.end_marker
+    }
+    /// Set the timeout as builder.
+    pub fn timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.timeout = timeout;
+        self
+    }
+    /// Set the timeout.
+    pub fn set_timeout(&mut self, timeout: Option<Duration>) {
+        self.timeout = timeout;
+    }
+    /// Get the timeout.
+    pub fn get_timeout(&self) -> Option<Duration> {
+        self.timeout
+    }
+    /// Set the default answer as builder.
+    pub fn default_answer(mut self, default: Option<String>) -> Self {
+        self.default = default;
+        self
+    }
+    /// Set the default answer.
+    pub fn set_default_answer(&mut self, default: Option<String>) {
+        self.default = default;
+    }
+    /// Get the default answer.
+    pub fn get_default_answer(&self) -> Option<&str> {
+        self.default.as_deref()
+    }
+    /// Set the uid of the worknode that holds this node.
+    pub fn set_node_uid(&mut self, node_uid: Option<Uuid>) {
+        self.node_uid = node_uid;
+    }
+}
+
+/// Get the stdin shared by the user nodes, so a line buffered by one node is not lost.
+fn stdin() -> &'static Mutex<BufReader<Stdin>> {
+    static STDIN: OnceLock<Mutex<BufReader<Stdin>>> = OnceLock::new();
+    STDIN.get_or_init(|| Mutex::new(BufReader::new(tokio::io::stdin())))
+}
+
+/// Create a UserNodeError of the terminal.
+fn io_error(e: std::io::Error) -> UserNodeError {
+    UserNodeError::new(
+        UserNodeErrorType::IoError,
+        format!("Failed to ask the user. {}", e),
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use tokio::runtime::Runtime;
+
+    #[test]
+    fn ask_user() {
+        let rt = Runtime::new().unwrap();
+        let context = RunContext::new();
+        context.set("name", "Tom").unwrap();
+
+        let node = UserNode::new("Hi {{context.name}}, {{input}}? ");
+        let prompt = node.render_prompt("color".to_string(), &context).unwrap();
+        let mut output = Vec::new();
+        let answer = rt.block_on(node.ask(&prompt, &mut &b"blue\r\nred\n"[..], &mut output));
+        assert_eq!(answer.unwrap(), "blue");
+        assert_eq!(String::from_utf8(output).unwrap(), "Hi Tom, color? ");
+
+        let multiline = UserNode::default().multiline(true);
+        let mut reader = &b"line 1\nline 2\nEOF\nrest\n"[..];
+        let answer = rt.block_on(multiline.ask("", &mut reader, &mut Vec::new()));
+        assert_eq!(answer.unwrap(), "line 1\nline 2");
+        let answer = rt.block_on(multiline.ask("", &mut reader, &mut Vec::new()));
+        assert_eq!(answer.unwrap(), "rest");
+
+        let error = rt.block_on(node.ask("", &mut &b""[..], &mut Vec::new()));
+        assert!(matches!(
+            error.unwrap_err().get_error_type(),
+            UserNodeErrorType::NoInput
+        ));
+        let defaulted = node.clone().default_answer(Some("green".to_string()));
+        let answer = rt.block_on(defaulted.ask("", &mut &b"\n"[..], &mut Vec::new()));
+        assert_eq!(answer.unwrap(), "green");
+
+        // a reader that never gives a line
+        let (_writer, reader) = tokio::io::duplex(64);
+        let slow = defaulted.timeout(Some(Duration::from_millis(50)));
+        let answer = rt.block_on(async {
+            slow.ask("", &mut BufReader::new(reader), &mut Vec::new())
+                .await
+        });
+        assert_eq!(answer.unwrap(), "green");
+    }
+
+    #[test]
+    fn input_source() {
+        let rt = Runtime::new().unwrap();
+        let context = RunContext::new();
+        let node = UserNode::new("Name of {{input}}?").default_answer(Some("Bob".to_string()));
+
+        context.set_user_input(Some(UserInput::new(|request: UserRequest| async move {
+            Some(format!("answer to {}", request.prompt))
+        })));
+        let answer = rt.block_on(node.execute("the cat".to_string(), &context));
+        assert_eq!(answer.unwrap(), "answer to Name of the cat?");
+
+        let (input, mut questions) = UserInput::channel(1);
+        context.set_user_input(Some(input));
+        let answers = rt.block_on(async {
+            let frontend = tokio::spawn(async move {
+                let question = questions.recv().await.unwrap();
+                assert_eq!(question.request.input, "the dog");
+                question.answer("Rex".to_string());
+                questions.recv().await.unwrap().skip();
+            });
+            let first = node.execute("the dog".to_string(), &context).await;
+            let second = node.execute("the bird".to_string(), &context).await;
+            frontend.await.unwrap();
+            (first.unwrap(), second.unwrap())
+        });
+        assert_eq!(answers, ("Rex".to_string(), "Bob".to_string()));
+    }
+}
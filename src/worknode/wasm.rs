@@ -0,0 +1,331 @@
+//! # Wasm
+//!
+//! This node runs a plugin, a WebAssembly component, so the logic of a node can be written in
+//! any language that compiles to WebAssembly and shipped without recompiling AIPilot.
+//!
+//! The component exports the function of this WIT world:
+//!
+//! ```wit
+//! package aipilot:plugin;
+//!
+//! world plugin {
+//!     export run: func(input: string) -> string;
+//! }
+//! ```
+//!
+//! The input of the node is given to `run`, and its result is the output of the node. The
+//! component may import the WASI interfaces, like one built by `cargo component`, but it runs
+//! sandboxed: it has no files, no network, no environment variables and no stdio. A plugin
+//! can be limited in the fuel it burns (about one unit per instruction), the memory it grows
+//! and the time it takes. A plugin file can also be a component in the text format.
+//!
+//! The component is compiled on the first execution, and kept in the node for the next ones.
+//! Every execution gets a new instance, so no state is kept between them.
+
+use crate::error::wasm_node_error::{WasmNodeError, WasmNodeErrorType, WasmNodeResult};
+
+use serde::{Deserialize, Serialize};
+use wasmtime::component::{Component, Linker, ResourceTable};
+use wasmtime::{Config, Engine, Store, StoreLimits, StoreLimitsBuilder};
+use wasmtime_wasi::{WasiCtx, WasiCtxBuilder, WasiCtxView, WasiView};
+
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use std::time::Duration;
+
+/// The fuel burnt between two yields of a plugin, so a timeout can stop it.
+const YIELD_INTERVAL: u64 = 10_000;
+
+/// The state of the store of a plugin.
+struct PluginState {
+    wasi: WasiCtx,
+    table: ResourceTable,
+    limits: StoreLimits,
+}
+
+impl WasiView for PluginState {
+    fn ctx(&mut self) -> WasiCtxView<'_> {
+        WasiCtxView {
+            ctx: &mut self.wasi,
+            table: &mut self.table,
+        }
+    }
+}
+
+#[derive(Clone)]
+/// The struct of a compiled plugin, and the file it is compiled from.
+struct Compiled {
+    path: PathBuf,
+    component: Component,
+}
+
+impl std::fmt::Debug for Compiled {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Compiled({})", self.path.display())
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+/// The struct of the wasm node.
+pub struct WasmNode {
+    /// The path of the component.
+    path: PathBuf,
+    /// The fuel the plugin may burn in an execution.
+    fuel: Option<u64>,
+    /// The bytes of memory the plugin may grow.
+    memory: Option<usize>,
+    /// The time the plugin may take before it is stopped.
+    timeout: Option<Duration>,
+    /// The component compiled on the first execution.
+    #[serde(skip)]
+    compiled: Option<Compiled>,
+}
+
+impl PartialEq for WasmNode {
+    fn eq(&self, other: &Self) -> bool {
+        self.path == other.path
+            && self.fuel == other.fuel
+            && self.memory == other.memory
+            && self.timeout == other.timeout
+    }
+}
+
+impl WasmNode {
+    /// Create a new WasmNode that runs the component of the file.
+    pub fn new<P: AsRef<Path>>(path: P) -> Self {
+        WasmNode {
+            path: path.as_ref().to_path_buf(),
+            ..Self::default()
+        }
+    }
+    /// Run the plugin with the input, and get its output.
+    pub async fn execute(&mut self, input: String) -> WasmNodeResult<String> {
+        let component = self.component()?;
+        let run = self.run(component, input);
+        match self.timeout {
+            // the plugin yields while it runs, so it is stopped when the future is dropped
+            Some(timeout) => tokio::time::timeout(timeout, run).await.map_err(|_| {
+                WasmNodeError::new(
+                    WasmNodeErrorType::Timeout,
+                    format!("{} didn't finish in {:?}.", self.path.display(), timeout),
+                )
+            })?,
+            None => run.await,
+        }
+    }
+    /// Get the compiled component, and compile it if the path changed.
+    fn component(&mut self) -> WasmNodeResult<Component> {
+        if let Some(compiled) = &self.compiled {
+            if compiled.path == self.path {
+                return Ok(compiled.component.clone());
+            }
+        }
+        let component = Component::from_file(engine()?, &self.path).map_err(|e| {
+            WasmNodeError::new(
+                WasmNodeErrorType::LoadError,
+                format!("Can't load the plugin {}. {:#}", self.path.display(), e),
+            )
+        })?;
+        self.compiled = Some(Compiled {
+            path: self.path.clone(),
+            component: component.clone(),
+        });
+        Ok(component)
+    }
+    /// Instantiate the component in a new store, and call its `run`.
+    async fn run(&self, component: Component, input: String) -> WasmNodeResult<String> {
+        let engine = engine()?;
+        let mut limits = StoreLimitsBuilder::new();
+        if let Some(memory) = self.memory {
+            limits = limits.memory_size(memory);
+        }
+        let mut store = Store::new(
+            engine,
+            PluginState {
+                wasi: WasiCtxBuilder::new().build(),
+                table: ResourceTable::new(),
+                limits: limits.build(),
+            },
+        );
+        store.limiter(|state| &mut state.limits);
+        store
+            .set_fuel(self.fuel.unwrap_or(u64::MAX))
+            .and_then(|_| store.fuel_async_yield_interval(Some(YIELD_INTERVAL)))
+            .map_err(|e| self.error(WasmNodeErrorType::InstantiateError, "set up", e))?;
+        let mut linker = Linker::new(engine);
+        wasmtime_wasi::p2::add_to_linker_async(&mut linker)
+            .map_err(|e| self.error(WasmNodeErrorType::InstantiateError, "link", e))?;
+        let instance = linker
+            .instantiate_async(&mut store, &component)
+            .await
+            .map_err(|e| self.error(WasmNodeErrorType::InstantiateError, "instantiate", e))?;
+        let run = instance
+            .get_typed_func::<(String,), (String,)>(&mut store, "run")
+            .map_err(|e| self.error(WasmNodeErrorType::LoadError, "find `run` in", e))?;
+        let (output,) = run
+            .call_async(&mut store, (input,))
+            .await
+            .map_err(|e| self.error(WasmNodeErrorType::CallError, "run", e))?;
+        Ok(output)
+    }
+    /// Create a WasmNodeError of something that failed with the plugin.
+    fn error(
+        &self,
+        error_type: WasmNodeErrorType,
+        action: &str,
+        e: wasmtime::Error,
+    ) -> WasmNodeError {
+        WasmNodeError::new(
+            error_type,
+            format!(
+                "Can't {} the plugin {}. {:#}",
+                action,
+                self.path.display(),
+                e
+            ),
+        )
+    }
+    /// Set the path of the component as builder.
+    pub fn path<P: AsRef<Path>>(mut self, path: P) -> Self {
+        self.set_path(path);
+        self
+    }
+    /// Set the path of the component.
+    pub fn set_path<P: AsRef<Path>>(&mut self, path: P) {
+        self.path = path.as_ref().to_path_buf();
+    }
+    /// Get the path of the component.
+    pub fn get_path(&self) -> &Path {
+        &self.path
+    }
+    /// Set the fuel the plugin may burn as builder.
+    pub fn fuel(mut self, fuel: Option<u64>) -> Self {
+        self.fuel = fuel;
+        self
+    }
+    /// Set the fuel the plugin may burn.
+    pub fn set_fuel(&mut self, fuel: Option<u64>) {
+        self.fuel = fuel;
+    }
+    /// Get the fuel the plugin may burn.
+    pub fn get_fuel(&self) -> Option<u64> {
+        self.fuel
+    }
+    /// Set the bytes of memory the plugin may grow as builder.
+    pub fn memory(mut self, memory: Option<usize>) -> Self {
+        self.memory = memory;
+        self
+    }
+    /// Set the bytes of memory the plugin may grow.
+    pub fn set_memory(&mut self, memory: Option<usize>) {
+        self.memory = memory;
+    }
+    /// Get the bytes of memory the plugin may grow.
+    pub fn get_memory(&self) -> Option<usize> {
+        self.memory
+    }
+    /// Set the timeout as builder.
+    pub fn timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.timeout = timeout;
+        self
+    }
+    /// Set the timeout.
+    pub fn set_timeout(&mut self, timeout: Option<Duration>) {
+        self.timeout = timeout;
+    }
+    /// Get the timeout.
+    pub fn get_timeout(&self) -> Option<Duration> {
+        self.timeout
+    }
+}
+
+/// Get the engine that compiles and runs the plugins. It is shared by all wasm nodes.
+fn engine() -> WasmNodeResult<&'static Engine> {
+    static ENGINE: OnceLock<Result<Engine, String>> = OnceLock::new();
+    ENGINE
+        .get_or_init(|| {
+            let mut config = Config::new();
+            config.consume_fuel(true);
+            Engine::new(&config).map_err(|e| format!("{:#}", e))
+        })
+        .as_ref()
+        .map_err(|e| {
+            WasmNodeError::new(
+                WasmNodeErrorType::LoadError,
+                format!("Can't create the engine of the plugins. {}", e),
+            )
+        })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use tokio::runtime::Runtime;
+
+    /// A plugin whose `run` returns its input, or loops forever.
+    fn plugin(body: &str) -> String {
+        format!(
+            r#"(component
+                (core module $m
+                    (memory (export "memory") 1)
+                    (global $next (mut i32) (i32.const 1024))
+                    (func (export "realloc") (param i32 i32 i32 i32) (result i32)
+                        (local $p i32)
+                        (local.set $p (global.get $next))
+                        (global.set $next (i32.add (local.get $p) (local.get 3)))
+                        (local.get $p))
+                    (func (export "run") (param $ptr i32) (param $len i32) (result i32)
+                        {}
+                        (i32.store (i32.const 0) (local.get $ptr))
+                        (i32.store (i32.const 4) (local.get $len))
+                        (i32.const 0)))
+                (core instance $i (instantiate $m))
+                (func (export "run") (param "input" string) (result string)
+                    (canon lift (core func $i "run")
+                        (memory (core memory $i "memory"))
+                        (realloc (core func $i "realloc")))))"#,
+            body
+        )
+    }
+
+    #[test]
+    fn run_plugins() {
+        let rt = Runtime::new().unwrap();
+        let dir = std::env::temp_dir().join(format!("wasm-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir(&dir).unwrap();
+        std::fs::write(dir.join("echo.wat"), plugin("")).unwrap();
+        std::fs::write(dir.join("loop.wat"), plugin("(loop $l (br $l))")).unwrap();
+
+        let mut echo = WasmNode::new(dir.join("echo.wat"));
+        let output = rt.block_on(echo.execute("héllo".to_string()));
+        assert_eq!(output.unwrap(), "héllo");
+        // the compiled component is kept
+        let output = rt.block_on(echo.execute("again".to_string()));
+        assert_eq!(output.unwrap(), "again");
+
+        let mut out_of_fuel = WasmNode::new(dir.join("loop.wat")).fuel(Some(100_000));
+        let error = rt.block_on(out_of_fuel.execute(String::new()));
+        assert!(matches!(
+            error.unwrap_err().get_error_type(),
+            WasmNodeErrorType::CallError
+        ));
+
+        let mut slow =
+            WasmNode::new(dir.join("loop.wat")).timeout(Some(Duration::from_millis(100)));
+        let error = rt.block_on(slow.execute(String::new()));
+        assert!(matches!(
+            error.unwrap_err().get_error_type(),
+            WasmNodeErrorType::Timeout
+        ));
+
+        let mut missing = WasmNode::new(dir.join("missing.wasm"));
+        let error = rt.block_on(missing.execute(String::new()));
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert!(matches!(
+            error.unwrap_err().get_error_type(),
+            WasmNodeErrorType::LoadError
+        ));
+    }
+}